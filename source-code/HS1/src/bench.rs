@@ -0,0 +1,194 @@
+//! `hs1 bench-vm`: run one script under every execution pipeline and report
+//! the wall time and peak memory of each.
+//!
+//! Each pipeline gets the program the way its own command would: the
+//! tree-walking interpreter the checked AST (`hs1 eval`), the VM the
+//! optimised bytecode (`hs2`), the native executable the optimised AST
+//! (`hs1 compile --native --crate-type exe`). Only running is timed, not
+//! compiling, and the fastest of `runs` runs is kept. The interpreter and
+//! the VM run inside `hs1`, so their peak RSS includes the compiler's own
+//! memory; the native executable is a child process and reports its own.
+//! Script output is captured, not shown, and compared with the VM's.
+use anyhow::Result;
+use serde_json::json;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use hackerscript_vm::{BufferHost, VM};
+
+use crate::timing::{self, TimeFormat};
+
+/// What one pipeline did: measured, or why it could not run.
+struct Outcome {
+    name: &'static str,
+    result: Result<Measured, String>,
+}
+
+struct Measured {
+    /// Fastest run
+    wall: Duration,
+    /// Highest peak over the runs, in bytes
+    peak_rss: Option<u64>,
+    lines: Vec<String>,
+}
+
+pub fn bench(input: &Path, runs: u32, format: TimeFormat) -> Result<()> {
+    let program = hs1::load(input)?;
+    hs1::check(&program)?;
+    let compiled = hs1::compile_file(input, true)?;
+    let runs = runs.max(1);
+
+    let outcomes = [
+        Outcome {
+            name: "interpreter",
+            result: measure(runs, || {
+                let mut interpreter = hackerscript_eval::Interpreter::new();
+                let mut host = BufferHost::default();
+                interpreter.run(&program, &mut host).map(|()| host.lines)
+            }),
+        },
+        Outcome {
+            name: "vm",
+            result: measure(runs, || {
+                let mut vm = VM::new();
+                vm.set_modules(hackerscript_stdlib::bytecode);
+                let mut host = BufferHost::default();
+                vm.run(&compiled.bytecode, &mut host).map(|()| host.lines)
+            }),
+        },
+        Outcome { name: "jit", result: Err("the Cranelift JIT does not run programs yet".to_string()) },
+        Outcome { name: "native", result: native(input, program, runs) },
+    ];
+    report(&outcomes, format);
+    Ok(())
+}
+
+/// Run `run` `runs` times in this process.
+fn measure(runs: u32, mut run: impl FnMut() -> Result<Vec<String>>) -> Result<Measured, String> {
+    let mut best: Option<Measured> = None;
+    for _ in 0..runs {
+        timing::reset_peak_rss();
+        let started = Instant::now();
+        let lines = run().map_err(|e| format!("{:#}", e))?;
+        let wall = started.elapsed();
+        best = Some(fastest(best, Measured { wall, peak_rss: timing::peak_rss(), lines }));
+    }
+    Ok(best.expect("at least one run"))
+}
+
+/// `next`, or `best` with the higher of the two peaks if it was faster.
+fn fastest(best: Option<Measured>, next: Measured) -> Measured {
+    match best {
+        Some(best) if best.wall <= next.wall => Measured { peak_rss: best.peak_rss.max(next.peak_rss), ..best },
+        Some(best) => Measured { peak_rss: best.peak_rss.max(next.peak_rss), ..next },
+        None => next,
+    }
+}
+
+#[cfg(feature = "native")]
+fn native(input: &Path, mut program: hackerscript_ast::Program, runs: u32) -> Result<Measured, String> {
+    use hackerscript_codegen::native;
+
+    hs1::optimize(&mut program);
+    let dir = std::env::temp_dir().join(format!("hs1-bench-{}", std::process::id()));
+    let build = || -> Result<std::path::PathBuf> {
+        let mut compiler = native::NativeCompiler::new("bench")?;
+        compiler.compile_program(&program)?;
+        compiler.compile_main(&program)?;
+        std::fs::create_dir_all(&dir)?;
+        let object = dir.join("bench.o");
+        let exe = dir.join("bench");
+        std::fs::write(&object, compiler.finish()?)?;
+        native::link_executable(&object, &exe)?;
+        Ok(exe)
+    };
+    let exe = build().map_err(|e| format!("cannot compile {}: {:#}", input.display(), e));
+    let mut best = None;
+    let result = exe.and_then(|exe| {
+        for _ in 0..runs {
+            best = Some(fastest(best.take(), run_child(&exe).map_err(|e| format!("{:#}", e))?));
+        }
+        Ok(best.expect("at least one run"))
+    });
+    std::fs::remove_dir_all(&dir).ok();
+    result
+}
+
+#[cfg(not(feature = "native"))]
+fn native(_input: &Path, _program: hackerscript_ast::Program, _runs: u32) -> Result<Measured, String> {
+    Err("hs1 was built without the `native` feature".to_string())
+}
+
+/// Run `exe` to completion, with its own peak RSS where the platform
+/// reports it per child.
+#[cfg(feature = "native")]
+fn run_child(exe: &Path) -> Result<Measured> {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+
+    let started = Instant::now();
+    let mut child = Command::new(exe).stdout(Stdio::piped()).spawn()?;
+    let mut stdout = String::new();
+    child.stdout.take().expect("piped above").read_to_string(&mut stdout)?;
+    let (success, peak_rss) = wait(child)?;
+    let wall = started.elapsed();
+    if !success {
+        anyhow::bail!("{} failed", exe.display());
+    }
+    Ok(Measured { wall, peak_rss, lines: stdout.lines().map(str::to_string).collect() })
+}
+
+#[cfg(all(feature = "native", target_os = "linux"))]
+fn wait(child: std::process::Child) -> Result<(bool, Option<u64>)> {
+    let mut status = 0;
+    // SAFETY: a zeroed rusage is valid, and `child` has not been waited for
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // ru_maxrss is in KiB on Linux
+    let success = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+    Ok((success, Some(usage.ru_maxrss as u64 * 1024)))
+}
+
+#[cfg(all(feature = "native", not(target_os = "linux")))]
+fn wait(mut child: std::process::Child) -> Result<(bool, Option<u64>)> {
+    Ok((child.wait()?.success(), None))
+}
+
+fn report(outcomes: &[Outcome], format: TimeFormat) {
+    let expected = outcomes.iter().find(|o| o.name == "vm").and_then(|o| o.result.as_ref().ok());
+    let note = |outcome: &Outcome| match (&outcome.result, expected) {
+        (Err(reason), _) => Some(reason.clone()),
+        (Ok(measured), Some(vm)) if measured.lines != vm.lines => Some("output differs from the vm's".to_string()),
+        _ => None,
+    };
+    match format {
+        TimeFormat::Text => {
+            println!("{:<12} {:>12} {:>12}", "pipeline", "wall", "peak rss");
+            for outcome in outcomes {
+                let (wall, rss) = match &outcome.result {
+                    Ok(measured) => (timing::wall(measured.wall), timing::rss(measured.peak_rss)),
+                    Err(_) => ("-".to_string(), "-".to_string()),
+                };
+                let note = note(outcome).map(|note| format!("  ({})", note)).unwrap_or_default();
+                println!("{:<12} {:>12} {:>12}{}", outcome.name, wall, rss, note);
+            }
+        }
+        TimeFormat::Json => {
+            let pipelines: Vec<_> = outcomes
+                .iter()
+                .map(|outcome| {
+                    let measured = outcome.result.as_ref().ok();
+                    json!({
+                        "name": outcome.name,
+                        "wall_ms": measured.map(|m| timing::ms(m.wall)),
+                        "peak_rss_bytes": measured.and_then(|m| m.peak_rss),
+                        "note": note(outcome),
+                    })
+                })
+                .collect();
+            println!("{}", json!({ "pipelines": pipelines }));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! The `hs1` pipeline as a library, so other tools can parse, check and
+//! compile HackerScript in-process instead of running the binary.
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::Path;
+
+use hackerscript_ast::Program;
+use hackerscript_bytecode::obfuscate::ObfuscateOptions;
+use hackerscript_bytecode::{Bytecode, Opcode};
+use hackerscript_codegen::opt::{self, Eliminated};
+use hackerscript_parser::loader::Loader;
+
+pub mod manifest;
+
+/// Parse one script. Its imports stay as statements; use `load` to follow them.
+pub fn parse(source: &str) -> Result<Program> {
+    hackerscript_parser::parse(source).map_err(|e| anyhow::anyhow!("Parse error:\n{}", e))
+}
+
+/// Parse the script at `path` with its imports expanded, `core:*` modules
+/// included. A script with a `--- strict ---` header is checked in strict
+/// mode first.
+pub fn load(path: &Path) -> Result<Program> {
+    load_with(path, false)
+}
+
+/// `load`, holding the script to strict mode with or without a header.
+pub fn load_strict(path: &Path) -> Result<Program> {
+    load_with(path, true)
+}
+
+fn load_with(path: &Path, strict: bool) -> Result<Program> {
+    let mut loader = loader(path, false);
+    let mut program = loader.parse(path)?;
+    program.strict |= strict;
+    check_strict(&program)?;
+    loader.expand(&mut program, path)?;
+    Ok(program)
+}
+
+/// The loader for a program whose entry script is `entry`. With
+/// `precompiled`, imports of the `core:*` modules written in HackerScript
+/// are left for the VM, which has them compiled already.
+pub fn loader(entry: &Path, precompiled: bool) -> Loader {
+    let mut loader = Loader::for_entry(entry);
+    loader.set_builtins(hackerscript_stdlib::source, precompiled);
+    loader
+}
+
+/// Every name and type error in `program`, one per line.
+pub fn check(program: &Program) -> Result<()> {
+    hackerscript_codegen::check::ensure(program)
+}
+
+/// The strict mode errors of a script that asks for strict mode, one per
+/// line. Check it as parsed: the files its imports add are not held to it.
+pub fn check_strict(program: &Program) -> Result<()> {
+    if program.strict {
+        hackerscript_codegen::strict::ensure(program)?;
+    }
+    Ok(())
+}
+
+/// Fold constants and drop dead code, returning what was dropped.
+pub fn optimize(program: &mut Program) -> Vec<Eliminated> {
+    opt::optimize(program)
+}
+
+/// Compile a checked program to the contents of a `.bc` file.
+pub fn compile(program: &Program) -> Result<Vec<u8>> {
+    Ok(hackerscript_codegen::compiler::compile(program)?.to_bytes())
+}
+
+/// A compiled script and what the optimiser dropped from it.
+#[derive(Debug)]
+pub struct Compiled {
+    pub bytecode: Bytecode,
+    pub eliminated: Vec<Eliminated>,
+}
+
+/// Load, check, optimise (when `optimize` is set) and compile the script at
+/// `path`, naming it after `path` in the debug section.
+pub fn compile_file(path: &Path, optimize: bool) -> Result<Compiled> {
+    compile_with(loader(path, true), path, optimize)
+}
+
+/// Compile the project `manifest` describes: its entry script and every
+/// module that imports, dependencies first, into one program.
+pub fn compile_project(manifest: &manifest::Manifest, optimize: bool) -> Result<Compiled> {
+    let mut loader = manifest.loader();
+    loader.set_builtins(hackerscript_stdlib::source, true);
+    compile_with(loader, &manifest.entry, optimize)
+}
+
+fn compile_with(mut loader: Loader, path: &Path, optimize: bool) -> Result<Compiled> {
+    let mut program = loader.parse(path)?;
+    check_strict(&program)?;
+    loader.expand(&mut program, path)?;
+    check(&program)?;
+    let eliminated = if optimize { opt::optimize(&mut program) } else { Vec::new() };
+    let bytecode = hackerscript_codegen::compiler::compile_named(&program, &path.display().to_string())?;
+    Ok(Compiled { bytecode, eliminated })
+}
+
+/// Obfuscate `bytecode` as `--obfuscate` does, keeping readable the names
+/// the precompiled modules it imports define or use.
+pub fn obfuscate(bytecode: &mut Bytecode, xor_strings: bool) -> Result<()> {
+    let mut options = ObfuscateOptions { xor_strings, keep: HashSet::new() };
+    let mut seen = HashSet::new();
+    let mut pending = imports(bytecode);
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let Some(bytes) = hackerscript_stdlib::bytecode(&name) else { continue };
+        let module = Bytecode::from_bytes(bytes)?;
+        options.keep.extend(module.constants.iter().map(str::to_string));
+        pending.extend(imports(&module));
+    }
+    hackerscript_bytecode::obfuscate::obfuscate(bytecode, &options)
+}
+
+/// The modules `bytecode` imports.
+fn imports(bytecode: &Bytecode) -> Vec<String> {
+    bytecode
+        .instructions()
+        .filter(|&(_, op)| op == Opcode::Import)
+        .filter_map(|(at, _)| hackerscript_bytecode::read_varint(&bytecode.code, at + 1))
+        .filter_map(|(idx, _)| bytecode.constants.get(idx as usize).map(str::to_string))
+        .collect()
+}
@@ -4,12 +4,14 @@ use log::info;
 use std::fs;
 use std::path::PathBuf;
 
-mod bytecode;
-mod compiler;
-mod parser;
+mod bench;
+mod project;
+mod timing;
 
-use compiler::Compiler;
-use parser::{HackerScriptParser, Rule};
+use hackerscript_ast::Edition;
+use hackerscript_bytecode as bytecode;
+#[cfg(feature = "native")]
+use hackerscript_codegen::native;
 
 #[derive(Parser)]
 #[command(name = "hs1", about = "HackerScript Compiler", version)]
@@ -28,13 +30,220 @@ enum Commands {
         output: Option<PathBuf>,
         #[arg(long)]
         dump: bool,
+        /// What to write: bytecode, or the parsed AST as pretty JSON (to
+        /// stdout unless -o is given)
+        #[arg(long = "emit", value_enum, value_name = "KIND", default_value = "bc", conflicts_with_all = ["native", "stream"])]
+        emit_kind: EmitKind,
         #[arg(long)]
         native: bool,
+        /// Output kind for --native
+        #[arg(long, value_enum, default_value = "obj")]
+        crate_type: CrateType,
+        /// Skip the optimisation passes (constant folding, dead code elimination)
+        #[arg(long)]
+        no_opt: bool,
+        /// Report what the optimisation passes eliminated
+        #[arg(short, long)]
+        verbose: bool,
+        /// Inline functions whose body is at most this many expression nodes
+        /// (0: only those marked `inline`)
+        #[arg(long, value_name = "NODES", default_value_t = hackerscript_codegen::inline::DEFAULT_BUDGET)]
+        inline_budget: usize,
+        /// Parse and emit one top-level statement at a time, for very large
+        /// scripts (only constant folding and the name check run)
+        #[arg(long, conflicts_with = "native")]
+        stream: bool,
+        /// Print wall-clock time and peak RSS of each compiler pass to stderr
+        #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text", conflicts_with = "stream")]
+        time_passes: Option<timing::TimeFormat>,
+        /// Write the fixes for common syntax errors (a missing `]`, `=` for
+        /// `==` in a condition, an unclosed string) back to the input
+        #[arg(long, conflicts_with = "stream")]
+        apply_suggestions: bool,
+        /// Check the script in strict mode, as a `--- strict ---` header does
+        #[arg(long, conflicts_with = "stream")]
+        strict: bool,
+        #[command(flatten)]
+        emit: EmitArgs,
     },
-    /// Check syntax only
+    /// Compile many .hcs files or directories of them in parallel, or with
+    /// no inputs the project described by hs.toml into one .bc
+    Build {
+        /// Files or directories (searched recursively for .hcs)
+        inputs: Vec<PathBuf>,
+        /// Project manifest to build (default: hs.toml in the current directory)
+        #[arg(long, value_name = "PATH", conflicts_with = "inputs")]
+        manifest: Option<PathBuf>,
+        /// Write .bc files under this directory instead of next to the sources
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Number of modules compiled at once (default: one per core)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Skip the optimisation passes (constant folding, dead code elimination)
+        #[arg(long)]
+        no_opt: bool,
+        /// Report eliminated code and build time
+        #[arg(short, long)]
+        verbose: bool,
+        #[command(flatten)]
+        emit: EmitArgs,
+    },
+    /// Check syntax and that every name a script uses is defined
     Check {
         input: PathBuf,
+        /// Check the script in strict mode, as a `--- strict ---` header does
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Rewrite scripts for a language edition: rename the words it reserves
+    /// and set their `--- edition ---` header
+    Fix {
+        /// Files or directories (searched recursively for .hcs)
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Edition to move to (default: the latest)
+        #[arg(long, value_name = "YEAR")]
+        edition: Option<Edition>,
     },
+    /// Run a script straight from the AST with the reference interpreter
+    Eval {
+        input: PathBuf,
+        /// Check the script in strict mode, as a `--- strict ---` header does
+        #[arg(long)]
+        strict: bool,
+        /// Make int overflow, float overflow and NaN catchable `math:` errors
+        /// instead of wrapping and propagating
+        #[arg(long)]
+        checked_math: bool,
+    },
+    /// Compile a script in memory and run it on the VM, with the arguments
+    /// after it in its `args` array
+    Run {
+        input: PathBuf,
+        /// Make int overflow, float overflow and NaN catchable `math:` errors
+        /// instead of wrapping and propagating
+        #[arg(long)]
+        checked_math: bool,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Replace the functions of a script running under `hs2 --attachable`
+    /// with those of an edited copy of it. Its globals keep their values
+    /// and its top level does not run again
+    Attach {
+        /// The runtime's process id, or the socket it listens on
+        target: String,
+        input: PathBuf,
+    },
+    /// Run a script under the interpreter, the VM, the JIT and a native
+    /// executable, comparing wall time and peak memory
+    BenchVm {
+        input: PathBuf,
+        /// Runs per pipeline; the fastest is reported
+        #[arg(long, default_value_t = 3)]
+        runs: u32,
+        #[arg(long, value_enum, default_value = "text")]
+        format: timing::TimeFormat,
+    },
+    /// Print the bytecode instruction set: opcodes, operands and stack effects
+    Isa {
+        #[arg(long, value_enum, default_value = "text")]
+        format: IsaFormat,
+    },
+    /// Print the listing `compile --dump` shows for an existing .bc file:
+    /// constant pool, then each instruction with its offset
+    Disasm { input: PathBuf },
+    /// Assemble a text file of instructions, as an `asm [ ... ]` block holds
+    /// them (`push_const "hi"`, `log_string`, `halt`), into .bc bytecode
+    Asm {
+        input: PathBuf,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        emit: EmitArgs,
+    },
+    /// Compile a script into one self-contained executable (the hs2 runtime
+    /// with the bytecode embedded)
+    Bundle {
+        input: PathBuf,
+        /// Executable to write (default: the script name without extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// hs2 executable to embed (default: the hs2 next to hs1)
+        #[arg(long)]
+        runtime: Option<PathBuf>,
+        /// Skip the optimisation passes (constant folding, dead code elimination)
+        #[arg(long)]
+        no_opt: bool,
+        #[command(flatten)]
+        emit: EmitArgs,
+    },
+}
+
+/// How .bc files are written.
+#[derive(Debug, Clone, Copy, clap::Args)]
+struct EmitArgs {
+    /// Leave out debug info (source name, function table) for release builds
+    #[arg(long)]
+    strip: bool,
+    /// zstd-compress the constant pool and debug info
+    #[arg(long)]
+    compress: bool,
+    /// Replace variable and function names with hashes and leave out debug info
+    #[arg(long)]
+    obfuscate: bool,
+    /// With --obfuscate, also XOR-encode the constant pool; each string is
+    /// decoded when the VM first uses it
+    #[arg(long, requires = "obfuscate")]
+    xor_strings: bool,
+}
+
+impl EmitArgs {
+    fn write(&self, mut bytecode: bytecode::Bytecode, path: &std::path::Path) -> Result<bytecode::Bytecode> {
+        let bytes = self.encode(&mut bytecode)?;
+        fs::write(path, bytes).context("Cannot create output file")?;
+        Ok(bytecode)
+    }
+
+    /// File contents for `bytecode`, stripping or obfuscating it first if
+    /// asked to.
+    fn encode(&self, bytecode: &mut bytecode::Bytecode) -> Result<Vec<u8>> {
+        if self.strip {
+            bytecode.strip();
+        }
+        if self.obfuscate {
+            hs1::obfuscate(bytecode, self.xor_strings)?;
+        }
+        bytecode.encode(bytecode::WriteOptions { compress: self.compress })
+    }
+}
+
+/// What `hs1 compile --emit` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum EmitKind {
+    /// Bytecode (.bc)
+    Bc,
+    /// The script's own `Program`, before imports are expanded, as JSON
+    Ast,
+}
+
+/// How `hs1 isa` prints the instruction set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum IsaFormat {
+    Text,
+    Markdown,
+}
+
+/// What `hs1 compile --native` should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CrateType {
+    /// Relocatable object file (.o)
+    Obj,
+    /// Shared library exporting every `pub func` with the C ABI (.so)
+    Cdylib,
+    /// Executable whose `main` runs the top-level statements
+    Exe,
 }
 
 fn main() -> Result<()> {
@@ -42,31 +251,101 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match &cli.command {
-        Commands::Compile { input, output, dump, native } => {
+        Commands::Compile {
+            input,
+            output,
+            dump,
+            emit_kind,
+            native,
+            crate_type,
+            no_opt,
+            verbose,
+            inline_budget,
+            stream,
+            time_passes,
+            apply_suggestions,
+            strict,
+            emit,
+        } => {
             if !input.exists() {
                 anyhow::bail!("Input file does not exist: {}", input.display());
             }
 
-            let source = fs::read_to_string(input).context("Failed to read source file")?;
-
-            let pairs = <HackerScriptParser as pest::Parser<Rule>>::parse(Rule::program, &source)
-                .map_err(|e| anyhow::anyhow!("Parse error:\n{}", e))?;
+            if *stream {
+                let out_path = output.clone().unwrap_or_else(|| input.with_extension("bc"));
+                let bytecode = emit.write(compile_streaming(input, !*no_opt)?, &out_path)?;
+                info!("Compiled {} → {}", input.display(), out_path.display());
+                if *dump {
+                    println!("\nBytecode dump:");
+                    bytecode::pretty_print(&bytecode);
+                }
+                return Ok(());
+            }
 
-            let mut compiler = Compiler::new();
-            for pair in pairs {
-                compiler.compile_pair(pair)?;
+            let mut timer = timing::PassTimer::new(time_passes.is_some());
+            let mut source = timer
+                .time("read", || fs::read_to_string(input))
+                .context("Failed to read source file")?;
+            if *apply_suggestions {
+                source = apply_suggestions_to(input, source)?;
             }
 
-            let bytecode = compiler.finish();
+            let tree = timer
+                .time("parse", || hackerscript_parser::parse_tree(&source))
+                .map_err(|e| parse_error(&source, &e))?;
+            let mut program = timer.time("lower", || hackerscript_parser::build_program(tree));
+            if *emit_kind == EmitKind::Ast {
+                let json = serde_json::to_string_pretty(&program)?;
+                match output {
+                    Some(path) => fs::write(path, json + "\n").context("Cannot create output file")?,
+                    None => println!("{}", json),
+                }
+                return Ok(());
+            }
+            program.strict |= *strict;
+            if program.strict {
+                timer.time("strict", || hs1::check_strict(&program))?;
+            }
+            timer.time("imports", || hs1::loader(input, !*native).expand(&mut program, input))?;
+            timer.time("check", || hs1::check(&program))?;
+            report_hints(input, &program);
 
-            let out_path = output.clone().unwrap_or_else(|| input.with_extension("bc"));
+            if !*no_opt {
+                timer.time("fold", || hackerscript_codegen::opt::fold_constants(&mut program));
+                let mut eliminated = timer.time("inline", || {
+                    let inlined = hackerscript_codegen::inline::inline_functions(&mut program, *inline_budget);
+                    // fold what the expanded calls made constant
+                    hackerscript_codegen::opt::fold_constants(&mut program);
+                    inlined
+                });
+                eliminated.extend(timer.time("dce", || hackerscript_codegen::opt::eliminate_dead_code(&mut program)));
+                report_eliminated(input, &eliminated, *verbose);
+            }
 
             if *native {
-                info!("Native codegen requested, but not yet implemented. Falling back to bytecode.");
+                #[cfg(feature = "native")]
+                {
+                    let result = timer.time("emit", || compile_native(input, output.as_deref(), &program, *crate_type));
+                    if let Some(format) = time_passes {
+                        timer.report(*format);
+                    }
+                    return result;
+                }
+                #[cfg(not(feature = "native"))]
+                info!("hs1 was built without the `native` feature ({:?} requested). Falling back to bytecode.", crate_type);
             }
 
-            bytecode::write_to_file(&bytecode, &out_path)?;
+            let bytecode = timer.time("emit", || {
+                hackerscript_codegen::compiler::compile_named(&program, &input.display().to_string())
+            })?;
+
+            let out_path = output.clone().unwrap_or_else(|| input.with_extension("bc"));
+
+            let bytecode = timer.time("write", || emit.write(bytecode, &out_path))?;
             info!("Compiled {} → {}", input.display(), out_path.display());
+            if let Some(format) = time_passes {
+                timer.report(*format);
+            }
 
             if *dump {
                 println!("\nBytecode dump:");
@@ -74,12 +353,318 @@ fn main() -> Result<()> {
             }
         }
 
-        Commands::Check { input } => {
-            let source = fs::read_to_string(input)?;
-            let _ = <HackerScriptParser as pest::Parser<Rule>>::parse(Rule::program, &source)?;
+        Commands::Build { inputs, manifest, out_dir, no_opt, verbose, emit, .. } if inputs.is_empty() => {
+            let started = std::time::Instant::now();
+            let path = manifest.clone().unwrap_or_else(|| PathBuf::from(hs1::manifest::FILE_NAME));
+            if manifest.is_none() && !path.exists() {
+                anyhow::bail!("No inputs given and no {} in the current directory", hs1::manifest::FILE_NAME);
+            }
+            let manifest = hs1::manifest::Manifest::read(&path)?;
+            let hs1::Compiled { bytecode, eliminated } = hs1::compile_project(&manifest, !*no_opt)?;
+            report_eliminated(&manifest.entry, &eliminated, *verbose);
+
+            let output = match out_dir {
+                Some(dir) => dir.join(manifest.output.file_name().context("Output path has no file name")?),
+                None => manifest.output.clone(),
+            };
+            if let Some(parent) = output.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                fs::create_dir_all(parent).with_context(|| format!("Cannot create {}", parent.display()))?;
+            }
+            emit.write(bytecode, &output)?;
+            info!("Compiled {} → {}", manifest.name, output.display());
+            if *verbose {
+                eprintln!("Built {} in {:.2?}", manifest.name, started.elapsed());
+            }
+        }
+
+        Commands::Build { inputs, out_dir, jobs, no_opt, verbose, emit, .. } => {
+            let started = std::time::Instant::now();
+            let sources = project::collect_sources(inputs)?;
+            let results = project::build(&sources, out_dir.as_deref(), !*no_opt, emit, *jobs)?;
+
+            let mut failed = 0;
+            for (source, result) in sources.iter().zip(results) {
+                match result {
+                    Ok(module) => {
+                        info!("Compiled {} → {}", source.path.display(), module.output.display());
+                        report_eliminated(&source.path, &module.eliminated, *verbose);
+                    }
+                    Err(err) => {
+                        failed += 1;
+                        eprintln!("error: {}: {:#}", source.path.display(), err);
+                    }
+                }
+            }
+            if *verbose {
+                eprintln!("Built {} module(s) in {:.2?}", sources.len(), started.elapsed());
+            }
+            if failed > 0 {
+                anyhow::bail!("{} of {} module(s) failed to compile", failed, sources.len());
+            }
+        }
+
+        Commands::Check { input, strict } => {
+            let program = if *strict { hs1::load_strict(input)? } else { hs1::load(input)? };
+            hs1::check(&program)?;
+            report_hints(input, &program);
             println!("Syntax OK: {}", input.display());
         }
+
+        Commands::Fix { inputs, edition } => {
+            let edition = edition.unwrap_or(Edition::LATEST);
+            for source in project::collect_sources(inputs)? {
+                let path = &source.path;
+                let text = fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+                let fixed = hackerscript_parser::editions::migrate(&text, edition)
+                    .map_err(|e| anyhow::anyhow!("Parse error:\n{}", e.with_path(&path.display().to_string())))?;
+                if fixed != text {
+                    fs::write(path, fixed).with_context(|| format!("Cannot write {}", path.display()))?;
+                    println!("Fixed {} for edition {}", path.display(), edition);
+                }
+            }
+        }
+
+        Commands::Eval { input, strict, checked_math } => {
+            let program = if *strict { hs1::load_strict(input)? } else { hs1::load(input)? };
+            hs1::check(&program)?;
+            let level = hackerscript_vm::Logger::level_from_env()?;
+            let mut interpreter = hackerscript_eval::Interpreter::new();
+            interpreter.set_logger(hackerscript_vm::Logger::new(level, hackerscript_vm::logger::Destination::Stderr));
+            interpreter.set_math(math(*checked_math));
+            interpreter.run(&program, &mut hackerscript_vm::StdHost)?;
+        }
+
+        Commands::Run { input, checked_math, args } => {
+            let hs1::Compiled { bytecode, eliminated } = hs1::compile_file(input, true)?;
+            report_eliminated(input, &eliminated, false);
+            let level = hackerscript_vm::Logger::level_from_env()?;
+            let mut vm = hackerscript_vm::VM::new();
+            vm.set_modules(hackerscript_stdlib::bytecode);
+            vm.set_logger(hackerscript_vm::Logger::new(level, hackerscript_vm::logger::Destination::Stderr));
+            vm.set_math(math(*checked_math));
+            let args = args.iter().map(|arg| hackerscript_vm::Value::from(arg.as_str())).collect();
+            vm.set_global(hackerscript_vm::ARGS, hackerscript_vm::Value::Array(args));
+            vm.run(&bytecode, &mut hackerscript_vm::StdHost)?;
+        }
+
+        Commands::Attach { target, input } => {
+            // unoptimised, so that functions nothing calls are kept
+            let hs1::Compiled { bytecode, .. } = hs1::compile_file(input, false)?;
+            let socket = hackerscript_vm::attach::target_socket(target);
+            let patched = hackerscript_vm::attach::attach(&socket, &bytecode.to_bytes())?;
+            println!("Patched {}", patched.join(", "));
+        }
+
+        Commands::BenchVm { input, runs, format } => bench::bench(input, *runs, *format)?,
+
+        Commands::Isa { format } => match format {
+            IsaFormat::Text => print!("{}", bytecode::isa::text()),
+            IsaFormat::Markdown => print!("{}", bytecode::isa::markdown()),
+        },
+
+        Commands::Disasm { input } => {
+            let bytecode =
+                bytecode::read_from_file(input).with_context(|| format!("Cannot disassemble {}", input.display()))?;
+            bytecode::pretty_print(&bytecode);
+        }
+
+        Commands::Asm { input, output, emit } => {
+            let source = fs::read_to_string(input).with_context(|| format!("Cannot read {}", input.display()))?;
+            let bytecode = hackerscript_codegen::asm::assemble_named(&source, &input.display().to_string())?;
+            let out_path = output.clone().unwrap_or_else(|| input.with_extension("bc"));
+            emit.write(bytecode, &out_path)?;
+            info!("Assembled {} → {}", input.display(), out_path.display());
+        }
+
+        Commands::Bundle { input, output, runtime, no_opt, emit } => {
+            let out_path = output.clone().unwrap_or_else(|| input.with_extension(""));
+            bundle(input, &out_path, runtime.as_deref(), !*no_opt, emit)?;
+            info!("Bundled {} → {}", input.display(), out_path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Warn about code the optimiser dropped because it could never run, and
+/// with `verbose` list the rest of what it dropped too.
+fn report_eliminated(path: &std::path::Path, eliminated: &[hackerscript_codegen::opt::Eliminated], verbose: bool) {
+    for item in eliminated {
+        if item.is_warning() {
+            eprintln!("warning: {}: eliminated {}", path.display(), item);
+        } else if verbose {
+            eprintln!("{}: eliminated {}", path.display(), item);
+        }
+    }
+}
+
+/// The arithmetic `--checked-math` asks for.
+fn math(checked: bool) -> hackerscript_vm::Math {
+    if checked {
+        hackerscript_vm::Math::Checked
+    } else {
+        hackerscript_vm::Math::Wrapping
     }
+}
+
+/// A syntax error, with the fix `suggest` knows for it.
+fn parse_error(source: &str, err: &hackerscript_parser::ParseError) -> anyhow::Error {
+    let Some(fix) = hackerscript_parser::suggest(source, err) else {
+        return anyhow::anyhow!("Parse error:\n{}", err);
+    };
+    let snippet: Vec<String> = fix.snippet(source).lines().map(|line| format!("    {}", line)).collect();
+    anyhow::anyhow!(
+        "Parse error:\n{}\nhelp: {} (`--apply-suggestions` does it):\n{}",
+        err,
+        fix.message,
+        snippet.join("\n")
+    )
+}
+
+/// Make the fixes `suggest` knows, one error at a time, and write the
+/// result back to `path`. Stops at the first error without one.
+fn apply_suggestions_to(path: &std::path::Path, mut source: String) -> Result<String> {
+    const MAX_FIXES: usize = 100;
+    let mut fixes = 0;
+    while fixes < MAX_FIXES {
+        let err = match hackerscript_parser::parse_tree(&source) {
+            Ok(_) => break,
+            Err(err) => err,
+        };
+        let Some(fix) = hackerscript_parser::suggest(&source, &err) else { break };
+        eprintln!("{}: {}", path.display(), fix.message);
+        source = fix.apply(&source);
+        fixes += 1;
+    }
+    if fixes > 0 {
+        fs::write(path, &source).with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    Ok(source)
+}
+
+/// Print what `check::hints` finds in `program`.
+fn report_hints(path: &std::path::Path, program: &hackerscript_ast::Program) {
+    for hint in hackerscript_codegen::check::hints(program) {
+        eprintln!("hint: {}: {}", path.display(), hint);
+    }
+}
+
+/// `hs1 bundle`: compile `input` and append it to a copy of the runtime.
+fn bundle(
+    input: &std::path::Path,
+    output: &std::path::Path,
+    runtime: Option<&std::path::Path>,
+    optimize: bool,
+    emit: &EmitArgs,
+) -> Result<()> {
+    let hs1::Compiled { mut bytecode, eliminated } = hs1::compile_file(input, optimize)?;
+    report_eliminated(input, &eliminated, false);
+    let payload = emit.encode(&mut bytecode)?;
+
+    let runtime = match runtime {
+        Some(path) => path.to_path_buf(),
+        None => default_runtime()?,
+    };
+    let runtime_bytes = fs::read(&runtime).with_context(|| format!("Cannot read runtime {}", runtime.display()))?;
+    fs::write(output, bytecode::bundle::append(&runtime_bytes, &payload))
+        .with_context(|| format!("Cannot write {}", output.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// `hs2` installed alongside this `hs1`, as the installer lays them out.
+fn default_runtime() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Cannot locate hs1")?;
+    let runtime = exe.with_file_name(format!("hs2{}", std::env::consts::EXE_SUFFIX));
+    if !runtime.is_file() {
+        anyhow::bail!("No hs2 runtime next to {} (pass --runtime)", exe.display());
+    }
+    Ok(runtime)
+}
+
+/// `hs1 compile --stream`: never holds more than one top-level statement.
+fn compile_streaming(input: &std::path::Path, optimize: bool) -> Result<bytecode::Bytecode> {
+    let file = fs::File::open(input).context("Failed to read source file")?;
+    let mut stream = hackerscript_parser::stream::StmtStream::new(std::io::BufReader::new(file));
+    let mut compiler = hackerscript_codegen::Compiler::new();
+    compiler.set_source(input.display().to_string());
+    let mut loader = hs1::loader(input, true);
+    let mut checker = hackerscript_codegen::check::StreamChecker::new();
+    while let Some(stmt) = stream.next() {
+        let stmt = stmt.map_err(|e| anyhow::anyhow!("Parse error {}", e))?;
+        checker.set_memory_mode(stream.memory_mode());
+        // an import becomes the module's statements, each held in full
+        let mut program = hackerscript_ast::Program { body: vec![stmt], ..Default::default() };
+        loader.expand(&mut program, input)?;
+        for stmt in program.body {
+            checker.stmt(&stmt);
+            let stmt = if optimize { stmt.map(hackerscript_codegen::opt::fold_stmt) } else { stmt };
+            compiler.compile_stmt(&stmt)?;
+        }
+    }
+    checker.ensure()?;
+    Ok(compiler.finish())
+}
+
+#[cfg(feature = "native")]
+fn compile_native(
+    input: &std::path::Path,
+    output: Option<&std::path::Path>,
+    program: &hackerscript_ast::Program,
+    crate_type: CrateType,
+) -> Result<()> {
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "hackerscript".to_string());
+
+    let mut compiler = native::NativeCompiler::new(&stem)?;
+    compiler.compile_program(program)?;
+    if crate_type == CrateType::Exe {
+        compiler.compile_main(program)?;
+    }
+    let guard = format!("HS_{}_H", stem.to_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let header = compiler.c_header(&guard);
+    let object = compiler.finish()?;
+
+    let obj_path = input.with_extension("o");
+    match crate_type {
+        CrateType::Obj => {
+            let out_path = output.map(PathBuf::from).unwrap_or(obj_path);
+            fs::write(&out_path, object).context("Cannot write object file")?;
+            write_header(&out_path, &stem, &header)?;
+            info!("Compiled {} → {}", input.display(), out_path.display());
+        }
+        CrateType::Cdylib => {
+            let out_path = output
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input.with_file_name(format!("lib{}.so", stem)));
+            fs::write(&obj_path, object).context("Cannot write object file")?;
+            native::link_cdylib(&obj_path, &out_path)?;
+            fs::remove_file(&obj_path).ok();
+            write_header(&out_path, &stem, &header)?;
+            info!("Compiled {} → {}", input.display(), out_path.display());
+        }
+        CrateType::Exe => {
+            let out_path = output.map(PathBuf::from).unwrap_or_else(|| input.with_extension(""));
+            fs::write(&obj_path, object).context("Cannot write object file")?;
+            native::link_executable(&obj_path, &out_path)?;
+            fs::remove_file(&obj_path).ok();
+            info!("Compiled {} → {}", input.display(), out_path.display());
+        }
+    }
+    Ok(())
+}
 
+/// `<stem>.h` next to a native object or library, declaring its exports.
+#[cfg(feature = "native")]
+fn write_header(output: &std::path::Path, stem: &str, header: &str) -> Result<()> {
+    let path = output.with_file_name(format!("{}.h", stem));
+    fs::write(&path, header).with_context(|| format!("Cannot write {}", path.display()))?;
+    info!("Wrote C header {}", path.display());
     Ok(())
 }
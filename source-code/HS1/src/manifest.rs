@@ -0,0 +1,105 @@
+//! `hs.toml`, the project manifest `hs1 build` reads when given no inputs:
+//!
+//! ```toml
+//! [project]
+//! name = "app"
+//! entry = "src/main.hcs"
+//! # module directories, searched in order before HS_PATH (default: ["src"])
+//! sources = ["src", "vendor"]
+//! # default: <name>.bc next to hs.toml
+//! output = "build/app.bc"
+//! # included before the entry script, in place of HS_PRELUDE
+//! prelude = "src/prelude.hcs"
+//! # for files without an `--- edition ---` header (default: "2024")
+//! edition = "2025"
+//! ```
+//!
+//! Relative paths are relative to the directory holding `hs.toml`.
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hackerscript_ast::Edition;
+use hackerscript_parser::loader::{self, Loader};
+
+/// File name `hs1 build` looks for in the current directory.
+pub const FILE_NAME: &str = "hs.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    project: Project,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Project {
+    name: String,
+    entry: PathBuf,
+    #[serde(default = "default_sources")]
+    sources: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    prelude: Option<PathBuf>,
+    #[serde(default)]
+    edition: Edition,
+}
+
+fn default_sources() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+/// A parsed manifest with its paths resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub name: String,
+    pub entry: PathBuf,
+    /// Module directories, searched in order
+    pub sources: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub prelude: Option<PathBuf>,
+    pub edition: Edition,
+}
+
+impl Manifest {
+    /// Read the manifest at `path`.
+    pub fn read(path: &Path) -> Result<Manifest> {
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let root = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Manifest::parse(&text, root).with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    /// Parse manifest `text`, resolving its paths against `root`.
+    pub fn parse(text: &str, root: &Path) -> Result<Manifest> {
+        let File { project } = toml::from_str(text)?;
+        if project.name.is_empty() {
+            anyhow::bail!("`project.name` is empty");
+        }
+        if project.sources.is_empty() {
+            anyhow::bail!("`project.sources` lists no directories");
+        }
+        let output = project.output.unwrap_or_else(|| PathBuf::from(format!("{}.bc", project.name)));
+        Ok(Manifest {
+            entry: root.join(project.entry),
+            sources: project.sources.iter().map(|dir| root.join(dir)).collect(),
+            output: root.join(output),
+            prelude: project.prelude.map(|prelude| root.join(prelude)),
+            edition: project.edition,
+            name: project.name,
+        })
+    }
+
+    /// The loader for this project: its source directories, then `HS_PATH`,
+    /// its prelude or else `HS_PRELUDE`, and its edition.
+    pub fn loader(&self) -> Loader {
+        let mut dirs = self.sources.iter().cloned();
+        let src_root = dirs.next().expect("checked in parse");
+        let mut loader = Loader::new(src_root, dirs.chain(loader::env_search_path()).collect());
+        loader.set_prelude(self.prelude.clone().or_else(loader::env_prelude));
+        loader.set_edition(self.edition);
+        loader
+    }
+}
@@ -1,3 +0,0 @@
-#[derive(pest_derive::Parser)]
-#[grammar = "hackerscript.pest"]
-pub struct HackerScriptParser;
@@ -0,0 +1,93 @@
+//! Multi-file builds. Each `.hcs` module is parsed, optimised and compiled
+//! on its own, so `hs1 build` spreads modules over a rayon thread pool and
+//! reports results in path order regardless of which finished first.
+use anyhow::{Context, Result};
+use crate::EmitArgs;
+use hackerscript_codegen::opt::Eliminated;
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A module to build: where it lives and where it sits relative to the
+/// directory it was found in (used to mirror the tree under `--out-dir`).
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub path: PathBuf,
+    pub relative: PathBuf,
+}
+
+/// A successfully compiled module.
+#[derive(Debug)]
+pub struct Module {
+    pub output: PathBuf,
+    pub eliminated: Vec<Eliminated>,
+}
+
+/// Expand files and directories (searched recursively for `.hcs`) into a
+/// sorted, de-duplicated module list.
+pub fn collect_sources(inputs: &[PathBuf]) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            walk(input, input, &mut sources)?;
+        } else if input.exists() {
+            let relative = input.file_name().map(PathBuf::from).unwrap_or_else(|| input.clone());
+            sources.push(Source { path: input.clone(), relative });
+        } else {
+            anyhow::bail!("Input does not exist: {}", input.display());
+        }
+    }
+    sources.sort_by(|a, b| a.path.cmp(&b.path));
+    sources.dedup_by(|a, b| a.path == b.path);
+    Ok(sources)
+}
+
+fn walk(root: &Path, dir: &Path, sources: &mut Vec<Source>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Cannot read directory {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(root, &path, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == "hcs") {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            sources.push(Source { path, relative });
+        }
+    }
+    Ok(())
+}
+
+/// Compile every module, at most `jobs` at a time (all cores when `None`).
+/// Results come back in the same order as `sources`.
+pub fn build(
+    sources: &[Source],
+    out_dir: Option<&Path>,
+    optimize: bool,
+    emit: &EmitArgs,
+    jobs: Option<usize>,
+) -> Result<Vec<Result<Module>>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .context("Cannot start compiler threads")?;
+    Ok(pool.install(|| {
+        sources
+            .par_iter()
+            .map(|source| compile_module(source, out_dir, optimize, emit))
+            .collect()
+    }))
+}
+
+/// Parse, check, optimise and compile one module to `.bc`.
+pub fn compile_module(source: &Source, out_dir: Option<&Path>, optimize: bool, emit: &EmitArgs) -> Result<Module> {
+    let hs1::Compiled { bytecode, eliminated } = hs1::compile_file(&source.path, optimize)?;
+
+    let output = match out_dir {
+        Some(dir) => dir.join(&source.relative).with_extension("bc"),
+        None => source.path.with_extension("bc"),
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Cannot create {}", parent.display()))?;
+    }
+    emit.write(bytecode, &output)?;
+    Ok(Module { output, eliminated })
+}
@@ -0,0 +1,124 @@
+//! `hs1 compile --time-passes`: wall-clock time and peak RSS of each phase.
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+/// Output format of the report, which always goes to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+struct Pass {
+    name: &'static str,
+    wall: Duration,
+    /// Peak resident set size while the pass ran, in bytes
+    peak_rss: Option<u64>,
+}
+
+/// Records passes run through `time`. When disabled it only runs them.
+#[derive(Debug)]
+pub struct PassTimer {
+    enabled: bool,
+    /// Whether the kernel let us reset the peak between passes; if not,
+    /// each peak is the process peak so far.
+    per_pass_peak: bool,
+    passes: Vec<Pass>,
+}
+
+impl PassTimer {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            per_pass_peak: enabled && reset_peak_rss(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn time<T>(&mut self, name: &'static str, pass: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return pass();
+        }
+        if self.per_pass_peak {
+            reset_peak_rss();
+        }
+        let started = Instant::now();
+        let result = pass();
+        let wall = started.elapsed();
+        self.passes.push(Pass { name, wall, peak_rss: peak_rss() });
+        result
+    }
+
+    pub fn report(&self, format: TimeFormat) {
+        if !self.enabled {
+            return;
+        }
+        let total: Duration = self.passes.iter().map(|p| p.wall).sum();
+        let peak = self.passes.iter().filter_map(|p| p.peak_rss).max();
+        match format {
+            TimeFormat::Text => {
+                let note = if self.per_pass_peak { "" } else { " (process peak so far)" };
+                eprintln!("{:<10} {:>12} {:>12}{}", "pass", "wall", "peak rss", note);
+                for pass in &self.passes {
+                    eprintln!("{:<10} {:>12} {:>12}", pass.name, wall(pass.wall), rss(pass.peak_rss));
+                }
+                eprintln!("{:<10} {:>12} {:>12}", "total", wall(total), rss(peak));
+            }
+            TimeFormat::Json => {
+                let passes: Vec<_> = self
+                    .passes
+                    .iter()
+                    .map(|p| json!({ "name": p.name, "wall_ms": ms(p.wall), "peak_rss_bytes": p.peak_rss }))
+                    .collect();
+                let report = json!({
+                    "passes": passes,
+                    "total_ms": ms(total),
+                    "peak_rss_bytes": peak,
+                    "per_pass_peak": self.per_pass_peak,
+                });
+                eprintln!("{}", report);
+            }
+        }
+    }
+}
+
+pub(crate) fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+pub(crate) fn wall(duration: Duration) -> String {
+    format!("{:.3}ms", ms(duration))
+}
+
+pub(crate) fn rss(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        None => "-".to_string(),
+    }
+}
+
+/// Reset the kernel's peak-RSS counter (`VmHWM`); false if unsupported.
+#[cfg(target_os = "linux")]
+pub(crate) fn reset_peak_rss() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reset_peak_rss() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn peak_rss() -> Option<u64> {
+    None
+}
@@ -0,0 +1,31 @@
+use std::process::Command;
+
+use hackerscript_vm::{BufferHost, VM};
+
+fn hs1(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hs1")).args(args).env("RUST_BACKTRACE", "0").output().unwrap()
+}
+
+#[test]
+fn asm_writes_a_bc_file_the_vm_runs() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("hello.hasm");
+    let source =
+        ".const \"hello\"\n    push_const 0\n    log_string\n    push_const \"again\"\n    log_string\n    halt\n";
+    std::fs::write(&input, source).unwrap();
+    let output = hs1(&["asm", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let bytecode = hackerscript_bytecode::read_from_file(&input.with_extension("bc")).unwrap();
+    assert_eq!(bytecode.constants.get(0), Some("hello"));
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["hello", "again"]);
+
+    // the verifier's checks apply
+    std::fs::write(&input, "push_const 3\nlog_string\nhalt\n").unwrap();
+    let output = hs1(&["asm", input.to_str().unwrap(), "-o", dir.join("bad.bc").to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid program in"), "{}", stderr);
+}
@@ -0,0 +1,24 @@
+use std::process::Command;
+
+#[test]
+fn json_report_lists_every_pipeline() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("bench_vm.hcs");
+    std::fs::write(&input, "let a = 1 + 2\nlog a\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(["bench-vm", "--runs", "1", "--format", "json"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("stdout is the JSON report");
+    let pipelines = report["pipelines"].as_array().unwrap();
+    let names: Vec<&str> = pipelines.iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["interpreter", "vm", "jit", "native"]);
+    for pipeline in &pipelines[..2] {
+        assert!(pipeline["wall_ms"].as_f64().unwrap() >= 0.0);
+        assert!(pipeline["note"].is_null(), "{}", pipeline);
+    }
+    assert!(pipelines[2]["wall_ms"].is_null());
+    assert!(pipelines[2]["note"].is_string());
+}
@@ -0,0 +1,38 @@
+use std::process::Command;
+
+fn hs1(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hs1")).args(args).env("RUST_BACKTRACE", "0").output().unwrap()
+}
+
+#[test]
+fn disasm_lists_a_bc_file_as_dump_does() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("disasm.hcs");
+    std::fs::write(&input, "func greet(name) [\n    log \"hi\", name\n]\ngreet(\"you\")\n").unwrap();
+    let (input, plain, compressed) = (input.to_str().unwrap(), dir.join("disasm.bc"), dir.join("disasm.zst.bc"));
+
+    let output = hs1(&["compile", "--dump", "-i", input, "-o", plain.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let dump = stdout.split_once("Bytecode dump:\n").expect("--dump prints a listing").1;
+    assert!(dump.contains("greet:") && dump.contains("(name)"), "{}", dump);
+    assert!(dump.contains("Functions (1):\n  0000: greet (1 params, 1 required, 1 slots)\n"), "{}", dump);
+
+    let output = hs1(&["compile", "--compress", "-i", input, "-o", compressed.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    for file in [&plain, &compressed] {
+        let output = hs1(&["disasm", file.to_str().unwrap()]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), dump);
+    }
+
+    // the functions listed come from the make_func headers, so stripping keeps them
+    let output = hs1(&["compile", "--strip", "-i", input, "-o", plain.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stripped = String::from_utf8(hs1(&["disasm", plain.to_str().unwrap()]).stdout).unwrap();
+    assert!(stripped.contains("  0000: greet (1 params"), "{}", stripped);
+
+    let output = hs1(&["disasm", input]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing HSBC header"));
+}
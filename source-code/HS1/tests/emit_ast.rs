@@ -0,0 +1,18 @@
+use std::process::Command;
+
+use hackerscript_ast::{Program, Stmt};
+
+#[test]
+fn emit_ast_prints_the_parsed_program() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("emit_ast.hcs");
+    std::fs::write(&input, "--- auto ---\nrequire <missing>\nlet a = 1 + 2\nlog a\n").unwrap();
+    let output =
+        Command::new(env!("CARGO_BIN_EXE_hs1")).args(["compile", "--emit", "ast", "-i"]).arg(&input).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let program: Program = serde_json::from_slice(&output.stdout).expect("stdout is the AST");
+    assert_eq!(program, hs1::parse(&std::fs::read_to_string(&input).unwrap()).unwrap());
+    // imports are left as written and nothing is folded
+    assert!(matches!(program.body[0].node, Stmt::Require { .. }));
+    assert!(!dir.join("emit_ast.bc").exists());
+}
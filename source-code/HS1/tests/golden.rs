@@ -0,0 +1,227 @@
+//! Golden-file suite: every `tests/golden/*.hcs` is run by the reference
+//! interpreter and on each backend. Stdout must match `<name>.stdout` and the
+//! exit code `<name>.exit` (0 when missing), so the backends cannot drift
+//! apart. `HS_BLESS=1 cargo test -p hs1 --test golden` rewrites the
+//! expectations from the reference interpreter (`hs1 eval`).
+//!
+//! The native backend (with `--features native`) compiles only part of the
+//! language. The programs it rejects are listed in `NATIVE_SKIPS`, and the
+//! test prints how many programs each backend ran; a skip that is not listed
+//! fails, and so does a listed program that compiles, so the list only
+//! shrinks. The hs2 JIT only sets up Cranelift and cannot run programs yet,
+//! so it is not a backend here.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use hackerscript_vm::{BufferHost, VM};
+use pretty_assertions::StrComparison;
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stdout: String,
+    exit: i32,
+}
+
+enum Run {
+    Ran(Outcome),
+    /// The backend rejected the program at compile time as not supported yet.
+    Unsupported(String),
+}
+
+type Backend = fn(&Path, &Path) -> Result<Run, String>;
+
+/// The golden programs the native backend rejects as not supported yet.
+const NATIVE_SKIPS: &[&str] = &[
+    "auto_memory",
+    "booleans",
+    "calls",
+    "conversions",
+    "default_params",
+    "destructuring",
+    "enums",
+    "errors",
+    "escapes",
+    "higher_order",
+    "lambdas",
+    "log_levels",
+    "log_values",
+    "logic",
+    "maps",
+    "match_patterns",
+    "reflection",
+    "rest_params",
+    "slices",
+    "try_except",
+    "undefined_variable",
+];
+
+/// The programs `backend` may skip.
+fn known_skips(backend: &str) -> &'static [&'static str] {
+    match backend {
+        "native" => NATIVE_SKIPS,
+        _ => &[],
+    }
+}
+
+fn backends() -> Vec<(&'static str, Backend)> {
+    #[allow(unused_mut)]
+    let mut backends: Vec<(&'static str, Backend)> = vec![("eval", run_eval), ("vm", run_vm)];
+    #[cfg(feature = "native")]
+    backends.push(("native", run_native));
+    backends
+}
+
+fn hs1(args: &[&str]) -> Result<(), String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .map_err(|e| format!("cannot run hs1: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().expect("test paths are UTF-8")
+}
+
+/// The reference interpreter in-process; a runtime error exits with 1 like `hs1 eval`.
+fn run_eval(source: &Path, _work: &Path) -> Result<Run, String> {
+    let program = hackerscript_parser::loader::load(source).map_err(|e| e.to_string())?;
+    let mut host = BufferHost::default();
+    let exit = match hackerscript_eval::eval(&program, &mut host) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    let stdout = host.lines.iter().map(|line| format!("{}\n", line)).collect();
+    Ok(Run::Ran(Outcome { stdout, exit }))
+}
+
+/// The line of an `hs1` error saying the backend cannot compile the program yet.
+fn unsupported(stderr: &str) -> Option<Run> {
+    let reason = stderr.lines().find(|line| line.contains("is not supported by the"))?;
+    Some(Run::Unsupported(reason.trim_start_matches("Error: ").to_string()))
+}
+
+/// `hs1 compile`, then the VM in-process; a runtime error exits with 1 like hs2.
+fn run_vm(source: &Path, work: &Path) -> Result<Run, String> {
+    let output = work.with_extension("bc");
+    if let Err(stderr) = hs1(&["compile", "-i", path_str(source), "-o", path_str(&output)]) {
+        return unsupported(&stderr).ok_or(stderr);
+    }
+    let bytecode = hackerscript_bytecode::read_from_file(&output).map_err(|e| format!("{:#}", e))?;
+    hackerscript_bytecode::verify(&bytecode).map_err(|e| format!("{:#}", e))?;
+    let mut host = BufferHost::default();
+    let exit = match VM::new().run(&bytecode, &mut host) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    let stdout = host.lines.iter().map(|line| format!("{}\n", line)).collect();
+    Ok(Run::Ran(Outcome { stdout, exit }))
+}
+
+/// `hs1 compile --native --crate-type exe`, then the linked executable.
+#[cfg(feature = "native")]
+fn run_native(source: &Path, work: &Path) -> Result<Run, String> {
+    let exe = work.with_extension("exe");
+    let args = ["compile", "--native", "--crate-type", "exe", "-i", path_str(source), "-o", path_str(&exe)];
+    if let Err(stderr) = hs1(&args) {
+        return unsupported(&stderr).ok_or(stderr);
+    }
+    let output = Command::new(&exe).output().map_err(|e| format!("cannot run {}: {}", exe.display(), e))?;
+    Ok(Run::Ran(Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        exit: output.status.code().unwrap_or(-1),
+    }))
+}
+
+fn expected(source: &Path) -> Outcome {
+    let stdout = fs::read_to_string(source.with_extension("stdout"))
+        .unwrap_or_else(|_| panic!("{} has no .stdout file (run with HS_BLESS=1)", source.display()));
+    let exit = fs::read_to_string(source.with_extension("exit"))
+        .map(|s| s.trim().parse().expect(".exit holds an exit code"))
+        .unwrap_or(0);
+    Outcome { stdout, exit }
+}
+
+fn bless(source: &Path, outcome: &Outcome) {
+    fs::write(source.with_extension("stdout"), &outcome.stdout).unwrap();
+    let exit = source.with_extension("exit");
+    if outcome.exit == 0 {
+        fs::remove_file(exit).ok();
+    } else {
+        fs::write(exit, format!("{}\n", outcome.exit)).unwrap();
+    }
+}
+
+fn sources() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut sources: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hcs"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+#[test]
+fn golden_programs_agree_on_every_backend() {
+    let work_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&work_dir).unwrap();
+    let blessing = std::env::var_os("HS_BLESS").is_some();
+    let sources = sources();
+    assert!(!sources.is_empty(), "no golden programs found");
+
+    let mut failures = Vec::new();
+    let mut skipped = Vec::new();
+    for source in &sources {
+        let name = source.file_stem().unwrap().to_string_lossy().into_owned();
+        for (backend, run) in backends() {
+            let work = work_dir.join(format!("{}-{}", name, backend));
+            let outcome = match run(source, &work) {
+                Ok(Run::Ran(outcome)) => outcome,
+                Ok(Run::Unsupported(reason)) => {
+                    eprintln!("{} [{}]: skipped: {}", name, backend, reason);
+                    if !known_skips(backend).contains(&name.as_str()) {
+                        failures.push(format!("{} [{}]: skipped, and not a known skip: {}", name, backend, reason));
+                    }
+                    skipped.push((backend, name.clone()));
+                    continue;
+                }
+                Err(err) => {
+                    failures.push(format!("{} [{}]: failed to compile:\n{}", name, backend, err));
+                    continue;
+                }
+            };
+            if blessing && backend == "eval" {
+                bless(source, &outcome);
+            }
+            let expected = expected(source);
+            if outcome != expected {
+                failures.push(format!(
+                    "{} [{}]: exit {} (expected {})\n{}",
+                    name,
+                    backend,
+                    outcome.exit,
+                    expected.exit,
+                    StrComparison::new(&expected.stdout, &outcome.stdout)
+                ));
+            }
+        }
+    }
+    for (backend, _) in backends() {
+        let skips = skipped.iter().filter(|(b, _)| *b == backend).count();
+        eprintln!("{}: ran {} of {} golden programs, skipped {}", backend, sources.len() - skips, sources.len(), skips);
+        for name in known_skips(backend) {
+            if !skipped.iter().any(|(b, skipped)| *b == backend && skipped == name) {
+                failures.push(format!("{} [{}]: runs now; remove it from the known skips", name, backend));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{} golden mismatch(es):\n\n{}", failures.len(), failures.join("\n\n"));
+}
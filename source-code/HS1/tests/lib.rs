@@ -0,0 +1,66 @@
+use hackerscript_bytecode::Bytecode;
+use hackerscript_vm::{BufferHost, VM};
+
+#[test]
+fn compiles_in_process() {
+    let program = hs1::parse("func double(x) [\n    return x * 2\n]\nlog double(21)\n").unwrap();
+    hs1::check(&program).unwrap();
+    let bytecode = Bytecode::from_bytes(&hs1::compile(&program).unwrap()).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["42"]);
+}
+
+#[test]
+fn reports_each_stage_as_an_error() {
+    assert!(hs1::parse("log (\n").unwrap_err().to_string().starts_with("Parse error:"));
+    let program = hs1::parse("log missing\n").unwrap();
+    assert_eq!(hs1::check(&program).unwrap_err().to_string(), "undefined variable `missing` at the top level");
+}
+
+#[test]
+fn compiles_files_with_their_imports() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("lib");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("util.hcs"), "func greet() [\n    return \"hi\"\n]\n").unwrap();
+    std::fs::write(dir.join("main.hcs"), "require <util>\nif false [\n    log 1\n]\nlog greet()\n").unwrap();
+    let compiled = hs1::compile_file(&dir.join("main.hcs"), true).unwrap();
+    assert_eq!(compiled.eliminated.iter().filter(|item| item.is_warning()).count(), 1);
+    let mut host = BufferHost::default();
+    VM::new().run(&compiled.bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["hi"]);
+}
+
+#[test]
+fn core_modules_are_left_for_the_vm_but_expanded_for_the_interpreter() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("lib_core");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.hcs"), "import <core:math>\nlog max(2, 3)\n").unwrap();
+    let compiled = hs1::compile_file(&dir.join("main.hcs"), true).unwrap();
+    let mut vm = VM::new();
+    vm.set_modules(hackerscript_stdlib::bytecode);
+    let mut host = BufferHost::default();
+    vm.run(&compiled.bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["3"]);
+
+    let program = hs1::load(&dir.join("main.hcs")).unwrap();
+    assert!(program.body.iter().any(|stmt| matches!(&stmt.node, hackerscript_ast::Stmt::Func(func) if func.name == "gcd")));
+}
+
+#[test]
+fn data_moves_between_vms_but_functions_stay_with_theirs() {
+    let bytecode = |source: &str| Bytecode::from_bytes(&hs1::compile(&hs1::parse(source).unwrap()).unwrap()).unwrap();
+    let mut maker = VM::new();
+    maker.run(&bytecode("let data = {\"n\": 1}\nlet f = func() [\n    return 3\n]\n"), &mut BufferHost::default()).unwrap();
+
+    let mut other = VM::new();
+    other.set_global("data", maker.global("data").unwrap().clone());
+    let mut host = BufferHost::default();
+    other.run(&bytecode("log data\n"), &mut host).unwrap();
+    assert_eq!(host.lines, [r#"{"n": 1}"#]);
+
+    let mut other = VM::new();
+    other.set_global("f", maker.global("f").unwrap().clone());
+    let err = other.run(&bytecode("log f()\n"), &mut BufferHost::default()).unwrap_err();
+    assert!(format!("{:#}", err).contains("`f` was made by another VM and can only be called there"), "{:#}", err);
+}
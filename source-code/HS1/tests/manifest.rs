@@ -0,0 +1,131 @@
+use hackerscript_ast::Edition;
+use hackerscript_bytecode::Bytecode;
+use hackerscript_vm::{BufferHost, VM};
+use hs1::manifest::Manifest;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[test]
+fn defaults_and_relative_paths() {
+    let manifest = Manifest::parse("[project]\nname = \"app\"\nentry = \"src/main.hcs\"\n", Path::new("proj")).unwrap();
+    assert_eq!(
+        manifest,
+        Manifest {
+            name: "app".to_string(),
+            entry: PathBuf::from("proj/src/main.hcs"),
+            sources: vec![PathBuf::from("proj/src")],
+            output: PathBuf::from("proj/app.bc"),
+            prelude: None,
+            edition: Edition::E2024,
+        }
+    );
+}
+
+#[test]
+fn rejects_unknown_keys_and_missing_fields() {
+    let err = Manifest::parse("[project]\nname = \"app\"\nentry = \"main.hcs\"\nouput = \"x\"\n", Path::new("."))
+        .unwrap_err();
+    assert!(err.to_string().contains("unknown field `ouput`"), "{}", err);
+    let err = Manifest::parse("[project]\nname = \"app\"\n", Path::new(".")).unwrap_err();
+    assert!(err.to_string().contains("missing field `entry`"), "{}", err);
+    let err = Manifest::parse("[project]\nname = \"app\"\nentry = \"a\"\nsources = []\n", Path::new(".")).unwrap_err();
+    assert_eq!(err.to_string(), "`project.sources` lists no directories");
+}
+
+#[test]
+fn builds_the_project_into_one_file() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("manifest");
+    std::fs::create_dir_all(dir.join("src/app")).unwrap();
+    std::fs::create_dir_all(dir.join("vendor/text")).unwrap();
+    std::fs::write(
+        dir.join("hs.toml"),
+        "[project]\nname = \"app\"\nentry = \"src/main.hcs\"\nsources = [\"src\", \"vendor\"]\noutput = \"out/app.bc\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("vendor/text/shout.hcs"), "func shout(s) [\n    return s + \"!\"\n]\n").unwrap();
+    std::fs::write(dir.join("src/app/greet.hcs"), "import <text.shout>\nfunc greet() [\n    return shout(\"hi\")\n]\n")
+        .unwrap();
+    std::fs::write(dir.join("src/main.hcs"), "import <app.greet>\nimport <core:math>\nlog greet()\nlog max(2, 3)\n")
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1")).arg("build").current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let bytecode = Bytecode::from_bytes(&std::fs::read(dir.join("out/app.bc")).unwrap()).unwrap();
+    let mut vm = VM::new();
+    vm.set_modules(hackerscript_stdlib::bytecode);
+    let mut host = BufferHost::default();
+    vm.run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["hi!", "3"]);
+}
+
+#[test]
+fn the_manifest_prelude_is_used_over_hs_prelude() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("manifest_prelude");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("hs.toml"),
+        "[project]\nname = \"app\"\nentry = \"src/main.hcs\"\nprelude = \"team.hcs\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("team.hcs"), "func shout(s) [\n    return s + \"!\"\n]\n").unwrap();
+    std::fs::write(dir.join("other.hcs"), "func shout(s) [\n    return s + \"?\"\n]\n").unwrap();
+    std::fs::write(dir.join("src/main.hcs"), "log shout(\"hi\")\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .arg("build")
+        .env("HS_PRELUDE", dir.join("other.hcs"))
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let bytecode = Bytecode::from_bytes(&std::fs::read(dir.join("app.bc")).unwrap()).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["hi!"]);
+
+    // without a manifest, `HS_PRELUDE` applies
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .arg("eval")
+        .arg(dir.join("src/main.hcs"))
+        .env("HS_PRELUDE", dir.join("other.hcs"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi?\n");
+}
+
+#[test]
+fn the_project_edition_applies_until_fix_moves_the_files() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("manifest_edition");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("hs.toml"), "[project]\nname = \"app\"\nentry = \"src/main.hcs\"\nedition = \"2025\"\n")
+        .unwrap();
+    std::fs::write(dir.join("src/main.hcs"), "let match = 2\nlog match * 3\n").unwrap();
+    let build = || Command::new(env!("CARGO_BIN_EXE_hs1")).arg("build").current_dir(&dir).output().unwrap();
+    let output = build();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("`match` is a keyword in edition 2025"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1")).args(["fix", "src"]).current_dir(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("src/main.hcs")).unwrap(),
+        "--- edition 2025 ---\nlet match_ = 2\nlog match_ * 3\n"
+    );
+    let output = build();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let bytecode = Bytecode::from_bytes(&std::fs::read(dir.join("app.bc")).unwrap()).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["6"]);
+}
+
+#[test]
+fn build_without_inputs_needs_a_manifest() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("manifest_missing");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1")).arg("build").current_dir(&dir).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no hs.toml in the current directory"));
+}
@@ -0,0 +1,69 @@
+use hackerscript_bytecode::obfuscate::hash_name;
+use hackerscript_bytecode::Bytecode;
+use hackerscript_vm::{BufferHost, VM};
+
+const SCRIPT: &str = "\
+import <core:math>
+let secret_token = \"hunter2\"
+func greet(person) [
+    return \"hi \" + person
+]
+let info = {\"name\": \"box\"}
+log greet(info[\"name\"]), max(2, 3), str(1)
+match secret_token [
+    case \"hunter2\" [ log \"matched\" ]
+]
+";
+
+fn compile(name: &str) -> Bytecode {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.hcs"), SCRIPT).unwrap();
+    hs1::compile_file(&dir.join("main.hcs"), true).unwrap().bytecode
+}
+
+fn run(bytecode: &Bytecode) -> (VM, Vec<String>) {
+    let mut vm = VM::new();
+    vm.set_modules(hackerscript_stdlib::bytecode);
+    let mut host = BufferHost::default();
+    vm.run(bytecode, &mut host).unwrap();
+    (vm, host.lines)
+}
+
+#[test]
+fn hashes_names_but_runs_the_same() {
+    let mut bytecode = compile("obfuscate_names");
+    let (_, expected) = run(&bytecode);
+    hs1::obfuscate(&mut bytecode, false).unwrap();
+    assert!(bytecode.debug.is_none());
+    let pool: Vec<&str> = bytecode.constants.iter().collect();
+    for name in ["secret_token", "greet", "person", "info"] {
+        assert!(!pool.contains(&name), "`{}` is still in {:?}", name, pool);
+        assert!(pool.contains(&hash_name(name).as_str()));
+    }
+    // string values, module names, natives and what the module defines stay
+    for kept in ["hunter2", "name", "core:math", "max"] {
+        assert!(pool.contains(&kept), "`{}` is missing from {:?}", kept, pool);
+    }
+    let (vm, lines) = run(&bytecode);
+    assert_eq!(lines, expected);
+    assert!(vm.global("secret_token").is_none());
+    assert!(vm.global(&hash_name("secret_token")).is_some());
+}
+
+#[test]
+fn xor_strings_leave_nothing_readable_in_the_file() {
+    let mut bytecode = compile("obfuscate_xor");
+    let (_, expected) = run(&bytecode);
+    hs1::obfuscate(&mut bytecode, true).unwrap();
+    let bytes = bytecode.to_bytes();
+    for text in ["hunter2", "greet", "hi ", "core:math"] {
+        assert!(!bytes.windows(text.len()).any(|window| window == text.as_bytes()), "`{}` is readable", text);
+    }
+    let read = Bytecode::from_bytes(&bytes).unwrap();
+    assert_eq!(run(&read).1, expected);
+    // the key comes from the chunk, so builds are reproducible
+    let mut again = compile("obfuscate_xor_again");
+    hs1::obfuscate(&mut again, true).unwrap();
+    assert_eq!(again.to_bytes(), bytes);
+}
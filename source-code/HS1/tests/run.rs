@@ -0,0 +1,48 @@
+use std::process::Command;
+
+fn hs1(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hs1")).args(args).env("RUST_BACKTRACE", "0").output().unwrap()
+}
+
+#[test]
+fn run_compiles_and_runs_a_script_with_its_arguments() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("run");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("greet.hcs");
+    std::fs::write(&input, "func greet(who) [\n    log \"hi\", who\n]\nlog args\ngreet(args[0])\n").unwrap();
+    let input = input.to_str().unwrap();
+
+    // everything after the script is its own, flags included
+    let output = hs1(&["run", input, "you", "--verbose"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "[\"you\", \"--verbose\"]\nhi you\n");
+    assert!(!dir.join("greet.bc").exists(), "run writes no .bc");
+
+    // `args` is defined, and empty, wherever nothing sets it
+    std::fs::write(dir.join("count.hcs"), "log args\n").unwrap();
+    for command in ["run", "eval", "check"] {
+        let output = hs1(&[command, dir.join("count.hcs").to_str().unwrap()]);
+        assert!(output.status.success(), "{}: {}", command, String::from_utf8_lossy(&output.stderr));
+    }
+    assert_eq!(hs1(&["eval", dir.join("count.hcs").to_str().unwrap()]).stdout, b"[]\n");
+
+    let output = hs1(&["run", dir.join("missing.hcs").to_str().unwrap()]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn checked_math_is_a_flag_of_run_and_eval() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("checked_math");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("overflow.hcs");
+    // constant, so the optimiser must not fold it away
+    std::fs::write(&input, "log 9223372036854775807 + 1\n").unwrap();
+    let input = input.to_str().unwrap();
+    for command in ["run", "eval"] {
+        assert_eq!(hs1(&[command, input]).stdout, b"-9223372036854775808\n", "{}", command);
+        let output = hs1(&[command, "--checked-math", input]);
+        assert!(!output.status.success(), "{}", command);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Integer overflow in 9223372036854775807 + 1"), "{}: {}", command, stderr);
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::Command;
+
+use hackerscript_bytecode::Bytecode;
+use hackerscript_vm::{BufferHost, VM};
+
+#[test]
+fn streaming_follows_imports() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("stream");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("util.hcs"), "func greet(name) [\n    return \"hi \" + name\n]\n").unwrap();
+    std::fs::write(dir.join("main.hcs"), "require <util>\nrequire <util>\nlog greet(\"there\")\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(["compile", "--stream", "-i"])
+        .arg(dir.join("main.hcs"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let bytecode = Bytecode::from_bytes(&std::fs::read(dir.join("main.bc")).unwrap()).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["hi there"]);
+}
+
+#[test]
+fn streaming_reports_import_cycles() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("stream_cycle");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.hcs"), "require <b>\n").unwrap();
+    std::fs::write(dir.join("b.hcs"), "require <a>\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(["compile", "--stream", "-i"])
+        .arg(dir.join("a.hcs"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("import cycle"));
+}
+
+#[test]
+fn both_modes_reject_undefined_names() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("stream_check");
+    std::fs::create_dir_all(&dir).unwrap();
+    // `twice` is defined after its first use, which is fine; `missing` never is
+    let source = "log twice(2)\nlog missing\nfunc twice(n) [\n    return n * 2\n]\n";
+    std::fs::write(dir.join("main.hcs"), source).unwrap();
+    for stream in [false, true] {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_hs1"));
+        command.args(["compile", "-i"]).arg(dir.join("main.hcs"));
+        if stream {
+            command.arg("--stream");
+        }
+        let output = command.output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "stream: {}", stream);
+        assert!(stderr.contains("undefined variable `missing` at the top level"), "stream: {}: {}", stream, stderr);
+        assert!(!stderr.contains("twice"), "stream: {}: {}", stream, stderr);
+    }
+}
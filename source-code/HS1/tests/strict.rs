@@ -0,0 +1,38 @@
+use std::process::Command;
+
+fn hs1(args: &[&str], input: &std::path::Path) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hs1")).args(args).arg(input).env("RUST_BACKTRACE", "0").output().unwrap()
+}
+
+#[test]
+fn strict_mode_is_a_flag_or_a_header() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("strict");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("main.hcs");
+    // the module breaks every rule, but only the script importing it is strict
+    std::fs::write(dir.join("helpers.hcs"), "func twice(x) [\n    return x * 2\n]\n").unwrap();
+    let source = "import <helpers>\nfunc half(x: int) -> int [\n    if x > 0 [\n        return x / 2\n    ]\n]\nlog twice(half(8))\n";
+    std::fs::write(&input, source).unwrap();
+
+    for args in [&["check"][..], &["eval"], &["compile", "-i"]] {
+        let output = hs1(args, &input);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+    for args in [&["check", "--strict"][..], &["eval", "--strict"], &["compile", "--strict", "-i"]] {
+        let output = hs1(args, &input);
+        assert!(!output.status.success(), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("line 2: the end can be reached without returning a value in `half` (strict mode)"),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+        assert!(!stderr.contains("`x` has no type"), "{:?}: {}", args, stderr);
+    }
+
+    std::fs::write(&input, format!("--- strict ---\n{}", source)).unwrap();
+    let output = hs1(&["check"], &input);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("in `half` (strict mode)"));
+}
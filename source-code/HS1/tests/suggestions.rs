@@ -0,0 +1,32 @@
+use std::process::Command;
+
+fn compile(input: &std::path::Path, extra: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(["compile", "-i"])
+        .arg(input)
+        .args(extra)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn syntax_errors_come_with_a_fix_that_can_be_written_back() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("suggestions.hcs");
+    let source = "let n = 2\nif n = 2 [\n    log \"two\n";
+    std::fs::write(&input, source).unwrap();
+
+    let output = compile(&input, &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("help: compare with `==`"), "{}", stderr);
+    assert!(stderr.contains("    if n == 2 ["), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), source);
+
+    let output = compile(&input, &["--apply-suggestions"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), "let n = 2\nif n == 2 [\n    log \"two\"\n]\n");
+    assert_eq!(stderr.matches("suggestions.hcs: ").count(), 3, "{}", stderr);
+}
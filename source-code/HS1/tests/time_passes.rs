@@ -0,0 +1,25 @@
+use std::process::Command;
+
+#[test]
+fn json_report_lists_every_pass() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("time_passes.hcs");
+    std::fs::write(&input, "--- auto ---\nlet a = 1 + 2\nlog a\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_hs1"))
+        .args(["compile", "--time-passes", "json", "-i"])
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.join("time_passes.bc"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr).expect("stderr is the JSON report");
+    let names: Vec<&str> = report["passes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|pass| pass["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["read", "parse", "lower", "imports", "check", "fold", "inline", "dce", "emit", "write"]);
+    assert!(report["total_ms"].as_f64().unwrap() >= 0.0);
+}
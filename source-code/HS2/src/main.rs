@@ -1,154 +1,234 @@
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process;
 use anyhow::{Context, Result};
-use cranelift_codegen::settings::{self, Configurable};
-use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
-use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::Module;
-use cranelift_native;
-use log::info;
+use clap::Parser;
+use hackerscript_vm::attach;
+use hackerscript_vm::audit::AuditLog;
+use hackerscript_vm::logger::{Destination, LEVEL_VAR};
+use hackerscript_vm::permissions::FLAGS_HELP;
+use hackerscript_vm::serve::{self, Server};
+use hackerscript_vm::trace::Trace;
+use hackerscript_vm::{bundle, load_bytecode, verify, Bytecode, Logger, Math, Permissions, StdHost, Value, ARGS, VM};
 
-// Simple bytecode representation (placeholder; extend as needed for HackerScript)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Opcode {
-    Nop,
-    LoadConst, // Load constant (i32 for simplicity)
-    Add, // Add two i32
-    Log, // Print top of stack
-    Halt,
+#[derive(Parser)]
+#[command(name = "hs2", about = "HackerScript Runtime", version, after_help = notes())]
+struct Cli {
+    /// Bytecode file to run
+    #[arg(required_unless_present = "serve", conflicts_with = "serve")]
+    input: Option<PathBuf>,
+    /// Arguments for the script, in its `args` array
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, requires = "input")]
+    args: Vec<String>,
+    /// Append every privileged operation to a JSON-lines audit log
+    #[arg(long, value_name = "LOG", conflicts_with = "serve")]
+    audit: Option<PathBuf>,
+    /// Send leveled logs to a file instead of stderr
+    #[arg(long, value_name = "FILE", conflicts_with = "syslog")]
+    log_file: Option<PathBuf>,
+    /// Send leveled logs to syslog instead of stderr
+    #[arg(long)]
+    syslog: bool,
+    /// Print (or, with --serve, reply with) the result, the logs and the run
+    /// time as one JSON object
+    #[arg(long, value_enum, default_value = "text")]
+    output: Output,
+    /// Save what the script got from the outside world
+    #[arg(long, value_name = "TRACE", conflicts_with_all = ["replay", "serve"])]
+    record: Option<PathBuf>,
+    /// Feed a --record trace back for a deterministic rerun
+    #[arg(long, value_name = "TRACE", conflicts_with = "serve")]
+    replay: Option<PathBuf>,
+    /// Make `sh` fail with a ProcessError when a command exits nonzero or
+    /// times out
+    #[arg(long, conflicts_with = "serve")]
+    strict_sh: bool,
+    /// Make int overflow, float overflow and NaN `math:` errors instead of
+    /// wrapping and propagating
+    #[arg(long, conflicts_with = "serve")]
+    checked_math: bool,
+    /// Let `hs1 attach` replace the script's functions while it runs, through
+    /// SOCKET (default: hs2-<pid>.sock in the temp directory). Compile the
+    /// script with `hs1 compile --no-opt`, or inlined calls miss the patch
+    #[arg(long, value_name = "SOCKET", num_args = 0..=1, require_equals = true, conflicts_with = "serve")]
+    attachable: Option<Option<PathBuf>>,
+    /// Run bytecode sent by clients that know the token
+    #[arg(long, value_name = "ADDR:PORT")]
+    serve: Option<String>,
+    /// File holding the --serve token
+    #[arg(long, value_name = "FILE", requires = "serve")]
+    token_file: Option<PathBuf>,
+    #[command(flatten)]
+    permissions: PermissionArgs,
 }
-struct Bytecode {
-    code: Vec<u8>,
-    constants: Vec<i32>, // Simple i32 constants for demo
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Output {
+    Text,
+    Json,
 }
-// Simple VM state
-struct VM {
-    stack: Vec<i32>,
-    pc: usize,
+
+/// `--sandbox` and `--allow-*`, as `Permissions::parse_flag` reads them;
+/// `FLAGS_HELP` documents them. A flag without `=` comes back empty and
+/// grants that capability for any target.
+#[derive(clap::Args)]
+struct PermissionArgs {
+    #[arg(long, hide = true)]
+    sandbox: bool,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    allow_read: Vec<String>,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    allow_write: Vec<String>,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    allow_net: Vec<String>,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    allow_run: Vec<String>,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    allow_signal: Vec<String>,
 }
-impl VM {
-    fn new() -> Self {
-        VM { stack: Vec::new(), pc: 0 }
-    }
-    fn run(&mut self, bytecode: &Bytecode) -> Result<()> {
-        loop {
-            if self.pc >= bytecode.code.len() {
-                return Err(anyhow::anyhow!("PC out of bounds"));
-            }
-            let op = match bytecode.code[self.pc] {
-                0 => Opcode::Nop,
-                1 => Opcode::LoadConst,
-                2 => Opcode::Add,
-                3 => Opcode::Log,
-                4 => Opcode::Halt,
-                _ => return Err(anyhow::anyhow!("Unknown opcode")),
-            };
-            self.pc += 1;
-            match op {
-                Opcode::Nop => {},
-                Opcode::LoadConst => {
-                    if self.pc + 4 > bytecode.code.len() {
-                        return Err(anyhow::anyhow!("Incomplete LoadConst"));
-                    }
-                    let const_idx = u32::from_le_bytes([
-                        bytecode.code[self.pc],
-                        bytecode.code[self.pc + 1],
-                        bytecode.code[self.pc + 2],
-                        bytecode.code[self.pc + 3],
-                    ]) as usize;
-                    self.pc += 4;
-                    if const_idx >= bytecode.constants.len() {
-                        return Err(anyhow::anyhow!("Invalid constant index"));
-                    }
-                    self.stack.push(bytecode.constants[const_idx]);
-                }
-                Opcode::Add => {
-                    if self.stack.len() < 2 {
-                        return Err(anyhow::anyhow!("Stack underflow on Add"));
-                    }
-                    let b = self.stack.pop().unwrap();
-                    let a = self.stack.pop().unwrap();
-                    self.stack.push(a + b);
-                }
-                Opcode::Log => {
-                    if self.stack.is_empty() {
-                        return Err(anyhow::anyhow!("Stack underflow on Log"));
-                    }
-                    let val = self.stack.pop().unwrap();
-                    println!("{}", val);
-                }
-                Opcode::Halt => break,
-            }
+
+impl PermissionArgs {
+    fn permissions(&self) -> Result<Permissions> {
+        let grants = [
+            ("read", &self.allow_read),
+            ("write", &self.allow_write),
+            ("net", &self.allow_net),
+            ("run", &self.allow_run),
+            ("signal", &self.allow_signal),
+        ];
+        let flags: Vec<String> = grants
+            .iter()
+            .flat_map(|(name, uses)| {
+                uses.iter().map(move |targets| match targets.as_str() {
+                    "" => format!("--allow-{}", name),
+                    targets => format!("--allow-{}={}", name, targets),
+                })
+            })
+            .collect();
+        if !self.sandbox && flags.is_empty() {
+            return Ok(Permissions::all());
         }
-        Ok(())
-    }
-}
-// Placeholder for loading bytecode from file (simple binary format: [code len u32] [code] [const len u32] [constants as i32 le])
-fn load_bytecode(file_path: &str) -> Result<Bytecode> {
-    let mut file = File::open(file_path).context("Failed to open bytecode file")?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).context("Failed to read bytecode file")?;
-    if buffer.len() < 8 {
-        return Err(anyhow::anyhow!("Bytecode too short"));
-    }
-    let code_len = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
-    let const_len_pos = 4 + code_len;
-    if buffer.len() < const_len_pos + 4 {
-        return Err(anyhow::anyhow!("Incomplete bytecode"));
-    }
-    let const_len = u32::from_le_bytes([buffer[const_len_pos], buffer[const_len_pos + 1], buffer[const_len_pos + 2], buffer[const_len_pos + 3]]) as usize;
-    let const_data_start = const_len_pos + 4;
-    if buffer.len() < const_data_start + const_len * 4 {
-        return Err(anyhow::anyhow!("Incomplete constants"));
-    }
-    let code = buffer[4..4 + code_len].to_vec();
-    let mut constants = Vec::with_capacity(const_len);
-    for i in 0..const_len {
-        let offset = const_data_start + i * 4;
-        let val = i32::from_le_bytes([buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3]]);
-        constants.push(val);
+        let mut permissions = Permissions::none();
+        for flag in &flags {
+            permissions.parse_flag(flag)?;
+        }
+        Ok(permissions)
     }
-    Ok(Bytecode { code, constants })
 }
-// Cranelift integration: Example JIT compilation (for performance; simple func that runs the VM or compiles bytecode to native)
-fn jit_example() -> Result<()> {
-    // Setup Cranelift
-    let mut flag_builder = settings::builder();
-    flag_builder.set("use_colocated_libcalls", "false").unwrap();
-    flag_builder.set("is_pic", "false").unwrap();
-    let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
-        panic!("host machine is not supported: {}", msg);
-    });
-    let isa = isa_builder
-    .finish(settings::Flags::new(flag_builder))
-    .unwrap();
-    let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
-    let module = JITModule::new(builder);
-    // Define a simple function (placeholder: e.g., add two numbers)
-    let mut ctx = module.make_context();
-    let mut func_builder_ctx = FunctionBuilderContext::new();
-    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut func_builder_ctx);
-    // ... Build IR here (skipped for brevity; in real use, translate bytecode to Cranelift IR)
-    // For demo, just log
-    info!("JIT setup complete (placeholder)");
-    Ok(())
+
+fn notes() -> String {
+    format!(
+        "`log.debug` .. `log.error` go to stderr; {} sets the minimum level (default info).\n\
+         --serve reads its token from --token-file or {}.\n\n\
+         Permissions (everything is allowed unless one of these is given):\n{}",
+        LEVEL_VAR,
+        serve::TOKEN_VAR,
+        FLAGS_HELP
+    )
 }
+
 fn main() -> Result<()> {
     env_logger::init();
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: hs2 <bytecode_file.bc>");
-        process::exit(1);
+    // An executable written by `hs1 bundle` carries its script after the
+    // runtime, and every argument is the script's
+    let embedded = env::current_exe()
+        .ok()
+        .and_then(|exe| bundle::read_embedded(&exe).ok().flatten());
+    if let Some(payload) = embedded {
+        let bytecode = Bytecode::from_bytes(&payload).context("Corrupt embedded bytecode")?;
+        let mut vm = VM::new();
+        vm.set_modules(hackerscript_stdlib::bytecode);
+        vm.set_logger(Logger::new(Logger::level_from_env()?, Destination::Stderr));
+        set_args(&mut vm, env::args().skip(1));
+        return run(&bytecode, "embedded bytecode", vm, false);
+    }
+
+    let cli = Cli::parse();
+    let permissions = cli.permissions.permissions()?;
+    let json = cli.output == Output::Json;
+    if let Some(addr) = &cli.serve {
+        return serve_on(addr, cli.token_file.as_deref(), permissions, json);
     }
-    let file_path = &args[1];
-    let bytecode = load_bytecode(file_path)?;
+    let file_path = cli.input.expect("clap requires an input without --serve");
+    let bytecode = load_bytecode(&file_path)?;
     let mut vm = VM::new();
-    vm.run(&bytecode)?;
+    vm.set_modules(hackerscript_stdlib::bytecode);
+    vm.set_permissions(permissions);
+    vm.set_strict_sh(cli.strict_sh);
+    if cli.checked_math {
+        vm.set_math(Math::Checked);
+    }
+    // with --output json, leveled logs are part of the outcome unless sent elsewhere
+    let destination = match (&cli.log_file, cli.syslog) {
+        (Some(path), _) => Destination::file(path)?,
+        (None, true) => Destination::syslog()?,
+        (None, false) if json => Destination::Host,
+        (None, false) => Destination::Stderr,
+    };
+    vm.set_logger(Logger::new(Logger::level_from_env()?, destination));
+    if let Some(path) = &cli.audit {
+        vm.set_audit(AuditLog::open(path)?);
+    }
+    match (&cli.record, &cli.replay) {
+        (Some(path), _) => vm.set_trace(Trace::record_to(path, &bytecode)?),
+        (None, Some(path)) => vm.set_trace(Trace::replay_from(path, &bytecode)?),
+        (None, None) => {}
+    }
+    set_args(&mut vm, cli.args);
+    // removes the socket once the script is done
+    let _listening = match cli.attachable {
+        Some(socket) => {
+            let socket = socket.unwrap_or_else(|| attach::socket_path(process::id()));
+            Some(attach::listen(&socket, vm.patcher())?)
+        }
+        None => None,
+    };
+    run(&bytecode, &file_path.display().to_string(), vm, json)?;
     // Optional JIT (for --- manual --- mode or perf boost; placeholder call)
+    #[cfg(feature = "jit")]
     if false { // Toggle based on mode; not implemented
-        jit_example()?;
+        hackerscript_vm::jit::jit_example()?;
+    }
+    Ok(())
+}
+
+/// Give the script its command-line arguments as `args`.
+fn set_args(vm: &mut VM, args: impl IntoIterator<Item = String>) {
+    vm.set_global(ARGS, Value::Array(args.into_iter().map(Value::from).collect()));
+}
+
+/// `hs2 --serve`: every job gets `permissions`, and leveled logs go back to
+/// the client.
+fn serve_on(addr: &str, token_file: Option<&Path>, permissions: Permissions, json: bool) -> Result<()> {
+    let token = match token_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read token file {}", path.display()))?
+            .trim_end()
+            .to_string(),
+        None => env::var(serve::TOKEN_VAR).unwrap_or_default(),
+    };
+    if token.is_empty() {
+        anyhow::bail!("--serve needs a token (--token-file or {})", serve::TOKEN_VAR);
+    }
+    let mut server = Server::new(token);
+    server.set_permissions(permissions);
+    server.set_modules(hackerscript_stdlib::bytecode);
+    server.set_level(Logger::level_from_env()?);
+    server.set_outcomes(json);
+    let listener = std::net::TcpListener::bind(addr).with_context(|| format!("Cannot listen on {}", addr))?;
+    eprintln!("Serving on {}", listener.local_addr()?);
+    server.serve(listener)
+}
+
+fn run(bytecode: &Bytecode, name: &str, mut vm: VM, json: bool) -> Result<()> {
+    verify(bytecode).with_context(|| format!("{} failed verification", name))?;
+    if !json {
+        return vm.run(bytecode, &mut StdHost);
+    }
+    let outcome = vm.run_outcome(bytecode, &mut StdHost);
+    println!("{}", outcome.to_json());
+    if !outcome.is_ok() {
+        process::exit(1);
     }
     Ok(())
 }
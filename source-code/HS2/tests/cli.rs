@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn hs2(program: &Path, args: &[&str]) -> Output {
+    Command::new(program).args(args).env("RUST_BACKTRACE", "0").output().unwrap()
+}
+
+/// `source` compiled to `name.bc` in a fresh directory.
+fn compile(name: &str, source: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join(format!("{}.hcs", name));
+    std::fs::write(&script, source).unwrap();
+    let output = dir.join(format!("{}.bc", name));
+    std::fs::write(&output, hs1::compile_file(&script, false).unwrap().bytecode.to_bytes()).unwrap();
+    output
+}
+
+#[test]
+fn help_is_not_a_script_path() {
+    let output = hs2(env!("CARGO_BIN_EXE_hs2").as_ref(), &["--help"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let help = String::from_utf8(output.stdout).unwrap();
+    assert!(help.contains("--allow-read[=PATH,..]"), "{}", help);
+
+    let output = hs2(env!("CARGO_BIN_EXE_hs2").as_ref(), &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn a_script_gets_the_arguments_after_it() {
+    let bytecode = compile("args", "log args\n");
+    let bytecode = bytecode.to_str().unwrap();
+
+    // everything after the script is its own, flags included
+    let output = hs2(env!("CARGO_BIN_EXE_hs2").as_ref(), &["--sandbox", bytecode, "you", "--sandbox"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, b"[\"you\", \"--sandbox\"]\n");
+
+    let output = hs2(env!("CARGO_BIN_EXE_hs2").as_ref(), &[bytecode]);
+    assert_eq!(output.stdout, b"[]\n");
+}
+
+#[test]
+fn a_bundled_script_gets_every_argument() {
+    let bytecode = compile("bundled_args", "log args\n");
+    let runtime = std::fs::read(env!("CARGO_BIN_EXE_hs2")).unwrap();
+    let exe = bytecode.with_extension("exe");
+    let bundle = hackerscript_bytecode::bundle::append(&runtime, &std::fs::read(&bytecode).unwrap());
+    std::fs::write(&exe, bundle).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let output = hs2(&exe, &["--help", "x"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(output.stdout, b"[\"--help\", \"x\"]\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn attach_replaces_a_function_while_the_script_runs() {
+    // bounded, so a patch that never lands fails the test instead of hanging it
+    let script = "func waiting() [
+    return true
+]
+let n = 0
+while waiting() && n < 100000000 [
+    let n = n + 1
+]
+log waiting()
+";
+    let bytecode = compile("attachable", script);
+    let socket = bytecode.with_extension("sock");
+    let child = Command::new(env!("CARGO_BIN_EXE_hs2"))
+        .arg(format!("--attachable={}", socket.display()))
+        .arg(&bytecode)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let patch = bytecode.with_file_name("patch.hcs");
+    std::fs::write(&patch, "func waiting() [\n    return false\n]\n").unwrap();
+    let patch = hs1::compile_file(&patch, false).unwrap().bytecode.to_bytes();
+    let mut patched = hackerscript_vm::attach::attach(&socket, &patch);
+    for _ in 0..100 {
+        if patched.is_ok() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        patched = hackerscript_vm::attach::attach(&socket, &patch);
+    }
+    assert_eq!(patched.unwrap(), ["waiting"]);
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"false\n");
+    assert!(!socket.exists());
+}
@@ -1,11 +1,8 @@
 use std::env;
 use std::fs;
 use std::process;
-use pest::Parser;
+use hackerscript_parser::{parse_tree, Rule};
 use pest::error::Error;
-#[derive(pest_derive::Parser)]
-#[grammar = "hackerscript.pest"]
-pub struct HackerScriptParser;
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() != 2 {
@@ -20,7 +17,7 @@ fn main() {
             process::exit(1);
         }
     };
-    match HackerScriptParser::parse(Rule::program, &code) {
+    match parse_tree(&code) {
         Ok(pairs) => {
             // Since no AST is wanted, just print the parse pairs for debugging/inspection
             println!("Parse successful. Pairs:");
@@ -30,7 +27,7 @@ fn main() {
         }
         Err(err) => {
             // Raw error output; diagnostics handled by HSDF separately
-            eprintln!("Parse error:\n{}", format_error(err, &code));
+            eprintln!("Parse error:\n{}", format_error(*err, &code));
             process::exit(1);
         }
     }
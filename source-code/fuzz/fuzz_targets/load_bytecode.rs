@@ -0,0 +1,12 @@
+#![no_main]
+// Reading a .bc file and verifying it must reject malformed input with an
+// error, never panic.
+use hackerscript_bytecode::{disassemble, verify, Bytecode};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(bytecode) = Bytecode::from_bytes(data) {
+        let _ = verify(&bytecode);
+        let _ = disassemble(&bytecode);
+    }
+});
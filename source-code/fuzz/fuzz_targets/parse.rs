@@ -0,0 +1,12 @@
+#![no_main]
+// The parser must return an error, never panic, whatever the input.
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: &str| {
+    let _ = hackerscript_parser::parse(source);
+    for item in hackerscript_parser::stream::StmtStream::new(source.as_bytes()) {
+        if item.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,26 @@
+#![no_main]
+// Bytecode that passes `verify` must run to completion or fail with an
+// error. `data` is the raw code section over a fixed constant pool; chunks
+// with backward jumps or `try` handlers are skipped, since loops can
+// legitimately run forever.
+use hackerscript_bytecode::{read_u32, verify, Bytecode, Opcode};
+use hackerscript_vm::{BufferHost, VM};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let bytecode = Bytecode {
+        code: data.to_vec(),
+        constants: vec!["a", "b", ""].into(),
+        debug: None,
+    };
+    if verify(&bytecode).is_err() {
+        return;
+    }
+    let loops = bytecode.instructions().any(|(at, op)| {
+        matches!(op, Opcode::Jump | Opcode::JumpIfFalse | Opcode::Try)
+            && read_u32(&bytecode.code, at + 1).is_some_and(|target| target as usize <= at)
+    });
+    if !loops {
+        let _ = VM::new().run(&bytecode, &mut BufferHost::default());
+    }
+});
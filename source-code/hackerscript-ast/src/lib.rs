@@ -0,0 +1,779 @@
+//! HackerScript abstract syntax tree.
+//!
+//! Produced by `hackerscript-parser`, consumed by the bytecode and native
+//! backends. Every tool works on these types instead of raw pest pairs.
+//!
+//! The serde representation is part of the public contract: statements are
+//! internally tagged with `"kind"` and every name is snake_case, so a JSON
+//! dump from one tool reads back in any other. Bump `AST_VERSION` whenever
+//! a change would break that.
+//!
+//! Every statement carries the `Span` it was parsed from; see `Spanned`.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// Version of the serialized AST layout.
+pub const AST_VERSION: u32 = 24;
+
+/// An identifier. The parser interns these, so every occurrence of a name in
+/// one file shares a single allocation and cloning is a reference-count bump.
+/// Serializes as a plain string.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol(s.into())
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Symbol(s.into())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where a node came from: byte offsets into its source file and the
+/// 1-based line it starts on. Nodes built by tools rather than the parser
+/// have the default span, line 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+    pub line: u32,
+}
+
+impl Span {
+    pub fn is_unknown(&self) -> bool {
+        self.line == 0
+    }
+}
+
+/// A node and its span. Compares and prints as the node alone, so the same
+/// code written on different lines is equal. Serializes as the node with a
+/// `"span"` field added.
+///
+/// Only statements are spanned; an error in an expression is reported at
+/// the statement holding it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Spanned<T> {
+    #[serde(flatten)]
+    pub node: T,
+    #[serde(default, skip_serializing_if = "Span::is_unknown")]
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span }
+    }
+
+    /// Replace the node, keeping the span.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned { node: f(self.node), span: self.span }
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(node: T) -> Self {
+        Spanned { node, span: Span::default() }
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T> DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: PartialEq> PartialEq<T> for Spanned<T> {
+    fn eq(&self, other: &T) -> bool {
+        self.node == *other
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+/// Call `visit` with the span of every statement in `body`, including those
+/// in nested blocks, function bodies and lambdas.
+pub fn visit_spans_mut(body: &mut [Spanned<Stmt>], visit: &mut impl FnMut(&mut Span)) {
+    for stmt in body {
+        visit(&mut stmt.span);
+        let mut exprs: Vec<&mut Expr> = Vec::new();
+        match &mut stmt.node {
+            Stmt::Func(func) => {
+                exprs.extend(func.params.iter_mut().filter_map(|param| param.default.as_mut()));
+                visit_spans_mut(&mut func.body, visit);
+            }
+            Stmt::Object { body, .. } | Stmt::While { body, .. } => visit_spans_mut(body, visit),
+            Stmt::If { cond, then_body, else_body } => {
+                exprs.push(cond);
+                visit_spans_mut(then_body, visit);
+                visit_spans_mut(else_body, visit);
+            }
+            Stmt::Match { subject, cases, default } => {
+                exprs.push(subject);
+                for case in cases {
+                    exprs.push(&mut case.value);
+                    visit_spans_mut(&mut case.body, visit);
+                }
+                visit_spans_mut(default, visit);
+            }
+            Stmt::Try { body, except, finally } => {
+                visit_spans_mut(body, visit);
+                if let Some(except) = except {
+                    visit_spans_mut(&mut except.body, visit);
+                }
+                visit_spans_mut(finally, visit);
+            }
+            Stmt::Let { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::Const { value, .. }
+            | Stmt::Expr { value }
+            | Stmt::Throw { value } => exprs.push(value),
+            Stmt::Return { value } => exprs.extend(value),
+            Stmt::Log { values, .. } => exprs.extend(values),
+            _ => {}
+        }
+        for expr in exprs {
+            lambda_spans_mut(expr, visit);
+        }
+    }
+}
+
+fn lambda_spans_mut(expr: &mut Expr, visit: &mut impl FnMut(&mut Span)) {
+    match expr {
+        Expr::Lambda { params, body, .. } => {
+            for default in params.iter_mut().filter_map(|param| param.default.as_mut()) {
+                lambda_spans_mut(default, visit);
+            }
+            visit_spans_mut(body, visit);
+        }
+        Expr::Call { args, .. } => args.iter_mut().for_each(|arg| lambda_spans_mut(arg, visit)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            lambda_spans_mut(lhs, visit);
+            lambda_spans_mut(rhs, visit);
+        }
+        Expr::Map { entries } => entries.iter_mut().for_each(|(_, value)| lambda_spans_mut(value, visit)),
+        Expr::Slice { target, start, end } => {
+            lambda_spans_mut(target, visit);
+            start.iter_mut().chain(end).for_each(|bound| lambda_spans_mut(bound, visit));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| lambda_spans_mut(part, visit))
+        }
+        Expr::Lit(_) | Expr::Var { .. } => {}
+    }
+}
+
+/// Call `visit` with every expression in `body`, each before the ones
+/// inside it, including those in nested blocks, function bodies and lambdas.
+pub fn visit_exprs_mut(body: &mut [Spanned<Stmt>], visit: &mut impl FnMut(&mut Expr)) {
+    for stmt in body {
+        let mut exprs: Vec<&mut Expr> = Vec::new();
+        match &mut stmt.node {
+            Stmt::Func(func) => {
+                exprs.extend(func.params.iter_mut().filter_map(|param| param.default.as_mut()));
+                visit_exprs_mut(&mut func.body, visit);
+            }
+            Stmt::Object { body, .. } => visit_exprs_mut(body, visit),
+            Stmt::While { cond, body } => {
+                exprs.push(cond);
+                visit_exprs_mut(body, visit);
+            }
+            Stmt::If { cond, then_body, else_body } => {
+                exprs.push(cond);
+                visit_exprs_mut(then_body, visit);
+                visit_exprs_mut(else_body, visit);
+            }
+            Stmt::Match { subject, cases, default } => {
+                exprs.push(subject);
+                for case in cases {
+                    exprs.push(&mut case.value);
+                    visit_exprs_mut(&mut case.body, visit);
+                }
+                visit_exprs_mut(default, visit);
+            }
+            Stmt::Try { body, except, finally } => {
+                visit_exprs_mut(body, visit);
+                if let Some(except) = except {
+                    visit_exprs_mut(&mut except.body, visit);
+                }
+                visit_exprs_mut(finally, visit);
+            }
+            Stmt::Let { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::Const { value, .. }
+            | Stmt::Expr { value }
+            | Stmt::Throw { value } => exprs.push(value),
+            Stmt::Return { value } => exprs.extend(value),
+            Stmt::Log { values, .. } => exprs.extend(values),
+            _ => {}
+        }
+        for expr in exprs {
+            visit_expr_mut(expr, visit);
+        }
+    }
+}
+
+fn visit_expr_mut(expr: &mut Expr, visit: &mut impl FnMut(&mut Expr)) {
+    visit(expr);
+    match expr {
+        Expr::Lambda { params, body, .. } => {
+            for default in params.iter_mut().filter_map(|param| param.default.as_mut()) {
+                visit_expr_mut(default, visit);
+            }
+            visit_exprs_mut(body, visit);
+        }
+        Expr::Call { args, .. } => args.iter_mut().for_each(|arg| visit_expr_mut(arg, visit)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            visit_expr_mut(lhs, visit);
+            visit_expr_mut(rhs, visit);
+        }
+        Expr::Map { entries } => entries.iter_mut().for_each(|(_, value)| visit_expr_mut(value, visit)),
+        Expr::Slice { target, start, end } => {
+            visit_expr_mut(target, visit);
+            start.iter_mut().chain(end).for_each(|bound| visit_expr_mut(bound, visit));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| visit_expr_mut(part, visit))
+        }
+        Expr::Lit(_) | Expr::Var { .. } => {}
+    }
+}
+
+/// A whole `.hcs` file.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Program {
+    /// `--- edition 2025 ---` header or the project's edition, if not the
+    /// default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edition: Option<Edition>,
+    /// `--- auto ---` / `--- manual ---` header, if present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mode: Option<MemoryMode>,
+    /// `--- strict ---` header or `--strict`: `hackerscript_codegen::strict`
+    /// checks the file before its imports are expanded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryMode {
+    /// `--- auto ---` or `--- automatic ---`: a value is freed when its last
+    /// owner drops it. Values are trees (a lambda captures copies), so there
+    /// are no cycles to collect and function values are reference counted.
+    Auto,
+    /// `--- manual ---`: as `auto`, but the code may also release a value
+    /// early with `free(name)`, which leaves the variable `null`
+    Manual,
+}
+
+/// The call that releases a variable's value in `manual` code; a script
+/// that defines its own `free` calls that instead.
+pub const FREE: &str = "free";
+
+/// The version of the language a file is written in. A later edition may
+/// reserve words an earlier one allowed as names; files without a header
+/// or a project edition are `E2024`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum Edition {
+    #[default]
+    #[serde(rename = "2024")]
+    E2024,
+    /// Keywords can no longer be used as names
+    #[serde(rename = "2025")]
+    E2025,
+}
+
+/// Words `Edition::E2025` reserves. `asm` and `sh` stay usable as names,
+/// since `sh` is also a native function.
+const RESERVED_2025: &[&str] = &[
+    "break", "case", "const", "continue", "default", "else", "enum", "except", "extends", "false", "finally", "func", "if",
+    "implements", "import", "interface", "let", "log", "match", "null", "object", "pub", "require", "return", "throw", "true",
+    "try", "while",
+];
+
+impl Edition {
+    pub const LATEST: Edition = Edition::E2025;
+    pub const ALL: &'static [Edition] = &[Edition::E2024, Edition::E2025];
+
+    pub fn year(self) -> &'static str {
+        match self {
+            Edition::E2024 => "2024",
+            Edition::E2025 => "2025",
+        }
+    }
+
+    /// The keywords that cannot be names in this edition.
+    pub fn reserved(self) -> &'static [&'static str] {
+        match self {
+            Edition::E2024 => &[],
+            Edition::E2025 => RESERVED_2025,
+        }
+    }
+
+    pub fn reserves(self, word: &str) -> bool {
+        self.reserved().contains(&word)
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.year())
+    }
+}
+
+impl std::str::FromStr for Edition {
+    type Err = String;
+
+    fn from_str(year: &str) -> Result<Self, String> {
+        Edition::ALL.iter().copied().find(|edition| edition.year() == year).ok_or_else(|| {
+            let years: Vec<&str> = Edition::ALL.iter().map(|edition| edition.year()).collect();
+            format!("unknown edition `{}` (expected {})", year, years.join(" or "))
+        })
+    }
+}
+
+/// Severity of `log.debug` / `log.info` / `log.warn` / `log.error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Stmt {
+    /// `import <repo:lib>`
+    Import { repo: Symbol, lib: Symbol },
+    /// `import <app.utils.net>`: a module under the project's `src` directory
+    /// or on `HS_PATH`
+    ImportModule { path: Vec<Symbol> },
+    /// `require <path>`
+    Require { path: String },
+    Func(Func),
+    /// `pub? object Name [ ... ]`, optionally `extends Parent` and then
+    /// `implements A, B`. A `pub` object's typed `let`s are the fields of a
+    /// C struct in the native header.
+    Object {
+        #[serde(default)]
+        public: bool,
+        name: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent: Option<Symbol>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        implements: Vec<Symbol>,
+        body: Vec<Spanned<Stmt>>,
+    },
+    Interface(Interface),
+    /// `let name = expr` or `let name: type = expr`
+    Let {
+        name: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ty: Option<Symbol>,
+        value: Expr,
+    },
+    /// `let [a, b] = pair` or `let {x, y} = point`
+    Destructure { pattern: Pattern, value: Expr },
+    /// `pub? const NAME = expr` or `pub? const NAME: type = expr`. The
+    /// checker rejects any later binding of NAME in its scope. There is no
+    /// `freeze`: maps and arrays are values, copied when bound or passed, and
+    /// nothing in the language writes into one in place.
+    Const {
+        #[serde(default)]
+        public: bool,
+        name: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ty: Option<Symbol>,
+        value: Expr,
+    },
+    Enum(Enum),
+    /// `if cond [ ... ] else [ ... ]`; `else if` nests another `If` in `else_body`
+    If {
+        cond: Expr,
+        then_body: Vec<Spanned<Stmt>>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        else_body: Vec<Spanned<Stmt>>,
+    },
+    /// `while cond [ ... ]`
+    While { cond: Expr, body: Vec<Spanned<Stmt>> },
+    /// `match subject [ case value [ ... ] default [ ... ] ]`: runs the first
+    /// case whose value equals `subject`, or `default`
+    Match {
+        subject: Expr,
+        cases: Vec<Case>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        default: Vec<Spanned<Stmt>>,
+    },
+    /// `break`: leave the innermost loop
+    Break,
+    /// `continue`: skip to the innermost loop's next iteration
+    Continue,
+    /// `return` or `return expr`
+    Return {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<Expr>,
+    },
+    /// `log expr, expr, ...`: the values separated by spaces on one line.
+    /// `log.warn ...` and the other levels are diagnostics rather than output.
+    Log {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        level: Option<LogLevel>,
+        values: Vec<Expr>,
+    },
+    /// An expression evaluated for its side effects, e.g. a call
+    Expr { value: Expr },
+    /// `asm [ ... ]`: bytecode written by hand, as the assembler source
+    Asm { code: String },
+    /// `try [ ... ] except err [ ... ] finally [ ... ]`: if `body` fails,
+    /// `except` runs with the error; `finally` runs last either way. At
+    /// least one of the two is present.
+    Try {
+        body: Vec<Spanned<Stmt>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        except: Option<Except>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        finally: Vec<Spanned<Stmt>>,
+    },
+    /// `throw expr`: fail with an error the nearest `except` catches
+    Throw { value: Expr },
+    /// `sh [ ... ]`: the commands of a shell block, one per line, run as a
+    /// single script
+    Sh { commands: Vec<String> },
+}
+
+/// The names a destructuring `let` or a `match` arm binds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Pattern {
+    /// `[a, b]` or `[a, ...rest]`: elements by position, and a copy of the
+    /// ones after them
+    Array {
+        names: Vec<Symbol>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rest: Option<Symbol>,
+    },
+    /// `{x, y}`: the entries keyed by the names
+    Map { names: Vec<Symbol> },
+}
+
+impl Pattern {
+    /// Every name bound, in order.
+    pub fn names(&self) -> impl Iterator<Item = &Symbol> {
+        match self {
+            Pattern::Array { names, rest } => names.iter().chain(rest.as_ref()),
+            Pattern::Map { names } => names.iter().chain(None),
+        }
+    }
+}
+
+/// `except err [ ... ]` in a `try`; the name is optional
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Except {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Symbol>,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+/// `case value [ ... ]` in a `match`, or `case [first, ...rest] [ ... ]`
+/// and `case {host, port} [ ... ]`, which match an array or map of that
+/// shape and bind its parts for the body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    /// What the subject must equal; `null`, and unused, with a pattern
+    pub value: Expr,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<Pattern>,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+impl Case {
+    /// `case value [ ... ]`
+    pub fn value(value: Expr, body: Vec<Spanned<Stmt>>) -> Self {
+        Case { value, pattern: None, body }
+    }
+
+    /// `case <pattern> [ ... ]`
+    pub fn pattern(pattern: Pattern, body: Vec<Spanned<Stmt>>) -> Self {
+        Case { value: Expr::Lit(Lit::Null), pattern: Some(pattern), body }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Expr {
+    Lit(Lit),
+    Var { name: Symbol },
+    /// `name(args)`
+    Call { callee: Symbol, args: Vec<Expr> },
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// `{ "key": value, ... }`; a repeated key keeps the last value
+    Map { entries: Vec<(String, Expr)> },
+    /// `target[index]`: a map by string key, or an array or string by
+    /// position (negative positions count from the end)
+    Index { target: Box<Expr>, index: Box<Expr> },
+    /// `target[start:end]`: a copy of part of an array or string; either
+    /// bound may be left out
+    Slice {
+        target: Box<Expr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<Box<Expr>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<Box<Expr>>,
+    },
+    /// `cond ? then_value : else_value`: only the chosen value is evaluated
+    Ternary {
+        cond: Box<Expr>,
+        then_value: Box<Expr>,
+        else_value: Box<Expr>,
+    },
+    /// `func (params) [ ... ]`: a function value that captures the
+    /// variables in scope where it is created
+    Lambda {
+        params: Vec<Param>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ret: Option<Symbol>,
+        body: Vec<Spanned<Stmt>>,
+    },
+}
+
+impl Expr {
+    pub fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Lit {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// `&&`: `lhs` if it is falsy, otherwise `rhs` (which is only evaluated then)
+    And,
+    /// `||`: `lhs` if it is truthy, otherwise `rhs`
+    Or,
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+/// `pub? func name(params): Ret inline? mode? [ body ]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Func {
+    #[serde(default)]
+    pub public: bool,
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ret: Option<Symbol>,
+    /// `func f() inline [ ... ]`: inline calls to it whatever its size
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inline: bool,
+    /// `func hot() manual [ ... ]`: the memory mode inside this function and
+    /// its lambdas, instead of the file's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mode: Option<MemoryMode>,
+    pub body: Vec<Spanned<Stmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: Symbol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ty: Option<Symbol>,
+    /// `name = expr`: the value when a call leaves the argument out. Only
+    /// trailing parameters have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Expr>,
+    /// `...name`: an array of the arguments after the other parameters.
+    /// Only the last parameter can be one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rest: bool,
+}
+
+impl Func {
+    /// Parameters every call must pass, i.e. those without a default.
+    pub fn required_params(&self) -> usize {
+        self.params.iter().take_while(|p| p.default.is_none() && !p.rest).count()
+    }
+
+    /// The `...rest` parameter, if any.
+    pub fn rest_param(&self) -> Option<&Param> {
+        self.params.last().filter(|p| p.rest)
+    }
+}
+
+/// `interface Name [ func method(a, b) ]`: the methods an `object` that
+/// implements it must define. Only the checker reads it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interface {
+    pub name: Symbol,
+    pub methods: Vec<Method>,
+}
+
+/// A method an `Interface` requires: a `func` without a body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Method {
+    pub name: Symbol,
+    pub params: Vec<Param>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ret: Option<Symbol>,
+}
+
+/// `pub? enum Name [ A, B = 10, C ]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Enum {
+    #[serde(default)]
+    pub public: bool,
+    pub name: Symbol,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: Symbol,
+    /// Explicit discriminant (`B = 10`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<i64>,
+}
+
+impl Enum {
+    /// Each variant with its discriminant. As in C, a variant without an
+    /// explicit value is one more than the previous variant, starting at 0.
+    pub fn discriminants(&self) -> impl Iterator<Item = (&Symbol, i64)> + '_ {
+        let mut next = 0i64;
+        self.variants.iter().map(move |variant| {
+            let value = variant.value.unwrap_or(next);
+            next = value.wrapping_add(1);
+            (&variant.name, value)
+        })
+    }
+}
@@ -0,0 +1,67 @@
+//! Bytecode appended to a copy of the runtime by `hs1 bundle`, so a script
+//! ships as one executable. At startup hs2 looks for the trailer at the end
+//! of its own file and runs the embedded chunk instead of reading arguments.
+//!
+//! Layout: `[runtime executable] [payload: a .bc file] [payload len u64 LE] ["HSBUNDLE"]`
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const TRAILER_MAGIC: &[u8; 8] = b"HSBUNDLE";
+const TRAILER_LEN: u64 = 16;
+
+/// `runtime` with `payload` embedded. A runtime that is itself a bundle has
+/// its old payload replaced.
+pub fn append(runtime: &[u8], payload: &[u8]) -> Vec<u8> {
+    let runtime = match payload_range(runtime) {
+        Some((start, _)) => &runtime[..start],
+        None => runtime,
+    };
+    let mut out = Vec::with_capacity(runtime.len() + payload.len() + TRAILER_LEN as usize);
+    out.extend_from_slice(runtime);
+    out.extend_from_slice(payload);
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(TRAILER_MAGIC);
+    out
+}
+
+/// The payload embedded in an in-memory executable, if any.
+pub fn payload(exe: &[u8]) -> Option<&[u8]> {
+    payload_range(exe).map(|(start, end)| &exe[start..end])
+}
+
+fn payload_range(exe: &[u8]) -> Option<(usize, usize)> {
+    let trailer_start = exe.len().checked_sub(TRAILER_LEN as usize)?;
+    let trailer = &exe[trailer_start..];
+    if &trailer[8..] != TRAILER_MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(trailer[..8].try_into().ok()?);
+    let start = trailer_start.checked_sub(usize::try_from(len).ok()?)?;
+    Some((start, trailer_start))
+}
+
+/// The payload embedded in the executable at `path`, reading only the
+/// trailer and the payload rather than the whole runtime.
+pub fn read_embedded(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let size = file.metadata()?.len();
+    if size < TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::Start(size - TRAILER_LEN))?;
+    file.read_exact(&mut trailer)?;
+    if &trailer[8..] != TRAILER_MAGIC {
+        return Ok(None);
+    }
+    let len = u64::from_le_bytes(trailer[..8].try_into().expect("8-byte slice"));
+    let start = (size - TRAILER_LEN)
+        .checked_sub(len)
+        .context("Corrupt bundle: payload length exceeds the file")?;
+    let mut payload = vec![0u8; usize::try_from(len).context("Bundle payload too large")?];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
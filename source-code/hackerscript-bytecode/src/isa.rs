@@ -0,0 +1,518 @@
+//! The instruction set, defined once. `opcodes!` turns each entry into an
+//! `Opcode` variant, its byte and mnemonic, an `Instruction` variant with a
+//! field per operand, and a row of `ISA`: the operands that follow the
+//! opcode byte, the stack effect and what it does. The emitter, the
+//! disassembler and the VM go through `Instruction::encode` and
+//! `Instruction::decode`; `instruction_len`, the verifier and `hs1 isa` read
+//! the table, so an instruction added here is documented and decodable at once.
+use std::fmt::Write as _;
+
+use crate::{read_f64, read_i64, read_u32, read_varint, write_varint, zigzag, Dispatch, FuncHeader, LogLevel};
+
+/// What follows an opcode byte, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// Varint index of a string constant
+    Const,
+    /// Varint index of the constant holding a name
+    Name,
+    /// Varint local slot
+    Slot,
+    /// Varint count of stack values
+    Count,
+    /// Varint index into `NATIVES`
+    Native,
+    /// Zigzag varint i64
+    Int,
+    /// f64 bits, 8 bytes
+    Float,
+    /// u8 argument count
+    Argc,
+    /// u8 `LogLevel`
+    Level,
+    /// u32 absolute offset, fixed width so it can be patched
+    Target,
+    /// `FuncHeader`
+    Func,
+    /// Zigzag varint low, varint count n, u32 default, n u32 targets
+    Targets,
+    /// Varint count n, u32 default, n (u32 constant index, u32 target)
+    /// sorted by string
+    StringTargets,
+}
+
+impl Operand {
+    pub fn name(self) -> &'static str {
+        match self {
+            Operand::Const => "const",
+            Operand::Name => "name",
+            Operand::Slot => "slot",
+            Operand::Count => "count",
+            Operand::Native => "native",
+            Operand::Int => "int",
+            Operand::Float => "float",
+            Operand::Argc => "argc",
+            Operand::Level => "level",
+            Operand::Target => "target",
+            Operand::Func => "func",
+            Operand::Targets => "targets",
+            Operand::StringTargets => "string_targets",
+        }
+    }
+
+    /// How the operand is encoded.
+    pub fn encoding(self) -> &'static str {
+        match self {
+            Operand::Const | Operand::Name | Operand::Slot | Operand::Count | Operand::Native => "varint",
+            Operand::Int => "zigzag varint",
+            Operand::Float => "f64, 8 bytes",
+            Operand::Argc | Operand::Level => "u8",
+            Operand::Target => "u32",
+            Operand::Func => "u32 entry, varint name, varint slots, u8 params, u8 required, u8 flags",
+            Operand::Targets => "zigzag varint low, varint n, u32 default, n u32",
+            Operand::StringTargets => "varint n, u32 default, n (u32 constant, u32 target)",
+        }
+    }
+
+    /// Bytes the operand at `at` takes, or `None` if it runs past the end
+    /// of `code`.
+    pub fn len(self, code: &[u8], at: usize) -> Option<usize> {
+        let len = match self {
+            Operand::Const | Operand::Name | Operand::Slot | Operand::Count | Operand::Native => {
+                crate::read_varint(code, at)?.1
+            }
+            Operand::Int => crate::read_i64(code, at)?.1,
+            Operand::Float => 8,
+            Operand::Argc | Operand::Level => 1,
+            Operand::Target => 4,
+            Operand::Func => crate::FuncHeader::read(code, at)?.1,
+            Operand::Targets | Operand::StringTargets => {
+                let (entry, low_len) = match self {
+                    Operand::Targets => (4, crate::read_i64(code, at)?.1),
+                    _ => (8, 0),
+                };
+                let (count, count_len) = crate::read_varint(code, at + low_len)?;
+                let table = usize::try_from(count).ok()?.checked_mul(entry)?.checked_add(4)?;
+                (low_len + count_len).checked_add(table)?
+            }
+        };
+        (at.checked_add(len)? <= code.len()).then_some(len)
+    }
+}
+
+/// One instruction of `ISA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpInfo {
+    pub opcode: Opcode,
+    pub operands: &'static [Operand],
+    /// Values popped and pushed, Forth style: `a b -- a + b`
+    pub stack: &'static str,
+    pub summary: &'static str,
+}
+
+macro_rules! opcodes {
+    ($(#[doc = $summary:literal] $name:ident = $byte:literal, $mnemonic:literal, [$($field:ident: $operand:ident),*], $stack:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        pub enum Opcode {
+            $(#[doc = $summary] $name = $byte,)*
+        }
+
+        impl Opcode {
+            pub fn from_byte(byte: u8) -> Option<Self> {
+                Some(match byte {
+                    $($byte => Opcode::$name,)*
+                    _ => return None,
+                })
+            }
+
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(Opcode::$name => $mnemonic,)*
+                }
+            }
+        }
+
+        instruction_enum!([] $($summary $name [$($field: $operand),*])*);
+
+        impl<'a> Instruction<'a> {
+            pub fn opcode(&self) -> Opcode {
+                match self {
+                    $(Instruction::$name { .. } => Opcode::$name,)*
+                }
+            }
+
+            /// The instruction at `pos` and how many bytes it takes, or
+            /// `None` if the opcode is unknown or an operand is invalid or
+            /// runs past the end of `code`.
+            pub fn decode(code: &'a [u8], pos: usize) -> Option<(Self, usize)> {
+                let mut at = pos + 1;
+                let instruction = match Opcode::from_byte(*code.get(pos)?)? {
+                    $(Opcode::$name => Instruction::$name { $($field: Codec::decode(Operand::$operand, code, &mut at)?),* },)*
+                };
+                Some((instruction, at - pos))
+            }
+
+            /// Append the opcode byte and the operands to `out`.
+            pub fn encode(&self, out: &mut Vec<u8>) {
+                out.push(self.opcode() as u8);
+                match self {
+                    $(Instruction::$name { $($field),* } => { $(Codec::encode($field, out);)* })*
+                }
+            }
+        }
+
+        /// Every instruction, by opcode byte.
+        pub const ISA: &[OpInfo] = &[
+            $(OpInfo { opcode: Opcode::$name, operands: &[$(Operand::$operand),*], stack: $stack, summary: $summary.trim_ascii() },)*
+        ];
+    };
+}
+
+/// `Instruction`, a unit variant for each opcode without operands.
+macro_rules! instruction_enum {
+    ([$($variants:tt)*]) => {
+        /// An instruction with its operands, fields named as in the table
+        /// of `opcodes!`.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum Instruction<'a> {
+            $($variants)*
+        }
+    };
+    ([$($variants:tt)*] $summary:literal $name:ident [] $($rest:tt)*) => {
+        instruction_enum!([$($variants)* #[doc = $summary] $name,] $($rest)*);
+    };
+    ([$($variants:tt)*] $summary:literal $name:ident [$($field:ident: $operand:ident),+] $($rest:tt)*) => {
+        instruction_enum!([$($variants)* #[doc = $summary] $name { $($field: operand_type!($operand)),+ },] $($rest)*);
+    };
+}
+
+/// The `Instruction` field type of an operand.
+macro_rules! operand_type {
+    (Const) => { u64 };
+    (Name) => { u64 };
+    (Slot) => { u64 };
+    (Count) => { u64 };
+    (Native) => { u64 };
+    (Int) => { i64 };
+    (Float) => { f64 };
+    (Argc) => { u8 };
+    (Level) => { LogLevel };
+    (Target) => { u32 };
+    (Func) => { FuncHeader };
+    (Targets) => { Dispatch<'a> };
+    (StringTargets) => { Dispatch<'a> };
+}
+
+/// How an `Instruction` field is read from and written to code.
+trait Codec<'a>: Sized {
+    /// The operand at `*at`, moving `*at` past it.
+    fn decode(operand: Operand, code: &'a [u8], at: &mut usize) -> Option<Self>;
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+impl Codec<'_> for u64 {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let (value, len) = read_varint(code, *at)?;
+        *at += len;
+        Some(value)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self);
+    }
+}
+
+impl Codec<'_> for i64 {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let (value, len) = read_i64(code, *at)?;
+        *at += len;
+        Some(value)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        write_varint(out, zigzag(*self));
+    }
+}
+
+impl Codec<'_> for f64 {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let value = read_f64(code, *at)?;
+        *at += 8;
+        Some(value)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bits().to_le_bytes());
+    }
+}
+
+impl Codec<'_> for u8 {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let value = *code.get(*at)?;
+        *at += 1;
+        Some(value)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Codec<'_> for LogLevel {
+    fn decode(operand: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let level = LogLevel::from_byte(*code.get(*at)?)?;
+        *at += operand.len(code, *at)?;
+        Some(level)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Codec<'_> for u32 {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let value = read_u32(code, *at)?;
+        *at += 4;
+        Some(value)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Codec<'_> for FuncHeader {
+    fn decode(_: Operand, code: &[u8], at: &mut usize) -> Option<Self> {
+        let (header, len) = FuncHeader::read(code, *at)?;
+        *at += len;
+        Some(header)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.entry.to_le_bytes());
+        write_varint(out, self.name);
+        write_varint(out, self.slots);
+        out.extend_from_slice(&[self.params, self.required, self.flags]);
+    }
+}
+
+impl<'a> Codec<'a> for Dispatch<'a> {
+    fn decode(operand: Operand, code: &'a [u8], at: &mut usize) -> Option<Self> {
+        let len = operand.len(code, *at)?;
+        let (low, low_len, width) = match operand {
+            Operand::Targets => {
+                let (low, low_len) = read_i64(code, *at)?;
+                (low, low_len, 4)
+            }
+            _ => (0, 0, 8),
+        };
+        let count_len = read_varint(code, *at + low_len)?.1;
+        let default = read_u32(code, *at + low_len + count_len)?;
+        let entries = &code[*at + low_len + count_len + 4..*at + len];
+        *at += len;
+        Some(Dispatch { low, default, entries, width })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        if !self.strings() {
+            write_varint(out, zigzag(self.low));
+        }
+        write_varint(out, self.len() as u64);
+        out.extend_from_slice(&self.default.to_le_bytes());
+        out.extend_from_slice(self.entries);
+    }
+}
+
+opcodes! {
+    /// Do nothing
+    Nop = 0, "nop", [], "--";
+    /// Push a string constant
+    PushConst = 1, "push_const", [index: Const], "-- s";
+    /// Add numbers, or join strings
+    Add = 2, "add", [], "a b -- a + b";
+    /// Log a value, collections pretty-printed
+    LogString = 3, "log_string", [], "v --";
+    /// Call function `handler` at an instruction boundary once `signal` arrives
+    OnSignal = 5, "on_signal", [], "signal handler --";
+    /// Send `signal` to process `pid`
+    SendSignal = 6, "send_signal", [], "pid signal --";
+    /// Return `v` from the current call; a signal handler returns nothing
+    Return = 7, "return", [], "v --";
+    /// Call a native with `argc` arguments
+    CallNative = 8, "call_native", [native: Native, argc: Argc], "a1 .. an -- result";
+    /// Start a function body, which is stepped over when reached
+    BeginFunc = 10, "begin_func", [], "--";
+    /// End a function body
+    EndFunc = 11, "end_func", [], "--";
+    /// Subtract
+    Sub = 12, "sub", [], "a b -- a - b";
+    /// Multiply
+    Mul = 13, "mul", [], "a b -- a * b";
+    /// Divide; dividing by zero is an error
+    Div = 14, "div", [], "a b -- a / b";
+    /// Remainder
+    Rem = 15, "rem", [], "a b -- a % b";
+    /// Equal
+    Eq = 16, "eq", [], "a b -- a == b";
+    /// Not equal
+    Ne = 17, "ne", [], "a b -- a != b";
+    /// Less than
+    Lt = 18, "lt", [], "a b -- a < b";
+    /// Less than or equal
+    Le = 19, "le", [], "a b -- a <= b";
+    /// Greater than
+    Gt = 20, "gt", [], "a b -- a > b";
+    /// Greater than or equal
+    Ge = 21, "ge", [], "a b -- a >= b";
+    /// Push an integer
+    PushInt = 22, "push_int", [value: Int], "-- n";
+    /// Push a float
+    PushFloat = 23, "push_float", [value: Float], "-- x";
+    /// Push `null`
+    PushNull = 24, "push_null", [], "-- null";
+    /// Push a global variable, or the function defined by that name
+    LoadVar = 25, "load_var", [name: Name], "-- v";
+    /// Set a global variable
+    StoreVar = 26, "store_var", [name: Name], "v --";
+    /// Jump
+    Jump = 27, "jump", [target: Target], "--";
+    /// Jump if the condition is false
+    JumpIfFalse = 28, "jump_if_false", [target: Target], "cond --";
+    /// Drop the top of the stack
+    Pop = 29, "pop", [], "v --";
+    /// Copy the top of the stack
+    Dup = 30, "dup", [], "v -- v v";
+    /// Make a map of `count` key/value pairs; keys are strings
+    MakeMap = 31, "make_map", [count: Count], "k1 v1 .. kn vn -- map";
+    /// Index a string, array or map
+    Index = 32, "index", [], "target index -- target[index]";
+    /// Log `count` values on one line
+    LogValues = 33, "log_values", [count: Count], "v1 .. vn --";
+    /// Push `true`
+    PushTrue = 34, "push_true", [], "-- true";
+    /// Push `false`
+    PushFalse = 35, "push_false", [], "-- false";
+    /// Log `count` values on one line at a level
+    LogAt = 36, "log_at", [count: Count, level: Level], "v1 .. vn --";
+    /// Slice a string or array; a null bound is open
+    Slice = 37, "slice", [], "target start end -- target[start:end]";
+    /// Until the matching `end_try`, send errors to the handler at `target` with the error pushed
+    Try = 38, "try", [handler: Target], "--";
+    /// Drop the handler of the innermost `try`
+    EndTry = 39, "end_try", [], "--";
+    /// Fail with `v` as the error
+    Throw = 40, "throw", [], "v --";
+    /// Run a script as an `sh [ ... ]` block
+    Sh = 41, "sh", [], "script --";
+    /// Push a local, or the variable `name` while the slot is unset
+    LoadLocal = 42, "load_local", [slot: Slot, name: Name], "-- v";
+    /// Set a local
+    StoreLocal = 43, "store_local", [slot: Slot], "v --";
+    /// Whether a local is still unset (a parameter that was not passed)
+    Unbound = 44, "unbound", [slot: Slot], "-- bool";
+    /// Make a function value
+    MakeFunc = 45, "make_func", [header: Func], "-- func";
+    /// Make a function callable by `name`
+    Define = 46, "define", [name: Name], "func --";
+    /// Call the variable or function `name` with `argc` arguments
+    Call = 47, "call", [name: Name, argc: Argc], "a1 .. an -- result";
+    /// Call the function in a local, or `name` while the slot is unset
+    CallLocal = 48, "call_local", [slot: Slot, name: Name, argc: Argc], "a1 .. an -- result";
+    /// Exchange the top two values
+    Swap = 49, "swap", [], "a b -- b a";
+    /// Jump to target `v - low`, or the default when out of range
+    JumpTable = 50, "jump_table", [table: Targets], "v --";
+    /// Jump to the target of the string `v`, or the default
+    JumpString = 51, "jump_string", [table: StringTargets], "v --";
+    /// Run the runtime's precompiled `repo:lib` module the first time it is imported
+    Import = 52, "import", [module: Name], "--";
+    /// Push the variant of an enum: its enum name, its name and its discriminant
+    MakeVariant = 53, "make_variant", [enum_name: Const, name: Const, value: Int], "-- variant";
+    /// Whether `v` is an array of exactly `count` elements
+    IsArrayLen = 54, "is_array_len", [count: Count], "v -- bool";
+    /// Whether `v` is an array of at least `count` elements
+    IsArrayMin = 55, "is_array_min", [count: Count], "v -- bool";
+    /// Whether `v` is a map with an entry keyed by the string constant
+    HasKey = 56, "has_key", [key: Const], "v -- bool";
+    /// Stop; at the end of a module's top level, return to its `import`
+    Halt = 255, "halt", [], "--";
+}
+
+impl Opcode {
+    /// Inverse of `mnemonic`.
+    pub fn from_mnemonic(name: &str) -> Option<Self> {
+        ISA.iter().map(|info| info.opcode).find(|op| op.mnemonic() == name)
+    }
+
+    /// This instruction's row of `ISA`.
+    pub fn info(self) -> &'static OpInfo {
+        ISA.iter().find(|info| info.opcode == self).expect("every opcode is in ISA")
+    }
+}
+
+/// `ISA` as an aligned plain-text table.
+pub fn text() -> String {
+    let rows: Vec<[String; 5]> = ISA.iter().map(row).collect();
+    let header = ["byte", "mnemonic", "operands", "stack", "summary"].map(str::to_string);
+    let mut widths = [0; 4];
+    for cells in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for cells in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter().zip(cells) {
+            write!(out, "{:<width$}  ", cell, width = width).expect("writing to a String cannot fail");
+        }
+        out.push_str(&cells[4]);
+        out.push('\n');
+    }
+    out
+}
+
+/// `ISA` as a Markdown document, with the operand encodings after it.
+pub fn markdown() -> String {
+    let mut out = String::from("# HackerScript instruction set\n\n");
+    out.push_str("Generated by `hs1 isa --format markdown` from `hackerscript-bytecode/src/isa.rs`.\n\n");
+    out.push_str("| Byte | Mnemonic | Operands | Stack | Summary |\n|---:|---|---|---|---|\n");
+    for info in ISA {
+        let [byte, mnemonic, operands, stack, summary] = row(info);
+        let operands = if operands.is_empty() { String::new() } else { format!("`{}`", operands) };
+        writeln!(out, "| {} | `{}` | {} | `{}` | {} |", byte, mnemonic, operands, stack, summary.replace('|', "\\|"))
+            .expect("writing to a String cannot fail");
+    }
+    out.push_str("\n## Operands\n\n| Operand | Encoding |\n|---|---|\n");
+    for operand in OPERANDS {
+        writeln!(out, "| `{}` | {} |", operand.name(), operand.encoding()).expect("writing to a String cannot fail");
+    }
+    out
+}
+
+const OPERANDS: [Operand; 13] = [
+    Operand::Const,
+    Operand::Name,
+    Operand::Slot,
+    Operand::Count,
+    Operand::Native,
+    Operand::Int,
+    Operand::Float,
+    Operand::Argc,
+    Operand::Level,
+    Operand::Target,
+    Operand::Func,
+    Operand::Targets,
+    Operand::StringTargets,
+];
+
+fn row(info: &OpInfo) -> [String; 5] {
+    [
+        (info.opcode as u8).to_string(),
+        info.opcode.mnemonic().to_string(),
+        info.operands.iter().map(|operand| operand.name()).collect::<Vec<_>>().join(" "),
+        info.stack.to_string(),
+        info.summary.to_string(),
+    ]
+}
@@ -0,0 +1,938 @@
+//! The HackerScript bytecode format: opcodes, the emitter used by the
+//! compiler, the `.bc` reader/writer and the disassembler.
+//!
+//! Constant indexes, variable names, native ids and integer literals are
+//! LEB128 varints (integers zigzag-encoded), so the common small operands
+//! take one byte. Jump targets stay fixed-width u32 so forward jumps can be
+//! patched once their target is known: the emitter's labels do that.
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+pub mod bundle;
+pub mod isa;
+pub mod obfuscate;
+mod pool;
+mod verify;
+
+pub use isa::{Instruction, OpInfo, Opcode, Operand, ISA};
+pub use pool::Constants;
+pub use verify::verify;
+
+/// The natives of the VM, by the id `CallNative` encodes; only append to it.
+pub const NATIVES: &[&str] = &[
+    "term_color",
+    "term_bold",
+    "term_clear",
+    "term_move_to",
+    "term_hide_cursor",
+    "term_show_cursor",
+    "term_size",
+    "term_progress",
+    "term_prompt",
+    "term_confirm",
+    "int",
+    "float",
+    "str",
+    "hex",
+    "bin",
+    "sh",
+    "Error",
+    "type_of",
+    "fields",
+    "methods",
+    "has_field",
+    "get_field",
+    "set_field",
+    "map",
+    "filter",
+    "reduce",
+    "sort",
+    "find",
+    "any",
+    "all",
+    "zip",
+];
+
+/// The global holding a script's command-line arguments, an array of
+/// strings: empty unless the host sets it, as `hs1 run` does.
+pub const ARGS: &str = "args";
+
+/// Calls the compiler lowers to an instruction of their own instead of a
+/// `CallNative`, because they need the VM and not just the host: each
+/// name, its opcode and how many arguments it takes. The instruction leaves
+/// nothing on the stack, so the call's value is `null`.
+pub const BUILTINS: &[(&str, Opcode, usize)] = &[
+    ("on_signal", Opcode::OnSignal, 2),
+    ("send_signal", Opcode::SendSignal, 2),
+];
+
+/// The opcode and arity of builtin `name`.
+pub fn builtin(name: &str) -> Option<(Opcode, usize)> {
+    BUILTINS.iter().find(|(builtin, ..)| *builtin == name).map(|&(_, opcode, arity)| (opcode, arity))
+}
+
+/// The `CallNative` id of native `name`.
+pub fn native_id(name: &str) -> Option<u32> {
+    NATIVES.iter().position(|native| *native == name).map(|i| i as u32)
+}
+
+/// The level operand of `LogAt`, least severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum LogLevel {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+impl LogLevel {
+    pub fn from_byte(b: u8) -> Option<LogLevel> {
+        Some(match b {
+            0 => LogLevel::Debug,
+            1 => LogLevel::Info,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Error,
+            _ => return None,
+        })
+    }
+
+    /// `debug`, `info`, `warn` or `error`
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    /// Inverse of `name`; `warning` is accepted too.
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "debug" => LogLevel::Debug,
+            "info" => LogLevel::Info,
+            "warn" | "warning" => LogLevel::Warn,
+            "error" => LogLevel::Error,
+            _ => return None,
+        })
+    }
+}
+
+/// Length of the instruction at `pos`, opcode byte included, or `None` if
+/// the opcode is unknown or its operands run past the end of `code`.
+pub fn instruction_len(code: &[u8], pos: usize) -> Option<usize> {
+    let op = Opcode::from_byte(*code.get(pos)?)?;
+    let mut at = pos + 1;
+    for operand in op.info().operands {
+        at += operand.len(code, at)?;
+    }
+    Some(at - pos)
+}
+
+/// `FuncHeader::flags` bit: the last parameter collects the extra arguments.
+pub const FUNC_REST: u8 = 1;
+/// `FuncHeader::flags` bit: the function is a lambda and keeps a copy of the
+/// locals of the call that creates it.
+pub const FUNC_CAPTURES: u8 = 2;
+
+/// The operands of `MakeFunc`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FuncHeader {
+    /// Offset of the function's `BeginFunc`; a call starts just after it
+    pub entry: u32,
+    /// Constant index of the function's name
+    pub name: u64,
+    /// Local slots a call needs: the captured ones, then the parameters,
+    /// then the body's own
+    pub slots: u64,
+    /// Parameters, the rest parameter not included
+    pub params: u8,
+    /// Parameters without a default
+    pub required: u8,
+    pub flags: u8,
+}
+
+impl FuncHeader {
+    /// The operands at `pos` and how many bytes they take.
+    pub fn read(code: &[u8], pos: usize) -> Option<(Self, usize)> {
+        let entry = read_u32(code, pos)?;
+        let (name, name_len) = read_varint(code, pos + 4)?;
+        let (slots, slots_len) = read_varint(code, pos + 4 + name_len)?;
+        let at = pos + 4 + name_len + slots_len;
+        let [params, required, flags] = code.get(at..at + 3)?.try_into().ok()?;
+        Some((FuncHeader { entry, name, slots, params, required, flags }, at + 3 - pos))
+    }
+}
+
+/// The operands of a `JumpTable` or `JumpString`, read in place.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dispatch<'a> {
+    /// Value of the first entry (0 for `JumpString`)
+    pub low: i64,
+    pub default: u32,
+    /// A u32 target per entry, or for `JumpString` a (u32 constant index,
+    /// u32 target) pair
+    entries: &'a [u8],
+    width: usize,
+}
+
+impl<'a> Dispatch<'a> {
+    /// The operands of a `JumpTable` whose entries are `targets`, each a
+    /// little-endian u32.
+    pub fn table(low: i64, default: u32, targets: &'a [u8]) -> Self {
+        assert!(targets.len().is_multiple_of(4), "jump table entries are u32 targets");
+        Dispatch { low, default, entries: targets, width: 4 }
+    }
+
+    /// The operands of a `JumpString` whose entries are `entries`, each a
+    /// little-endian (u32 constant index, u32 target) pair, sorted by string.
+    pub fn string_table(default: u32, entries: &'a [u8]) -> Self {
+        assert!(entries.len().is_multiple_of(8), "jump string entries are (u32 constant, u32 target) pairs");
+        Dispatch { low: 0, default, entries, width: 8 }
+    }
+
+    /// The table of the instruction at `pos`, if it is a complete `JumpTable`
+    /// or `JumpString`.
+    pub fn read(code: &'a [u8], pos: usize) -> Option<Self> {
+        match Instruction::decode(code, pos)?.0 {
+            Instruction::JumpTable { table } | Instruction::JumpString { table } => Some(table),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() / self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether this is a `JumpString`, whose entries match strings.
+    pub fn strings(&self) -> bool {
+        self.width == 8
+    }
+
+    /// Where entry `i` jumps.
+    pub fn target(&self, i: usize) -> u32 {
+        read_u32(self.entries, i * self.width + self.width - 4).unwrap_or(self.default)
+    }
+
+    /// The constant index entry `i` of a `JumpString` matches.
+    pub fn key(&self, i: usize) -> u64 {
+        read_u32(self.entries, i * self.width).map_or(u64::MAX, u64::from)
+    }
+
+    /// Every target, the default first.
+    pub fn targets(&self) -> impl Iterator<Item = u32> + '_ {
+        std::iter::once(self.default).chain((0..self.len()).map(|i| self.target(i)))
+    }
+}
+
+/// Names and entry points of the functions in a chunk, the source it was
+/// compiled from and the source line of each statement. Only used for
+/// diagnostics; `hs1 compile --strip` leaves it out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub source: String,
+    pub functions: Vec<FunctionDebug>,
+    /// Sorted by offset; an entry covers the code up to the next one
+    pub lines: Vec<LineDebug>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDebug {
+    pub name: String,
+    /// Offset of the function's `BeginFunc`
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineDebug {
+    /// Offset of the first instruction of a statement
+    pub offset: u32,
+    /// 1-based line in `DebugInfo::source`
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bytecode {
+    pub code: Vec<u8>,
+    pub constants: Constants,
+    pub debug: Option<DebugInfo>,
+}
+
+/// How `Bytecode::encode` lays out the file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// zstd-compress the constant pool and debug section
+    pub compress: bool,
+}
+
+pub struct BytecodeEmitter {
+    code: Vec<u8>,
+    constants: Constants,
+    constant_index: HashMap<String, usize>,
+    debug: DebugInfo,
+    /// Offset of each label, once bound
+    labels: Vec<Option<u32>>,
+    /// (operand offset, label) of targets waiting for their label
+    fixups: Vec<(usize, Label)>,
+}
+
+/// A place in the code to jump to, which can be used before it is bound;
+/// see `BytecodeEmitter::label`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+impl Default for BytecodeEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BytecodeEmitter {
+    pub fn new() -> Self {
+        Self {
+            code: Vec::new(),
+            constants: Constants::new(),
+            constant_index: HashMap::new(),
+            debug: DebugInfo::default(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub fn emit(&mut self, op: Opcode) {
+        self.code.push(op as u8);
+    }
+
+    /// Emit an instruction and its operands.
+    pub fn emit_instruction(&mut self, instruction: &Instruction) {
+        instruction.encode(&mut self.code);
+    }
+
+    pub fn emit_u8(&mut self, value: u8) {
+        self.code.push(value);
+    }
+
+    /// Fixed-width operand, for jump targets that are patched later.
+    pub fn emit_u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn emit_varint(&mut self, value: u64) {
+        write_varint(&mut self.code, value);
+    }
+
+    pub fn emit_i64(&mut self, value: i64) {
+        write_varint(&mut self.code, zigzag(value));
+    }
+
+    pub fn emit_f64(&mut self, value: f64) {
+        self.code.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    /// An emitter with no code that shares this one's constant pool, so a
+    /// fragment can be checked on its own before it is emitted here.
+    pub fn fork(&self) -> Self {
+        Self {
+            code: Vec::new(),
+            constants: self.constants.clone(),
+            constant_index: self.constant_index.clone(),
+            debug: DebugInfo::default(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    pub fn emit_func_header(&mut self, header: &FuncHeader) {
+        self.emit_u32(header.entry);
+        self.emit_varint(header.name);
+        self.emit_varint(header.slots);
+        self.code.extend_from_slice(&[header.params, header.required, header.flags]);
+    }
+
+    /// Offset the next emitted byte will land at.
+    pub fn position(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Overwrite a u32 operand emitted earlier, e.g. a forward jump target.
+    pub fn patch_u32(&mut self, pos: usize, value: u32) {
+        self.code[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// A label bound to nothing yet; jumps to it are patched by `bind`.
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// A label bound to the current position, e.g. the start of a loop.
+    pub fn here(&mut self) -> Label {
+        let label = self.label();
+        self.bind(label);
+        label
+    }
+
+    /// Bind `label` to the current position and patch the jumps to it.
+    /// A label is bound once.
+    pub fn bind(&mut self, label: Label) {
+        let offset = self.code.len() as u32;
+        assert!(self.labels[label.0].replace(offset).is_none(), "{:?} is bound twice", label);
+        let (ready, waiting) = std::mem::take(&mut self.fixups).into_iter().partition(|&(_, to)| to == label);
+        self.fixups = waiting;
+        for (at, _) in ready {
+            self.patch_u32(at, offset);
+        }
+    }
+
+    /// Emit `op`, whose only operand is a `Target`, jumping to `to`.
+    pub fn emit_jump(&mut self, op: Opcode, to: Label) {
+        assert_eq!(op.info().operands, [Operand::Target], "{:?} is not a jump", op);
+        self.emit(op);
+        self.emit_u32(0);
+        self.fixup(self.code.len() - 4, to);
+    }
+
+    /// Make the u32 operand emitted at `at` the offset of `to`, now if it
+    /// is bound or else when it is.
+    pub fn fixup(&mut self, at: usize, to: Label) {
+        match self.labels[to.0] {
+            Some(offset) => self.patch_u32(at, offset),
+            None => self.fixups.push((at, to)),
+        }
+    }
+
+    /// Index of `s` in the constant pool, reusing an existing entry.
+    pub fn add_constant(&mut self, s: String) -> usize {
+        if let Some(&idx) = self.constant_index.get(&s) {
+            return idx;
+        }
+        let idx = self.constants.len();
+        self.constants.push(&s);
+        self.constant_index.insert(s, idx);
+        idx
+    }
+
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.debug.source = source.into();
+    }
+
+    /// Record that function `name` starts at the current position.
+    pub fn mark_function(&mut self, name: impl Into<String>) {
+        self.debug.functions.push(FunctionDebug {
+            name: name.into(),
+            offset: self.code.len() as u32,
+        });
+    }
+
+    /// Record that the code from the current position on comes from `line`.
+    pub fn mark_line(&mut self, line: u32) {
+        let offset = self.code.len() as u32;
+        match self.debug.lines.last_mut() {
+            Some(last) if last.line == line => {}
+            Some(last) if last.offset == offset => last.line = line,
+            _ => self.debug.lines.push(LineDebug { offset, line }),
+        }
+    }
+
+    /// The bytecode emitted. Every label jumped to must be bound.
+    pub fn finish(self) -> Bytecode {
+        assert!(self.fixups.is_empty(), "jumps to unbound labels: {:?}", self.fixups);
+        Bytecode {
+            code: self.code,
+            constants: self.constants,
+            debug: Some(self.debug),
+        }
+    }
+}
+
+/// Little-endian u32 operand at `pos`, if the code is long enough.
+pub fn read_u32(code: &[u8], pos: usize) -> Option<u32> {
+    let bytes = code.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// f64 operand (stored as its bit pattern) at `pos`, if the code is long enough.
+pub fn read_f64(code: &[u8], pos: usize) -> Option<f64> {
+    let bytes = code.get(pos..pos + 8)?;
+    Some(f64::from_bits(u64::from_le_bytes(bytes.try_into().ok()?)))
+}
+
+/// LEB128 varint at `pos`: the value and how many bytes it took.
+pub fn read_varint(code: &[u8], pos: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in code.get(pos..)?.iter().take(10).enumerate() {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Zigzag-encoded varint at `pos`, as written by `BytecodeEmitter::emit_i64`.
+pub fn read_i64(code: &[u8], pos: usize) -> Option<(i64, usize)> {
+    read_varint(code, pos).map(|(value, len)| (unzigzag(value), len))
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+const MAGIC: &[u8; 4] = b"HSBC";
+const FORMAT_VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_DEBUG: u8 = 2;
+const FLAG_XOR: u8 = 4;
+
+impl Bytecode {
+    /// Decoded instruction stream as `(offset, opcode)` pairs. Stops at the
+    /// first unknown or truncated instruction.
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, Opcode)> + '_ {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            let len = instruction_len(&self.code, pos)?;
+            let at = pos;
+            pos += len;
+            Some((at, Opcode::from_byte(self.code[at])?))
+        })
+    }
+
+    /// The header of every `MakeFunc`, in code order: each function's name,
+    /// arity and entry point. Unlike the debug info, stripped files have it.
+    pub fn functions(&self) -> impl Iterator<Item = FuncHeader> + '_ {
+        self.instructions().filter(|&(_, op)| op == Opcode::MakeFunc).filter_map(|(at, _)| {
+            match Instruction::decode(&self.code, at)?.0 {
+                Instruction::MakeFunc { header } => Some(header),
+                _ => None,
+            }
+        })
+    }
+
+    /// Drop debug information, as `hs1 compile --strip` does.
+    pub fn strip(&mut self) {
+        self.debug = None;
+    }
+
+    /// Uncompressed file contents.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(WriteOptions::default())
+            .expect("uncompressed encoding cannot fail")
+    }
+
+    // File format:
+    //   "HSBC" | version u8 | flags u8
+    //   [code len u32] [code]
+    //   [pool len u32] [pool: varint count, (varint len, utf-8 bytes)*]
+    //   or with FLAG_XOR [pool: u64 key, varint count, (varint len, encoded bytes)*]
+    //   [debug len u32] [debug: varint len, source, varint count, (varint len, name, varint offset)*,
+    //                           varint count, (varint offset, varint line)*]   if FLAG_DEBUG
+    // Files written before the line table end the debug payload after the functions.
+    // With FLAG_COMPRESSED the pool and debug payloads are zstd frames.
+    pub fn encode(&self, options: WriteOptions) -> Result<Vec<u8>> {
+        let mut flags = 0;
+        if options.compress {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.debug.is_some() {
+            flags |= FLAG_DEBUG;
+        }
+        if self.constants.xor_key().is_some() {
+            flags |= FLAG_XOR;
+        }
+        let mut out = Vec::with_capacity(16 + self.code.len());
+        out.extend_from_slice(MAGIC);
+        out.push(FORMAT_VERSION);
+        out.push(flags);
+        out.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.code);
+
+        let mut pool = Vec::new();
+        if let Some(key) = self.constants.xor_key() {
+            pool.extend_from_slice(&key.to_le_bytes());
+        }
+        write_varint(&mut pool, self.constants.len() as u64);
+        for entry in (0..self.constants.len()).filter_map(|idx| self.constants.encoded(idx)) {
+            write_varint(&mut pool, entry.len() as u64);
+            pool.extend_from_slice(entry);
+        }
+        write_section(&mut out, pool, options.compress)?;
+
+        if let Some(debug) = &self.debug {
+            let mut section = Vec::new();
+            write_str(&mut section, &debug.source);
+            write_varint(&mut section, debug.functions.len() as u64);
+            for func in &debug.functions {
+                write_str(&mut section, &func.name);
+                write_varint(&mut section, u64::from(func.offset));
+            }
+            write_varint(&mut section, debug.lines.len() as u64);
+            for line in &debug.lines {
+                write_varint(&mut section, u64::from(line.offset));
+                write_varint(&mut section, u64::from(line.line));
+            }
+            write_section(&mut out, section, options.compress)?;
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self> {
+        if buffer.get(..4) != Some(MAGIC.as_slice()) {
+            anyhow::bail!("Not a HackerScript bytecode file (missing HSBC header)");
+        }
+        let version = *buffer.get(4).context("Bytecode too short")?;
+        if version != FORMAT_VERSION {
+            anyhow::bail!("Unsupported bytecode version {} (expected {})", version, FORMAT_VERSION);
+        }
+        let flags = *buffer.get(5).context("Bytecode too short")?;
+        let compressed = flags & FLAG_COMPRESSED != 0;
+
+        let mut pos = 6;
+        let code = read_section(buffer, &mut pos, false).context("Incomplete bytecode")?.into_owned();
+
+        let pool = read_section(buffer, &mut pos, compressed).context("Incomplete constants")?;
+        let mut reader = Reader { bytes: &pool, pos: 0 };
+        let constants = if flags & FLAG_XOR != 0 {
+            let key = u64::from_le_bytes(reader.take(8).context("Truncated XOR key")?.try_into().expect("8 bytes"));
+            let count = reader.varint()?;
+            let entries = (0..count).map(|_| reader.bytes()).collect::<Result<Vec<_>>>()?;
+            Constants::from_encoded(key, entries)?
+        } else {
+            let count = reader.varint()?;
+            let mut constants = Constants::new();
+            for _ in 0..count {
+                constants.push(reader.str()?);
+            }
+            constants
+        };
+
+        let debug = if flags & FLAG_DEBUG != 0 {
+            let section = read_section(buffer, &mut pos, compressed).context("Incomplete debug info")?;
+            let mut reader = Reader { bytes: &section, pos: 0 };
+            let source = reader.string()?;
+            let count = reader.varint()?;
+            let mut functions = Vec::new();
+            for _ in 0..count {
+                let name = reader.string()?;
+                let offset = reader.varint()? as u32;
+                functions.push(FunctionDebug { name, offset });
+            }
+            let mut lines = Vec::new();
+            if reader.pos < reader.bytes.len() {
+                for _ in 0..reader.varint()? {
+                    let offset = reader.varint()? as u32;
+                    let line = reader.varint()? as u32;
+                    lines.push(LineDebug { offset, line });
+                }
+            }
+            Some(DebugInfo { source, functions, lines })
+        } else {
+            None
+        };
+        Ok(Bytecode { code, constants, debug })
+    }
+
+    /// Name of the function whose body contains `offset`, from the debug info.
+    pub fn function_at(&self, offset: usize) -> Option<&str> {
+        let debug = self.debug.as_ref()?;
+        debug
+            .functions
+            .iter()
+            .filter(|f| f.offset as usize <= offset)
+            .max_by_key(|f| f.offset)
+            .map(|f| f.name.as_str())
+    }
+
+    /// Source line of the statement whose code contains `offset`, from the
+    /// debug info.
+    pub fn line_at(&self, offset: usize) -> Option<u32> {
+        let lines = &self.debug.as_ref()?.lines;
+        let next = lines.partition_point(|entry| entry.offset as usize <= offset);
+        Some(lines[next.checked_sub(1)?].line)
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_section(out: &mut Vec<u8>, payload: Vec<u8>, compress: bool) -> Result<()> {
+    let payload = if compress { compress_bytes(&payload)? } else { payload };
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&payload);
+    Ok(())
+}
+
+fn read_section<'a>(buffer: &'a [u8], pos: &mut usize, compressed: bool) -> Result<Cow<'a, [u8]>> {
+    let len = read_u32(buffer, *pos).context("Missing section length")? as usize;
+    *pos += 4;
+    let payload = buffer
+        .get(*pos..)
+        .and_then(|rest| rest.get(..len))
+        .context("Section runs past the end of the file")?;
+    *pos += len;
+    if compressed {
+        decompress_bytes(payload).map(Cow::Owned)
+    } else {
+        Ok(Cow::Borrowed(payload))
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compress_bytes(payload: &[u8]) -> Result<Vec<u8>> {
+    zstd::bulk::compress(payload, 19).context("zstd compression failed")
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_bytes(payload: &[u8]) -> Result<Vec<u8>> {
+    zstd::stream::decode_all(payload).context("zstd decompression failed")
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_bytes(_payload: &[u8]) -> Result<Vec<u8>> {
+    anyhow::bail!("this build has no zstd support")
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_bytes(_payload: &[u8]) -> Result<Vec<u8>> {
+    anyhow::bail!("bytecode is zstd-compressed but this build has no zstd support")
+}
+
+/// Cursor over a decoded section.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let (value, len) = read_varint(self.bytes, self.pos).context("Truncated varint")?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..)?.get(..len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// A length-prefixed run of bytes.
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.varint()? as usize;
+        self.take(len).context("Truncated string")
+    }
+
+    fn str(&mut self) -> Result<&'a str> {
+        std::str::from_utf8(self.bytes()?).context("String is not valid UTF-8")
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.str().map(str::to_string)
+    }
+}
+
+pub fn write_to_file(bytecode: &Bytecode, path: &Path) -> Result<()> {
+    write_to_file_with(bytecode, path, WriteOptions::default())
+}
+
+pub fn write_to_file_with(bytecode: &Bytecode, path: &Path, options: WriteOptions) -> Result<()> {
+    let bytes = bytecode.encode(options)?;
+    let mut file = File::create(path).context("Cannot create output file")?;
+    file.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Files at least this big are memory-mapped by `read_from_file`; reading
+/// smaller ones is cheaper than setting up the mapping.
+#[cfg(feature = "mmap")]
+const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+pub fn read_from_file(path: &Path) -> Result<Bytecode> {
+    #[cfg(feature = "mmap")]
+    {
+        let file = File::open(path).context("Failed to read bytecode file")?;
+        if file.metadata().context("Failed to read bytecode file")?.len() >= MMAP_THRESHOLD {
+            // SAFETY: the mapping is only read while `from_bytes` copies the
+            // sections out of it; a file truncated meanwhile faults the read
+            let map = unsafe { memmap2::Mmap::map(&file) }.context("Failed to map bytecode file")?;
+            return Bytecode::from_bytes(&map);
+        }
+    }
+    let buffer = std::fs::read(path).context("Failed to read bytecode file")?;
+    Bytecode::from_bytes(&buffer)
+}
+
+pub fn pretty_print(bytecode: &Bytecode) {
+    print!("{}", disassemble(bytecode));
+}
+
+/// The listing `pretty_print` shows: source, constant pool, functions and
+/// one line per instruction. Undecodable bytes are listed rather than rejected.
+pub fn disassemble(bytecode: &Bytecode) -> String {
+    let mut out = String::new();
+    write_listing(&mut out, bytecode).expect("writing to a String cannot fail");
+    out
+}
+
+fn write_listing(out: &mut String, bytecode: &Bytecode) -> std::fmt::Result {
+    if let Some(debug) = &bytecode.debug {
+        if !debug.source.is_empty() {
+            writeln!(out, "Source: {}", debug.source)?;
+        }
+    }
+    writeln!(out, "Constants ({}):", bytecode.constants.len())?;
+    for (i, s) in bytecode.constants.iter().enumerate() {
+        writeln!(out, "  {:3}: {:?}", i, s)?;
+    }
+    let mut functions: Vec<FuncHeader> = bytecode.functions().collect();
+    functions.sort_by_key(|header| header.entry);
+    if !functions.is_empty() {
+        writeln!(out, "\nFunctions ({}):", functions.len())?;
+    }
+    for header in functions {
+        let rest = if header.flags & FUNC_REST != 0 { ", rest" } else { "" };
+        let captures = if header.flags & FUNC_CAPTURES != 0 { ", captures" } else { "" };
+        writeln!(
+            out,
+            "  {:04x}: {} ({} params, {} required, {} slots{}{})",
+            header.entry,
+            constant_name(bytecode, header.name),
+            header.params,
+            header.required,
+            header.slots,
+            rest,
+            captures
+        )?;
+    }
+    writeln!(out, "\nCode:")?;
+    let code = &bytecode.code;
+    let mut i = 0;
+    while i < code.len() {
+        if let Some(debug) = &bytecode.debug {
+            for func in debug.functions.iter().filter(|f| f.offset as usize == i) {
+                writeln!(out, "{}:", func.name)?;
+            }
+        }
+        let byte = code[i];
+        write!(out, "{:04x}: ", i)?;
+        let Some(op) = Opcode::from_byte(byte) else {
+            writeln!(out, "??? (0x{:02x})", byte)?;
+            i += 1;
+            continue;
+        };
+        let Some((instruction, len)) = Instruction::decode(code, i) else {
+            match instruction_len(code, i) {
+                Some(len) => {
+                    writeln!(out, "{} <invalid>", op.mnemonic())?;
+                    i += len;
+                    continue;
+                }
+                None => {
+                    writeln!(out, "{} <incomplete>", op.mnemonic())?;
+                    break;
+                }
+            }
+        };
+        let mnemonic = op.mnemonic();
+        match instruction {
+            Instruction::PushConst { index } => writeln!(out, "{} {}", mnemonic, index)?,
+            Instruction::LoadVar { name: idx }
+            | Instruction::StoreVar { name: idx }
+            | Instruction::Define { name: idx }
+            | Instruction::Import { module: idx }
+            | Instruction::HasKey { key: idx } => match bytecode.constants.get(idx as usize) {
+                Some(c) => writeln!(out, "{} {} ({})", mnemonic, idx, c)?,
+                None => writeln!(out, "{} {}", mnemonic, idx)?,
+            },
+            Instruction::LoadLocal { slot, name } => {
+                writeln!(out, "{} {} ({})", mnemonic, slot, constant_name(bytecode, name))?;
+            }
+            Instruction::StoreLocal { slot } | Instruction::Unbound { slot } => writeln!(out, "{} {}", mnemonic, slot)?,
+            Instruction::Call { name, argc } => {
+                writeln!(out, "{} {} {}", mnemonic, constant_name(bytecode, name), argc)?
+            }
+            Instruction::MakeVariant { enum_name, name, value } => {
+                let (enum_name, name) = (constant_name(bytecode, enum_name), constant_name(bytecode, name));
+                writeln!(out, "{} {:?} {:?} {}", mnemonic, enum_name, name, value)?;
+            }
+            Instruction::CallLocal { slot, name, argc } => {
+                writeln!(out, "{} {} ({}) {}", mnemonic, slot, constant_name(bytecode, name), argc)?;
+            }
+            Instruction::MakeFunc { header } => {
+                writeln!(
+                    out,
+                    "{} {:04x} {} {} {} {} {}",
+                    mnemonic,
+                    header.entry,
+                    constant_name(bytecode, header.name),
+                    header.slots,
+                    header.params,
+                    header.required,
+                    header.flags
+                )?;
+            }
+            Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target }
+            | Instruction::Try { handler: target } => {
+                writeln!(out, "{} {:04x}", mnemonic, target)?;
+            }
+            Instruction::JumpTable { table } => {
+                write!(out, "{} {} {} {:04x}", mnemonic, table.low, table.len(), table.default)?;
+                for i in 0..table.len() {
+                    write!(out, " {:04x}", table.target(i))?;
+                }
+                writeln!(out)?;
+            }
+            Instruction::JumpString { table } => {
+                write!(out, "{} {} {:04x}", mnemonic, table.len(), table.default)?;
+                for i in 0..table.len() {
+                    write!(out, " {:?} {:04x}", constant_name(bytecode, table.key(i)), table.target(i))?;
+                }
+                writeln!(out)?;
+            }
+            Instruction::PushInt { value } => writeln!(out, "{} {}", mnemonic, value)?,
+            Instruction::PushFloat { value } => writeln!(out, "{} {}", mnemonic, value)?,
+            Instruction::MakeMap { count }
+            | Instruction::LogValues { count }
+            | Instruction::IsArrayLen { count }
+            | Instruction::IsArrayMin { count } => writeln!(out, "{} {}", mnemonic, count)?,
+            Instruction::CallNative { native, argc } => writeln!(out, "{} {} {}", mnemonic, native, argc)?,
+            Instruction::LogAt { count, level } => writeln!(out, "{} {} {}", mnemonic, count, level.name())?,
+            _ => writeln!(out, "{}", mnemonic)?,
+        }
+        i += len;
+    }
+    Ok(())
+}
+
+/// The constant at `idx` as an operand of a listing, or `?` if there is none.
+fn constant_name(bytecode: &Bytecode, idx: u64) -> &str {
+    bytecode.constants.get(idx as usize).unwrap_or("?")
+}
@@ -0,0 +1,104 @@
+//! `--obfuscate`: make a chunk say less about the script it came from.
+//!
+//! Every pool entry that is only ever used as the name of a variable or
+//! function becomes `hash_name` of itself, and the debug info (source name,
+//! function table, line table) is dropped. Entries also used as string
+//! values, module names, natives and the `keep` names stay as they are, so
+//! the chunk runs the same. With `xor_strings` the pool is then XOR-encoded
+//! and decoded an entry at a time as the VM uses it.
+//!
+//! None of this is encryption: the XOR key is in the file, and a name can be
+//! found by hashing guesses.
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+
+use crate::{native_id, read_varint, Bytecode, Dispatch, FuncHeader, Opcode, Operand};
+
+#[derive(Debug, Clone, Default)]
+pub struct ObfuscateOptions {
+    /// XOR-encode the constant pool
+    pub xor_strings: bool,
+    /// Names to leave readable, e.g. the globals of imported modules or
+    /// those the host reads
+    pub keep: HashSet<String>,
+}
+
+/// What `name` becomes in an obfuscated chunk, for hosts that look up a
+/// global by name.
+pub fn hash_name(name: &str) -> String {
+    format!("_{:016x}", fnv1a(name.as_bytes()))
+}
+
+pub fn obfuscate(bytecode: &mut Bytecode, options: &ObfuscateOptions) -> Result<()> {
+    let mut names = HashSet::new();
+    let mut values = HashSet::new();
+    let code = &bytecode.code;
+    let mut pos = 0;
+    while pos < code.len() {
+        let op = Opcode::from_byte(code[pos]).with_context(|| format!("{:04x}: unknown opcode", pos))?;
+        let len =
+            crate::instruction_len(code, pos).with_context(|| format!("{:04x}: incomplete {}", pos, op.mnemonic()))?;
+        let mut at = pos + 1;
+        for &operand in op.info().operands {
+            match operand {
+                Operand::Name if op == Opcode::Import => {
+                    values.insert(read_varint(code, at).expect("instruction_len read it").0);
+                }
+                Operand::Name => {
+                    names.insert(read_varint(code, at).expect("instruction_len read it").0);
+                }
+                Operand::Func => {
+                    names.insert(FuncHeader::read(code, at).expect("instruction_len read it").0.name);
+                }
+                Operand::Const => {
+                    values.insert(read_varint(code, at).expect("instruction_len read it").0);
+                }
+                Operand::StringTargets => {
+                    let table = Dispatch::read(code, pos).expect("instruction_len read it");
+                    values.extend((0..table.len()).map(|i| table.key(i)));
+                }
+                _ => {}
+            }
+            at += operand.len(code, at).expect("instruction_len read it");
+        }
+        pos += len;
+    }
+
+    // hash -> the name it replaces
+    let mut hashed: HashMap<String, &str> = HashMap::new();
+    let mut kept = Vec::new();
+    let mut pool = Vec::with_capacity(bytecode.constants.len());
+    for (idx, entry) in bytecode.constants.iter().enumerate() {
+        let idx = idx as u64;
+        if !names.contains(&idx) || values.contains(&idx) || native_id(entry).is_some() || options.keep.contains(entry)
+        {
+            if names.contains(&idx) {
+                kept.push(entry);
+            }
+            pool.push(entry.to_string());
+            continue;
+        }
+        let hash = hash_name(entry);
+        if let Some(other) = hashed.insert(hash.clone(), entry).filter(|other| *other != entry) {
+            bail!("`{}` and `{}` hash to the same name", other, entry);
+        }
+        pool.push(hash);
+    }
+    if let Some(name) = kept.iter().find(|name| hashed.contains_key(**name)) {
+        bail!("`{}` is kept but is also the hash of `{}`", name, hashed[*name]);
+    }
+
+    let mut constants: crate::Constants = pool.iter().collect();
+    if options.xor_strings {
+        // derived from the chunk so builds stay reproducible
+        let key = pool.iter().fold(fnv1a(&bytecode.code), |key, entry| key.rotate_left(5) ^ fnv1a(entry.as_bytes()));
+        constants = constants.xor_encoded(key);
+    }
+    bytecode.constants = constants;
+    bytecode.strip();
+    Ok(())
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
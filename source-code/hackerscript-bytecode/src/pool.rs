@@ -0,0 +1,172 @@
+//! The constant pool, kept as one string with the end of each entry, so
+//! loading a `.bc` file does not allocate a `String` per constant: the VM
+//! copies an entry out only when it uses it.
+//!
+//! A pool written by `hs1 --obfuscate --xor-strings` keeps its entries
+//! XOR-encoded in memory too, and decodes each the first time it is asked
+//! for. That only keeps the strings out of a plain `strings` dump: the key
+//! is in the file.
+use anyhow::{Context, Result};
+use std::fmt;
+use std::sync::OnceLock;
+
+#[derive(Clone, Default)]
+pub struct Constants {
+    /// The entries one after another, unless they are encoded
+    text: String,
+    ends: Vec<usize>,
+    xor: Option<Xor>,
+}
+
+#[derive(Clone)]
+struct Xor {
+    key: u64,
+    /// The encoded entries one after another
+    bytes: Vec<u8>,
+    decoded: Vec<OnceLock<String>>,
+}
+
+impl Constants {
+    pub fn new() -> Self {
+        Constants::default()
+    }
+
+    /// A pool of XOR-encoded `entries`, as `encoded` gives them. Fails if
+    /// an entry does not decode to UTF-8.
+    pub fn from_encoded<'a>(key: u64, entries: impl IntoIterator<Item = &'a [u8]>) -> Result<Self> {
+        let mut pool = Constants::default();
+        let mut xor = Xor { key, bytes: Vec::new(), decoded: Vec::new() };
+        let mut scratch = Vec::new();
+        for (idx, entry) in entries.into_iter().enumerate() {
+            scratch.clear();
+            scratch.extend_from_slice(entry);
+            keystream(key, idx, &mut scratch);
+            std::str::from_utf8(&scratch).with_context(|| format!("Constant {} is not valid UTF-8", idx))?;
+            xor.bytes.extend_from_slice(entry);
+            xor.decoded.push(OnceLock::new());
+            pool.ends.push(xor.bytes.len());
+        }
+        pool.xor = Some(xor);
+        Ok(pool)
+    }
+
+    /// This pool with its entries XOR-encoded under `key`.
+    pub fn xor_encoded(&self, key: u64) -> Constants {
+        let mut pool =
+            Constants { xor: Some(Xor { key, bytes: Vec::new(), decoded: Vec::new() }), ..Constants::default() };
+        self.iter().for_each(|s| pool.push(s));
+        pool
+    }
+
+    /// The XOR key, if the entries are encoded.
+    pub fn xor_key(&self) -> Option<u64> {
+        self.xor.as_ref().map(|xor| xor.key)
+    }
+
+    /// Entry `idx` as stored: encoded if the pool is.
+    pub fn encoded(&self, idx: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(idx)?;
+        let start = idx.checked_sub(1).map_or(0, |prev| self.ends[prev]);
+        match &self.xor {
+            None => Some(&self.text.as_bytes()[start..end]),
+            Some(xor) => Some(&xor.bytes[start..end]),
+        }
+    }
+
+    /// Entry `idx`, if the pool has one.
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        let end = *self.ends.get(idx)?;
+        let start = idx.checked_sub(1).map_or(0, |prev| self.ends[prev]);
+        match &self.xor {
+            None => Some(&self.text[start..end]),
+            Some(xor) => Some(xor.decoded[idx].get_or_init(|| {
+                let mut bytes = xor.bytes[start..end].to_vec();
+                keystream(xor.key, idx, &mut bytes);
+                String::from_utf8(bytes).expect("checked when the pool was made")
+            })),
+        }
+    }
+
+    pub fn push(&mut self, s: &str) {
+        match &mut self.xor {
+            None => {
+                self.text.push_str(s);
+                self.ends.push(self.text.len());
+            }
+            Some(xor) => {
+                let start = xor.bytes.len();
+                xor.bytes.extend_from_slice(s.as_bytes());
+                keystream(xor.key, self.ends.len(), &mut xor.bytes[start..]);
+                xor.decoded.push(OnceLock::new());
+                self.ends.push(xor.bytes.len());
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        (0..self.len()).filter_map(|idx| self.get(idx))
+    }
+}
+
+/// XOR `bytes`, entry `idx` of a pool, with the key stream of `key`. Its
+/// own inverse.
+fn keystream(key: u64, idx: usize, bytes: &mut [u8]) {
+    let mut state = key ^ (idx as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    for chunk in bytes.chunks_mut(8) {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        chunk.iter_mut().zip(z.to_le_bytes()).for_each(|(byte, k)| *byte ^= k);
+    }
+}
+
+impl PartialEq for Constants {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for Constants {}
+
+impl fmt::Debug for Constants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Constants {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut pool = Constants::new();
+        iter.into_iter().for_each(|s| pool.push(s.as_ref()));
+        pool
+    }
+}
+
+impl<S: AsRef<str>> From<Vec<S>> for Constants {
+    fn from(strings: Vec<S>) -> Self {
+        strings.into_iter().collect()
+    }
+}
+
+impl<S: AsRef<str>> PartialEq<[S]> for Constants {
+    fn eq(&self, other: &[S]) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a == b.as_ref())
+    }
+}
+
+impl<S: AsRef<str>> PartialEq<Vec<S>> for Constants {
+    fn eq(&self, other: &Vec<S>) -> bool {
+        *self == other[..]
+    }
+}
@@ -0,0 +1,122 @@
+//! Structural checks run on untrusted bytecode before the VM executes it.
+//!
+//! A chunk that passes `verify` decodes completely, only references constants
+//! that exist and known log levels, only jumps (and points `try` handlers)
+//! to instruction boundaries inside the same function, keeps the strings of
+//! a `jump_string` sorted, only makes functions
+//! out of a `BeginFunc`, has balanced `BeginFunc`/`EndFunc` pairs and cannot
+//! run off the end of the code.
+//! Type errors, stack underflow and unknown natives are still reported by
+//! the VM at run time.
+use anyhow::{bail, Context, Result};
+use crate::{instruction_len, read_u32, read_varint, Bytecode, Dispatch, FuncHeader, LogLevel, Opcode, Operand};
+
+pub fn verify(bytecode: &Bytecode) -> Result<()> {
+    let code = &bytecode.code;
+    // (instruction offset, offset of the enclosing BeginFunc or None at top
+    // level), sorted by offset
+    let mut owner: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut funcs: Vec<usize> = Vec::new();
+    let mut jumps: Vec<(usize, usize)> = Vec::new();
+    let mut entries: Vec<(usize, usize)> = Vec::new();
+    let mut last = None;
+    let mut pos = 0;
+    while pos < code.len() {
+        let Some(op) = Opcode::from_byte(code[pos]) else {
+            bail!("{:04x}: unknown opcode 0x{:02x}", pos, code[pos]);
+        };
+        let Some(len) = instruction_len(code, pos) else {
+            bail!("{:04x}: incomplete {}", pos, op.mnemonic());
+        };
+        owner.push((pos, funcs.last().copied()));
+        // operands as `ISA` lists them
+        let mut at = pos + 1;
+        for &operand in op.info().operands {
+            let constant = match operand {
+                Operand::Const | Operand::Name => Some(read_varint(code, at).unwrap_or_default().0),
+                Operand::Func => {
+                    let (header, _) = FuncHeader::read(code, at).unwrap_or((FuncHeader::default(), 0));
+                    entries.push((pos, header.entry as usize));
+                    Some(header.name)
+                }
+                Operand::Target => {
+                    jumps.push((pos, read_u32(code, at).unwrap_or_default() as usize));
+                    None
+                }
+                Operand::Level => {
+                    if LogLevel::from_byte(code[at]).is_none() {
+                        bail!("{:04x}: {} has unknown level {}", pos, op.mnemonic(), code[at]);
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(idx) = constant.filter(|&idx| idx >= bytecode.constants.len() as u64) {
+                bail!(
+                    "{:04x}: {} references constant {} but the pool has {}",
+                    pos,
+                    op.mnemonic(),
+                    idx,
+                    bytecode.constants.len()
+                );
+            }
+            at += operand.len(code, at).expect("instruction_len read it");
+        }
+        match op {
+            Opcode::JumpTable | Opcode::JumpString => {
+                let table = Dispatch::read(code, pos).expect("instruction_len read it");
+                jumps.extend(table.targets().map(|target| (pos, target as usize)));
+                if op == Opcode::JumpString {
+                    let mut keys = Vec::with_capacity(table.len());
+                    for i in 0..table.len() {
+                        let key = bytecode.constants.get(table.key(i) as usize).with_context(|| {
+                            format!("{:04x}: jump_string references constant {} but the pool has {}", pos, table.key(i), bytecode.constants.len())
+                        })?;
+                        keys.push(key);
+                    }
+                    if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+                        bail!("{:04x}: jump_string strings are not sorted", pos);
+                    }
+                }
+            }
+            Opcode::BeginFunc => funcs.push(pos),
+            Opcode::EndFunc => {
+                funcs.pop().with_context(|| format!("{:04x}: end_func without begin_func", pos))?;
+            }
+            _ => {}
+        }
+        last = Some(op);
+        pos += len;
+    }
+    if let Some(open) = funcs.last() {
+        bail!("{:04x}: begin_func without end_func", open);
+    }
+    if !matches!(last, Some(Opcode::Halt | Opcode::Jump)) {
+        bail!("code does not end with halt or jump");
+    }
+    let owner_of = |offset: usize| owner.binary_search_by_key(&offset, |&(pos, _)| pos).ok().map(|i| owner[i].1);
+    for (at, target) in jumps {
+        match owner_of(target) {
+            None => bail!("{:04x}: jump target {:04x} is not an instruction", at, target),
+            Some(func) if Some(func) != owner_of(at) => {
+                bail!("{:04x}: jump target {:04x} is in another function", at, target)
+            }
+            _ => {}
+        }
+    }
+    for (at, entry) in entries {
+        if code.get(entry) != Some(&(Opcode::BeginFunc as u8)) || owner_of(entry).is_none() {
+            bail!("{:04x}: make_func entry {:04x} is not a begin_func", at, entry);
+        }
+    }
+    if let Some(debug) = &bytecode.debug {
+        for func in &debug.functions {
+            if code.get(func.offset as usize) != Some(&(Opcode::BeginFunc as u8))
+                || owner_of(func.offset as usize).is_none()
+            {
+                bail!("debug info puts `{}` at {:04x}, which is not a begin_func", func.name, func.offset);
+            }
+        }
+    }
+    Ok(())
+}
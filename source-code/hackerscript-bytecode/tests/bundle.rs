@@ -0,0 +1,32 @@
+use hackerscript_bytecode::bundle;
+
+#[test]
+fn appended_payload_is_found_again() {
+    let runtime = b"\x7fELF pretend runtime".to_vec();
+    let exe = bundle::append(&runtime, b"HSBC payload");
+    assert!(exe.starts_with(&runtime));
+    assert_eq!(bundle::payload(&exe), Some(&b"HSBC payload"[..]));
+
+    let path = std::env::temp_dir().join(format!("hs-bundle-{}", std::process::id()));
+    std::fs::write(&path, &exe).unwrap();
+    let embedded = bundle::read_embedded(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(embedded.as_deref(), Some(&b"HSBC payload"[..]));
+}
+
+#[test]
+fn rebundling_replaces_the_old_payload() {
+    let first = bundle::append(b"runtime", b"old payload");
+    let second = bundle::append(&first, b"new");
+    assert_eq!(second, bundle::append(b"runtime", b"new"));
+}
+
+#[test]
+fn plain_and_corrupt_executables_have_no_payload() {
+    assert_eq!(bundle::payload(b"runtime"), None);
+    assert_eq!(bundle::payload(b""), None);
+    let mut corrupt = bundle::append(b"rt", b"payload");
+    let len_at = corrupt.len() - 16;
+    corrupt[len_at..len_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(bundle::payload(&corrupt), None);
+}
@@ -0,0 +1,196 @@
+use hackerscript_bytecode::{
+    disassemble, read_from_file, read_i64, read_u32, read_varint, write_to_file, Bytecode, BytecodeEmitter, Constants,
+    FuncHeader, Instruction, Opcode, FUNC_REST,
+};
+
+fn sample() -> Bytecode {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.set_source("sample.hcs");
+    emitter.mark_function("f");
+    emitter.mark_line(1);
+    emitter.emit(Opcode::BeginFunc);
+    emitter.emit(Opcode::EndFunc);
+    emitter.mark_line(3);
+    for (i, n) in [0i64, -1, 63, -64, 300, i64::MIN, i64::MAX].into_iter().enumerate() {
+        emitter.emit(Opcode::PushInt);
+        emitter.emit_i64(n);
+        let idx = emitter.add_constant(format!("constant {}", i % 3));
+        emitter.emit(Opcode::PushConst);
+        emitter.emit_varint(idx as u64);
+    }
+    emitter.emit(Opcode::Halt);
+    emitter.finish()
+}
+
+fn assert_same(a: &Bytecode, b: &Bytecode) {
+    assert_eq!(a.code, b.code);
+    assert_eq!(a.constants, b.constants);
+    assert_eq!(a.debug, b.debug);
+}
+
+#[test]
+fn small_operands_take_one_byte() {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.emit(Opcode::PushInt);
+    emitter.emit_i64(-5);
+    emitter.emit(Opcode::PushConst);
+    emitter.emit_varint(7);
+    let code = emitter.finish().code;
+    assert_eq!(code.len(), 4);
+    assert_eq!(read_i64(&code, 1), Some((-5, 1)));
+    assert_eq!(read_varint(&code, 3), Some((7, 1)));
+}
+
+#[test]
+fn labels_patch_jumps_before_and_after_they_are_bound() {
+    let mut emitter = BytecodeEmitter::new();
+    let top = emitter.here();
+    let (end, unused) = (emitter.label(), emitter.label());
+    emitter.emit(Opcode::PushTrue);
+    emitter.emit_jump(Opcode::JumpIfFalse, end);
+    emitter.emit_jump(Opcode::Jump, top);
+    emitter.emit_jump(Opcode::Try, end);
+    emitter.bind(end);
+    emitter.emit(Opcode::Halt);
+    emitter.bind(unused);
+    let code = emitter.finish().code;
+    assert_eq!(read_u32(&code, 2), Some(16));
+    assert_eq!(read_u32(&code, 7), Some(0));
+    assert_eq!(read_u32(&code, 12), Some(16));
+}
+
+#[test]
+fn the_function_table_lists_every_make_func() {
+    let mut emitter = BytecodeEmitter::new();
+    let name = emitter.add_constant("f".to_string()) as u64;
+    emitter.emit(Opcode::BeginFunc);
+    emitter.emit(Opcode::PushNull);
+    emitter.emit(Opcode::Return);
+    emitter.emit(Opcode::EndFunc);
+    let header = FuncHeader { entry: 0, name, slots: 3, params: 2, required: 1, flags: FUNC_REST };
+    emitter.emit_instruction(&Instruction::MakeFunc { header });
+    emitter.emit(Opcode::Halt);
+    let mut bytecode = emitter.finish();
+    bytecode.strip();
+    assert_eq!(bytecode.functions().collect::<Vec<_>>(), [header]);
+    assert!(disassemble(&bytecode).contains("Functions (1):\n  0000: f (2 params, 1 required, 3 slots, rest)\n"));
+}
+
+#[test]
+fn instructions_walk_variable_length_operands() {
+    let bytecode = sample();
+    let ops: Vec<Opcode> = bytecode.instructions().map(|(_, op)| op).collect();
+    assert_eq!(ops.len(), 2 + 7 * 2 + 1);
+    assert_eq!(ops.last(), Some(&Opcode::Halt));
+}
+
+#[test]
+fn round_trips_with_debug_info() {
+    let bytecode = sample();
+    assert_same(&Bytecode::from_bytes(&bytecode.to_bytes()).unwrap(), &bytecode);
+    assert_eq!(bytecode.function_at(1), Some("f"));
+    assert_eq!(bytecode.line_at(1), Some(1));
+    assert_eq!(bytecode.line_at(2), Some(3));
+    assert_eq!(bytecode.line_at(bytecode.code.len() - 1), Some(3));
+}
+
+#[test]
+fn reads_debug_info_without_a_line_table() {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.emit(Opcode::Halt);
+    let mut bytes = emitter.finish().to_bytes();
+    // an older writer ends the debug section after the function table
+    assert_eq!(bytes[bytes.len() - 7..], [3, 0, 0, 0, 0, 0, 0]);
+    bytes.pop();
+    let len = bytes.len();
+    bytes[len - 6] = 2;
+    let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+    assert!(bytecode.debug.unwrap().lines.is_empty());
+}
+
+#[test]
+fn strip_drops_debug_info() {
+    let mut bytecode = sample();
+    let full = bytecode.to_bytes().len();
+    bytecode.strip();
+    let stripped = bytecode.to_bytes();
+    assert!(stripped.len() < full);
+    assert_eq!(Bytecode::from_bytes(&stripped).unwrap().debug, None);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn round_trips_compressed() {
+    let bytecode = sample();
+    let bytes = bytecode.encode(hackerscript_bytecode::WriteOptions { compress: true }).unwrap();
+    assert_same(&Bytecode::from_bytes(&bytes).unwrap(), &bytecode);
+}
+
+#[test]
+fn round_trips_xor_encoded_constants() {
+    let mut bytecode = sample();
+    bytecode.constants = bytecode.constants.xor_encoded(0x5eed);
+    let bytes = bytecode.to_bytes();
+    let read = Bytecode::from_bytes(&bytes).unwrap();
+    assert_eq!(read.constants.xor_key(), Some(0x5eed));
+    assert_same(&read, &bytecode);
+    assert!(!bytes.windows(8).any(|window| window == b"constant"));
+    // entries are encoded apart, so equal strings do not look alike
+    let pool = Constants::from(vec!["same", "same"]).xor_encoded(1);
+    assert_ne!(pool.encoded(0), pool.encoded(1));
+    assert_eq!(pool.get(0), pool.get(1));
+}
+
+#[test]
+fn rejects_files_without_header() {
+    assert!(Bytecode::from_bytes(&[0, 0, 0, 0]).is_err());
+    assert!(Bytecode::from_bytes(b"HSBC\x01").is_err());
+}
+
+#[test]
+fn rejects_string_lengths_past_the_end_of_the_pool() {
+    let mut file = b"HSBC\x01\x00".to_vec();
+    file.extend_from_slice(&0u32.to_le_bytes());
+    let pool = [0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    file.extend_from_slice(&(pool.len() as u32).to_le_bytes());
+    file.extend_from_slice(&pool);
+    assert!(Bytecode::from_bytes(&file).is_err());
+}
+
+#[test]
+fn mnemonics_name_one_opcode_each() {
+    for op in (0..=u8::MAX).filter_map(Opcode::from_byte) {
+        assert_eq!(Opcode::from_mnemonic(op.mnemonic()), Some(op));
+    }
+    assert_eq!(Opcode::from_mnemonic("push"), None);
+}
+
+#[test]
+fn constants_index_one_shared_buffer() {
+    let pool: Constants = ["", "ab", "ü", ""].into_iter().collect();
+    assert_eq!(pool.len(), 4);
+    assert_eq!(pool.get(0), Some(""));
+    assert_eq!(pool.get(1), Some("ab"));
+    assert_eq!(pool.get(2), Some("ü"));
+    assert_eq!(pool.get(4), None);
+    assert_eq!(pool, vec!["", "ab", "ü", ""]);
+    assert_eq!(format!("{:?}", pool), r#"["", "ab", "ü", ""]"#);
+}
+
+#[test]
+fn reads_large_files() {
+    // past the size at which the file is mapped rather than read
+    let mut emitter = BytecodeEmitter::new();
+    for i in 0..20_000 {
+        let idx = emitter.add_constant(format!("constant {}", i));
+        emitter.emit(Opcode::PushConst);
+        emitter.emit_varint(idx as u64);
+        emitter.emit(Opcode::Pop);
+    }
+    emitter.emit(Opcode::Halt);
+    let bytecode = emitter.finish();
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("large.bc");
+    write_to_file(&bytecode, &path).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() > 256 * 1024);
+    assert_same(&read_from_file(&path).unwrap(), &bytecode);
+}
@@ -0,0 +1,79 @@
+use hackerscript_bytecode::{
+    disassemble, instruction_len, isa, BytecodeEmitter, Dispatch, FuncHeader, Instruction, LogLevel, Opcode, ISA,
+};
+
+#[test]
+fn every_opcode_has_one_row() {
+    assert!(ISA.windows(2).all(|pair| (pair[0].opcode as u8) < (pair[1].opcode as u8)));
+    for byte in 0..=u8::MAX {
+        let row = ISA.iter().find(|info| info.opcode as u8 == byte);
+        assert_eq!(Opcode::from_byte(byte), row.map(|info| info.opcode));
+    }
+    for info in ISA {
+        assert_eq!(Opcode::from_mnemonic(info.opcode.mnemonic()), Some(info.opcode));
+        assert!(info.stack.contains("--") && !info.summary.is_empty(), "{:?}", info);
+    }
+}
+
+#[test]
+fn lengths_follow_the_operands() {
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::CallLocal);
+    e.emit_varint(300);
+    e.emit_varint(1);
+    e.emit_u8(2);
+    e.emit(Opcode::PushFloat);
+    e.emit_f64(1.5);
+    e.emit(Opcode::Halt);
+    let code = e.finish().code;
+    assert_eq!(instruction_len(&code, 0), Some(1 + 2 + 1 + 1));
+    assert_eq!(instruction_len(&code, 5), Some(9));
+    assert_eq!(instruction_len(&code, 14), Some(1));
+    // operands cut short
+    assert_eq!(instruction_len(&code[..10], 5), None);
+}
+
+#[test]
+fn instructions_decode_as_they_were_encoded() {
+    let targets: Vec<u8> = [7u32, 9].iter().flat_map(|target| target.to_le_bytes()).collect();
+    let keys: Vec<u8> = [0u32, 11].iter().flat_map(|word| word.to_le_bytes()).collect();
+    let header = FuncHeader { entry: 3, name: 0, slots: 2, params: 1, required: 1, flags: 0 };
+    let instructions = [
+        Instruction::CallLocal { slot: 300, name: 0, argc: 2 },
+        Instruction::PushInt { value: -70 },
+        Instruction::PushFloat { value: 1.5 },
+        Instruction::LogAt { count: 2, level: LogLevel::Warn },
+        Instruction::MakeFunc { header },
+        Instruction::JumpTable { table: Dispatch::table(-1, 5, &targets) },
+        Instruction::JumpString { table: Dispatch::string_table(5, &keys) },
+        Instruction::Try { handler: 40 },
+        Instruction::Halt,
+    ];
+    let mut e = BytecodeEmitter::new();
+    e.add_constant("f".to_string());
+    for instruction in &instructions {
+        e.emit_instruction(instruction);
+    }
+    let bytecode = e.finish();
+    let mut pos = 0;
+    for instruction in instructions {
+        let (decoded, len) = Instruction::decode(&bytecode.code, pos).unwrap();
+        assert_eq!(decoded, instruction);
+        assert_eq!(instruction_len(&bytecode.code, pos), Some(len));
+        pos += len;
+    }
+    assert_eq!(pos, bytecode.code.len());
+    assert!(disassemble(&bytecode).contains("jump_table -1 2 0005 0007 0009\n"), "{}", disassemble(&bytecode));
+    assert!(disassemble(&bytecode).contains("jump_string 1 0005 \"f\" 000b\n"));
+    // an unknown level decodes to nothing, though its length is known
+    let code = [Opcode::LogAt as u8, 1, 9];
+    assert_eq!(Instruction::decode(&code, 0), None);
+    assert_eq!(instruction_len(&code, 0), Some(3));
+}
+
+#[test]
+fn the_isa_document_is_up_to_date() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/ISA.md");
+    let written = std::fs::read_to_string(path).unwrap();
+    assert!(written == isa::markdown(), "{} is stale: run `hs1 isa --format markdown > {}`", path, path);
+}
@@ -0,0 +1,114 @@
+use hackerscript_bytecode::{disassemble, instruction_len, verify, Bytecode, BytecodeEmitter, Opcode};
+use proptest::prelude::*;
+
+fn chunk(code: Vec<u8>, constants: &[&str]) -> Bytecode {
+    Bytecode {
+        code,
+        constants: constants.iter().collect(),
+        debug: None,
+    }
+}
+
+fn error(bytecode: &Bytecode) -> String {
+    verify(bytecode).unwrap_err().to_string()
+}
+
+#[test]
+fn accepts_emitted_code() {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.mark_function("f");
+    emitter.emit(Opcode::BeginFunc);
+    emitter.emit(Opcode::PushNull);
+    emitter.emit(Opcode::Return);
+    emitter.emit(Opcode::EndFunc);
+    let idx = emitter.add_constant("x".to_string());
+    emitter.emit(Opcode::PushInt);
+    emitter.emit_i64(1);
+    emitter.emit(Opcode::JumpIfFalse);
+    let patch = emitter.position();
+    emitter.emit_u32(0);
+    emitter.emit(Opcode::LoadVar);
+    emitter.emit_varint(idx as u64);
+    emitter.emit(Opcode::LogString);
+    emitter.patch_u32(patch, emitter.position() as u32);
+    emitter.emit(Opcode::Halt);
+    verify(&emitter.finish()).unwrap();
+}
+
+#[test]
+fn rejects_malformed_code() {
+    assert!(error(&chunk(vec![4, 255], &[])).contains("unknown opcode 0x04"));
+    assert!(error(&chunk(vec![Opcode::PushFloat as u8, 0, 0], &[])).contains("incomplete push_float"));
+    assert!(error(&chunk(vec![Opcode::PushConst as u8, 1, 255], &["a"])).contains("constant 1"));
+    assert!(error(&chunk(vec![Opcode::PushNull as u8], &[])).contains("does not end with halt"));
+    assert!(error(&chunk(vec![Opcode::EndFunc as u8, 255], &[])).contains("end_func without"));
+    assert!(error(&chunk(vec![Opcode::BeginFunc as u8, 255], &[])).contains("begin_func without"));
+    // into the middle of its own operand
+    assert!(error(&chunk(vec![Opcode::Jump as u8, 1, 0, 0, 0], &[])).contains("not an instruction"));
+    // from top level into a function body
+    let code = vec![Opcode::Jump as u8, 6, 0, 0, 0, 10, 255, 11, 255];
+    assert!(error(&chunk(code, &[])).contains("another function"));
+    // a function made from something that is not one
+    let code = vec![Opcode::MakeFunc as u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, Opcode::Pop as u8, 255];
+    assert!(error(&chunk(code, &["f"])).contains("make_func entry 0000 is not a begin_func"));
+}
+
+#[test]
+fn checks_every_target_of_a_jump_table() {
+    // jump_table 0 2 default [halt, target]; halt at 15
+    let table = |target: u8| vec![Opcode::JumpTable as u8, 0, 2, 15, 0, 0, 0, 15, 0, 0, 0, target, 0, 0, 0, 255];
+    verify(&chunk(table(15), &[])).unwrap();
+    assert!(error(&chunk(table(3), &[])).contains("jump target 0003 is not an instruction"));
+    // a count running past the end
+    assert!(error(&chunk(vec![Opcode::JumpTable as u8, 0, 0xff, 0xff, 0xff, 0xff, 0x0f, 255], &[])).contains("incomplete jump_table"));
+}
+
+#[test]
+fn jump_string_keys_must_be_sorted_constants() {
+    // jump_string 2 default [first, second]; every target is the halt at 22
+    let code = |first: u8, second: u8| {
+        vec![Opcode::JumpString as u8, 2, 22, 0, 0, 0, first, 0, 0, 0, 22, 0, 0, 0, second, 0, 0, 0, 22, 0, 0, 0, 255]
+    };
+    verify(&chunk(code(0, 1), &["a", "b"])).unwrap();
+    assert!(error(&chunk(code(1, 0), &["a", "b"])).contains("not sorted"));
+    assert!(error(&chunk(code(0, 2), &["a", "b"])).contains("references constant 2"));
+}
+
+#[test]
+fn rejects_debug_info_pointing_outside_functions() {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.emit(Opcode::Nop);
+    emitter.mark_function("f");
+    emitter.emit(Opcode::Halt);
+    assert!(error(&emitter.finish()).contains("`f`"));
+}
+
+proptest! {
+    #[test]
+    fn from_bytes_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+        let mut file = b"HSBC\x01".to_vec();
+        file.extend_from_slice(&bytes);
+        let _ = Bytecode::from_bytes(&file);
+        let _ = Bytecode::from_bytes(&bytes);
+    }
+
+    #[test]
+    fn verify_and_disassemble_never_panic(
+        code in prop::collection::vec(any::<u8>(), 0..128),
+        constants in prop::collection::vec(".{0,4}", 0..4),
+    ) {
+        let bytecode = Bytecode { code, constants: constants.into(), debug: None };
+        let _ = verify(&bytecode);
+        let _ = disassemble(&bytecode);
+    }
+
+    #[test]
+    fn verified_code_decodes_completely(code in prop::collection::vec(any::<u8>(), 0..64)) {
+        let bytecode = Bytecode { code, constants: vec!["c"].into(), debug: None };
+        if verify(&bytecode).is_ok() {
+            let (last, _) = bytecode.instructions().last().unwrap();
+            let len = instruction_len(&bytecode.code, last).unwrap();
+            prop_assert_eq!(last + len, bytecode.code.len());
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! The assembler behind `asm [ ... ]` blocks: bytecode written by hand, one
+//! mnemonic (as the disassembler prints it) followed by its operands.
+//!
+//! ```text
+//! asm [
+//!     push_const "hi"     @ a string literal, or a raw pool index
+//!     load_var name
+//!     call_native str 1   @ a native by name or id, then the argument count
+//! top:
+//!     jump_if_false top   @ jumps and `try` handlers name labels in the same block
+//!     jump_table 1 2 top top top    @ lowest value, count, default, a label per value
+//!     jump_string 1 top "get" top   @ count, default, then string and label pairs
+//!     log_at 1 warn
+//!     call greet 0        @ a script function, variable or native by name
+//! ]
+//! ```
+//!
+//! `.const "text"` adds a string to the constant pool without emitting
+//! anything, so a raw `push_const` index can name it; the pool is numbered
+//! in the order strings are first added. `hs1 asm` assembles a whole file
+//! this way with `assemble_named`.
+use anyhow::{bail, Context, Result};
+use hackerscript_bytecode::{native_id, verify, Bytecode, BytecodeEmitter, FuncHeader, LogLevel, Opcode};
+use std::collections::HashMap;
+
+/// Assemble `source` into `emitter`, after checking it with the verifier
+/// on its own.
+pub fn emit_block(emitter: &mut BytecodeEmitter, source: &str) -> Result<()> {
+    let mut fragment = emitter.fork();
+    assemble(&mut fragment, source)?;
+    fragment.emit(Opcode::Halt);
+    verify(&fragment.finish()).context("invalid `asm` block")?;
+    assemble(emitter, source)
+}
+
+/// Assemble `source` as a program of its own, the way `compile_named`
+/// compiles a script: it must pass the verifier, so it ends in `halt` or a
+/// `jump`.
+pub fn assemble_named(source: &str, name: &str) -> Result<Bytecode> {
+    let mut emitter = BytecodeEmitter::new();
+    emitter.set_source(name);
+    assemble(&mut emitter, source)?;
+    let bytecode = emitter.finish();
+    verify(&bytecode).with_context(|| format!("invalid program in {}", name))?;
+    Ok(bytecode)
+}
+
+/// Assemble `source` into `emitter`. Labels resolve to offsets in
+/// `emitter`, so the same source assembles the same way at any position.
+pub fn assemble(emitter: &mut BytecodeEmitter, source: &str) -> Result<()> {
+    let mut tokens = tokenize(source)?.into_iter().peekable();
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    // (operand offset, label, line) of jumps to labels not defined yet
+    let mut fixups: Vec<(usize, String, usize)> = Vec::new();
+    while let Some(token) = tokens.next() {
+        let line = token.line;
+        let Token { text: Text::Word(word), .. } = token else {
+            bail!("asm line {}: expected an instruction, found {}", line, token.text);
+        };
+        if word == ".const" {
+            let Some(Token { text: Text::Str(s), .. }) = tokens.next() else {
+                bail!("asm line {}: `.const` expects a string", line);
+            };
+            emitter.add_constant(s);
+            continue;
+        }
+        if let Some(label) = word.strip_suffix(':') {
+            if labels.insert(label.to_string(), emitter.position() as u32).is_some() {
+                bail!("asm line {}: label `{}` is defined twice", line, label);
+            }
+            continue;
+        }
+        let op = Opcode::from_mnemonic(&word)
+            .with_context(|| format!("asm line {}: unknown instruction `{}`", line, word))?;
+        let mut operand = |what: &str| {
+            tokens
+                .next()
+                .with_context(|| format!("asm line {}: `{}` expects {}", line, word, what))
+        };
+        emitter.emit(op);
+        match op {
+            Opcode::PushConst => match operand("a string or a constant index")?.text {
+                Text::Str(s) => {
+                    let idx = emitter.add_constant(s);
+                    emitter.emit_varint(idx as u64);
+                }
+                Text::Word(w) => emitter.emit_varint(parse(&w, line, "a constant index")?),
+            },
+            Opcode::LoadVar | Opcode::StoreVar | Opcode::Define | Opcode::Import | Opcode::HasKey => {
+                let name = match operand("a name")?.text {
+                    Text::Word(w) | Text::Str(w) => w,
+                };
+                let idx = emitter.add_constant(name);
+                emitter.emit_varint(idx as u64);
+            }
+            Opcode::LoadLocal | Opcode::StoreLocal | Opcode::Unbound | Opcode::CallLocal => {
+                emitter.emit_varint(parse(&operand("a slot")?.text.word(line)?, line, "a slot")?);
+                if op != Opcode::StoreLocal && op != Opcode::Unbound {
+                    let name = operand("a name")?.text.word(line)?;
+                    let idx = emitter.add_constant(name);
+                    emitter.emit_varint(idx as u64);
+                }
+                if op == Opcode::CallLocal {
+                    emitter.emit_u8(parse(&operand("an argument count")?.text.word(line)?, line, "an argument count")?);
+                }
+            }
+            Opcode::Call => {
+                let name = operand("a function name")?.text.word(line)?;
+                let argc = parse(&operand("an argument count")?.text.word(line)?, line, "an argument count")?;
+                let idx = emitter.add_constant(name);
+                emitter.emit_varint(idx as u64);
+                emitter.emit_u8(argc);
+            }
+            Opcode::MakeFunc => {
+                // label name slots params required flags
+                let label = operand("a label")?.text.word(line)?;
+                let name = operand("a function name")?.text.word(line)?;
+                let mut number = |what: &str| operand(what).and_then(|token| token.text.word(line));
+                let slots = parse(&number("a slot count")?, line, "a slot count")?;
+                let params = parse(&number("a parameter count")?, line, "a parameter count")?;
+                let required = parse(&number("a parameter count")?, line, "a parameter count")?;
+                let flags = parse(&number("flags")?, line, "flags")?;
+                let name = emitter.add_constant(name) as u64;
+                fixups.push((emitter.position(), label, line));
+                emitter.emit_func_header(&FuncHeader { entry: 0, name, slots, params, required, flags });
+            }
+            // make_variant "Color" "Red" 0
+            Opcode::MakeVariant => {
+                for what in ["an enum name", "a variant name"] {
+                    let Text::Str(name) = operand(what)?.text else {
+                        bail!("asm line {}: `{}` expects {} as a string", line, word, what);
+                    };
+                    let idx = emitter.add_constant(name);
+                    emitter.emit_varint(idx as u64);
+                }
+                emitter.emit_i64(parse(&operand("a discriminant")?.text.word(line)?, line, "an integer")?);
+            }
+            Opcode::PushInt => emitter.emit_i64(parse(&operand("an integer")?.text.word(line)?, line, "an integer")?),
+            Opcode::PushFloat => emitter.emit_f64(parse(&operand("a number")?.text.word(line)?, line, "a number")?),
+            Opcode::MakeMap | Opcode::LogValues | Opcode::IsArrayLen | Opcode::IsArrayMin => {
+                emitter.emit_varint(parse(&operand("a count")?.text.word(line)?, line, "a count")?)
+            }
+            Opcode::CallNative => {
+                let native = operand("a native name or id")?.text.word(line)?;
+                let id = match native_id(&native) {
+                    Some(id) => id,
+                    None => parse(&native, line, "a native name or id")?,
+                };
+                let argc = parse(&operand("an argument count")?.text.word(line)?, line, "an argument count")?;
+                emitter.emit_varint(u64::from(id));
+                emitter.emit_u8(argc);
+            }
+            Opcode::LogAt => {
+                let count = parse(&operand("a count")?.text.word(line)?, line, "a count")?;
+                let level = operand("a level")?.text.word(line)?;
+                let level = LogLevel::parse(&level)
+                    .with_context(|| format!("asm line {}: unknown log level `{}`", line, level))?;
+                emitter.emit_varint(count);
+                emitter.emit_u8(level as u8);
+            }
+            Opcode::Jump | Opcode::JumpIfFalse | Opcode::Try => {
+                let label = operand("a label")?.text.word(line)?;
+                fixups.push((emitter.position(), label, line));
+                emitter.emit_u32(0);
+            }
+            // jump_table low count default label...
+            Opcode::JumpTable => {
+                emitter.emit_i64(parse(&operand("the lowest value")?.text.word(line)?, line, "an integer")?);
+                let count: u64 = parse(&operand("a count")?.text.word(line)?, line, "a count")?;
+                emitter.emit_varint(count);
+                for _ in 0..=count {
+                    let label = operand("a label")?.text.word(line)?;
+                    fixups.push((emitter.position(), label, line));
+                    emitter.emit_u32(0);
+                }
+            }
+            // jump_string count default ("string" label)...; sorted here
+            Opcode::JumpString => {
+                let count: u64 = parse(&operand("a count")?.text.word(line)?, line, "a count")?;
+                let default = operand("a label")?.text.word(line)?;
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    let Text::Str(key) = operand("a string")?.text else {
+                        bail!("asm line {}: `{}` expects a string", line, word);
+                    };
+                    entries.push((key, operand("a label")?.text.word(line)?));
+                }
+                entries.sort();
+                emitter.emit_varint(count);
+                fixups.push((emitter.position(), default, line));
+                emitter.emit_u32(0);
+                for (key, label) in entries {
+                    let idx = emitter.add_constant(key);
+                    emitter.emit_u32(u32::try_from(idx).context("too many constants")?);
+                    fixups.push((emitter.position(), label, line));
+                    emitter.emit_u32(0);
+                }
+            }
+            _ => {}
+        }
+    }
+    for (at, label, line) in fixups {
+        let target = labels
+            .get(&label)
+            .with_context(|| format!("asm line {}: undefined label `{}`", line, label))?;
+        emitter.patch_u32(at, *target);
+    }
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(word: &str, line: usize, what: &str) -> Result<T> {
+    word.parse()
+        .map_err(|_| anyhow::anyhow!("asm line {}: expected {}, found `{}`", line, what, word))
+}
+
+struct Token {
+    text: Text,
+    /// 1-based, within the block
+    line: usize,
+}
+
+enum Text {
+    Word(String),
+    Str(String),
+}
+
+impl Text {
+    fn word(self, line: usize) -> Result<String> {
+        match self {
+            Text::Word(w) => Ok(w),
+            Text::Str(s) => bail!("asm line {}: unexpected string {:?}", line, s),
+        }
+    }
+}
+
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Text::Word(w) => write!(f, "`{}`", w),
+            Text::Str(s) => write!(f, "string {:?}", s),
+        }
+    }
+}
+
+/// Words and string literals, skipping whitespace and `@` comments.
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '@' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some(c @ ('"' | '\\' | '{' | '}')) => c,
+                            _ => bail!("asm line {}: invalid escape in string", line),
+                        }),
+                        Some('\n') | None => bail!("asm line {}: unterminated string", line),
+                        Some(c) => s.push(c),
+                    }
+                }
+                tokens.push(Token { text: Text::Str(s), line });
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !matches!(c, '"' | '@')) {
+                    word.push(c);
+                }
+                tokens.push(Token { text: Text::Word(word), line });
+            }
+        }
+    }
+    Ok(tokens)
+}
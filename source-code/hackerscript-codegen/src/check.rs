@@ -0,0 +1,810 @@
+//! Name checking before code generation: every variable and function a
+//! script uses must be bound somewhere it can see it, and no two `func`s or
+//! `object`s may share a name. A name declared with `const` may not be bound
+//! again later in its scope, by `let`, another `const`, an `except` or an
+//! `asm` `store_var`. An `object` must define every method of the interfaces
+//! it implements, with as many parameters, itself or through the objects it
+//! extends. It may only extend an object, and not itself through a cycle of
+//! `extends`. Nothing runs an `object` declaration, so its name has no value
+//! and may not be used as one.
+//!
+//! Scopes follow the interpreter: top-level `let`s are globals, a `func`
+//! sees its parameters, its own `let`s and the globals, and a lambda also
+//! sees the locals of the function it is created in. Functions are global
+//! wherever they are defined, and a call may also name a native or a
+//! builtin. `args`, the script's command-line arguments, is a global every
+//! runtime defines.
+//!
+//! `free(name)` is only allowed in `manual` code: the top level of a
+//! `--- manual ---` file, a function marked `manual`, or a function of such
+//! a file that is not marked `auto`. Lambdas take the mode of the function
+//! they are created in. Values are copied, never shared, so freeing a
+//! variable cannot leave another one dangling.
+//!
+//! The check does not follow control flow: a name is bound in a scope if any
+//! statement of that scope binds it, so `if debug [ let level = 1 ]` then
+//! `log level` passes here and can still fail at runtime. Scripts whose
+//! imports were not expanded are only checked for duplicates.
+//!
+//! `StreamChecker` checks names a statement at a time, for compilers that
+//! never hold the whole program.
+//!
+//! The type errors of `types` are reported here too. `hints` finds what is
+//! worth a note but not an error, such as a `match` on an enum that misses
+//! some of its variants.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use hackerscript_ast::{Expr, Func, Lit, MemoryMode, Param, Pattern, Program, Spanned, Stmt, FREE};
+use hackerscript_bytecode::{builtin, native_id, ARGS};
+
+use crate::types;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CheckError {
+    #[error("undefined variable `{name}` {scope}")]
+    UndefinedVariable { name: String, scope: String },
+    #[error("undefined function `{name}` {scope}")]
+    UndefinedFunction { name: String, scope: String },
+    #[error("object `{name}` has no value at run time, but is used {scope}")]
+    ObjectValue { name: String, scope: String },
+    #[error("cannot assign to constant `{name}` {scope}")]
+    ConstAssign { name: String, scope: String },
+    #[error("object `{object}` implements `{name}`, which is not an interface")]
+    UnknownInterface { name: String, object: String },
+    #[error("object `{object}` extends `{parent}`, which is not an object")]
+    UnknownParent { object: String, parent: String },
+    /// The objects of the cycle, as "`A` extends `B` extends `A`"
+    #[error("objects extend each other in a cycle: {cycle}")]
+    InheritanceCycle { cycle: String },
+    #[error("object `{object}` does not define `{method}`, required by interface `{interface}`")]
+    MissingMethod { object: String, interface: String, method: String },
+    #[error("`{object}.{method}` is a field, but interface `{interface}` declares a method")]
+    FieldNotMethod { object: String, interface: String, method: String },
+    #[error("`{object}.{method}` takes {found} parameter(s), but interface `{interface}` declares {expected}")]
+    MethodParams { object: String, interface: String, method: String, expected: usize, found: usize },
+    #[error("`free` needs manual memory mode {scope}")]
+    FreeInAuto { scope: String },
+    #[error("`free` takes one variable {scope}")]
+    FreeArgs { scope: String },
+    #[error("{kind} `{name}` is defined more than once")]
+    Duplicate { kind: &'static str, name: String },
+    #[error("{}{message} {scope}", at_line(*.line))]
+    Type { message: String, scope: String, line: Option<u32> },
+    /// A rule of `strict` mode
+    #[error("{}{message} {scope} (strict mode)", at_line(*.line))]
+    Strict { message: String, scope: String, line: Option<u32> },
+}
+
+fn at_line(line: Option<u32>) -> String {
+    line.map(|line| format!("line {}: ", line)).unwrap_or_default()
+}
+
+/// Every name error in `program`, in source order, then every type error.
+pub fn check(program: &Program) -> Vec<CheckError> {
+    let mut checker = Checker::new();
+    checker.set_file_mode(program.memory_mode);
+    duplicates(&program.body, &mut HashSet::new(), &mut checker.errors);
+    if !has_imports(&program.body) {
+        globals(&program.body, &mut checker.globals);
+        object_names(&program.body, &mut checker.object_names);
+        checker.constants(&program.body);
+        checker.objects(&program.body);
+        checker.body(&program.body);
+    }
+    checker.errors.extend(types::check(program));
+    checker.errors
+}
+
+/// The name check of `check`, fed one top-level statement at a time. A name
+/// used before the statement that binds it is only an error if nothing has
+/// bound it by `finish`, as the whole-program check sees every binding of a
+/// scope at once. Objects, interfaces and types need the whole program and
+/// are not checked.
+pub struct StreamChecker {
+    checker: Checker,
+    /// The top-level `const`s so far
+    consts: HashSet<String>,
+    /// The functions, objects, interfaces and enums so far
+    declared: HashSet<String>,
+    /// Whether an `import` was left unexpanded
+    imports: bool,
+}
+
+impl Default for StreamChecker {
+    fn default() -> Self {
+        StreamChecker { checker: Checker::new(), consts: HashSet::new(), declared: HashSet::new(), imports: false }
+    }
+}
+
+impl StreamChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The file's `--- auto ---` / `--- manual ---` header, once the
+    /// stream has read it.
+    pub fn set_memory_mode(&mut self, mode: Option<MemoryMode>) {
+        self.checker.set_file_mode(mode);
+    }
+
+    pub fn stmt(&mut self, stmt: &Spanned<Stmt>) {
+        let body = std::slice::from_ref(stmt);
+        duplicates(body, &mut self.declared, &mut self.checker.errors);
+        self.imports |= has_imports(body);
+        if self.imports {
+            return;
+        }
+        globals(body, &mut self.checker.globals);
+        object_names(body, &mut self.checker.object_names);
+        let mut rebound = Vec::new();
+        rebindings(body, &mut self.consts, &mut rebound);
+        for name in rebound {
+            let scope = self.checker.scope.clone();
+            self.checker.report(CheckError::ConstAssign { name, scope });
+        }
+        self.checker.body(body);
+    }
+
+    /// The errors of every statement, in the order they were found.
+    pub fn finish(self) -> Vec<CheckError> {
+        let Checker { globals, object_names, errors, .. } = self.checker;
+        errors
+            .into_iter()
+            .filter_map(|error| match error {
+                CheckError::UndefinedVariable { name, scope } | CheckError::UndefinedFunction { name, scope }
+                    if object_names.contains(&name) && !globals.contains(&name) =>
+                {
+                    Some(CheckError::ObjectValue { name, scope })
+                }
+                CheckError::UndefinedVariable { ref name, .. } | CheckError::UndefinedFunction { ref name, .. } => {
+                    (!self.imports && !globals.contains(name)).then_some(error)
+                }
+                error => Some(error),
+            })
+            .collect()
+    }
+
+    /// `finish` as one error, with a line per problem.
+    pub fn ensure(self) -> anyhow::Result<()> {
+        one_error(self.finish())
+    }
+}
+
+/// `check` as one error, with a line per problem.
+pub fn ensure(program: &Program) -> anyhow::Result<()> {
+    one_error(check(program))
+}
+
+/// `errors`, if there are any, as one error with a line per problem.
+pub(crate) fn one_error(errors: Vec<CheckError>) -> anyhow::Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let lines: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    anyhow::bail!("{}", lines.join("\n"))
+}
+
+/// Something worth pointing out in a program that still compiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hint {
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", at_line(self.line), self.message)
+    }
+}
+
+/// The hints for `program`, in source order. So far: a `match` with no
+/// `default` whose cases are variants of one enum, but not all of them.
+pub fn hints(program: &Program) -> Vec<Hint> {
+    let mut enums = HashMap::new();
+    walk(&program.body, &mut |stmt| {
+        if let Stmt::Enum(decl) = stmt {
+            enums.insert(decl.name.as_str(), decl);
+        }
+    });
+    let mut hints = Vec::new();
+    walk_spanned(&program.body, &mut |stmt| {
+        let Stmt::Match { cases, default, .. } = &stmt.node else { return };
+        let Some(covered) = cases.iter().map(|case| variant(&case.value)).collect::<Option<Vec<_>>>() else { return };
+        let Some((enum_name, _)) = covered.first() else { return };
+        let Some(decl) = enums.get(enum_name).filter(|_| default.is_empty()) else { return };
+        if covered.iter().any(|(name, _)| name != enum_name) {
+            return;
+        }
+        let missing: Vec<String> = decl
+            .variants
+            .iter()
+            .filter(|variant| !covered.iter().any(|(_, name)| *name == variant.name.as_str()))
+            .map(|variant| format!("`{}.{}`", enum_name, variant.name))
+            .collect();
+        if !missing.is_empty() {
+            hints.push(Hint {
+                line: (!stmt.span.is_unknown()).then_some(stmt.span.line),
+                message: format!(
+                    "`match` has no case for {}; add {} or a `default`",
+                    missing.join(", "),
+                    if missing.len() == 1 { "it" } else { "them" }
+                ),
+            });
+        }
+    });
+    hints
+}
+
+/// What `extends` leads to from an object.
+enum Chain<'a> {
+    /// The object and each one it extends, nearest first
+    Objects(Vec<&'a Stmt>),
+    /// An object in the chain extends `parent`, which is not an object
+    UnknownParent { object: &'a str, parent: &'a str },
+    /// The objects of a cycle in the chain, starting with the one defined first
+    Cycle(Vec<&'a str>),
+}
+
+/// Follow `extends` from `object`.
+fn chain<'a>(objects: &[(&'a str, &'a Stmt)], object: &'a str) -> Chain<'a> {
+    let find = |name: &str| objects.iter().position(|(object, _)| *object == name);
+    let mut chain: Vec<usize> = Vec::new();
+    let mut next = Some(object);
+    while let Some(name) = next {
+        let Some(at) = find(name) else {
+            let object = chain.last().map_or(object, |&last| objects[last].0);
+            return Chain::UnknownParent { object, parent: name };
+        };
+        if let Some(start) = chain.iter().position(|&seen| seen == at) {
+            let mut cycle = chain.split_off(start);
+            let first = cycle.iter().enumerate().min_by_key(|(_, at)| **at).map_or(0, |(first, _)| first);
+            cycle.rotate_left(first);
+            return Chain::Cycle(cycle.into_iter().map(|at| objects[at].0).collect());
+        }
+        chain.push(at);
+        next = match objects[at].1 {
+            Stmt::Object { parent, .. } => parent.as_deref(),
+            _ => None,
+        };
+    }
+    Chain::Objects(chain.into_iter().map(|at| objects[at].1).collect())
+}
+
+/// A method or field of an object.
+enum Member<'a> {
+    Method(&'a Func),
+    Field,
+}
+
+/// The member `name` of the first object of `chain` that has one, so an
+/// object's own members hide those it extends.
+fn member_of<'a>(chain: &[&'a Stmt], name: &str) -> Option<Member<'a>> {
+    chain.iter().find_map(|object| {
+        let Stmt::Object { body, .. } = object else { return None };
+        body.iter().find_map(|stmt| match &stmt.node {
+            Stmt::Func(func) if func.name.as_str() == name => Some(Member::Method(func)),
+            Stmt::Let { name: field, .. } | Stmt::Const { name: field, .. } if field.as_str() == name => {
+                Some(Member::Field)
+            }
+            _ => None,
+        })
+    })
+}
+
+/// `Enum.Variant` as its enum and variant names.
+fn variant(expr: &Expr) -> Option<(&str, &str)> {
+    let Expr::Index { target, index } = expr else { return None };
+    match (&**target, &**index) {
+        (Expr::Var { name }, Expr::Lit(Lit::Str(variant))) => Some((name.as_str(), variant.as_str())),
+        _ => None,
+    }
+}
+
+struct Checker {
+    globals: HashSet<String>,
+    /// The declared objects, which are not globals: nothing runs their
+    /// declarations, so their names have no value
+    object_names: HashSet<String>,
+    /// Locals of the enclosing function and of each lambda inside it, innermost last
+    locals: Vec<HashSet<String>>,
+    /// Where the errors say the names are, e.g. "in `main`"
+    scope: String,
+    /// The memory mode of the file, and of the code being checked
+    file_mode: MemoryMode,
+    mode: MemoryMode,
+    errors: Vec<CheckError>,
+}
+
+impl Checker {
+    fn new() -> Self {
+        Checker {
+            globals: HashSet::from([ARGS.to_string()]),
+            object_names: HashSet::new(),
+            locals: Vec::new(),
+            scope: "at the top level".to_string(),
+            file_mode: MemoryMode::Auto,
+            mode: MemoryMode::Auto,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Check the top level, and the functions that do not pick their own
+    /// memory mode, in `mode`.
+    fn set_file_mode(&mut self, mode: Option<MemoryMode>) {
+        self.file_mode = mode.unwrap_or(MemoryMode::Auto);
+        self.mode = self.file_mode;
+    }
+
+    /// Report every binding in a scope that rebinds one of its earlier
+    /// `const`s. Inner functions and lambdas are scopes of their own.
+    fn constants(&mut self, body: &[Spanned<Stmt>]) {
+        let mut consts = HashSet::new();
+        let mut rebound = Vec::new();
+        rebindings(body, &mut consts, &mut rebound);
+        for name in rebound {
+            let scope = self.scope.clone();
+            self.report(CheckError::ConstAssign { name, scope });
+        }
+    }
+
+    /// Report every `object` that extends an unknown object or, through
+    /// other objects, itself, and every one that does not define each method
+    /// of the interfaces it implements, itself or through the objects it
+    /// extends.
+    fn objects(&mut self, body: &[Spanned<Stmt>]) {
+        let mut interfaces = HashMap::new();
+        let mut objects = Vec::new();
+        walk(body, &mut |stmt| match stmt {
+            Stmt::Interface(decl) => {
+                interfaces.insert(decl.name.as_str(), decl);
+            }
+            Stmt::Object { name, .. } => objects.push((name.as_str(), stmt)),
+            _ => {}
+        });
+        for &(object, stmt) in &objects {
+            let Stmt::Object { implements, .. } = stmt else { continue };
+            let chain = match chain(&objects, object) {
+                Chain::Objects(chain) => chain,
+                Chain::UnknownParent { object, parent } => {
+                    let (object, parent) = (object.to_string(), parent.to_string());
+                    self.report(CheckError::UnknownParent { object, parent });
+                    continue;
+                }
+                Chain::Cycle(cycle) => {
+                    let mut names: Vec<String> = cycle.iter().map(|name| format!("`{}`", name)).collect();
+                    names.push(names[0].clone());
+                    self.report(CheckError::InheritanceCycle { cycle: names.join(" extends ") });
+                    continue;
+                }
+            };
+            for interface in implements {
+                let Some(decl) = interfaces.get(interface.as_str()) else {
+                    let (name, object) = (interface.to_string(), object.to_string());
+                    self.report(CheckError::UnknownInterface { name, object });
+                    continue;
+                };
+                for method in &decl.methods {
+                    let found = member_of(&chain, &method.name);
+                    let (object, interface) = (object.to_string(), interface.to_string());
+                    let name = method.name.to_string();
+                    match found {
+                        None => self.report(CheckError::MissingMethod { object, interface, method: name }),
+                        Some(Member::Field) => {
+                            self.report(CheckError::FieldNotMethod { object, interface, method: name })
+                        }
+                        Some(Member::Method(func)) if func.params.len() != method.params.len() => {
+                            let (expected, found) = (method.params.len(), func.params.len());
+                            self.report(CheckError::MethodParams { object, interface, method: name, expected, found });
+                        }
+                        Some(Member::Method(_)) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn bound(&self, name: &str) -> bool {
+        self.globals.contains(name) || self.locals.iter().any(|scope| scope.contains(name))
+    }
+
+    fn report(&mut self, error: CheckError) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    fn body(&mut self, body: &[Spanned<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Log { values, .. } => values.iter().for_each(|value| self.expr(value)),
+            Stmt::Let { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::Const { value, .. }
+            | Stmt::Expr { value }
+            | Stmt::Throw { value } => self.expr(value),
+            Stmt::Return { value } => value.iter().for_each(|value| self.expr(value)),
+            Stmt::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.body(then_body);
+                self.body(else_body);
+            }
+            Stmt::While { cond, body } => {
+                self.expr(cond);
+                self.body(body);
+            }
+            Stmt::Match { subject, cases, default } => {
+                self.expr(subject);
+                for case in cases {
+                    self.expr(&case.value);
+                    self.body(&case.body);
+                }
+                self.body(default);
+            }
+            Stmt::Try { body, except, finally } => {
+                self.body(body);
+                if let Some(except) = except {
+                    self.body(&except.body);
+                }
+                self.body(finally);
+            }
+            Stmt::Func(func) => self.func(func),
+            // object bodies do not run
+            Stmt::Object { .. } => {}
+            Stmt::Break
+            | Stmt::Continue
+            | Stmt::Sh { .. }
+            | Stmt::Asm { .. }
+            | Stmt::Enum(_)
+            | Stmt::Interface(_)
+            | Stmt::Import { .. }
+            | Stmt::ImportModule { .. }
+            | Stmt::Require { .. } => {}
+        }
+    }
+
+    /// A named function captures nothing, so only its own locals are in scope.
+    fn func(&mut self, func: &Func) {
+        let outer = std::mem::take(&mut self.locals);
+        let scope = std::mem::replace(&mut self.scope, format!("in `{}`", func.name));
+        let mode = std::mem::replace(&mut self.mode, func.memory_mode.unwrap_or(self.file_mode));
+        self.function_body(&func.params, &func.body);
+        self.mode = mode;
+        self.scope = scope;
+        self.locals = outer;
+    }
+
+    fn function_body(&mut self, params: &[Param], body: &[Spanned<Stmt>]) {
+        let mut locals: HashSet<String> = params.iter().map(|param| param.name.to_string()).collect();
+        bindings(body, &mut locals);
+        self.locals.push(locals);
+        self.constants(body);
+        // defaults are evaluated in the callee's frame
+        for default in params.iter().filter_map(|param| param.default.as_ref()) {
+            self.expr(default);
+        }
+        self.body(body);
+        self.locals.pop();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lit(_) => {}
+            Expr::Var { name } => {
+                if !self.bound(name) {
+                    let (name, scope) = (name.to_string(), self.scope.clone());
+                    self.report(if self.object_names.contains(&name) {
+                        CheckError::ObjectValue { name, scope }
+                    } else {
+                        CheckError::UndefinedVariable { name, scope }
+                    });
+                }
+            }
+            Expr::Call { callee, args } if callee.as_str() == FREE && !self.bound(callee) => {
+                let scope = self.scope.clone();
+                if self.mode != MemoryMode::Manual {
+                    self.report(CheckError::FreeInAuto { scope: scope.clone() });
+                }
+                match args.as_slice() {
+                    [arg @ Expr::Var { .. }] => self.expr(arg),
+                    _ => self.report(CheckError::FreeArgs { scope }),
+                }
+            }
+            Expr::Call { callee, args } => {
+                if !self.bound(callee) && native_id(callee).is_none() && builtin(callee).is_none() {
+                    let (name, scope) = (callee.to_string(), self.scope.clone());
+                    self.report(if self.object_names.contains(&name) {
+                        CheckError::ObjectValue { name, scope }
+                    } else {
+                        CheckError::UndefinedFunction { name, scope }
+                    });
+                }
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Map { entries } => entries.iter().for_each(|(_, value)| self.expr(value)),
+            Expr::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            Expr::Slice { target, start, end } => {
+                self.expr(target);
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                self.expr(cond);
+                self.expr(then_value);
+                self.expr(else_value);
+            }
+            Expr::Lambda { params, body, .. } => {
+                let inner = format!("in a lambda {}", self.scope);
+                let scope = std::mem::replace(&mut self.scope, inner);
+                self.function_body(params, body);
+                self.scope = scope;
+            }
+        }
+    }
+}
+
+/// Report every function, object, interface, enum, enum variant and method
+/// of `body` declared twice, or already in `seen`, adding the new ones to it.
+fn duplicates(body: &[Spanned<Stmt>], seen: &mut HashSet<String>, errors: &mut Vec<CheckError>) {
+    // one error per name, whatever the kinds of its later declarations
+    let mut report = |kind, name: String| {
+        if !errors.iter().any(|error| matches!(error, CheckError::Duplicate { name: seen, .. } if *seen == name)) {
+            errors.push(CheckError::Duplicate { kind, name });
+        }
+    };
+    // methods share a namespace with the other members of their object only,
+    // so an object can override the methods it extends
+    let mut methods = HashSet::new();
+    walk(body, &mut |stmt| {
+        let Stmt::Object { body, .. } = stmt else { return };
+        methods.extend(body.iter().filter_map(|stmt| match &stmt.node {
+            Stmt::Func(func) => Some(func as *const Func),
+            _ => None,
+        }));
+    });
+    walk(body, &mut |stmt| {
+        let (kind, name) = match stmt {
+            Stmt::Func(func) if methods.contains(&(func as *const Func)) => return,
+            Stmt::Func(func) => ("function", func.name.to_string()),
+            Stmt::Object { name: object, body, .. } => {
+                let mut members = HashSet::new();
+                for stmt in body {
+                    if let Stmt::Func(func) = &stmt.node {
+                        if !members.insert(&func.name) {
+                            report("method", format!("{}.{}", object, func.name));
+                        }
+                    }
+                }
+                ("object", object.to_string())
+            }
+            Stmt::Interface(decl) => ("interface", decl.name.to_string()),
+            Stmt::Enum(decl) => {
+                let mut variants = HashSet::new();
+                for variant in &decl.variants {
+                    if !variants.insert(&variant.name) {
+                        let name = format!("{}.{}", decl.name, variant.name);
+                        report("variant", name);
+                    }
+                }
+                ("enum", decl.name.to_string())
+            }
+            _ => return,
+        };
+        if !seen.insert(name.clone()) {
+            report(kind, name);
+        }
+    });
+}
+
+/// Visit every statement in `body`, including those in nested blocks,
+/// function bodies and lambdas.
+pub(crate) fn walk<'a>(body: &'a [Spanned<Stmt>], visit: &mut impl FnMut(&'a Stmt)) {
+    walk_spanned(body, &mut |stmt| visit(&stmt.node));
+}
+
+/// `walk`, with each statement's span.
+fn walk_spanned<'a>(body: &'a [Spanned<Stmt>], visit: &mut impl FnMut(&'a Spanned<Stmt>)) {
+    for stmt in body {
+        visit(stmt);
+        let mut exprs: Vec<&'a Expr> = Vec::new();
+        match &stmt.node {
+            Stmt::If { cond, then_body, else_body } => {
+                exprs.push(cond);
+                walk_spanned(then_body, visit);
+                walk_spanned(else_body, visit);
+            }
+            Stmt::While { cond, body } => {
+                exprs.push(cond);
+                walk_spanned(body, visit);
+            }
+            Stmt::Match { subject, cases, default } => {
+                exprs.push(subject);
+                for case in cases {
+                    exprs.push(&case.value);
+                    walk_spanned(&case.body, visit);
+                }
+                walk_spanned(default, visit);
+            }
+            Stmt::Try { body, except, finally } => {
+                walk_spanned(body, visit);
+                if let Some(except) = except {
+                    walk_spanned(&except.body, visit);
+                }
+                walk_spanned(finally, visit);
+            }
+            Stmt::Func(func) => walk_spanned(&func.body, visit),
+            Stmt::Object { body, .. } => walk_spanned(body, visit),
+            Stmt::Log { values, .. } => exprs.extend(values),
+            Stmt::Let { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::Const { value, .. }
+            | Stmt::Expr { value }
+            | Stmt::Throw { value } => exprs.push(value),
+            Stmt::Return { value } => exprs.extend(value),
+            _ => {}
+        }
+        for expr in exprs {
+            lambdas(expr, &mut |body| walk_spanned(body, visit));
+        }
+    }
+}
+
+/// Call `visit` with the body of every lambda in `expr`, outermost first.
+fn lambdas<'a>(expr: &'a Expr, visit: &mut impl FnMut(&'a [Spanned<Stmt>])) {
+    match expr {
+        Expr::Lambda { params, body, .. } => {
+            visit(body);
+            params.iter().filter_map(|param| param.default.as_ref()).for_each(|default| lambdas(default, visit));
+        }
+        Expr::Call { args, .. } => args.iter().for_each(|arg| lambdas(arg, visit)),
+        Expr::Binary { lhs, rhs, .. } => {
+            lambdas(lhs, visit);
+            lambdas(rhs, visit);
+        }
+        Expr::Map { entries } => entries.iter().for_each(|(_, value)| lambdas(value, visit)),
+        Expr::Index { target, index } => {
+            lambdas(target, visit);
+            lambdas(index, visit);
+        }
+        Expr::Slice { target, start, end } => {
+            lambdas(target, visit);
+            start.iter().chain(end).for_each(|bound| lambdas(bound, visit));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| lambdas(part, visit))
+        }
+        Expr::Lit(_) | Expr::Var { .. } => {}
+    }
+}
+
+/// The globals of a program: top-level bindings, plus every function, enum
+/// and `asm` `store_var` anywhere.
+fn globals(body: &[Spanned<Stmt>], names: &mut HashSet<String>) {
+    bindings(body, names);
+    walk(body, &mut |stmt| match stmt {
+        Stmt::Func(func) => {
+            names.insert(func.name.to_string());
+        }
+        Stmt::Enum(decl) => {
+            names.insert(decl.name.to_string());
+        }
+        Stmt::Asm { code } => names.extend(stored(code)),
+        _ => {}
+    });
+}
+
+fn object_names(body: &[Spanned<Stmt>], names: &mut HashSet<String>) {
+    walk(body, &mut |stmt| {
+        if let Stmt::Object { name, .. } = stmt {
+            names.insert(name.to_string());
+        }
+    });
+}
+
+/// Names bound by `let` (plain or destructuring), `const`, `case` patterns and `except` in `body`
+/// and its nested blocks, but not in the functions or lambdas inside it.
+pub(crate) fn bindings(body: &[Spanned<Stmt>], names: &mut HashSet<String>) {
+    for stmt in body {
+        match &stmt.node {
+            Stmt::Let { name, .. } | Stmt::Const { name, .. } => {
+                names.insert(name.to_string());
+            }
+            Stmt::Destructure { pattern, .. } => names.extend(pattern.names().map(|name| name.to_string())),
+            Stmt::If { then_body, else_body, .. } => {
+                bindings(then_body, names);
+                bindings(else_body, names);
+            }
+            Stmt::While { body, .. } => bindings(body, names),
+            Stmt::Match { cases, default, .. } => {
+                for case in cases {
+                    names.extend(case.pattern.iter().flat_map(Pattern::names).map(|name| name.to_string()));
+                    bindings(&case.body, names);
+                }
+                bindings(default, names);
+            }
+            Stmt::Try { body, except, finally } => {
+                bindings(body, names);
+                if let Some(except) = except {
+                    names.extend(except.name.iter().map(|name| name.to_string()));
+                    bindings(&except.body, names);
+                }
+                bindings(finally, names);
+            }
+            Stmt::Asm { code } => names.extend(stored(code)),
+            _ => {}
+        }
+    }
+}
+
+/// Like `bindings`, in source order: collect the `const`s of `body` into
+/// `consts` and push each later binding of one of them to `rebound`.
+fn rebindings(body: &[Spanned<Stmt>], consts: &mut HashSet<String>, rebound: &mut Vec<String>) {
+    for stmt in body {
+        match &stmt.node {
+            Stmt::Let { name, .. } | Stmt::Const { name, .. } if consts.contains(&name.to_string()) => {
+                rebound.push(name.to_string())
+            }
+            Stmt::Destructure { pattern, .. } => {
+                let names = pattern.names().filter(|name| consts.contains(name.as_str()));
+                rebound.extend(names.map(|name| name.to_string()))
+            }
+            Stmt::Const { name, .. } => {
+                consts.insert(name.to_string());
+            }
+            Stmt::If { then_body, else_body, .. } => {
+                rebindings(then_body, consts, rebound);
+                rebindings(else_body, consts, rebound);
+            }
+            Stmt::While { body, .. } => rebindings(body, consts, rebound),
+            Stmt::Match { cases, default, .. } => {
+                for case in cases {
+                    let names =
+                        case.pattern.iter().flat_map(Pattern::names).filter(|name| consts.contains(name.as_str()));
+                    rebound.extend(names.map(|name| name.to_string()));
+                    rebindings(&case.body, consts, rebound);
+                }
+                rebindings(default, consts, rebound);
+            }
+            Stmt::Try { body, except, finally } => {
+                rebindings(body, consts, rebound);
+                if let Some(except) = except {
+                    let names = except.name.iter().filter(|name| consts.contains(name.as_str()));
+                    rebound.extend(names.map(|name| name.to_string()));
+                    rebindings(&except.body, consts, rebound);
+                }
+                rebindings(finally, consts, rebound);
+            }
+            Stmt::Asm { code } => rebound.extend(stored(code).filter(|name| consts.contains(name))),
+            _ => {}
+        }
+    }
+}
+
+/// The variables an `asm` block stores by name.
+pub(crate) fn stored(code: &str) -> impl Iterator<Item = String> + '_ {
+    code.lines().filter_map(|line| {
+        let mut words = line.split('@').next()?.split_whitespace();
+        (words.next()? == "store_var").then(|| words.next()).flatten().map(str::to_string)
+    })
+}
+
+/// Whether an `import` or `require` is still in `body`, so names may come
+/// from a module that was not loaded.
+fn has_imports(body: &[Spanned<Stmt>]) -> bool {
+    let mut found = false;
+    walk(body, &mut |stmt| {
+        found |= matches!(stmt, Stmt::Import { .. } | Stmt::ImportModule { .. } | Stmt::Require { .. });
+    });
+    found
+}
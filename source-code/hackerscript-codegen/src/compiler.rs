@@ -0,0 +1,840 @@
+use anyhow::Result;
+use hackerscript_ast::{BinOp, Expr, Lit, Param, Pattern, Program, Spanned, Stmt, FREE};
+use hackerscript_bytecode::{
+    builtin, native_id, Bytecode, BytecodeEmitter, Dispatch, FuncHeader, Instruction, Label, LogLevel, Opcode,
+    FUNC_CAPTURES, FUNC_REST,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::{asm, check};
+
+pub struct Compiler {
+    emitter: BytecodeEmitter,
+    /// Functions of the program, wherever they are defined; they shadow builtins
+    functions: HashSet<String>,
+    /// Names top-level statements bind; a call of one is not a call of a builtin
+    globals: HashSet<String>,
+    /// Slots of the function or lambda being compiled, `None` at the top level
+    locals: Option<Locals>,
+    /// Enclosing loops, innermost last
+    loops: Vec<Loop>,
+    /// What leaving the enclosing `try`s early has to undo, innermost last
+    unwinds: Vec<Unwind>,
+}
+
+/// A `while` being compiled: where `continue` and `break` jump to, and how
+/// many `unwinds` it started with.
+#[derive(Clone, Copy)]
+struct Loop {
+    start: Label,
+    end: Label,
+    unwinds: usize,
+}
+
+/// Where the locals of a function body live: its parameters and the names
+/// it binds, after those a lambda captured from the function around it.
+#[derive(Clone, Default)]
+struct Locals {
+    slots: HashMap<String, u64>,
+    count: u64,
+}
+
+impl Locals {
+    fn add(&mut self, name: &str) -> u64 {
+        let slot = self.count;
+        self.slots.insert(name.to_string(), slot);
+        self.count += 1;
+        slot
+    }
+}
+
+/// Something `break`, `continue` or `return` must undo when it jumps out of
+/// a `try`.
+#[derive(Clone)]
+enum Unwind {
+    /// An installed handler, and the `finally` to run after removing it
+    Handler(Vec<Spanned<Stmt>>),
+    /// The error a `finally` is about to re-raise, on the stack
+    Error,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            emitter: BytecodeEmitter::new(),
+            functions: HashSet::new(),
+            globals: HashSet::new(),
+            locals: None,
+            loops: Vec::new(),
+            unwinds: Vec::new(),
+        }
+    }
+
+    /// Source file name recorded in the debug section.
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.emitter.set_source(source);
+    }
+
+    pub fn compile_program(&mut self, program: &Program) -> Result<()> {
+        check::walk(&program.body, &mut |stmt| {
+            if let Stmt::Func(func) = stmt {
+                self.functions.insert(func.name.to_string());
+            }
+        });
+        check::bindings(&program.body, &mut self.globals);
+        // top-level functions and enums can be used before their definition, as in the interpreter
+        let (funcs, rest): (Vec<&Spanned<Stmt>>, Vec<&Spanned<Stmt>>) =
+            program.body.iter().partition(|stmt| matches!(stmt.node, Stmt::Func(_) | Stmt::Enum(_)));
+        for stmt in funcs.into_iter().chain(rest) {
+            self.compile_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    pub fn compile_stmt(&mut self, stmt: &Spanned<Stmt>) -> Result<()> {
+        if !stmt.span.is_unknown() {
+            self.emitter.mark_line(stmt.span.line);
+        }
+        match &stmt.node {
+            Stmt::Log { level: Some(level), values } => {
+                for value in values {
+                    self.compile_expr(value)?;
+                }
+                let count = values.len() as u64;
+                self.emitter.emit_instruction(&Instruction::LogAt { count, level: bytecode_level(*level) });
+            }
+            Stmt::Log { level: None, values } => {
+                for value in values {
+                    self.compile_expr(value)?;
+                }
+                if let [_] = values.as_slice() {
+                    self.emitter.emit(Opcode::LogString);
+                } else {
+                    self.emitter.emit_instruction(&Instruction::LogValues { count: values.len() as u64 });
+                }
+            }
+            Stmt::Let { name, value, .. } | Stmt::Const { name, value, .. } => {
+                self.compile_expr(value)?;
+                self.store(name);
+            }
+            Stmt::Destructure { pattern, value } => {
+                self.compile_expr(value)?;
+                self.unpack(pattern)?;
+            }
+            Stmt::If { .. } if self.if_chain_dispatch(stmt)? => {}
+            Stmt::If { cond, then_body, else_body } => {
+                self.compile_expr(cond)?;
+                let to_else = self.emitter.label();
+                self.emitter.emit_jump(Opcode::JumpIfFalse, to_else);
+                for stmt in then_body {
+                    self.compile_stmt(stmt)?;
+                }
+                if else_body.is_empty() {
+                    self.emitter.bind(to_else);
+                } else {
+                    let to_end = self.emitter.label();
+                    self.emitter.emit_jump(Opcode::Jump, to_end);
+                    self.emitter.bind(to_else);
+                    for stmt in else_body {
+                        self.compile_stmt(stmt)?;
+                    }
+                    self.emitter.bind(to_end);
+                }
+            }
+            Stmt::While { cond, body } => {
+                let (start, end) = (self.emitter.here(), self.emitter.label());
+                self.compile_expr(cond)?;
+                self.emitter.emit_jump(Opcode::JumpIfFalse, end);
+                self.loops.push(Loop { start, end, unwinds: self.unwinds.len() });
+                for stmt in body {
+                    self.compile_stmt(stmt)?;
+                }
+                self.loops.pop();
+                self.emitter.emit_jump(Opcode::Jump, start);
+                self.emitter.bind(end);
+            }
+            Stmt::Break => {
+                let Some(&Loop { end, unwinds, .. }) = self.loops.last() else {
+                    anyhow::bail!("`break` outside a loop");
+                };
+                self.unwind_to(unwinds, false)?;
+                self.emitter.emit_jump(Opcode::Jump, end);
+            }
+            Stmt::Continue => {
+                let Some(&Loop { start, unwinds, .. }) = self.loops.last() else {
+                    anyhow::bail!("`continue` outside a loop");
+                };
+                self.unwind_to(unwinds, false)?;
+                self.emitter.emit_jump(Opcode::Jump, start);
+            }
+            Stmt::Match { subject, cases, default } => {
+                let values: Option<Vec<&Lit>> = cases
+                    .iter()
+                    .map(|case| match (&case.pattern, &case.value) {
+                        (None, Expr::Lit(lit)) => Some(lit),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(table) = values.as_deref().and_then(Table::for_values) {
+                    let cases: Vec<(&Lit, &[Spanned<Stmt>])> =
+                        values.unwrap_or_default().into_iter().zip(cases.iter().map(|case| &case.body[..])).collect();
+                    return self.dispatch(subject, &cases, default, table);
+                }
+                // the subject stays on the stack while the cases compare against it
+                self.compile_expr(subject)?;
+                let to_end = self.emitter.label();
+                for case in cases {
+                    let to_next = self.emitter.label();
+                    match &case.pattern {
+                        // binding the parts takes the subject
+                        Some(pattern) => {
+                            self.test_shape(pattern, to_next);
+                            self.unpack(pattern)?;
+                        }
+                        None => {
+                            self.emitter.emit(Opcode::Dup);
+                            self.compile_expr(&case.value)?;
+                            self.emitter.emit(Opcode::Eq);
+                            self.emitter.emit_jump(Opcode::JumpIfFalse, to_next);
+                            self.emitter.emit(Opcode::Pop);
+                        }
+                    }
+                    for stmt in &case.body {
+                        self.compile_stmt(stmt)?;
+                    }
+                    self.emitter.emit_jump(Opcode::Jump, to_end);
+                    self.emitter.bind(to_next);
+                }
+                self.emitter.emit(Opcode::Pop);
+                for stmt in default {
+                    self.compile_stmt(stmt)?;
+                }
+                self.emitter.bind(to_end);
+            }
+            Stmt::Func(func) => {
+                // a named function sees none of the locals around it
+                let outer = self.locals.take();
+                self.functions.insert(func.name.to_string());
+                let made = self.function(&func.name, &func.params, &func.body);
+                self.locals = outer;
+                made?;
+                let idx = self.emitter.add_constant(func.name.to_string());
+                self.emitter.emit_instruction(&Instruction::Define { name: idx as u64 });
+            }
+            Stmt::Return { value } => {
+                // the value is computed before the `finally` blocks run
+                match value {
+                    Some(value) => self.compile_expr(value)?,
+                    None => self.emitter.emit(Opcode::PushNull),
+                }
+                if self.locals.is_none() {
+                    // a top-level `return` ends the script
+                    self.emitter.emit(Opcode::Pop);
+                    self.unwind_to(0, false)?;
+                    self.emitter.emit(Opcode::Halt);
+                } else {
+                    self.unwind_to(0, true)?;
+                    self.emitter.emit(Opcode::Return);
+                }
+            }
+            Stmt::Expr { value } => {
+                self.compile_expr(value)?;
+                self.emitter.emit(Opcode::Pop);
+            }
+            Stmt::Try { body, except, finally } => {
+                let to_finally = (!finally.is_empty()).then(|| self.begin_try(Unwind::Handler(finally.clone())));
+                let to_except = except.as_ref().map(|_| self.begin_try(Unwind::Handler(Vec::new())));
+                for stmt in body {
+                    self.compile_stmt(stmt)?;
+                }
+                if let (Some(to_except), Some(except)) = (to_except, except) {
+                    self.end_try();
+                    let to_end = self.emitter.label();
+                    self.emitter.emit_jump(Opcode::Jump, to_end);
+                    // the handler starts with the error on the stack
+                    self.emitter.bind(to_except);
+                    match &except.name {
+                        Some(name) => self.store(name),
+                        None => self.emitter.emit(Opcode::Pop),
+                    }
+                    for stmt in &except.body {
+                        self.compile_stmt(stmt)?;
+                    }
+                    self.emitter.bind(to_end);
+                }
+                if let Some(to_finally) = to_finally {
+                    self.end_try();
+                    for stmt in finally {
+                        self.compile_stmt(stmt)?;
+                    }
+                    let to_end = self.emitter.label();
+                    self.emitter.emit_jump(Opcode::Jump, to_end);
+                    // on an error: run the `finally` too, then raise the error again
+                    self.emitter.bind(to_finally);
+                    self.unwinds.push(Unwind::Error);
+                    for stmt in finally {
+                        self.compile_stmt(stmt)?;
+                    }
+                    self.unwinds.pop();
+                    self.emitter.emit(Opcode::Throw);
+                    self.emitter.bind(to_end);
+                }
+            }
+            Stmt::Throw { value } => {
+                self.compile_expr(value)?;
+                self.emitter.emit(Opcode::Throw);
+            }
+            Stmt::Asm { code } => asm::emit_block(&mut self.emitter, code)?,
+            Stmt::Sh { commands } if commands.is_empty() => {}
+            Stmt::Sh { commands } => {
+                let idx = self.emitter.add_constant(commands.join("\n"));
+                self.emitter.emit_instruction(&Instruction::PushConst { index: idx as u64 });
+                self.emitter.emit(Opcode::Sh);
+            }
+            // a global map from each variant's name to the variant
+            Stmt::Enum(decl) => {
+                let enum_name = self.emitter.add_constant(decl.name.to_string());
+                for (name, value) in decl.discriminants() {
+                    let name = self.emitter.add_constant(name.to_string());
+                    self.emitter.emit_instruction(&Instruction::PushConst { index: name as u64 });
+                    let (enum_name, name) = (enum_name as u64, name as u64);
+                    self.emitter.emit_instruction(&Instruction::MakeVariant { enum_name, name, value });
+                }
+                self.emitter.emit_instruction(&Instruction::MakeMap { count: decl.variants.len() as u64 });
+                self.globals.insert(decl.name.to_string());
+                self.emitter.emit_instruction(&Instruction::StoreVar { name: enum_name as u64 });
+            }
+            // one the loader left is a module built into the runtime
+            Stmt::Import { repo, lib } => {
+                let idx = self.emitter.add_constant(format!("{}:{}", repo, lib));
+                self.emitter.emit_instruction(&Instruction::Import { module: idx as u64 });
+            }
+            // the loader replaces top-level imports with the module's statements
+            Stmt::ImportModule { .. } | Stmt::Require { .. } => {
+                anyhow::bail!("line {}: imports must be at the top level and resolved before compiling", stmt.span.line);
+            }
+            // only the checker reads interfaces
+            Stmt::Interface(_) => {}
+            Stmt::Object { .. } => {
+                log::warn!("Unhandled statement: {:?}", stmt);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn compile_expr(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Lit(Lit::Null) => self.emitter.emit(Opcode::PushNull),
+            Expr::Lit(Lit::Bool(true)) => self.emitter.emit(Opcode::PushTrue),
+            Expr::Lit(Lit::Bool(false)) => self.emitter.emit(Opcode::PushFalse),
+            Expr::Lit(Lit::Int(n)) => {
+                self.emitter.emit_instruction(&Instruction::PushInt { value: *n });
+            }
+            Expr::Lit(Lit::Float(x)) => {
+                self.emitter.emit_instruction(&Instruction::PushFloat { value: *x });
+            }
+            Expr::Lit(Lit::Str(s)) => {
+                let idx = self.emitter.add_constant(s.clone());
+                self.emitter.emit_instruction(&Instruction::PushConst { index: idx as u64 });
+            }
+            Expr::Var { name } => {
+                let idx = self.emitter.add_constant(name.to_string()) as u64;
+                let instruction = match self.slot(name) {
+                    // an unset local is looked up as a global, as the interpreter does
+                    Some(slot) => Instruction::LoadLocal { slot, name: idx },
+                    None => Instruction::LoadVar { name: idx },
+                };
+                self.emitter.emit_instruction(&instruction);
+            }
+            Expr::Call { callee, args } if callee.as_str() == FREE && !self.defines(callee) => {
+                let [Expr::Var { name }] = args.as_slice() else {
+                    anyhow::bail!("`{}` takes one variable", FREE);
+                };
+                // the old value is dropped as the slot is overwritten
+                self.emitter.emit(Opcode::PushNull);
+                self.store(name);
+                self.emitter.emit(Opcode::PushNull);
+            }
+            Expr::Call { callee, args } if self.is_builtin(callee) => {
+                let (opcode, arity) = builtin(callee).expect("checked by the guard");
+                if args.len() != arity {
+                    anyhow::bail!("`{}` takes {} arguments but {} were given", callee, arity, args.len());
+                }
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                self.emitter.emit(opcode);
+                self.emitter.emit(Opcode::PushNull);
+            }
+            Expr::Call { callee, args } => {
+                let argc = u8::try_from(args.len())
+                    .map_err(|_| anyhow::anyhow!("too many arguments to `{}` ({})", callee, args.len()))?;
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                let native = native_id(callee)
+                    .filter(|_| !self.functions.contains(callee.as_str()) && !self.globals.contains(callee.as_str()));
+                let instruction = match (self.slot(callee), native) {
+                    (Some(slot), _) => {
+                        let name = self.emitter.add_constant(callee.to_string()) as u64;
+                        Instruction::CallLocal { slot, name, argc }
+                    }
+                    // nothing else can be called by that name
+                    (None, Some(id)) => Instruction::CallNative { native: u64::from(id), argc },
+                    (None, None) => {
+                        let name = self.emitter.add_constant(callee.to_string()) as u64;
+                        Instruction::Call { name, argc }
+                    }
+                };
+                self.emitter.emit_instruction(&instruction);
+            }
+            Expr::Binary { op: op @ (BinOp::And | BinOp::Or), lhs, rhs } => {
+                // keep `lhs` as the result unless it decides nothing
+                self.compile_expr(lhs)?;
+                self.emitter.emit(Opcode::Dup);
+                let to_end = self.emitter.label();
+                if *op == BinOp::Or {
+                    let on_false = self.emitter.label();
+                    self.emitter.emit_jump(Opcode::JumpIfFalse, on_false);
+                    self.emitter.emit_jump(Opcode::Jump, to_end);
+                    self.emitter.bind(on_false);
+                } else {
+                    self.emitter.emit_jump(Opcode::JumpIfFalse, to_end);
+                }
+                self.emitter.emit(Opcode::Pop);
+                self.compile_expr(rhs)?;
+                self.emitter.bind(to_end);
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                let (to_else, to_end) = (self.emitter.label(), self.emitter.label());
+                self.compile_expr(cond)?;
+                self.emitter.emit_jump(Opcode::JumpIfFalse, to_else);
+                self.compile_expr(then_value)?;
+                self.emitter.emit_jump(Opcode::Jump, to_end);
+                self.emitter.bind(to_else);
+                self.compile_expr(else_value)?;
+                self.emitter.bind(to_end);
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                self.compile_expr(lhs)?;
+                self.compile_expr(rhs)?;
+                self.emitter.emit(binop_opcode(*op));
+            }
+            Expr::Map { entries } => {
+                for (key, value) in entries {
+                    let idx = self.emitter.add_constant(key.clone());
+                    self.emitter.emit_instruction(&Instruction::PushConst { index: idx as u64 });
+                    self.compile_expr(value)?;
+                }
+                self.emitter.emit_instruction(&Instruction::MakeMap { count: entries.len() as u64 });
+            }
+            Expr::Index { target, index } => {
+                self.compile_expr(target)?;
+                self.compile_expr(index)?;
+                self.emitter.emit(Opcode::Index);
+            }
+            Expr::Slice { target, start, end } => {
+                self.compile_expr(target)?;
+                for bound in [start, end] {
+                    match bound {
+                        Some(bound) => self.compile_expr(bound)?,
+                        None => self.emitter.emit(Opcode::PushNull),
+                    }
+                }
+                self.emitter.emit(Opcode::Slice);
+            }
+            Expr::Lambda { params, body, .. } => self.function("lambda", params, body)?,
+        }
+        Ok(())
+    }
+
+    /// Compile `stmt`, an `if`, as one `match` if it is a chain comparing
+    /// a variable with enough constants for a jump table.
+    fn if_chain_dispatch(&mut self, stmt: &Spanned<Stmt>) -> Result<bool> {
+        let Some((subject, cases, default)) = if_chain(stmt) else {
+            return Ok(false);
+        };
+        let values: Vec<&Lit> = cases.iter().map(|(value, _)| *value).collect();
+        let Some(table) = Table::for_values(&values) else {
+            return Ok(false);
+        };
+        self.dispatch(subject, &cases, default, table)?;
+        Ok(true)
+    }
+
+    /// Compile a `match` on constants as a jump on the subject's value. As
+    /// with comparing one by one, the first of equal cases wins.
+    fn dispatch(
+        &mut self,
+        subject: &Expr,
+        cases: &[(&Lit, &[Spanned<Stmt>])],
+        default: &[Spanned<Stmt>],
+        table: Table,
+    ) -> Result<()> {
+        self.compile_expr(subject)?;
+        // the case each entry jumps to, with placeholder targets fixed up below
+        let mut entry_cases: Vec<Option<usize>> = Vec::new();
+        let mut entries = Vec::new();
+        let width = match table {
+            Table::Ints { low, len } => {
+                for value in (0..len).map(|i| low + i as i64) {
+                    entry_cases.push(cases.iter().position(|(lit, _)| **lit == Lit::Int(value)));
+                    entries.extend_from_slice(&0u32.to_le_bytes());
+                }
+                self.emitter.emit_instruction(&Instruction::JumpTable { table: Dispatch::table(low, 0, &entries) });
+                4
+            }
+            Table::Strings => {
+                let mut keys: Vec<(&str, usize)> = Vec::with_capacity(cases.len());
+                for (case, (lit, _)) in cases.iter().enumerate() {
+                    if let Lit::Str(key) = lit {
+                        if !keys.iter().any(|(seen, _)| seen == key) {
+                            keys.push((key, case));
+                        }
+                    }
+                }
+                keys.sort();
+                for (key, case) in keys {
+                    let idx = self.emitter.add_constant(key.to_string());
+                    entry_cases.push(Some(case));
+                    entries.extend_from_slice(&u32::try_from(idx)?.to_le_bytes());
+                    entries.extend_from_slice(&0u32.to_le_bytes());
+                }
+                self.emitter.emit_instruction(&Instruction::JumpString { table: Dispatch::string_table(0, &entries) });
+                8
+            }
+        };
+        // point the default and each entry at its case; the entries end the
+        // instruction, just after the default
+        let starts: Vec<Label> = cases.iter().map(|_| self.emitter.label()).collect();
+        let (default_start, to_end) = (self.emitter.label(), self.emitter.label());
+        let first = self.emitter.position() - entries.len();
+        self.emitter.fixup(first - 4, default_start);
+        for (i, case) in entry_cases.into_iter().enumerate() {
+            self.emitter.fixup(first + i * width + width - 4, case.map_or(default_start, |case| starts[case]));
+        }
+        for ((_, body), start) in cases.iter().zip(starts) {
+            self.emitter.bind(start);
+            for stmt in *body {
+                self.compile_stmt(stmt)?;
+            }
+            self.emitter.emit_jump(Opcode::Jump, to_end);
+        }
+        self.emitter.bind(default_start);
+        for stmt in default {
+            self.compile_stmt(stmt)?;
+        }
+        self.emitter.bind(to_end);
+        Ok(())
+    }
+
+    /// Compile a function between `BeginFunc` and `EndFunc`, then push it
+    /// with `MakeFunc`. Inside another function's body it is a lambda, which
+    /// keeps that function's slots and copies their values when it is made.
+    fn function(&mut self, name: &str, params: &[Param], body: &[Spanned<Stmt>]) -> Result<()> {
+        let fixed = params.iter().filter(|param| !param.rest).count();
+        let required = params.iter().take_while(|param| param.default.is_none() && !param.rest).count();
+        let (Ok(fixed), Ok(required)) = (u8::try_from(fixed), u8::try_from(required)) else {
+            anyhow::bail!("`{}` has too many parameters ({})", name, params.len());
+        };
+        let mut flags = 0;
+        if params.last().is_some_and(|param| param.rest) {
+            flags |= FUNC_REST;
+        }
+        if self.locals.is_some() {
+            flags |= FUNC_CAPTURES;
+        }
+
+        let entry = self.emitter.position() as u32;
+        self.emitter.mark_function(name);
+        self.emitter.emit(Opcode::BeginFunc);
+        let mut locals = self.locals.clone().unwrap_or_default();
+        let first = locals.count;
+        for param in params {
+            locals.add(&param.name);
+        }
+        let mut bound = HashSet::new();
+        check::bindings(body, &mut bound);
+        // sorted, so the same source always gets the same slots
+        let mut bound: Vec<String> = bound.into_iter().filter(|name| !locals.slots.contains_key(name)).collect();
+        bound.sort();
+        for name in &bound {
+            locals.add(name);
+        }
+        let outer = self.locals.replace(locals);
+        // a loop or `try` around the definition is not one inside the body
+        let outer_loops = std::mem::take(&mut self.loops);
+        let outer_unwinds = std::mem::take(&mut self.unwinds);
+        let compiled = self.function_body(first, params, body);
+        self.loops = outer_loops;
+        self.unwinds = outer_unwinds;
+        let slots = std::mem::replace(&mut self.locals, outer).expect("set above").count;
+        compiled?;
+        self.emitter.emit(Opcode::EndFunc);
+
+        let name = self.emitter.add_constant(name.to_string()) as u64;
+        let header = FuncHeader { entry, name, slots, params: fixed, required, flags };
+        self.emitter.emit_instruction(&Instruction::MakeFunc { header });
+        Ok(())
+    }
+
+    /// The defaults of the parameters a call left out, then `body`, then a
+    /// `return` in case it falls off the end.
+    fn function_body(&mut self, first: u64, params: &[Param], body: &[Spanned<Stmt>]) -> Result<()> {
+        for (slot, param) in (first..).zip(params) {
+            let Some(default) = param.default.as_ref().filter(|_| !param.rest) else {
+                continue;
+            };
+            self.emitter.emit_instruction(&Instruction::Unbound { slot });
+            let passed = self.emitter.label();
+            self.emitter.emit_jump(Opcode::JumpIfFalse, passed);
+            self.compile_expr(default)?;
+            self.emitter.emit_instruction(&Instruction::StoreLocal { slot });
+            self.emitter.bind(passed);
+        }
+        for stmt in body {
+            self.compile_stmt(stmt)?;
+        }
+        self.emitter.emit(Opcode::PushNull);
+        self.emitter.emit(Opcode::Return);
+        Ok(())
+    }
+
+    /// Whether the script binds `name` itself, hiding a builtin of that name.
+    fn defines(&self, name: &str) -> bool {
+        self.slot(name).is_some() || self.functions.contains(name) || self.globals.contains(name)
+    }
+
+    /// Whether a call to `name` is to a builtin: nothing the script defines
+    /// hides it, as for natives.
+    fn is_builtin(&self, name: &str) -> bool {
+        builtin(name).is_some() && !self.defines(name)
+    }
+
+    /// The slot of local `name`, if it is one.
+    fn slot(&self, name: &str) -> Option<u64> {
+        self.locals.as_ref()?.slots.get(name).copied()
+    }
+
+    /// Pop the top of the stack into the names of `pattern`.
+    fn unpack(&mut self, pattern: &Pattern) -> Result<()> {
+        let keys: Vec<Expr> = match pattern {
+            Pattern::Array { names, .. } => (0..names.len() as i64).map(|i| Expr::Lit(Lit::Int(i))).collect(),
+            Pattern::Map { names } => names.iter().map(|name| Expr::Lit(Lit::Str(name.to_string()))).collect(),
+        };
+        for (name, key) in pattern.names().zip(&keys) {
+            self.emitter.emit(Opcode::Dup);
+            self.compile_expr(key)?;
+            self.emitter.emit(Opcode::Index);
+            self.store(name);
+        }
+        match pattern {
+            Pattern::Array { rest: Some(rest), .. } => {
+                self.compile_expr(&Expr::Lit(Lit::Int(keys.len() as i64)))?;
+                self.emitter.emit(Opcode::PushNull);
+                self.emitter.emit(Opcode::Slice);
+                self.store(rest);
+            }
+            _ => self.emitter.emit(Opcode::Pop),
+        }
+        Ok(())
+    }
+
+    /// Test that the top of the stack has the shape of `pattern`, leaving
+    /// it there: an array of the right length, or a map with every key.
+    /// A mismatch jumps to `mismatch`.
+    fn test_shape(&mut self, pattern: &Pattern, mismatch: Label) {
+        let test = |compiler: &mut Self, instruction: Instruction| {
+            compiler.emitter.emit(Opcode::Dup);
+            compiler.emitter.emit_instruction(&instruction);
+            compiler.emitter.emit_jump(Opcode::JumpIfFalse, mismatch);
+        };
+        match pattern {
+            Pattern::Array { names, rest } => {
+                let count = names.len() as u64;
+                match rest {
+                    Some(_) => test(self, Instruction::IsArrayMin { count }),
+                    None => test(self, Instruction::IsArrayLen { count }),
+                }
+            }
+            Pattern::Map { names } => {
+                for name in names {
+                    let key = self.emitter.add_constant(name.to_string()) as u64;
+                    test(self, Instruction::HasKey { key });
+                }
+            }
+        }
+    }
+
+    /// Pop the top of the stack into variable `name`.
+    fn store(&mut self, name: &str) {
+        match self.slot(name) {
+            Some(slot) => {
+                self.emitter.emit_instruction(&Instruction::StoreLocal { slot });
+            }
+            None => {
+                if self.locals.is_none() {
+                    self.globals.insert(name.to_string());
+                }
+                let idx = self.emitter.add_constant(name.to_string());
+                self.emitter.emit_instruction(&Instruction::StoreVar { name: idx as u64 });
+            }
+        }
+    }
+
+    /// Install a handler, returning the label to bind where it starts.
+    fn begin_try(&mut self, unwind: Unwind) -> Label {
+        let handler = self.emitter.label();
+        self.emitter.emit_jump(Opcode::Try, handler);
+        self.unwinds.push(unwind);
+        handler
+    }
+
+    fn end_try(&mut self) {
+        self.emitter.emit(Opcode::EndTry);
+        self.unwinds.pop();
+    }
+
+    /// Undo the `unwinds` above `depth`, innermost first, before a jump out
+    /// of them: remove each handler and run its `finally`, and drop any
+    /// error a `finally` was going to re-raise. With `keep_top`, the value
+    /// on top of the stack (what a `return` returns) stays there.
+    fn unwind_to(&mut self, depth: usize, keep_top: bool) -> Result<()> {
+        let left = self.unwinds.split_off(depth);
+        for (i, unwind) in left.iter().enumerate().rev() {
+            match unwind {
+                Unwind::Error => {
+                    if keep_top {
+                        self.emitter.emit(Opcode::Swap);
+                    }
+                    self.emitter.emit(Opcode::Pop);
+                }
+                Unwind::Handler(finally) => {
+                    self.emitter.emit(Opcode::EndTry);
+                    // the `finally` runs inside the handlers still around it
+                    self.unwinds.extend(left[..i].iter().cloned());
+                    for stmt in finally {
+                        self.compile_stmt(stmt)?;
+                    }
+                    self.unwinds.truncate(depth);
+                }
+            }
+        }
+        self.unwinds.extend(left);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Bytecode {
+        self.emitter.emit(Opcode::Halt);
+        self.emitter.finish()
+    }
+}
+
+/// Fewest cases worth a jump table rather than comparing one by one.
+const MIN_TABLE_CASES: usize = 4;
+
+/// How a `match` on constant cases jumps straight to the right one.
+#[derive(Debug, Clone, Copy)]
+enum Table {
+    /// `JumpTable` over `low..low + len`
+    Ints { low: i64, len: u64 },
+    /// `JumpString`
+    Strings,
+}
+
+impl Table {
+    /// The table for these case values, if they are enough ints close
+    /// together or enough strings.
+    fn for_values(values: &[&Lit]) -> Option<Table> {
+        if values.len() < MIN_TABLE_CASES {
+            return None;
+        }
+        if values.iter().all(|value| matches!(value, Lit::Str(_))) {
+            return Some(Table::Strings);
+        }
+        let ints: Vec<i64> = values
+            .iter()
+            .map(|value| match value {
+                // a float equals an int only as exactly as f64 holds it
+                Lit::Int(n) if n.unsigned_abs() <= 1 << 53 => Some(*n),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        let (low, high) = (*ints.iter().min()?, *ints.iter().max()?);
+        let len = high.abs_diff(low) + 1;
+        // at least half the entries jump to a case
+        (len <= 2 * values.len() as u64).then_some(Table::Ints { low, len })
+    }
+}
+
+/// `if x == 1 [ ] else if x == 2 [ ] ...`: the variable, each constant it is
+/// compared with and its branch, and the final `else`.
+type IfChain<'a> = (&'a Expr, Vec<(&'a Lit, &'a [Spanned<Stmt>])>, &'a [Spanned<Stmt>]);
+
+fn if_chain(stmt: &Spanned<Stmt>) -> Option<IfChain<'_>> {
+    let mut subject: Option<&Expr> = None;
+    let mut cases = Vec::new();
+    let mut rest = std::slice::from_ref(stmt);
+    while let [Spanned { node: Stmt::If { cond, then_body, else_body }, .. }] = rest {
+        let Expr::Binary { op: BinOp::Eq, lhs, rhs } = cond else {
+            break;
+        };
+        let (var, lit) = match (lhs.as_ref(), rhs.as_ref()) {
+            (var @ Expr::Var { .. }, Expr::Lit(lit)) | (Expr::Lit(lit), var @ Expr::Var { .. }) => (var, lit),
+            _ => break,
+        };
+        if *subject.get_or_insert(var) != var {
+            break;
+        }
+        cases.push((lit, &then_body[..]));
+        rest = else_body;
+    }
+    Some((subject?, cases, rest))
+}
+
+/// Compile a whole program to bytecode.
+pub fn compile(program: &Program) -> Result<Bytecode> {
+    let mut compiler = Compiler::new();
+    compiler.compile_program(program)?;
+    Ok(compiler.finish())
+}
+
+/// Like `compile`, recording `source` as the file name in the debug section.
+pub fn compile_named(program: &Program, source: &str) -> Result<Bytecode> {
+    let mut compiler = Compiler::new();
+    compiler.set_source(source);
+    compiler.compile_program(program)?;
+    Ok(compiler.finish())
+}
+
+fn binop_opcode(op: BinOp) -> Opcode {
+    match op {
+        BinOp::Add => Opcode::Add,
+        BinOp::Sub => Opcode::Sub,
+        BinOp::Mul => Opcode::Mul,
+        BinOp::Div => Opcode::Div,
+        BinOp::Rem => Opcode::Rem,
+        BinOp::Eq => Opcode::Eq,
+        BinOp::Ne => Opcode::Ne,
+        BinOp::Lt => Opcode::Lt,
+        BinOp::Le => Opcode::Le,
+        BinOp::Gt => Opcode::Gt,
+        BinOp::Ge => Opcode::Ge,
+        BinOp::And | BinOp::Or => unreachable!("`{}` short-circuits and is compiled to jumps", op.symbol()),
+    }
+}
+
+fn bytecode_level(level: hackerscript_ast::LogLevel) -> LogLevel {
+    match level {
+        hackerscript_ast::LogLevel::Debug => LogLevel::Debug,
+        hackerscript_ast::LogLevel::Info => LogLevel::Info,
+        hackerscript_ast::LogLevel::Warn => LogLevel::Warn,
+        hackerscript_ast::LogLevel::Error => LogLevel::Error,
+    }
+}
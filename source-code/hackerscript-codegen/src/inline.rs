@@ -0,0 +1,404 @@
+//! Inlining of small functions, run by `opt::optimize` before the second
+//! constant-folding pass so the expanded calls fold too. It works on the
+//! AST, so the bytecode compiler and the native backend both get it.
+//!
+//! Only a top-level function whose whole body is `return <expr>` can be
+//! inlined: a call to it becomes `<expr>` with the arguments put in for the
+//! parameters. A call is only expanded where that cannot change what the
+//! script does:
+//!
+//! - the function does not reach itself through its calls, and its name is
+//!   neither a native's nor a global variable's;
+//! - it has no defaults, rest parameter or lambdas, and does not call its
+//!   parameters;
+//! - the call passes one argument per parameter, each a literal or a
+//!   variable, so evaluating them again, later or not at all is harmless;
+//! - nothing in scope at the call site hides the function or a name its
+//!   body uses.
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use hackerscript_ast::{Expr, Func, Param, Program, Spanned, Stmt};
+use hackerscript_bytecode::native_id;
+
+use crate::check;
+use crate::opt::Eliminated;
+
+/// Largest body, in expression nodes, inlined without `inline`.
+pub const DEFAULT_BUDGET: usize = 12;
+
+/// Inline calls to functions whose body is at most `budget` expression nodes,
+/// and to every `func f() inline [ ... ]` that can be inlined at all.
+/// Reports how many calls to each function were expanded.
+pub fn inline_functions(program: &mut Program, budget: usize) -> Vec<Eliminated> {
+    let mut inliner = Inliner { bodies: candidates(&program.body), scopes: Vec::new(), counts: HashMap::new() };
+    if inliner.bodies.is_empty() {
+        return Vec::new();
+    }
+    inliner.expand_bodies(budget);
+    inliner.block(&mut program.body);
+
+    program
+        .body
+        .iter()
+        .filter_map(|stmt| match &stmt.node {
+            Stmt::Func(func) => inliner
+                .counts
+                .get(func.name.as_str())
+                .map(|&calls| Eliminated::Inlined { name: func.name.to_string(), calls }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A function that can be inlined: its parameters and returned expression.
+struct Body {
+    params: Vec<String>,
+    expr: Expr,
+    inline: bool,
+}
+
+impl Body {
+    /// Names the body uses other than its parameters.
+    fn free_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        vars_in(&self.expr, &mut names);
+        let mut free: Vec<String> =
+            names.into_iter().filter(|name| !self.params.iter().any(|param| param == name)).map(String::from).collect();
+        callees_in(&self.expr, &mut free);
+        free
+    }
+}
+
+/// Top-level functions that may be inlined, by name.
+fn candidates(body: &[Spanned<Stmt>]) -> HashMap<String, Body> {
+    let mut globals = HashSet::new();
+    check::bindings(body, &mut globals);
+    let funcs: HashMap<&str, &Func> = body
+        .iter()
+        .filter_map(|stmt| match &stmt.node {
+            Stmt::Func(func) => Some((func.name.as_str(), func)),
+            _ => None,
+        })
+        .collect();
+
+    funcs
+        .values()
+        .filter(|func| native_id(&func.name).is_none() && !globals.contains(func.name.as_str()))
+        .filter(|func| !recursive(func, &funcs))
+        .filter_map(|func| {
+            let [Spanned { node: Stmt::Return { value: Some(expr) }, .. }] = &func.body[..] else {
+                return None;
+            };
+            if func.params.iter().any(|param| param.default.is_some() || param.rest) || has_lambda(expr) {
+                return None;
+            }
+            let params: Vec<String> = func.params.iter().map(|param| param.name.to_string()).collect();
+            let mut callees = Vec::new();
+            callees_in(expr, &mut callees);
+            if callees.iter().any(|callee| params.contains(callee)) {
+                return None;
+            }
+            // the budget is checked once the calls in the body are expanded too
+            Some((func.name.to_string(), Body { params, expr: expr.clone(), inline: func.inline }))
+        })
+        .collect()
+}
+
+/// Whether `func` can call itself, directly or through other functions.
+fn recursive(func: &Func, funcs: &HashMap<&str, &Func>) -> bool {
+    let mut pending = Vec::new();
+    crate::opt::func_calls(func, &mut pending);
+    let mut seen = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if name == func.name.as_str() {
+            return true;
+        }
+        if seen.insert(name) {
+            if let Some(callee) = funcs.get(name) {
+                crate::opt::func_calls(callee, &mut pending);
+            }
+        }
+    }
+    false
+}
+
+struct Inliner {
+    bodies: HashMap<String, Body>,
+    /// Locals of the function being rewritten and of each lambda inside it,
+    /// innermost last; empty at the top level
+    scopes: Vec<HashSet<String>>,
+    /// Calls expanded so far, by function
+    counts: HashMap<String, usize>,
+}
+
+impl Inliner {
+    /// Expand the calls inside each inlinable body, callees first, and drop
+    /// the bodies that end up over `budget`.
+    fn expand_bodies(&mut self, budget: usize) {
+        let mut names: Vec<String> = self.bodies.keys().cloned().collect();
+        names.sort();
+        let mut done = HashSet::new();
+        for name in names {
+            self.expand_body(&name, budget, &mut done);
+        }
+        // only calls in the program itself are reported
+        self.counts.clear();
+    }
+
+    fn expand_body(&mut self, name: &str, budget: usize, done: &mut HashSet<String>) {
+        if !done.insert(name.to_string()) {
+            return;
+        }
+        let Some(mut body) = self.bodies.remove(name) else {
+            return;
+        };
+        let mut callees = Vec::new();
+        callees_in(&body.expr, &mut callees);
+        for callee in callees {
+            self.expand_body(&callee, budget, done);
+        }
+        self.scopes.push(body.params.iter().cloned().collect());
+        self.expr(&mut body.expr);
+        self.scopes.pop();
+        if body.inline || size(&body.expr) <= budget {
+            self.bodies.insert(name.to_string(), body);
+        }
+    }
+
+    fn block(&mut self, body: &mut [Spanned<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Log { values, .. } => values.iter_mut().for_each(|value| self.expr(value)),
+            Stmt::Let { value, .. }
+            | Stmt::Destructure { value, .. }
+            | Stmt::Const { value, .. }
+            | Stmt::Expr { value }
+            | Stmt::Throw { value } => self.expr(value),
+            Stmt::Return { value } => value.iter_mut().for_each(|value| self.expr(value)),
+            Stmt::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.block(then_body);
+                self.block(else_body);
+            }
+            Stmt::While { cond, body } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::Match { subject, cases, default } => {
+                self.expr(subject);
+                for case in cases {
+                    self.expr(&mut case.value);
+                    self.block(&mut case.body);
+                }
+                self.block(default);
+            }
+            Stmt::Try { body, except, finally } => {
+                self.block(body);
+                if let Some(except) = except {
+                    self.block(&mut except.body);
+                }
+                self.block(finally);
+            }
+            // a named function sees only its own locals and the globals
+            Stmt::Func(func) => {
+                let outer = mem::take(&mut self.scopes);
+                self.function(&mut func.params, &mut func.body);
+                self.scopes = outer;
+            }
+            Stmt::Object { body, .. } => self.block(body),
+            Stmt::Break
+            | Stmt::Continue
+            | Stmt::Sh { .. }
+            | Stmt::Asm { .. }
+            | Stmt::Enum(_)
+            | Stmt::Interface(_)
+            | Stmt::Import { .. }
+            | Stmt::ImportModule { .. }
+            | Stmt::Require { .. } => {}
+        }
+    }
+
+    fn function(&mut self, params: &mut [Param], body: &mut [Spanned<Stmt>]) {
+        let mut locals: HashSet<String> = params.iter().map(|param| param.name.to_string()).collect();
+        check::bindings(body, &mut locals);
+        self.scopes.push(locals);
+        for default in params.iter_mut().filter_map(|param| param.default.as_mut()) {
+            self.expr(default);
+        }
+        self.block(body);
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Call { args, .. } => args.iter_mut().for_each(|arg| self.expr(arg)),
+            Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Map { entries } => entries.iter_mut().for_each(|(_, value)| self.expr(value)),
+            Expr::Slice { target, start, end } => {
+                self.expr(target);
+                start.iter_mut().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                [cond, then_value, else_value].into_iter().for_each(|part| self.expr(part))
+            }
+            Expr::Lambda { params, body, .. } => self.function(params, body),
+            Expr::Var { .. } | Expr::Lit(_) => {}
+        }
+        if let Some(inlined) = self.inlined(expr) {
+            *expr = inlined;
+        }
+    }
+
+    fn local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    /// The body of the function `expr` calls with its arguments put in, if
+    /// the call can be expanded here.
+    fn inlined(&mut self, expr: &Expr) -> Option<Expr> {
+        let Expr::Call { callee, args } = expr else {
+            return None;
+        };
+        let body = self.bodies.get(callee.as_str())?;
+        if args.len() != body.params.len() || self.local(callee) {
+            return None;
+        }
+        if body.free_names().iter().any(|name| self.local(name)) {
+            return None;
+        }
+        let mut callees = Vec::new();
+        callees_in(&body.expr, &mut callees);
+        let calls = !callees.is_empty();
+        for (param, arg) in body.params.iter().zip(args) {
+            match arg {
+                Expr::Lit(_) => {}
+                // a local cannot change while the body runs, but a call could change a global
+                Expr::Var { name } if !calls || self.local(name) => {
+                    // reading it can fail, so it must still be read
+                    if !uses(&body.expr, param) {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        let mut inlined = body.expr.clone();
+        substitute(&mut inlined, &body.params, args);
+        *self.counts.entry(callee.to_string()).or_default() += 1;
+        Some(inlined)
+    }
+}
+
+/// Put `args` in for the variables named by `params`.
+fn substitute(expr: &mut Expr, params: &[String], args: &[Expr]) {
+    match expr {
+        Expr::Var { name } => {
+            if let Some(i) = params.iter().position(|param| param == name.as_str()) {
+                *expr = args[i].clone();
+            }
+        }
+        Expr::Call { args: inner, .. } => inner.iter_mut().for_each(|arg| substitute(arg, params, args)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            substitute(lhs, params, args);
+            substitute(rhs, params, args);
+        }
+        Expr::Map { entries } => entries.iter_mut().for_each(|(_, value)| substitute(value, params, args)),
+        Expr::Slice { target, start, end } => {
+            substitute(target, params, args);
+            start.iter_mut().chain(end).for_each(|bound| substitute(bound, params, args));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| substitute(part, params, args))
+        }
+        Expr::Lambda { .. } | Expr::Lit(_) => {}
+    }
+}
+
+/// Number of expression nodes in `expr`.
+fn size(expr: &Expr) -> usize {
+    1 + match expr {
+        Expr::Call { args, .. } => args.iter().map(size).sum(),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => size(lhs) + size(rhs),
+        Expr::Map { entries } => entries.iter().map(|(_, value)| size(value)).sum(),
+        Expr::Slice { target, start, end } => {
+            size(target) + start.iter().chain(end).map(|bound| size(bound)).sum::<usize>()
+        }
+        Expr::Ternary { cond, then_value, else_value } => size(cond) + size(then_value) + size(else_value),
+        Expr::Lambda { .. } | Expr::Var { .. } | Expr::Lit(_) => 0,
+    }
+}
+
+fn has_lambda(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lambda { .. } => true,
+        Expr::Call { args, .. } => args.iter().any(has_lambda),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => has_lambda(lhs) || has_lambda(rhs),
+        Expr::Map { entries } => entries.iter().any(|(_, value)| has_lambda(value)),
+        Expr::Slice { target, start, end } => {
+            has_lambda(target) || start.iter().chain(end).any(|bound| has_lambda(bound))
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            has_lambda(cond) || has_lambda(then_value) || has_lambda(else_value)
+        }
+        Expr::Var { .. } | Expr::Lit(_) => false,
+    }
+}
+
+/// Whether `expr` reads the variable `name`.
+fn uses(expr: &Expr, name: &str) -> bool {
+    let mut vars = Vec::new();
+    vars_in(expr, &mut vars);
+    vars.contains(&name)
+}
+
+fn vars_in<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Var { name } => out.push(name),
+        Expr::Call { args, .. } => args.iter().for_each(|arg| vars_in(arg, out)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            vars_in(lhs, out);
+            vars_in(rhs, out);
+        }
+        Expr::Map { entries } => entries.iter().for_each(|(_, value)| vars_in(value, out)),
+        Expr::Slice { target, start, end } => {
+            vars_in(target, out);
+            start.iter().chain(end).for_each(|bound| vars_in(bound, out));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| vars_in(part, out))
+        }
+        Expr::Lambda { .. } | Expr::Lit(_) => {}
+    }
+}
+
+/// The functions `expr` calls by name.
+fn callees_in(expr: &Expr, out: &mut Vec<String>) {
+    if let Expr::Call { callee, .. } = expr {
+        out.push(callee.to_string());
+    }
+    match expr {
+        Expr::Call { args, .. } => args.iter().for_each(|arg| callees_in(arg, out)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            callees_in(lhs, out);
+            callees_in(rhs, out);
+        }
+        Expr::Map { entries } => entries.iter().for_each(|(_, value)| callees_in(value, out)),
+        Expr::Slice { target, start, end } => {
+            callees_in(target, out);
+            start.iter().chain(end).for_each(|bound| callees_in(bound, out));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| callees_in(part, out))
+        }
+        Expr::Lambda { .. } | Expr::Var { .. } | Expr::Lit(_) => {}
+    }
+}
@@ -0,0 +1,13 @@
+//! Code generation from the shared AST: `.bc` bytecode and (with the `native`
+//! feature) Cranelift objects and shared libraries.
+pub mod asm;
+pub mod check;
+pub mod compiler;
+pub mod inline;
+pub mod opt;
+pub mod strict;
+pub mod types;
+#[cfg(feature = "native")]
+pub mod native;
+
+pub use compiler::Compiler;
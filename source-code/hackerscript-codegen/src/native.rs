@@ -0,0 +1,1250 @@
+//! `hs1 compile --native`: lower the AST to Cranelift IR in an object file.
+//!
+//! Values are typed statically: ints (`i64`), bools (`i8`), floats (`f64`)
+//! and strings, which are pointers to NUL-terminated text. An untyped
+//! parameter takes the type of the argument the first call passes it.
+//! Anything the backend cannot lower yet is an error rather than being
+//! dropped, so native code never silently diverges from the VM.
+//!
+//! Formatting floats and joining strings with `+` call into a small C
+//! runtime (`native_runtime.c`) that the linker compiles in. Joined strings
+//! are never freed.
+use anyhow::{Context, Result};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{types, AbiParam, Block, Endianness, InstBuilder, MemFlags, Signature, Type, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_module::{default_libcall_names, DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use hackerscript_ast::{BinOp, Enum, Expr, Func, Lit, Program, Spanned, Stmt};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+/// C-ABI types allowed in exported signatures, and the types of values in
+/// native code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CType {
+    Bool,
+    Int,
+    Float,
+    CStr,
+}
+
+impl CType {
+    fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "Bool" | "bool" => Ok(CType::Bool),
+            "Int" | "int" => Ok(CType::Int),
+            "Float" | "float" => Ok(CType::Float),
+            "String" | "string" | "CStr" | "cstr" => Ok(CType::CStr),
+            other => anyhow::bail!("type `{}` cannot cross the C ABI (use Bool, Int, Float or String)", other),
+        }
+    }
+
+    fn of(lit: &Lit) -> Option<Self> {
+        match lit {
+            Lit::Null => None,
+            Lit::Bool(_) => Some(CType::Bool),
+            Lit::Int(_) => Some(CType::Int),
+            Lit::Float(_) => Some(CType::Float),
+            Lit::Str(_) => Some(CType::CStr),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            CType::Bool => "bool",
+            CType::Int => "int",
+            CType::Float => "float",
+            CType::CStr => "string",
+        }
+    }
+
+    /// The name with its article, for messages
+    fn a(self) -> &'static str {
+        match self {
+            CType::Bool => "a bool",
+            CType::Int => "an int",
+            CType::Float => "a float",
+            CType::CStr => "a string",
+        }
+    }
+
+    fn ir_type(self, pointer: Type) -> Type {
+        match self {
+            CType::Bool => types::I8,
+            CType::Int => types::I64,
+            CType::Float => types::F64,
+            CType::CStr => pointer,
+        }
+    }
+
+    /// C declaration of `name` with this type, e.g. `const char *name`.
+    fn c_decl(self, name: &str) -> String {
+        match self {
+            CType::Bool => format!("bool {}", name),
+            CType::Int => format!("int64_t {}", name),
+            CType::Float => format!("double {}", name),
+            CType::CStr => format!("const char *{}", name),
+        }
+    }
+}
+
+/// Something the object exports, for the generated C header.
+#[derive(Debug)]
+enum Export {
+    Func {
+        name: String,
+        params: Vec<(String, CType)>,
+        ret: Option<CType>,
+    },
+    Const {
+        name: String,
+        ty: CType,
+    },
+    Enum {
+        name: String,
+        variants: Vec<(String, i64)>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, CType)>,
+    },
+}
+
+/// A top-level function as declared in the object.
+#[derive(Debug, Clone)]
+struct NativeFunc {
+    id: FuncId,
+    params: Vec<CType>,
+    ret: Option<CType>,
+}
+
+/// Longest `i64` in decimal: `-9223372036854775808`
+const INT_DIGITS: i64 = 20;
+
+pub struct NativeCompiler {
+    module: ObjectModule,
+    strings: HashMap<String, DataId>,
+    /// libc functions the code calls
+    imports: HashMap<&'static str, FuncId>,
+    /// `.hs.write_int`, once something logs an int
+    write_int: Option<FuncId>,
+    /// The program's top-level functions and compile-time constants
+    funcs: HashMap<String, Func>,
+    consts: HashMap<String, Lit>,
+    /// Functions declared so far; only those called or exported are
+    declared: HashMap<String, NativeFunc>,
+    /// Declared functions whose bodies are still to be compiled
+    pending: Vec<String>,
+    exports: Vec<Export>,
+}
+
+impl NativeCompiler {
+    pub fn new(name: &str) -> Result<Self> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("is_pic", "true")?;
+        let isa_builder = cranelift_native::builder()
+            .map_err(|msg| anyhow::anyhow!("host machine is not supported: {}", msg))?;
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder))?;
+        let builder = ObjectBuilder::new(isa, name, default_libcall_names())?;
+        Ok(Self {
+            module: ObjectModule::new(builder),
+            strings: HashMap::new(),
+            imports: HashMap::new(),
+            write_int: None,
+            funcs: HashMap::new(),
+            consts: HashMap::new(),
+            declared: HashMap::new(),
+            pending: Vec::new(),
+            exports: Vec::new(),
+        })
+    }
+
+    /// Compile every top-level `pub func` into an exported C function, and
+    /// every `pub const` and `pub enum` into exported read-only data
+    /// (`Color_Red` for variant `Red` of `Color`). A `pub object` only adds
+    /// its struct to the header. Functions they call are compiled too, but
+    /// not exported.
+    pub fn compile_program(&mut self, program: &Program) -> Result<()> {
+        self.scan(program);
+        for stmt in &program.body {
+            match &stmt.node {
+                Stmt::Func(func) if func.public => self.define_export(func)?,
+                Stmt::Const { public: true, name, .. } => {
+                    let Some(lit) = self.consts.get(name.as_str()).cloned() else {
+                        anyhow::bail!("`const {}` must be a compile-time constant to be exported", name);
+                    };
+                    self.define_const(name, &lit)?;
+                }
+                Stmt::Enum(def) if def.public => self.define_enum(def)?,
+                Stmt::Object { public: true, name, parent, body, .. } => {
+                    self.define_struct(name, parent.is_some(), body)?
+                }
+                _ => {}
+            }
+        }
+        self.define_pending()
+    }
+
+    /// C header declaring everything `compile_program` exported.
+    /// `guard` names the include guard, e.g. `HS_COLORS_H`.
+    pub fn c_header(&self, guard: &str) -> String {
+        let mut out = String::new();
+        write_header(&mut out, guard, &self.exports).expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Compile the top-level statements into a C `main`, for `--crate-type exe`.
+    pub fn compile_main(&mut self, program: &Program) -> Result<()> {
+        self.scan(program);
+        let mut sig = self.module.make_signature();
+        sig.returns.push(AbiParam::new(types::I32));
+        let id = self
+            .module
+            .declare_function("main", Linkage::Export, &sig)
+            .context("Cannot declare `main`")?;
+        self.define_function(id, sig, None, &[], &program.body).context("Cannot compile `main`")?;
+        self.define_pending()
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>> {
+        Ok(self.module.finish().emit()?)
+    }
+
+    /// Record the top-level functions and the constants whose values are
+    /// known at compile time.
+    fn scan(&mut self, program: &Program) {
+        for stmt in &program.body {
+            match &stmt.node {
+                Stmt::Func(func) => {
+                    self.funcs.insert(func.name.to_string(), func.clone());
+                }
+                Stmt::Const { name, value, .. } => {
+                    if let Some(lit) = const_value(value, &self.consts) {
+                        self.consts.insert(name.to_string(), lit);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn define_export(&mut self, func: &Func) -> Result<()> {
+        let native = self.function(&func.name, &[])?;
+        let params = func.params.iter().zip(native.params).map(|(p, ty)| (p.name.to_string(), ty)).collect();
+        self.exports.push(Export::Func { name: func.name.to_string(), params, ret: native.ret });
+        Ok(())
+    }
+
+    /// Top-level function `name`, declared on first use. A `pub func` keeps
+    /// its name and needs typed parameters to cross the C ABI; any other is
+    /// local to the object, and its untyped parameters take the types of
+    /// `args`, those of the call that declares it.
+    fn function(&mut self, name: &str, args: &[CType]) -> Result<NativeFunc> {
+        if let Some(native) = self.declared.get(name) {
+            return Ok(native.clone());
+        }
+        let func = self.funcs.get(name).with_context(|| format!("no function `{}`", name))?.clone();
+        let pointer = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        let mut params = Vec::new();
+        for (i, param) in func.params.iter().enumerate() {
+            let ty = match (&param.ty, func.public) {
+                _ if param.rest && func.public => {
+                    anyhow::bail!("rest parameter `...{}` of exported function cannot cross the C ABI", param.name)
+                }
+                _ if param.rest => return Err(unsupported(format!("rest parameter `...{}`", param.name))),
+                _ if param.default.is_some() && !func.public => {
+                    return Err(unsupported(format!("a default value for parameter `{}`", param.name)))
+                }
+                (Some(ty), true) => CType::from_name(ty)?,
+                (Some(ty), false) => {
+                    CType::from_name(ty).map_err(|_| unsupported(format!("parameter type `{}`", ty)))?
+                }
+                (None, true) => anyhow::bail!("parameter `{}` of exported function needs a type", param.name),
+                (None, false) => args.get(i).copied().unwrap_or(CType::Int),
+            };
+            sig.params.push(AbiParam::new(ty.ir_type(pointer)));
+            params.push(ty);
+        }
+        let ret = match (&func.ret, func.public) {
+            (Some(ty), true) => Some(CType::from_name(ty)?),
+            (Some(ty), false) => Some(CType::from_name(ty).map_err(|_| unsupported(format!("return type `{}`", ty)))?),
+            (None, true) => None,
+            (None, false) => {
+                let params: HashMap<&str, CType> =
+                    func.params.iter().map(|p| p.name.as_str()).zip(params.iter().copied()).collect();
+                returned_type(&func.body, &params)
+            }
+        };
+        if let Some(ret) = ret {
+            sig.returns.push(AbiParam::new(ret.ir_type(pointer)));
+        }
+
+        let (symbol, linkage) =
+            if func.public { (name.to_string(), Linkage::Export) } else { (format!(".hs.{}", name), Linkage::Local) };
+        let id = self
+            .module
+            .declare_function(&symbol, linkage, &sig)
+            .with_context(|| format!("Cannot declare `{}`", name))?;
+        let native = NativeFunc { id, params, ret };
+        self.declared.insert(name.to_string(), native.clone());
+        self.pending.push(name.to_string());
+        Ok(native)
+    }
+
+    /// Compile the bodies of the functions declared so far, and of those
+    /// they call.
+    fn define_pending(&mut self) -> Result<()> {
+        while let Some(name) = self.pending.pop() {
+            let func = self.funcs[&name].clone();
+            let native = self.declared[&name].clone();
+            let pointer = self.module.target_config().pointer_type();
+            let mut sig = self.module.make_signature();
+            sig.params.extend(native.params.iter().map(|ty| AbiParam::new(ty.ir_type(pointer))));
+            sig.returns.extend(native.ret.map(|ty| AbiParam::new(ty.ir_type(pointer))));
+            let params: Vec<_> = func.params.iter().map(|p| p.name.as_str()).zip(native.params).collect();
+            self.define_function(native.id, sig, Some((&func, native.ret)), &params, &func.body)
+                .with_context(|| format!("Cannot compile `{}`", name))?;
+        }
+        Ok(())
+    }
+
+    /// Define `id` from `body`. `func` is `None` for `main`, which returns 0.
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        sig: Signature,
+        func: Option<(&Func, Option<CType>)>,
+        params: &[(&str, CType)],
+        body: &[Spanned<Stmt>],
+    ) -> Result<()> {
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = sig;
+        let mut fn_ctx = FunctionBuilderContext::new();
+        {
+            let mut builder = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+            let entry = builder.create_block();
+            builder.append_block_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+            let args = builder.block_params(entry).to_vec();
+
+            let pointer = self.module.target_config().pointer_type();
+            let mut lowering = Lowering {
+                compiler: self,
+                builder,
+                pointer,
+                func: func.map(|(func, _)| func.name.as_str()),
+                ret: func.and_then(|(_, ret)| ret),
+                vars: HashMap::new(),
+                variables: 0,
+                loops: Vec::new(),
+                reachable: true,
+            };
+            for (&(name, ty), arg) in params.iter().zip(args) {
+                lowering.assign(name, arg, ty)?;
+            }
+            lowering.body(body)?;
+            lowering.fall_off_end()?;
+            lowering.builder.seal_all_blocks();
+            lowering.builder.finalize();
+        }
+        self.module.define_function(id, &mut ctx)?;
+        self.module.clear_context(&mut ctx);
+        Ok(())
+    }
+
+    fn define_const(&mut self, name: &str, value: &Lit) -> Result<()> {
+        let (bytes, ty) = match value {
+            Lit::Bool(b) => (vec![u8::from(*b)], CType::Bool),
+            Lit::Int(n) => (self.int_bytes(*n), CType::Int),
+            Lit::Float(x) => (self.int_bytes(x.to_bits() as i64), CType::Float),
+            Lit::Str(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                (bytes, CType::CStr)
+            }
+            Lit::Null => anyhow::bail!("`const {}` is null, which has no C representation", name),
+        };
+        self.define_exported_data(name, bytes)?;
+        self.exports.push(Export::Const { name: name.to_string(), ty });
+        Ok(())
+    }
+
+    fn define_enum(&mut self, def: &Enum) -> Result<()> {
+        let mut variants = Vec::new();
+        for (variant, value) in def.discriminants() {
+            let symbol = format!("{}_{}", def.name, variant);
+            self.define_exported_data(&symbol, self.int_bytes(value))?;
+            variants.push((symbol, value));
+        }
+        self.exports.push(Export::Enum { name: def.name.to_string(), variants });
+        Ok(())
+    }
+
+    /// The layout of `pub object name`: a field per typed `let`, in order.
+    /// Its methods are not exported.
+    fn define_struct(&mut self, name: &str, extends: bool, body: &[Spanned<Stmt>]) -> Result<()> {
+        if extends {
+            anyhow::bail!("`pub object {}` cannot extend another object to cross the C ABI", name);
+        }
+        let mut fields = Vec::new();
+        for stmt in body {
+            if let Stmt::Let { name: field, ty, .. } = &stmt.node {
+                let ty = ty
+                    .as_deref()
+                    .with_context(|| format!("field `{}` of `pub object {}` needs a type", field, name))?;
+                fields.push((field.to_string(), CType::from_name(ty)?));
+            }
+        }
+        if fields.is_empty() {
+            anyhow::bail!("`pub object {}` has no typed `let` fields to export", name);
+        }
+        self.exports.push(Export::Struct { name: name.to_string(), fields });
+        Ok(())
+    }
+
+    fn define_exported_data(&mut self, name: &str, bytes: Vec<u8>) -> Result<()> {
+        let id = self
+            .module
+            .declare_data(name, Linkage::Export, false, false)
+            .with_context(|| format!("Cannot declare `{}`", name))?;
+        let mut desc = DataDescription::new();
+        desc.set_align(8);
+        desc.define(bytes.into_boxed_slice());
+        self.module.define_data(id, &desc)?;
+        Ok(())
+    }
+
+    /// `n` laid out as a 64-bit integer for the target.
+    fn int_bytes(&self, n: i64) -> Vec<u8> {
+        match self.module.isa().endianness() {
+            Endianness::Little => n.to_le_bytes().to_vec(),
+            Endianness::Big => n.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// The libc function `name`, declared on first use.
+    fn import(&mut self, name: &'static str, params: &[Type], ret: Option<Type>) -> Result<FuncId> {
+        if let Some(id) = self.imports.get(name) {
+            return Ok(*id);
+        }
+        let mut sig = Signature::new(self.module.isa().default_call_conv());
+        sig.params.extend(params.iter().map(|ty| AbiParam::new(*ty)));
+        sig.returns.extend(ret.map(AbiParam::new));
+        let id = self.module.declare_function(name, Linkage::Import, &sig)?;
+        self.imports.insert(name, id);
+        Ok(id)
+    }
+
+    /// `write(fd, bytes, len)`; output is unbuffered, so nothing needs
+    /// flushing when a runtime error exits.
+    fn write(&mut self) -> Result<FuncId> {
+        let pointer = self.module.target_config().pointer_type();
+        self.import("write", &[types::I32, pointer, pointer], Some(pointer))
+    }
+
+    /// `.hs.write_int(n)`: write `n` in decimal to stdout, building the
+    /// digits backwards in a static buffer.
+    fn write_int(&mut self) -> Result<FuncId> {
+        if let Some(id) = self.write_int {
+            return Ok(id);
+        }
+        let pointer = self.module.target_config().pointer_type();
+        let write = self.write()?;
+        let digits = self.module.declare_data(".hs.digits", Linkage::Local, true, false)?;
+        let mut desc = DataDescription::new();
+        desc.define_zeroinit(INT_DIGITS as usize);
+        self.module.define_data(digits, &desc)?;
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(types::I64));
+        let id = self.module.declare_function(".hs.write_int", Linkage::Local, &sig)?;
+
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = sig;
+        let mut fn_ctx = FunctionBuilderContext::new();
+        {
+            let mut b = FunctionBuilder::new(&mut ctx.func, &mut fn_ctx);
+            let [entry, digit, sign, minus, out] = [(); 5].map(|()| b.create_block());
+            b.append_block_params_for_function_params(entry);
+            b.append_block_param(digit, types::I64);
+            b.append_block_param(digit, pointer);
+            for block in [sign, minus, out] {
+                b.append_block_param(block, pointer);
+            }
+
+            b.switch_to_block(entry);
+            let n = b.block_params(entry)[0];
+            let gv = self.module.declare_data_in_func(digits, b.func);
+            let base = b.ins().global_value(pointer, gv);
+            let end = b.ins().iconst(pointer, INT_DIGITS);
+            let negative = b.ins().icmp_imm(IntCC::SignedLessThan, n, 0);
+            // wraps for i64::MIN, whose magnitude is still right unsigned
+            let negated = b.ins().ineg(n);
+            let magnitude = b.ins().select(negative, negated, n);
+            b.ins().jump(digit, &[magnitude, end]);
+
+            b.switch_to_block(digit);
+            let (rest, pos) = (b.block_params(digit)[0], b.block_params(digit)[1]);
+            let pos = b.ins().iadd_imm(pos, -1);
+            let d = b.ins().urem_imm(rest, 10);
+            let c = b.ins().iadd_imm(d, i64::from(b'0'));
+            let c = b.ins().ireduce(types::I8, c);
+            let at = b.ins().iadd(base, pos);
+            b.ins().store(MemFlags::new(), c, at, 0);
+            let rest = b.ins().udiv_imm(rest, 10);
+            b.ins().brif(rest, digit, &[rest, pos], sign, &[pos]);
+
+            b.switch_to_block(sign);
+            let pos = b.block_params(sign)[0];
+            b.ins().brif(negative, minus, &[pos], out, &[pos]);
+
+            b.switch_to_block(minus);
+            let pos = b.block_params(minus)[0];
+            let pos = b.ins().iadd_imm(pos, -1);
+            let c = b.ins().iconst(types::I8, i64::from(b'-'));
+            let at = b.ins().iadd(base, pos);
+            b.ins().store(MemFlags::new(), c, at, 0);
+            b.ins().jump(out, &[pos]);
+
+            b.switch_to_block(out);
+            let pos = b.block_params(out)[0];
+            let at = b.ins().iadd(base, pos);
+            let len = b.ins().isub(end, pos);
+            let stdout = b.ins().iconst(types::I32, 1);
+            let write = self.module.declare_func_in_func(write, b.func);
+            b.ins().call(write, &[stdout, at, len]);
+            b.ins().return_(&[]);
+            b.seal_all_blocks();
+            b.finalize();
+        }
+        self.module.define_function(id, &mut ctx)?;
+        self.module.clear_context(&mut ctx);
+        self.write_int = Some(id);
+        Ok(id)
+    }
+
+    fn string_data(&mut self, s: &str) -> Result<DataId> {
+        if let Some(id) = self.strings.get(s) {
+            return Ok(*id);
+        }
+        let name = format!(".hs_str.{}", self.strings.len());
+        let id = self.module.declare_data(&name, Linkage::Local, false, false)?;
+        let mut desc = DataDescription::new();
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        desc.define(bytes.into_boxed_slice());
+        self.module.define_data(id, &desc)?;
+        self.strings.insert(s.to_string(), id);
+        Ok(id)
+    }
+}
+
+/// Error for something the backend cannot lower yet.
+fn unsupported(what: impl std::fmt::Display) -> anyhow::Error {
+    anyhow::anyhow!("{} is not supported by the native backend yet", what)
+}
+
+/// State while lowering one function body.
+struct Lowering<'a> {
+    compiler: &'a mut NativeCompiler,
+    builder: FunctionBuilder<'a>,
+    pointer: Type,
+    /// `None` in `main`
+    func: Option<&'a str>,
+    ret: Option<CType>,
+    /// Variables visible here; a `let` inside a block is dropped at its end
+    vars: HashMap<String, (Variable, CType)>,
+    /// Cranelift variables declared so far
+    variables: u32,
+    /// `(continue, break)` targets of the enclosing loops
+    loops: Vec<(Block, Block)>,
+    /// Whether control can reach the current block
+    reachable: bool,
+}
+
+impl Lowering<'_> {
+    fn body(&mut self, body: &[Spanned<Stmt>]) -> Result<()> {
+        body.iter().try_for_each(|stmt| self.stmt(&stmt.node))
+    }
+
+    /// Lower a nested block entered when `reachable`; returns whether
+    /// control can leave its end.
+    fn block(&mut self, body: &[Spanned<Stmt>], reachable: bool) -> Result<bool> {
+        let outer: HashSet<String> = self.vars.keys().cloned().collect();
+        self.reachable = reachable;
+        self.body(body)?;
+        self.vars.retain(|name, _| outer.contains(name));
+        Ok(self.reachable)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Log { level: None, values } => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        self.write_str(1, " ")?;
+                    }
+                    let (value, ty) = self.expr(value)?;
+                    self.write_value(value, ty)?;
+                }
+                self.write_str(1, "\n")
+            }
+            Stmt::Let { name, value, .. } | Stmt::Const { name, value, .. } => {
+                let (value, ty) = self.expr(value)?;
+                self.assign(name, value, ty)
+            }
+            Stmt::If { cond, then_body, else_body } => {
+                let cond = self.condition(cond)?;
+                let [then_block, else_block, merge] = [(); 3].map(|()| self.builder.create_block());
+                self.builder.ins().brif(cond, then_block, &[], else_block, &[]);
+                let entered = self.reachable;
+                let mut reachable = false;
+                for (block, body) in [(then_block, then_body), (else_block, else_body)] {
+                    self.builder.switch_to_block(block);
+                    reachable |= self.block(body, entered)?;
+                    self.builder.ins().jump(merge, &[]);
+                }
+                self.builder.switch_to_block(merge);
+                self.reachable = reachable;
+                Ok(())
+            }
+            Stmt::While { cond, body } => {
+                let [header, body_block, exit] = [(); 3].map(|()| self.builder.create_block());
+                self.builder.ins().jump(header, &[]);
+                self.builder.switch_to_block(header);
+                let cond = self.condition(cond)?;
+                self.builder.ins().brif(cond, body_block, &[], exit, &[]);
+                self.builder.switch_to_block(body_block);
+                let entered = self.reachable;
+                self.loops.push((header, exit));
+                self.block(body, entered)?;
+                self.loops.pop();
+                self.builder.ins().jump(header, &[]);
+                self.builder.switch_to_block(exit);
+                self.reachable = entered;
+                Ok(())
+            }
+            Stmt::Match { subject, cases, default } => {
+                let subject = self.expr(subject)?;
+                let merge = self.builder.create_block();
+                let entered = self.reachable;
+                let mut reachable = false;
+                for case in cases {
+                    if case.pattern.is_some() {
+                        return Err(unsupported("a pattern in a `case`"));
+                    }
+                    let value = self.expr(&case.value)?;
+                    let matched = self.equals(subject, value)?;
+                    let [body, next] = [(); 2].map(|()| self.builder.create_block());
+                    self.builder.ins().brif(matched, body, &[], next, &[]);
+                    self.builder.switch_to_block(body);
+                    reachable |= self.block(&case.body, entered)?;
+                    self.builder.ins().jump(merge, &[]);
+                    self.builder.switch_to_block(next);
+                }
+                reachable |= self.block(default, entered)?;
+                self.builder.ins().jump(merge, &[]);
+                self.builder.switch_to_block(merge);
+                self.reachable = reachable;
+                Ok(())
+            }
+            Stmt::Break | Stmt::Continue => {
+                let &(next, exit) = self.loops.last().ok_or_else(|| unsupported(describe(stmt)))?;
+                let target = if matches!(stmt, Stmt::Break) { exit } else { next };
+                self.builder.ins().jump(target, &[]);
+                self.unreachable();
+                Ok(())
+            }
+            Stmt::Return { value } => self.return_(value.as_ref()),
+            Stmt::Expr { value: Expr::Call { callee, args } } => self.call(callee, args).map(drop),
+            Stmt::Expr { value } => self.expr(value).map(drop),
+            // compiled separately (exported if `pub`)
+            Stmt::Func(_) | Stmt::Enum(_) if self.func.is_none() => Ok(()),
+            Stmt::Object { public: true, .. } if self.func.is_none() => Ok(()),
+            // only the checker reads interfaces
+            Stmt::Interface(_) => Ok(()),
+            other => Err(unsupported(describe(other))),
+        }
+    }
+
+    fn return_(&mut self, value: Option<&Expr>) -> Result<()> {
+        match (self.func, value, self.ret) {
+            (None, None, _) => {
+                let zero = self.builder.ins().iconst(types::I32, 0);
+                self.builder.ins().return_(&[zero]);
+            }
+            (None, Some(_), _) => return Err(unsupported("`return` with a value at the top level")),
+            (Some(_), None, None) => {
+                self.builder.ins().return_(&[]);
+            }
+            (Some(func), None, Some(_)) => {
+                return Err(unsupported(format!("a bare `return` in `{}`, which returns a value", func)))
+            }
+            (Some(func), Some(_), None) => {
+                anyhow::bail!("exported function `{}` returns a value and needs a return type", func)
+            }
+            (Some(func), Some(value), Some(ret)) => {
+                let (value, ty) = self.expr(value)?;
+                if ty != ret {
+                    let what = format!("returning {} from `{}`, which returns {},", ty.a(), func, ret.a());
+                    return Err(unsupported(what));
+                }
+                self.builder.ins().return_(&[value]);
+            }
+        }
+        self.unreachable();
+        Ok(())
+    }
+
+    /// Close the function: `main` returns 0 and a function without a
+    /// result returns. A function with one must not run off its end, where
+    /// the VM would return `null`.
+    fn fall_off_end(&mut self) -> Result<()> {
+        if self.reachable {
+            if let (Some(func), Some(_)) = (self.func, self.ret) {
+                return Err(unsupported(format!("reaching the end of `{}` without returning a value", func)));
+            }
+            self.return_(None)?;
+        }
+        self.return_zero();
+        Ok(())
+    }
+
+    /// Continue in a fresh block no jump leads to, for statements after a
+    /// `return`, `break` or `continue`.
+    fn unreachable(&mut self) {
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+        self.reachable = false;
+    }
+
+    /// Return zero values, ending a block that is never reached.
+    fn return_zero(&mut self) {
+        let returns: Vec<Type> = self.builder.func.signature.returns.iter().map(|ret| ret.value_type).collect();
+        let zeros: Vec<Value> = returns
+            .into_iter()
+            .map(|ty| match ty {
+                types::F64 => self.builder.ins().f64const(0.0),
+                _ => self.builder.ins().iconst(ty, 0),
+            })
+            .collect();
+        self.builder.ins().return_(&zeros);
+    }
+
+    /// `let name = value`. The name keeps the type it was first given.
+    fn assign(&mut self, name: &str, value: Value, ty: CType) -> Result<()> {
+        let var = match self.vars.get(name) {
+            Some(&(var, old)) if old == ty => var,
+            Some(&(_, old)) => {
+                return Err(unsupported(format!("changing `{}` from {} to {}", name, old.name(), ty.name())))
+            }
+            None => {
+                let var = Variable::from_u32(self.variables);
+                self.variables += 1;
+                self.builder.declare_var(var, ty.ir_type(self.pointer));
+                self.vars.insert(name.to_string(), (var, ty));
+                var
+            }
+        };
+        self.builder.def_var(var, value);
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(Value, CType)> {
+        match expr {
+            Expr::Lit(lit) => self.lit(lit),
+            Expr::Var { name } => {
+                if let Some(&(var, ty)) = self.vars.get(name.as_str()) {
+                    return Ok((self.builder.use_var(var), ty));
+                }
+                match self.compiler.consts.get(name.as_str()).cloned() {
+                    Some(lit) => self.lit(&lit),
+                    None if self.compiler.funcs.contains_key(name.as_str()) => {
+                        Err(unsupported(format!("using function `{}` as a value", name)))
+                    }
+                    None => Err(unsupported(format!("reading `{}` outside the block or function that set it", name))),
+                }
+            }
+            Expr::Call { callee, args } => self
+                .call(callee, args)?
+                .ok_or_else(|| unsupported(format!("using the result of `{}`, which returns nothing,", callee))),
+            Expr::Binary { op: op @ (BinOp::And | BinOp::Or), lhs, rhs } => self.logic(*op, lhs, rhs),
+            Expr::Binary { op, lhs, rhs } => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.binary(*op, lhs, rhs)
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                let cond = self.condition(cond)?;
+                let [then_block, else_block, merge] = [(); 3].map(|()| self.builder.create_block());
+                self.builder.ins().brif(cond, then_block, &[], else_block, &[]);
+                self.builder.switch_to_block(then_block);
+                let (then_value, ty) = self.expr(then_value)?;
+                let result = self.builder.append_block_param(merge, ty.ir_type(self.pointer));
+                self.builder.ins().jump(merge, &[then_value]);
+                self.builder.switch_to_block(else_block);
+                let (else_value, else_ty) = self.expr(else_value)?;
+                if else_ty != ty {
+                    return Err(unsupported(format!("`?:` choosing between {} and {}", ty.a(), else_ty.a())));
+                }
+                self.builder.ins().jump(merge, &[else_value]);
+                self.builder.switch_to_block(merge);
+                Ok((result, ty))
+            }
+            Expr::Map { .. } => Err(unsupported("a map")),
+            Expr::Index { .. } => Err(unsupported("indexing")),
+            Expr::Slice { .. } => Err(unsupported("slicing")),
+            Expr::Lambda { .. } => Err(unsupported("a lambda")),
+        }
+    }
+
+    fn lit(&mut self, lit: &Lit) -> Result<(Value, CType)> {
+        let value = match lit {
+            Lit::Null => return Err(unsupported("`null`")),
+            Lit::Bool(b) => self.builder.ins().iconst(types::I8, i64::from(*b)),
+            Lit::Int(n) => self.builder.ins().iconst(types::I64, *n),
+            Lit::Float(x) => self.builder.ins().f64const(*x),
+            Lit::Str(s) => self.string(s)?,
+        };
+        Ok((value, CType::of(lit).expect("null is rejected above")))
+    }
+
+    /// A call of top-level function `callee`; `None` if it has no result.
+    fn call(&mut self, callee: &str, args: &[Expr]) -> Result<Option<(Value, CType)>> {
+        if self.vars.contains_key(callee) || !self.compiler.funcs.contains_key(callee) {
+            return Err(unsupported(format!("calling `{}`", callee)));
+        }
+        let args = args.iter().map(|arg| self.expr(arg)).collect::<Result<Vec<_>>>()?;
+        let types: Vec<CType> = args.iter().map(|&(_, ty)| ty).collect();
+        let native = self.compiler.function(callee, &types)?;
+        if args.len() != native.params.len() {
+            return Err(unsupported(format!(
+                "calling `{}` with {} arguments instead of {}",
+                callee,
+                args.len(),
+                native.params.len()
+            )));
+        }
+        let mut values = Vec::new();
+        for (i, (&(value, ty), param)) in args.iter().zip(&native.params).enumerate() {
+            if ty != *param {
+                let name = &self.compiler.funcs[callee].params[i].name;
+                return Err(unsupported(format!(
+                    "passing {} to `{}`'s {} parameter `{}`",
+                    ty.a(),
+                    callee,
+                    param.name(),
+                    name
+                )));
+            }
+            values.push(value);
+        }
+        let func = self.compiler.module.declare_func_in_func(native.id, self.builder.func);
+        let call = self.builder.ins().call(func, &values);
+        Ok(native.ret.map(|ty| (self.builder.inst_results(call)[0], ty)))
+    }
+
+    /// `&&` and `||`, which evaluate to one of their operands.
+    fn logic(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) -> Result<(Value, CType)> {
+        let (lhs, ty) = self.expr(lhs)?;
+        let truthy = self.truthy(lhs, ty);
+        let [rhs_block, merge] = [(); 2].map(|()| self.builder.create_block());
+        let result = self.builder.append_block_param(merge, ty.ir_type(self.pointer));
+        if op == BinOp::And {
+            self.builder.ins().brif(truthy, rhs_block, &[], merge, &[lhs]);
+        } else {
+            self.builder.ins().brif(truthy, merge, &[lhs], rhs_block, &[]);
+        }
+        self.builder.switch_to_block(rhs_block);
+        let (rhs, rhs_ty) = self.expr(rhs)?;
+        if rhs_ty != ty {
+            return Err(unsupported(format!("`{}` between {} and {}", op.symbol(), ty.a(), rhs_ty.a())));
+        }
+        self.builder.ins().jump(merge, &[rhs]);
+        self.builder.switch_to_block(merge);
+        Ok((result, ty))
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: (Value, CType), rhs: (Value, CType)) -> Result<(Value, CType)> {
+        use CType::{Float, Int};
+        match (op, lhs.1, rhs.1) {
+            (BinOp::Eq, ..) => Ok((self.equals(lhs, rhs)?, CType::Bool)),
+            (BinOp::Ne, ..) => {
+                let eq = self.equals(lhs, rhs)?;
+                Ok((self.builder.ins().bxor_imm(eq, 1), CType::Bool))
+            }
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, ..) => Ok((self.compare(op, lhs, rhs)?, CType::Bool)),
+            (BinOp::Add, Int, Int) => Ok((self.builder.ins().iadd(lhs.0, rhs.0), Int)),
+            (BinOp::Sub, Int, Int) => Ok((self.builder.ins().isub(lhs.0, rhs.0), Int)),
+            (BinOp::Mul, Int, Int) => Ok((self.builder.ins().imul(lhs.0, rhs.0), Int)),
+            (BinOp::Div | BinOp::Rem, Int, Int) => Ok((self.divide(op, lhs.0, rhs.0)?, Int)),
+            (BinOp::Rem, Int | Float, Int | Float) => Err(unsupported("`%` on a float")),
+            (_, Int | Float, Int | Float) => {
+                let (x, y) = (self.float(lhs), self.float(rhs));
+                let value = match op {
+                    BinOp::Add => self.builder.ins().fadd(x, y),
+                    BinOp::Sub => self.builder.ins().fsub(x, y),
+                    BinOp::Mul => self.builder.ins().fmul(x, y),
+                    _ => self.builder.ins().fdiv(x, y),
+                };
+                Ok((value, Float))
+            }
+            (BinOp::Add, CType::CStr, _) => {
+                let rhs = self.text(rhs)?;
+                Ok((self.runtime_str("hs_concat", &[lhs.0, rhs])?, CType::CStr))
+            }
+            (_, lhs, rhs) => Err(unsupported(format!("`{}` on {} and {}", op.symbol(), lhs.a(), rhs.a()))),
+        }
+    }
+
+    /// Integer `/` or `%`: a runtime error for a zero divisor, and wrapping
+    /// like the VM where `i64::MIN / -1` would trap.
+    fn divide(&mut self, op: BinOp, x: Value, y: Value) -> Result<Value> {
+        let [fail, ok] = [(); 2].map(|()| self.builder.create_block());
+        self.builder.ins().brif(y, ok, &[], fail, &[]);
+        self.builder.switch_to_block(fail);
+        self.runtime_error("Division by zero")?;
+        self.builder.switch_to_block(ok);
+
+        let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, y, -1);
+        let one = self.builder.ins().iconst(types::I64, 1);
+        let divisor = self.builder.ins().select(minus_one, one, y);
+        Ok(if op == BinOp::Div {
+            let quotient = self.builder.ins().sdiv(x, divisor);
+            let negated = self.builder.ins().ineg(x);
+            self.builder.ins().select(minus_one, negated, quotient)
+        } else {
+            let remainder = self.builder.ins().srem(x, divisor);
+            let zero = self.builder.ins().iconst(types::I64, 0);
+            self.builder.ins().select(minus_one, zero, remainder)
+        })
+    }
+
+    /// Print `Error: message` to stderr and exit with 1, as the VM's runner does.
+    fn runtime_error(&mut self, message: &str) -> Result<()> {
+        self.write_str(2, &format!("Error: {}\n", message))?;
+        let exit = self.compiler.import("exit", &[types::I32], None)?;
+        let exit = self.compiler.module.declare_func_in_func(exit, self.builder.func);
+        let code = self.builder.ins().iconst(types::I32, 1);
+        self.builder.ins().call(exit, &[code]);
+        self.return_zero();
+        Ok(())
+    }
+
+    /// `==` as the VM compares: ints and floats by value, strings by
+    /// contents, and values of other different types never equal.
+    fn equals(&mut self, (x, xt): (Value, CType), (y, yt): (Value, CType)) -> Result<Value> {
+        use CType::{Bool, CStr, Float, Int};
+        Ok(match (xt, yt) {
+            (Int, Int) | (Bool, Bool) => self.builder.ins().icmp(IntCC::Equal, x, y),
+            (Int | Float, Int | Float) => {
+                let (x, y) = (self.float((x, xt)), self.float((y, yt)));
+                self.builder.ins().fcmp(FloatCC::Equal, x, y)
+            }
+            (CStr, CStr) => {
+                let order = self.strcmp(x, y)?;
+                self.builder.ins().icmp_imm(IntCC::Equal, order, 0)
+            }
+            _ => self.builder.ins().iconst(types::I8, 0),
+        })
+    }
+
+    fn compare(&mut self, op: BinOp, (x, xt): (Value, CType), (y, yt): (Value, CType)) -> Result<Value> {
+        use CType::{CStr, Float, Int};
+        let (int_cc, float_cc) = match op {
+            BinOp::Lt => (IntCC::SignedLessThan, FloatCC::LessThan),
+            BinOp::Le => (IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual),
+            BinOp::Gt => (IntCC::SignedGreaterThan, FloatCC::GreaterThan),
+            _ => (IntCC::SignedGreaterThanOrEqual, FloatCC::GreaterThanOrEqual),
+        };
+        Ok(match (xt, yt) {
+            (Int, Int) => self.builder.ins().icmp(int_cc, x, y),
+            (Int | Float, Int | Float) => {
+                let (x, y) = (self.float((x, xt)), self.float((y, yt)));
+                self.builder.ins().fcmp(float_cc, x, y)
+            }
+            (CStr, CStr) => {
+                let order = self.strcmp(x, y)?;
+                self.builder.ins().icmp_imm(int_cc, order, 0)
+            }
+            _ => return Err(unsupported(format!("comparing {} and {}", xt.a(), yt.a()))),
+        })
+    }
+
+    fn strcmp(&mut self, x: Value, y: Value) -> Result<Value> {
+        let strcmp = self.compiler.import("strcmp", &[self.pointer, self.pointer], Some(types::I32))?;
+        let strcmp = self.compiler.module.declare_func_in_func(strcmp, self.builder.func);
+        let call = self.builder.ins().call(strcmp, &[x, y]);
+        Ok(self.builder.inst_results(call)[0])
+    }
+
+    /// An int or float operand as a float.
+    fn float(&mut self, (value, ty): (Value, CType)) -> Value {
+        match ty {
+            CType::Int => self.builder.ins().fcvt_from_sint(types::F64, value),
+            _ => value,
+        }
+    }
+
+    /// Whether the value of `cond` counts as true, as a bool.
+    fn condition(&mut self, cond: &Expr) -> Result<Value> {
+        let (value, ty) = self.expr(cond)?;
+        Ok(self.truthy(value, ty))
+    }
+
+    fn truthy(&mut self, value: Value, ty: CType) -> Value {
+        match ty {
+            CType::Bool => value,
+            CType::Int => self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0),
+            CType::Float => {
+                let zero = self.builder.ins().f64const(0.0);
+                self.builder.ins().fcmp(FloatCC::NotEqual, value, zero)
+            }
+            // not the empty string
+            CType::CStr => {
+                let first = self.builder.ins().load(types::I8, MemFlags::new(), value, 0);
+                self.builder.ins().icmp_imm(IntCC::NotEqual, first, 0)
+            }
+        }
+    }
+
+    fn string(&mut self, s: &str) -> Result<Value> {
+        let id = self.compiler.string_data(s)?;
+        let gv = self.compiler.module.declare_data_in_func(id, self.builder.func);
+        Ok(self.builder.ins().global_value(self.pointer, gv))
+    }
+
+    /// Write `value` to stdout the way `log` shows it.
+    fn write_value(&mut self, value: Value, ty: CType) -> Result<()> {
+        match ty {
+            CType::Int => {
+                let write_int = self.compiler.write_int()?;
+                let write_int = self.compiler.module.declare_func_in_func(write_int, self.builder.func);
+                self.builder.ins().call(write_int, &[value]);
+            }
+            CType::Bool => {
+                let (yes, no) = (self.string("true")?, self.string("false")?);
+                let text = self.builder.ins().select(value, yes, no);
+                let four = self.builder.ins().iconst(self.pointer, 4);
+                let five = self.builder.ins().iconst(self.pointer, 5);
+                let len = self.builder.ins().select(value, four, five);
+                self.write_bytes(1, text, len)?;
+            }
+            CType::CStr => {
+                let strlen = self.compiler.import("strlen", &[self.pointer], Some(self.pointer))?;
+                let strlen = self.compiler.module.declare_func_in_func(strlen, self.builder.func);
+                let call = self.builder.ins().call(strlen, &[value]);
+                let len = self.builder.inst_results(call)[0];
+                self.write_bytes(1, value, len)?;
+            }
+            CType::Float => {
+                self.runtime_call("hs_write_float", &[value], None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `value` as the text `log` shows, for joining to a string.
+    fn text(&mut self, (value, ty): (Value, CType)) -> Result<Value> {
+        Ok(match ty {
+            CType::CStr => value,
+            CType::Bool => {
+                let (yes, no) = (self.string("true")?, self.string("false")?);
+                self.builder.ins().select(value, yes, no)
+            }
+            CType::Int => self.runtime_str("hs_int_str", &[value])?,
+            CType::Float => self.runtime_str("hs_float_str", &[value])?,
+        })
+    }
+
+    /// Call `name` from the C runtime, which returns a `ret` if there is one.
+    fn runtime_call(&mut self, name: &'static str, args: &[Value], ret: Option<Type>) -> Result<Option<Value>> {
+        let params: Vec<Type> = args.iter().map(|&arg| self.builder.func.dfg.value_type(arg)).collect();
+        let func = self.compiler.import(name, &params, ret)?;
+        let func = self.compiler.module.declare_func_in_func(func, self.builder.func);
+        let call = self.builder.ins().call(func, args);
+        Ok(self.builder.inst_results(call).first().copied())
+    }
+
+    /// `name` from the C runtime, which builds a string.
+    fn runtime_str(&mut self, name: &'static str, args: &[Value]) -> Result<Value> {
+        Ok(self.runtime_call(name, args, Some(self.pointer))?.expect("declared with a result"))
+    }
+
+    fn write_str(&mut self, fd: i64, s: &str) -> Result<()> {
+        let text = self.string(s)?;
+        let len = self.builder.ins().iconst(self.pointer, s.len() as i64);
+        self.write_bytes(fd, text, len)
+    }
+
+    fn write_bytes(&mut self, fd: i64, text: Value, len: Value) -> Result<()> {
+        let write = self.compiler.write()?;
+        let write = self.compiler.module.declare_func_in_func(write, self.builder.func);
+        let fd = self.builder.ins().iconst(types::I32, fd);
+        self.builder.ins().call(write, &[fd, text, len]);
+        Ok(())
+    }
+}
+
+/// What an untyped function with parameters of types `params` returns,
+/// judged by its first `return` of a value: an int unless that value is
+/// plainly something else. Returns that do not match are rejected when the
+/// body is lowered.
+fn returned_type(body: &[Spanned<Stmt>], params: &HashMap<&str, CType>) -> Option<CType> {
+    body.iter().find_map(|stmt| match &stmt.node {
+        Stmt::Return { value: Some(value) } => Some(apparent_type(value, params).unwrap_or(CType::Int)),
+        Stmt::If { then_body, else_body, .. } => {
+            returned_type(then_body, params).or_else(|| returned_type(else_body, params))
+        }
+        Stmt::While { body, .. } => returned_type(body, params),
+        Stmt::Match { cases, default, .. } => {
+            cases.iter().find_map(|case| returned_type(&case.body, params)).or_else(|| returned_type(default, params))
+        }
+        _ => None,
+    })
+}
+
+fn apparent_type(expr: &Expr, params: &HashMap<&str, CType>) -> Option<CType> {
+    match expr {
+        Expr::Lit(lit) => CType::of(lit),
+        Expr::Var { name } => params.get(name.as_str()).copied(),
+        Expr::Binary { op: BinOp::And | BinOp::Or, lhs, .. } => apparent_type(lhs, params),
+        Expr::Binary { op: BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem, lhs, rhs } => {
+            match (apparent_type(lhs, params), apparent_type(rhs, params)) {
+                (Some(CType::Float), _) | (_, Some(CType::Float)) => Some(CType::Float),
+                (Some(CType::CStr), _) | (_, Some(CType::CStr)) => Some(CType::CStr),
+                _ => None,
+            }
+        }
+        Expr::Binary { .. } => Some(CType::Bool),
+        Expr::Ternary { then_value, else_value, .. } => {
+            apparent_type(then_value, params).or_else(|| apparent_type(else_value, params))
+        }
+        _ => None,
+    }
+}
+
+fn describe(stmt: &Stmt) -> &'static str {
+    match stmt {
+        Stmt::Log { level: Some(_), .. } => "leveled `log`",
+        Stmt::Log { .. } => "`log`",
+        Stmt::Let { .. } => "`let`",
+        Stmt::Destructure { .. } => "a destructuring `let`",
+        Stmt::Const { .. } => "a local `const`",
+        Stmt::Enum(_) => "a local `enum`",
+        Stmt::If { .. } => "`if`",
+        Stmt::While { .. } => "`while`",
+        Stmt::Match { .. } => "`match`",
+        Stmt::Break | Stmt::Continue => "`break`/`continue` outside a loop",
+        Stmt::Return { .. } => "`return` with a value",
+        Stmt::Expr { .. } => "an expression statement",
+        Stmt::Asm { .. } => "`asm`",
+        Stmt::Try { .. } => "`try`",
+        Stmt::Throw { .. } => "`throw`",
+        Stmt::Sh { .. } => "an `sh` block",
+        Stmt::Func(_) => "a nested `func`",
+        Stmt::Object { .. } => "`object`",
+        Stmt::Interface(_) => "`interface`",
+        Stmt::Import { .. } | Stmt::ImportModule { .. } | Stmt::Require { .. } => "`import`/`require`",
+    }
+}
+
+/// Value of a `const` initializer built from literals and earlier constants.
+fn const_value(expr: &Expr, consts: &HashMap<String, Lit>) -> Option<Lit> {
+    match expr {
+        Expr::Lit(lit) => Some(lit.clone()),
+        Expr::Var { name } => consts.get(name.as_str()).cloned(),
+        Expr::Binary { op, lhs, rhs } => {
+            crate::opt::eval_binary(*op, &const_value(lhs, consts)?, &const_value(rhs, consts)?)
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            let chosen = if crate::opt::truthy(&const_value(cond, consts)?) { then_value } else { else_value };
+            const_value(chosen, consts)
+        }
+        Expr::Call { .. } | Expr::Map { .. } | Expr::Index { .. } | Expr::Slice { .. } | Expr::Lambda { .. } => None,
+    }
+}
+
+fn write_header(out: &mut String, guard: &str, exports: &[Export]) -> std::fmt::Result {
+    writeln!(out, "/* Generated by hs1. Do not edit. */")?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}", guard)?;
+    writeln!(out)?;
+    writeln!(out, "#include <stdbool.h>")?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "extern \"C\" {{")?;
+    writeln!(out, "#endif")?;
+    for export in exports {
+        writeln!(out)?;
+        match export {
+            Export::Func { name, params, ret } => {
+                let params: Vec<String> = params.iter().map(|(name, ty)| ty.c_decl(name)).collect();
+                let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+                match ret {
+                    Some(ret) => writeln!(out, "{}({});", ret.c_decl(name), params)?,
+                    None => writeln!(out, "void {}({});", name, params)?,
+                }
+            }
+            Export::Const { name, ty: CType::CStr } => writeln!(out, "extern const char {}[];", name)?,
+            Export::Const { name, ty } => writeln!(out, "extern const {};", ty.c_decl(name))?,
+            Export::Enum { name, variants } => {
+                writeln!(out, "typedef int64_t {};", name)?;
+                for (symbol, value) in variants {
+                    writeln!(out, "extern const {} {}; /* {} */", name, symbol, value)?;
+                }
+            }
+            Export::Struct { name, fields } => {
+                writeln!(out, "typedef struct {} {{", name)?;
+                for (field, ty) in fields {
+                    writeln!(out, "    {};", ty.c_decl(field))?;
+                }
+                writeln!(out, "}} {};", name)?;
+            }
+        }
+    }
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "#endif /* {} */", guard)
+}
+
+/// Link an object produced by `NativeCompiler` into a shared library with the system C compiler.
+pub fn link_cdylib(object: &Path, output: &Path) -> Result<()> {
+    link(object, output, &["-shared", "-fPIC"])
+}
+
+/// Link an object with a `compile_main` entry point into an executable.
+pub fn link_executable(object: &Path, output: &Path) -> Result<()> {
+    link(object, output, &[])
+}
+
+/// The C runtime native code calls into, compiled in by `link`.
+const RUNTIME: &str = include_str!("native_runtime.c");
+
+fn link(object: &Path, output: &Path, flags: &[&str]) -> Result<()> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let runtime = output.with_extension("hs-runtime.c");
+    std::fs::write(&runtime, RUNTIME).with_context(|| format!("Cannot write {}", runtime.display()))?;
+    let status = Command::new(&cc)
+        .args(flags)
+        .arg("-o")
+        .arg(output)
+        .arg(object)
+        .arg(&runtime)
+        .status();
+    std::fs::remove_file(&runtime).ok();
+    let status = status.with_context(|| format!("Cannot run linker `{}`", cc))?;
+    if !status.success() {
+        anyhow::bail!("Linker `{}` failed with {}", cc, status);
+    }
+    Ok(())
+}
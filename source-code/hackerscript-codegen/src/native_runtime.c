@@ -0,0 +1,101 @@
+/* Runtime support for `hs1 compile --native`, compiled and linked next to
+ * the object. Strings built at run time are never freed. */
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define HS_HIDDEN __attribute__((visibility("hidden")))
+
+/* Enough for any double in positional notation: 309 integer digits, or
+ * "0." and 323 zeros before 17 significant digits, plus a sign. */
+#define FLOAT_LEN 352
+
+/* `x` as the VM shows it: the fewest significant digits that read back as
+ * `x`, never in exponent notation, with no fraction for whole numbers. */
+static void format_float(double x, char *out) {
+    if (x != x) {
+        strcpy(out, "NaN");
+        return;
+    }
+    if (x == 1.0 / 0.0 || x == -1.0 / 0.0) {
+        strcpy(out, x < 0 ? "-inf" : "inf");
+        return;
+    }
+    char sci[32];
+    for (int precision = 0; precision < 17; precision++) {
+        snprintf(sci, sizeof sci, "%.*e", precision, x);
+        if (strtod(sci, NULL) == x) {
+            break;
+        }
+    }
+    /* sci is [-]d[.ddd]e[+-]xx */
+    const char *p = sci;
+    if (*p == '-') {
+        *out++ = *p++;
+    }
+    char digits[20];
+    int count = 0;
+    for (; *p != 'e'; p++) {
+        if (*p != '.') {
+            digits[count++] = *p;
+        }
+    }
+    int exponent = atoi(p + 1);
+    while (count > 1 && digits[count - 1] == '0') {
+        count--;
+    }
+    if (exponent < 0) {
+        *out++ = '0';
+        *out++ = '.';
+        for (int i = -1; i > exponent; i--) {
+            *out++ = '0';
+        }
+        memcpy(out, digits, count);
+        out += count;
+    } else {
+        for (int i = 0; i <= exponent || i < count; i++) {
+            if (i == exponent + 1) {
+                *out++ = '.';
+            }
+            *out++ = i < count ? digits[i] : '0';
+        }
+    }
+    *out = '\0';
+}
+
+HS_HIDDEN void hs_write_float(double x) {
+    char text[FLOAT_LEN];
+    format_float(x, text);
+    write(1, text, strlen(text));
+}
+
+HS_HIDDEN char *hs_float_str(double x) {
+    char *text = malloc(FLOAT_LEN);
+    if (text == NULL) {
+        abort();
+    }
+    format_float(x, text);
+    return text;
+}
+
+HS_HIDDEN char *hs_int_str(int64_t n) {
+    char *text = malloc(24);
+    if (text == NULL) {
+        abort();
+    }
+    snprintf(text, 24, "%lld", (long long)n);
+    return text;
+}
+
+HS_HIDDEN char *hs_concat(const char *a, const char *b) {
+    size_t a_len = strlen(a), b_len = strlen(b);
+    char *text = malloc(a_len + b_len + 1);
+    if (text == NULL) {
+        abort();
+    }
+    memcpy(text, a, a_len);
+    memcpy(text + a_len, b, b_len + 1);
+    return text;
+}
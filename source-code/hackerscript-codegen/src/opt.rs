@@ -0,0 +1,563 @@
+//! AST optimisation passes (constant folding, inlining, dead code
+//! elimination), run before emission unless `hs1 compile --no-opt`.
+//!
+//! Every fold must agree with what the VM would compute at runtime; anything
+//! that would raise a runtime error (division by zero, `1 + "a"`) is left in
+//! place so the error still happens where the script expects it.
+use hackerscript_ast::{BinOp, Case, Except, Expr, Func, Lit, Program, Spanned, Stmt};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+
+/// Something an optimisation pass removed, as reported by `hs1 compile -v`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Eliminated {
+    /// A function that is neither called, `pub` nor `main`
+    Function(String),
+    /// Statements after a `return`, `break`, `continue` or `throw` in `scope`
+    Unreachable { scope: String, count: usize },
+    /// An `if` in `scope` whose condition is always `taken`
+    Branch { scope: String, taken: bool },
+    /// A `while` in `scope` whose condition is always false
+    Loop { scope: String },
+    /// `calls` calls to `name`, replaced by its body
+    Inlined { name: String, calls: usize },
+}
+
+impl fmt::Display for Eliminated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Eliminated::Function(name) => write!(f, "unreferenced function `{}`", name),
+            Eliminated::Unreachable { scope, count } => {
+                write!(f, "{} unreachable statement(s) after a jump in {}", count, scope)
+            }
+            Eliminated::Branch { scope, taken } => {
+                write!(f, "branch of `if` in {} (condition is always {})", scope, taken)
+            }
+            Eliminated::Loop { scope } => write!(f, "`while` in {} (condition is always false)", scope),
+            Eliminated::Inlined { name, calls } => write!(f, "{} call(s) to `{}` (inlined)", calls, name),
+        }
+    }
+}
+
+impl Eliminated {
+    /// Whether the script probably did not mean it: code that can never
+    /// run, rather than a function nothing calls.
+    pub fn is_warning(&self) -> bool {
+        !matches!(self, Eliminated::Function(_) | Eliminated::Inlined { .. })
+    }
+}
+
+/// Run all optimisation passes over `program`, returning what was removed.
+pub fn optimize(program: &mut Program) -> Vec<Eliminated> {
+    optimize_with(program, crate::inline::DEFAULT_BUDGET)
+}
+
+/// `optimize`, inlining functions of up to `inline_budget` expression nodes.
+pub fn optimize_with(program: &mut Program, inline_budget: usize) -> Vec<Eliminated> {
+    fold_constants(program);
+    let mut report = crate::inline::inline_functions(program, inline_budget);
+    fold_constants(program);
+    report.extend(eliminate_dead_code(program));
+    report
+}
+
+/// Fold constant arithmetic and string concatenation.
+pub fn fold_constants(program: &mut Program) {
+    program.body = fold_block(mem::take(&mut program.body));
+}
+
+fn fold_block(body: Vec<Spanned<Stmt>>) -> Vec<Spanned<Stmt>> {
+    body.into_iter().map(|stmt| stmt.map(fold_stmt)).collect()
+}
+
+/// Constant-fold a single statement, e.g. one top-level item of a streamed
+/// compilation.
+pub fn fold_stmt(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::If { cond, then_body, else_body } => Stmt::If {
+            cond: fold_expr(cond),
+            then_body: fold_block(then_body),
+            else_body: fold_block(else_body),
+        },
+        Stmt::While { cond, body } => Stmt::While {
+            cond: fold_expr(cond),
+            body: fold_block(body),
+        },
+        Stmt::Match { subject, cases, default } => Stmt::Match {
+            subject: fold_expr(subject),
+            cases: cases
+                .into_iter()
+                .map(|case| Case {
+                    value: fold_expr(case.value),
+                    pattern: case.pattern,
+                    body: fold_block(case.body),
+                })
+                .collect(),
+            default: fold_block(default),
+        },
+        Stmt::Func(mut func) => {
+            for param in &mut func.params {
+                param.default = param.default.take().map(fold_expr);
+            }
+            func.body = fold_block(mem::take(&mut func.body));
+            Stmt::Func(func)
+        }
+        Stmt::Object { public, name, parent, implements, body } => Stmt::Object {
+            public,
+            name,
+            parent,
+            implements,
+            body: fold_block(body),
+        },
+        Stmt::Let { name, ty, value } => Stmt::Let {
+            name,
+            ty,
+            value: fold_expr(value),
+        },
+        Stmt::Destructure { pattern, value } => Stmt::Destructure { pattern, value: fold_expr(value) },
+        Stmt::Const { public, name, ty, value } => Stmt::Const {
+            public,
+            name,
+            ty,
+            value: fold_expr(value),
+        },
+        Stmt::Log { level, values } => Stmt::Log {
+            level,
+            values: values.into_iter().map(fold_expr).collect(),
+        },
+        Stmt::Expr { value } => Stmt::Expr { value: fold_expr(value) },
+        Stmt::Return { value } => Stmt::Return {
+            value: value.map(fold_expr),
+        },
+        Stmt::Try { body, except, finally } => Stmt::Try {
+            body: fold_block(body),
+            except: except.map(|except| Except { name: except.name, body: fold_block(except.body) }),
+            finally: fold_block(finally),
+        },
+        Stmt::Throw { value } => Stmt::Throw { value: fold_expr(value) },
+        other => other,
+    }
+}
+
+fn fold_expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary { op, lhs, rhs } => {
+            let lhs = fold_expr(*lhs);
+            let rhs = fold_expr(*rhs);
+            // a known left side decides `&&` / `||` whatever the right side is
+            if let (BinOp::And | BinOp::Or, Expr::Lit(a)) = (op, &lhs) {
+                return if truthy(a) == (op == BinOp::Or) { lhs } else { rhs };
+            }
+            if let (Expr::Lit(a), Expr::Lit(b)) = (&lhs, &rhs) {
+                if let Some(lit) = eval_binary(op, a, b) {
+                    return Expr::Lit(lit);
+                }
+            }
+            Expr::binary(op, lhs, rhs)
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            let cond = fold_expr(*cond);
+            // a known condition picks its branch; the other one never runs
+            match const_truth(&cond) {
+                Some(true) => fold_expr(*then_value),
+                Some(false) => fold_expr(*else_value),
+                None => Expr::Ternary {
+                    cond: Box::new(cond),
+                    then_value: Box::new(fold_expr(*then_value)),
+                    else_value: Box::new(fold_expr(*else_value)),
+                },
+            }
+        }
+        Expr::Call { callee, args } => Expr::Call {
+            callee,
+            args: args.into_iter().map(fold_expr).collect(),
+        },
+        Expr::Map { entries } => Expr::Map {
+            entries: entries.into_iter().map(|(key, value)| (key, fold_expr(value))).collect(),
+        },
+        Expr::Index { target, index } => Expr::Index {
+            target: Box::new(fold_expr(*target)),
+            index: Box::new(fold_expr(*index)),
+        },
+        Expr::Slice { target, start, end } => Expr::Slice {
+            target: Box::new(fold_expr(*target)),
+            start: start.map(|start| Box::new(fold_expr(*start))),
+            end: end.map(|end| Box::new(fold_expr(*end))),
+        },
+        Expr::Lambda { mut params, ret, body } => {
+            for param in &mut params {
+                param.default = param.default.take().map(fold_expr);
+            }
+            Expr::Lambda { params, ret, body: fold_block(body) }
+        }
+        other => other,
+    }
+}
+
+/// Drop `if` branches whose condition is known at compile time, statements
+/// after `return`, `break`, `continue` or `throw` (in lambdas too), and
+/// top-level functions nothing can reach.
+pub fn eliminate_dead_code(program: &mut Program) -> Vec<Eliminated> {
+    let mut report = Vec::new();
+    program.body = dce_block(mem::take(&mut program.body), "top level", &mut report);
+
+    let live: HashSet<String> = live_functions(&program.body).into_iter().map(String::from).collect();
+    program.body.retain(|stmt| match &stmt.node {
+        Stmt::Func(func) if !live.contains(func.name.as_str()) => {
+            report.push(Eliminated::Function(func.name.to_string()));
+            false
+        }
+        _ => true,
+    });
+    report
+}
+
+fn dce_block(body: Vec<Spanned<Stmt>>, scope: &str, report: &mut Vec<Eliminated>) -> Vec<Spanned<Stmt>> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut rest = body.into_iter();
+    while let Some(Spanned { mut node, span }) = rest.next() {
+        stmt_lambdas(&mut node, scope, report);
+        match node {
+            Stmt::If { cond, then_body, else_body } => match const_truth(&cond) {
+                Some(taken) => {
+                    report.push(Eliminated::Branch {
+                        scope: scope.to_string(),
+                        taken,
+                    });
+                    let body = if taken { then_body } else { else_body };
+                    out.extend(dce_block(body, scope, report));
+                }
+                None => out.push(Spanned::new(
+                    Stmt::If {
+                        cond,
+                        then_body: dce_block(then_body, scope, report),
+                        else_body: dce_block(else_body, scope, report),
+                    },
+                    span,
+                )),
+            },
+            Stmt::While { cond, .. } if const_truth(&cond) == Some(false) => {
+                report.push(Eliminated::Loop { scope: scope.to_string() });
+            }
+            Stmt::While { cond, body } => {
+                let body = dce_block(body, scope, report);
+                out.push(Spanned::new(Stmt::While { cond, body }, span));
+            }
+            Stmt::Match { subject, cases, default } => {
+                let cases = cases
+                    .into_iter()
+                    .map(|case| Case {
+                        value: case.value,
+                        pattern: case.pattern,
+                        body: dce_block(case.body, scope, report),
+                    })
+                    .collect();
+                let default = dce_block(default, scope, report);
+                out.push(Spanned::new(Stmt::Match { subject, cases, default }, span));
+            }
+            Stmt::Func(mut func) => {
+                let scope = format!("func `{}`", func.name);
+                func.body = dce_block(mem::take(&mut func.body), &scope, report);
+                out.push(Spanned::new(Stmt::Func(func), span));
+            }
+            Stmt::Object { public, name, parent, implements, body } => {
+                let body = dce_block(body, &format!("object `{}`", name), report);
+                out.push(Spanned::new(Stmt::Object { public, name, parent, implements, body }, span));
+            }
+            Stmt::Try { body, except, finally } => {
+                let body = dce_block(body, scope, report);
+                let except = except.map(|except| Except { name: except.name, body: dce_block(except.body, scope, report) });
+                let finally = dce_block(finally, scope, report);
+                out.push(Spanned::new(Stmt::Try { body, except, finally }, span));
+            }
+            other => out.push(Spanned::new(other, span)),
+        }
+        if out.last().is_some_and(always_jumps) {
+            let count = rest.len();
+            if count > 0 {
+                report.push(Eliminated::Unreachable {
+                    scope: scope.to_string(),
+                    count,
+                });
+            }
+            break;
+        }
+    }
+    out
+}
+
+/// Run `dce_block` over the lambdas in the expressions of `stmt` itself,
+/// leaving its blocks to the caller.
+fn stmt_lambdas(stmt: &mut Stmt, scope: &str, report: &mut Vec<Eliminated>) {
+    let exprs: Vec<&mut Expr> = match stmt {
+        Stmt::Let { value, .. }
+        | Stmt::Destructure { value, .. }
+        | Stmt::Const { value, .. }
+        | Stmt::Expr { value }
+        | Stmt::Throw { value } => vec![value],
+        Stmt::Log { values, .. } => values.iter_mut().collect(),
+        Stmt::Return { value } => value.iter_mut().collect(),
+        Stmt::If { cond, .. } | Stmt::While { cond, .. } => vec![cond],
+        Stmt::Match { subject, cases, .. } => std::iter::once(subject).chain(cases.iter_mut().map(|case| &mut case.value)).collect(),
+        Stmt::Func(func) => func.params.iter_mut().filter_map(|param| param.default.as_mut()).collect(),
+        _ => Vec::new(),
+    };
+    for expr in exprs {
+        lambdas(expr, scope, report);
+    }
+}
+
+fn lambdas(expr: &mut Expr, scope: &str, report: &mut Vec<Eliminated>) {
+    match expr {
+        Expr::Lambda { params, body, .. } => {
+            for default in params.iter_mut().filter_map(|param| param.default.as_mut()) {
+                lambdas(default, scope, report);
+            }
+            *body = dce_block(mem::take(body), &format!("a lambda in {}", scope), report);
+        }
+        Expr::Call { args, .. } => args.iter_mut().for_each(|arg| lambdas(arg, scope, report)),
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            lambdas(lhs, scope, report);
+            lambdas(rhs, scope, report);
+        }
+        Expr::Map { entries } => entries.iter_mut().for_each(|(_, value)| lambdas(value, scope, report)),
+        Expr::Slice { target, start, end } => {
+            lambdas(target, scope, report);
+            start.iter_mut().chain(end).for_each(|bound| lambdas(bound, scope, report));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| lambdas(part, scope, report))
+        }
+        Expr::Var { .. } | Expr::Lit(_) => {}
+    }
+}
+
+/// Whether control never reaches the statement after `stmt`.
+fn always_jumps(stmt: &Spanned<Stmt>) -> bool {
+    match &stmt.node {
+        Stmt::Return { .. } | Stmt::Break | Stmt::Continue | Stmt::Throw { .. } => true,
+        Stmt::If { then_body, else_body, .. } => {
+            then_body.last().is_some_and(always_jumps) && else_body.last().is_some_and(always_jumps)
+        }
+        Stmt::Match { cases, default, .. } => {
+            cases.iter().all(|case| case.body.last().is_some_and(always_jumps))
+                && default.last().is_some_and(always_jumps)
+        }
+        // a failing `body` leaves through the `except`, or the error goes on up
+        Stmt::Try { body, except, finally } => {
+            finally.last().is_some_and(always_jumps)
+                || (body.last().is_some_and(always_jumps)
+                    && except.as_ref().is_none_or(|except| except.body.last().is_some_and(always_jumps)))
+        }
+        _ => false,
+    }
+}
+
+/// Names of top-level functions reachable from top-level code, `pub`
+/// functions and `main`. Using a function as a value counts as a call.
+fn live_functions(body: &[Spanned<Stmt>]) -> HashSet<&str> {
+    let mut funcs: HashMap<&str, Vec<&Func>> = HashMap::new();
+    let mut pending = Vec::new();
+    for stmt in body {
+        match &stmt.node {
+            Stmt::Func(func) => {
+                funcs.entry(func.name.as_str()).or_default().push(func);
+                if func.public || func.name == "main" {
+                    pending.push(func.name.as_str());
+                }
+            }
+            _ => collect_calls(std::slice::from_ref(stmt), &mut pending),
+        }
+    }
+
+    let mut live = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if live.insert(name) {
+            for func in funcs.get(name).into_iter().flatten() {
+                func_calls(func, &mut pending);
+            }
+        }
+    }
+    live
+}
+
+fn collect_calls<'a>(body: &'a [Spanned<Stmt>], out: &mut Vec<&'a str>) {
+    for stmt in body {
+        match &stmt.node {
+            Stmt::Func(func) => func_calls(func, out),
+            Stmt::Object { body, .. } => collect_calls(body, out),
+            Stmt::If { cond, then_body, else_body } => {
+                expr_calls(cond, out);
+                collect_calls(then_body, out);
+                collect_calls(else_body, out);
+            }
+            Stmt::While { cond, body } => {
+                expr_calls(cond, out);
+                collect_calls(body, out);
+            }
+            Stmt::Match { subject, cases, default } => {
+                expr_calls(subject, out);
+                for case in cases {
+                    expr_calls(&case.value, out);
+                    collect_calls(&case.body, out);
+                }
+                collect_calls(default, out);
+            }
+            Stmt::Let { value, .. } | Stmt::Destructure { value, .. } | Stmt::Const { value, .. } | Stmt::Expr { value } => {
+                expr_calls(value, out)
+            }
+            Stmt::Log { values, .. } => values.iter().for_each(|value| expr_calls(value, out)),
+            Stmt::Return { value } => value.iter().for_each(|value| expr_calls(value, out)),
+            Stmt::Try { body, except, finally } => {
+                collect_calls(body, out);
+                except.iter().for_each(|except| collect_calls(&except.body, out));
+                collect_calls(finally, out);
+            }
+            Stmt::Throw { value } => expr_calls(value, out),
+            Stmt::Import { .. }
+            | Stmt::ImportModule { .. }
+            | Stmt::Require { .. }
+            | Stmt::Enum(_)
+            | Stmt::Interface(_)
+            | Stmt::Break
+            | Stmt::Continue
+            | Stmt::Asm { .. }
+            | Stmt::Sh { .. } => {}
+        }
+    }
+}
+
+/// Calls in a function's body and in its parameter defaults.
+pub(crate) fn func_calls<'a>(func: &'a Func, out: &mut Vec<&'a str>) {
+    func.params.iter().filter_map(|p| p.default.as_ref()).for_each(|default| expr_calls(default, out));
+    collect_calls(&func.body, out);
+}
+
+fn expr_calls<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Call { callee, args } => {
+            out.push(callee);
+            args.iter().for_each(|arg| expr_calls(arg, out));
+        }
+        Expr::Binary { lhs, rhs, .. } | Expr::Index { target: lhs, index: rhs } => {
+            expr_calls(lhs, out);
+            expr_calls(rhs, out);
+        }
+        Expr::Map { entries } => entries.iter().for_each(|(_, value)| expr_calls(value, out)),
+        Expr::Slice { target, start, end } => {
+            expr_calls(target, out);
+            start.iter().chain(end).for_each(|bound| expr_calls(bound, out));
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            [cond, then_value, else_value].into_iter().for_each(|part| expr_calls(part, out))
+        }
+        Expr::Lambda { params, body, .. } => {
+            params.iter().filter_map(|p| p.default.as_ref()).for_each(|default| expr_calls(default, out));
+            collect_calls(body, out);
+        }
+        // a function passed around by name may be called through the value
+        Expr::Var { name } => out.push(name),
+        Expr::Lit(_) => {}
+    }
+}
+
+/// Value of a condition, if it does not depend on runtime state.
+fn const_truth(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Lit(lit) => Some(truthy(lit)),
+        Expr::Binary { op, lhs, rhs } => match (lhs.as_ref(), rhs.as_ref()) {
+            (Expr::Lit(a), Expr::Lit(b)) => compare_op(*op, a, b),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn truthy(lit: &Lit) -> bool {
+    match lit {
+        Lit::Null => false,
+        Lit::Bool(b) => *b,
+        Lit::Int(n) => *n != 0,
+        Lit::Float(x) => *x != 0.0,
+        Lit::Str(s) => !s.is_empty(),
+    }
+}
+
+/// Value of an operator on two literals, as the VM would compute it, or
+/// `None` when it must fail at run time (division by zero, `1 < "a"`) or
+/// may (an int overflow or a float that is not finite, which wrap or
+/// propagate unless the VM checks math).
+pub fn eval_binary(op: BinOp, a: &Lit, b: &Lit) -> Option<Lit> {
+    match (op, a, b) {
+        (BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, ..) => {
+            compare_op(op, a, b).map(Lit::Bool)
+        }
+        (BinOp::And, ..) => Some(if truthy(a) { b.clone() } else { a.clone() }),
+        (BinOp::Or, ..) => Some(if truthy(a) { a.clone() } else { b.clone() }),
+        (BinOp::Add, Lit::Str(a), b) => Some(Lit::Str(format!("{}{}", a, display(b)))),
+        (_, Lit::Int(x), Lit::Int(y)) => Some(Lit::Int(match op {
+            BinOp::Add => x.checked_add(*y)?,
+            BinOp::Sub => x.checked_sub(*y)?,
+            BinOp::Mul => x.checked_mul(*y)?,
+            BinOp::Div => x.checked_div(*y)?,
+            BinOp::Rem => x.checked_rem(*y)?,
+            _ => return None,
+        })),
+        (_, Lit::Int(_) | Lit::Float(_), Lit::Int(_) | Lit::Float(_)) => {
+            let (x, y) = (as_f64(a)?, as_f64(b)?);
+            let value = match op {
+                BinOp::Add => x + y,
+                BinOp::Sub => x - y,
+                BinOp::Mul => x * y,
+                BinOp::Div => x / y,
+                BinOp::Rem => x % y,
+                _ => return None,
+            };
+            value.is_finite().then_some(Lit::Float(value))
+        }
+        _ => None,
+    }
+}
+
+/// Result of a comparison operator on two literals, as the VM would compute it.
+fn compare_op(op: BinOp, a: &Lit, b: &Lit) -> Option<bool> {
+    let equal = match (a, b) {
+        (Lit::Int(_), Lit::Float(_)) | (Lit::Float(_), Lit::Int(_)) => as_f64(a) == as_f64(b),
+        _ => a == b,
+    };
+    let ord = || match (a, b) {
+        (Lit::Int(x), Lit::Int(y)) => Some(x.cmp(y)),
+        (Lit::Str(x), Lit::Str(y)) => Some(x.cmp(y)),
+        _ => as_f64(a)?.partial_cmp(&as_f64(b)?),
+    };
+    Some(match op {
+        BinOp::Eq => equal,
+        BinOp::Ne => !equal,
+        BinOp::Lt => ord()? == Ordering::Less,
+        BinOp::Le => ord()? != Ordering::Greater,
+        BinOp::Gt => ord()? == Ordering::Greater,
+        BinOp::Ge => ord()? != Ordering::Less,
+        _ => return None,
+    })
+}
+
+fn as_f64(lit: &Lit) -> Option<f64> {
+    match lit {
+        Lit::Int(n) => Some(*n as f64),
+        Lit::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// How the VM prints a literal when it is concatenated onto a string.
+fn display(lit: &Lit) -> String {
+    match lit {
+        Lit::Null => "null".to_string(),
+        Lit::Bool(b) => b.to_string(),
+        Lit::Int(n) => n.to_string(),
+        Lit::Float(x) => x.to_string(),
+        Lit::Str(s) => s.clone(),
+    }
+}
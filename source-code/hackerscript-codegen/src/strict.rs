@@ -0,0 +1,316 @@
+//! The checks a file opts into with a `--- strict ---` header (or `hs1
+//! --strict`), on top of those of `check`:
+//!
+//! - every function and lambda parameter has a type, unless a default or
+//!   `...rest` gives it one, and a function that returns a value declares
+//!   what it returns, so nothing is `any` without saying so;
+//! - a variable is read only after a `let` (or parameter, `case` pattern,
+//!   `except` or `asm` `store_var`) binds it, in its own block or one around it.
+//!   Functions, enums and objects can be used anywhere, and a function can
+//!   read any global;
+//! - a function or lambda does not bind a name that is already a global or a
+//!   variable of the function around it;
+//! - a function that returns a value does so on every path, rather than
+//!   reaching its end and returning `null`.
+//!
+//! They hold for one file as it was parsed, before its imports are expanded:
+//! the modules it imports keep their own rules, and their names are not
+//! known here, so reading or shadowing one passes.
+use std::collections::HashSet;
+
+use hackerscript_ast::{Expr, Lit, Param, Pattern, Program, Spanned, Stmt, Symbol};
+
+use crate::check::{self, CheckError};
+
+/// Every strict mode error in `program`, in source order, whether or not it
+/// asks for strict mode.
+pub fn check(program: &Program) -> Vec<CheckError> {
+    let mut hoisted = HashSet::new();
+    check::walk(&program.body, &mut |stmt| match stmt {
+        Stmt::Func(func) => {
+            hoisted.insert(func.name.to_string());
+        }
+        Stmt::Enum(decl) => {
+            hoisted.insert(decl.name.to_string());
+        }
+        Stmt::Object { name, .. } => {
+            hoisted.insert(name.to_string());
+        }
+        _ => {}
+    });
+    let mut globals = hoisted.clone();
+    check::bindings(&program.body, &mut globals);
+    let mut strict = Strict {
+        hoisted,
+        globals,
+        scopes: Vec::new(),
+        scope: "at the top level".to_string(),
+        line: None,
+        errors: Vec::new(),
+    };
+    strict.block(&program.body);
+    strict.errors
+}
+
+/// `check` as one error, with a line per problem.
+pub fn ensure(program: &Program) -> anyhow::Result<()> {
+    check::one_error(check(program))
+}
+
+/// A block's variables; a function or lambda body also has every name it binds.
+#[derive(Default)]
+struct Scope {
+    names: HashSet<String>,
+    function: Option<HashSet<String>>,
+}
+
+struct Strict {
+    /// Functions, enums and objects, which are visible before their definitions
+    hoisted: HashSet<String>,
+    /// `hoisted` and everything the top level binds, which functions can read
+    globals: HashSet<String>,
+    /// The blocks around the statement being checked, innermost last. A
+    /// named function starts over; a lambda sees the blocks it is created in.
+    scopes: Vec<Scope>,
+    /// Where the errors say the problem is, e.g. "in `main`"
+    scope: String,
+    /// Line of the statement being checked, if the parser recorded one
+    line: Option<u32>,
+    errors: Vec<CheckError>,
+}
+
+impl Strict {
+    fn report(&mut self, message: String) {
+        let error = CheckError::Strict { message, scope: self.scope.clone(), line: self.line };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    fn in_function(&self) -> bool {
+        self.scopes.iter().any(|scope| scope.function.is_some())
+    }
+
+    fn visible(&self, name: &str) -> bool {
+        let outside = if self.in_function() { &self.globals } else { &self.hoisted };
+        outside.contains(name) || self.scopes.iter().any(|scope| scope.names.contains(name))
+    }
+
+    /// Whether `name` is bound anywhere the statement can see, in order or not.
+    fn bound(&self, name: &str) -> bool {
+        self.globals.contains(name)
+            || self.scopes.iter().any(|scope| scope.function.as_ref().is_some_and(|names| names.contains(name)))
+    }
+
+    /// Bind `name` in the innermost block. In a function, it is a new
+    /// variable unless the function already has one.
+    fn declare(&mut self, name: &str) {
+        if self.in_function() {
+            let own = self.scopes.iter().rposition(|scope| scope.function.is_some()).unwrap_or_default();
+            if !self.scopes[own..].iter().any(|scope| scope.names.contains(name)) {
+                if self.scopes[..own].iter().any(|scope| scope.names.contains(name)) {
+                    self.report(format!("`{}` shadows a variable of the enclosing function", name));
+                } else if self.globals.contains(name) {
+                    self.report(format!("`{}` shadows a global", name));
+                }
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.insert(name.to_string());
+        }
+    }
+
+    fn block(&mut self, body: &[Spanned<Stmt>]) {
+        let outer = self.line;
+        self.scopes.push(Scope::default());
+        for stmt in body {
+            self.line = (!stmt.span.is_unknown()).then_some(stmt.span.line);
+            self.stmt(&stmt.node);
+        }
+        self.scopes.pop();
+        self.line = outer;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, value, .. } | Stmt::Const { name, value, .. } => {
+                self.expr(value);
+                self.declare(name);
+            }
+            Stmt::Destructure { pattern, value } => {
+                self.expr(value);
+                pattern.names().for_each(|name| self.declare(name));
+            }
+            Stmt::Log { values, .. } => values.iter().for_each(|value| self.expr(value)),
+            Stmt::Expr { value } | Stmt::Throw { value } => self.expr(value),
+            Stmt::Return { value } => value.iter().for_each(|value| self.expr(value)),
+            Stmt::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.block(then_body);
+                self.block(else_body);
+            }
+            Stmt::While { cond, body } => {
+                self.expr(cond);
+                self.block(body);
+            }
+            Stmt::Match { subject, cases, default } => {
+                self.expr(subject);
+                for case in cases {
+                    self.expr(&case.value);
+                    self.scopes.push(Scope::default());
+                    case.pattern.iter().flat_map(Pattern::names).for_each(|name| self.declare(name));
+                    self.block(&case.body);
+                    self.scopes.pop();
+                }
+                self.block(default);
+            }
+            Stmt::Try { body, except, finally } => {
+                self.block(body);
+                if let Some(except) = except {
+                    self.scopes.push(Scope::default());
+                    except.name.iter().for_each(|name| self.declare(name));
+                    self.block(&except.body);
+                    self.scopes.pop();
+                }
+                self.block(finally);
+            }
+            Stmt::Asm { code } => check::stored(code).for_each(|name| self.declare(&name)),
+            Stmt::Func(func) => {
+                let outer = std::mem::take(&mut self.scopes);
+                let scope = std::mem::replace(&mut self.scope, format!("in `{}`", func.name));
+                self.function(&func.params, func.ret.as_ref(), &func.body);
+                self.scope = scope;
+                self.scopes = outer;
+            }
+            // object bodies do not run
+            Stmt::Object { .. }
+            | Stmt::Break
+            | Stmt::Continue
+            | Stmt::Sh { .. }
+            | Stmt::Enum(_)
+            | Stmt::Interface(_)
+            | Stmt::Import { .. }
+            | Stmt::ImportModule { .. }
+            | Stmt::Require { .. } => {}
+        }
+    }
+
+    fn function(&mut self, params: &[Param], ret: Option<&Symbol>, body: &[Spanned<Stmt>]) {
+        let mut bound: HashSet<String> = params.iter().map(|param| param.name.to_string()).collect();
+        check::bindings(body, &mut bound);
+        for param in params {
+            if param.ty.is_none() && param.default.is_none() && !param.rest {
+                self.report(format!("parameter `{}` has no type", param.name));
+            }
+        }
+        let returns_value = returns_value(body);
+        if ret.is_none() && returns_value {
+            self.report("the return value has no declared type".to_string());
+        }
+        let void = ret.is_some_and(|ret| matches!(ret.to_ascii_lowercase().as_str(), "null" | "void"));
+        if (returns_value || ret.is_some() && !void) && !always_returns(body) {
+            self.report("the end can be reached without returning a value".to_string());
+        }
+        self.scopes.push(Scope { names: HashSet::new(), function: Some(bound) });
+        for param in params {
+            // defaults are evaluated in the callee's frame
+            param.default.iter().for_each(|default| self.expr(default));
+            self.declare(&param.name);
+        }
+        self.block(body);
+        self.scopes.pop();
+    }
+
+    fn read(&mut self, name: &str) {
+        // a name nothing binds is `check`'s undefined variable
+        if !self.visible(name) && self.bound(name) {
+            self.report(format!("`{}` is read before it is declared, or outside the block that declares it", name));
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lit(_) => {}
+            Expr::Var { name } => self.read(name),
+            Expr::Call { callee, args } => {
+                if !self.hoisted.contains(callee.as_str()) {
+                    self.read(callee);
+                }
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Map { entries } => entries.iter().for_each(|(_, value)| self.expr(value)),
+            Expr::Index { target, index } => {
+                self.expr(target);
+                self.expr(index);
+            }
+            Expr::Slice { target, start, end } => {
+                self.expr(target);
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                self.expr(cond);
+                self.expr(then_value);
+                self.expr(else_value);
+            }
+            Expr::Lambda { params, ret, body } => {
+                let inner = format!("in a lambda {}", self.scope);
+                let scope = std::mem::replace(&mut self.scope, inner);
+                self.function(params, ret.as_ref(), body);
+                self.scope = scope;
+            }
+        }
+    }
+}
+
+/// Whether a `return` in `body`, outside its functions and lambdas, returns a value.
+fn returns_value(body: &[Spanned<Stmt>]) -> bool {
+    body.iter().any(|stmt| match &stmt.node {
+        Stmt::Return { value } => value.is_some(),
+        Stmt::If { then_body, else_body, .. } => returns_value(then_body) || returns_value(else_body),
+        Stmt::While { body, .. } => returns_value(body),
+        Stmt::Match { cases, default, .. } => {
+            cases.iter().any(|case| returns_value(&case.body)) || returns_value(default)
+        }
+        Stmt::Try { body, except, finally } => {
+            returns_value(body)
+                || except.as_ref().is_some_and(|except| returns_value(&except.body))
+                || returns_value(finally)
+        }
+        _ => false,
+    })
+}
+
+/// Whether every path through `body` ends in a `return` or `throw`, or
+/// never ends.
+fn always_returns(body: &[Spanned<Stmt>]) -> bool {
+    body.iter().any(|stmt| match &stmt.node {
+        Stmt::Return { .. } | Stmt::Throw { .. } => true,
+        Stmt::If { then_body, else_body, .. } => always_returns(then_body) && always_returns(else_body),
+        Stmt::Match { cases, default, .. } => {
+            !default.is_empty() && always_returns(default) && cases.iter().all(|case| always_returns(&case.body))
+        }
+        Stmt::Try { body, except, finally } => {
+            always_returns(finally)
+                || always_returns(body) && except.as_ref().is_none_or(|except| always_returns(&except.body))
+        }
+        Stmt::While { cond: Expr::Lit(Lit::Bool(true)), body } => !breaks(body),
+        _ => false,
+    })
+}
+
+/// Whether a `break` in `body` leaves the loop `body` belongs to.
+fn breaks(body: &[Spanned<Stmt>]) -> bool {
+    body.iter().any(|stmt| match &stmt.node {
+        Stmt::Break => true,
+        Stmt::If { then_body, else_body, .. } => breaks(then_body) || breaks(else_body),
+        Stmt::Match { cases, default, .. } => cases.iter().any(|case| breaks(&case.body)) || breaks(default),
+        Stmt::Try { body, except, finally } => {
+            breaks(body) || except.as_ref().is_some_and(|except| breaks(&except.body)) || breaks(finally)
+        }
+        _ => false,
+    })
+}
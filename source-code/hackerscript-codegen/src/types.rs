@@ -0,0 +1,582 @@
+//! Type checking before code generation. Annotations are optional
+//! (`let x: int = 5`, `func f(a: string) -> number`); the types of everything
+//! else are inferred from literals, operators, natives and annotated
+//! functions, following control flow: branches join, loops run to a fixed
+//! point and a variable assigned in a `try` may hold anything in `except`.
+//!
+//! Only operations that fail whatever the unknown values turn out to be are
+//! reported, using the VM's own rules: adding a map to an int, indexing an
+//! int, passing a string where a parameter is declared `number`. Anything
+//! the checker cannot see through, such as a global read inside a function,
+//! is `any` and passes.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use hackerscript_ast::{BinOp, Expr, Func, Lit, Param, Pattern, Program, Spanned, Stmt, Symbol};
+
+use crate::check::{self, CheckError};
+
+/// What the checker knows about a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Null,
+    Bool,
+    Int,
+    Float,
+    /// An int or a float
+    Number,
+    Str,
+    Array,
+    Map,
+    Func,
+}
+
+impl Type {
+    /// The type an annotation names, ignoring case. Names the checker does
+    /// not know, such as an object's, are `Any`.
+    pub fn named(name: &str) -> Type {
+        match name.to_ascii_lowercase().as_str() {
+            "null" | "void" => Type::Null,
+            "bool" | "boolean" => Type::Bool,
+            "int" | "integer" => Type::Int,
+            "float" => Type::Float,
+            "number" => Type::Number,
+            "str" | "string" => Type::Str,
+            "array" | "list" => Type::Array,
+            "map" => Type::Map,
+            "func" | "function" => Type::Func,
+            _ => Type::Any,
+        }
+    }
+
+    fn numeric(self) -> bool {
+        matches!(self, Type::Int | Type::Float | Type::Number)
+    }
+
+    /// Whether no value has both types.
+    fn disjoint(self, other: Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => false,
+            (Type::Number, other) | (other, Type::Number) => !other.numeric(),
+            (a, b) => a != b,
+        }
+    }
+
+    /// A type for values of either type.
+    fn join(self, other: Type) -> Type {
+        if self == other {
+            self
+        } else if self.numeric() && other.numeric() {
+            Type::Number
+        } else {
+            Type::Any
+        }
+    }
+
+    /// A value of type `self` stored where `declared` is expected.
+    fn narrow(self, declared: Type) -> Type {
+        match (self, declared) {
+            (Type::Any | Type::Number, declared) if declared != Type::Any => declared,
+            (value, _) => value,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Any => "any",
+            Type::Null => "null",
+            Type::Bool => "bool",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Number => "number",
+            Type::Str => "string",
+            Type::Array => "array",
+            Type::Map => "map",
+            Type::Func => "func",
+        })
+    }
+}
+
+/// Every type error in `program`, in source order.
+pub fn check(program: &Program) -> Vec<CheckError> {
+    let mut funcs = HashMap::new();
+    check::walk(&program.body, &mut |stmt| {
+        if let Stmt::Func(func) = stmt {
+            funcs.entry(func.name.as_str()).or_insert(func);
+        }
+    });
+    let mut variables = HashSet::new();
+    check::bindings(&program.body, &mut variables);
+    let globals = annotations(&[], &program.body);
+    let mut types = Types {
+        funcs,
+        variables,
+        declared: globals.clone(),
+        globals,
+        top: true,
+        ret: None,
+        loops: Vec::new(),
+        scope: "at the top level".to_string(),
+        line: None,
+        errors: Vec::new(),
+    };
+    types.block(&program.body, Some(Env::new()));
+    types.errors
+}
+
+/// The variables known at a point of the program, or `None` where it cannot
+/// be reached. A name that is missing is read from the globals.
+type Env = HashMap<Symbol, Type>;
+
+#[derive(Default)]
+struct Loop {
+    breaks: Option<Env>,
+    continues: Option<Env>,
+}
+
+struct Types<'a> {
+    funcs: HashMap<&'a str, &'a Func>,
+    /// Names any top-level statement binds, which a call may find before a function
+    variables: HashSet<String>,
+    /// Annotated top-level names
+    globals: HashMap<String, Type>,
+    /// Annotated names of the function, lambda or top level being checked
+    declared: HashMap<String, Type>,
+    /// Whether the top level is being checked, where the env holds the globals
+    top: bool,
+    /// The return annotation of the function being checked
+    ret: Option<Type>,
+    loops: Vec<Loop>,
+    /// Where the errors say the problem is, e.g. "in `main`"
+    scope: String,
+    /// Line of the statement being checked, if the parser recorded one
+    line: Option<u32>,
+    errors: Vec<CheckError>,
+}
+
+impl<'a> Types<'a> {
+    fn report(&mut self, message: String) {
+        let error = CheckError::Type { message, scope: self.scope.clone(), line: self.line };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    fn lookup(&self, env: &Env, name: &str) -> Type {
+        match env.get(name) {
+            Some(ty) => *ty,
+            None if self.top => Type::Any,
+            None => self.globals.get(name).copied().unwrap_or(Type::Any),
+        }
+    }
+
+    fn block(&mut self, body: &'a [Spanned<Stmt>], mut env: Option<Env>) -> Option<Env> {
+        let outer = self.line;
+        for stmt in body {
+            self.line = (!stmt.span.is_unknown()).then_some(stmt.span.line);
+            self.stmt(stmt, &mut env);
+        }
+        self.line = outer;
+        env
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt, env: &mut Option<Env>) {
+        if let Stmt::Func(func) = stmt {
+            return self.func(func);
+        }
+        let Some(current) = env else { return };
+        match stmt {
+            Stmt::Let { name, value, .. } | Stmt::Const { name, value, .. } => {
+                let value = self.expr(value, current);
+                let ty = self.bind(name, value);
+                current.insert(name.clone(), ty);
+            }
+            Stmt::Destructure { pattern, value } => {
+                self.expr(value, current);
+                for name in pattern.names() {
+                    let ty = self.bind(name, Type::Any);
+                    current.insert(name.clone(), ty);
+                }
+            }
+            Stmt::Log { values, .. } => {
+                for value in values {
+                    self.expr(value, current);
+                }
+            }
+            Stmt::Expr { value } => {
+                self.expr(value, current);
+            }
+            Stmt::Throw { value } => {
+                self.expr(value, current);
+                *env = None;
+            }
+            Stmt::Return { value } => {
+                let ty = value.as_ref().map_or(Type::Null, |value| self.expr(value, current));
+                if let Some(ret) = self.ret.filter(|ret| ty.disjoint(*ret)) {
+                    self.report(format!("expected {} for the return value, found {}", ret, ty));
+                }
+                *env = None;
+            }
+            Stmt::Break | Stmt::Continue => {
+                let current = env.take();
+                if let Some(innermost) = self.loops.last_mut() {
+                    let exit = if matches!(stmt, Stmt::Break) { &mut innermost.breaks } else { &mut innermost.continues };
+                    *exit = join(exit.take(), current);
+                }
+            }
+            Stmt::If { cond, then_body, else_body } => {
+                self.expr(cond, current);
+                let then_env = self.block(then_body, env.clone());
+                let else_env = self.block(else_body, env.take());
+                *env = join(then_env, else_env);
+            }
+            Stmt::While { cond, body } => *env = self.while_loop(cond, body, env.take()),
+            Stmt::Match { subject, cases, default } => {
+                self.expr(subject, current);
+                for case in cases {
+                    self.expr(&case.value, current);
+                }
+                let start = env.take();
+                let mut out = self.block(default, start.clone());
+                for case in cases {
+                    let mut case_start = start.clone();
+                    if let Some(case_start) = &mut case_start {
+                        for name in case.pattern.iter().flat_map(Pattern::names) {
+                            let ty = self.bind(name, Type::Any);
+                            case_start.insert(name.clone(), ty);
+                        }
+                    }
+                    let case_env = self.block(&case.body, case_start);
+                    out = join(out, case_env);
+                }
+                *env = out;
+            }
+            Stmt::Try { body, except, finally } => *env = self.try_stmt(body, except.as_ref(), finally, env.take()),
+            Stmt::Asm { code } => {
+                for name in check::stored(code) {
+                    current.insert(name.as_str().into(), Type::Any);
+                }
+            }
+            Stmt::Func(_)
+            | Stmt::Object { .. }
+            | Stmt::Sh { .. }
+            | Stmt::Enum(_)
+            | Stmt::Interface(_)
+            | Stmt::Import { .. }
+            | Stmt::ImportModule { .. }
+            | Stmt::Require { .. } => {}
+        }
+    }
+
+    /// The type `name` holds after binding a `value`, checked against its annotation.
+    fn bind(&mut self, name: &str, value: Type) -> Type {
+        match self.declared.get(name).copied() {
+            Some(declared) => {
+                if value.disjoint(declared) {
+                    self.report(format!("expected {} for `{}`, found {}", declared, name, value));
+                }
+                value.narrow(declared)
+            }
+            None => value,
+        }
+    }
+
+    /// Check the body until the types at the top of the loop stop changing.
+    fn while_loop(&mut self, cond: &'a Expr, body: &'a [Spanned<Stmt>], env: Option<Env>) -> Option<Env> {
+        let mut head = env;
+        loop {
+            let Some(current) = &head else { return None };
+            self.expr(cond, current);
+            self.loops.push(Loop::default());
+            let out = self.block(body, head.clone());
+            let exits = self.loops.pop().unwrap_or_default();
+            let next = join(head.clone(), join(out, exits.continues));
+            if next == head {
+                return join(head, exits.breaks);
+            }
+            head = next;
+        }
+    }
+
+    fn try_stmt(
+        &mut self,
+        body: &'a [Spanned<Stmt>],
+        except: Option<&'a hackerscript_ast::Except>,
+        finally: &'a [Spanned<Stmt>],
+        env: Option<Env>,
+    ) -> Option<Env> {
+        let before = env.clone();
+        let done = self.block(body, env);
+        // an error can come before or after any assignment in the body
+        let mut assigned = HashSet::new();
+        check::bindings(body, &mut assigned);
+        let caught = havoc(join(before, done.clone()), &assigned);
+        let normal = match except {
+            Some(except) => {
+                let mut start = caught.clone();
+                if let (Some(start), Some(name)) = (&mut start, &except.name) {
+                    start.insert(name.clone(), Type::Any);
+                }
+                let handled = self.block(&except.body, start);
+                check::bindings(&except.body, &mut assigned);
+                join(done, handled)
+            }
+            None => done,
+        };
+        let unwinding = havoc(caught, &assigned);
+        let reachable = normal.is_some();
+        let out = self.block(finally, join(normal, unwinding));
+        out.filter(|_| reachable)
+    }
+
+    fn func(&mut self, func: &'a Func) {
+        let scope = std::mem::replace(&mut self.scope, format!("in `{}`", func.name));
+        let top = std::mem::replace(&mut self.top, false);
+        self.function_body(&func.params, func.ret.as_ref(), &func.body, Env::new());
+        self.top = top;
+        self.scope = scope;
+    }
+
+    fn function_body(&mut self, params: &'a [Param], ret: Option<&Symbol>, body: &'a [Spanned<Stmt>], mut env: Env) {
+        let declared = std::mem::replace(&mut self.declared, annotations(params, body));
+        let ret = std::mem::replace(&mut self.ret, ret.map(|ret| Type::named(ret)));
+        let loops = std::mem::take(&mut self.loops);
+        for param in params {
+            let ty = match &param.default {
+                _ if param.rest => Type::Array,
+                // defaults are evaluated in the callee's frame
+                Some(default) => {
+                    let value = self.expr(default, &env);
+                    self.bind(&param.name, value)
+                }
+                None => param.ty.as_ref().map_or(Type::Any, |ty| Type::named(ty)),
+            };
+            env.insert(param.name.clone(), ty);
+        }
+        self.block(body, Some(env));
+        self.loops = loops;
+        self.ret = ret;
+        self.declared = declared;
+    }
+
+    fn expr(&mut self, expr: &'a Expr, env: &Env) -> Type {
+        match expr {
+            Expr::Lit(lit) => match lit {
+                Lit::Null => Type::Null,
+                Lit::Bool(_) => Type::Bool,
+                Lit::Int(_) => Type::Int,
+                Lit::Float(_) => Type::Float,
+                Lit::Str(_) => Type::Str,
+            },
+            Expr::Var { name } => self.lookup(env, name),
+            Expr::Call { callee, args } => {
+                let args: Vec<Type> = args.iter().map(|arg| self.expr(arg, env)).collect();
+                self.call(callee, &args, env)
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let lhs = self.expr(lhs, env);
+                let rhs = self.expr(rhs, env);
+                self.binary(*op, lhs, rhs)
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                self.expr(cond, env);
+                self.expr(then_value, env).join(self.expr(else_value, env))
+            }
+            Expr::Map { entries } => {
+                for (_, value) in entries {
+                    self.expr(value, env);
+                }
+                Type::Map
+            }
+            Expr::Index { target, index } => {
+                let target = self.expr(target, env);
+                let index = self.expr(index, env);
+                self.index(target, index)
+            }
+            Expr::Slice { target, start, end } => {
+                let target = self.expr(target, env);
+                for bound in start.iter().chain(end) {
+                    let bound = self.expr(bound, env);
+                    if bound.disjoint(Type::Int) && bound != Type::Null {
+                        self.report(format!("slice bounds must be ints, found {}", bound));
+                    }
+                }
+                match target {
+                    Type::Any | Type::Array | Type::Str => target,
+                    other => {
+                        self.report(format!("cannot slice {}", other));
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Lambda { params, ret, body } => {
+                let inner = format!("in a lambda {}", self.scope);
+                let scope = std::mem::replace(&mut self.scope, inner);
+                let top = std::mem::replace(&mut self.top, false);
+                // a lambda at the top level captures nothing and reads the globals when called
+                let captured = if top { Env::new() } else { env.clone() };
+                self.function_body(params, ret.as_ref(), body, captured);
+                self.top = top;
+                self.scope = scope;
+                Type::Func
+            }
+        }
+    }
+
+    /// A variable holding a function comes first, then the functions, then the natives.
+    fn call(&mut self, callee: &str, args: &[Type], env: &Env) -> Type {
+        if env.contains_key(callee) || self.variables.contains(callee) {
+            return Type::Any;
+        }
+        if let Some(func) = self.funcs.get(callee).copied() {
+            for (param, arg) in func.params.iter().filter(|param| !param.rest).zip(args) {
+                let Some(declared) = param.ty.as_ref().map(|ty| Type::named(ty)) else { continue };
+                if arg.disjoint(declared) {
+                    self.report(format!(
+                        "expected {} for argument `{}` of `{}`, found {}",
+                        declared, param.name, callee, arg
+                    ));
+                }
+            }
+            return func.ret.as_ref().map_or(Type::Any, |ret| Type::named(ret));
+        }
+        match callee {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "str" | "hex" | "bin" => Type::Str,
+            "type_of" => Type::Str,
+            "fields" | "methods" | "map" | "filter" | "sort" | "zip" => Type::Array,
+            "has_field" | "any" | "all" => Type::Bool,
+            "sh" | "Error" | "set_field" => Type::Map,
+            _ => Type::Any,
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Type, rhs: Type) -> Type {
+        match op {
+            BinOp::Eq | BinOp::Ne => Type::Bool,
+            BinOp::And | BinOp::Or => lhs.join(rhs),
+            // a string on the left takes anything
+            BinOp::Add => match (lhs, rhs) {
+                (Type::Str, _) => Type::Str,
+                (Type::Any, _) => Type::Any,
+                (lhs, rhs) if lhs.numeric() && (rhs.numeric() || rhs == Type::Any) => arith(lhs, rhs),
+                (lhs, rhs) => {
+                    self.report(format!("cannot add {} and {}", lhs, rhs));
+                    Type::Any
+                }
+            },
+            BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
+                if lhs.disjoint(Type::Number) || rhs.disjoint(Type::Number) {
+                    self.report(format!("cannot apply `{}` to {} and {}", op.symbol(), lhs, rhs));
+                    return Type::Any;
+                }
+                arith(lhs, rhs)
+            }
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                let comparable = |ty: Type| ty == Type::Any || ty == Type::Str || ty.numeric();
+                let mismatched = (lhs == Type::Str && rhs.numeric()) || (lhs.numeric() && rhs == Type::Str);
+                if !comparable(lhs) || !comparable(rhs) || mismatched {
+                    self.report(format!("cannot compare {} and {}", lhs, rhs));
+                }
+                Type::Bool
+            }
+        }
+    }
+
+    fn index(&mut self, target: Type, index: Type) -> Type {
+        let (key, element) = match target {
+            Type::Any => return Type::Any,
+            Type::Map => (Type::Str, Type::Any),
+            Type::Array => (Type::Int, Type::Any),
+            Type::Str => (Type::Int, Type::Str),
+            other => {
+                self.report(format!("cannot index {}", other));
+                return Type::Any;
+            }
+        };
+        if index.disjoint(key) {
+            self.report(format!("cannot index {} with {}", target, index));
+        }
+        element
+    }
+}
+
+/// The type of `+`, `-`, `*`, `/` or `%` on two numbers.
+fn arith(lhs: Type, rhs: Type) -> Type {
+    match (lhs, rhs) {
+        (Type::Int, Type::Int) => Type::Int,
+        (Type::Float, _) | (_, Type::Float) => Type::Float,
+        _ => Type::Number,
+    }
+}
+
+/// The env after one of two paths; a name only one of them binds may still
+/// be a global on the other.
+fn join(a: Option<Env>, b: Option<Env>) -> Option<Env> {
+    let (mut a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => return a.or(b),
+    };
+    for (name, ty) in a.iter_mut() {
+        *ty = b.get(name).map_or(Type::Any, |other| ty.join(*other));
+    }
+    for name in b.into_keys() {
+        a.entry(name).or_insert(Type::Any);
+    }
+    Some(a)
+}
+
+fn havoc(env: Option<Env>, names: &HashSet<String>) -> Option<Env> {
+    env.map(|mut env| {
+        for name in names {
+            env.insert(name.as_str().into(), Type::Any);
+        }
+        env
+    })
+}
+
+/// The annotated parameters and bindings of a function body, or of the top
+/// level. A name annotated twice keeps its first type.
+fn annotations(params: &[Param], body: &[Spanned<Stmt>]) -> HashMap<String, Type> {
+    let mut declared = HashMap::new();
+    for param in params {
+        if let Some(ty) = &param.ty {
+            declared.entry(param.name.to_string()).or_insert(Type::named(ty));
+        }
+    }
+    annotated(body, &mut declared);
+    declared
+}
+
+/// `annotations` of the bindings in `body` and its nested blocks, like
+/// `check::bindings`.
+fn annotated(body: &[Spanned<Stmt>], declared: &mut HashMap<String, Type>) {
+    for stmt in body {
+        match &stmt.node {
+            Stmt::Let { name, ty: Some(ty), .. } | Stmt::Const { name, ty: Some(ty), .. } => {
+                declared.entry(name.to_string()).or_insert(Type::named(ty));
+            }
+            Stmt::If { then_body, else_body, .. } => {
+                annotated(then_body, declared);
+                annotated(else_body, declared);
+            }
+            Stmt::While { body, .. } => annotated(body, declared),
+            Stmt::Match { cases, default, .. } => {
+                cases.iter().for_each(|case| annotated(&case.body, declared));
+                annotated(default, declared);
+            }
+            Stmt::Try { body, except, finally } => {
+                annotated(body, declared);
+                if let Some(except) = except {
+                    annotated(&except.body, declared);
+                }
+                annotated(finally, declared);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use hackerscript_bytecode::{verify, Bytecode};
+use hackerscript_codegen::asm::assemble_named;
+use hackerscript_codegen::compiler::compile_named;
+use hackerscript_vm::{BufferHost, VM};
+
+fn compile(source: &str) -> anyhow::Result<Bytecode> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    compile_named(&program, "asm.hcs")
+}
+
+fn run(source: &str) -> Vec<String> {
+    let bytecode = compile(source).unwrap();
+    verify(&bytecode).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    host.lines
+}
+
+#[test]
+fn asm_blocks_run_inline_with_the_surrounding_code() {
+    let source = "let n = 3\nasm [\n    @ count n down to zero ] (brackets in comments are fine)\nloop:\n    load_var n\n    jump_if_false done\n    load_var n  log_string\n    load_var n  push_int 1  sub  store_var n\n    jump loop\ndone:\n    push_const \"a ] b\"\n    call_native str 1\n    log_at 1 warn\n]\nlog \"n is\", n\n";
+    assert_eq!(run(source), ["3", "2", "1", "[warn] a ] b", "n is 0"]);
+}
+
+#[test]
+fn asm_blocks_can_define_and_call_functions() {
+    let source = "asm [\n    jump over\nbody:\n    begin_func\n    load_local 0 x  push_int 2  mul  return\n    end_func\nover:\n    make_func body double 1 1 1 0\n    define double\n]\nlog double(21)\nasm [ push_int 4  call double 1  log_string ]\n";
+    assert_eq!(run(source), ["42", "8"]);
+}
+
+#[test]
+fn asm_blocks_can_dispatch_through_tables() {
+    let source = "\
+let n = 2
+let s = \"b\"
+asm [
+    load_var n
+    jump_table 1 2 other one two
+one:
+    push_const \"one\"  log_string  jump strings
+two:
+    push_const \"two\"  log_string  jump strings
+other:
+    push_const \"other\"  log_string
+strings:
+    load_var s
+    jump_string 2 done \"b\" b \"a\" a
+a:
+    push_const \"a\"  log_string  jump done
+b:
+    push_const \"b\"  log_string
+done:
+]
+";
+    assert_eq!(run(source), ["two", "b"]);
+}
+
+#[test]
+fn raw_constant_indexes_refer_to_the_program_pool() {
+    assert_eq!(run("log \"first\"\nasm [ push_const 0  log_string ]\n"), ["first", "first"]);
+}
+
+#[test]
+fn bad_blocks_are_rejected_at_compile_time() {
+    for (code, message) in [
+        ("push", "asm line 1: unknown instruction `push`"),
+        ("push_int", "asm line 1: `push_int` expects an integer"),
+        ("push_int x", "asm line 1: expected an integer, found `x`"),
+        ("\n jump nowhere", "asm line 2: undefined label `nowhere`"),
+        ("a: a: nop", "asm line 1: label `a` is defined twice"),
+        ("log_at 1 loud", "asm line 1: unknown log level `loud`"),
+        ("push_const \"open", "asm line 1: unterminated string"),
+        (".const 1", "asm line 1: `.const` expects a string"),
+    ] {
+        let err = compile(&format!("asm [ {} ]\n", code)).unwrap_err();
+        assert!(format!("{:#}", err).contains(message), "{}: {:#}", code, err);
+    }
+}
+
+#[test]
+fn asm_blocks_can_make_enum_variants() {
+    let source = "enum Color [ Red, Green = 5 ]\nasm [ make_variant \"Color\" \"Green\" 5  store_var c ]\nlog c, c == Color.Green, int(c)\n";
+    assert_eq!(run(source), ["Color.Green true 5"]);
+}
+
+#[test]
+fn the_verifier_checks_each_block_on_its_own() {
+    for code in ["begin_func", "push_const 7", "end_func"] {
+        let err = compile(&format!("log \"x\"\nasm [ {} ]\n", code)).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("invalid `asm` block: "), "{}: {:#}", code, err);
+    }
+}
+
+#[test]
+fn a_whole_program_can_be_assembled() {
+    let bytecode = assemble_named(".const \"a\" .const \"b\"\npush_const 1\nlog_string\nhalt\n", "prog.hasm").unwrap();
+    assert_eq!(bytecode.debug.as_ref().map(|debug| debug.source.as_str()), Some("prog.hasm"));
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["b"]);
+    // without `halt` it runs off the end
+    let err = assemble_named("push_int 1\n", "prog.hasm").unwrap_err();
+    assert!(format!("{:#}", err).starts_with("invalid program in prog.hasm: "), "{:#}", err);
+}
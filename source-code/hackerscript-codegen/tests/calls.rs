@@ -0,0 +1,157 @@
+use hackerscript_bytecode::{verify, Opcode};
+use hackerscript_codegen::compiler::compile_named;
+use hackerscript_vm::{BufferHost, VM};
+
+fn run(source: &str) -> (Vec<String>, anyhow::Result<()>) {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let bytecode = compile_named(&program, "calls.hcs").unwrap();
+    verify(&bytecode).unwrap();
+    let mut host = BufferHost::default();
+    let result = VM::new().run(&bytecode, &mut host);
+    (host.lines, result)
+}
+
+#[test]
+fn functions_get_their_own_locals() {
+    let source = "\
+let x = \"global\"
+func fib(n) [
+    if n < 2 [
+        return n
+    ]
+    let x = fib(n - 1) + fib(n - 2)
+    return x
+]
+log fib(15), x
+func shadow(str) [
+    return str(str)   @ a local that is not a function does not hide the native
+]
+log shadow(1) + \"!\"
+";
+    let (lines, result) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["610 global", "1!"]);
+}
+
+#[test]
+fn lambdas_capture_a_copy_of_the_locals() {
+    let source = "\
+func make(start) [
+    let total = start
+    let add = func (n = 1, ...more) [
+        let total = total + n
+        log more
+        return total
+    ]
+    let total = 100
+    return add
+]
+let add = make(10)
+log add(), add(5, 0, 0), add(5)
+";
+    let (lines, result) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["[]", "[0, 0]", "[]", "11 15 15"]);
+}
+
+#[test]
+fn return_computes_its_value_before_the_finally_blocks() {
+    let source = "\
+func loud(x) [
+    log \"computing\"
+    return x
+]
+func f() [
+    try [
+        try [
+            throw \"inner\"
+        ] finally [
+            return loud(1)
+        ]
+    ] finally [
+        log \"finally\"
+    ]
+]
+log f()
+";
+    let (lines, result) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["computing", "finally", "1"]);
+}
+
+#[test]
+fn calls_fail_the_way_the_interpreter_does() {
+    for (source, message) in [
+        ("func f(a, b = 1) [\n]\nf()\n", "line 3: `f` takes 1 to 2 argument(s) but 0 were given"),
+        ("func f(a, ...rest) [\n]\nf()\n", "line 3: `f` takes at least 1 argument(s) but 0 were given"),
+        ("func f() [\n    return f()\n]\nf()\n", "line 2: Call stack overflow in `f` (more than 256 nested calls)"),
+        ("missing(1)\n", "line 1: Undefined function `missing`"),
+    ] {
+        let (_, result) = run(source);
+        assert_eq!(format!("{:#}", result.unwrap_err()), message, "{}", source);
+    }
+}
+
+#[test]
+fn unresolved_imports_are_an_error() {
+    let program = hackerscript_parser::parse("if true [\n    require <lib>\n]\n").unwrap();
+    let error = compile_named(&program, "calls.hcs").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: imports must be at the top level and resolved before compiling");
+}
+
+#[test]
+fn conditionals_evaluate_only_the_chosen_value() {
+    let source = "\
+func sign(n) [
+    return n < 0 ? \"-\" : n == 0 ? \"0\" : \"+\"
+]
+log sign(-5), sign(0), sign(7)
+let picked = 1 ? \"left\" : undefined_name
+log picked, (0 ? 1 : 2) * 10
+";
+    let (lines, result) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["- 0 +", "left 20"]);
+}
+
+#[test]
+fn signal_builtins_take_a_function_value() {
+    let ops = |source: &str| {
+        let program = hackerscript_parser::parse(source).expect("test source should parse");
+        hackerscript_codegen::check::ensure(&program).unwrap();
+        let bytecode = compile_named(&program, "calls.hcs").unwrap();
+        bytecode.instructions().map(|(_, op)| op).collect::<Vec<_>>()
+    };
+    let source = "\
+func stop() [
+    log \"stopping\"
+]
+let done = on_signal(\"TERM\", stop)
+on_signal(\"INT\", func () [ log \"interrupted\" ])
+";
+    let lowered = ops(source);
+    assert_eq!(lowered.iter().filter(|op| **op == Opcode::OnSignal).count(), 2);
+    assert!(!lowered.contains(&Opcode::Call));
+    // a function of the script's own hides the builtin
+    let shadowed = ops("func send_signal(pid, signal) [\n]\nsend_signal(1, \"TERM\")\n");
+    assert!(!shadowed.contains(&Opcode::SendSignal) && shadowed.contains(&Opcode::Call));
+
+    let program = hackerscript_parser::parse("on_signal(\"TERM\")\n").unwrap();
+    let error = compile_named(&program, "calls.hcs").unwrap_err();
+    assert_eq!(error.to_string(), "`on_signal` takes 2 arguments but 1 were given");
+}
+
+#[test]
+fn manual_functions_can_free_their_variables() {
+    let body = "    let data = \"rows\"\n    free(data)\n    free(total)\n    return data\n";
+    let source = format!("let total = 6\nfunc hot() manual [\n{}]\nlog hot(), total\n", body);
+    let program = hackerscript_parser::parse(&source).expect("test source should parse");
+    hackerscript_codegen::check::ensure(&program).unwrap();
+    let (lines, result) = run(&source);
+    result.unwrap();
+    assert_eq!(lines, ["null null"]);
+    // without the annotation the function runs in the file's auto mode
+    let auto = hackerscript_parser::parse(&source.replace(" manual [", " [")).unwrap();
+    let error = hackerscript_codegen::check::ensure(&auto).unwrap_err();
+    assert!(error.to_string().contains("`free` needs manual memory mode in `hot`"), "{}", error);
+}
@@ -0,0 +1,343 @@
+use hackerscript_codegen::check::{self, CheckError};
+
+fn errors(source: &str) -> Vec<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    check::check(&program).iter().map(CheckError::to_string).collect()
+}
+
+#[test]
+fn reports_names_nothing_binds() {
+    let source = "log greeting\nfunc f(a) [\n    return a + b\n]\nlet g = func () [ return nope(1) ]\n";
+    assert_eq!(
+        errors(source),
+        [
+            "undefined variable `greeting` at the top level",
+            "undefined variable `b` in `f`",
+            "undefined function `nope` in a lambda at the top level",
+        ]
+    );
+    // each name once per scope
+    assert_eq!(errors("log x, x\nlog x\n").len(), 1);
+}
+
+#[test]
+fn follows_the_interpreters_scopes() {
+    let source = "\
+func main() [
+    let total = later + count()
+    if total [
+        let inner = 1
+    ]
+    log inner
+    let add = func (x, step = total) [ return x + step + inner ]
+    try [
+        helper()
+    ] except err [
+        log err.message
+    ]
+    log str(add(1)), Color.Red
+]
+func count() [
+    return 1
+]
+enum Color [ Red ]
+let later = 2
+asm [
+    push_int 1
+    store_var from_asm   @ named by the assembler
+]
+log from_asm, main
+func declare() [
+    func helper() [
+    ]
+]
+";
+    assert_eq!(errors(source), Vec::<String>::new());
+}
+
+#[test]
+fn functions_do_not_see_each_others_locals() {
+    let source = "func a() [\n    let secret = 1\n]\nfunc b() [\n    return secret\n]\nlog secret\n";
+    assert_eq!(errors(source), ["undefined variable `secret` in `b`", "undefined variable `secret` at the top level"]);
+    // natives can be called but are not values
+    assert_eq!(errors("log int(\"1\")\nlet f = int\n"), ["undefined variable `int` at the top level"]);
+}
+
+#[test]
+fn reports_functions_and_objects_defined_twice() {
+    let source = "func f() [\n]\nobject f [\n]\nfunc g() [\n    func g() [\n    ]\n]\nfunc f() [\n]\n";
+    assert_eq!(errors(source), ["object `f` is defined more than once", "function `g` is defined more than once"]);
+    // each object has its own methods
+    let methods = "object A [\n    func f() [\n    ]\n    func f() [\n    ]\n]\nobject B [\n    func f() [\n    ]\n]\n";
+    assert_eq!(errors(methods), ["method `A.f` is defined more than once"]);
+}
+
+#[test]
+fn reports_enums_and_variants_defined_twice() {
+    let source = "enum Color [ Red, Green, Red ]\nenum Color [ Blue ]\n";
+    assert_eq!(
+        errors(source),
+        ["variant `Color.Red` is defined more than once", "enum `Color` is defined more than once"]
+    );
+}
+
+#[test]
+fn objects_define_the_methods_of_their_interfaces() {
+    let source = "\
+interface Printable [
+    func print()
+    func width(columns)
+]
+object Base [
+    func print() [
+        log \"base\"
+    ]
+]
+object Doc extends Base implements Printable [
+    func width() [
+        return 80
+    ]
+]
+object Page implements Printable, Missing [
+]
+";
+    assert_eq!(
+        errors(source),
+        [
+            "`Doc.width` takes 0 parameter(s), but interface `Printable` declares 1",
+            "object `Page` does not define `print`, required by interface `Printable`",
+            "object `Page` does not define `width`, required by interface `Printable`",
+            "object `Page` implements `Missing`, which is not an interface",
+        ]
+    );
+    let complete = "interface I [\n    func f(a)\n]\nobject A extends B implements I [\n]\nobject B [\n    func f(x) [\n    ]\n]\n";
+    assert_eq!(errors(complete), Vec::<String>::new());
+}
+
+#[test]
+fn objects_only_extend_known_objects() {
+    let source = "object A extends Missing [\n]\nobject B extends A [\n]\nobject C extends Printable [\n]\ninterface Printable [\n    func print()\n]\n";
+    assert_eq!(
+        errors(source),
+        [
+            "object `A` extends `Missing`, which is not an object",
+            "object `C` extends `Printable`, which is not an object",
+        ]
+    );
+}
+
+#[test]
+fn objects_do_not_extend_themselves() {
+    let source = "\
+interface I [
+    func f()
+]
+object Leaf extends B implements I [
+]
+object A extends B [
+]
+object B extends C [
+]
+object C extends A [
+]
+object Self extends Self [
+]
+";
+    assert_eq!(
+        errors(source),
+        [
+            "objects extend each other in a cycle: `A` extends `B` extends `C` extends `A`",
+            "objects extend each other in a cycle: `Self` extends `Self`"
+        ]
+    );
+}
+
+#[test]
+fn methods_and_fields_resolve_through_the_parent_chain() {
+    let source = "\
+interface Shape [
+    func area()
+    func name()
+    func sides()
+]
+object Base [
+    func area() [
+        return 0
+    ]
+    func name() [
+        return \"base\"
+    ]
+    func sides() [
+        return 0
+    ]
+]
+object Polygon extends Base [
+    let sides = 3
+]
+object Triangle extends Polygon implements Shape [
+    func name() [
+        return \"triangle\"
+    ]
+]
+object Square extends Triangle implements Shape [
+    func sides() [
+        return 4
+    ]
+    let area = 16
+]
+";
+    assert_eq!(
+        errors(source),
+        [
+            "`Triangle.sides` is a field, but interface `Shape` declares a method",
+            "`Square.area` is a field, but interface `Shape` declares a method",
+        ]
+    );
+}
+
+#[test]
+fn hints_at_matches_that_miss_variants() {
+    let hints = |source: &str| {
+        let program = hackerscript_parser::parse(source).expect("test source should parse");
+        check::hints(&program).iter().map(ToString::to_string).collect::<Vec<_>>()
+    };
+    let source = "\
+enum Color [ Red, Green, Blue ]
+func show(c) [
+    match c [
+        case Color.Red [ log 1 ]
+    ]
+]
+match Color.Red [
+    case Color.Red [ log 1 ]
+    case Color.Green [ log 2 ]
+    case Color.Blue [ log 3 ]
+]
+";
+    assert_eq!(hints(source), ["line 3: `match` has no case for `Color.Green`, `Color.Blue`; add them or a `default`"]);
+    // a `default` or a case that is not a variant covers the rest
+    assert!(hints("enum E [ A, B ]\nmatch E.A [\n    case E.A [ log 1 ]\n    default [ log 2 ]\n]\n").is_empty());
+    assert!(hints("enum E [ A, B ]\nmatch E.A [\n    case E.A [ log 1 ]\n    case 3 [ log 2 ]\n]\n").is_empty());
+}
+
+#[test]
+fn unexpanded_imports_may_bind_anything() {
+    assert_eq!(errors("import <app.utils>\nlog from_utils\n"), Vec::<String>::new());
+    assert_eq!(errors("require <lib>\nfunc f() [\n]\nfunc f() [\n]\n"), ["function `f` is defined more than once"]);
+}
+
+#[test]
+fn constants_are_not_bound_again_in_their_scope() {
+    let source = "\
+const LIMIT = 3
+if LIMIT [
+    let LIMIT = 4
+]
+const LIMIT = 5
+func f() [
+    const step = 1
+    let step = 2
+    let LIMIT = 6
+    return step + LIMIT
+]
+let g = func () [
+    let LIMIT = 7
+    return LIMIT
+]
+";
+    assert_eq!(
+        errors(source),
+        ["cannot assign to constant `LIMIT` at the top level", "cannot assign to constant `step` in `f`",]
+    );
+    // a `let` before the `const` is not an assignment to it
+    assert_eq!(errors("let x = 1\nconst x = 2\nlog x\n"), Vec::<String>::new());
+}
+
+#[test]
+fn except_and_asm_cannot_rebind_a_const() {
+    for rebind in ["try [\n    throw 2\n] except config [\n]\n", "asm [\n    push_int 3\n    store_var config\n]\n"] {
+        let source = format!("const config = 1\n{}", rebind);
+        assert_eq!(errors(&source), ["cannot assign to constant `config` at the top level"], "{}", rebind);
+    }
+}
+
+#[test]
+fn destructuring_binds_every_name_in_the_pattern() {
+    let source = "func f(...xs) [\n    let [a, ...rest] = xs\n    return a + rest[0]\n]\nlet {x, y} = { \"x\": 1 }\nlog x, y, f(1, 2)\n";
+    assert_eq!(errors(source), Vec::<String>::new());
+    assert_eq!(errors("const x = 1\nlet {x, y} = {}\n"), ["cannot assign to constant `x` at the top level"]);
+}
+
+#[test]
+fn match_patterns_bind_their_names() {
+    let source = "func f(v) [\n    match v [\n        case [a, ...rest] [\n            return a + rest[0]\n        ]\n        case {host} [\n            return host\n        ]\n    ]\n]\nlog f(1)\n";
+    assert_eq!(errors(source), Vec::<String>::new());
+    assert_eq!(
+        errors("const host = 1\nmatch {} [\n    case {host} [\n    ]\n]\n"),
+        ["cannot assign to constant `host` at the top level"]
+    );
+}
+
+fn streamed(source: &str) -> Vec<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let mut checker = check::StreamChecker::new();
+    checker.set_memory_mode(program.memory_mode);
+    program.body.iter().for_each(|stmt| checker.stmt(stmt));
+    checker.finish().iter().map(CheckError::to_string).collect()
+}
+
+#[test]
+fn streaming_finds_the_same_name_errors() {
+    let source = "\
+log later(1)
+func later(x) [
+    return x + missing
+]
+const limit = 3
+let limit = 4
+func later(y) [ return y ]
+log nope
+";
+    let expected = [
+        "undefined variable `missing` in `later`",
+        "cannot assign to constant `limit` at the top level",
+        "function `later` is defined more than once",
+        "undefined variable `nope` at the top level",
+    ];
+    assert_eq!(streamed(source), expected);
+    let mut whole = errors(source);
+    whole.sort();
+    let mut expected = expected.map(str::to_string).to_vec();
+    expected.sort();
+    assert_eq!(whole, expected);
+}
+
+#[test]
+fn free_needs_manual_memory_mode() {
+    let body = "    let buffer = str(42)\n    free(buffer)\n    return func () [ free(buffer) ]\n";
+    assert_eq!(errors(&format!("func hot() manual [\n{}]\n", body)), Vec::<String>::new());
+    assert_eq!(
+        errors(&format!("func hot() [\n{}]\n", body)),
+        ["`free` needs manual memory mode in `hot`", "`free` needs manual memory mode in a lambda in `hot`"]
+    );
+    // a function may also opt out of the file's mode
+    let source = "--- manual ---\nlet cache = {}\nfree(cache)\nfunc f() automatic [\n    free(cache)\n]\n";
+    assert_eq!(errors(source), ["`free` needs manual memory mode in `f`"]);
+    assert_eq!(streamed(source), ["`free` needs manual memory mode in `f`"]);
+    assert_eq!(errors("--- manual ---\nfree(1)\n"), ["`free` takes one variable at the top level"]);
+    // a script's own `free` is an ordinary function
+    assert_eq!(errors("func free(x) [\n]\nfree(1)\n"), Vec::<String>::new());
+}
+
+#[test]
+fn object_names_are_not_values() {
+    let source = "object Point [\n    func norm() [\n    ]\n]\nlog methods(Point)\nfunc f() [\n    return Point()\n]\n";
+    let expected = [
+        "object `Point` has no value at run time, but is used at the top level",
+        "object `Point` has no value at run time, but is used in `f`",
+    ];
+    assert_eq!(errors(source), expected);
+    assert_eq!(streamed(source), expected);
+    // a map of the same name is a value
+    assert_eq!(errors("object Point [\n]\nlet Point = {}\nlog methods(Point)\n"), Vec::<String>::new());
+}
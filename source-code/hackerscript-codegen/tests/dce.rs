@@ -0,0 +1,118 @@
+use hackerscript_ast::{Expr, Lit, Program, Spanned, Stmt};
+use hackerscript_codegen::opt::{self, Eliminated};
+
+fn optimize(source: &str) -> (Program, Vec<Eliminated>) {
+    let mut program = hackerscript_parser::parse(source).expect("test source should parse");
+    // without inlining, which would remove the calls these tests keep functions alive with
+    opt::fold_constants(&mut program);
+    let eliminated = opt::eliminate_dead_code(&mut program);
+    (program, eliminated)
+}
+
+fn func_names(program: &Program) -> Vec<&str> {
+    program
+        .body
+        .iter()
+        .filter_map(|stmt| match &stmt.node {
+            Stmt::Func(func) => Some(func.name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn removes_unreferenced_functions_transitively() {
+    let source = "func a() [\n    b()\n]\nfunc b() [\n    log 1\n]\nfunc orphan() [\n    c()\n]\nfunc c() [\n    log 2\n]\npub func api() [\n    log 3\n]\na()\n";
+    let (program, eliminated) = optimize(source);
+    assert_eq!(func_names(&program), vec!["a", "b", "api"]);
+    assert!(eliminated.contains(&Eliminated::Function("orphan".into())));
+    assert!(eliminated.contains(&Eliminated::Function("c".into())));
+}
+
+#[test]
+fn keeps_main() {
+    let (program, eliminated) = optimize("func main() [\n    log 1\n]\n");
+    assert_eq!(func_names(&program), vec!["main"]);
+    assert!(eliminated.is_empty());
+}
+
+#[test]
+fn drops_statements_after_return() {
+    let source = "pub func f() [\n    if 1 > 0 [\n        return 1\n    ] else [\n        return 2\n    ]\n    log \"dead\"\n    log \"dead\"\n]\n";
+    let (program, eliminated) = optimize(source);
+    let Stmt::Func(func) = &program.body[0].node else { panic!("expected a function") };
+    assert_eq!(func.body, vec![Stmt::Return { value: Some(Expr::Lit(Lit::Int(1))) }]);
+    assert_eq!(
+        eliminated,
+        vec![
+            Eliminated::Branch { scope: "func `f`".into(), taken: true },
+            Eliminated::Unreachable { scope: "func `f`".into(), count: 2 },
+        ]
+    );
+}
+
+#[test]
+fn drops_loops_that_never_run_and_keeps_called_functions_in_loops() {
+    let source = "func step() [\n    log 1\n]\nwhile 1 > 2 [\n    log \"never\"\n]\nlet i = 0\nwhile i < 3 [\n    step()\n    let i = i + 1\n]\n";
+    let (program, eliminated) = optimize(source);
+    assert_eq!(func_names(&program), vec!["step"]);
+    assert_eq!(eliminated, vec![Eliminated::Loop { scope: "top level".into() }]);
+    assert!(matches!(program.body.last().map(|stmt| &stmt.node), Some(Stmt::While { .. })));
+}
+
+#[test]
+fn drops_statements_after_break_and_continue() {
+    let source = "while 1 [\n    if 1 > 2 [\n        log 0\n    ] else [\n        continue\n    ]\n    log \"dead\"\n]\nwhile 1 [\n    break\n    log \"dead\"\n]\n";
+    let (program, eliminated) = optimize(source);
+    let bodies: Vec<&[Spanned<Stmt>]> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::While { body, .. } => body.as_slice(),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(bodies, [&[Stmt::Continue][..], &[Stmt::Break][..]]);
+    assert_eq!(
+        eliminated,
+        vec![
+            Eliminated::Branch { scope: "top level".into(), taken: false },
+            Eliminated::Unreachable { scope: "top level".into(), count: 1 },
+            Eliminated::Unreachable { scope: "top level".into(), count: 1 },
+        ]
+    );
+}
+
+#[test]
+fn calls_in_parameter_defaults_keep_functions_alive() {
+    let (program, eliminated) = optimize("func fallback() [\n    return 1\n]\nfunc f(x = fallback()) [\n    log x\n]\nf()\n");
+    assert_eq!(func_names(&program), vec!["fallback", "f"]);
+    assert!(eliminated.is_empty());
+}
+
+#[test]
+fn functions_used_as_values_or_in_lambdas_stay_alive() {
+    let source = "func inc(x) [\n    return x + 1\n]\nfunc helper() [\n    return 2\n]\nfunc unused() [\n]\nlet f = inc\nlet g = func () [ return helper() ]\n";
+    let (program, eliminated) = optimize(source);
+    assert_eq!(func_names(&program), vec!["inc", "helper"]);
+    assert_eq!(eliminated, vec![Eliminated::Function("unused".to_string())]);
+}
+
+#[test]
+fn drops_dead_code_in_lambdas_and_warns_about_it() {
+    let source = "let f = func () [\n    return 1\n    log \"dead\"\n]\nfunc unused() [\n]\n";
+    let (program, eliminated) = optimize(source);
+    let [Spanned { node: Stmt::Let { value: Expr::Lambda { body, .. }, .. }, .. }] = &program.body[..] else {
+        panic!("unexpected {:?}", program.body)
+    };
+    assert_eq!(body.len(), 1);
+    assert_eq!(
+        eliminated,
+        vec![
+            Eliminated::Unreachable { scope: "a lambda in top level".into(), count: 1 },
+            Eliminated::Function("unused".into()),
+        ]
+    );
+    let warnings: Vec<bool> = eliminated.iter().map(Eliminated::is_warning).collect();
+    assert_eq!(warnings, [true, false]);
+}
@@ -0,0 +1,95 @@
+use hackerscript_bytecode::{verify, Bytecode, Opcode};
+use hackerscript_codegen::compiler::compile_named;
+use hackerscript_vm::{BufferHost, VM};
+
+fn compile(source: &str) -> Bytecode {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let bytecode = compile_named(&program, "dispatch.hcs").unwrap();
+    verify(&bytecode).unwrap();
+    bytecode
+}
+
+fn run(bytecode: &Bytecode) -> Vec<String> {
+    let mut host = BufferHost::default();
+    VM::new().run(bytecode, &mut host).unwrap();
+    host.lines
+}
+
+fn ops(bytecode: &Bytecode) -> Vec<Opcode> {
+    bytecode.instructions().map(|(_, op)| op).collect()
+}
+
+/// `func name(x)` over `body`, then a `log name(arg)` per argument.
+fn calls(name: &str, body: &str, args: &[&str]) -> String {
+    let logs: String = args.iter().map(|arg| format!("log {}({})\n", name, arg)).collect();
+    format!("func {}(x) [\n{}]\n{}", name, body, logs)
+}
+
+const INTS: &str = "    match x [
+        case 3 [ return \"three\" ]
+        case 1 [ return \"one\" ]
+        case 2 [ return \"two\" ]
+        case 1 [ return \"again\" ]
+        case 5 [ return \"five\" ]
+    ]
+    return \"other\"
+";
+
+#[test]
+fn dense_int_cases_use_a_jump_table() {
+    let bytecode = compile(&calls("name", INTS, &["1", "2", "3", "4", "5", "6", "0", "2.0", "2.5", "\"1\"", "true"]));
+    assert!(ops(&bytecode).contains(&Opcode::JumpTable));
+    assert!(!ops(&bytecode).contains(&Opcode::Eq));
+    // a float matches the int it equals, as `==` would, and the first equal case wins
+    assert_eq!(
+        run(&bytecode),
+        ["one", "two", "three", "other", "five", "other", "other", "two", "other", "other", "other"]
+    );
+}
+
+#[test]
+fn string_cases_use_a_sorted_table() {
+    let body = "    match x [
+        case \"put\" [ return 3 ]
+        case \"get\" [ return 1 ]
+        case \"post\" [ return 2 ]
+        case \"delete\" [ return 4 ]
+    ]
+    return 0
+";
+    let bytecode = compile(&calls("method", body, &["\"get\"", "\"post\"", "\"put\"", "\"delete\"", "\"patch\"", "\"\"", "1"]));
+    assert!(ops(&bytecode).contains(&Opcode::JumpString));
+    assert_eq!(run(&bytecode), ["1", "2", "3", "4", "0", "0", "0"]);
+}
+
+#[test]
+fn if_chains_on_one_variable_dispatch_too() {
+    let body = "    if x == 10 [
+        return \"ten\"
+    ] else if 11 == x [
+        return \"eleven\"
+    ] else if x == 12 [
+        return \"twelve\"
+    ] else if x == 13 [
+        return \"thirteen\"
+    ] else if x > 0 [
+        return \"positive\"
+    ] else [
+        return \"other\"
+    ]
+";
+    let bytecode = compile(&calls("name", body, &["10", "11", "12", "13", "14", "-1"]));
+    assert!(ops(&bytecode).contains(&Opcode::JumpTable));
+    assert_eq!(run(&bytecode), ["ten", "eleven", "twelve", "thirteen", "positive", "other"]);
+}
+
+#[test]
+fn sparse_mixed_or_few_cases_compare_one_by_one() {
+    for cases in ["1, 100, 1000, 10000", "1, 2, 3, \"4\"", "1, 2, 3", "1, 2, 3, 9007199254740993"] {
+        let body: String = cases.split(", ").map(|value| format!("        case {} [ return 1 ]\n", value)).collect();
+        let bytecode = compile(&calls("f", &format!("    match x [\n{}    ]\n    return 0\n", body), &["1"]));
+        let ops = ops(&bytecode);
+        assert!(!ops.contains(&Opcode::JumpTable) && !ops.contains(&Opcode::JumpString), "{}", cases);
+        assert_eq!(run(&bytecode), ["1"], "{}", cases);
+    }
+}
@@ -0,0 +1,119 @@
+use hackerscript_ast::{Expr, Lit, Program, Stmt};
+use hackerscript_bytecode::{Bytecode, Opcode};
+use hackerscript_codegen::{compiler, opt};
+
+fn parse(source: &str) -> Program {
+    hackerscript_parser::parse(source).expect("test source should parse")
+}
+
+/// Bytecode with and without the optimisation passes.
+fn both(source: &str) -> (Bytecode, Bytecode) {
+    let program = parse(source);
+    let plain = compiler::compile(&program).unwrap();
+    let mut optimized = program;
+    opt::optimize(&mut optimized);
+    (plain, compiler::compile(&optimized).unwrap())
+}
+
+fn count(bytecode: &Bytecode) -> usize {
+    bytecode.instructions().count()
+}
+
+#[test]
+fn folds_integer_arithmetic() {
+    let mut program = parse("log 2 + 3 * 4 - 10 / 5 % 3\n");
+    opt::optimize(&mut program);
+    assert_eq!(program.body, vec![Stmt::Log { level: None, values: vec![Expr::Lit(Lit::Int(12))] }]);
+
+    let (plain, optimized) = both("log 2 + 3 * 4 - 10 / 5 % 3\n");
+    assert_eq!(count(&optimized), 3); // push_int, log_string, halt
+    assert!(count(&optimized) < count(&plain));
+}
+
+#[test]
+fn folds_string_concatenation_into_one_constant() {
+    let (plain, optimized) = both("log \"a\" + \"b\" + 1 + \"c\"\n");
+    assert_eq!(plain.constants, vec!["a", "b", "c"]);
+    assert_eq!(optimized.constants, vec!["ab1c"]);
+    assert!(count(&optimized) < count(&plain));
+}
+
+#[test]
+fn folds_mixed_int_float() {
+    let mut program = parse("log 1 + 0.5\n");
+    opt::optimize(&mut program);
+    assert_eq!(program.body, vec![Stmt::Log { level: None, values: vec![Expr::Lit(Lit::Float(1.5))] }]);
+}
+
+#[test]
+fn drops_branch_with_constant_condition() {
+    let source = "if 1 < 2 [\n    log \"yes\"\n] else [\n    log \"no\"\n]\nif \"\" [\n    log \"empty\"\n]\n";
+    let (plain, optimized) = both(source);
+    assert_eq!(optimized.constants, vec!["yes"]);
+    assert!(!optimized.instructions().any(|(_, op)| matches!(op, Opcode::Jump | Opcode::JumpIfFalse)));
+    assert!(plain.constants.len() > optimized.constants.len());
+    assert!(count(&optimized) < count(&plain));
+}
+
+#[test]
+fn picks_the_branch_of_a_constant_conditional() {
+    let mut program = parse("log 2 > 1 ? \"yes\" : missing()\nlog x ? 1 + 1 : 0\n");
+    opt::optimize(&mut program);
+    assert_eq!(program.body[0], Stmt::Log { level: None, values: vec![Expr::Lit(Lit::Str("yes".into()))] });
+    let Stmt::Log { values, .. } = &program.body[1].node else { panic!("unexpected {:?}", program.body[1]) };
+    assert!(matches!(&values[0], Expr::Ternary { then_value, .. } if **then_value == Expr::Lit(Lit::Int(2))));
+}
+
+#[test]
+fn keeps_runtime_conditions_and_variables() {
+    let source = "let x = 1 + 1\nif x == 2 [\n    log \"two\"\n]\n";
+    let mut program = parse(source);
+    opt::optimize(&mut program);
+    assert_eq!(program.body[0], Stmt::Let { name: "x".into(), ty: None, value: Expr::Lit(Lit::Int(2)) });
+    assert!(matches!(program.body[1].node, Stmt::If { .. }));
+}
+
+#[test]
+fn leaves_runtime_errors_unfolded() {
+    for source in ["log 1 / 0\n", "log 1 % 0\n", "log 1 + \"a\"\n"] {
+        let mut program = parse(source);
+        opt::optimize(&mut program);
+        assert!(matches!(&program.body[0].node, Stmt::Log { values, .. } if matches!(values[..], [Expr::Binary { .. }])), "{}", source);
+    }
+}
+
+#[test]
+fn folds_inside_function_bodies() {
+    let mut program = parse("func f() [\n    log 6 * 7\n]\n");
+    opt::fold_constants(&mut program);
+    let Stmt::Func(func) = &program.body[0].node else { panic!("expected a function") };
+    assert_eq!(func.body, vec![Stmt::Log { level: None, values: vec![Expr::Lit(Lit::Int(42))] }]);
+}
+
+#[test]
+fn folds_comparisons_to_booleans() {
+    let mut program = parse("log 1 < 2, \"a\" == \"b\", true == true, \"ok: \" + (2 >= 3)\n");
+    opt::optimize(&mut program);
+    let values = vec![
+        Expr::Lit(Lit::Bool(true)),
+        Expr::Lit(Lit::Bool(false)),
+        Expr::Lit(Lit::Bool(true)),
+        Expr::Lit(Lit::Str("ok: false".into())),
+    ];
+    assert_eq!(program.body, vec![Stmt::Log { level: None, values }]);
+
+    // ordering booleans is a runtime error, so it stays
+    let mut program = parse("log true < false\n");
+    opt::optimize(&mut program);
+    assert!(matches!(&program.body[0].node, Stmt::Log { values, .. } if matches!(values[..], [Expr::Binary { .. }])));
+}
+
+#[test]
+fn folds_logic_with_a_known_left_side() {
+    let mut program = parse("log 0 || \"x\", 1 && 2, null && f(), 3 || f(), f() && 0\n");
+    opt::optimize(&mut program);
+    let Stmt::Log { values, .. } = &program.body[0].node else { panic!("expected a log") };
+    assert_eq!(values[..4], [Expr::Lit(Lit::Str("x".into())), Expr::Lit(Lit::Int(2)), Expr::Lit(Lit::Null), Expr::Lit(Lit::Int(3))]);
+    // the call still has to run
+    assert!(matches!(values[4], Expr::Binary { .. }));
+}
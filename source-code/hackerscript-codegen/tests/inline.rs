@@ -0,0 +1,134 @@
+use hackerscript_ast::{BinOp, Expr, Lit, Program, Stmt};
+use hackerscript_codegen::compiler::compile;
+use hackerscript_codegen::inline::{inline_functions, DEFAULT_BUDGET};
+use hackerscript_codegen::opt::{self, Eliminated};
+use hackerscript_vm::{BufferHost, VM};
+
+fn parse(source: &str) -> Program {
+    hackerscript_parser::parse(source).expect("test source should parse")
+}
+
+fn run(program: &Program) -> Vec<String> {
+    let mut host = BufferHost::default();
+    VM::new().run(&compile(program).unwrap(), &mut host).unwrap();
+    host.lines
+}
+
+/// Which functions had calls inlined, and how many.
+fn inlined(eliminated: &[Eliminated]) -> Vec<(&str, usize)> {
+    eliminated
+        .iter()
+        .filter_map(|item| match item {
+            Eliminated::Inlined { name, calls } => Some((name.as_str(), *calls)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn inlined_programs_print_the_same() {
+    let source = "\
+let base = 10
+func square(x) [
+    return x * x
+]
+func offset(x) [
+    return square(x) + base
+]
+func greet(name) [
+    return \"hi \" + name
+]
+func main() [
+    let n = 3
+    log offset(n), square(n), greet(\"bob\")
+    let add = func (k) [ return square(k) ]
+    log add(4)
+]
+log square(5), offset(2)
+main()
+";
+    let plain = run(&parse(source));
+    let mut program = parse(source);
+    let eliminated = opt::optimize(&mut program);
+    assert_eq!(run(&program), plain);
+    assert_eq!(inlined(&eliminated), [("square", 4), ("offset", 2), ("greet", 1)]);
+    // `log square(5), offset(2)` folds as far as the global allows
+    let values = program.body.iter().rev().find_map(|stmt| match &stmt.node {
+        Stmt::Log { values, .. } => Some(values),
+        _ => None,
+    });
+    let offset = Expr::binary(BinOp::Add, Expr::Lit(Lit::Int(4)), Expr::Var { name: "base".into() });
+    assert_eq!(values.unwrap(), &[Expr::Lit(Lit::Int(25)), offset]);
+    assert!(eliminated.contains(&Eliminated::Function("square".into())));
+}
+
+#[test]
+fn recursive_functions_are_left_alone() {
+    let source = "\
+func fact(n) [
+    return n < 2 && 1 || n * fact(n - 1)
+]
+func even(n) [
+    return n == 0 || odd(n - 1)
+]
+func odd(n) [
+    return n != 0 && even(n - 1)
+]
+log fact(5), even(4)
+";
+    let mut program = parse(source);
+    assert!(inline_functions(&mut program, usize::MAX).is_empty());
+    assert_eq!(program, parse(source));
+}
+
+#[test]
+fn the_budget_limits_what_is_inlined_unless_marked() {
+    let source = "\
+func big(a, b) [
+    return a * b + a * b + a * b + a * b
+]
+func tagged(a, b) inline [
+    return a * b + a * b + a * b + a * b
+]
+func small(a) [
+    return a + 1
+]
+log big(1, 2), tagged(1, 2), small(1)
+";
+    let mut program = parse(source);
+    assert_eq!(inlined(&inline_functions(&mut program, DEFAULT_BUDGET)), [("tagged", 1), ("small", 1)]);
+    let mut program = parse(source);
+    assert_eq!(inlined(&inline_functions(&mut program, 0)), [("tagged", 1)]);
+    let mut program = parse(source);
+    assert_eq!(inlined(&inline_functions(&mut program, 100)).len(), 3);
+}
+
+#[test]
+fn only_expands_calls_it_can_prove_equivalent() {
+    let source = "\
+let scale = 2
+func times(x) [
+    return x * scale
+]
+func twice(x) [
+    return times(x) + times(x)
+]
+func ignore(x) [
+    return 1
+]
+func f(scale) [
+    return times(scale)
+]
+func g(times) [
+    return times(1)
+]
+let k = 3
+log times(k), times(read()), twice(k), ignore(k), times(1, 2)
+";
+    let mut program = parse(source);
+    let eliminated = inline_functions(&mut program, DEFAULT_BUDGET);
+    // `f`'s parameter hides the global `times` reads and `g`'s hides the
+    // function, a call is not passed as an argument, an unused variable is
+    // still read, and a wrong argument count still fails at run time
+    assert_eq!(inlined(&eliminated), [("times", 3), ("twice", 1)]);
+}
@@ -0,0 +1,45 @@
+use hackerscript_bytecode::verify;
+use hackerscript_codegen::compiler::compile_named;
+use hackerscript_vm::{BufferHost, VM};
+
+fn run(source: &str) -> Vec<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let bytecode = compile_named(&program, "loops.hcs").unwrap();
+    verify(&bytecode).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    host.lines
+}
+
+#[test]
+fn break_and_continue_jump_to_the_innermost_loop() {
+    let source = "let i = 0\nwhile i < 3 [\n    let i = i + 1\n    let j = 0\n    while 1 [\n        let j = j + 1\n        if j == 2 [\n            continue\n        ]\n        match j [\n            case 4 [\n                break\n            ]\n        ]\n        log i, j\n    ]\n    if i == 2 [\n        break\n    ]\n]\nlog \"done\", i\n";
+    assert_eq!(run(source), ["1 1", "1 3", "2 1", "2 3", "done 2"]);
+}
+
+#[test]
+fn break_and_continue_outside_a_loop_do_not_compile() {
+    for (source, message) in [
+        ("break\n", "`break` outside a loop"),
+        ("if 1 [\n    continue\n]\n", "`continue` outside a loop"),
+        ("while 1 [\n    func f() [\n        break\n    ]\n    break\n]\n", "`break` outside a loop"),
+    ] {
+        let program = hackerscript_parser::parse(source).unwrap();
+        let err = compile_named(&program, "loops.hcs").unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", source);
+    }
+}
+
+#[test]
+fn jumps_out_of_a_try_run_its_finally_and_leave_the_stack_clean() {
+    let source = "let i = 0\nwhile 1 [\n    let i = i + 1\n    try [\n        try [\n            if i == 3 [\n                break\n            ]\n            throw i\n        ] finally [\n            log \"inner\", i\n            if i == 2 [\n                continue\n            ]\n        ]\n    ] except err [\n        log \"caught\", err.message\n    ] finally [\n        log \"outer\", i\n    ]\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let bytecode = compile_named(&program, "loops.hcs").unwrap();
+    verify(&bytecode).unwrap();
+    let mut host = BufferHost::default();
+    let mut vm = VM::new();
+    vm.run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["inner 1", "caught 1", "outer 1", "inner 2", "outer 2", "inner 3", "outer 3"]);
+    // the error the inner `finally` dropped with its `continue` is gone too
+    assert_eq!(vm.result(), None);
+}
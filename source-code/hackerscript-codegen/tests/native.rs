@@ -0,0 +1,123 @@
+#![cfg(feature = "native")]
+
+use hackerscript_codegen::native::{self, NativeCompiler};
+use hackerscript_vm::{BufferHost, VM};
+
+fn header(source: &str) -> anyhow::Result<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let mut compiler = NativeCompiler::new("api")?;
+    compiler.compile_program(&program)?;
+    Ok(compiler.c_header("HS_API_H"))
+}
+
+#[test]
+fn the_header_declares_every_export_in_order() {
+    let source = "\
+pub const LIMIT = 3
+pub enum Mode [ Off, On ]
+pub object Point [
+    let x: Int = 0
+    let label: String = \"\"
+    func describe() [
+    ]
+]
+object Private [
+    let hidden = 1
+]
+pub func reset(force: Bool) [
+]
+";
+    let header = header(source).unwrap();
+    let body = header.split("extern \"C\" {\n#endif\n").nth(1).unwrap().split("\n#ifdef __cplusplus").next().unwrap();
+    assert_eq!(
+        body,
+        "
+extern const int64_t LIMIT;
+
+typedef int64_t Mode;
+extern const Mode Mode_Off; /* 0 */
+extern const Mode Mode_On; /* 1 */
+
+typedef struct Point {
+    int64_t x;
+    const char *label;
+} Point;
+
+void reset(bool force);
+"
+    );
+}
+
+#[test]
+fn exported_objects_need_typed_fields() {
+    for (source, message) in [
+        ("pub object P [\n    let x = 1\n]\n", "field `x` of `pub object P` needs a type"),
+        ("pub object P [\n    let x: Array = 1\n]\n", "type `Array` cannot cross the C ABI"),
+        ("pub object P [\n]\n", "`pub object P` has no typed `let` fields to export"),
+        ("pub object P extends Q [\n    let x: Int = 1\n]\n", "`pub object P` cannot extend another object"),
+    ] {
+        let err = header(source).unwrap_err();
+        assert!(err.to_string().contains(message), "{}: {}", source, err);
+    }
+}
+
+#[test]
+fn exported_functions_compile_the_functions_they_call() {
+    let source = "\
+func square(x) [
+    return x * x
+]
+pub func area(side: Int): Int [
+    return square(side)
+]
+";
+    let header = header(source).unwrap();
+    assert!(header.contains("int64_t area(int64_t side);"), "{}", header);
+    assert!(!header.contains("square"), "{}", header);
+}
+
+#[test]
+fn code_the_backend_cannot_lower_is_rejected() {
+    for (source, message) in [
+        ("pub func f() [\n    log null\n]\n", "`null`"),
+        ("pub func f() [\n    let x = 1\n    let x = \"one\"\n]\n", "changing `x` from int to string"),
+        (
+            "func g(n) [\n    if n [\n        return 1\n    ]\n]\npub func f() [\n    g(1)\n]\n",
+            "reaching the end of `g`",
+        ),
+        ("func g(n) [\n]\npub func f() [\n    g(1)\n    g(\"x\")\n]\n", "passing a string to `g`'s int parameter `n`"),
+    ] {
+        let err = format!("{:#}", header(source).unwrap_err());
+        assert!(err.contains(message), "{}: {}", source, err);
+        assert!(err.contains("is not supported by the native backend"), "{}: {}", source, err);
+    }
+}
+
+#[test]
+fn floats_and_joined_strings_print_as_the_vm_prints_them() {
+    let source = "\
+func greet(name) [
+    return \"hello \" + name
+]
+log 0.1 + 0.2, 1.0 / 3, 10.0 / 4 * 4, -2.5
+log 100000000000000000000.0 * 10, 0.000001 / 8
+log \"n = \" + 5 + \", x = \" + 0.5 + \", \" + true
+log greet(\"world\")
+";
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let bytecode = hackerscript_codegen::compiler::compile(&program).unwrap();
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    let expected: String = host.lines.iter().map(|line| format!("{}\n", line)).collect();
+
+    let dir = std::env::temp_dir().join(format!("hs-native-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (object, exe) = (dir.join("main.o"), dir.join("main"));
+    let mut compiler = NativeCompiler::new("main").unwrap();
+    compiler.compile_main(&program).unwrap();
+    std::fs::write(&object, compiler.finish().unwrap()).unwrap();
+    native::link_executable(&object, &exe).unwrap();
+    let output = std::process::Command::new(&exe).output().unwrap();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
@@ -0,0 +1,305 @@
+//! parse → compile → encode → decode → disassemble, on generated programs.
+use hackerscript_ast::{BinOp, Case, Except, Expr, Func, Lit, LogLevel, MemoryMode, Param, Program, Spanned, Stmt};
+use hackerscript_bytecode::{disassemble, verify, Bytecode};
+use hackerscript_parser::escape;
+use proptest::prelude::*;
+
+const KEYWORDS: &[&str] = &[
+    "let", "const", "enum", "if", "else", "while", "match", "case", "default", "func", "pub", "object", "return", "log", "import", "require", "null",
+    "true", "false", "break", "continue", "asm", "try", "except", "finally", "throw",
+];
+
+fn identifier() -> impl Strategy<Value = String> {
+    "[a-z_][a-z0-9_]{0,5}".prop_filter("keyword", |s| !KEYWORDS.contains(&s.as_str()))
+}
+
+fn lit() -> impl Strategy<Value = Lit> {
+    prop_oneof![
+        Just(Lit::Null),
+        any::<bool>().prop_map(Lit::Bool),
+        (0..=i64::MAX).prop_map(Lit::Int),
+        (0..4000u32).prop_map(|n| Lit::Float(f64::from(n) / 4.0)),
+        "[a-z \"\\\\\n\t{}]{0,6}".prop_map(Lit::Str),
+    ]
+}
+
+fn binop() -> impl Strategy<Value = BinOp> {
+    use BinOp::*;
+    prop::sample::select(vec![Add, Sub, Mul, Div, Rem, Eq, Ne, Lt, Le, Gt, Ge, And, Or])
+}
+
+fn expr() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![lit().prop_map(Expr::Lit), identifier().prop_map(|name| Expr::Var { name: name.into() })];
+    leaf.prop_recursive(4, 16, 2, |inner| {
+        prop_oneof![
+            4 => (binop(), inner.clone(), inner.clone()).prop_map(|(op, lhs, rhs)| Expr::binary(op, lhs, rhs)),
+            1 => prop::collection::vec(("[a-z]{0,3}", inner.clone()), 0..3).prop_map(|entries| Expr::Map { entries }),
+            1 => (inner.clone(), inner.clone()).prop_map(|(target, index)| Expr::Index {
+                target: Box::new(target),
+                index: Box::new(index),
+            }),
+            1 => (inner.clone(), inner.clone(), inner.clone()).prop_map(|(cond, then_value, else_value)| Expr::Ternary {
+                cond: Box::new(cond),
+                then_value: Box::new(then_value),
+                else_value: Box::new(else_value),
+            }),
+            1 => (inner.clone(), prop::option::of(inner.clone()), prop::option::of(inner)).prop_map(|(target, start, end)| {
+                Expr::Slice { target: Box::new(target), start: start.map(Box::new), end: end.map(Box::new) }
+            }),
+        ]
+    })
+}
+
+fn stmt(in_func: bool) -> impl Strategy<Value = Stmt> {
+    let simple = prop_oneof![
+        (identifier(), prop::option::of(identifier()), expr())
+            .prop_map(|(name, ty, value)| Stmt::Let { name: name.into(), ty: ty.map(Into::into), value }),
+        (
+            prop::option::of(prop::sample::select(vec![LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error])),
+            prop::collection::vec(expr(), 1..3),
+        )
+            .prop_map(|(level, values)| Stmt::Log { level, values }),
+        expr().prop_map(|value| Stmt::Expr { value }),
+        expr().prop_map(|value| Stmt::Throw { value }),
+        prop::collection::vec("[a-z]{1,4}( \\[ [a-z]{1,3} \\])?", 1..3).prop_map(|commands| Stmt::Sh { commands }),
+        if in_func {
+            prop::option::of(expr()).prop_map(|value| Stmt::Return { value }).boxed()
+        } else {
+            Just(Stmt::Return { value: None }).boxed()
+        },
+    ];
+    simple.prop_recursive(2, 12, 3, |inner| {
+        let body = prop::collection::vec(inner.prop_map(Spanned::from), 0..3);
+        prop_oneof![
+            (expr(), body.clone(), body.clone())
+                .prop_map(|(cond, then_body, else_body)| Stmt::If { cond, then_body, else_body }),
+            (expr(), body.clone()).prop_map(|(cond, body)| Stmt::While { cond, body }),
+            (expr(), prop::collection::vec((expr(), body.clone()), 0..3), body.clone()).prop_map(|(subject, cases, default)| {
+                let cases = cases.into_iter().map(|(value, body)| Case::value(value, body)).collect();
+                Stmt::Match { subject, cases, default }
+            }),
+            // an empty `finally` is the same as none, so it needs an `except`
+            (body.clone(), prop::option::of((prop::option::of(identifier()), body.clone())), body)
+                .prop_filter("try needs except or finally", |(_, except, finally)| except.is_some() || !finally.is_empty())
+                .prop_map(|(body, except, finally)| {
+                    let except = except.map(|(name, body)| Except { name: name.map(Into::into), body });
+                    Stmt::Try { body, except, finally }
+                }),
+        ]
+    })
+}
+
+fn memory_mode() -> impl Strategy<Value = Option<MemoryMode>> {
+    prop::option::of(prop_oneof![Just(MemoryMode::Auto), Just(MemoryMode::Manual)])
+}
+
+fn func() -> impl Strategy<Value = Stmt> {
+    let param = |default, rest| {
+        (identifier(), prop::option::of(identifier()), default).prop_map(move |(name, ty, default)| Param {
+            name: name.into(),
+            ty: ty.map(Into::into),
+            default,
+            rest,
+        })
+    };
+    // parameters with a default come after the others, then `...rest`
+    let params = (
+        prop::collection::vec(param(Just(None).boxed(), false), 0..3),
+        prop::collection::vec(param(expr().prop_map(Some).boxed(), false), 0..2),
+        prop::option::of(param(Just(None).boxed(), true)),
+    )
+        .prop_map(|(mut params, defaulted, rest)| {
+            params.extend(defaulted);
+            params.extend(rest);
+            params
+        });
+    (
+        identifier(),
+        params,
+        prop::option::of(identifier()),
+        any::<bool>(),
+        memory_mode(),
+        prop::collection::vec(stmt(true).prop_map(Spanned::from), 0..3),
+    )
+        .prop_map(|(name, params, ret, inline, memory_mode, body)| {
+            let ret = ret.map(Into::into);
+            Stmt::Func(Func { public: false, name: name.into(), params, ret, inline, memory_mode, body })
+        })
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    let item = prop_oneof![4 => stmt(false), 1 => func()].prop_map(Spanned::from);
+    (memory_mode(), any::<bool>(), prop::collection::vec(item, 0..6)).prop_map(|(memory_mode, strict, body)| Program {
+        edition: None,
+        memory_mode,
+        strict,
+        body,
+    })
+}
+
+fn render(program: &Program) -> String {
+    let mut out = String::new();
+    match program.memory_mode {
+        Some(MemoryMode::Auto) => out.push_str("--- auto ---\n"),
+        Some(MemoryMode::Manual) => out.push_str("--- manual ---\n"),
+        None => {}
+    }
+    if program.strict {
+        out.push_str("--- strict ---\n");
+    }
+    for stmt in &program.body {
+        render_stmt(stmt, &mut out);
+    }
+    out
+}
+
+fn render_block(body: &[Spanned<Stmt>], out: &mut String) {
+    out.push_str("[\n");
+    for stmt in body {
+        render_stmt(stmt, out);
+    }
+    out.push(']');
+}
+
+fn render_stmt(stmt: &Stmt, out: &mut String) {
+    match stmt {
+        Stmt::Let { name, ty, value } => {
+            let ty = ty.as_ref().map(|ty| format!(": {}", ty)).unwrap_or_default();
+            out.push_str(&format!("let {}{} = {}", name, ty, render_expr(value)))
+        }
+        Stmt::Log { level, values } => {
+            let values: Vec<String> = values.iter().map(render_expr).collect();
+            let level = level.map(|level| format!(".{}", level.name())).unwrap_or_default();
+            out.push_str(&format!("log{} {}", level, values.join(", ")));
+        }
+        Stmt::Expr { value } => out.push_str(&render_expr(value)),
+        Stmt::Throw { value } => out.push_str(&format!("throw {}", render_expr(value))),
+        Stmt::Sh { commands } => out.push_str(&format!("sh [\n{}\n]", commands.join("\n"))),
+        Stmt::Return { value: None } => out.push_str("return"),
+        Stmt::Return { value: Some(value) } => out.push_str(&format!("return {}", render_expr(value))),
+        Stmt::If { cond, then_body, else_body } => {
+            out.push_str(&format!("if {} ", render_expr(cond)));
+            render_block(then_body, out);
+            if !else_body.is_empty() {
+                out.push_str(" else ");
+                render_block(else_body, out);
+            }
+        }
+        Stmt::While { cond, body } => {
+            out.push_str(&format!("while {} ", render_expr(cond)));
+            render_block(body, out);
+        }
+        Stmt::Match { subject, cases, default } => {
+            out.push_str(&format!("match {} [\n", render_expr(subject)));
+            for case in cases {
+                out.push_str(&format!("case {} ", render_expr(&case.value)));
+                render_block(&case.body, out);
+                out.push('\n');
+            }
+            if !default.is_empty() {
+                out.push_str("default ");
+                render_block(default, out);
+                out.push('\n');
+            }
+            out.push(']');
+        }
+        Stmt::Try { body, except, finally } => {
+            out.push_str("try ");
+            render_block(body, out);
+            if let Some(except) = except {
+                out.push_str(" except ");
+                if let Some(name) = &except.name {
+                    out.push_str(&format!("{} ", name));
+                }
+                render_block(&except.body, out);
+            }
+            if !finally.is_empty() {
+                out.push_str(" finally ");
+                render_block(finally, out);
+            }
+        }
+        Stmt::Func(func) => {
+            let params: Vec<String> = func
+                .params
+                .iter()
+                .map(|p| {
+                    let mut param = format!("{}{}", if p.rest { "..." } else { "" }, p.name);
+                    if let Some(ty) = &p.ty {
+                        param.push_str(&format!(": {}", ty));
+                    }
+                    if let Some(default) = &p.default {
+                        param.push_str(&format!(" = {}", render_expr(default)));
+                    }
+                    param
+                })
+                .collect();
+            out.push_str(&format!("func {}({})", func.name, params.join(", ")));
+            if let Some(ret) = &func.ret {
+                out.push_str(&format!(": {}", ret));
+            }
+            if func.inline {
+                out.push_str(" inline");
+            }
+            match func.memory_mode {
+                Some(MemoryMode::Auto) => out.push_str(" auto"),
+                Some(MemoryMode::Manual) => out.push_str(" manual"),
+                None => {}
+            }
+            out.push(' ');
+            render_block(&func.body, out);
+        }
+        other => unreachable!("not generated: {:?}", other),
+    }
+    out.push('\n');
+}
+
+fn render_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Lit(Lit::Null) => "null".to_string(),
+        Expr::Lit(Lit::Bool(b)) => b.to_string(),
+        Expr::Lit(Lit::Int(n)) => n.to_string(),
+        Expr::Lit(Lit::Float(x)) => format!("{:?}", x),
+        Expr::Lit(Lit::Str(s)) => format!("\"{}\"", escape(s)),
+        Expr::Var { name } => name.to_string(),
+        Expr::Binary { op, lhs, rhs } => format!("({} {} {})", render_expr(lhs), op.symbol(), render_expr(rhs)),
+        Expr::Map { entries } => {
+            let entries: Vec<String> =
+                entries.iter().map(|(key, value)| format!("\"{}\": {}", key, render_expr(value))).collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Expr::Index { target, index } => format!("({})[{}]", render_expr(target), render_expr(index)),
+        Expr::Slice { target, start, end } => {
+            let bound = |bound: &Option<Box<Expr>>| bound.as_deref().map(render_expr).unwrap_or_default();
+            format!("({})[{}:{}]", render_expr(target), bound(start), bound(end))
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            format!("({} ? {} : {})", render_expr(cond), render_expr(then_value), render_expr(else_value))
+        }
+        other => unreachable!("not generated: {:?}", other),
+    }
+}
+
+proptest! {
+    #[test]
+    fn rendered_programs_parse_back(program in program()) {
+        let source = render(&program);
+        let parsed = hackerscript_parser::parse(&source).map_err(|e| TestCaseError::fail(format!("{}\n{}", e, source)))?;
+        prop_assert_eq!(parsed, program);
+    }
+
+    #[test]
+    fn compiled_programs_verify_and_survive_encoding(program in program()) {
+        let source = render(&program);
+        let parsed = hackerscript_parser::parse(&source).unwrap();
+        let bytecode = hackerscript_codegen::compiler::compile_named(&parsed, "prop.hcs").unwrap();
+        verify(&bytecode).map_err(|e| TestCaseError::fail(format!("{}\n{}", e, disassemble(&bytecode))))?;
+        let listing = disassemble(&bytecode);
+        let decoded = Bytecode::from_bytes(&bytecode.to_bytes()).unwrap();
+        verify(&decoded).unwrap();
+        prop_assert_eq!(disassemble(&decoded), listing);
+
+        // compiling the same source again is deterministic
+        let again = hackerscript_codegen::compiler::compile_named(&hackerscript_parser::parse(&source).unwrap(), "prop.hcs").unwrap();
+        prop_assert_eq!(again.code, bytecode.code);
+    }
+}
@@ -0,0 +1,117 @@
+use hackerscript_codegen::check::{self, CheckError};
+use hackerscript_codegen::strict;
+
+fn strict(source: &str) -> Vec<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    strict::check(&program).iter().map(CheckError::to_string).collect()
+}
+
+#[test]
+fn parameters_and_return_values_need_types() {
+    let source = "\
+func f(a, b: int, c = 1, ...rest) [
+    return b
+]
+func g(x: int) -> int [
+    return x
+]
+func h(x: int) [
+    log x
+]
+let twice = func (n) [ return n * 2 ]
+";
+    assert_eq!(
+        strict(source),
+        [
+            "line 1: parameter `a` has no type in `f` (strict mode)",
+            "line 1: the return value has no declared type in `f` (strict mode)",
+            "line 10: parameter `n` has no type in a lambda at the top level (strict mode)",
+            "line 10: the return value has no declared type in a lambda at the top level (strict mode)",
+        ]
+    );
+}
+
+#[test]
+fn variables_are_declared_before_they_are_read() {
+    let source = "\
+log total
+let total = 1
+if total [
+    let inner = 2
+]
+log inner, total, later(), Color.Red
+func later() -> int [
+    return total + late
+]
+enum Color [ Red ]
+let late = 3
+";
+    assert_eq!(
+        strict(source),
+        [
+            "line 1: `total` is read before it is declared, or outside the block that declares it at the top level (strict mode)",
+            "line 6: `inner` is read before it is declared, or outside the block that declares it at the top level (strict mode)",
+        ]
+    );
+    // a `case` pattern binds for its own body
+    let source = "func f(v: any) [\n    match v [\n        case {host} [\n            log host\n        ]\n    ]\n    log host\n]\n";
+    assert_eq!(
+        strict(source),
+        ["line 7: `host` is read before it is declared, or outside the block that declares it in `f` (strict mode)"]
+    );
+    // a name nothing binds is `check`'s error, or comes from an import
+    assert_eq!(strict("log nope\n"), Vec::<String>::new());
+    let program = hackerscript_parser::parse("log nope\n").unwrap();
+    assert_eq!(check::check(&program).len(), 1);
+}
+
+#[test]
+fn functions_do_not_shadow_globals_or_captured_variables() {
+    let source = "\
+let count = 0
+func bump(step: int) [
+    let count = step
+    let fresh = 1
+    let fresh = fresh + 1
+    let add = func (fresh: int) -> int [ return fresh ]
+]
+func main(bump: int) [
+]
+";
+    assert_eq!(
+        strict(source),
+        [
+            "line 3: `count` shadows a global in `bump` (strict mode)",
+            "line 6: `fresh` shadows a variable of the enclosing function in a lambda in `bump` (strict mode)",
+            "line 8: `bump` shadows a global in `main` (strict mode)",
+        ]
+    );
+}
+
+#[test]
+fn functions_that_return_a_value_do_so_on_every_path() {
+    let source = "\
+func sign(x: int) -> int [
+    if x < 0 [
+        return -1
+    ] else if x > 0 [
+        return 1
+    ]
+]
+func pick(x: int) -> string [
+    match x [
+        case 1 [ return \"one\" ]
+        default [ throw \"no\" ]
+    ]
+]
+func forever() -> int [
+    while true [
+        log 1
+    ]
+]
+func done(x: int) -> void [
+    log x
+]
+";
+    assert_eq!(strict(source), ["line 1: the end can be reached without returning a value in `sign` (strict mode)"]);
+}
@@ -0,0 +1,125 @@
+use hackerscript_codegen::check::{self, CheckError};
+use hackerscript_codegen::types::{self, Type};
+
+fn errors(source: &str) -> Vec<String> {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    types::check(&program).iter().map(CheckError::to_string).collect()
+}
+
+#[test]
+fn reports_operations_that_always_fail() {
+    let source = "\
+let n = 1
+let s = \"a\"
+log n + s, s + n, n - \"x\", {} + 1, n * 2.5
+log n[0], s[\"k\"], s[0], {}[1], s[n:\"2\"], n[1:]
+log n < s, null > 1, s < \"b\", n == s
+";
+    assert_eq!(
+        errors(source),
+        [
+            "line 3: cannot add int and string at the top level",
+            "line 3: cannot apply `-` to int and string at the top level",
+            "line 3: cannot add map and int at the top level",
+            "line 4: cannot index int at the top level",
+            "line 4: cannot index string with string at the top level",
+            "line 4: cannot index map with int at the top level",
+            "line 4: slice bounds must be ints, found string at the top level",
+            "line 4: cannot slice int at the top level",
+            "line 5: cannot compare int and string at the top level",
+            "line 5: cannot compare null and int at the top level",
+        ]
+    );
+}
+
+#[test]
+fn checks_annotations_on_bindings_calls_and_returns() {
+    let source = "\
+let x: number = 5
+let x = \"five\"
+const NAME: string = 1
+func f(a: string, b: Int = 2.5) -> number [
+    return a
+]
+log f(1), f(\"a\") + 1, f(\"a\") + {}
+let g = func (...rest: int) -> bool [ return rest ]
+";
+    assert_eq!(
+        errors(source),
+        [
+            "line 2: expected number for `x`, found string at the top level",
+            "line 3: expected string for `NAME`, found int at the top level",
+            "line 4: expected int for `b`, found float in `f`",
+            "line 5: expected number for the return value, found string in `f`",
+            "line 7: expected string for argument `a` of `f`, found int at the top level",
+            "line 7: cannot add number and map at the top level",
+            "line 8: expected bool for the return value, found array in a lambda at the top level",
+        ]
+    );
+    assert_eq!(Type::named("STRING"), Type::Str);
+    // an object's name, or any other the checker does not know, takes anything
+    assert_eq!(errors("let p: Point = 1\nlog p + 1\n"), Vec::<String>::new());
+}
+
+#[test]
+fn follows_control_flow() {
+    let source = "\
+func main(flag) [
+    let v = 1
+    if flag [
+        let v = \"one\"
+    ]
+    log v - 1
+    let w = 1
+    while flag [
+        log w - 1
+        let w = {}
+    ]
+    let n = 1
+    try [
+        let n = \"n\"
+        risky()
+    ] except [
+        log n - 1
+    ]
+    let d = 1
+    match flag [
+        case 1 [
+            return
+        ]
+        default [
+            let d = \"d\"
+        ]
+    ]
+    log d - 1
+]
+";
+    assert_eq!(
+        errors(source),
+        ["line 28: cannot apply `-` to string and int in `main`"],
+        "only `d`, which is a string on every path that reaches it"
+    );
+}
+
+#[test]
+fn unknown_values_pass() {
+    let source = "\
+let total = 1
+func f(a) [
+    log a + 1, total + {}, a[\"k\"], int(a) + 1
+    let add = func (b) [ return b - a ]
+    return add(a)
+]
+let int = func (x) [ return x ]
+log int(1) + {}, sh(\"ls\")[\"stdout\"]
+";
+    assert_eq!(errors(source), Vec::<String>::new());
+    assert_eq!(errors("log str(1) + 1, int(\"1\") + {}\n"), ["line 1: cannot add int and map at the top level"]);
+}
+
+#[test]
+fn check_reports_type_errors_after_name_errors() {
+    let program = hackerscript_parser::parse("log missing\nlog 1 + {}\n").unwrap();
+    let errors: Vec<String> = check::check(&program).iter().map(CheckError::to_string).collect();
+    assert_eq!(errors, ["undefined variable `missing` at the top level", "line 2: cannot add int and map at the top level"]);
+}
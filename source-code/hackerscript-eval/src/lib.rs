@@ -0,0 +1,562 @@
+//! Tree-walking interpreter: runs a `Program` straight from the AST.
+//!
+//! This is the reference semantics for HackerScript. It is deliberately
+//! simple, and the bytecode VM and native code are tested against it (see
+//! the golden suite in `HS1/tests`). Operators go through
+//! `hackerscript_vm::vm::binary_with`, so arithmetic and comparisons are shared
+//! with the VM by construction; statements and calls are not.
+//!
+//! Top-level `let`s are globals. Inside a function, parameters and `let`s
+//! are locals and reads fall back to globals. Top-level functions and enums
+//! can be used before their definition. An enum is a global map from each
+//! variant's name to a `Value::Variant`.
+//!
+//! Functions are values: a top-level function's name and a `func (...) [ ]`
+//! lambda both evaluate to a `Value::Func`. A lambda captures a copy of the
+//! locals of the call it is created in; globals are read when it runs.
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, HashMap};
+
+use hackerscript_ast::{
+    BinOp, Case, Enum, Except, Expr, Func, Lit, LogLevel, Param, Pattern, Program, Spanned, Stmt, Symbol, FREE,
+};
+use hackerscript_vm::{exception, natives, Exception, Function, Host, Logger, Math, Opcode, Value, Variant, ARGS};
+
+/// Calls nested deeper than this fail instead of overflowing the Rust stack.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    globals: HashMap<String, Value>,
+    functions: HashMap<String, Function>,
+    /// One map of locals per active call
+    frames: Vec<HashMap<String, Value>>,
+    logger: Logger,
+    math: Math,
+    /// Line of the statement being run, if the parser recorded one. An
+    /// error leaves it at the statement that failed.
+    line: Option<u32>,
+    /// Each active call's function and the line it was called from; a
+    /// failed call stays until its error is caught, for the error's `stack`
+    calls: Vec<(String, Option<u32>)>,
+}
+
+/// What the interpreter keeps in a `Function`'s body.
+struct Closure {
+    func: Func,
+    /// Locals of the frame the function was created in
+    captured: HashMap<String, Value>,
+}
+
+/// How a statement finished.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_global(ARGS, Value::Array(Vec::new()));
+        interpreter
+    }
+
+    /// A top-level variable, after `run`.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    /// Set a top-level variable before `run`, as `VM::set_global`.
+    pub fn set_global(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.globals.insert(name.into(), value.into());
+    }
+
+    /// Where `log.info` and the other leveled logs go, as `VM::set_logger`.
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// Whether arithmetic wraps or fails on overflow and NaN, as `VM::set_math`.
+    pub fn set_math(&mut self, math: Math) {
+        self.math = math;
+    }
+
+    pub fn run(&mut self, program: &Program, host: &mut dyn Host) -> Result<()> {
+        // rejected before anything runs, as the compiler does
+        if let Some(jump) = loose_jump(&program.body, false) {
+            bail!("`{}` outside a loop", jump);
+        }
+        self.calls.clear();
+        for stmt in &program.body {
+            match &stmt.node {
+                Stmt::Func(func) => self.define(func),
+                Stmt::Enum(decl) => self.define_enum(decl),
+                _ => {}
+            }
+        }
+        match self.block(&program.body, host) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(match self.line {
+                Some(line) => err.context(format!("line {}", line)),
+                None => err,
+            }),
+        }
+    }
+
+    fn define(&mut self, func: &Func) {
+        let closure = Closure { func: func.clone(), captured: HashMap::new() };
+        self.functions.insert(func.name.to_string(), Function::new(func.name.as_str(), closure));
+    }
+
+    /// An enum is a global map from each variant's name to the variant.
+    fn define_enum(&mut self, decl: &Enum) {
+        let variants = decl
+            .discriminants()
+            .map(|(name, value)| {
+                (name.to_string(), Value::Variant(Variant::new(decl.name.as_str(), name.as_str(), value)))
+            })
+            .collect();
+        self.globals.insert(decl.name.to_string(), Value::Map(variants));
+    }
+
+    fn block(&mut self, body: &[Spanned<Stmt>], host: &mut dyn Host) -> Result<Flow> {
+        let outer = self.line;
+        for stmt in body {
+            self.line = (!stmt.span.is_unknown()).then_some(stmt.span.line);
+            match self.stmt(stmt, host)? {
+                Flow::Next => {}
+                jump => {
+                    self.line = outer;
+                    return Ok(jump);
+                }
+            }
+        }
+        self.line = outer;
+        Ok(Flow::Next)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, host: &mut dyn Host) -> Result<Flow> {
+        match stmt {
+            Stmt::Log { level, values } => self.log(*level, values, host)?,
+            Stmt::Let { name, value, .. } | Stmt::Const { name, value, .. } => {
+                let value = self.expr(value, host)?;
+                self.assign(name, value);
+            }
+            Stmt::Destructure { pattern, value } => self.destructure(pattern, value, host)?,
+            Stmt::If { cond, then_body, else_body } => {
+                let body = if self.expr(cond, host)?.is_truthy() { then_body } else { else_body };
+                return self.block(body, host);
+            }
+            Stmt::While { cond, body } => {
+                while self.expr(cond, host)?.is_truthy() {
+                    match self.block(body, host)? {
+                        Flow::Next | Flow::Continue => {}
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                    }
+                }
+            }
+            Stmt::Match { subject, cases, default } => return self.match_stmt(subject, cases, default, host),
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+            Stmt::Return { value } => {
+                let value = match value {
+                    Some(value) => self.expr(value, host)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Expr { value } => {
+                self.expr(value, host)?;
+            }
+            Stmt::Try { body, except, finally } => return self.try_stmt(body, except.as_ref(), finally, host),
+            Stmt::Throw { value } => {
+                let value = self.expr(value, host)?;
+                return Err(Exception::thrown(value).into());
+            }
+            Stmt::Sh { commands } => self.sh(commands, host)?,
+            Stmt::Func(func) => self.define(func),
+            Stmt::Asm { .. } => bail!("`asm` is not supported by the interpreter"),
+            Stmt::Enum(decl) => self.define_enum(decl),
+            // only the checker reads interfaces
+            Stmt::Interface(_) => {}
+            Stmt::Import { .. } | Stmt::ImportModule { .. } | Stmt::Require { .. } | Stmt::Object { .. } => {
+                log::warn!("Unhandled statement: {:?}", stmt);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    // `log` and `try` are kept out of `stmt` so that each level of a deep
+    // recursion takes less of the Rust stack.
+    fn log(&mut self, level: Option<LogLevel>, values: &[Expr], host: &mut dyn Host) -> Result<()> {
+        let values = values.iter().map(|value| self.expr(value, host)).collect::<Result<Vec<_>>>()?;
+        let line = hackerscript_vm::vm::log_line(&values);
+        match level {
+            None => host.log(&line),
+            Some(level) => self.logger.log(host, runtime_level(level), &line)?,
+        }
+        Ok(())
+    }
+
+    fn match_stmt(
+        &mut self,
+        subject: &Expr,
+        cases: &[Case],
+        default: &[Spanned<Stmt>],
+        host: &mut dyn Host,
+    ) -> Result<Flow> {
+        let subject = self.expr(subject, host)?;
+        for case in cases {
+            if let Some(pattern) = &case.pattern {
+                if fits(pattern, &subject) {
+                    self.unpack(pattern, &subject)?;
+                    return self.block(&case.body, host);
+                }
+                continue;
+            }
+            let value = self.expr(&case.value, host)?;
+            if hackerscript_vm::vm::binary(Opcode::Eq, subject.clone(), value)?.is_truthy() {
+                return self.block(&case.body, host);
+            }
+        }
+        self.block(default, host)
+    }
+
+    fn try_stmt(
+        &mut self,
+        body: &[Spanned<Stmt>],
+        except: Option<&Except>,
+        finally: &[Spanned<Stmt>],
+        host: &mut dyn Host,
+    ) -> Result<Flow> {
+        let depth = self.calls.len();
+        let mut outcome = self.block(body, host);
+        if let (Err(err), Some(except)) = (&outcome, except) {
+            let error = Exception::caught(err, self.trace());
+            self.calls.truncate(depth);
+            if let Some(name) = &except.name {
+                self.assign(name, error);
+            }
+            outcome = self.block(&except.body, host);
+        }
+        // a jump or error in `finally` replaces whatever was leaving
+        match self.block(finally, host)? {
+            Flow::Next => outcome,
+            jump => Ok(jump),
+        }
+    }
+
+    /// Where the statement that failed is, innermost call first, as the VM
+    /// gives it.
+    fn trace(&self) -> Vec<String> {
+        let mut line = self.line;
+        let mut trace = Vec::new();
+        for (function, caller) in self.calls.iter().rev() {
+            trace.push(exception::location(Some(function), line));
+            line = *caller;
+        }
+        trace.push(exception::location(None, line));
+        trace
+    }
+
+    /// An `sh [ ... ]` block: its lines as one script.
+    fn sh(&mut self, commands: &[String], host: &mut dyn Host) -> Result<()> {
+        if commands.is_empty() {
+            return Ok(());
+        }
+        let script = commands.join("\n");
+        let output = host.sh(&script, None)?;
+        hackerscript_vm::process::finish_block(&script, output, host, &mut self.logger)
+    }
+
+    /// Bind the names of `pattern` to the parts of `value` they stand for.
+    fn destructure(&mut self, pattern: &Pattern, value: &Expr, host: &mut dyn Host) -> Result<()> {
+        let value = self.expr(value, host)?;
+        self.unpack(pattern, &value)
+    }
+
+    fn unpack(&mut self, pattern: &Pattern, value: &Value) -> Result<()> {
+        match pattern {
+            Pattern::Array { names, rest } => {
+                for (i, name) in names.iter().enumerate() {
+                    let item = hackerscript_vm::vm::index(value, &Value::Int(i as i64))?;
+                    self.assign(name, item);
+                }
+                if let Some(rest) = rest {
+                    let start = Value::Int(names.len() as i64);
+                    let items = hackerscript_vm::vm::slice(value, &start, &Value::Null)?;
+                    self.assign(rest, items);
+                }
+            }
+            Pattern::Map { names } => {
+                for name in names {
+                    let entry = hackerscript_vm::vm::index(value, &Value::Str(name.to_string()))?;
+                    self.assign(name, entry);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, name: &str, value: Value) {
+        match self.frames.last_mut() {
+            Some(locals) => locals.insert(name.to_string(), value),
+            None => self.globals.insert(name.to_string(), value),
+        };
+    }
+
+    /// `free(name)`: drop the value of `name` now, leaving it `null`.
+    fn free(&mut self, args: &[Expr]) -> Result<Value> {
+        let [Expr::Var { name }] = args else {
+            bail!("`{}` takes one variable", FREE);
+        };
+        let name = name.as_str();
+        match self.frames.last_mut().and_then(|locals| locals.get_mut(name)) {
+            Some(value) => *value = Value::Null,
+            None => match self.globals.get_mut(name) {
+                Some(value) => *value = Value::Null,
+                None => bail!("Undefined variable `{}`", name),
+            },
+        }
+        Ok(Value::Null)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Value> {
+        self.variable(name)
+            .cloned()
+            .or_else(|| self.functions.get(name).cloned().map(Value::Func))
+            .ok_or_else(|| anyhow::anyhow!("Undefined variable `{}`", name))
+    }
+
+    fn variable(&self, name: &str) -> Option<&Value> {
+        self.frames.last().and_then(|locals| locals.get(name)).or_else(|| self.globals.get(name))
+    }
+
+    fn expr(&mut self, expr: &Expr, host: &mut dyn Host) -> Result<Value> {
+        Ok(match expr {
+            Expr::Lit(Lit::Null) => Value::Null,
+            Expr::Lit(Lit::Bool(b)) => Value::Bool(*b),
+            Expr::Lit(Lit::Int(n)) => Value::Int(*n),
+            Expr::Lit(Lit::Float(x)) => Value::Float(*x),
+            Expr::Lit(Lit::Str(s)) => Value::Str(s.clone()),
+            Expr::Var { name } => self.lookup(name)?,
+            Expr::Binary { op: op @ (BinOp::And | BinOp::Or), lhs, rhs } => {
+                let a = self.expr(lhs, host)?;
+                if a.is_truthy() == (*op == BinOp::Or) {
+                    a
+                } else {
+                    self.expr(rhs, host)?
+                }
+            }
+            Expr::Ternary { cond, then_value, else_value } => {
+                if self.expr(cond, host)?.is_truthy() {
+                    self.expr(then_value, host)?
+                } else {
+                    self.expr(else_value, host)?
+                }
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let a = self.expr(lhs, host)?;
+                let b = self.expr(rhs, host)?;
+                hackerscript_vm::vm::binary_with(self.math, opcode(*op), a, b)?
+            }
+            Expr::Map { entries } => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let value = self.expr(value, host)?;
+                    map.insert(key.clone(), value);
+                }
+                Value::Map(map)
+            }
+            Expr::Index { target, index } => {
+                let target = self.expr(target, host)?;
+                let index = self.expr(index, host)?;
+                hackerscript_vm::vm::index(&target, &index)?
+            }
+            Expr::Slice { target, start, end } => {
+                let target = self.expr(target, host)?;
+                let mut bound = |bound: &Option<Box<Expr>>| match bound {
+                    Some(bound) => self.expr(bound, host),
+                    None => Ok(Value::Null),
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                hackerscript_vm::vm::slice(&target, &start, &end)?
+            }
+            Expr::Call { callee, args }
+                if callee.as_str() == FREE
+                    && self.variable(callee).is_none()
+                    && !self.functions.contains_key(callee.as_str()) =>
+            {
+                self.free(args)?
+            }
+            Expr::Call { callee, args } => {
+                let args = args.iter().map(|arg| self.expr(arg, host)).collect::<Result<Vec<_>>>()?;
+                self.call(callee, args, host)?
+            }
+            Expr::Lambda { params, ret, body } => self.lambda(params, ret, body)?,
+        })
+    }
+
+    fn lambda(&self, params: &[Param], ret: &Option<Symbol>, body: &[Spanned<Stmt>]) -> Result<Value> {
+        if let Some(jump) = loose_jump(body, false) {
+            bail!("`{}` outside a loop", jump);
+        }
+        let func = Func {
+            public: false,
+            name: Symbol::from("lambda"),
+            params: params.to_vec(),
+            ret: ret.clone(),
+            inline: false,
+            memory_mode: None,
+            body: body.to_vec(),
+        };
+        let captured = self.frames.last().cloned().unwrap_or_default();
+        Ok(Value::Func(Function::new("lambda", Closure { func, captured })))
+    }
+
+    /// Call `name`: a variable holding a function, a top-level function or
+    /// a native, in that order.
+    fn call(&mut self, name: &str, args: Vec<Value>, host: &mut dyn Host) -> Result<Value> {
+        let func = match self.variable(name) {
+            Some(Value::Func(func)) => func.clone(),
+            _ => match self.functions.get(name) {
+                Some(func) => func.clone(),
+                None => {
+                    if let Some(native) = natives::higher_order(name) {
+                        return native(&args, &mut |func, args| self.invoke(func.name(), func, args, host));
+                    }
+                    return match natives::id_of(name).and_then(natives::lookup) {
+                        Some((_, native)) => native(host, &args),
+                        None => bail!("Undefined function `{}`", name),
+                    };
+                }
+            },
+        };
+        self.invoke(name, &func, args, host)
+    }
+
+    /// Run `func`, called as `name`, with `args`.
+    fn invoke(&mut self, name: &str, func: &Function, args: Vec<Value>, host: &mut dyn Host) -> Result<Value> {
+        let Some(closure) = func.body::<Closure>() else {
+            bail!("`{}` cannot be called by the interpreter", name);
+        };
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            bail!("Call stack overflow in `{}` (more than {} nested calls)", name, MAX_CALL_DEPTH);
+        }
+        let (locals, passed) = bind(name, closure, args)?;
+        self.frames.push(locals);
+        self.calls.push((closure.func.name.to_string(), self.line));
+        let flow = self.fill_defaults(&closure.func, passed, host).and_then(|()| self.block(&closure.func.body, host));
+        self.frames.pop();
+        let flow = flow?;
+        self.calls.pop();
+        Ok(match flow {
+            Flow::Return(value) => value,
+            // `run` rejects jumps that could leave a function body
+            Flow::Next | Flow::Break | Flow::Continue => Value::Null,
+        })
+    }
+
+    /// Bind the parameters a call left out, evaluating their defaults in the
+    /// callee's frame so they can use the parameters before them.
+    fn fill_defaults(&mut self, func: &Func, passed: usize, host: &mut dyn Host) -> Result<()> {
+        for param in func.params[passed..].iter().filter(|p| !p.rest) {
+            let default = param.default.as_ref().expect("arity was checked against required_params");
+            let value = self.expr(default, host)?;
+            self.assign(&param.name, value);
+        }
+        Ok(())
+    }
+}
+
+/// The frame for a call of `closure` with `args`: what it captured, then
+/// the parameters that were passed, with how many were.
+fn bind(name: &str, closure: &Closure, mut args: Vec<Value>) -> Result<(HashMap<String, Value>, usize)> {
+    let func = &closure.func;
+    let required = func.required_params();
+    let fixed = func.params.len() - usize::from(func.rest_param().is_some());
+    if args.len() < required || (args.len() > fixed && func.rest_param().is_none()) {
+        let expected = if func.rest_param().is_some() {
+            format!("at least {}", required)
+        } else if required == fixed {
+            required.to_string()
+        } else {
+            format!("{} to {}", required, fixed)
+        };
+        bail!("`{}` takes {} argument(s) but {} were given", name, expected, args.len());
+    }
+    let extra = args.split_off(args.len().min(fixed));
+    let passed = args.len();
+    let mut locals = closure.captured.clone();
+    locals.extend(func.params.iter().map(|p| p.name.to_string()).zip(args));
+    if let Some(rest) = func.rest_param() {
+        locals.insert(rest.name.to_string(), Value::Array(extra));
+    }
+    Ok((locals, passed))
+}
+
+/// Whether a `case` pattern matches `value`: an array of its length (at
+/// least, with `...rest`), or a map with all of its keys.
+fn fits(pattern: &Pattern, value: &Value) -> bool {
+    match (pattern, value) {
+        (Pattern::Array { names, rest: None }, Value::Array(items)) => items.len() == names.len(),
+        (Pattern::Array { names, rest: Some(_) }, Value::Array(items)) => items.len() >= names.len(),
+        (Pattern::Map { names }, Value::Map(entries)) => names.iter().all(|name| entries.contains_key(name.as_str())),
+        _ => false,
+    }
+}
+
+fn opcode(op: BinOp) -> Opcode {
+    match op {
+        BinOp::Add => Opcode::Add,
+        BinOp::Sub => Opcode::Sub,
+        BinOp::Mul => Opcode::Mul,
+        BinOp::Div => Opcode::Div,
+        BinOp::Rem => Opcode::Rem,
+        BinOp::Eq => Opcode::Eq,
+        BinOp::Ne => Opcode::Ne,
+        BinOp::Lt => Opcode::Lt,
+        BinOp::Le => Opcode::Le,
+        BinOp::Gt => Opcode::Gt,
+        BinOp::Ge => Opcode::Ge,
+        BinOp::And | BinOp::Or => unreachable!("`{}` short-circuits and is handled by the interpreter", op.symbol()),
+    }
+}
+
+/// The first `break` or `continue` in `body` that is not inside a loop;
+/// function bodies start outside one.
+fn loose_jump(body: &[Spanned<Stmt>], in_loop: bool) -> Option<&'static str> {
+    body.iter().find_map(|stmt| match &stmt.node {
+        Stmt::Break if !in_loop => Some("break"),
+        Stmt::Continue if !in_loop => Some("continue"),
+        Stmt::While { body, .. } => loose_jump(body, true),
+        Stmt::If { then_body, else_body, .. } => loose_jump(then_body, in_loop).or_else(|| loose_jump(else_body, in_loop)),
+        Stmt::Match { cases, default, .. } => cases
+            .iter()
+            .find_map(|case| loose_jump(&case.body, in_loop))
+            .or_else(|| loose_jump(default, in_loop)),
+        Stmt::Try { body, except, finally } => loose_jump(body, in_loop)
+            .or_else(|| except.as_ref().and_then(|except| loose_jump(&except.body, in_loop)))
+            .or_else(|| loose_jump(finally, in_loop)),
+        Stmt::Func(func) => loose_jump(&func.body, false),
+        Stmt::Object { body, .. } => loose_jump(body, false),
+        _ => None,
+    })
+}
+
+/// Run `program` with a fresh interpreter.
+pub fn eval(program: &Program, host: &mut dyn Host) -> Result<()> {
+    Interpreter::new().run(program, host)
+}
+
+fn runtime_level(level: LogLevel) -> hackerscript_vm::LogLevel {
+    match level {
+        LogLevel::Debug => hackerscript_vm::LogLevel::Debug,
+        LogLevel::Info => hackerscript_vm::LogLevel::Info,
+        LogLevel::Warn => hackerscript_vm::LogLevel::Warn,
+        LogLevel::Error => hackerscript_vm::LogLevel::Error,
+    }
+}
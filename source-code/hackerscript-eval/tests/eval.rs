@@ -0,0 +1,190 @@
+use hackerscript_eval::Interpreter;
+use hackerscript_vm::{BufferHost, Math, Value};
+
+fn run(source: &str) -> (anyhow::Result<()>, Vec<String>, Interpreter) {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let mut interpreter = Interpreter::new();
+    let mut host = BufferHost::default();
+    let result = interpreter.run(&program, &mut host);
+    (result, host.lines, interpreter)
+}
+
+#[test]
+fn function_locals_do_not_leak_into_globals() {
+    let source = "let x = 1\nfunc f(x) [\n    let y = x + 1\n    return y\n]\nlet z = f(41)\n";
+    let (result, _, interpreter) = run(source);
+    result.unwrap();
+    assert_eq!(interpreter.global("x"), Some(&Value::Int(1)));
+    assert_eq!(interpreter.global("z"), Some(&Value::Int(42)));
+    assert_eq!(interpreter.global("y"), None);
+}
+
+#[test]
+fn functions_read_globals_and_can_be_called_before_their_definition() {
+    let source = "let base = 10\nlog add(5)\nfunc add(n) [\n    return base + n\n]\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["15"]);
+}
+
+#[test]
+fn top_level_return_stops_the_script() {
+    let (result, lines, _) = run("log 1\nif 1 [\n    return\n]\nlog 2\n");
+    result.unwrap();
+    assert_eq!(lines, ["1"]);
+}
+
+#[test]
+fn call_errors_are_reported() {
+    let (result, ..) = run("func f(a) [\n    return a\n]\nf(1, 2)\n");
+    assert!(format!("{:#}", result.unwrap_err()).contains("takes 1 argument(s) but 2 were given"));
+    let (result, ..) = run("missing()\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 1: Undefined function `missing`");
+    let (result, ..) = run("func forever(n) [\n    return forever(n + 1)\n]\nforever(0)\n");
+    assert!(format!("{:#}", result.unwrap_err()).contains("Call stack overflow"));
+}
+
+#[test]
+fn break_and_continue_outside_a_loop_are_rejected_before_running() {
+    for (source, message) in [
+        ("log 1\nbreak\n", "`break` outside a loop"),
+        ("log 1\nif 1 [\n    continue\n]\n", "`continue` outside a loop"),
+        ("while 1 [\n    func f() [\n        break\n    ]\n    break\n]\n", "`break` outside a loop"),
+    ] {
+        let (result, lines, _) = run(source);
+        assert_eq!(format!("{:#}", result.unwrap_err()), message, "{}", source);
+        assert!(lines.is_empty());
+    }
+}
+
+#[test]
+fn missing_arguments_take_their_defaults() {
+    let source = "let greeting = \"Hello\"\nfunc greet(name, prefix = greeting, line = prefix + \", \" + name) [\n    log line\n]\ngreet(\"Ada\")\ngreet(\"Bob\", \"Hi\")\ngreet(\"Cy\", \"Yo\", \"custom\")\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["Hello, Ada", "Hi, Bob", "custom"]);
+
+    let (result, ..) = run("func f(a, b = 1) [\n]\nf()\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 3: `f` takes 1 to 2 argument(s) but 0 were given");
+    let (result, ..) = run("func f(a = missing) [\n]\nf()\n");
+    assert!(format!("{:#}", result.unwrap_err()).contains("missing"));
+}
+
+#[test]
+fn a_rest_parameter_collects_the_remaining_arguments() {
+    let source = "func tag(name, sep = \":\", ...items) [\n    log name, sep, items\n]\ntag(\"a\")\ntag(\"b\", \"-\")\ntag(\"c\", \"=\", 1, \"two\", 3)\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["a : []", "b - []", "c = [1, \"two\", 3]"]);
+
+    let (result, ..) = run("func f(a, ...rest) [\n]\nf()\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 3: `f` takes at least 1 argument(s) but 0 were given");
+}
+
+#[test]
+fn lambdas_capture_the_locals_they_are_created_with() {
+    let source = "func adder(n) [\n    return func (x) [ return x + n ]\n]\nlet add2 = adder(2)\nlet add5 = adder(5)\nlog add2(1), add5(1)\nlet count = 1\nlet show = func () [ log count ]\nlet count = 2\nshow()\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    // locals are copied when the lambda is made; globals are read when it runs
+    assert_eq!(lines, ["3 6", "2"]);
+}
+
+#[test]
+fn functions_are_values() {
+    let source = "func twice(f, x) [\n    return f(f(x))\n]\nfunc inc(x) [\n    return x + 1\n]\nlog twice(inc, 1), twice(func (s) [ return s + \"!\" ], \"hi\")\nlet g = inc\nlog g(10), g == inc, inc\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["3 hi!!", "11 true <func inc>"]);
+
+    let (result, ..) = run("let f = func () [\n    break\n]\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 1: `break` outside a loop");
+    let (result, ..) = run("let f = func (a) [\n]\nf()\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 3: `f` takes 1 argument(s) but 0 were given");
+}
+
+#[test]
+fn errors_raised_in_a_call_are_caught_by_the_caller() {
+    let source = "func f(n) [\n    let local = n\n    return n / 0\n]\ntry [\n    f(1)\n] except err [\n    log err.kind, err.message, err.stack\n]\nlog 2\n";
+    let (result, lines, interpreter) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["math:division_by_zero Division by zero [\"f (line 3)\", \"top level (line 6)\"]", "2"]);
+    // the `except` runs back in the caller's scope
+    assert!(interpreter.global("err").is_some());
+    assert_eq!(interpreter.global("local"), None);
+}
+
+#[test]
+fn finally_runs_when_a_function_returns_from_its_try() {
+    let source = "func f() [\n    try [\n        return 1\n    ] finally [\n        log \"cleanup\"\n    ]\n    return 2\n]\nlog f()\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["cleanup", "1"]);
+}
+
+#[test]
+fn errors_escaping_a_finally_keep_their_message() {
+    let (result, lines, _) = run("try [\n    throw \"first\"\n] finally [\n    log \"cleanup\"\n]\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 2: first");
+    assert_eq!(lines, ["cleanup"]);
+    let (result, ..) = run("try [\n    throw \"first\"\n] except [\n    throw \"second\"\n]\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 4: second");
+}
+
+#[test]
+fn conditionals_evaluate_only_the_chosen_value() {
+    let (result, lines, _) = run("let n = 3\nlog n > 2 ? \"big\" : missing, n > 5 ? missing : \"small\"\n");
+    result.unwrap();
+    assert_eq!(lines, ["big small"]);
+}
+
+#[test]
+fn destructuring_takes_elements_and_entries() {
+    let source = "let [a, b, ...rest] = \"hey\"\nlet {x, y} = { \"x\": 1 }\nlog a, b, rest, x, y\n";
+    let (result, lines, _) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["h e y 1 null"]);
+    let (result, ..) = run("let [a, b] = \"a\"\n");
+    assert_eq!(format!("{:#}", result.unwrap_err()), "line 1: Index 1 out of range for a string of length 1");
+}
+
+#[test]
+fn checked_math_turns_overflow_and_nan_into_errors() {
+    let source =
+        ["max + 1", "0 - max - 2", "float(\"1e308\") * 10", "1.5 / 0", "float(\"inf\") - float(\"inf\")", "7 % 0"]
+            .iter()
+            .map(|expr| format!("try [\n    log {}\n] except err [\n    log err.kind, err.message\n]\n", expr))
+            .collect::<String>();
+    let program = hackerscript_parser::parse(&format!("let max = 9223372036854775807\n{}", source)).unwrap();
+    let mut lines = Vec::new();
+    for math in [Math::Wrapping, Math::Checked] {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_math(math);
+        let mut host = BufferHost::default();
+        interpreter.run(&program, &mut host).unwrap();
+        lines.push(host.lines);
+    }
+    let wrapped =
+        ["-9223372036854775808", "9223372036854775807", "inf", "inf", "NaN", "math:division_by_zero Division by zero"];
+    assert_eq!(lines[0], wrapped);
+    assert_eq!(
+        lines[1],
+        [
+            "math:overflow Integer overflow in 9223372036854775807 + 1",
+            "math:overflow Integer overflow in -9223372036854775807 - 2",
+            "math:overflow Float overflow in 1e308 * 10.0",
+            "math:division_by_zero Division by zero",
+            "math:nan NaN from inf - inf",
+            "math:division_by_zero Division by zero",
+        ]
+    );
+}
+
+#[test]
+fn free_leaves_a_variable_null() {
+    let source = "--- manual ---\nlet total = 6\nfunc hot() [\n    let data = \"rows\"\n    free(data)\n    free(total)\n    return data\n]\nlog hot(), total\n";
+    let (result, lines, interpreter) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["null null"]);
+    assert_eq!(interpreter.global("total"), Some(&Value::Null));
+}
@@ -0,0 +1,33 @@
+//! Write a large script for `parse_stats` to stdout: functions, bindings,
+//! branches, loops and calls, over a few hundred names reused throughout.
+//!
+//!     cargo run -p hackerscript-parser --example gen_script -- 120000 > big.hcs
+use std::io::{self, BufWriter, Write};
+
+/// Distinct names, so most identifiers repeat as they would in real code.
+const NAMES: usize = 500;
+
+fn main() -> io::Result<()> {
+    let statements: usize = match std::env::args().nth(1) {
+        Some(count) => count.parse().expect("usage: gen_script [statements]"),
+        None => 120_000,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    for i in 0..statements {
+        let name = i % NAMES;
+        let other = (i * 7 + 3) % NAMES;
+        match i % 4 {
+            0 => write!(
+                out,
+                "func step_{name}(value_{name}, count: int): int [\n    let total = value_{name} * {i} + count\n    return total - value_{name} / 3\n]\n"
+            )?,
+            1 => writeln!(out, "let value_{name} = step_{other}(value_{other}, {i}) + (value_{name} % 7)")?,
+            2 => write!(
+                out,
+                "if value_{name} > value_{other} && value_{name} != {i} [\n    log \"bigger: \" + value_{name}\n] else [\n    let value_{other} = value_{other} - 1\n]\n"
+            )?,
+            _ => write!(out, "while value_{name} < {i} [\n    let value_{name} = value_{name} + step_{other}(1, 2)\n]\n")?,
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,54 @@
+//! Parse a file and report parse time plus heap usage of the resulting AST.
+//! `gen_script` writes a large input:
+//!
+//!     cargo run -p hackerscript-parser --example gen_script -- 120000 > big.hcs
+//!     cargo run --release -p hackerscript-parser --example parse_stats -- big.hcs
+//!
+//! Identifiers are interned as `Symbol`s, but each expression node is still
+//! its own `Box`. Moving nodes into an arena or an index-based pool would
+//! change how every AST consumer walks expressions and how the AST
+//! serializes, so it has not been done.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let path = std::env::args().nth(1).expect("usage: parse_stats <file.hcs>");
+    let source = std::fs::read_to_string(&path).expect("cannot read source");
+
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let program = hackerscript_parser::parse(&source).expect("parse error");
+    let elapsed = started.elapsed();
+
+    println!("statements:   {}", program.body.len());
+    println!("parse time:   {:.2?}", elapsed);
+    println!("allocations:  {}", ALLOCATIONS.load(Ordering::Relaxed) - allocations);
+    println!("AST heap:     {} KiB", (LIVE.load(Ordering::Relaxed) - base) / 1024);
+    println!("peak heap:    {} KiB", (PEAK.load(Ordering::Relaxed) - base) / 1024);
+}
@@ -0,0 +1,95 @@
+//! Language editions. A file picks one with a `--- edition 2025 ---` header
+//! (before any `--- auto ---`), or gets its project's `edition` from
+//! `hs.toml`; with neither it is on `Edition::E2024`. Every edition shares
+//! the grammar, and what an edition changes is checked on the parse tree:
+//! so far, only the words it reserves.
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use hackerscript_ast::Edition;
+use pest::error::{Error, ErrorVariant};
+use pest::iterators::{Pair, Pairs};
+
+use crate::{ParseError, Rule};
+
+pub(crate) fn build_edition(pair: Pair<Rule>) -> Edition {
+    pair.into_inner().next().and_then(|year| year.as_str().parse().ok()).unwrap_or_default()
+}
+
+/// The edition of a parsed file: its header's, or else `project`.
+fn edition_of(mut pairs: Pairs<'_, Rule>, project: Edition) -> Edition {
+    pairs.find(|pair| pair.as_rule() == Rule::edition).map_or(project, build_edition)
+}
+
+/// Fail at the first name in `pairs` that their edition reserves.
+pub(crate) fn check(pairs: Pairs<'_, Rule>, project: Edition) -> Result<(), ParseError> {
+    let edition = edition_of(pairs.clone(), project);
+    if edition.reserved().is_empty() {
+        return Ok(());
+    }
+    let mut found = Vec::new();
+    names(pairs, &mut found);
+    match found.into_iter().find(|name| edition.reserves(name.as_str())) {
+        Some(name) => {
+            let message = format!(
+                "`{}` is a keyword in edition {} and cannot be a name; `hs1 fix --edition {}` renames it",
+                name.as_str(),
+                edition,
+                edition
+            );
+            Err(Box::new(Error::new_from_span(ErrorVariant::CustomError { message }, name.as_span())))
+        }
+        None => Ok(()),
+    }
+}
+
+/// `source` rewritten for edition `to`: each name `to` reserves gets `_`
+/// appended until it names nothing else, and the `--- edition ---` header
+/// is set. The names are not checked against the edition `source` is on,
+/// so a file can be fixed after its project moved on.
+pub fn migrate(source: &str, to: Edition) -> Result<String, ParseError> {
+    let pairs = crate::errors::parse_rule(Rule::program, source)?;
+    let mut found = Vec::new();
+    names(pairs.clone(), &mut found);
+    let used: HashSet<&str> = found.iter().map(|name| name.as_str()).collect();
+    let mut renamed: HashMap<&str, String> = HashMap::new();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for name in &found {
+        let word = name.as_str();
+        if !to.reserves(word) {
+            continue;
+        }
+        let new = renamed.entry(word).or_insert_with(|| {
+            let mut new = format!("{}_", word);
+            while used.contains(new.as_str()) {
+                new.push('_');
+            }
+            new
+        });
+        edits.push((name.as_span().start()..name.as_span().end(), new.clone()));
+    }
+    let header = format!("--- edition {} ---", to);
+    match pairs.clone().find(|pair| pair.as_rule() == Rule::edition) {
+        Some(old) => edits.push((old.as_span().start()..old.as_span().end(), header)),
+        None => edits.push((0..0, header + "\n")),
+    }
+    // back to front, so earlier offsets stay valid
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut out = source.to_string();
+    for (range, text) in edits.into_iter().rev() {
+        out.replace_range(range, &text);
+    }
+    Ok(out)
+}
+
+/// The identifiers in `pairs` that name something. Field names are map
+/// keys and module paths are file names, so neither counts.
+fn names<'i>(pairs: Pairs<'i, Rule>, out: &mut Vec<Pair<'i, Rule>>) {
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::identifier => out.push(pair),
+            Rule::field | Rule::module_path => {}
+            _ => names(pair.into_inner(), out),
+        }
+    }
+}
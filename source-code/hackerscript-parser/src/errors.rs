@@ -0,0 +1,188 @@
+//! Syntax errors that say what could have come next. pest's own message
+//! names every rule it tried (`expected EOI, stmt, and, or, eq, ...`), so on
+//! a failure the source is parsed again with pest's token tracking on and
+//! the tokens expected at the furthest point are summed up the way a person
+//! would say them: "expected `]`, `else`, or statement, found end of input".
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use pest::error::{Error, ErrorVariant, InputLocation};
+use pest::iterators::Pairs;
+use pest::{Parser, Position};
+
+use crate::stream::{self, CommentError};
+use crate::{HackerScriptParser, ParseError, Rule};
+
+/// Words that start a statement; any of them stands for "statement".
+const STATEMENTS: &[&str] = &[
+    "asm", "break", "const", "continue", "enum", "if", "import", "interface", "let", "log", "match", "object", "pub", "require",
+    "return", "sh", "throw", "try", "while",
+];
+/// Tokens that start an expression. `(` stands for "expression": every
+/// expression may start with one, while a string or a digit may be wanted
+/// on its own.
+const EXPRESSIONS: &[&str] = &["(", "\"", "{", "func", "null", "true", "false", "0..9"];
+const OPERATORS: &[&str] = &["+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "?"];
+/// Tracked by pest but never worth suggesting.
+const NOISE: &[&str] = &[" ", "\t", "\n", "\r\n", "@", "-/", "_", "\\", "a..z", "A..Z"];
+
+/// `HackerScriptParser::parse` with the error explained.
+pub(crate) fn parse_rule(rule: Rule, source: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    HackerScriptParser::parse(rule, source).map_err(|err| explain(rule, source, err))
+}
+
+/// Turn pest's error for `source` into one listing the expected tokens.
+/// Token tracking is a process-wide pest switch, so it is only on for the
+/// second parse, and one thread at a time flips it.
+pub(crate) fn explain(rule: Rule, source: &str, err: Error<Rule>) -> ParseError {
+    if let Some(comment) = stream::comment_error(source) {
+        return explain_comment(source, comment);
+    }
+    static DETAIL: Mutex<()> = Mutex::new(());
+    let detailed = {
+        let _guard = DETAIL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        pest::set_error_detail(true);
+        let detailed = HackerScriptParser::parse(rule, source).err();
+        pest::set_error_detail(false);
+        detailed
+    };
+    let Some(attempts) = detailed.and_then(|err| err.parse_attempts()) else {
+        return Box::new(err);
+    };
+    let (at, tokens) = if attempts.expected_tokens().is_empty() {
+        // a lookahead that matched, like the `]` that may end a statement,
+        // moved the furthest position past the failure with no tokens there
+        (failure(&err), rule_tokens(&err.variant))
+    } else {
+        let tokens = attempts.expected_tokens().iter().map(|token| token.to_string()).collect();
+        (attempts.max_position, tokens)
+    };
+    let at = at.min(source.len());
+    // letters and digits right after a word would only make it longer
+    let in_word = source[..at].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+    let mut tokens: Vec<String> = tokens
+        .into_iter()
+        .filter(|token| !(in_word && ["a..z", "A..Z", "0..9"].contains(&token.as_str())))
+        .collect();
+    // only a block can be closed
+    if stream::depth(&source[..at]) <= 0 {
+        tokens.retain(|token| token != "]");
+    }
+    let line = &source[source[..at].rfind('\n').map_or(0, |start| start + 1)..at];
+    let mut message = format!("expected {}", expected(&tokens, !line.trim().is_empty()));
+    if let Some(found) = found(source, at) {
+        message.push_str(&format!(", found {}", found));
+    }
+    let position = Position::new(source, at).expect("parse positions are char boundaries");
+    Box::new(Error::new_from_pos(ErrorVariant::CustomError { message }, position))
+}
+
+/// A block comment problem, which pest would only report as the tokens
+/// that could have come instead of its `-/`.
+fn explain_comment(source: &str, comment: CommentError) -> ParseError {
+    let (at, message) = match comment {
+        CommentError::Nested { open, at } => {
+            let (line, col) = Position::new(source, open).expect("scan offsets are char boundaries").line_col();
+            (at, format!("block comments do not nest: `-/` inside the comment opened at {}:{}", line, col))
+        }
+        CommentError::Unclosed { open } => (open, "block comment is never closed with `-\\`".to_string()),
+        CommentError::Stray { at } => (at, "`-\\` closes no block comment".to_string()),
+    };
+    let position = Position::new(source, at).expect("scan offsets are char boundaries");
+    Box::new(Error::new_from_pos(ErrorVariant::CustomError { message }, position))
+}
+
+fn failure(err: &Error<Rule>) -> usize {
+    match err.location {
+        InputLocation::Pos(pos) => pos,
+        InputLocation::Span((start, _)) => start,
+    }
+}
+
+/// Tokens standing for the rules pest's own error expected.
+fn rule_tokens(variant: &ErrorVariant<Rule>) -> Vec<String> {
+    let ErrorVariant::ParsingError { positives, .. } = variant else { return Vec::new() };
+    positives
+        .iter()
+        .filter_map(|rule| match rule {
+            Rule::newline => Some("\n"),
+            Rule::and | Rule::or | Rule::eq | Rule::ne | Rule::le | Rule::ge | Rule::lt | Rule::gt => Some("=="),
+            Rule::add | Rule::sub | Rule::mul | Rule::div | Rule::rem => Some("+"),
+            _ => None,
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// The source a syntax error points at: the word or character there, and
+/// nothing at the end of a line or of the input.
+pub fn error_span(source: &str, err: &ParseError) -> Range<usize> {
+    let start = failure(err).min(source.len());
+    start..start + token_len(&source[start..])
+}
+
+fn token_len(rest: &str) -> usize {
+    let word = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    match rest.chars().next() {
+        None | Some('\n' | '\r') => 0,
+        Some(_) if word > 0 => word,
+        Some(c) => c.len_utf8(),
+    }
+}
+
+fn found(source: &str, at: usize) -> Option<String> {
+    let rest = &source[at..];
+    Some(match rest.chars().next() {
+        None => "end of input".to_string(),
+        Some('\n' | '\r') => "end of line".to_string(),
+        Some(' ' | '\t') => return None,
+        Some(_) => format!("`{}`", &rest[..token_len(rest)]),
+    })
+}
+
+/// "`]`, `else`, or statement", from pest's expected tokens. A newline is
+/// only worth naming after something on the same line, where it would end
+/// the statement.
+fn expected(tokens: &[String], mid_line: bool) -> String {
+    let statement = tokens.iter().any(|token| STATEMENTS.contains(&token.as_str()));
+    let expression = !statement && tokens.iter().any(|token| token == "(");
+    // `-` alone is the sign of a number
+    let operator = tokens.iter().any(|token| token != "-" && OPERATORS.contains(&token.as_str()));
+    let letters = tokens.iter().any(|token| token == "a..z");
+    let mut literal: BTreeSet<String> = BTreeSet::new();
+    for token in tokens {
+        let token = token.as_str();
+        let covered = NOISE.contains(&token)
+            || (statement && (STATEMENTS.contains(&token) || EXPRESSIONS.contains(&token)))
+            || (expression && EXPRESSIONS.contains(&token))
+            || (operator && OPERATORS.contains(&token))
+            || ((statement || expression) && token == "-");
+        if !covered {
+            literal.insert(match token {
+                "0..9" => "digit".to_string(),
+                _ => format!("`{}`", token),
+            });
+        }
+    }
+    let mut parts: Vec<String> = literal.into_iter().collect();
+    if statement {
+        parts.push("statement".to_string());
+    } else if expression {
+        parts.push("expression".to_string());
+    } else if letters {
+        parts.push("identifier".to_string());
+    }
+    if operator {
+        parts.push("operator".to_string());
+    }
+    if mid_line && tokens.iter().any(|token| token == "\n") {
+        parts.push("end of line".to_string());
+    }
+    match parts.len() {
+        0 => "something else".to_string(),
+        1 => parts.remove(0),
+        2 => format!("{} or {}", parts[0], parts[1]),
+        n => format!("{}, or {}", parts[..n - 1].join(", "), parts[n - 1]),
+    }
+}
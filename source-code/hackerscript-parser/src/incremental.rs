@@ -0,0 +1,175 @@
+//! Incremental re-parsing for editors and watch modes.
+//!
+//! A `ParsedFile` remembers the byte span of every top-level item. When an
+//! edit comes in, only the items it touches (plus one neighbour on each side,
+//! since an edit at a boundary can merge or split items) are re-parsed; the
+//! rest keep their AST and just have their spans shifted. If the affected
+//! region no longer parses on its own, or the edit touches the file header,
+//! the whole file is re-parsed so errors carry real positions.
+use crate::{build_memory_mode, editions, parse_tree, Builder, HackerScriptParser, ParseError, Rule};
+use hackerscript_ast::{visit_spans_mut, Edition, MemoryMode, Program, Spanned, Stmt};
+use pest::error::{Error, ErrorVariant};
+use pest::{Parser, Position};
+use std::ops::Range;
+
+/// Replace `range` (byte offsets into the previous text) with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// One top-level statement and the source it came from. Comments are items
+/// too, with no statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub span: Range<usize>,
+    pub stmt: Option<Spanned<Stmt>>,
+}
+
+/// What `ParsedFile::apply` had to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reparse {
+    /// Top-level items parsed again
+    pub items: usize,
+    /// Whether the whole file was re-parsed
+    pub full: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedFile {
+    source: String,
+    edition: Option<Edition>,
+    memory_mode: Option<MemoryMode>,
+    strict: bool,
+    /// Where the first item starts; everything before is the header
+    body_start: usize,
+    items: Vec<Item>,
+}
+
+impl ParsedFile {
+    pub fn parse(source: impl Into<String>) -> Result<Self, ParseError> {
+        let source = source.into();
+        let mut builder = Builder::default();
+        let mut edition = None;
+        let mut memory_mode = None;
+        let mut strict = false;
+        let mut body_start = None;
+        let mut items = Vec::new();
+        for pair in parse_tree(&source)? {
+            match pair.as_rule() {
+                Rule::edition => edition = Some(editions::build_edition(pair)),
+                Rule::memory_mode => memory_mode = Some(build_memory_mode(pair)),
+                Rule::strict => strict = true,
+                Rule::stmt => {
+                    let span = pair.as_span().start()..pair.as_span().end();
+                    body_start.get_or_insert(span.start);
+                    items.push(Item { span, stmt: builder.stmt(pair) });
+                }
+                _ => {}
+            }
+        }
+        let body_start = body_start.unwrap_or(source.len());
+        Ok(ParsedFile { source, edition, memory_mode, strict, body_start, items })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// The current AST.
+    pub fn program(&self) -> Program {
+        Program {
+            edition: self.edition,
+            memory_mode: self.memory_mode,
+            strict: self.strict,
+            body: self.items.iter().filter_map(|item| item.stmt.clone()).collect(),
+        }
+    }
+
+    /// Apply an edit and bring the AST up to date. On a syntax error, or an
+    /// edit range that is past the end or splits a character (say, a
+    /// position an editor converted from UTF-16 wrongly), the previous
+    /// state is kept.
+    pub fn apply(&mut self, edit: &TextEdit) -> Result<Reparse, ParseError> {
+        let Range { start, end } = edit.range;
+        // past the end is not a boundary either
+        if start > end || !self.source.is_char_boundary(start) || !self.source.is_char_boundary(end) {
+            let message = format!("edit range {:?} is outside the source or splits a character", edit.range);
+            let at = (0..=start.min(self.source.len())).rev().find(|&at| self.source.is_char_boundary(at)).unwrap_or(0);
+            let position = Position::new(&self.source, at).expect("found a char boundary");
+            return Err(Box::new(Error::new_from_pos(ErrorVariant::CustomError { message }, position)));
+        }
+        let mut source = self.source.clone();
+        source.replace_range(edit.range.clone(), &edit.text);
+
+        // items tile the file from `body_start` to the end, so any edit past
+        // the header touches at least one of them
+        if edit.range.start < self.body_start {
+            return self.reparse_all(source);
+        }
+        let mut touched = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.span.start <= edit.range.end && item.span.end >= edit.range.start)
+            .map(|(i, _)| i);
+        let Some(first) = touched.next() else {
+            return self.reparse_all(source);
+        };
+        let last = touched.next_back().unwrap_or(first);
+
+        let first = first.saturating_sub(1);
+        let last = (last + 1).min(self.items.len() - 1);
+        let delta = edit.text.len() as isize - edit.range.len() as isize;
+        let start = self.items[first].span.start;
+        let end = (self.items[last].span.end as isize + delta) as usize;
+
+        // a word the file's edition reserves fails here, and again with its position below
+        let edition = self.edition.unwrap_or_default();
+        let Some(pairs) = HackerScriptParser::parse(Rule::items, &source[start..end])
+            .ok()
+            .filter(|pairs| editions::check(pairs.clone(), edition).is_ok())
+        else {
+            return self.reparse_all(source);
+        };
+        let mut builder = Builder {
+            line_offset: source[..start].matches('\n').count() as u32,
+            byte_offset: start as u32,
+            ..Builder::default()
+        };
+        let fresh: Vec<Item> = pairs
+            .filter(|pair| pair.as_rule() == Rule::stmt)
+            .map(|pair| Item {
+                span: start + pair.as_span().start()..start + pair.as_span().end(),
+                stmt: builder.stmt(pair),
+            })
+            .collect();
+
+        let reparsed = fresh.len();
+        let lines = edit.text.matches('\n').count() as isize - self.source[edit.range.clone()].matches('\n').count() as isize;
+        for item in &mut self.items[last + 1..] {
+            item.span.start = (item.span.start as isize + delta) as usize;
+            item.span.end = (item.span.end as isize + delta) as usize;
+            if let Some(stmt) = &mut item.stmt {
+                visit_spans_mut(std::slice::from_mut(stmt), &mut |span| {
+                    span.start = (span.start as isize + delta) as u32;
+                    span.end = (span.end as isize + delta) as u32;
+                    span.line = (span.line as isize + lines) as u32;
+                });
+            }
+        }
+        self.items.splice(first..=last, fresh);
+        self.source = source;
+        Ok(Reparse { items: reparsed, full: false })
+    }
+
+    fn reparse_all(&mut self, source: String) -> Result<Reparse, ParseError> {
+        *self = ParsedFile::parse(source)?;
+        Ok(Reparse { items: self.items.len(), full: true })
+    }
+}
@@ -0,0 +1,547 @@
+//! The one HackerScript grammar (`hackerscript.pest`) and the AST builder on top of it.
+use hackerscript_ast::{BinOp, Case, Edition, Enum, Except, Expr, Func, Interface, Lit, LogLevel, MemoryMode, Method, Param, Pattern, Program, Span, Spanned, Stmt, Symbol, Variant};
+use pest::iterators::{Pair, Pairs};
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+pub mod editions;
+mod errors;
+pub mod incremental;
+pub mod loader;
+pub mod stream;
+mod suggest;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "hackerscript.pest"]
+pub struct HackerScriptParser;
+
+pub type ParseError = Box<pest::error::Error<Rule>>;
+
+pub use errors::error_span;
+pub use suggest::{suggest, Suggestion};
+
+/// Raw parse tree, for tools that want pest pairs instead of the AST.
+pub fn parse_tree(source: &str) -> Result<Pairs<'_, Rule>, ParseError> {
+    parse_tree_in(source, Edition::default())
+}
+
+/// `parse_tree` for a file of a project on `edition`; the file's own
+/// `--- edition ---` header wins.
+pub fn parse_tree_in(source: &str, edition: Edition) -> Result<Pairs<'_, Rule>, ParseError> {
+    let pairs = errors::parse_rule(Rule::program, source)?;
+    editions::check(pairs.clone(), edition)?;
+    Ok(pairs)
+}
+
+/// Parse a whole `.hcs` source file into a `Program`.
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    parse_in(source, Edition::default())
+}
+
+/// `parse` for a file of a project on `edition`.
+pub fn parse_in(source: &str, edition: Edition) -> Result<Program, ParseError> {
+    let mut program = build_program(parse_tree_in(source, edition)?);
+    if program.edition.is_none() && edition != Edition::default() {
+        program.edition = Some(edition);
+    }
+    Ok(program)
+}
+
+/// Lower a parse tree from `parse_tree` into a `Program`. `parse` does both
+/// steps; they are separate so `hs1 compile --time-passes` can time each.
+pub fn build_program(pairs: Pairs<'_, Rule>) -> Program {
+    let mut builder = Builder::default();
+    let mut program = Program::default();
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::edition => program.edition = Some(editions::build_edition(pair)),
+            Rule::memory_mode => program.memory_mode = Some(build_memory_mode(pair)),
+            Rule::strict => program.strict = true,
+            Rule::stmt => program.body.extend(builder.stmt(pair)),
+            _ => {}
+        }
+    }
+    program
+}
+
+pub(crate) fn build_memory_mode(pair: Pair<Rule>) -> MemoryMode {
+    if pair.as_str().contains("manual") {
+        MemoryMode::Manual
+    } else {
+        MemoryMode::Auto
+    }
+}
+
+/// Turns pest pairs into AST nodes, interning identifiers as it goes.
+#[derive(Default)]
+pub(crate) struct Builder {
+    symbols: HashSet<Symbol>,
+    /// Lines and bytes before the parsed text, when it is part of a
+    /// larger source
+    pub(crate) line_offset: u32,
+    pub(crate) byte_offset: u32,
+}
+
+impl Builder {
+    fn symbol(&mut self, pair: &Pair<Rule>) -> Symbol {
+        let name = pair.as_str();
+        if let Some(symbol) = self.symbols.get(name) {
+            return symbol.clone();
+        }
+        let symbol = Symbol::from(name);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    fn span(&self, pair: &Pair<Rule>) -> Span {
+        let span = pair.as_span();
+        Span {
+            start: self.byte_offset + span.start() as u32,
+            end: self.byte_offset + span.end() as u32,
+            line: self.line_offset + pair.line_col().0 as u32,
+        }
+    }
+
+    pub(crate) fn stmt(&mut self, pair: Pair<Rule>) -> Option<Spanned<Stmt>> {
+        let inner = pair.into_inner().find(|p| p.as_rule() != Rule::newline)?;
+        let span = self.span(&inner);
+        self.node(inner).map(|stmt| Spanned::new(stmt, span))
+    }
+
+    fn node(&mut self, inner: Pair<Rule>) -> Option<Stmt> {
+        match inner.as_rule() {
+            Rule::import_stmt => {
+                let mut parts = inner.into_inner();
+                let first = parts.next().unwrap();
+                if first.as_rule() == Rule::module_path {
+                    let path = first.into_inner().map(|name| self.symbol(&name)).collect();
+                    return Some(Stmt::ImportModule { path });
+                }
+                let repo = self.symbol(&first);
+                let lib = self.symbol(&parts.next().unwrap());
+                Some(Stmt::Import { repo, lib })
+            }
+            Rule::require_stmt => {
+                let path = inner.into_inner().next().unwrap().as_str().to_string();
+                Some(Stmt::Require { path })
+            }
+            Rule::func_def => Some(Stmt::Func(self.func(inner))),
+            Rule::object_def => {
+                let mut parts = inner.into_inner().peekable();
+                let public = parts.next_if(|p| p.as_rule() == Rule::pub_kw).is_some();
+                let name = self.symbol(&parts.next().unwrap());
+                let parent = parts.next_if(|p| p.as_rule() == Rule::parent).map(|p| self.symbol(&p));
+                let implements = match parts.next_if(|p| p.as_rule() == Rule::implements) {
+                    Some(names) => names.into_inner().map(|name| self.symbol(&name)).collect(),
+                    None => Vec::new(),
+                };
+                let body = self.block(parts.next().unwrap());
+                Some(Stmt::Object { public, name, parent, implements, body })
+            }
+            Rule::interface_def => {
+                let mut parts = inner.into_inner().filter(|p| p.as_rule() != Rule::newline);
+                let name = self.symbol(&parts.next().unwrap());
+                let methods = parts.filter(|p| p.as_rule() == Rule::method_sig).map(|sig| self.method(sig)).collect();
+                Some(Stmt::Interface(Interface { name, methods }))
+            }
+            Rule::let_stmt => {
+                let mut parts = inner.into_inner().peekable();
+                let name = self.symbol(&parts.next().unwrap());
+                let ty = parts.next_if(|p| p.as_rule() == Rule::type_name).map(|t| self.symbol(&t));
+                let value = self.expr(parts.next().unwrap());
+                Some(Stmt::Let { name, ty, value })
+            }
+            Rule::destructure_stmt => {
+                let mut parts = inner.into_inner();
+                let pattern = self.pattern(parts.next().unwrap());
+                let value = self.expr(parts.next().unwrap());
+                Some(Stmt::Destructure { pattern, value })
+            }
+            Rule::const_stmt => {
+                let mut parts = inner.into_inner().peekable();
+                let public = parts.next_if(|p| p.as_rule() == Rule::pub_kw).is_some();
+                let name = self.symbol(&parts.next().unwrap());
+                let ty = parts.next_if(|p| p.as_rule() == Rule::type_name).map(|t| self.symbol(&t));
+                let value = self.expr(parts.next().unwrap());
+                Some(Stmt::Const { public, name, ty, value })
+            }
+            Rule::enum_def => Some(Stmt::Enum(self.enum_def(inner))),
+            Rule::if_stmt => Some(self.if_stmt(inner)),
+            Rule::while_stmt => {
+                let mut parts = inner.into_inner();
+                let cond = self.expr(parts.next().unwrap());
+                let body = self.block(parts.next().unwrap());
+                Some(Stmt::While { cond, body })
+            }
+            Rule::match_stmt => Some(self.match_stmt(inner)),
+            Rule::try_stmt => Some(self.try_stmt(inner)),
+            Rule::throw_stmt => Some(Stmt::Throw {
+                value: self.expr(inner.into_inner().next().unwrap()),
+            }),
+            Rule::asm_stmt => Some(Stmt::Asm {
+                code: inner.into_inner().next().unwrap().as_str().to_string(),
+            }),
+            Rule::sh_stmt => Some(Stmt::Sh {
+                commands: inner
+                    .into_inner()
+                    .next()
+                    .unwrap()
+                    .as_str()
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect(),
+            }),
+            Rule::break_stmt => Some(Stmt::Break),
+            Rule::continue_stmt => Some(Stmt::Continue),
+            Rule::return_stmt => Some(Stmt::Return {
+                value: inner.into_inner().next().map(|e| self.expr(e)),
+            }),
+            Rule::log_stmt => {
+                let mut parts = inner.into_inner().peekable();
+                let level = parts.next_if(|p| p.as_rule() == Rule::log_level).map(|p| match p.as_str() {
+                    "debug" => LogLevel::Debug,
+                    "info" => LogLevel::Info,
+                    "warn" => LogLevel::Warn,
+                    _ => LogLevel::Error,
+                });
+                let values = parts.map(|e| self.expr(e)).collect();
+                Some(Stmt::Log { level, values })
+            }
+            Rule::expr_stmt => Some(Stmt::Expr {
+                value: self.expr(inner.into_inner().next().unwrap()),
+            }),
+            _ => None,
+        }
+    }
+
+    fn pattern(&mut self, pair: Pair<Rule>) -> Pattern {
+        let rule = pair.as_rule();
+        let mut names = Vec::new();
+        let mut rest = None;
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::rest_name => rest = Some(self.symbol(&part.into_inner().next().unwrap())),
+                _ => names.push(self.symbol(&part)),
+            }
+        }
+        match rule {
+            Rule::array_pattern => Pattern::Array { names, rest },
+            _ => Pattern::Map { names },
+        }
+    }
+
+    fn if_stmt(&mut self, pair: Pair<Rule>) -> Stmt {
+        let mut parts = pair.into_inner().filter(|p| p.as_rule() != Rule::newline);
+        let cond = self.expr(parts.next().unwrap());
+        let then_body = self.block(parts.next().unwrap());
+        let else_body = match parts.next().and_then(|clause| clause.into_inner().next()) {
+            Some(inner) if inner.as_rule() == Rule::if_stmt => {
+                let span = self.span(&inner);
+                vec![Spanned::new(self.if_stmt(inner), span)]
+            }
+            Some(block) => self.block(block),
+            None => Vec::new(),
+        };
+        Stmt::If { cond, then_body, else_body }
+    }
+
+    fn try_stmt(&mut self, pair: Pair<Rule>) -> Stmt {
+        let mut parts = pair.into_inner().filter(|p| p.as_rule() != Rule::newline);
+        let body = self.block(parts.next().unwrap());
+        let mut except = None;
+        let mut finally = Vec::new();
+        for clause in parts {
+            let rule = clause.as_rule();
+            let mut inner = clause.into_inner().peekable();
+            match rule {
+                Rule::except_clause => {
+                    let name = inner.next_if(|p| p.as_rule() == Rule::identifier).map(|p| self.symbol(&p));
+                    let body = self.block(inner.next().unwrap());
+                    except = Some(Except { name, body });
+                }
+                _ => finally = self.block(inner.next().unwrap()),
+            }
+        }
+        Stmt::Try { body, except, finally }
+    }
+
+    fn match_stmt(&mut self, pair: Pair<Rule>) -> Stmt {
+        let mut parts = pair.into_inner().filter(|p| p.as_rule() != Rule::newline);
+        let subject = self.expr(parts.next().unwrap());
+        let mut cases = Vec::new();
+        let mut default = Vec::new();
+        for clause in parts {
+            let rule = clause.as_rule();
+            let mut inner = clause.into_inner();
+            match rule {
+                Rule::case_clause => {
+                    let test = inner.next().unwrap();
+                    let body = inner.next().unwrap();
+                    cases.push(match test.as_rule() {
+                        Rule::array_pattern | Rule::map_pattern => {
+                            let pattern = self.pattern(test);
+                            Case::pattern(pattern, self.block(body))
+                        }
+                        _ => {
+                            let value = self.expr(test);
+                            Case::value(value, self.block(body))
+                        }
+                    });
+                }
+                _ => default = self.block(inner.next().unwrap()),
+            }
+        }
+        Stmt::Match { subject, cases, default }
+    }
+
+    fn enum_def(&mut self, pair: Pair<Rule>) -> Enum {
+        let mut def = Enum {
+            public: false,
+            name: Symbol::from(""),
+            variants: Vec::new(),
+        };
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::pub_kw => def.public = true,
+                Rule::identifier => def.name = self.symbol(&inner),
+                Rule::variant => {
+                    let mut parts = inner.into_inner();
+                    let name = self.symbol(&parts.next().unwrap());
+                    // out-of-range discriminants fall back to the implicit value
+                    let value = parts.next().and_then(|d| d.as_str().parse().ok());
+                    def.variants.push(Variant { name, value });
+                }
+                _ => {}
+            }
+        }
+        def
+    }
+
+    fn func(&mut self, pair: Pair<Rule>) -> Func {
+        let mut func = Func {
+            public: false,
+            name: Symbol::from(""),
+            params: Vec::new(),
+            ret: None,
+            inline: false,
+            memory_mode: None,
+            body: Vec::new(),
+        };
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::pub_kw => func.public = true,
+                Rule::identifier => func.name = self.symbol(&inner),
+                Rule::params => func.params = self.params(inner),
+                Rule::return_type => {
+                    func.ret = inner.into_inner().next().map(|t| self.symbol(&t));
+                }
+                Rule::func_inline => func.inline = true,
+                Rule::func_memory_mode => func.memory_mode = Some(build_memory_mode(inner)),
+                Rule::block => func.body = self.block(inner),
+                _ => {}
+            }
+        }
+        func
+    }
+
+    fn method(&mut self, pair: Pair<Rule>) -> Method {
+        let mut method = Method { name: Symbol::from(""), params: Vec::new(), ret: None };
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::identifier => method.name = self.symbol(&inner),
+                Rule::params => method.params = self.params(inner),
+                Rule::return_type => method.ret = inner.into_inner().next().map(|t| self.symbol(&t)),
+                _ => {}
+            }
+        }
+        method
+    }
+
+    fn params(&mut self, pair: Pair<Rule>) -> Vec<Param> {
+        pair.into_inner()
+            .map(|param| {
+                let rest = param.as_rule() == Rule::rest_param;
+                let mut parts = param.into_inner().peekable();
+                let name = self.symbol(&parts.next().unwrap());
+                let ty = parts.next_if(|p| p.as_rule() == Rule::type_name).map(|t| self.symbol(&t));
+                let default = parts.next().map(|e| self.expr(e));
+                Param { name, ty, default, rest }
+            })
+            .collect()
+    }
+
+    fn block(&mut self, pair: Pair<Rule>) -> Vec<Spanned<Stmt>> {
+        pair.into_inner()
+            .filter(|p| p.as_rule() == Rule::stmt)
+            .filter_map(|p| self.stmt(p))
+            .collect()
+    }
+
+    fn expr(&mut self, pair: Pair<Rule>) -> Expr {
+        // the Pratt callbacks both need the builder, so share it through a RefCell
+        let conditional = pair.clone().into_inner().find(|part| part.as_rule() == Rule::conditional);
+        let this = std::cell::RefCell::new(self);
+        let expr = pratt()
+            .map_primary(|p| this.borrow_mut().operand(p))
+            .map_infix(|lhs, op, rhs| {
+                let op = match op.as_rule() {
+                    Rule::add => BinOp::Add,
+                    Rule::sub => BinOp::Sub,
+                    Rule::mul => BinOp::Mul,
+                    Rule::div => BinOp::Div,
+                    Rule::rem => BinOp::Rem,
+                    Rule::eq => BinOp::Eq,
+                    Rule::ne => BinOp::Ne,
+                    Rule::lt => BinOp::Lt,
+                    Rule::le => BinOp::Le,
+                    Rule::gt => BinOp::Gt,
+                    Rule::ge => BinOp::Ge,
+                    Rule::and => BinOp::And,
+                    Rule::or => BinOp::Or,
+                    rule => unreachable!("not a binary operator: {:?}", rule),
+                };
+                Expr::binary(op, lhs, rhs)
+            })
+            .map_postfix(|target, index| {
+                let inner = index.into_inner().next().unwrap();
+                if inner.as_rule() == Rule::identifier {
+                    return Expr::Index {
+                        target: Box::new(target),
+                        index: Box::new(Expr::Lit(Lit::Str(inner.as_str().to_string()))),
+                    };
+                }
+                if inner.as_rule() != Rule::slice {
+                    return Expr::Index {
+                        target: Box::new(target),
+                        index: Box::new(this.borrow_mut().expr(inner)),
+                    };
+                }
+                let (mut start, mut end) = (None, None);
+                for bound in inner.into_inner() {
+                    let rule = bound.as_rule();
+                    let value = Some(Box::new(this.borrow_mut().expr(bound.into_inner().next().unwrap())));
+                    match rule {
+                        Rule::slice_start => start = value,
+                        _ => end = value,
+                    }
+                }
+                Expr::Slice { target: Box::new(target), start, end }
+            })
+            .parse(pair.into_inner().filter(|part| part.as_rule() != Rule::conditional));
+        let Some(conditional) = conditional else { return expr };
+        let this = this.into_inner();
+        let mut values = conditional.into_inner().map(|value| Box::new(this.expr(value)));
+        let (then_value, else_value) = (values.next().unwrap(), values.next().unwrap());
+        Expr::Ternary { cond: Box::new(expr), then_value, else_value }
+    }
+
+    fn operand(&mut self, pair: Pair<Rule>) -> Expr {
+        match pair.as_rule() {
+            Rule::number => {
+                let text = pair.as_str();
+                match text.parse::<i64>() {
+                    Ok(n) => Expr::Lit(Lit::Int(n)),
+                    // too big for an int; the grammar only admits decimal digits
+                    Err(_) => Expr::Lit(Lit::Float(text.parse().expect("number literals are valid floats"))),
+                }
+            }
+            Rule::string => Expr::Lit(Lit::Str(string_value(&pair))),
+            Rule::null_lit => Expr::Lit(Lit::Null),
+            Rule::bool_lit => Expr::Lit(Lit::Bool(pair.as_str() == "true")),
+            Rule::identifier => Expr::Var {
+                name: self.symbol(&pair),
+            },
+            Rule::call => {
+                let mut parts = pair.into_inner();
+                let callee = self.symbol(&parts.next().unwrap());
+                let args = parts
+                    .next()
+                    .map(|args| args.into_inner().map(|a| self.expr(a)).collect())
+                    .unwrap_or_default();
+                Expr::Call { callee, args }
+            }
+            Rule::lambda => {
+                let Func { params, ret, body, .. } = self.func(pair);
+                Expr::Lambda { params, ret, body }
+            }
+            Rule::map_lit => Expr::Map {
+                entries: pair
+                    .into_inner()
+                    .filter(|p| p.as_rule() == Rule::map_entry)
+                    .map(|entry| {
+                        let mut parts = entry.into_inner();
+                        let key = string_value(&parts.next().unwrap());
+                        (key, self.expr(parts.next().unwrap()))
+                    })
+                    .collect(),
+            },
+            Rule::expr => self.expr(pair),
+            rule => unreachable!("not an operand: {:?}", rule),
+        }
+    }
+}
+
+fn string_value(pair: &Pair<Rule>) -> String {
+    let raw = pair.as_str();
+    unescape(&raw[1..raw.len() - 1])
+}
+
+/// The text a string literal stands for, given what is between its quotes.
+/// The grammar only admits the escapes handled here.
+pub fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Inverse of `unescape`: `text` as it would be written between quotes.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '{' => out.push_str("\\{"),
+            '}' => out.push_str("\\}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Binary operator table, loosest binding first; indexing binds tightest.
+fn pratt() -> &'static PrattParser<Rule> {
+    static PRATT: OnceLock<PrattParser<Rule>> = OnceLock::new();
+    PRATT.get_or_init(|| {
+        PrattParser::new()
+            .op(Op::infix(Rule::or, Assoc::Left))
+            .op(Op::infix(Rule::and, Assoc::Left))
+            .op(Op::infix(Rule::eq, Assoc::Left) | Op::infix(Rule::ne, Assoc::Left))
+            .op(Op::infix(Rule::lt, Assoc::Left)
+                | Op::infix(Rule::le, Assoc::Left)
+                | Op::infix(Rule::gt, Assoc::Left)
+                | Op::infix(Rule::ge, Assoc::Left))
+            .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
+            .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left) | Op::infix(Rule::rem, Assoc::Left))
+            .op(Op::postfix(Rule::index) | Op::postfix(Rule::field))
+    })
+}
@@ -0,0 +1,359 @@
+//! Module loading: replaces `import` and `require` statements with the
+//! top-level statements of the file they name, so the compiler and the
+//! interpreter see one program.
+//!
+//! Resolution order:
+//! 1. `require <path>` is relative to the requiring file (`.hcs` is added
+//!    when the path has no extension).
+//! 2. `import <app.utils.net>` looks for `app/utils/net.hcs` in the project's
+//!    `src` directory, then in each directory of `HS_PATH` in order.
+//! 3. `import <repo:lib>` looks for `repo/lib.hcs` in the same places,
+//!    then among the built-in modules (`core:*`) when the loader has them.
+//!
+//! The `src` directory is the closest ancestor of the entry script that is
+//! named `src` or contains one; without either, the entry script's own
+//! directory. Every file is included once, at its first import: its
+//! top-level statements run there, before the rest of the importing file,
+//! and later imports of it add nothing. Its `--- mode ---` header is ignored
+//! in favour of the entry script's. A file that imports itself, directly or
+//! through other modules, is an error naming every import on the way round.
+//!
+//! In each file, `__module__` is the name it was imported by (`app.utils.net`,
+//! `core:math`, a `require` path as written; `main` for the entry script and
+//! `prelude` for the prelude) and `__file__` the path it was read from, or
+//! the module name for a built-in one.
+//!
+//! A prelude (`HS_PRELUDE`, or `prelude` in `hs.toml`) is included before
+//! the entry script's first statement, as if the script required it, so
+//! every script sees its definitions without importing anything.
+//!
+//! Files are parsed on the loader's edition unless they name their own with
+//! a `--- edition ---` header; the built-in modules keep the default one.
+use hackerscript_ast::{visit_exprs_mut, Edition, Expr, Lit, Program, Spanned, Stmt};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{fs, io, mem};
+
+use crate::ParseError;
+
+/// Environment variable with extra module directories, separated like `PATH`.
+pub const SEARCH_PATH_VAR: &str = "HS_PATH";
+
+/// Environment variable naming a file included before every script.
+pub const PRELUDE_VAR: &str = "HS_PRELUDE";
+
+/// What `__module__` is in the entry script.
+pub const ENTRY_MODULE: &str = "main";
+
+/// The source of a module built into the toolchain, by `repo:lib` name.
+pub type BuiltinSource = fn(&str) -> Option<&'static str>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Cannot read {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Parse error:\n{error}")]
+    Parse { path: PathBuf, error: ParseError },
+    #[error("{}: cannot find module `{module}` (looked for {})", from.display(), display_paths(searched))]
+    NotFound { module: String, from: PathBuf, searched: Vec<PathBuf> },
+    /// Each file in the cycle with the line and text of the import that
+    /// leads to the next, the last leading back to the first.
+    #[error("import cycle: {}", display_cycle(cycle))]
+    Cycle { cycle: Vec<(PathBuf, u32, String)> },
+}
+
+/// `a.hcs -> b.hcs -> a.hcs`, then `file:line: import` for each file.
+fn display_cycle(cycle: &[(PathBuf, u32, String)]) -> String {
+    let mut chain: Vec<String> = cycle.iter().map(|(file, _, _)| file.display().to_string()).collect();
+    chain.extend(chain.first().cloned());
+    let mut out = chain.join(" -> ");
+    for (file, line, import) in cycle {
+        out.push_str(&format!("\n  {}:{}: {}", file.display(), line, import));
+    }
+    out
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug, Clone)]
+pub struct Loader {
+    src_root: PathBuf,
+    search_path: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+    /// The files being expanded, outermost first, canonical and as resolved
+    stack: Vec<(PathBuf, PathBuf)>,
+    /// The line and text of the import each file on the stack but the last
+    /// is expanding
+    via: Vec<(u32, String)>,
+    builtins: Option<BuiltinSource>,
+    /// Leave imports of built-in modules for the runtime
+    keep_builtins: bool,
+    prelude: Option<PathBuf>,
+    edition: Edition,
+}
+
+impl Loader {
+    pub fn new(src_root: impl Into<PathBuf>, search_path: Vec<PathBuf>) -> Self {
+        Loader {
+            src_root: src_root.into(),
+            search_path,
+            loaded: HashSet::new(),
+            stack: Vec::new(),
+            via: Vec::new(),
+            builtins: None,
+            keep_builtins: false,
+            prelude: None,
+            edition: Edition::default(),
+        }
+    }
+
+    /// Make `import <repo:lib>` fall back to `builtins` when no file has the
+    /// module. With `keep` the import stays, for a runtime that has the
+    /// module precompiled; otherwise it is expanded from the source.
+    pub fn set_builtins(&mut self, builtins: BuiltinSource, keep: bool) {
+        self.builtins = Some(builtins);
+        self.keep_builtins = keep;
+    }
+
+    /// Include `path` before the first program this loader expands.
+    pub fn set_prelude(&mut self, path: Option<PathBuf>) {
+        self.prelude = path;
+    }
+
+    /// Parse files without an edition header as `edition`.
+    pub fn set_edition(&mut self, edition: Edition) {
+        self.edition = edition;
+    }
+
+    /// The loader for a program whose entry script is `entry`, searching
+    /// `HS_PATH` after the project's `src` directory, with the `HS_PRELUDE`
+    /// prelude.
+    pub fn for_entry(entry: &Path) -> Self {
+        let mut loader = Loader::new(src_root(entry), env_search_path());
+        loader.set_prelude(env_prelude());
+        loader
+    }
+
+    pub fn src_root(&self) -> &Path {
+        &self.src_root
+    }
+
+    /// Read and parse `path`, then expand its imports.
+    pub fn load(&mut self, path: &Path) -> Result<Program, LoadError> {
+        let mut program = self.parse(path)?;
+        self.expand(&mut program, path)?;
+        Ok(program)
+    }
+
+    /// Read and parse `path` on the loader's edition, leaving its imports
+    /// for `expand`.
+    pub fn parse(&self, path: &Path) -> Result<Program, LoadError> {
+        parse_file(path, self.edition)
+    }
+
+    /// Expand the top-level imports of `program`, which was parsed from
+    /// `file`. The first time, the prelude goes in front of it.
+    pub fn expand(&mut self, program: &mut Program, file: &Path) -> Result<(), LoadError> {
+        self.loaded.insert(canonical(file));
+        let mut body = Vec::new();
+        if let Some(prelude) = self.prelude.clone().filter(|prelude| self.loaded.insert(canonical(prelude))) {
+            body = self.expand_file(parse_file(&prelude, self.edition)?.body, &prelude, "prelude")?;
+        }
+        body.extend(self.expand_file(mem::take(&mut program.body), file, ENTRY_MODULE)?);
+        program.body = body;
+        Ok(())
+    }
+
+    /// Expand `body`, parsed from `file` for module `module`, with `file` on
+    /// the stack.
+    fn expand_file(
+        &mut self,
+        mut body: Vec<Spanned<Stmt>>,
+        file: &Path,
+        module: &str,
+    ) -> Result<Vec<Spanned<Stmt>>, LoadError> {
+        introspect(&mut body, module, &file.display().to_string());
+        self.stack.push((canonical(file), file.to_path_buf()));
+        let out = self.expand_body(body, file);
+        self.stack.pop();
+        out
+    }
+
+    fn expand_body(&mut self, body: Vec<Spanned<Stmt>>, file: &Path) -> Result<Vec<Spanned<Stmt>>, LoadError> {
+        let mut out = Vec::with_capacity(body.len());
+        for stmt in body {
+            if let Some((name, source)) = self.builtin(&stmt) {
+                if self.keep_builtins {
+                    out.push(stmt);
+                } else if self.loaded.insert(PathBuf::from(&name)) {
+                    let module = crate::parse(source).map_err(|error| LoadError::Parse {
+                        path: PathBuf::from(&name),
+                        error: Box::new(error.with_path(&name)),
+                    })?;
+                    out.extend(self.expand_module(module, Path::new(&name), &stmt)?);
+                }
+                continue;
+            }
+            let Some(path) = self.resolve(&stmt, file)? else {
+                out.push(stmt);
+                continue;
+            };
+            let key = canonical(&path);
+            if let Some(start) = self.stack.iter().position(|(open, _)| *open == key) {
+                let imports = self.via[start..].iter().cloned().chain([(stmt.span.line, written(&stmt))]);
+                let cycle = self.stack[start..]
+                    .iter()
+                    .zip(imports)
+                    .map(|((_, file), (line, import))| (file.clone(), line, import))
+                    .collect();
+                return Err(LoadError::Cycle { cycle });
+            }
+            if !self.loaded.insert(key) {
+                continue;
+            }
+            let module = parse_file(&path, self.edition)?;
+            out.extend(self.expand_module(module, &path, &stmt)?);
+        }
+        Ok(out)
+    }
+
+    /// Expand `module`, read from `path` for `import`.
+    fn expand_module(
+        &mut self,
+        module: Program,
+        path: &Path,
+        import: &Spanned<Stmt>,
+    ) -> Result<Vec<Spanned<Stmt>>, LoadError> {
+        self.via.push((import.span.line, written(import)));
+        let expanded = self.expand_file(module.body, path, &module_name(import));
+        self.via.pop();
+        expanded
+    }
+
+    /// The name and source of the built-in module an `import <repo:lib>`
+    /// names, when no file in the search path has it.
+    fn builtin(&self, stmt: &Stmt) -> Option<(String, &'static str)> {
+        let (Stmt::Import { repo, lib }, Some(builtins)) = (stmt, self.builtins) else {
+            return None;
+        };
+        let relative = Path::new(&**repo).join(format!("{}.hcs", lib));
+        if self.roots().any(|root| root.join(&relative).is_file()) {
+            return None;
+        }
+        let name = format!("{}:{}", repo, lib);
+        builtins(&name).map(|source| (name, source))
+    }
+
+    /// The file an `import` or `require` in `from` refers to, or `None` for
+    /// any other statement.
+    pub fn resolve(&self, stmt: &Stmt, from: &Path) -> Result<Option<PathBuf>, LoadError> {
+        let (module, candidates) = match stmt {
+            Stmt::Require { path } => {
+                let mut target = from.parent().unwrap_or(Path::new("")).join(path);
+                if target.extension().is_none() {
+                    target.set_extension("hcs");
+                }
+                (path.clone(), vec![target])
+            }
+            Stmt::ImportModule { path } => {
+                let names: Vec<&str> = path.iter().map(|s| &**s).collect();
+                let relative: PathBuf = names.iter().collect::<PathBuf>().with_extension("hcs");
+                (names.join("."), self.roots().map(|root| root.join(&relative)).collect())
+            }
+            Stmt::Import { repo, lib } => {
+                let relative = Path::new(&**repo).join(format!("{}.hcs", lib));
+                (format!("{}:{}", repo, lib), self.roots().map(|root| root.join(&relative)).collect())
+            }
+            _ => return Ok(None),
+        };
+        match candidates.iter().find(|path| path.is_file()) {
+            Some(found) => Ok(Some(found.clone())),
+            None => Err(LoadError::NotFound { module, from: from.to_path_buf(), searched: candidates }),
+        }
+    }
+
+    fn roots(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.src_root).chain(&self.search_path)
+    }
+}
+
+/// Load `entry` and everything it imports, searching `HS_PATH`.
+pub fn load(entry: &Path) -> Result<Program, LoadError> {
+    Loader::for_entry(entry).load(entry)
+}
+
+/// The directories listed in `HS_PATH`, in order.
+pub fn env_search_path() -> Vec<PathBuf> {
+    std::env::var_os(SEARCH_PATH_VAR)
+        .map(|dirs| std::env::split_paths(&dirs).filter(|d| !d.as_os_str().is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// The file `HS_PRELUDE` names, if it is set and not empty.
+pub fn env_prelude() -> Option<PathBuf> {
+    std::env::var_os(PRELUDE_VAR).filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+/// The `src` directory of the project `entry` belongs to.
+pub fn src_root(entry: &Path) -> PathBuf {
+    let dir = match entry.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = canonical(dir);
+    for ancestor in dir.ancestors() {
+        if ancestor.file_name().is_some_and(|name| name == "src") {
+            return ancestor.to_path_buf();
+        }
+        let src = ancestor.join("src");
+        if src.is_dir() {
+            return src;
+        }
+    }
+    dir
+}
+
+fn parse_file(path: &Path, edition: Edition) -> Result<Program, LoadError> {
+    let source = fs::read_to_string(path).map_err(|source| LoadError::Io { path: path.to_path_buf(), source })?;
+    crate::parse_in(&source, edition).map_err(|error| LoadError::Parse {
+        path: path.to_path_buf(),
+        error: Box::new(error.with_path(&path.display().to_string())),
+    })
+}
+
+/// Replace `__module__` and `__file__` in `body` with `module` and `file`.
+pub fn introspect(body: &mut [Spanned<Stmt>], module: &str, file: &str) {
+    visit_exprs_mut(body, &mut |expr| {
+        let value = match expr {
+            Expr::Var { name } if name.as_str() == "__module__" => module,
+            Expr::Var { name } if name.as_str() == "__file__" => file,
+            _ => return,
+        };
+        *expr = Expr::Lit(Lit::Str(value.to_string()));
+    });
+}
+
+/// The name an import statement gives its module.
+fn module_name(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Require { path } => path.clone(),
+        Stmt::ImportModule { path } => path.iter().map(|name| name.as_str()).collect::<Vec<_>>().join("."),
+        Stmt::Import { repo, lib } => format!("{}:{}", repo, lib),
+        _ => String::new(),
+    }
+}
+
+/// An import statement as it is written.
+fn written(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Require { .. } => format!("require <{}>", module_name(stmt)),
+        Stmt::ImportModule { .. } | Stmt::Import { .. } => format!("import <{}>", module_name(stmt)),
+        _ => String::new(),
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,314 @@
+//! Statement-at-a-time parsing for scripts too large to hold as one parse tree.
+//!
+//! Lines are buffered until the brackets balance and the buffer parses as
+//! whole statements. Every statement but the last is then handed out; the
+//! last one is held back because the next line may still extend it (an
+//! `else` on its own line). Memory use is bounded by the largest top-level
+//! item rather than by the file.
+use crate::{build_memory_mode, editions, Builder, HackerScriptParser, ParseError, Rule};
+use hackerscript_ast::{Edition, MemoryMode, Spanned, Stmt};
+use pest::error::InputLocation;
+use pest::Parser;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StreamError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// `error` positions are relative to `line`, where the failing chunk starts
+    #[error("in statement starting at line {line}:\n{error}")]
+    Parse { line: usize, error: ParseError },
+}
+
+pub struct StmtStream<R> {
+    reader: R,
+    builder: Builder,
+    buffer: String,
+    /// 1-based line number of the first line in `buffer`
+    buffer_line: usize,
+    /// Bytes read before `buffer`
+    buffer_offset: usize,
+    /// Unclosed `[` and block comments in `buffer`
+    scan: Scan,
+    ready: VecDeque<Spanned<Stmt>>,
+    started: bool,
+    edition: Option<Edition>,
+    memory_mode: Option<MemoryMode>,
+    strict: bool,
+    done: bool,
+}
+
+impl<R: BufRead> StmtStream<R> {
+    pub fn new(reader: R) -> Self {
+        StmtStream {
+            reader,
+            builder: Builder::default(),
+            buffer: String::new(),
+            buffer_line: 1,
+            buffer_offset: 0,
+            scan: Scan::default(),
+            ready: VecDeque::new(),
+            started: false,
+            edition: None,
+            memory_mode: None,
+            strict: false,
+            done: false,
+        }
+    }
+
+    /// The `--- edition ---` header, once the first statement has been read.
+    pub fn edition(&self) -> Option<Edition> {
+        self.edition
+    }
+
+    /// The `--- auto ---` / `--- manual ---` header, once the first
+    /// statement has been read.
+    pub fn memory_mode(&self) -> Option<MemoryMode> {
+        self.memory_mode
+    }
+
+    /// Whether the file has a `--- strict ---` header, once the first
+    /// statement has been read.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Parse the buffer; `Ok(false)` means it ends mid-statement.
+    fn parse_buffer(&mut self, at_eof: bool) -> Result<bool, StreamError> {
+        // the header can only come before the first statement
+        let rule = if self.started { Rule::items } else { Rule::program };
+        let pairs = match HackerScriptParser::parse(rule, &self.buffer) {
+            Ok(pairs) => pairs,
+            Err(error) => {
+                let pos = match error.location {
+                    InputLocation::Pos(pos) => pos,
+                    InputLocation::Span((start, _)) => start,
+                };
+                if !at_eof && self.buffer[pos..].trim().is_empty() {
+                    return Ok(false);
+                }
+                let error = crate::errors::explain(rule, &self.buffer, error);
+                return Err(StreamError::Parse { line: self.buffer_line, error });
+            }
+        };
+
+        if let Err(error) = editions::check(pairs.clone(), self.edition.unwrap_or_default()) {
+            return Err(StreamError::Parse { line: self.buffer_line, error });
+        }
+        let mut stmts = Vec::new();
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::edition => self.edition = Some(editions::build_edition(pair)),
+                Rule::memory_mode => self.memory_mode = Some(build_memory_mode(pair)),
+                Rule::strict => self.strict = true,
+                Rule::stmt => stmts.push((pair.as_span().start(), pair)),
+                _ => {}
+            }
+        }
+        let keep_from = match stmts.last() {
+            Some((start, _)) if !at_eof => *start,
+            _ => self.buffer.len(),
+        };
+        let mut flushed = false;
+        self.builder.line_offset = self.buffer_line as u32 - 1;
+        self.builder.byte_offset = self.buffer_offset as u32;
+        for (start, pair) in stmts {
+            if start >= keep_from {
+                break;
+            }
+            flushed = true;
+            if let Some(stmt) = self.builder.stmt(pair) {
+                self.ready.push_back(stmt);
+            }
+        }
+        if flushed || at_eof {
+            self.started = true;
+            self.buffer_line += self.buffer[..keep_from].matches('\n').count();
+            self.buffer_offset += keep_from;
+            self.buffer.drain(..keep_from);
+        }
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for StmtStream<R> {
+    type Item = Result<Spanned<Stmt>, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(stmt) = self.ready.pop_front() {
+                return Some(Ok(stmt));
+            }
+            if self.done {
+                return None;
+            }
+            let start = self.buffer.len();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => {
+                    self.done = true;
+                    if let Err(err) = self.parse_buffer(true) {
+                        return Some(Err(err));
+                    }
+                }
+                Ok(_) => {
+                    self.scan.line(&self.buffer[start..]);
+                    if self.scan.balanced() {
+                        if let Err(err) = self.parse_buffer(false) {
+                            self.done = true;
+                            return Some(Err(err));
+                        }
+                    }
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+    }
+}
+
+/// Bracket depth across lines, ignoring strings, `@` comments and `-/ ... -\`
+/// block comments. Inside an `sh [ ... ]` block the shell's rules apply
+/// instead: `@` and `-/` are plain text and quotes, which may be single and
+/// may span lines, hide brackets.
+#[derive(Debug, Default)]
+struct Scan {
+    depth: isize,
+    /// Where each `[` still open starts, outermost first
+    open: Vec<usize>,
+    /// `depth` just outside the `sh [` being scanned
+    sh: Option<isize>,
+    /// Open shell quote
+    quote: Option<char>,
+    /// Where the open block comment starts
+    comment: Option<usize>,
+    /// The first misplaced `-/` or `-\`
+    misplaced: Option<CommentError>,
+    /// Bytes scanned before the current line
+    offset: usize,
+}
+
+/// A block comment the grammar rejects, by byte offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CommentError {
+    /// A `-/` at `at` inside the comment opened at `open`
+    Nested { open: usize, at: usize },
+    /// A `-/` at `open` with no `-\` after it
+    Unclosed { open: usize },
+    /// A `-\` at `at` outside any comment
+    Stray { at: usize },
+}
+
+impl Scan {
+    fn line(&mut self, line: &str) {
+        let mut in_string = false;
+        let mut chars = line.char_indices();
+        while let Some((at, c)) = chars.next() {
+            if self.sh.is_some() {
+                match (self.quote, c) {
+                    (Some('"'), '\\') => {
+                        chars.next();
+                    }
+                    (Some(quote), c) if c == quote => self.quote = None,
+                    (Some(_), _) => {}
+                    (None, '\'' | '"') => self.quote = Some(c),
+                    (None, '[') => self.open_block(at),
+                    (None, ']') => {
+                        self.close_block();
+                        if self.sh == Some(self.depth) {
+                            self.sh = None;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            let rest = &line[at..];
+            if let Some(open) = self.comment {
+                if rest.starts_with("-\\") {
+                    self.comment = None;
+                    chars.next();
+                } else if rest.starts_with("-/") {
+                    self.misplaced.get_or_insert(CommentError::Nested { open, at: self.offset + at });
+                    chars.next();
+                }
+                continue;
+            }
+            match c {
+                '\\' if in_string => {
+                    chars.next();
+                }
+                '"' => in_string = !in_string,
+                '@' if !in_string => break,
+                '-' if !in_string && rest.starts_with("-/") => {
+                    self.comment = Some(self.offset + at);
+                    chars.next();
+                }
+                '-' if !in_string && rest.starts_with("-\\") => {
+                    self.misplaced.get_or_insert(CommentError::Stray { at: self.offset + at });
+                    chars.next();
+                }
+                '[' if !in_string => {
+                    if opens_sh(&line[..at]) {
+                        self.sh = Some(self.depth);
+                    }
+                    self.open_block(at);
+                }
+                ']' if !in_string => self.close_block(),
+                _ => {}
+            }
+        }
+        self.offset += line.len();
+    }
+
+    fn open_block(&mut self, at: usize) {
+        self.depth += 1;
+        self.open.push(self.offset + at);
+    }
+
+    fn close_block(&mut self) {
+        self.depth -= 1;
+        self.open.pop();
+    }
+
+    /// Whether the lines so far could end a statement.
+    fn balanced(&self) -> bool {
+        self.depth <= 0 && self.comment.is_none()
+    }
+}
+
+/// Bracket depth at the end of `source`, as the stream counts it.
+pub(crate) fn depth(source: &str) -> isize {
+    scan(source).depth
+}
+
+/// Where each `[` that `source` never closes starts, outermost first.
+pub(crate) fn unclosed(source: &str) -> Vec<usize> {
+    scan(source).open
+}
+
+/// The first block comment in `source` that is nested, never closed, or
+/// closed without being opened.
+pub(crate) fn comment_error(source: &str) -> Option<CommentError> {
+    let scan = scan(source);
+    scan.misplaced.or(scan.comment.map(|open| CommentError::Unclosed { open }))
+}
+
+fn scan(source: &str) -> Scan {
+    let mut scan = Scan::default();
+    source.split_inclusive('\n').for_each(|line| scan.line(line));
+    scan
+}
+
+/// Whether a `[` after `before` opens an `sh` block: `sh` and spaces at the
+/// start of a statement. `if sh [` is a condition, so a keyword before `sh`
+/// rules it out.
+fn opens_sh(before: &str) -> bool {
+    let word = before.trim_end_matches([' ', '\t']);
+    word.len() < before.len()
+        && word
+            .strip_suffix("sh")
+            .is_some_and(|rest| rest.trim_end_matches([' ', '\t']).is_empty() || rest.ends_with(['[', ']']))
+}
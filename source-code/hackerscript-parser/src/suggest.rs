@@ -0,0 +1,109 @@
+//! Fixes for the syntax errors people make most: a block never closed with
+//! `]`, `=` where a condition compares with `==`, and a string missing its
+//! closing quote. Each is a replacement of part of the source, so a tool
+//! can show it as help or write it back (`hs1 compile --apply-suggestions`).
+use std::ops::Range;
+
+use crate::{errors, stream, ParseError};
+
+/// Replace `span` of the source with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// What the fix does, e.g. "compare with `==`"
+    pub message: String,
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    /// `source` with the fix made.
+    pub fn apply(&self, source: &str) -> String {
+        let mut fixed = source.to_string();
+        fixed.replace_range(self.span.clone(), &self.replacement);
+        fixed
+    }
+
+    /// The lines the fix touches as they read after it.
+    pub fn snippet(&self, source: &str) -> String {
+        let fixed = self.apply(source);
+        let start = fixed[..self.span.start].rfind('\n').map_or(0, |newline| newline + 1);
+        let end = self.span.start + self.replacement.len();
+        let end = fixed[end..].find('\n').map_or(fixed.len(), |newline| end + newline);
+        fixed[start..end.max(start)].trim_end().to_string()
+    }
+}
+
+/// A fix for `err`, the error parsing `source`, if it is one of the
+/// mistakes this module knows.
+pub fn suggest(source: &str, err: &ParseError) -> Option<Suggestion> {
+    let at = errors::error_span(source, err).start;
+    let line_start = source[..at].rfind('\n').map_or(0, |newline| newline + 1);
+    let before = &source[line_start..at];
+    let rest = &source[at..];
+
+    if rest.starts_with(['\n', '\r']) || rest.is_empty() {
+        if let Some(quote) = open_quote(before) {
+            return Some(Suggestion {
+                message: format!("close the string opened at column {} with `\"`", quote + 1),
+                span: at..at,
+                replacement: "\"".to_string(),
+            });
+        }
+    }
+    if rest.starts_with('=') && !rest.starts_with("==") && is_condition(before) {
+        return Some(Suggestion {
+            message: "compare with `==`".to_string(),
+            span: at..at + 1,
+            replacement: "==".to_string(),
+        });
+    }
+    if rest.trim().is_empty() {
+        let open = stream::unclosed(source);
+        if !open.is_empty() {
+            // a `]` per open block, innermost first, indented like its opening line
+            let mut closing = String::new();
+            if !source.is_empty() && !source.ends_with('\n') {
+                closing.push('\n');
+            }
+            for &bracket in open.iter().rev() {
+                let line = &source[source[..bracket].rfind('\n').map_or(0, |newline| newline + 1)..];
+                let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+                closing.push_str(indent);
+                closing.push_str("]\n");
+            }
+            let message = match open.len() {
+                1 => "close the block with `]`".to_string(),
+                n => format!("close the {} open blocks with `]`", n),
+            };
+            return Some(Suggestion { message, span: source.len()..source.len(), replacement: closing });
+        }
+    }
+    None
+}
+
+/// Where the string left open at the end of `line` starts, ignoring a
+/// trailing `@` comment.
+fn open_quote(line: &str) -> Option<usize> {
+    let mut open = None;
+    let mut chars = line.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match (open, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(_), '"') => open = None,
+            (None, '"') => open = Some(at),
+            (None, '@') => break,
+            _ => {}
+        }
+    }
+    open
+}
+
+/// Whether the text before a `=` on its line is the start of an `if` or
+/// `while` condition.
+fn is_condition(before: &str) -> bool {
+    let words: Vec<&str> = before.split_whitespace().collect();
+    let start = words.iter().rposition(|word| matches!(*word, "if" | "while"));
+    start.is_some_and(|start| !words[start..].iter().any(|word| word.ends_with('[')))
+}
@@ -0,0 +1,6 @@
+use hackerscript_ast::{Spanned, Stmt};
+
+/// The statements of `body` without their spans, to match on.
+pub fn nodes(body: &[Spanned<Stmt>]) -> Vec<&Stmt> {
+    body.iter().map(|stmt| &stmt.node).collect()
+}
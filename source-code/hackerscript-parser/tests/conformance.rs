@@ -0,0 +1,79 @@
+//! Conformance suite: every way into the grammar must accept the same
+//! language and build the same program. `parse` and `parse_tree` back hs1,
+//! hs3 and hsdf; `ParsedFile` backs editors and watch modes; `StmtStream`
+//! backs `hs1 compile --stream` and finds statement ends on its own.
+//!
+//! Every file in `conformance/accept` and in the hs1 golden suite must parse
+//! to the same `Program` on each path; every file in `conformance/reject`
+//! must be rejected by all of them.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hackerscript_ast::Program;
+use hackerscript_parser::incremental::ParsedFile;
+use hackerscript_parser::stream::StmtStream;
+
+fn sources(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hcs"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no sources in {}", dir.display());
+    files
+}
+
+fn corpus(dir: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(dir)
+}
+
+/// What each frontend made of `source`, labelled, with errors as text.
+fn frontends(source: &str) -> Vec<(&'static str, Result<Program, String>)> {
+    let tree = hackerscript_parser::parse_tree(source)
+        .map(hackerscript_parser::build_program)
+        .map_err(|e| e.to_string());
+    let full = hackerscript_parser::parse(source).map_err(|e| e.to_string());
+    let incremental = ParsedFile::parse(source).map(|file| file.program()).map_err(|e| e.to_string());
+    let mut stream = StmtStream::new(source.as_bytes());
+    let streamed = (&mut stream)
+        .collect::<Result<Vec<_>, _>>()
+        .map(|body| Program {
+            edition: stream.edition(),
+            memory_mode: stream.memory_mode(),
+            strict: stream.strict(),
+            body,
+        })
+        .map_err(|e| e.to_string());
+    vec![("parse_tree", tree), ("parse", full), ("incremental", incremental), ("stream", streamed)]
+}
+
+#[test]
+fn every_frontend_accepts_the_same_programs() {
+    let mut files = sources(&corpus("tests/conformance/accept"));
+    files.extend(sources(&corpus("../HS1/tests/golden")));
+    for file in files {
+        let source = fs::read_to_string(&file).unwrap();
+        let results = frontends(&source);
+        let expected = match &results[0].1 {
+            Ok(program) => program,
+            Err(err) => panic!("{} is rejected by parse_tree:\n{}", file.display(), err),
+        };
+        for (name, result) in &results[1..] {
+            match result {
+                Ok(program) => assert_eq!(program, expected, "{} builds a different program via {}", file.display(), name),
+                Err(err) => panic!("{} is rejected by {}:\n{}", file.display(), name, err),
+            }
+        }
+    }
+}
+
+#[test]
+fn every_frontend_rejects_the_same_programs() {
+    for file in sources(&corpus("tests/conformance/reject")) {
+        let source = fs::read_to_string(&file).unwrap();
+        for (name, result) in frontends(&source) {
+            assert!(result.is_err(), "{} is accepted by {}", file.display(), name);
+        }
+    }
+}
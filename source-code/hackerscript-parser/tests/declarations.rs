@@ -0,0 +1,192 @@
+use hackerscript_ast::{Expr, Lit, MemoryMode, Stmt};
+
+mod common;
+use common::nodes;
+
+#[test]
+fn parses_const_declarations() {
+    let program = hackerscript_parser::parse("const A = 1\npub const B = A + 1\n").unwrap();
+    match &nodes(&program.body)[..] {
+        [Stmt::Const { public: false, name: a, ty: None, value: Expr::Lit(Lit::Int(1)) }, Stmt::Const { public: true, name: b, ty: None, value: Expr::Binary { .. } }] => {
+            assert_eq!(a, "A");
+            assert_eq!(b, "B");
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn enum_discriminants_follow_c_rules() {
+    let source = "pub enum Color [\n    Red,\n    Green = 10,\n    Blue,\n]\nenum Sign [ Neg = -1, Zero, Pos ]\nenum Empty []\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let enums: Vec<_> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Enum(def) => def,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert!(enums[0].public && !enums[1].public);
+    let values = |i: usize| -> Vec<(String, i64)> {
+        enums[i].discriminants().map(|(name, value)| (name.to_string(), value)).collect()
+    };
+    assert_eq!(values(0), [("Red".into(), 0), ("Green".into(), 10), ("Blue".into(), 11)]);
+    assert_eq!(values(1), [("Neg".into(), -1), ("Zero".into(), 0), ("Pos".into(), 1)]);
+    assert!(values(2).is_empty());
+}
+
+#[test]
+fn objects_record_the_parent_they_extend() {
+    let source = "object Base [\n    func hello() [\n        log \"hi\"\n    ]\n]\nobject Child extends Base [\n]\nobject extends_ [ ]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let objects: Vec<(&str, Option<&str>, usize)> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Object { name, parent, body, .. } => (name.as_str(), parent.as_deref(), body.len()),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(objects, [("Base", None, 1), ("Child", Some("Base"), 0), ("extends_", None, 0)]);
+    assert!(hackerscript_parser::parse("object Child extends [ ]\n").is_err());
+    let program = hackerscript_parser::parse("pub object Point [\n    let x: Int = 0\n]\n").unwrap();
+    assert!(matches!(program.body[0].node, Stmt::Object { public: true, .. }));
+}
+
+#[test]
+fn interfaces_list_method_signatures_that_objects_implement() {
+    let source = "\
+interface Printable [
+    @ shown to the user
+    func print()
+    func format(width: Int, fill = \" \") -> String
+]
+object Doc extends Base implements Printable, Named [
+]
+";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Interface(interface), Stmt::Object { parent, implements, .. }] = &stmts[..] else {
+        panic!("unexpected {:?}", program.body)
+    };
+    assert_eq!(interface.name.as_str(), "Printable");
+    let methods: Vec<(&str, usize)> = interface.methods.iter().map(|m| (m.name.as_str(), m.params.len())).collect();
+    assert_eq!(methods, [("print", 0), ("format", 2)]);
+    assert_eq!(interface.methods[1].ret.as_deref(), Some("String"));
+    assert_eq!(parent.as_deref(), Some("Base"));
+    assert_eq!(implements.iter().map(|name| name.as_str()).collect::<Vec<_>>(), ["Printable", "Named"]);
+    // signatures only
+    assert!(hackerscript_parser::parse("interface I [\n    func f() [\n    ]\n]\n").is_err());
+}
+
+#[test]
+fn trailing_parameters_can_have_defaults() {
+    let program = hackerscript_parser::parse("func greet(name: String, prefix = \"Hello\", times: Int = 1 + 1) [\n]\n").unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Func(func)] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    let defaults: Vec<Option<&Expr>> = func.params.iter().map(|p| p.default.as_ref()).collect();
+    assert_eq!(defaults[..2], [None, Some(&Expr::Lit(Lit::Str("Hello".into())))]);
+    assert!(matches!(defaults[2], Some(Expr::Binary { .. })));
+    assert_eq!(func.params[2].ty.as_deref(), Some("Int"));
+    assert_eq!(func.required_params(), 1);
+
+    let err = hackerscript_parser::parse("func f(a = 1, b) [\n]\n").unwrap_err();
+    assert_eq!(err.line_col, pest::error::LineColLocation::Pos((1, 16)), "{}", err);
+    assert_eq!(err.variant.message(), "expected `:` or `=`, found `)`");
+}
+
+#[test]
+fn a_rest_parameter_comes_last() {
+    for (source, names) in [
+        ("func log_all(...items) [\n]\n", vec!["items"]),
+        ("func f(a, b = 1, ...more: Array) [\n]\n", vec!["a", "b", "more"]),
+    ] {
+        let program = hackerscript_parser::parse(source).unwrap();
+        let stmts = nodes(&program.body);
+        let [Stmt::Func(func)] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+        assert_eq!(func.params.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), names);
+        assert_eq!(func.rest_param().map(|p| p.name.as_str()), names.last().copied());
+        assert_eq!(func.required_params(), names.len().saturating_sub(2).min(1));
+    }
+    assert!(hackerscript_parser::parse("func f(...a, b) [\n]\n").is_err());
+    assert!(hackerscript_parser::parse("func f(...a = 1) [\n]\n").is_err());
+}
+
+#[test]
+fn lambdas_are_expressions() {
+    let program = hackerscript_parser::parse("let f = func (x, y = 2): Int [\n    return x + y\n]\nfunc(x) [\n]\n").unwrap();
+    match &nodes(&program.body)[..] {
+        [Stmt::Let { name, ty: None, value: Expr::Lambda { params, ret: Some(ret), body } }, Stmt::Expr { value: Expr::Lambda { params: bare, .. } }] => {
+            assert_eq!(name, "f");
+            assert_eq!(params.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["x", "y"]);
+            assert_eq!(ret, "Int");
+            assert!(matches!(&nodes(body)[..], [Stmt::Return { value: Some(_) }]));
+            assert_eq!(bare.len(), 1);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    // `funcs(x)` is still a call
+    let program = hackerscript_parser::parse("funcs(x)\n").unwrap();
+    assert!(matches!(&nodes(&program.body)[..], [Stmt::Expr { value: Expr::Call { .. } }]), "{:?}", program.body);
+}
+
+#[test]
+fn bindings_and_return_types_take_annotations() {
+    let source = "let x: number = 5\npub const NAME : string = \"hs\"\nfunc f(a: string) -> number [\n    return 1\n]\nlet g = func () -> bool [ return true ]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    match &nodes(&program.body)[..] {
+        [Stmt::Let { ty: Some(x), .. }, Stmt::Const { ty: Some(name), .. }, Stmt::Func(f), Stmt::Let { ty: None, value: Expr::Lambda { ret: Some(g), .. }, .. }] => {
+            assert_eq!([x.as_str(), name.as_str(), g.as_str()], ["number", "string", "bool"]);
+            assert_eq!(f.params[0].ty.as_deref(), Some("string"));
+            assert_eq!(f.ret.as_deref(), Some("number"));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(hackerscript_parser::parse("func f(): int [\n]\n").unwrap().body, hackerscript_parser::parse("func f() -> int [\n]\n").unwrap().body);
+    assert!(hackerscript_parser::parse("let x: = 1\n").is_err());
+}
+
+#[test]
+fn functions_can_override_the_memory_mode() {
+    let source = "--- auto ---\nfunc hot() manual [\n]\nfunc f(x): int automatic [\n]\nfunc g() [\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let modes: Vec<Option<MemoryMode>> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Func(func) => func.memory_mode,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(modes, [Some(MemoryMode::Manual), Some(MemoryMode::Auto), None]);
+    assert!(hackerscript_parser::parse("func f() manually [\n]\n").is_err());
+    // only named functions take one
+    assert!(hackerscript_parser::parse("let f = func () manual [ ]\n").is_err());
+}
+
+#[test]
+fn a_header_opts_into_strict_mode() {
+    let program = hackerscript_parser::parse("--- auto ---\n--- strict ---\nlog 1\n").unwrap();
+    assert!(program.strict);
+    assert_eq!(program.memory_mode, Some(MemoryMode::Auto));
+    assert!(!hackerscript_parser::parse("log 1\n").unwrap().strict);
+    // the header comes before any statement
+    assert!(hackerscript_parser::parse("log 1\n--- strict ---\n").is_err());
+}
+
+#[test]
+fn functions_can_ask_to_be_inlined() {
+    let program = hackerscript_parser::parse("func f(x) inline manual [\n]\nfunc inlined() [\n]\n").unwrap();
+    let funcs: Vec<(&str, bool, Option<MemoryMode>)> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Func(func) => (func.name.as_str(), func.inline, func.memory_mode),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(funcs, [("f", true, Some(MemoryMode::Manual)), ("inlined", false, None)]);
+    assert!(hackerscript_parser::parse("func f() manual inline [\n]\n").is_err());
+    assert!(hackerscript_parser::parse("func f() inlined [\n]\n").is_err());
+}
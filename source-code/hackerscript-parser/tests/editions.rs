@@ -0,0 +1,54 @@
+use hackerscript_ast::{Edition, Stmt};
+use hackerscript_parser::editions::migrate;
+use hackerscript_parser::incremental::{ParsedFile, TextEdit};
+
+#[test]
+fn the_header_picks_the_edition() {
+    let program = hackerscript_parser::parse("--- edition 2025 ---\n--- auto ---\nlog 1\n").unwrap();
+    assert_eq!(program.edition, Some(Edition::E2025));
+    assert!(program.memory_mode.is_some());
+    assert_eq!(hackerscript_parser::parse("log 1\n").unwrap().edition, None);
+    assert!(hackerscript_parser::parse("--- edition 2030 ---\nlog 1\n").is_err());
+    // the file's header wins over the project's edition
+    let program = hackerscript_parser::parse_in("--- edition 2024 ---\nlet if = 1\n", Edition::E2025).unwrap();
+    assert_eq!(program.edition, Some(Edition::E2024));
+    assert_eq!(hackerscript_parser::parse_in("log 1\n", Edition::E2025).unwrap().edition, Some(Edition::E2025));
+}
+
+#[test]
+fn edition_2025_reserves_keywords_as_names() {
+    let source = "let m = { \"if\": 1 }\nfunc default(enum) [\n    return enum + m.if\n]\n";
+    assert!(hackerscript_parser::parse(source).is_ok());
+    let err = hackerscript_parser::parse_in(source, Edition::E2025).unwrap_err();
+    assert_eq!(err.line_col, pest::error::LineColLocation::Span((2, 6), (2, 13)));
+    assert!(err.to_string().contains("`default` is a keyword in edition 2025"), "{}", err);
+    // field names are map keys, and `sh` is still a function
+    assert!(hackerscript_parser::parse_in("log m.match, sh(\"ls\")\n", Edition::E2025).is_ok());
+}
+
+#[test]
+fn migrate_renames_reserved_names_and_sets_the_header() {
+    let source = "--- auto ---\nlet match = 1\nlet match_ = 2\nlog match + match_, m.match\n";
+    let fixed = migrate(source, Edition::E2025).unwrap();
+    assert_eq!(
+        fixed,
+        "--- edition 2025 ---\n--- auto ---\nlet match__ = 1\nlet match_ = 2\nlog match__ + match_, m.match\n"
+    );
+    assert!(hackerscript_parser::parse(&fixed).is_ok());
+    // an existing header is replaced, and nothing else changes
+    let fixed = migrate("--- edition 2024 ---\nlog 1\n", Edition::E2025).unwrap();
+    assert_eq!(fixed, "--- edition 2025 ---\nlog 1\n");
+    // a file already failing on its edition can still be fixed
+    let fixed = migrate("--- edition 2025 ---\nlet if = 1\n", Edition::E2025).unwrap();
+    assert_eq!(fixed, "--- edition 2025 ---\nlet if_ = 1\n");
+    assert!(migrate("let x = \n", Edition::E2025).is_err());
+}
+
+#[test]
+fn edits_that_use_a_reserved_word_fail() {
+    let mut file = ParsedFile::parse("--- edition 2025 ---\nlet a = 1\nlog a\n").unwrap();
+    let at = "--- edition 2025 ---\nlet ".len();
+    assert!(file.apply(&TextEdit { range: at..at + 1, text: "while".to_string() }).is_err());
+    assert!(matches!(&file.program().body[0].node, Stmt::Let { name, .. } if name == "a"));
+    assert_eq!(file.program().edition, Some(Edition::E2025));
+}
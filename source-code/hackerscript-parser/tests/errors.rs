@@ -0,0 +1,64 @@
+use hackerscript_parser::stream::{StmtStream, StreamError};
+
+/// The message and the source the error points at.
+fn error(source: &str) -> (String, &str) {
+    let err = hackerscript_parser::parse(source).unwrap_err();
+    (err.variant.message().into_owned(), &source[hackerscript_parser::error_span(source, &err)])
+}
+
+#[test]
+fn lists_the_expected_tokens_instead_of_rule_names() {
+    assert_eq!(error("if x [\n    log 1\n"), ("expected `]` or statement, found end of input".to_string(), ""));
+    assert_eq!(error("let x = 1 +\nlog x\n"), ("expected expression, found end of line".to_string(), ""));
+    assert_eq!(error("func f( [ ]\n").0, "expected `)`, `...`, or identifier, found `[`");
+    assert_eq!(error("match x [\n    foo\n]\n"), ("expected `]`, `case`, or `default`, found `foo`".to_string(), "foo"));
+    assert_eq!(error("try [\n]\nlog 1\n"), ("expected `except` or `finally`, found `log`".to_string(), "log"));
+    assert_eq!(error("enum E [ A = -x ]\n"), ("expected digit, found `x`".to_string(), "x"));
+    assert_eq!(error("log \"open\nlog 2\n").0, "expected `\"`, found end of line");
+    assert_eq!(error("let x = a ? 1 log x\n").0, "expected `:` or operator, found `log`");
+}
+
+#[test]
+fn one_statement_per_line() {
+    assert_eq!(error("let x = 2 log x\n"), ("expected operator or end of line, found `log`".to_string(), "log"));
+    assert_eq!(error("f(1) ]\n"), ("expected operator or end of line, found `]`".to_string(), "]"));
+    assert_eq!(error("while x [ break ] log 1\n").0, "expected end of line, found `log`");
+    // inside a block the block may end there too
+    assert_eq!(error("if x [ log 1 log 2 ]\n").0, "expected `,`, `]`, operator, or end of line, found `log`");
+    for source in ["if x [ log 1 ]\n", "let x = 1 @ a comment\nlog x", "log 1   \n\n  \t\n", "f()\r\ng()\r\n"] {
+        assert!(hackerscript_parser::parse(source).is_ok(), "{:?}", source);
+    }
+}
+
+#[test]
+fn points_at_the_furthest_failure_on_one_line() {
+    let source = "log 1\nlet x = 2 ]\nlog 3\n";
+    let err = hackerscript_parser::parse(source).unwrap_err();
+    assert!(matches!(err.line_col, pest::error::LineColLocation::Pos((2, 11))));
+    assert_eq!(hackerscript_parser::error_span(source, &err), 16..17);
+    assert!(err.to_string().contains("found `]`"), "{}", err);
+}
+
+#[test]
+fn block_comments_that_nest_or_never_close() {
+    assert_eq!(
+        error("let x = 1\n-/ outer\n  -/ inner -\\\n-\\\n"),
+        ("block comments do not nest: `-/` inside the comment opened at 2:1".to_string(), "-")
+    );
+    assert_eq!(error("log 1 -/ open\nlog 2\n"), ("block comment is never closed with `-\\`".to_string(), "-"));
+    assert_eq!(error("log 1 -\\\n"), ("`-\\` closes no block comment".to_string(), "-"));
+    // inside strings and line comments they are plain text
+    assert_eq!(error("log \"-/\" @ -/\nlog (\n").0, "expected expression, found end of line");
+}
+
+#[test]
+fn stream_errors_are_explained_too() {
+    let results: Vec<_> = StmtStream::new("log 1\nlog 2 )\nlog 3\n".as_bytes()).collect();
+    match results.last() {
+        Some(Err(StreamError::Parse { error, .. })) => {
+            assert!(error.variant.message().starts_with("expected "), "{}", error);
+            assert!(error.variant.message().ends_with("found `)`"), "{}", error);
+        }
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
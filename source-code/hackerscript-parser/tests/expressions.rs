@@ -0,0 +1,95 @@
+use hackerscript_ast::{Expr, Lit, Stmt};
+
+mod common;
+use common::nodes;
+
+/// The expression of `let x = <source>`, fully parenthesized.
+fn grouped(source: &str) -> String {
+    let program = hackerscript_parser::parse(&format!("let x = {}\n", source)).unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Let { value, .. }] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    render(value)
+}
+
+fn render(expr: &Expr) -> String {
+    match expr {
+        Expr::Lit(Lit::Int(n)) => n.to_string(),
+        Expr::Lit(Lit::Str(s)) => format!("{:?}", s),
+        Expr::Var { name } => name.to_string(),
+        Expr::Binary { op, lhs, rhs } => format!("({} {} {})", render(lhs), op.symbol(), render(rhs)),
+        Expr::Index { target, index } => format!("{}[{}]", render(target), render(index)),
+        Expr::Slice { target, start, end } => {
+            let bound = |bound: &Option<Box<Expr>>| bound.as_deref().map(render).unwrap_or_default();
+            format!("{}[{}:{}]", render(target), bound(start), bound(end))
+        }
+        Expr::Ternary { cond, then_value, else_value } => {
+            format!("({} ? {} : {})", render(cond), render(then_value), render(else_value))
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+#[test]
+fn multiplication_binds_tighter_than_addition() {
+    assert_eq!(grouped("1 + 2 * 3"), "(1 + (2 * 3))");
+    assert_eq!(grouped("1 * 2 + 3"), "((1 * 2) + 3)");
+    assert_eq!(grouped("a - b % c / d"), "(a - ((b % c) / d))");
+}
+
+#[test]
+fn operators_of_one_level_associate_to_the_left() {
+    assert_eq!(grouped("10 - 4 - 3"), "((10 - 4) - 3)");
+    assert_eq!(grouped("8 / 4 / 2"), "((8 / 4) / 2)");
+    assert_eq!(grouped("a || b || c"), "((a || b) || c)");
+}
+
+#[test]
+fn parentheses_override_precedence() {
+    assert_eq!(grouped("(1 + 2) * 3"), "((1 + 2) * 3)");
+    assert_eq!(grouped("1 - (2 - 3)"), "(1 - (2 - 3))");
+    assert_eq!(grouped("( a || b ) && c"), "((a || b) && c)");
+}
+
+#[test]
+fn comparisons_sit_between_arithmetic_and_logic() {
+    assert_eq!(grouped("a + 1 < b * 2"), "((a + 1) < (b * 2))");
+    assert_eq!(grouped("a < b == c > d"), "((a < b) == (c > d))");
+    assert_eq!(grouped("a == 1 || b != 2 && c"), "((a == 1) || ((b != 2) && c))");
+    assert_eq!(grouped("m[a] + 1 && n"), "((m[a] + 1) && n)");
+}
+
+#[test]
+fn parses_slices_with_optional_bounds() {
+    let program = hackerscript_parser::parse("let x = s[1:n - 1]\nlet y = s[:-2]\nlet z = s[ 3 : ]\nlet w = s[:]\n").unwrap();
+    let bounds: Vec<(Option<String>, Option<String>)> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Let { value: Expr::Slice { start, end, .. }, .. } => {
+                (start.as_deref().map(render), end.as_deref().map(render))
+            }
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(bounds, [(some("1"), some("(n - 1)")), (None, some("-2")), (some("3"), None), (None, None)]);
+    assert_eq!(grouped("s[1:2][0] + 1"), "(s[1:2][0] + 1)");
+}
+
+#[test]
+fn conditionals_bind_loosest_and_chain_to_the_right() {
+    assert_eq!(grouped("a > 1 ? a - 1 : 0"), "((a > 1) ? (a - 1) : 0)");
+    assert_eq!(grouped("a || b ? x : y && z"), "((a || b) ? x : (y && z))");
+    assert_eq!(grouped("a ? 1 : b ? 2 : 3"), "(a ? 1 : (b ? 2 : 3))");
+    assert_eq!(grouped("a ? b ? 1 : 2 : 3"), "(a ? (b ? 1 : 2) : 3)");
+    assert_eq!(grouped("(a ? 1 : 2) + 1"), "((a ? 1 : 2) + 1)");
+    assert_eq!(grouped("s[a ? 0 : 1:n]"), "s[(a ? 0 : 1):n]");
+    assert!(hackerscript_parser::parse("let x = a ? 1\n").is_err());
+}
+
+#[test]
+fn field_access_is_an_index_by_name() {
+    assert_eq!(grouped("r.stdout"), "r[\"stdout\"]");
+    assert_eq!(grouped("r.code == 0 && a.b[1].c"), "((r[\"code\"] == 0) && a[\"b\"][1][\"c\"])");
+    assert_eq!(grouped("1.5 + r.x"), grouped("1.5 + r[\"x\"]"));
+}
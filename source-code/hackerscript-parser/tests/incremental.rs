@@ -0,0 +1,98 @@
+use hackerscript_ast::{Span, Spanned, Stmt};
+use hackerscript_parser::incremental::{ParsedFile, Reparse, TextEdit};
+
+const SOURCE: &str = "--- auto ---\nlet a = 1\nlog a + 2\nfunc f(x: int) [\n    log x\n]\n@ comment\nif a > 0 [\n    log \"pos\"\n] else [\n    log \"neg\"\n]\nlog \"end\"\n";
+
+/// Every span in `body`, nested ones included.
+fn spans(mut body: Vec<Spanned<Stmt>>) -> Vec<Span> {
+    let mut spans = Vec::new();
+    hackerscript_ast::visit_spans_mut(&mut body, &mut |span| spans.push(*span));
+    spans
+}
+
+fn edit(file: &mut ParsedFile, find: &str, text: &str) -> Reparse {
+    let start = file.source().find(find).expect("edit target");
+    let edit = TextEdit { range: start..start + find.len(), text: text.to_string() };
+    let reparse = file.apply(&edit).expect("edit should parse");
+    let full = hackerscript_parser::parse(file.source()).unwrap();
+    assert_eq!(file.program(), full);
+    assert_eq!(spans(file.program().body), spans(full.body), "spans after an edit");
+    reparse
+}
+
+#[test]
+fn reparses_only_the_touched_items() {
+    let mut file = ParsedFile::parse(SOURCE).unwrap();
+    let total = file.items().len();
+    let reparse = edit(&mut file, "log a + 2", "log a * 3");
+    assert!(!reparse.full);
+    assert!(reparse.items <= 3 && reparse.items < total);
+}
+
+#[test]
+fn handles_edits_that_split_and_join_items() {
+    let mut file = ParsedFile::parse(SOURCE).unwrap();
+    edit(&mut file, "log a + 2\n", "log a\nlog 2\nlet b = a\n");
+    edit(&mut file, "\nlog 2", " + 2");
+    edit(&mut file, "    log x\n", "    log x\n    return x\n");
+    edit(&mut file, "log \"end\"\n", "");
+}
+
+#[test]
+fn header_edits_reparse_everything() {
+    let mut file = ParsedFile::parse(SOURCE).unwrap();
+    let reparse = edit(&mut file, "auto", "manual");
+    assert!(reparse.full);
+}
+
+#[test]
+fn keeps_previous_state_on_syntax_error() {
+    let mut file = ParsedFile::parse(SOURCE).unwrap();
+    let start = file.source().find("let a").unwrap();
+    assert!(file.apply(&TextEdit { range: start..start + 3, text: "let (".into() }).is_err());
+    assert_eq!(file.source(), SOURCE);
+    assert_eq!(file.program(), hackerscript_parser::parse(SOURCE).unwrap());
+}
+
+#[test]
+fn rejects_edits_outside_the_source_or_inside_a_character() {
+    let source = "log \"héllo\"\n";
+    let mut file = ParsedFile::parse(source).unwrap();
+    let inside = source.find('é').unwrap() + 1;
+    for range in [0..source.len() + 1, inside..inside, inside - 1..inside, std::ops::Range { start: 9, end: 5 }] {
+        let err = file.apply(&TextEdit { range: range.clone(), text: "x".into() }).unwrap_err();
+        assert!(err.to_string().contains("is outside the source or splits a character"), "{:?}: {}", range, err);
+        assert_eq!(file.source(), source);
+    }
+}
+
+#[test]
+fn random_edits_match_a_full_parse() {
+    let snippets = ["log 1\n", " + 2", "\n", "[", "]", "x", "let y = 3\n", "\"s\"", " ", "if y [\n", "@ c\n"];
+    let mut seed: u64 = 0x5eed;
+    let mut next = |n: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % n.max(1)
+    };
+    for _ in 0..300 {
+        let mut file = ParsedFile::parse(SOURCE).unwrap();
+        for _ in 0..5 {
+            let len = file.source().len();
+            let start = next(len + 1);
+            let end = (start + next(8)).min(len);
+            if !file.source().is_char_boundary(start) || !file.source().is_char_boundary(end) {
+                continue;
+            }
+            let text = snippets[next(snippets.len())].to_string();
+            let mut expected = file.source().to_string();
+            expected.replace_range(start..end, &text);
+            match file.apply(&TextEdit { range: start..end, text }) {
+                Ok(_) => {
+                    assert_eq!(file.source(), expected);
+                    assert_eq!(file.program(), hackerscript_parser::parse(&expected).unwrap(), "{:?}", expected);
+                }
+                Err(_) => assert!(hackerscript_parser::parse(&expected).is_err(), "{:?}", expected),
+            }
+        }
+    }
+}
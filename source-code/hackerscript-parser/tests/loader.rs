@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hackerscript_ast::{Expr, Lit, Spanned, Stmt};
+use hackerscript_parser::loader::{self, LoadError, Loader};
+
+/// A fresh directory tree with the given files.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("loader").join(name);
+    fs::remove_dir_all(&root).ok();
+    for (path, text) in files {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    root
+}
+
+/// The string each top-level `let` in `body` binds, in order.
+fn lets(body: &[Spanned<Stmt>]) -> Vec<String> {
+    body.iter()
+        .filter_map(|stmt| match &stmt.node {
+            Stmt::Let { value: Expr::Lit(Lit::Str(s)), .. } => Some(s.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn parses_both_import_forms() {
+    let program = hackerscript_parser::parse("import <app.utils.net>\nimport <core:term>\nimport <utils>\n").unwrap();
+    let paths: Vec<String> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::ImportModule { path } => path.iter().map(|s| s.to_string()).collect::<Vec<_>>().join("."),
+            Stmt::Import { repo, lib } => format!("{}:{}", repo, lib),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(paths, ["app.utils.net", "core:term", "utils"]);
+}
+
+#[test]
+fn src_root_is_found_from_nested_scripts() {
+    let root = project("src-root", &[("proj/src/app/main.hcs", ""), ("proj/bin/tool.hcs", ""), ("loose/x.hcs", "")]);
+    let src = fs::canonicalize(root.join("proj/src")).unwrap();
+    assert_eq!(loader::src_root(&root.join("proj/src/app/main.hcs")), src);
+    assert_eq!(loader::src_root(&root.join("proj/bin/tool.hcs")), src);
+}
+
+#[test]
+fn resolves_src_before_the_search_path_and_includes_each_file_once() {
+    let root = project(
+        "order",
+        &[
+            ("proj/src/main.hcs", "import <app.net>\nimport <lib:extra>\nrequire <local>\nimport <app.net>\nlet main = \"main\"\n"),
+            ("proj/src/local.hcs", "let local = \"local\"\n"),
+            ("proj/src/app/net.hcs", "require <../local>\nlet net = \"src net\"\n"),
+            ("shared/app/net.hcs", "let net = \"shared net\"\n"),
+            ("shared/lib/extra.hcs", "import <app.net>\nlet extra = \"extra\"\n"),
+        ],
+    );
+    let entry = root.join("proj/src/main.hcs");
+    let mut loader = Loader::new(loader::src_root(&entry), vec![root.join("shared")]);
+    let program = loader.load(&entry).unwrap();
+    assert_eq!(lets(&program.body), ["local", "src net", "extra", "main"]);
+    assert!(program.body.iter().all(|stmt| !matches!(&stmt.node, Stmt::Import { .. } | Stmt::ImportModule { .. } | Stmt::Require { .. })));
+}
+
+#[test]
+fn the_prelude_comes_first_and_once() {
+    let root = project(
+        "prelude",
+        &[
+            ("src/main.hcs", "require <util>\nlet main = \"main\"\n"),
+            ("src/util.hcs", "let util = \"util\"\n"),
+            ("shared/prelude.hcs", "require <../src/util>\nlet prelude = \"prelude\"\n"),
+        ],
+    );
+    let entry = root.join("src/main.hcs");
+    let mut loader = Loader::new(loader::src_root(&entry), Vec::new());
+    loader.set_prelude(Some(root.join("shared/prelude.hcs")));
+    let program = loader.load(&entry).unwrap();
+    assert_eq!(lets(&program.body), ["util", "prelude", "main"]);
+
+    let mut loader = Loader::new(loader::src_root(&entry), Vec::new());
+    loader.set_prelude(Some(root.join("shared/missing.hcs")));
+    assert!(matches!(loader.load(&entry), Err(LoadError::Io { .. })));
+}
+
+#[test]
+fn reports_missing_modules_with_every_place_searched() {
+    let root = project("missing", &[("src/main.hcs", "import <no.such>\n")]);
+    let entry = root.join("src/main.hcs");
+    let err = Loader::new(loader::src_root(&entry), vec![root.join("elsewhere")]).load(&entry).unwrap_err();
+    let LoadError::NotFound { module, searched, .. } = &err else { panic!("unexpected {:?}", err) };
+    assert_eq!(module, "no.such");
+    assert_eq!(searched.len(), 2);
+    assert!(err.to_string().contains("elsewhere/no/such.hcs"), "{}", err);
+}
+
+#[test]
+fn parse_errors_name_the_module() {
+    let root = project("bad-module", &[("main.hcs", "require <bad>\n"), ("bad.hcs", "let = 1\n")]);
+    let err = loader::load(&root.join("main.hcs")).unwrap_err();
+    assert!(matches!(&err, LoadError::Parse { path, .. } if path.ends_with("bad.hcs")), "{:?}", err);
+    assert!(err.to_string().contains("bad.hcs"), "{}", err);
+}
+
+#[test]
+fn reports_import_cycles_as_a_chain() {
+    let root = project(
+        "cycle",
+        &[
+            ("src/main.hcs", "require <a>\n"),
+            ("src/a.hcs", "let a = \"a\"\nimport <lib.b>\n"),
+            ("src/lib/b.hcs", "require <../a>\n"),
+        ],
+    );
+    let err = loader::load(&root.join("src/main.hcs")).unwrap_err();
+    let LoadError::Cycle { cycle } = &err else { panic!("unexpected {:?}", err) };
+    let imports: Vec<(u32, &str)> = cycle.iter().map(|(_, line, import)| (*line, import.as_str())).collect();
+    assert_eq!(imports, [(2, "import <lib.b>"), (1, "require <../a>")]);
+    assert!(cycle[0].0.ends_with("a.hcs") && cycle[1].0.ends_with("lib/b.hcs"), "{:?}", cycle);
+    let message = err.to_string();
+    let chain = message.lines().next().unwrap();
+    assert!(chain.starts_with("import cycle: ") && chain.ends_with("a.hcs"), "{}", message);
+    assert_eq!(chain.matches(" -> ").count(), 2, "{}", message);
+    assert!(message.lines().nth(1).unwrap().ends_with("a.hcs:2: import <lib.b>"), "{}", message);
+
+    // a file importing itself is the shortest cycle; a diamond is not one
+    let root = project("self", &[("main.hcs", "require <main>\n")]);
+    assert!(matches!(loader::load(&root.join("main.hcs")), Err(LoadError::Cycle { cycle }) if cycle.len() == 1));
+    let root = project(
+        "diamond",
+        &[("main.hcs", "require <a>\nrequire <b>\n"), ("a.hcs", "require <c>\n"), ("b.hcs", "require <c>\n"), ("c.hcs", "")],
+    );
+    assert!(loader::load(&root.join("main.hcs")).is_ok());
+}
+
+fn builtin(name: &str) -> Option<&'static str> {
+    (name == "core:greet").then_some("let greet = \"builtin\"\n")
+}
+
+#[test]
+fn builtins_are_kept_or_expanded_when_no_file_has_them() {
+    let root = project("builtins", &[("src/main.hcs", "import <core:greet>\nimport <core:greet>\nlet main = \"main\"\n")]);
+    let entry = root.join("src/main.hcs");
+    let mut expanding = Loader::new(loader::src_root(&entry), Vec::new());
+    expanding.set_builtins(builtin, false);
+    assert_eq!(lets(&expanding.load(&entry).unwrap().body), ["builtin", "main"]);
+
+    let mut keeping = Loader::new(loader::src_root(&entry), Vec::new());
+    keeping.set_builtins(builtin, true);
+    let program = keeping.load(&entry).unwrap();
+    assert!(matches!(&program.body[0].node, Stmt::Import { repo, lib } if repo == "core" && lib == "greet"));
+    assert_eq!(lets(&program.body), ["main"]);
+
+    // a file in the search path comes first
+    fs::create_dir_all(root.join("src/core")).unwrap();
+    fs::write(root.join("src/core/greet.hcs"), "let greet = \"file\"\n").unwrap();
+    assert_eq!(lets(&keeping.clone().load(&entry).unwrap().body), ["file", "main"]);
+}
+
+#[test]
+fn each_file_knows_its_module_name_and_path() {
+    let root = project(
+        "introspect",
+        &[
+            ("src/main.hcs", "import <app.net>\nrequire <local>\nlet main = __module__\nlet main_file = __file__\n"),
+            ("src/local.hcs", "let local = __module__\n"),
+            (
+                "src/app/net.hcs",
+                "func where() [\n    return __module__\n]\nlet net = __module__\nimport <core:greet>\n",
+            ),
+        ],
+    );
+    let entry = root.join("src/main.hcs");
+    let mut loader = Loader::new(loader::src_root(&entry), Vec::new());
+    loader.set_builtins(|name| (name == "core:greet").then_some("let greet = __file__\n"), false);
+    let program = loader.load(&entry).unwrap();
+    let main_file = entry.display().to_string();
+    assert_eq!(lets(&program.body), ["app.net", "core:greet", "local", "main", main_file.as_str()]);
+    let Stmt::Func(func) = &program.body[0].node else { panic!("unexpected {:?}", program.body) };
+    assert!(matches!(&func.body[0].node, Stmt::Return { value: Some(Expr::Lit(Lit::Str(name))) } if name == "app.net"));
+}
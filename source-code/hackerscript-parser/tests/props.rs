@@ -0,0 +1,37 @@
+use proptest::prelude::*;
+
+// Fragments the grammar cares about, so random input gets past the header
+// and into statement and expression rules instead of failing on byte 0.
+const TOKENS: &[&str] = &[
+    "--- auto ---\n", "--- manual ---\n", "let ", "if ", "else ", "func ", "pub ", "object ", "return",
+    "log ", "import <", "require ", "[", "]", "(", ")", ",", ":", "=", "+", "-", "*", "/", "%", "==", "!=",
+    "<", ">", "<=", ">=", "\"", "\\", "@", "\n", " ", "x", "f", "1", "2.5", "null", "int", "core:term",
+];
+
+fn tokens() -> impl Strategy<Value = String> {
+    let header = prop::sample::select(&["", "--- auto ---\n"][..]);
+    let body = prop::collection::vec(prop::sample::select(TOKENS), 0..40);
+    (header, body).prop_map(|(header, parts)| format!("{}{}", header, parts.concat()))
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics_on_arbitrary_text(source in any::<String>()) {
+        let _ = hackerscript_parser::parse(&source);
+    }
+
+    #[test]
+    fn parse_never_panics_on_token_soup(source in tokens()) {
+        let _ = hackerscript_parser::parse(&source);
+    }
+
+    #[test]
+    fn stream_never_panics_on_token_soup(source in tokens()) {
+        let stream = hackerscript_parser::stream::StmtStream::new(source.as_bytes());
+        for item in stream.take(64) {
+            if item.is_err() {
+                break;
+            }
+        }
+    }
+}
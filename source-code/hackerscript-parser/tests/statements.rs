@@ -0,0 +1,232 @@
+use hackerscript_ast::{Expr, Lit, LogLevel, Pattern, Stmt};
+
+mod common;
+use common::nodes;
+
+#[test]
+fn parses_match_with_cases_comments_and_default() {
+    let source = "match x [\n    case 1 [\n        log \"one\"\n    ]\n    @ two\n    case 2 [\n    ]\n    default [\n        log \"many\"\n    ]\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Match { subject: Expr::Var { name }, cases, default }] = &stmts[..] else {
+        panic!("unexpected {:?}", program.body);
+    };
+    assert_eq!(name, "x");
+    let values: Vec<&Expr> = cases.iter().map(|case| &case.value).collect();
+    assert_eq!(values, [&Expr::Lit(Lit::Int(1)), &Expr::Lit(Lit::Int(2))]);
+    assert_eq!(cases[0].body.len(), 1);
+    assert!(cases[1].body.is_empty());
+    assert_eq!(default.len(), 1);
+}
+
+#[test]
+fn match_arms_can_destructure() {
+    let source = "match x [\n    case {host, port} [\n    ]\n    case [first, ...rest] [\n    ]\n    case { \"k\": 1 } [\n    ]\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Match { cases, .. }] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    let patterns: Vec<Option<&Pattern>> = cases.iter().map(|case| case.pattern.as_ref()).collect();
+    assert_eq!(
+        patterns,
+        [
+            Some(&Pattern::Map { names: vec!["host".into(), "port".into()] }),
+            Some(&Pattern::Array { names: vec!["first".into()], rest: Some("rest".into()) }),
+            None,
+        ]
+    );
+    // a map literal is still a value to compare with
+    assert!(matches!(cases[2].value, Expr::Map { .. }));
+}
+
+#[test]
+fn match_without_cases_or_default() {
+    let program = hackerscript_parser::parse("match f() [\n]\n").unwrap();
+    assert!(matches!(&nodes(&program.body)[..], [Stmt::Match { cases, default, .. }] if cases.is_empty() && default.is_empty()));
+    assert!(hackerscript_parser::parse("match x [\n    default [\n    ]\n    case 1 [\n    ]\n]\n").is_err());
+}
+
+#[test]
+fn parses_map_literals_and_indexing() {
+    let program = hackerscript_parser::parse("let m = {\n    \"a\": 1,\n    \"b\": { \"c\": x },\n}\nlog m[\"b\"][\"c\"] + 1\n").unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Let { value: Expr::Map { entries }, .. }, Stmt::Log { level: None, values }] = &stmts[..] else {
+        panic!("unexpected {:?}", program.body);
+    };
+    let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["a", "b"]);
+    // indexing binds tighter than `+`
+    let [Expr::Binary { lhs, .. }] = &values[..] else { panic!("unexpected {:?}", values) };
+    let Expr::Index { target, index } = lhs.as_ref() else { panic!("unexpected {:?}", lhs) };
+    assert!(matches!(target.as_ref(), Expr::Index { .. }));
+    assert_eq!(index.as_ref(), &Expr::Lit(Lit::Str("c".into())));
+}
+
+#[test]
+fn a_space_before_the_bracket_opens_a_block() {
+    let program = hackerscript_parser::parse("if m [\n    log 1\n]\nif m[\"k\"] [\n    log 2\n]\n").unwrap();
+    let conds: Vec<&Expr> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::If { cond, .. } => cond,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert!(matches!(conds[0], Expr::Var { .. }));
+    assert!(matches!(conds[1], Expr::Index { .. }));
+}
+
+#[test]
+fn parses_boolean_literals_but_not_longer_names() {
+    let program = hackerscript_parser::parse("log true, false, trueish, false_\n").unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Log { level: None, values }] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    assert_eq!(values[0], Expr::Lit(Lit::Bool(true)));
+    assert_eq!(values[1], Expr::Lit(Lit::Bool(false)));
+    assert!(matches!(&values[2..], [Expr::Var { .. }, Expr::Var { .. }]));
+}
+
+#[test]
+fn parses_leveled_logs() {
+    let program = hackerscript_parser::parse("log.warn \"disk\", 93\nlog.debug x\nlog \"plain\"\n").unwrap();
+    let levels: Vec<Option<LogLevel>> = program
+        .body
+        .iter()
+        .map(|stmt| match &stmt.node {
+            Stmt::Log { level, .. } => *level,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(levels, [Some(LogLevel::Warn), Some(LogLevel::Debug), None]);
+    assert!(hackerscript_parser::parse("log.trace x\n").is_err());
+}
+
+#[test]
+fn log_takes_any_expressions_with_or_without_parentheses() {
+    let parse = |source: &str| hackerscript_parser::parse(source).unwrap().body;
+    assert_eq!(parse("log(x)\n"), parse("log x\n"));
+    assert_eq!(parse("log(a, \"b\") @ both\n"), parse("log a, \"b\"\n"));
+    assert_eq!(parse("log.info(a, b + 1)\n"), parse("log.info a, b + 1\n"));
+    assert_eq!(parse("log(a) + b\n"), parse("log a + b\n"));
+    assert_eq!(parse("if x [ log(1, 2) ]\n"), parse("if x [ log 1, 2 ]\n"));
+    assert!(matches!(&nodes(&parse("log \"a\" + b\n"))[..], [Stmt::Log { values, .. }] if matches!(values[..], [Expr::Binary { .. }])));
+    // not a statement on its own, so a call like any other
+    assert!(matches!(&nodes(&parse("logx\n"))[..], [Stmt::Expr { .. }]));
+    assert!(matches!(&nodes(&parse("log(a, b) + 1\n"))[..], [Stmt::Expr { .. }]));
+}
+
+#[test]
+fn unescapes_string_literals() {
+    let program = hackerscript_parser::parse("log \"say \\\"hi\\\"\\n\\tC:\\\\tmp \\{x\\}\", { \"a\\\"b\": 1 }\n").unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::Log { values, .. }] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    assert_eq!(values[0], Expr::Lit(Lit::Str("say \"hi\"\n\tC:\\tmp {x}".into())));
+    let Expr::Map { entries } = &values[1] else { panic!("unexpected {:?}", values[1]) };
+    assert_eq!(entries[0].0, "a\"b");
+
+    for bad in ["log \"\\q\"\n", "log \"trailing \\\"\n", "log \"open\n\"\n"] {
+        assert!(hackerscript_parser::parse(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn escape_is_the_inverse_of_unescape() {
+    for text in ["", "plain", "a \"b\" \\ c\n\t\r {d}"] {
+        assert_eq!(hackerscript_parser::unescape(&hackerscript_parser::escape(text)), text);
+    }
+}
+
+#[test]
+fn parses_break_and_continue_but_not_longer_names() {
+    let program = hackerscript_parser::parse("while 1 [\n    break\n    continue\n    breaker\n]\n").unwrap();
+    let stmts = nodes(&program.body);
+    let [Stmt::While { body, .. }] = &stmts[..] else { panic!("unexpected {:?}", program.body) };
+    assert_eq!(body[..2], [Stmt::Break, Stmt::Continue]);
+    assert!(matches!(&body[2].node, Stmt::Expr { value: Expr::Var { .. } }));
+}
+
+#[test]
+fn asm_blocks_keep_their_source_for_the_assembler() {
+    let program = hackerscript_parser::parse("asm [\n    push_const \"]\"  @ not the end ]\n    log_string\n]\nlog 1\n").unwrap();
+    assert_eq!(program.body[0], Stmt::Asm { code: "\n    push_const \"]\"  @ not the end ]\n    log_string\n".into() });
+    assert!(matches!(program.body[1].node, Stmt::Log { .. }));
+}
+
+#[test]
+fn parses_try_with_except_and_finally() {
+    let source = "try [\n    risky()\n] except err [\n    log err.message\n]\nfinally [\n    log \"done\"\n]\ntry [\n] except [\n]\ntry [\n    throw \"x\"\n] finally [\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    match &nodes(&program.body)[..] {
+        [Stmt::Try { body, except: Some(except), finally }, Stmt::Try { except: Some(bare), .. }, Stmt::Try { body: thrower, except: None, .. }] => {
+            assert_eq!(body.len(), 1);
+            assert_eq!(except.name.as_deref(), Some("err"));
+            assert_eq!(except.body.len(), 1);
+            assert_eq!(finally.len(), 1);
+            assert_eq!(bare.name, None);
+            assert_eq!(thrower[..], [Stmt::Throw { value: Expr::Lit(Lit::Str("x".into())) }]);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    // a `try` needs something after it
+    assert!(hackerscript_parser::parse("try [\n    log 1\n]\n").is_err());
+}
+
+#[test]
+fn sh_blocks_keep_one_command_per_line() {
+    let source = "sh [ make test ]\nsh [\n    cd build\n\n    if [ -f out ]; then echo \"]\"; fi\n]\nlog 1\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    assert_eq!(program.body[0], Stmt::Sh { commands: vec!["make test".into()] });
+    assert_eq!(
+        program.body[1],
+        Stmt::Sh { commands: vec!["cd build".into(), "if [ -f out ]; then echo \"]\"; fi".into()] }
+    );
+    assert!(matches!(program.body[2].node, Stmt::Log { .. }));
+    // `sh(...)` is still the native
+    let program = hackerscript_parser::parse("let r = sh(\"ls\")\n").unwrap();
+    assert!(matches!(&nodes(&program.body)[..], [Stmt::Let { value: Expr::Call { .. }, .. }]), "{:?}", program.body);
+}
+
+#[test]
+fn records_the_line_of_every_statement() {
+    let source = "log 1\n\nif x [\n    log 2\n]\n";
+    let program = hackerscript_parser::parse(source).unwrap();
+    let lines: Vec<u32> = program.body.iter().map(|stmt| stmt.span.line).collect();
+    assert_eq!(lines, [1, 3]);
+    let span = program.body[0].span;
+    assert_eq!(&source[span.start as usize..span.end as usize], "log 1");
+    let Stmt::If { then_body, .. } = &program.body[1].node else {
+        panic!("unexpected {:?}", program.body);
+    };
+    assert_eq!(then_body[0].span.line, 4);
+}
+
+#[test]
+fn block_comments_go_wherever_a_space_may() {
+    let commented = "-/ header\n   [ spans lines -\\\nlet x = 1 -/ inline -\\ + 2\nif x > 2 [\n    -/ alone -\\\n    log \"-/ not a comment -\\\\\" -/ after -\\\n]\nmatch x [\n    -/ before a case -\\\n    case 3 [\n    ]\n]\n";
+    let plain = "let x = 1 + 2\nif x > 2 [\n    log \"-/ not a comment -\\\\\"\n]\nmatch x [\n    case 3 [\n    ]\n]\n";
+    let commented = hackerscript_parser::parse(commented).unwrap();
+    let plain = hackerscript_parser::parse(plain).unwrap();
+    assert_eq!(nodes(&commented.body), nodes(&plain.body));
+}
+
+#[test]
+fn parses_destructuring_lets() {
+    let program = hackerscript_parser::parse("let [a, b, ...rest] = xs\nlet {x, y} = point\nlet [...all] = xs\n").unwrap();
+    let patterns: Vec<&Pattern> = nodes(&program.body)
+        .into_iter()
+        .map(|stmt| match stmt {
+            Stmt::Destructure { pattern, .. } => pattern,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        patterns,
+        [
+            &Pattern::Array { names: vec!["a".into(), "b".into()], rest: Some("rest".into()) },
+            &Pattern::Map { names: vec!["x".into(), "y".into()] },
+            &Pattern::Array { names: Vec::new(), rest: Some("all".into()) },
+        ]
+    );
+    assert!(hackerscript_parser::parse("let [] = xs\n").is_err());
+    assert!(hackerscript_parser::parse("let [...rest, a] = xs\n").is_err());
+}
@@ -0,0 +1,50 @@
+use hackerscript_ast::{MemoryMode, Span, Spanned, Stmt};
+use hackerscript_parser::stream::{StmtStream, StreamError};
+
+const SOURCE: &str = "\n--- manual ---\n\nlet a = 1 + 2\n@ comment\nif a > 2 [\n    log \"big\"\n]\nelse [\n    log \"small\"\n]\nfunc f(x: int) [\n    if x [\n        log x\n    ]\n]\nlog \"[not a bracket\" @ ]\nlog a";
+
+/// Every span in `body`, nested ones included.
+fn spans(mut body: Vec<Spanned<Stmt>>) -> Vec<Span> {
+    let mut spans = Vec::new();
+    hackerscript_ast::visit_spans_mut(&mut body, &mut |span| spans.push(*span));
+    spans
+}
+
+#[test]
+fn yields_the_same_statements_as_a_full_parse() {
+    let mut stream = StmtStream::new(SOURCE.as_bytes());
+    let stmts: Vec<_> = (&mut stream).collect::<Result<_, _>>().unwrap();
+    let program = hackerscript_parser::parse(SOURCE).unwrap();
+    assert_eq!(stmts, program.body);
+    assert_eq!(spans(stmts), spans(program.body));
+    assert_eq!(stream.memory_mode(), Some(MemoryMode::Manual));
+}
+
+#[test]
+fn waits_for_block_comments_to_close() {
+    let source = "log 1\n-/ a comment [\n   over lines\n-\\\nlog 2 -/ and\n ] after -\\\nlog 3\n";
+    let stmts: Vec<_> = StmtStream::new(source.as_bytes()).collect::<Result<_, _>>().unwrap();
+    assert_eq!(stmts, hackerscript_parser::parse(source).unwrap().body);
+    assert_eq!(stmts.len(), 3);
+}
+
+#[test]
+fn reports_the_line_of_the_failing_statement() {
+    let source = "log 1\nlog 2\nif x [\n    log (\n]\nlog 3\n";
+    let results: Vec<_> = StmtStream::new(source.as_bytes()).collect();
+    // `log 2` is still held back when the `if` fails, so the chunk starts there
+    assert!(results[0].is_ok());
+    match results.last() {
+        Some(Err(StreamError::Parse { line, error })) => {
+            assert_eq!(*line, 2);
+            assert!(matches!(error.line_col, pest::error::LineColLocation::Pos((3, _))));
+        }
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn reports_unterminated_blocks_at_end_of_file() {
+    let results: Vec<_> = StmtStream::new("log 1\nif x [\n    log 2\n".as_bytes()).collect();
+    assert!(matches!(results.last(), Some(Err(StreamError::Parse { .. }))));
+}
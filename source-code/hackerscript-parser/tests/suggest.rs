@@ -0,0 +1,51 @@
+use hackerscript_parser::{parse, suggest, Suggestion};
+
+fn suggestion(source: &str) -> Option<Suggestion> {
+    suggest(source, &parse(source).unwrap_err())
+}
+
+/// The source after applying suggestions until it parses.
+fn fixed(source: &str) -> String {
+    let mut source = source.to_string();
+    while let Err(err) = parse(&source) {
+        let suggestion = suggest(&source, &err).unwrap_or_else(|| panic!("no suggestion for {:?}: {}", source, err));
+        source = suggestion.apply(&source);
+    }
+    source
+}
+
+#[test]
+fn a_single_equals_in_a_condition_becomes_a_comparison() {
+    let source = "let x = 1\nif x = 1 [\n    log x\n]\n";
+    let suggestion = suggestion(source).unwrap();
+    assert_eq!((suggestion.span.clone(), suggestion.replacement.as_str()), (15..16, "=="));
+    assert!(suggestion.message.starts_with("compare with `==`"), "{}", suggestion.message);
+    assert_eq!(suggestion.snippet(source), "if x == 1 [");
+    assert_eq!(fixed("while n > 0 && n = 3 [\n]\n"), "while n > 0 && n == 3 [\n]\n");
+    assert_eq!(fixed("if x [\n] else if y = 2 [\n]\n"), "if x [\n] else if y == 2 [\n]\n");
+}
+
+#[test]
+fn a_string_left_open_is_closed_at_the_end_of_its_line() {
+    let source = "log \"a\", \"b @ not a comment\nlog 2\n";
+    let suggestion = suggestion(source).unwrap();
+    assert_eq!(suggestion.message, "close the string opened at column 10 with `\"`");
+    assert_eq!(suggestion.snippet(source), "log \"a\", \"b @ not a comment\"");
+    assert_eq!(fixed("let s = \"say \\\"hi\\\"\n"), "let s = \"say \\\"hi\\\"\"\n");
+}
+
+#[test]
+fn blocks_left_open_are_closed_at_their_indentation() {
+    let source = "func f() [\n    if 1 [\n        log 1\n    ]\n    while 0 [\n        log 2\n";
+    let suggestion = suggestion(source).unwrap();
+    assert_eq!(suggestion.message, "close the 2 open blocks with `]`");
+    assert_eq!(fixed(source), format!("{}    ]\n]\n", source));
+    assert_eq!(fixed("if x [\n    log 1"), "if x [\n    log 1\n]\n");
+}
+
+#[test]
+fn other_mistakes_get_no_suggestion() {
+    for source in ["let x = 2 log x\n", "func f( [ ]\n", "let x == 1\n", "log 1 ]\n"] {
+        assert_eq!(suggestion(source), None, "{:?}", source);
+    }
+}
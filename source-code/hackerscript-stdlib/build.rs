@@ -0,0 +1,38 @@
+//! Compiles every `core/<lib>.hcs` into the `core:<lib>` module of `MODULES`.
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+fn main() {
+    println!("cargo:rerun-if-changed=core");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("cargo sets OUT_DIR"));
+    let dir = Path::new(&env::var_os("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR")).join("core");
+    let mut sources: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("core/ is readable")
+        .map(|entry| entry.expect("core/ is readable").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hcs"))
+        .collect();
+    sources.sort();
+
+    let mut table = String::from("pub(crate) const MODULES: &[(&str, &str, &[u8])] = &[\n");
+    for path in sources {
+        let lib = path.file_stem().and_then(|stem| stem.to_str()).expect("module names are UTF-8");
+        let name = format!("core:{}", lib);
+        let source = fs::read_to_string(&path).expect("module is readable");
+        let bytecode = compile(&source, &name).unwrap_or_else(|e| panic!("{}: {:#}", path.display(), e));
+        let bc = out_dir.join(format!("{}.bc", lib));
+        fs::write(&bc, bytecode).expect("OUT_DIR is writable");
+        table.push_str(&format!("    ({:?}, include_str!({:?}), include_bytes!({:?})),\n", name, path, bc));
+    }
+    table.push_str("];\n");
+    fs::write(out_dir.join("modules.rs"), table).expect("OUT_DIR is writable");
+}
+
+/// Check, fold and compile one module. Dead code elimination would drop
+/// every function, since the module itself calls few of them.
+fn compile(source: &str, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut program = hackerscript_parser::parse(source).map_err(|e| anyhow::anyhow!("Parse error:\n{}", e))?;
+    hackerscript_parser::loader::introspect(&mut program.body, name, name);
+    hackerscript_codegen::check::ensure(&program)?;
+    hackerscript_codegen::opt::fold_constants(&mut program);
+    Ok(hackerscript_codegen::compiler::compile_named(&program, name)?.to_bytes())
+}
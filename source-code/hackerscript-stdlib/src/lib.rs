@@ -0,0 +1,24 @@
+//! The `core:*` modules written in HackerScript, compiled to bytecode when
+//! this crate is built.
+//!
+//! `hs1` leaves `import <core:lib>` in a script when `lib` is one of these
+//! (and no file in the search path shadows it), and the VM runs the
+//! precompiled module the first time the import is reached. The reference
+//! interpreter expands the import from `source` instead.
+
+include!(concat!(env!("OUT_DIR"), "/modules.rs"));
+
+/// The `repo:lib` names of every module, sorted.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    MODULES.iter().map(|(name, _, _)| *name)
+}
+
+/// The source of module `name`.
+pub fn source(name: &str) -> Option<&'static str> {
+    MODULES.iter().find(|(module, _, _)| *module == name).map(|(_, source, _)| *source)
+}
+
+/// The `.bc` contents of module `name`, for `VM::set_modules`.
+pub fn bytecode(name: &str) -> Option<&'static [u8]> {
+    MODULES.iter().find(|(module, _, _)| *module == name).map(|(_, _, bytecode)| *bytecode)
+}
@@ -0,0 +1,152 @@
+use hackerscript_bytecode::{verify, Bytecode};
+use hackerscript_codegen::compiler::compile_named;
+use hackerscript_vm::{BufferHost, ModuleCache, VM};
+use std::sync::{Arc, OnceLock};
+
+fn run(source: &str) -> (Vec<String>, anyhow::Result<()>) {
+    let program = hackerscript_parser::parse(source).expect("test source should parse");
+    let bytecode = compile_named(&program, "modules.hcs").unwrap();
+    let mut vm = VM::new();
+    vm.set_modules(hackerscript_stdlib::bytecode);
+    let mut host = BufferHost::default();
+    let result = vm.run(&bytecode, &mut host);
+    (host.lines, result)
+}
+
+#[test]
+fn every_module_is_precompiled_and_verifies() {
+    let names: Vec<&str> = hackerscript_stdlib::names().collect();
+    assert!(names.contains(&"core:math"), "{:?}", names);
+    for name in names {
+        assert!(hackerscript_stdlib::source(name).is_some());
+        let bytecode = Bytecode::from_bytes(hackerscript_stdlib::bytecode(name).unwrap()).unwrap();
+        verify(&bytecode).unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+    }
+    assert!(hackerscript_stdlib::bytecode("core:nothing").is_none());
+}
+
+#[test]
+fn programs_call_into_imported_modules() {
+    let source = "\
+func twice(x) [
+    return x * 2
+]
+import <core:math>
+import <core:math>
+log pow(3, 4), gcd(12, 18), clamp(15, 0, 10), twice(abs(0 - 2)), sign(0 - 7)
+";
+    let (lines, result) = run(source);
+    result.unwrap();
+    assert_eq!(lines, ["81 6 10 4 -1"]);
+}
+
+#[test]
+fn errors_in_a_module_unwind_to_the_program() {
+    let source = "\
+import <core:math>
+try [
+    log pow(\"x\", 2)
+] except err [
+    log \"caught\"
+]
+log max(1, 2)
+log gcd(\"a\", 1)
+";
+    let (lines, result) = run(source);
+    assert_eq!(lines, ["caught", "2"]);
+    let message = format!("{:#}", result.unwrap_err());
+    assert!(message.starts_with("line ") && message.contains(" of core:math: "), "{}", message);
+}
+
+#[test]
+fn a_runtime_without_the_module_fails_at_the_import() {
+    let program = hackerscript_parser::parse("log 1\nimport <core:math>\n").unwrap();
+    let bytecode = compile_named(&program, "modules.hcs").unwrap();
+    let mut host = BufferHost::default();
+    let error = VM::new().run(&bytecode, &mut host).unwrap_err();
+    assert_eq!(host.lines, ["1"]);
+    assert_eq!(format!("{:#}", error), "line 2: No module `core:math` in this runtime");
+}
+
+#[test]
+fn vms_share_decoded_modules_but_not_globals() {
+    let program = hackerscript_parser::parse("import <core:math>\nlet seen = max(1, 2)\n").unwrap();
+    let bytecode = compile_named(&program, "shared.hcs").unwrap();
+    let cache = ModuleCache::new(hackerscript_stdlib::bytecode);
+    let vms: Vec<VM> = (0..2)
+        .map(|_| {
+            let mut vm = VM::new();
+            vm.set_module_cache(cache.clone());
+            vm.run(&bytecode, &mut BufferHost::default()).unwrap();
+            vm
+        })
+        .collect();
+    assert_eq!(cache.decoded(), ["core:math"]);
+    assert!(Arc::ptr_eq(&cache.get("core:math").unwrap().unwrap(), &cache.get("core:math").unwrap().unwrap()));
+    assert!(cache.get("core:nothing").unwrap().is_none());
+    // each VM ran the program with its own globals
+    for vm in &vms {
+        assert_eq!(vm.global("seen"), Some(&hackerscript_vm::Value::Int(2)));
+    }
+}
+
+/// Modules whose top levels log as they run; `test:a` and `test:b` import
+/// each other.
+fn logging(name: &str) -> Option<&'static [u8]> {
+    static MODULES: OnceLock<Vec<(&str, Vec<u8>)>> = OnceLock::new();
+    let modules = MODULES.get_or_init(|| {
+        [
+            ("test:once", "log \"init\", __module__\n"),
+            ("test:a", "log \"a starts\"\nimport <test:b>\nlog \"a ends\"\n"),
+            ("test:b", "log \"b starts\"\nimport <test:a>\n"),
+        ]
+        .into_iter()
+        .map(|(name, source)| {
+            let mut program = hackerscript_parser::parse(source).unwrap();
+            hackerscript_parser::loader::introspect(&mut program.body, name, name);
+            (name, compile_named(&program, name).unwrap().to_bytes())
+        })
+        .collect()
+    });
+    modules.iter().find(|(module, _)| *module == name).map(|(_, bytes)| bytes.as_slice())
+}
+
+#[test]
+fn a_module_runs_once_and_an_import_cycle_fails() {
+    let program = hackerscript_parser::parse("log 1\nimport <test:once>\nimport <test:once>\nlog 2\n").unwrap();
+    let bytecode = compile_named(&program, "once.hcs").unwrap();
+    let mut vm = VM::new();
+    vm.set_modules(logging);
+    let mut host = BufferHost::default();
+    vm.run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["1", "init test:once", "2"]);
+
+    let program = hackerscript_parser::parse("import <test:a>\nlog \"done\"\n").unwrap();
+    let bytecode = compile_named(&program, "cycle.hcs").unwrap();
+    let mut vm = VM::new();
+    vm.set_modules(logging);
+    let mut host = BufferHost::default();
+    let error = vm.run(&bytecode, &mut host).unwrap_err();
+    assert_eq!(host.lines, ["a starts", "b starts"]);
+    let message = format!("{:#}", error);
+    assert!(message.ends_with("import cycle: test:a -> test:b -> test:a"), "{}", message);
+}
+
+#[test]
+fn core_term_wraps_the_term_natives() {
+    let source = "\
+import <core:term>
+log bold(\"x\") == term_bold(\"x\"), color(\"x\", \"red\") == term_color(\"x\", \"red\")
+try [
+    move_to(0 - 1, 0)
+] except err [
+    log err.message
+]
+clear()
+";
+    let (lines, result) = run(source);
+    assert_eq!(lines, ["true true", "term_move_to: column -1 is not between 0 and 65535"]);
+    // a `BufferHost` has no terminal
+    let message = format!("{:#}", result.unwrap_err());
+    assert!(message.ends_with("a terminal is not available in this host"), "{}", message);
+}
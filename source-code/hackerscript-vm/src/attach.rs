@@ -0,0 +1,144 @@
+//! `hs2 --attachable` / `hs1 attach <pid-or-socket> <script.hcs>`: live
+//! patching of a long-running script. `hs1 attach` compiles the edited
+//! script and sends it over a Unix socket; between two instructions, the
+//! running VM swaps in the new version of every top-level function the
+//! script defines (`VM::patch`).
+//!
+//! Each connection sends one frame holding the `.bc` file and gets back one
+//! `serve::Reply`: `Done` with the names of the functions patched, or
+//! `Error`. The socket is only usable by the user running the script.
+//!
+//! Only calls that still go through a function see a patch: compile the
+//! running script with `hs1 compile --no-opt`, as the optimiser inlines.
+use anyhow::{Context, Result};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+
+use crate::serve::{read_frame, write_frame, Reply};
+use crate::Bytecode;
+
+/// A patch and where to send what became of it.
+type Request = (Bytecode, mpsc::Sender<Result<Vec<String>>>);
+
+/// Where the runtime with process id `pid` listens unless told otherwise.
+pub fn socket_path(pid: u32) -> PathBuf {
+    std::env::temp_dir().join(format!("hs2-{}.sock", pid))
+}
+
+/// The socket `hs1 attach` means by `target`: a process id, or a path.
+pub fn target_socket(target: &str) -> PathBuf {
+    match target.parse() {
+        Ok(pid) => socket_path(pid),
+        Err(_) => PathBuf::from(target),
+    }
+}
+
+/// Hands patches to a running VM, from any thread; see `VM::patcher`.
+#[derive(Debug, Clone)]
+pub struct Patcher {
+    requests: mpsc::Sender<Request>,
+    pending: Arc<AtomicBool>,
+}
+
+/// The VM's end of its `Patcher`s.
+#[derive(Debug)]
+pub(crate) struct Patches {
+    requests: mpsc::Receiver<Request>,
+    pending: Arc<AtomicBool>,
+    patcher: Patcher,
+}
+
+impl Patcher {
+    /// Queue `patch` and wait for the VM to apply it, returning the
+    /// functions it replaced or added. A VM that has been dropped applies
+    /// nothing; one that is not running waits for its next `run`.
+    pub fn apply(&self, patch: Bytecode) -> Result<Vec<String>> {
+        let (reply, applied) = mpsc::channel();
+        let gone = || anyhow::anyhow!("the script is no longer running");
+        self.requests.send((patch, reply)).map_err(|_| gone())?;
+        self.pending.store(true, Ordering::SeqCst);
+        applied.recv().map_err(|_| gone())?
+    }
+}
+
+impl Patches {
+    pub(crate) fn new() -> Self {
+        let (sender, requests) = mpsc::channel();
+        let pending = Arc::new(AtomicBool::new(false));
+        let patcher = Patcher { requests: sender, pending: Arc::clone(&pending) };
+        Patches { requests, pending, patcher }
+    }
+
+    pub(crate) fn patcher(&self) -> Patcher {
+        self.patcher.clone()
+    }
+
+    /// Every patch queued since the last call; cheap when there is none.
+    pub(crate) fn take(&self) -> Vec<Request> {
+        if !self.pending.load(Ordering::Relaxed) || !self.pending.swap(false, Ordering::SeqCst) {
+            return Vec::new();
+        }
+        self.requests.try_iter().collect()
+    }
+}
+
+/// Removes the socket file when dropped.
+#[derive(Debug)]
+pub struct Listening {
+    path: PathBuf,
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Accept patches on the Unix socket `path`, on a thread of its own, and
+/// hand them to `patcher`. A socket file nobody listens on any more is
+/// replaced.
+pub fn listen(path: &Path, patcher: Patcher) -> Result<Listening> {
+    if path.exists() && UnixStream::connect(path).is_err() {
+        std::fs::remove_file(path).with_context(|| format!("Cannot remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("Cannot listen on {}", path.display()))?;
+    let listening = Listening { path: path.to_path_buf() };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // a client that hangs up early only loses its own reply
+            let _ = answer(stream, &patcher);
+        }
+    });
+    Ok(listening)
+}
+
+fn answer(mut stream: UnixStream, patcher: &Patcher) -> Result<()> {
+    let Some(frame) = read_frame(&mut stream)? else {
+        return Ok(());
+    };
+    let reply = match Bytecode::from_bytes(&frame).and_then(|patch| patcher.apply(patch)) {
+        Ok(names) => Reply::Done(Some(names.join(", "))),
+        Err(err) => Reply::Error(format!("{:#}", err)),
+    };
+    reply.write(&mut stream)?;
+    Ok(())
+}
+
+/// Send `patch`, a `.bc` file, to the script listening on `socket`,
+/// returning the functions it patched.
+pub fn attach(socket: &Path, patch: &[u8]) -> Result<Vec<String>> {
+    let mut stream = UnixStream::connect(socket).with_context(|| format!("Cannot attach to {}", socket.display()))?;
+    write_frame(&mut stream, patch)?;
+    match Reply::read(&mut stream)? {
+        Some(Reply::Done(names)) => {
+            Ok(names.map_or_else(Vec::new, |names| names.split(", ").map(str::to_string).collect()))
+        }
+        Some(Reply::Error(message)) => Err(anyhow::anyhow!(message)),
+        Some(other) => Err(anyhow::anyhow!("unexpected reply {:?}", other)),
+        None => Err(anyhow::anyhow!("{} closed the connection", socket.display())),
+    }
+}
@@ -0,0 +1,179 @@
+//! `hs2 --audit <file>`: a JSON-lines record of every privileged operation a
+//! script performs, written before the operation runs. If the record cannot
+//! be written the operation is refused, so the log never misses an action.
+//!
+//! One object per line:
+//! `{"time":"2026-01-02T03:04:05.678Z","kind":"ffi","op":"term_prompt","args":["Name?"],"source":"tool.hcs","offset":42}`
+//! `source` is `null` for stripped bytecode.
+use anyhow::{Context, Result};
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::value::Value;
+
+/// What kind of privileged operation an audit record describes. Scripts
+/// cannot run commands, write files or open sockets yet; those get their
+/// own kinds when they can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditKind {
+    /// A native function (`CallNative`)
+    Ffi,
+    /// A signal sent to another process
+    Signal,
+}
+
+impl AuditKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditKind::Ffi => "ffi",
+            AuditKind::Signal => "signal",
+        }
+    }
+}
+
+pub struct AuditLog {
+    out: Box<dyn Write + Send>,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+impl AuditLog {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        AuditLog { out: Box::new(out) }
+    }
+
+    /// Append to the log file at `path`, creating it if needed.
+    #[cfg(feature = "fs")]
+    pub fn open(path: &std::path::Path) -> Result<Self> {
+        let file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Cannot open audit log {}", path.display()))?;
+        Ok(AuditLog::new(std::io::BufWriter::new(file)))
+    }
+
+    /// Write one record and flush it.
+    pub fn record(&mut self, kind: AuditKind, op: &str, args: &[Value], source: Option<&str>, offset: usize) -> Result<()> {
+        let mut line = String::new();
+        write_record(&mut line, SystemTime::now(), kind, op, args, source, offset)
+            .expect("writing to a String cannot fail");
+        line.push('\n');
+        self.out.write_all(line.as_bytes()).context("Cannot write audit log")?;
+        self.out.flush().context("Cannot write audit log")
+    }
+}
+
+fn write_record(
+    out: &mut String,
+    time: SystemTime,
+    kind: AuditKind,
+    op: &str,
+    args: &[Value],
+    source: Option<&str>,
+    offset: usize,
+) -> fmt::Result {
+    write!(out, "{{\"time\":\"")?;
+    write_timestamp(out, time)?;
+    write!(out, "\",\"kind\":\"{}\",\"op\":", kind.as_str())?;
+    write_json_str(out, op)?;
+    out.push_str(",\"args\":[");
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_json(out, arg)?;
+    }
+    out.push_str("],\"source\":");
+    match source {
+        Some(source) => write_json_str(out, source)?,
+        None => out.push_str("null"),
+    }
+    write!(out, ",\"offset\":{}}}", offset)
+}
+
+/// RFC 3339 in UTC with milliseconds.
+pub(crate) fn write_timestamp(out: &mut String, time: SystemTime) -> fmt::Result {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    write!(
+        out,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+pub(crate) fn write_json(out: &mut String, value: &Value) -> fmt::Result {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{}", b)?,
+        Value::Int(i) => write!(out, "{}", i)?,
+        Value::Float(x) if x.is_finite() => write!(out, "{:?}", x)?,
+        Value::Float(_) => out.push_str("null"),
+        Value::Str(s) => write_json_str(out, s)?,
+        Value::Func(func) => write_json_str(out, &format!("{:?}", func))?,
+        Value::Variant(variant) => write_json_str(out, &format!("{:?}", variant))?,
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item)?;
+            }
+            out.push(']');
+        }
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_str(out, key)?;
+                out.push(':');
+                write_json(out, item)?;
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn write_json_str(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Errors as scripts see them in `except err [ ... ]`: an `Error`, the map
+//!
+//! - `kind`: what went wrong, as `area:detail`, e.g. `process:timeout`,
+//!   `permission:net` or a script's own `net:timeout`; `error` when nothing
+//!   says more
+//! - `message`: the text the error would have stopped the script with
+//! - `data`: a map of details, e.g. a failed command's `stdout`, `stderr`,
+//!   `code` and `timed_out`
+//! - `stack`: where it was raised, innermost call first, e.g.
+//!   `["fetch (line 4)", "top level (line 9)"]`; a call the compiler
+//!   inlined has no entry of its own
+//!
+//! The native `Error(kind, message, data)` makes one. `throw value` raises a
+//! map as an `Error`, adding the fields it lacks, so `throw err` re-raises a
+//! caught error unchanged; any other value becomes the message of an `error`.
+//! The stack is filled in where the error is caught, unless it already has one.
+use std::collections::BTreeMap;
+
+use crate::permissions::PermissionDenied;
+use crate::process::ProcessError;
+use crate::value::Value;
+
+/// The kind of an error nothing gives a kind to.
+pub const GENERIC: &str = "error";
+
+/// An error raised by `throw`, or re-raised after a `finally`.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{}", message(.0))]
+pub struct Exception(pub Value);
+
+impl Exception {
+    /// An `Error` of `kind`, for natives and hosts to fail with.
+    pub fn new(kind: &str, message: impl Into<String>, data: BTreeMap<String, Value>) -> Self {
+        Exception(error_value(kind, message.into(), data))
+    }
+
+    /// What `throw value` raises.
+    pub fn thrown(value: Value) -> Self {
+        match value {
+            Value::Map(mut fields) => {
+                fields.entry("kind".to_string()).or_insert_with(|| Value::from(GENERIC));
+                fields.entry("message".to_string()).or_insert_with(|| Value::from(""));
+                fields.entry("data".to_string()).or_insert_with(|| Value::Map(BTreeMap::new()));
+                fields.entry("stack".to_string()).or_insert_with(|| Value::Array(Vec::new()));
+                Exception(Value::Map(fields))
+            }
+            other => Exception::new(GENERIC, other.to_string(), BTreeMap::new()),
+        }
+    }
+
+    /// The value an `except` block receives for `err`, raised at `stack`
+    /// (see `location`) unless it already knows where.
+    pub fn caught(err: &anyhow::Error, stack: Vec<String>) -> Value {
+        let mut value = match err.chain().find_map(|e| e.downcast_ref::<Exception>()) {
+            Some(Exception(value)) => value.clone(),
+            None => classify(err),
+        };
+        if let Value::Map(fields) = &mut value {
+            let unset = !matches!(fields.get("stack"), Some(Value::Array(frames)) if !frames.is_empty());
+            if unset {
+                fields.insert("stack".to_string(), Value::Array(stack.into_iter().map(Value::Str).collect()));
+            }
+        }
+        value
+    }
+}
+
+/// An entry of an error's `stack`: the function running (`None` at a top
+/// level) and the line, when the debug info has it.
+pub fn location(function: Option<&str>, line: Option<u32>) -> String {
+    let function = function.unwrap_or("top level");
+    match line {
+        Some(line) => format!("{} (line {})", function, line),
+        None => function.to_string(),
+    }
+}
+
+/// The `Error` for a failure of the runtime, a native or a command.
+fn classify(err: &anyhow::Error) -> Value {
+    let message = format!("{:#}", err);
+    if let Some(process) = err.chain().find_map(|e| e.downcast_ref::<ProcessError>()) {
+        let kind = match process.output.code {
+            _ if process.output.timed_out => "process:timeout",
+            Some(_) => "process:exit",
+            None => "process:killed",
+        };
+        let Value::Map(mut data) = Value::from(process.output.clone()) else {
+            unreachable!("process output converts to a map");
+        };
+        data.insert("command".to_string(), Value::from(process.command.as_str()));
+        return error_value(kind, message, data);
+    }
+    if let Some(denied) = err.chain().find_map(|e| e.downcast_ref::<PermissionDenied>()) {
+        let data = BTreeMap::from([
+            ("capability".to_string(), Value::from(denied.capability)),
+            ("target".to_string(), Value::from(denied.target.as_str())),
+        ]);
+        return error_value(&format!("permission:{}", denied.capability), message, data);
+    }
+    error_value(GENERIC, message, BTreeMap::new())
+}
+
+fn error_value(kind: &str, message: String, data: BTreeMap<String, Value>) -> Value {
+    Value::Map(BTreeMap::from([
+        ("kind".to_string(), Value::from(kind)),
+        ("message".to_string(), Value::Str(message)),
+        ("data".to_string(), Value::Map(data)),
+        ("stack".to_string(), Value::Array(Vec::new())),
+    ]))
+}
+
+fn message(value: &Value) -> String {
+    match value {
+        Value::Map(fields) => match fields.get("message") {
+            Some(message) => message.to_string(),
+            None => value.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
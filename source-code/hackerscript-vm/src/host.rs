@@ -0,0 +1,105 @@
+use anyhow::Result;
+#[cfg(feature = "fs")]
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use crate::logger::LogLevel;
+use crate::process::ProcessOutput;
+
+/// Everything the VM needs from the outside world goes through a `Host`.
+/// Native builds use `StdHost`; wasm embedders provide their own functions.
+pub trait Host {
+    /// Called for every `log` executed by the script.
+    fn log(&mut self, message: &str);
+
+    /// Called for `log.info` and the other leveled logs the logger sends to
+    /// the host. By default they are logged as `[warn] disk almost full`.
+    fn log_at(&mut self, level: LogLevel, message: &str) {
+        self.log(&format!("[{}] {}", level.name(), message));
+    }
+
+    /// Read a whole file (or host-defined resource) by name.
+    fn read(&mut self, path: &str) -> Result<Vec<u8>> {
+        Err(anyhow::anyhow!("io is not available in this host (read {})", path))
+    }
+
+    /// Write a whole file (or host-defined resource) by name.
+    fn write(&mut self, path: &str, _data: &[u8]) -> Result<()> {
+        Err(anyhow::anyhow!("io is not available in this host (write {})", path))
+    }
+
+    /// Run `command` with the system shell, killing it once `timeout` has
+    /// passed. A command that fails is still `Ok`.
+    fn sh(&mut self, command: &str, _timeout: Option<Duration>) -> Result<ProcessOutput> {
+        Err(anyhow::anyhow!("processes are not available in this host (sh {})", command))
+    }
+
+    /// Write `text` to the terminal as it is, escape sequences and all, with
+    /// no newline added. Used by the `core:term` natives.
+    fn term_write(&mut self, _text: &str) -> Result<()> {
+        Err(anyhow::anyhow!("a terminal is not available in this host"))
+    }
+
+    /// Read a line typed at the terminal, without its line ending.
+    fn term_read_line(&mut self) -> Result<String> {
+        Err(anyhow::anyhow!("a terminal is not available in this host"))
+    }
+
+    /// The terminal's size, as columns and rows.
+    fn term_size(&mut self) -> Result<(u16, u16)> {
+        Err(anyhow::anyhow!("a terminal is not available in this host"))
+    }
+}
+
+/// Host backed by stdout and the local filesystem.
+#[cfg(feature = "fs")]
+#[derive(Debug, Default)]
+pub struct StdHost;
+
+#[cfg(feature = "fs")]
+impl Host for StdHost {
+    fn log(&mut self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        Ok(std::fs::write(path, data)?)
+    }
+
+    fn sh(&mut self, command: &str, timeout: Option<Duration>) -> Result<ProcessOutput> {
+        Ok(crate::process::run_shell(command, timeout)?)
+    }
+
+    fn term_write(&mut self, text: &str) -> Result<()> {
+        let mut out = std::io::stdout().lock();
+        out.write_all(text.as_bytes())?;
+        Ok(out.flush()?)
+    }
+
+    fn term_read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    #[cfg(feature = "term")]
+    fn term_size(&mut self) -> Result<(u16, u16)> {
+        Ok(crossterm::terminal::size()?)
+    }
+}
+
+/// Host that collects log lines in memory (useful for embedding and tests).
+#[derive(Debug, Default)]
+pub struct BufferHost {
+    pub lines: Vec<String>,
+}
+
+impl Host for BufferHost {
+    fn log(&mut self, message: &str) {
+        self.lines.push(message.to_string());
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+use log::info;
+
+// Cranelift integration: Example JIT compilation (for performance; simple func that runs the VM or compiles bytecode to native)
+pub fn jit_example() -> Result<()> {
+    // Setup Cranelift
+    let mut flag_builder = settings::builder();
+    flag_builder.set("use_colocated_libcalls", "false").unwrap();
+    flag_builder.set("is_pic", "false").unwrap();
+    let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
+        panic!("host machine is not supported: {}", msg);
+    });
+    let isa = isa_builder
+    .finish(settings::Flags::new(flag_builder))
+    .unwrap();
+    let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    let module = JITModule::new(builder);
+    // Define a simple function (placeholder: e.g., add two numbers)
+    let mut ctx = module.make_context();
+    let mut func_builder_ctx = FunctionBuilderContext::new();
+    let _builder = FunctionBuilder::new(&mut ctx.func, &mut func_builder_ctx);
+    // ... Build IR here (skipped for brevity; in real use, translate bytecode to Cranelift IR)
+    // For demo, just log
+    info!("JIT setup complete (placeholder)");
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! HackerScript Runtime (bytecode VM) as a library.
+//!
+//! The VM itself only depends on `Host` for the outside world, so it builds for
+//! `wasm32-unknown-unknown` / `wasm32-wasi` with `--no-default-features --features wasm`.
+//!
+//! A `VM` is `Send`: it owns every value it works on, so a server can hand
+//! one to a worker thread or hold it across an `.await`. There is no heap
+//! shared between VMs; see `Value` for what moving a value from one to
+//! another does.
+#[cfg(feature = "attach")]
+pub mod attach;
+pub mod audit;
+pub mod exception;
+pub mod host;
+pub mod logger;
+pub mod modules;
+pub mod natives;
+pub mod outcome;
+pub mod permissions;
+pub mod process;
+pub mod value;
+pub mod vm;
+
+#[cfg(feature = "jit")]
+pub mod jit;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "signals")]
+pub mod signals;
+#[cfg(feature = "record")]
+pub mod trace;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use exception::Exception;
+pub use hackerscript_bytecode::{verify, Bytecode, Opcode, ARGS};
+pub use host::{BufferHost, Host};
+pub use logger::{LogLevel, Logger};
+pub use modules::ModuleCache;
+pub use outcome::{LogRecord, RunOutcome};
+#[cfg(feature = "fs")]
+pub use host::StdHost;
+#[cfg(feature = "fs")]
+pub use hackerscript_bytecode::read_from_file as load_bytecode;
+#[cfg(feature = "fs")]
+pub use hackerscript_bytecode::bundle;
+pub use permissions::{PermissionDenied, Permissions};
+pub use process::{ProcessError, ProcessOutput};
+pub use value::{ConversionError, Function, Value, Variant};
+pub use vm::{Math, VM};
@@ -0,0 +1,135 @@
+//! Leveled logging: `log.debug`, `log.info`, `log.warn` and `log.error`.
+//!
+//! Plain `log` is program output and always goes to the host. Leveled
+//! messages are diagnostics: anything below the minimum level (`HS_LOG`,
+//! `info` by default) is dropped, and the rest go to a `Destination`. The
+//! host gets `[warn] disk almost full`; stderr and files get a timestamp,
+//! `2026-01-02T03:04:05.678Z WARN  disk almost full`.
+use anyhow::{Context, Result};
+use std::fmt;
+use std::io::Write;
+use std::time::SystemTime;
+
+pub use hackerscript_bytecode::LogLevel;
+
+use crate::audit::write_timestamp;
+use crate::host::Host;
+
+/// Environment variable holding the minimum level.
+pub const LEVEL_VAR: &str = "HS_LOG";
+
+/// Where leveled messages go.
+pub enum Destination {
+    /// `Host::log`, like plain `log` (the default, so embedders see everything)
+    Host,
+    Stderr,
+    /// A file or any other writer, flushed after every message
+    Writer(Box<dyn Write + Send>),
+    /// The local syslog daemon, with the facility `user`
+    #[cfg(unix)]
+    Syslog(std::os::unix::net::UnixDatagram),
+}
+
+impl Destination {
+    /// Append to the file at `path`, creating it if needed.
+    #[cfg(feature = "fs")]
+    pub fn file(path: &std::path::Path) -> Result<Self> {
+        let file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Cannot open log file {}", path.display()))?;
+        Ok(Destination::Writer(Box::new(std::io::BufWriter::new(file))))
+    }
+
+    /// Connect to `/dev/log`.
+    #[cfg(unix)]
+    pub fn syslog() -> Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect("/dev/log").context("Cannot connect to syslog at /dev/log")?;
+        Ok(Destination::Syslog(socket))
+    }
+}
+
+impl fmt::Debug for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Destination::Host => "Host",
+            Destination::Stderr => "Stderr",
+            Destination::Writer(_) => "Writer",
+            #[cfg(unix)]
+            Destination::Syslog(_) => "Syslog",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Logger {
+    level: LogLevel,
+    destination: Destination,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new(LogLevel::Info, Destination::Host)
+    }
+}
+
+impl Logger {
+    pub fn new(level: LogLevel, destination: Destination) -> Self {
+        Logger { level, destination }
+    }
+
+    /// The minimum level from `HS_LOG`, `info` when it is not set.
+    pub fn level_from_env() -> Result<LogLevel> {
+        match std::env::var(LEVEL_VAR) {
+            Ok(name) => LogLevel::parse(&name)
+                .with_context(|| format!("Invalid {}={} (use debug, info, warn or error)", LEVEL_VAR, name)),
+            Err(_) => Ok(LogLevel::Info),
+        }
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    /// Write `message` if `level` is enabled.
+    pub fn log(&mut self, host: &mut dyn Host, level: LogLevel, message: &str) -> Result<()> {
+        if !self.enabled(level) {
+            return Ok(());
+        }
+        match &mut self.destination {
+            Destination::Host => host.log_at(level, message),
+            Destination::Stderr => eprintln!("{}", timestamped(level, message)),
+            Destination::Writer(out) => {
+                writeln!(out, "{}", timestamped(level, message))
+                    .and_then(|()| out.flush())
+                    .context("Cannot write log")?;
+            }
+            #[cfg(unix)]
+            Destination::Syslog(socket) => {
+                // RFC 3164 priority: facility `user` (1) * 8 + severity
+                let severity = match level {
+                    LogLevel::Debug => 7,
+                    LogLevel::Info => 6,
+                    LogLevel::Warn => 4,
+                    LogLevel::Error => 3,
+                };
+                let line = format!("<{}>hackerscript[{}]: {}", 8 + severity, std::process::id(), message);
+                socket.send(line.as_bytes()).context("Cannot write to syslog")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn timestamped(level: LogLevel, message: &str) -> String {
+    let mut line = String::new();
+    write_timestamp(&mut line, SystemTime::now()).expect("writing to a String cannot fail");
+    line.push_str(&format!(" {:<5} {}", level.name().to_ascii_uppercase(), message));
+    line
+}
@@ -0,0 +1,60 @@
+//! Precompiled modules shared between VMs. Each module is decoded the first
+//! time any VM imports it and kept as an `Arc<Bytecode>`, so a service that
+//! starts a fresh `VM` per request, each given a clone of one `ModuleCache`,
+//! only pays for that VM's own globals and stack.
+//!
+//! Decoded modules are read-only: what a module's top level defines lives
+//! in the importing VM, so one request cannot change what the next sees.
+//! A VM runs a module's top level once, at its first `import`, even if it
+//! fails; importing it again before that top level ends is an import cycle.
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use hackerscript_bytecode::Bytecode;
+
+use crate::vm::ModuleSource;
+
+/// A `ModuleSource` with the modules decoded so far. Clones share them.
+#[derive(Clone)]
+pub struct ModuleCache {
+    source: ModuleSource,
+    decoded: Arc<Mutex<HashMap<String, Arc<Bytecode>>>>,
+}
+
+impl ModuleCache {
+    pub fn new(source: ModuleSource) -> Self {
+        ModuleCache { source, decoded: Arc::default() }
+    }
+
+    /// Module `name`, decoding it on first use; `None` when the source has
+    /// no such module.
+    pub fn get(&self, name: &str) -> Result<Option<Arc<Bytecode>>> {
+        if let Some(module) = self.lock().get(name) {
+            return Ok(Some(Arc::clone(module)));
+        }
+        let Some(bytes) = (self.source)(name) else { return Ok(None) };
+        // decoded unlocked; if another VM got there first, its copy wins
+        let bytecode = Bytecode::from_bytes(bytes).map_err(|e| anyhow::anyhow!("Corrupt module `{}`: {}", name, e))?;
+        Ok(Some(Arc::clone(self.lock().entry(name.to_string()).or_insert_with(|| Arc::new(bytecode)))))
+    }
+
+    /// The names of the modules decoded so far, sorted.
+    pub fn decoded(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.lock().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<Bytecode>>> {
+        // the map is only ever inserted into, so a panic elsewhere cannot leave it half-changed
+        self.decoded.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModuleCache").field("decoded", &self.decoded()).finish_non_exhaustive()
+    }
+}
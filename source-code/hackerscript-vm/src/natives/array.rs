@@ -0,0 +1,172 @@
+//! `map`, `filter`, `reduce`, `sort`, `find`, `any`, `all` and `zip`. All
+//! but `zip` call a script function for each item, through the `Call` the
+//! VM or the interpreter running the script passes in. Arrays are values,
+//! so each returns a new array rather than changing its argument.
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use super::arg;
+use crate::host::Host;
+use crate::value::{Function, Value};
+
+/// Calls a script function with arguments and returns its result.
+pub type Call<'a> = dyn FnMut(&Function, Vec<Value>) -> Result<Value> + 'a;
+
+fn array_arg<'a>(args: &'a [Value], native: &str) -> Result<&'a [Value]> {
+    match arg(args, 0, native)? {
+        Value::Array(items) => Ok(items),
+        other => bail!("{}: expected array, found {}", native, other.type_name()),
+    }
+}
+
+fn func_arg<'a>(args: &'a [Value], i: usize, native: &str) -> Result<&'a Function> {
+    match arg(args, i, native)? {
+        Value::Func(func) => Ok(func),
+        other => bail!("{}: expected function, found {}", native, other.type_name()),
+    }
+}
+
+/// What the table holds for the natives here that call back: they can only
+/// run through `natives::higher_order`.
+pub fn needs_caller(_: &mut dyn Host, _: &[Value]) -> Result<Value> {
+    bail!("this native calls a script function, which only the VM or interpreter running the script can do")
+}
+
+/// `map(items, f)`: `f(item)` for each item.
+pub fn map(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "map")?, func_arg(args, 1, "map")?);
+    let mut out = Vec::with_capacity(items.len());
+    for item in items {
+        out.push(call(f, vec![item.clone()])?);
+    }
+    Ok(Value::Array(out))
+}
+
+/// `filter(items, f)`: the items `f` is truthy for.
+pub fn filter(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "filter")?, func_arg(args, 1, "filter")?);
+    let mut out = Vec::new();
+    for item in items {
+        if call(f, vec![item.clone()])?.is_truthy() {
+            out.push(item.clone());
+        }
+    }
+    Ok(Value::Array(out))
+}
+
+/// `reduce(items, f, initial)`: `f(total, item)` for each item, starting
+/// from `initial`, or from the first item when there is none.
+pub fn reduce(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "reduce")?, func_arg(args, 1, "reduce")?);
+    let (mut total, rest) = match (args.get(2), items) {
+        (Some(initial), items) => (initial.clone(), items),
+        (None, [first, rest @ ..]) => (first.clone(), rest),
+        (None, []) => bail!("reduce: empty array and no initial value"),
+    };
+    for item in rest {
+        total = call(f, vec![total, item.clone()])?;
+    }
+    Ok(total)
+}
+
+/// `sort(items)` orders numbers and strings; `sort(items, f)` puts `a`
+/// before `b` when `f(a, b)` is negative. Equal items keep their order.
+pub fn sort(args: &[Value], call: &mut Call) -> Result<Value> {
+    let items = array_arg(args, "sort")?.to_vec();
+    let sorted = match args.get(1) {
+        Some(_) => {
+            let f = func_arg(args, 1, "sort")?;
+            merge_sort(items, &mut |a, b| match call(f, vec![a.clone(), b.clone()])? {
+                Value::Int(n) => Ok(n < 0),
+                Value::Float(x) => Ok(x < 0.0),
+                other => bail!("sort: the comparator returned {}, not a number", other.type_name()),
+            })?
+        }
+        None => merge_sort(items, &mut |a, b| match natural_order(a, b) {
+            Some(order) => Ok(order == Ordering::Less),
+            None => bail!("sort: cannot compare {} with {}", a.type_name(), b.type_name()),
+        })?,
+    };
+    Ok(Value::Array(sorted))
+}
+
+/// Ints and floats by value, strings by bytes.
+fn natural_order(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => number(a).partial_cmp(&number(b)),
+        (Value::Str(x), Value::Str(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+fn number(value: &Value) -> f64 {
+    match value {
+        Value::Int(n) => *n as f64,
+        Value::Float(x) => *x,
+        _ => f64::NAN,
+    }
+}
+
+/// A stable sort that stops at the first error `less` returns. Unlike
+/// `slice::sort_by` it cannot panic on a comparator that is not a total order.
+fn merge_sort(mut items: Vec<Value>, less: &mut dyn FnMut(&Value, &Value) -> Result<bool>) -> Result<Vec<Value>> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = merge_sort(items.split_off(items.len() / 2), less)?;
+    let left = merge_sort(items, less)?;
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        let next = if less(b, a)? { right.next() } else { left.next() };
+        out.extend(next);
+    }
+    out.extend(left);
+    out.extend(right);
+    Ok(out)
+}
+
+/// `find(items, f)`: the first item `f` is truthy for, or `null`.
+pub fn find(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "find")?, func_arg(args, 1, "find")?);
+    for item in items {
+        if call(f, vec![item.clone()])?.is_truthy() {
+            return Ok(item.clone());
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// `any(items, f)`: whether `f` is truthy for some item, stopping at the first.
+pub fn any(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "any")?, func_arg(args, 1, "any")?);
+    for item in items {
+        if call(f, vec![item.clone()])?.is_truthy() {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// `all(items, f)`: whether `f` is truthy for every item, stopping at the
+/// first it is not.
+pub fn all(args: &[Value], call: &mut Call) -> Result<Value> {
+    let (items, f) = (array_arg(args, "all")?, func_arg(args, 1, "all")?);
+    for item in items {
+        if !call(f, vec![item.clone()])?.is_truthy() {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// `zip(a, b)`: `[a[i], b[i]]` pairs, as many as the shorter array has items.
+pub fn zip(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let a = array_arg(args, "zip")?;
+    let b = match arg(args, 1, "zip")? {
+        Value::Array(items) => items,
+        other => bail!("zip: expected array, found {}", other.type_name()),
+    };
+    Ok(Value::Array(a.iter().zip(b).map(|(x, y)| Value::Array(vec![x.clone(), y.clone()])).collect()))
+}
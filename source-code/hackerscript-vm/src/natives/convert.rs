@@ -0,0 +1,104 @@
+//! `int`, `float`, `str`, `hex` and `bin`: conversions that fail loudly
+//! instead of guessing, with a `conversion:invalid` error that has the
+//! value in its data.
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+use super::arg;
+use crate::exception::Exception;
+use crate::host::Host;
+use crate::value::Value;
+
+/// The kind of the error a failed conversion raises.
+const INVALID: &str = "conversion:invalid";
+
+fn invalid(native: &str, message: String, value: &Value) -> anyhow::Error {
+    let data = BTreeMap::from([("value".to_string(), value.clone())]);
+    Exception::new(INVALID, format!("{}: {}", native, message), data).into()
+}
+
+fn only<'a>(args: &'a [Value], native: &str) -> Result<&'a Value> {
+    if args.len() > 1 {
+        bail!("{}: takes 1 argument but {} were given", native, args.len());
+    }
+    arg(args, 0, native)
+}
+
+/// `int("42")`, `int("-0x1f")`, `int(3.9)` (truncates), `int(true)`
+pub fn int(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let value = only(args, "int")?;
+    Ok(Value::Int(match value {
+        Value::Int(n) => *n,
+        Value::Bool(b) => i64::from(*b),
+        Value::Variant(variant) => variant.value(),
+        // `as` saturates; only convert floats that fit
+        Value::Float(x) if x.is_finite() && x.trunc() >= i64::MIN as f64 && x.trunc() < i64::MAX as f64 => *x as i64,
+        Value::Float(x) => return Err(invalid("int", format!("{} does not fit in an int", x), value)),
+        Value::Str(s) => {
+            parse_int(s).ok_or_else(|| invalid("int", format!("cannot convert {:?} to an int", s), value))?
+        }
+        other => return Err(invalid("int", format!("cannot convert {} to an int", other.type_name()), value)),
+    }))
+}
+
+/// Decimal, or hexadecimal/octal/binary with a `0x`/`0o`/`0b` prefix, so
+/// `int(hex(n)) == n`.
+fn parse_int(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (radix, digits) = match digits.get(..2).map(str::to_ascii_lowercase).as_deref() {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    if digits.is_empty() || digits.starts_with(['+', '-']) {
+        return None;
+    }
+    // parse the magnitude with its sign so i64::MIN round-trips
+    let signed = if negative { format!("-{}", digits) } else { digits.to_string() };
+    i64::from_str_radix(&signed, radix).ok()
+}
+
+/// `float("3.14")`, `float(2)`
+pub fn float(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let value = only(args, "float")?;
+    Ok(Value::Float(match value {
+        Value::Float(x) => *x,
+        Value::Int(n) => *n as f64,
+        Value::Bool(b) => f64::from(u8::from(*b)),
+        Value::Str(s) => {
+            s.trim().parse().map_err(|_| invalid("float", format!("cannot convert {:?} to a float", s), value))?
+        }
+        other => return Err(invalid("float", format!("cannot convert {} to a float", other.type_name()), value)),
+    }))
+}
+
+/// `str(x)`: what `log x` would print
+pub fn str(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    Ok(Value::Str(only(args, "str")?.to_string()))
+}
+
+/// `hex(255)` is `"0xff"`, `hex(-1)` is `"-0x1"`
+pub fn hex(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let n = int_only(args, "hex")?;
+    let sign = if n < 0 { "-" } else { "" };
+    Ok(Value::Str(format!("{}0x{:x}", sign, n.unsigned_abs())))
+}
+
+/// `bin(5)` is `"0b101"`
+pub fn bin(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let n = int_only(args, "bin")?;
+    let sign = if n < 0 { "-" } else { "" };
+    Ok(Value::Str(format!("{}0b{:b}", sign, n.unsigned_abs())))
+}
+
+fn int_only(args: &[Value], native: &str) -> Result<i64> {
+    match only(args, native)? {
+        Value::Int(n) => Ok(*n),
+        other => Err(invalid(native, format!("expected int, found {}", other.type_name()), other)),
+    }
+}
@@ -0,0 +1,33 @@
+//! `Error(kind, message, data)`: the value `throw` raises and `except`
+//! receives, see `exception`.
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+use super::str_arg;
+use crate::exception::Exception;
+use crate::host::Host;
+use crate::value::Value;
+
+/// `Error("net:timeout", "no answer", {"after": 5})`; the message and data
+/// can be left out.
+pub fn error(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    if args.len() > 3 {
+        bail!("Error: takes 1 to 3 arguments but {} were given", args.len());
+    }
+    let kind = str_arg(args, 0, "Error")?;
+    if kind.is_empty() {
+        bail!("Error: the kind is empty");
+    }
+    let message = match args.get(1) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::Str(message)) => message.clone(),
+        Some(other) => other.to_string(),
+    };
+    let data = match args.get(2) {
+        None | Some(Value::Null) => BTreeMap::new(),
+        Some(Value::Map(data)) => data.clone(),
+        Some(other) => bail!("Error: expected map for the data, found {}", other.type_name()),
+    };
+    let Exception(value) = Exception::new(kind, message, data);
+    Ok(value)
+}
@@ -0,0 +1,145 @@
+//! Functions behind `native name(...)` in the core library (`core/*.hcs`),
+//! and the builtins scripts call by name (`int`, `str`, `Error`, `map`, ...).
+//! The table follows `hackerscript_bytecode::NATIVES`, whose order is the
+//! native id encoded by `CallNative`.
+use anyhow::Result;
+
+use crate::host::Host;
+use crate::value::Value;
+
+pub type NativeFn = fn(&mut dyn Host, &[Value]) -> Result<Value>;
+
+/// A native that calls script functions, through `call`.
+pub type HigherOrderFn = fn(&[Value], &mut Call) -> Result<Value>;
+
+pub use array::Call;
+
+pub static NATIVES: &[(&str, NativeFn)] = &[
+    ("term_color", term::color),
+    ("term_bold", term::bold),
+    ("term_clear", term::clear),
+    ("term_move_to", term::move_to),
+    ("term_hide_cursor", term::hide_cursor),
+    ("term_show_cursor", term::show_cursor),
+    ("term_size", term::size),
+    ("term_progress", term::progress),
+    ("term_prompt", term::prompt),
+    ("term_confirm", term::confirm),
+    ("int", convert::int),
+    ("float", convert::float),
+    ("str", convert::str),
+    ("hex", convert::hex),
+    ("bin", convert::bin),
+    ("sh", shell::sh),
+    ("Error", error::error),
+    ("type_of", reflect::type_of),
+    ("fields", reflect::fields),
+    ("methods", reflect::methods),
+    ("has_field", reflect::has_field),
+    ("get_field", reflect::get_field),
+    ("set_field", reflect::set_field),
+    ("map", array::needs_caller),
+    ("filter", array::needs_caller),
+    ("reduce", array::needs_caller),
+    ("sort", array::needs_caller),
+    ("find", array::needs_caller),
+    ("any", array::needs_caller),
+    ("all", array::needs_caller),
+    ("zip", array::zip),
+];
+
+/// The natives above that call back into the script. Whatever runs it looks
+/// them up here first, with a `Call` for its own functions.
+static HIGHER_ORDER: &[(&str, HigherOrderFn)] = &[
+    ("map", array::map),
+    ("filter", array::filter),
+    ("reduce", array::reduce),
+    ("sort", array::sort),
+    ("find", array::find),
+    ("any", array::any),
+    ("all", array::all),
+];
+
+/// Natives that only compute a value from their arguments; they are not
+/// privileged, so `--audit` does not record them. Those that call back are
+/// here too: the calls they make are audited one by one.
+const PURE: &[&str] = &[
+    "int",
+    "float",
+    "str",
+    "hex",
+    "bin",
+    "Error",
+    "type_of",
+    "fields",
+    "methods",
+    "has_field",
+    "get_field",
+    "set_field",
+    "map",
+    "filter",
+    "reduce",
+    "sort",
+    "find",
+    "any",
+    "all",
+    "zip",
+];
+
+pub fn lookup(id: u32) -> Option<(&'static str, NativeFn)> {
+    NATIVES.get(id as usize).copied()
+}
+
+pub fn id_of(name: &str) -> Option<u32> {
+    NATIVES.iter().position(|(n, _)| *n == name).map(|i| i as u32)
+}
+
+pub fn is_pure(name: &str) -> bool {
+    PURE.contains(&name)
+}
+
+pub fn higher_order(name: &str) -> Option<HigherOrderFn> {
+    HIGHER_ORDER.iter().find(|(n, _)| *n == name).map(|&(_, native)| native)
+}
+
+fn arg<'a>(args: &'a [Value], i: usize, native: &str) -> Result<&'a Value> {
+    args.get(i)
+        .ok_or_else(|| anyhow::anyhow!("{}: missing argument {}", native, i + 1))
+}
+
+fn str_arg<'a>(args: &'a [Value], i: usize, native: &str) -> Result<&'a str> {
+    match arg(args, i, native)? {
+        Value::Str(s) => Ok(s),
+        other => Err(anyhow::anyhow!("{}: expected string, found {}", native, other.type_name())),
+    }
+}
+
+#[cfg_attr(not(feature = "term"), allow(dead_code))]
+fn int_arg(args: &[Value], i: usize, native: &str) -> Result<i64> {
+    match arg(args, i, native)? {
+        Value::Int(n) => Ok(*n),
+        Value::Float(x) => Ok(*x as i64),
+        other => Err(anyhow::anyhow!("{}: expected int, found {}", native, other.type_name())),
+    }
+}
+
+mod array;
+mod convert;
+mod error;
+mod reflect;
+mod shell;
+#[cfg(feature = "term")]
+mod term;
+
+#[cfg(not(feature = "term"))]
+mod term {
+    macro_rules! unavailable {
+        ($($name:ident),*) => {$(
+            pub fn $name(_: &mut dyn crate::host::Host, _: &[crate::value::Value]) -> anyhow::Result<crate::value::Value> {
+                Err(anyhow::anyhow!("core:term is not available in this build"))
+            }
+        )*};
+    }
+
+    unavailable!(color, bold, clear, move_to, hide_cursor, show_cursor, size, progress, prompt, confirm);
+}
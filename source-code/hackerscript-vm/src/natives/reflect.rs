@@ -0,0 +1,69 @@
+//! `type_of`, `fields`, `methods`, `has_field`, `get_field` and `set_field`,
+//! for serializers and debugging helpers written in HackerScript. They work
+//! on maps, whose methods are the fields holding functions; an `object`
+//! declaration has no value at run time. Values are copied, not shared, so
+//! `set_field` returns the changed map.
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+use super::{arg, str_arg};
+use crate::host::Host;
+use crate::value::Value;
+
+fn takes(args: &[Value], count: usize, native: &str) -> Result<()> {
+    if args.len() > count {
+        bail!("{}: takes {} argument(s) but {} were given", native, count, args.len());
+    }
+    Ok(())
+}
+
+fn map_arg<'a>(args: &'a [Value], native: &str) -> Result<&'a BTreeMap<String, Value>> {
+    match arg(args, 0, native)? {
+        Value::Map(fields) => Ok(fields),
+        other => bail!("{}: expected map, found {}", native, other.type_name()),
+    }
+}
+
+/// `type_of(x)`: `"int"`, `"string"`, `"map"`, ..., as errors name types
+pub fn type_of(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 1, "type_of")?;
+    Ok(Value::from(arg(args, 0, "type_of")?.type_name()))
+}
+
+/// `fields({"b": 1, "a": 2})` is `["a", "b"]`
+pub fn fields(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 1, "fields")?;
+    Ok(Value::Array(map_arg(args, "fields")?.keys().map(|key| Value::from(key.as_str())).collect()))
+}
+
+/// `methods(obj)`: the fields holding functions, in the order of `fields`
+pub fn methods(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 1, "methods")?;
+    let methods = map_arg(args, "methods")?.iter().filter(|(_, value)| matches!(value, Value::Func(_)));
+    Ok(Value::Array(methods.map(|(key, _)| Value::from(key.as_str())).collect()))
+}
+
+/// `has_field(obj, "name")`
+pub fn has_field(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 2, "has_field")?;
+    let fields = map_arg(args, "has_field")?;
+    Ok(Value::Bool(fields.contains_key(str_arg(args, 1, "has_field")?)))
+}
+
+/// `get_field(obj, "name")`, or the third argument (`null` if left out)
+/// when there is no such field
+pub fn get_field(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 3, "get_field")?;
+    let fields = map_arg(args, "get_field")?;
+    let field = fields.get(str_arg(args, 1, "get_field")?);
+    Ok(field.or(args.get(2)).cloned().unwrap_or(Value::Null))
+}
+
+/// `set_field(obj, "name", value)`: `obj` with the field added or replaced
+pub fn set_field(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    takes(args, 3, "set_field")?;
+    let mut fields = map_arg(args, "set_field")?.clone();
+    let name = str_arg(args, 1, "set_field")?.to_string();
+    fields.insert(name, arg(args, 2, "set_field")?.clone());
+    Ok(Value::Map(fields))
+}
@@ -0,0 +1,18 @@
+//! `sh(command)` and `sh(command, timeout_seconds)`.
+use anyhow::{bail, Result};
+use std::time::Duration;
+
+use super::str_arg;
+use crate::host::Host;
+use crate::value::Value;
+
+pub fn sh(host: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let command = str_arg(args, 0, "sh")?;
+    let timeout = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(Value::Int(secs)) if *secs > 0 => Some(Duration::from_secs(*secs as u64)),
+        Some(Value::Float(secs)) if *secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(*secs)),
+        Some(other) => bail!("sh: the timeout must be a positive number of seconds, found {}", other),
+    };
+    Ok(host.sh(command, timeout)?.into())
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use crossterm::style::{Color, Stylize};
+use crossterm::{cursor, terminal, Command};
+
+use super::{int_arg, str_arg};
+use crate::host::Host;
+use crate::value::Value;
+
+fn parse_color(name: &str) -> Result<Color> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "grey" | "gray" => Color::Grey,
+        other => anyhow::bail!("term_color: unknown color `{}`", other),
+    })
+}
+
+pub fn color(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let text = str_arg(args, 0, "term_color")?;
+    let color = parse_color(str_arg(args, 1, "term_color")?)?;
+    Ok(Value::Str(text.with(color).to_string()))
+}
+
+pub fn bold(_: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let text = str_arg(args, 0, "term_bold")?;
+    Ok(Value::Str(text.bold().to_string()))
+}
+
+/// `command` as the escape sequence a terminal understands.
+fn ansi(command: impl Command) -> String {
+    let mut out = String::new();
+    command.write_ansi(&mut out).expect("writing to a String cannot fail");
+    out
+}
+
+pub fn clear(host: &mut dyn Host, _: &[Value]) -> Result<Value> {
+    host.term_write(&(ansi(terminal::Clear(terminal::ClearType::All)) + &ansi(cursor::MoveTo(0, 0))))?;
+    Ok(Value::Null)
+}
+
+pub fn move_to(host: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let coordinate = |at, name| {
+        let n = int_arg(args, at, "term_move_to")?;
+        u16::try_from(n).map_err(|_| anyhow::anyhow!("term_move_to: {} {} is not between 0 and {}", name, n, u16::MAX))
+    };
+    let (col, row) = (coordinate(0, "column")?, coordinate(1, "row")?);
+    host.term_write(&ansi(cursor::MoveTo(col, row)))?;
+    Ok(Value::Null)
+}
+
+pub fn hide_cursor(host: &mut dyn Host, _: &[Value]) -> Result<Value> {
+    host.term_write(&ansi(cursor::Hide))?;
+    Ok(Value::Null)
+}
+
+pub fn show_cursor(host: &mut dyn Host, _: &[Value]) -> Result<Value> {
+    host.term_write(&ansi(cursor::Show))?;
+    Ok(Value::Null)
+}
+
+pub fn size(host: &mut dyn Host, _: &[Value]) -> Result<Value> {
+    let (cols, rows) = host.term_size()?;
+    Ok(Value::Array(vec![Value::Int(cols as i64), Value::Int(rows as i64)]))
+}
+
+pub fn progress(host: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let current = int_arg(args, 0, "term_progress")?.max(0);
+    let total = int_arg(args, 1, "term_progress")?.max(1);
+    let width = match args.get(2) {
+        Some(_) => int_arg(args, 2, "term_progress")?,
+        None => 40,
+    };
+    // the whole line fits the terminal: the bar, its brackets and " 100%"
+    let cols = host.term_size().map_or(u16::MAX, |(cols, _)| cols);
+    let width = width.min(i64::from(cols) - 7).max(1);
+    let ratio = (current.min(total) as f64) / (total as f64);
+    let filled = (ratio * width as f64).round() as usize;
+    let bar = format!("{}{}", "#".repeat(filled), " ".repeat(width as usize - filled));
+    let end = if current >= total { "\n" } else { "" };
+    host.term_write(&format!("\r[{}] {:3.0}%{}", bar, ratio * 100.0, end))?;
+    Ok(Value::Null)
+}
+
+fn read_line(host: &mut dyn Host, message: &str) -> Result<String> {
+    host.term_write(message)?;
+    host.term_read_line()
+}
+
+pub fn prompt(host: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let message = str_arg(args, 0, "term_prompt")?;
+    Ok(Value::Str(read_line(host, message)?))
+}
+
+pub fn confirm(host: &mut dyn Host, args: &[Value]) -> Result<Value> {
+    let message = str_arg(args, 0, "term_confirm")?;
+    let default = matches!(args.get(1), Some(Value::Bool(true)));
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = read_line(host, &format!("{} {} ", message, hint))?;
+    Ok(Value::Bool(match answer.trim().to_ascii_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" | "t" | "tak" => true,
+        _ => false,
+    }))
+}
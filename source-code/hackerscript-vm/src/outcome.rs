@@ -0,0 +1,128 @@
+//! `RunOutcome`: everything a run produced, as data instead of output. For
+//! embedders that want the result value and the logs without going through
+//! a `Host`, and for `hs2 --output json`:
+//!
+//! `{"ok":true,"result":3,"logs":[{"level":null,"message":"hi"},{"level":"warn","message":"low"}],"duration_ms":0.042,"error":null}`
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use crate::audit::{write_json, write_json_str};
+use crate::host::Host;
+use crate::logger::LogLevel;
+use crate::value::Value;
+use crate::{Bytecode, VM};
+
+/// One line a script logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    /// `None` for plain `log`
+    pub level: Option<LogLevel>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RunOutcome {
+    /// The value left on the stack, if any
+    pub result: Option<Value>,
+    /// In the order they were logged; leveled logs only if the logger sends
+    /// them to the host
+    pub logs: Vec<LogRecord>,
+    pub duration: Duration,
+    /// Why the run failed, if it did
+    pub error: Option<String>,
+}
+
+impl RunOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out).expect("writing to a String cannot fail");
+        out
+    }
+
+    fn write_json(&self, out: &mut String) -> std::fmt::Result {
+        write!(out, "{{\"ok\":{},\"result\":", self.is_ok())?;
+        match &self.result {
+            Some(value) => write_json(out, value)?,
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"logs\":[");
+        for (i, record) in self.logs.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            match record.level {
+                Some(level) => write!(out, "{{\"level\":\"{}\",\"message\":", level.name())?,
+                None => out.push_str("{\"level\":null,\"message\":"),
+            }
+            write_json_str(out, &record.message)?;
+            out.push('}');
+        }
+        write!(out, "],\"duration_ms\":{:.3},\"error\":", self.duration.as_secs_f64() * 1000.0)?;
+        match &self.error {
+            Some(error) => write_json_str(out, error)?,
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        Ok(())
+    }
+}
+
+impl VM {
+    /// Run `bytecode` like `run`, but collect its logs instead of sending
+    /// them to `host` (which still serves reads and writes). A failed run is
+    /// an outcome too, with whatever was logged before the error.
+    pub fn run_outcome(&mut self, bytecode: &Bytecode, host: &mut dyn Host) -> RunOutcome {
+        let mut recorder = Recorder { inner: host, logs: Vec::new() };
+        let start = Instant::now();
+        let result = self.run(bytecode, &mut recorder);
+        let duration = start.elapsed();
+        RunOutcome {
+            result: result.is_ok().then(|| self.result().cloned()).flatten(),
+            logs: recorder.logs,
+            duration,
+            error: result.err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+struct Recorder<'a> {
+    inner: &'a mut dyn Host,
+    logs: Vec<LogRecord>,
+}
+
+impl Host for Recorder<'_> {
+    fn log(&mut self, message: &str) {
+        self.logs.push(LogRecord { level: None, message: message.to_string() });
+    }
+
+    fn log_at(&mut self, level: LogLevel, message: &str) {
+        self.logs.push(LogRecord { level: Some(level), message: message.to_string() });
+    }
+
+    fn read(&mut self, path: &str) -> anyhow::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> anyhow::Result<()> {
+        self.inner.write(path, data)
+    }
+
+    fn sh(&mut self, command: &str, timeout: Option<std::time::Duration>) -> anyhow::Result<crate::ProcessOutput> {
+        self.inner.sh(command, timeout)
+    }
+    fn term_write(&mut self, text: &str) -> anyhow::Result<()> {
+        self.inner.term_write(text)
+    }
+
+    fn term_read_line(&mut self) -> anyhow::Result<String> {
+        self.inner.term_read_line()
+    }
+
+    fn term_size(&mut self) -> anyhow::Result<(u16, u16)> {
+        self.inner.term_size()
+    }
+}
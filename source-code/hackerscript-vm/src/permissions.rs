@@ -0,0 +1,320 @@
+//! Granular capabilities for privileged operations, in the style of Deno:
+//! `--allow-read=/tmp --allow-net=10.0.0.0/8 --allow-run=nmap`.
+//!
+//! `Permissions::all()` (the default) grants everything. Once a sandbox is
+//! requested, every capability starts empty and each `--allow-*` flag adds to
+//! it; a flag without `=` grants that capability for any target. Operations
+//! check the capability before they run and fail with `PermissionDenied`.
+//!
+//! `sh` blocks run their command through `/bin/sh`, so they need
+//! `--allow-run=sh`, and that grant lets them run any program: the shell
+//! command is not parsed for the programs it names.
+use anyhow::{bail, Context, Result};
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::host::Host;
+use crate::process::{ProcessError, ProcessOutput};
+
+/// Usage text for the flags `parse_flag` accepts.
+pub const FLAGS_HELP: &str = concat!(
+    "  --sandbox                 deny every capability not granted below\n",
+    "  --allow-read[=PATH,..]    read files under PATH\n",
+    "  --allow-write[=PATH,..]   write files under PATH\n",
+    "  --allow-net[=HOST,..]     connect to HOST (a name, an address or a CIDR range)\n",
+    "  --allow-run[=PROGRAM,..]  run PROGRAM; `sh` blocks need sh, which lets them run anything\n",
+    "  --allow-signal[=PID,..]   send signals to PID; handling them needs this process's PID",
+);
+
+/// Raised when a script uses a capability it was not granted.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("missing capability: {capability} access to `{target}` (grant it with --allow-{capability}={target})")]
+pub struct PermissionDenied {
+    /// `read`, `write`, `net`, `run` or `signal`
+    pub capability: &'static str,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Grant<T> {
+    All,
+    Only(Vec<T>),
+}
+
+impl<T> Grant<T> {
+    fn none() -> Self {
+        Grant::Only(Vec::new())
+    }
+
+    fn allows(&self, matches: impl Fn(&T) -> bool) -> bool {
+        match self {
+            Grant::All => true,
+            Grant::Only(entries) => entries.iter().any(matches),
+        }
+    }
+
+    fn add(&mut self, entries: Option<Vec<T>>) {
+        match (self, entries) {
+            (grant, None) => *grant = Grant::All,
+            (Grant::Only(existing), Some(entries)) => existing.extend(entries),
+            (Grant::All, Some(_)) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NetTarget {
+    Host(String),
+    Range { base: IpAddr, prefix: u8 },
+}
+
+impl NetTarget {
+    fn parse(entry: &str) -> Result<Self> {
+        let Some((base, prefix)) = entry.split_once('/') else {
+            return Ok(match entry.parse::<IpAddr>() {
+                Ok(base) => NetTarget::Range { base, prefix: max_prefix(base) },
+                Err(_) => NetTarget::Host(entry.to_ascii_lowercase()),
+            });
+        };
+        let base: IpAddr = base.parse().with_context(|| format!("Invalid network `{}`", entry))?;
+        let prefix: u8 = prefix.parse().with_context(|| format!("Invalid prefix length in `{}`", entry))?;
+        if prefix > max_prefix(base) {
+            bail!("Prefix length {} is too long for {}", prefix, base);
+        }
+        Ok(NetTarget::Range { base, prefix })
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match (self, host.parse::<IpAddr>()) {
+            (NetTarget::Range { base, prefix }, Ok(addr)) => in_range(addr, *base, *prefix),
+            (NetTarget::Host(name), Err(_)) => name.eq_ignore_ascii_case(host),
+            _ => false,
+        }
+    }
+}
+
+fn max_prefix(addr: IpAddr) -> u8 {
+    if addr.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+fn in_range(addr: IpAddr, base: IpAddr, prefix: u8) -> bool {
+    let (addr, base, bits) = match (addr, base) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => (u128::from(u32::from(a)), u128::from(u32::from(b)), 32),
+        (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a), u128::from(b), 128),
+        _ => return false,
+    };
+    let shift = bits - u32::from(prefix);
+    shift >= bits || addr >> shift == base >> shift
+}
+
+/// What a script may do outside the VM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permissions {
+    read: Grant<PathBuf>,
+    write: Grant<PathBuf>,
+    net: Grant<NetTarget>,
+    run: Grant<String>,
+    signal: Grant<i32>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions::all()
+    }
+}
+
+impl Permissions {
+    /// No restrictions (how scripts ran before permissions existed).
+    pub fn all() -> Self {
+        Permissions {
+            read: Grant::All,
+            write: Grant::All,
+            net: Grant::All,
+            run: Grant::All,
+            signal: Grant::All,
+        }
+    }
+
+    /// Nothing granted.
+    pub fn none() -> Self {
+        Permissions {
+            read: Grant::none(),
+            write: Grant::none(),
+            net: Grant::none(),
+            run: Grant::none(),
+            signal: Grant::none(),
+        }
+    }
+
+    /// Build permissions from command-line flags, leaving the other arguments.
+    /// Without `--sandbox` or any `--allow-*` flag everything is granted.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<(Self, Vec<String>)> {
+        let mut permissions = Permissions::none();
+        let mut sandboxed = false;
+        let mut rest = Vec::new();
+        for arg in args {
+            if permissions.parse_flag(&arg)? {
+                sandboxed = true;
+            } else {
+                rest.push(arg);
+            }
+        }
+        Ok((if sandboxed { permissions } else { Permissions::all() }, rest))
+    }
+
+    /// Apply one `--sandbox` / `--allow-*` flag. Returns `false` for any other
+    /// argument.
+    pub fn parse_flag(&mut self, arg: &str) -> Result<bool> {
+        if arg == "--sandbox" {
+            return Ok(true);
+        }
+        let Some(flag) = arg.strip_prefix("--allow-") else {
+            return Ok(false);
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (flag, None),
+        };
+        let entries = value.map(|v| v.split(',').filter(|e| !e.is_empty()));
+        match name {
+            "read" => self.read.add(entries.map(|e| e.map(resolve).collect())),
+            "write" => self.write.add(entries.map(|e| e.map(resolve).collect())),
+            "net" => self.net.add(entries.map(|e| e.map(NetTarget::parse).collect()).transpose()?),
+            "run" => self.run.add(entries.map(|e| e.map(String::from).collect())),
+            "signal" => self.signal.add(
+                entries
+                    .map(|e| e.map(|pid| pid.parse().with_context(|| format!("Invalid pid `{}`", pid))).collect())
+                    .transpose()?,
+            ),
+            _ => bail!("Unknown permission flag `{}`", arg),
+        }
+        Ok(true)
+    }
+
+    pub fn check_read(&self, path: &Path) -> Result<(), PermissionDenied> {
+        check_path(&self.read, "read", path)
+    }
+
+    pub fn check_write(&self, path: &Path) -> Result<(), PermissionDenied> {
+        check_path(&self.write, "write", path)
+    }
+
+    /// `host` is a host name or an IP address, without a port.
+    pub fn check_net(&self, host: &str) -> Result<(), PermissionDenied> {
+        check(self.net.allows(|target| target.matches(host)), "net", host)
+    }
+
+    /// `program` as the script names it; `nmap` and `/usr/bin/nmap` are
+    /// different grants.
+    pub fn check_run(&self, program: &str) -> Result<(), PermissionDenied> {
+        check(self.run.allows(|granted| granted == program), "run", program)
+    }
+
+    pub fn check_signal(&self, pid: i32) -> Result<(), PermissionDenied> {
+        check(self.signal.allows(|granted| *granted == pid), "signal", &pid.to_string())
+    }
+
+    /// A script that handles signals takes them on behalf of this process,
+    /// so the grant must cover its pid.
+    pub fn check_signal_handler(&self) -> Result<(), PermissionDenied> {
+        self.check_signal(i32::try_from(std::process::id()).unwrap_or(i32::MAX))
+    }
+}
+
+/// The host natives see: file access and commands go through the
+/// permission checks, and under `--strict-sh` failed commands are errors.
+pub(crate) struct Guarded<'a> {
+    pub host: &'a mut dyn Host,
+    pub permissions: &'a Permissions,
+    pub strict_sh: bool,
+}
+
+impl Host for Guarded<'_> {
+    fn log(&mut self, message: &str) {
+        self.host.log(message);
+    }
+
+    fn log_at(&mut self, level: crate::LogLevel, message: &str) {
+        self.host.log_at(level, message);
+    }
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>> {
+        self.permissions.check_read(Path::new(path))?;
+        self.host.read(path)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        self.permissions.check_write(Path::new(path))?;
+        self.host.write(path, data)
+    }
+
+    /// Needs `--allow-run=sh`, whatever programs `command` runs.
+    fn sh(&mut self, command: &str, timeout: Option<Duration>) -> Result<ProcessOutput> {
+        self.permissions.check_run("sh")?;
+        let output = self.host.sh(command, timeout)?;
+        if self.strict_sh && !output.success() {
+            return Err(ProcessError { command: command.to_string(), output }.into());
+        }
+        Ok(output)
+    }
+
+    fn term_write(&mut self, text: &str) -> Result<()> {
+        self.host.term_write(text)
+    }
+
+    fn term_read_line(&mut self) -> Result<String> {
+        self.host.term_read_line()
+    }
+
+    fn term_size(&mut self) -> Result<(u16, u16)> {
+        self.host.term_size()
+    }
+}
+
+fn check(allowed: bool, capability: &'static str, target: &str) -> Result<(), PermissionDenied> {
+    if allowed {
+        Ok(())
+    } else {
+        Err(PermissionDenied { capability, target: target.to_string() })
+    }
+}
+
+fn check_path(grant: &Grant<PathBuf>, capability: &'static str, path: &Path) -> Result<(), PermissionDenied> {
+    let resolved = resolve(path);
+    check(grant.allows(|root| resolved.starts_with(root)), capability, &resolved.display().to_string())
+}
+
+/// Absolute form of `path` with `..` and symlinks resolved the way the OS
+/// will resolve them, so neither can step outside a granted directory.
+fn resolve(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let absolute = match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    if let Ok(real) = std::fs::canonicalize(&absolute) {
+        return real;
+    }
+    // a file that does not exist yet: resolve the directory it would be in
+    if let (Some(parent), Some(name)) = (absolute.parent(), absolute.file_name()) {
+        if let Ok(real) = std::fs::canonicalize(parent) {
+            return real.join(name);
+        }
+    }
+    let mut normal = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::ParentDir => {
+                normal.pop();
+            }
+            Component::CurDir => {}
+            other => normal.push(other),
+        }
+    }
+    normal
+}
@@ -0,0 +1,159 @@
+//! Shell commands: `sh("make test")`, or `sh("make test", 30)` to kill it
+//! after 30 seconds. The result is a map scripts read with `.stdout`,
+//! `.stderr`, `.code` (`null` when the command was killed) and
+//! `.timed_out`. A failing command is not an error unless the VM runs with
+//! `--strict-sh`, which turns a nonzero exit or a timeout into a
+//! `ProcessError`.
+//!
+//! An `sh [ ... ]` block runs its lines as one script. What the script
+//! prints is logged as the script's output, what it writes to stderr is
+//! logged as warnings, and it always fails with a `ProcessError` if the
+//! script exits with a nonzero code: a block has no result to check.
+use std::collections::BTreeMap;
+
+use crate::host::Host;
+use crate::logger::{LogLevel, Logger};
+use crate::value::{ConversionError, Value};
+
+/// What a finished (or killed) command produced.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProcessOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` if the command was killed, by a signal or for timing out
+    pub code: Option<i32>,
+    pub timed_out: bool,
+}
+
+impl ProcessOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl From<ProcessOutput> for Value {
+    fn from(output: ProcessOutput) -> Self {
+        let mut map = BTreeMap::new();
+        map.insert("stdout".to_string(), Value::Str(output.stdout));
+        map.insert("stderr".to_string(), Value::Str(output.stderr));
+        map.insert("code".to_string(), output.code.map_or(Value::Null, |code| Value::Int(i64::from(code))));
+        map.insert("timed_out".to_string(), Value::Bool(output.timed_out));
+        Value::Map(map)
+    }
+}
+
+impl TryFrom<Value> for ProcessOutput {
+    type Error = ConversionError;
+
+    /// Back from the map `sh` returns, e.g. one replayed from a trace.
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let Value::Map(mut fields) = value else {
+            return Err(ConversionError(format!("expected a process result, found {}", value.type_name())));
+        };
+        let mut field = |name: &str| fields.remove(name).unwrap_or_default();
+        let (stdout, stderr, code, timed_out) = (field("stdout"), field("stderr"), field("code"), field("timed_out"));
+        let missing = |name: &str| ConversionError(format!("process result has no `{}`", name));
+        Ok(ProcessOutput {
+            stdout: String::try_from(stdout).map_err(|_| missing("stdout"))?,
+            stderr: String::try_from(stderr).map_err(|_| missing("stderr"))?,
+            code: match code {
+                Value::Null => None,
+                code => Some(i32::try_from(code)?),
+            },
+            timed_out: bool::try_from(timed_out).map_err(|_| missing("timed_out"))?,
+        })
+    }
+}
+
+/// Report the output of an `sh [ ... ]` block that ran `script`, failing if
+/// the script did.
+pub fn finish_block(script: &str, output: ProcessOutput, host: &mut dyn Host, logger: &mut Logger) -> anyhow::Result<()> {
+    for line in output.stdout.lines() {
+        host.log(line);
+    }
+    for line in output.stderr.lines() {
+        logger.log(host, LogLevel::Warn, line)?;
+    }
+    if !output.success() {
+        return Err(ProcessError { command: script.to_string(), output }.into());
+    }
+    Ok(())
+}
+
+/// Raised under `--strict-sh` when a command fails, and by any `sh [ ... ]`
+/// block that fails.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("ProcessError: `{command}` {}", describe(.output))]
+pub struct ProcessError {
+    pub command: String,
+    pub output: ProcessOutput,
+}
+
+fn describe(output: &ProcessOutput) -> String {
+    let status = match output.code {
+        _ if output.timed_out => "timed out".to_string(),
+        Some(code) => format!("exited with code {}", code),
+        None => "was killed".to_string(),
+    };
+    match output.stderr.trim_end().lines().last() {
+        Some(last) => format!("{}: {}", status, last),
+        None => status,
+    }
+}
+
+/// Run `command` with `sh -c`, killing it once `timeout` has passed.
+#[cfg(feature = "fs")]
+pub fn run_shell(command: &str, timeout: Option<std::time::Duration>) -> std::io::Result<ProcessOutput> {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    let mut command = {
+        let mut sh = Command::new("sh");
+        sh.arg("-c").arg(command).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        sh
+    };
+    // with a timeout, in a group of its own so the kill reaches whatever the
+    // shell started too; without one, Ctrl-C reaches it as usual
+    #[cfg(unix)]
+    if timeout.is_some() {
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    }
+    let mut child = command.spawn()?;
+    // drain both pipes while waiting, so a chatty command cannot block on a full pipe
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut text = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut text);
+            }
+            String::from_utf8_lossy(&text).into_owned()
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let start = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if timeout.is_some_and(|limit| start.elapsed() >= limit) {
+            timed_out = true;
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(-(child.id() as i32), libc::SIGKILL);
+            }
+            #[cfg(not(unix))]
+            child.kill()?;
+            break child.wait()?;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    Ok(ProcessOutput {
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+        code: if timed_out { None } else { status.code() },
+        timed_out,
+    })
+}
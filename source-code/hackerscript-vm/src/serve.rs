@@ -0,0 +1,228 @@
+//! `hs2 --serve <addr>`: run bytecode sent over TCP, so an orchestrator can
+//! dispatch scripts to an agent without starting a process per run.
+//!
+//! Every message is a frame: a little-endian u32 length, then that many
+//! bytes. The client's first frame is the shared token; each frame after
+//! that is a `.bc` file (as `hs1 compile` writes it) to verify and run in a
+//! fresh VM. For every job the server streams back `Reply` frames: one
+//! `Log` per line the script logs, then a single `Done` or `Error`; or, with
+//! `Server::set_outcomes`, a single `Outcome`. A wrong token gets an `Error`
+//! and the connection is closed.
+use anyhow::{Context, Result};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::host::{BufferHost, Host};
+use crate::logger::{Destination, LogLevel, Logger};
+use crate::modules::ModuleCache;
+use crate::permissions::Permissions;
+use crate::vm::ModuleSource;
+use crate::{verify, Bytecode, RunOutcome, VM};
+
+/// Environment variable holding the token clients must send first.
+pub const TOKEN_VAR: &str = "HS_SERVE_TOKEN";
+
+/// Frames longer than this are refused rather than buffered.
+pub const MAX_FRAME: usize = 16 << 20;
+
+pub fn write_frame(out: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(payload.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(payload)?;
+    out.flush()
+}
+
+/// The next frame, or `None` if the peer closed the connection between frames.
+pub fn read_frame(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes is too long", len)));
+    }
+    let mut payload = vec![0; len];
+    input.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// What the server sends back for a job. On the wire: a frame holding a
+/// tag byte (`l`, `d`, `e` or `o`) followed by the UTF-8 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// A line the script logged (leveled logs arrive as `[warn] ...`)
+    Log(String),
+    /// The job finished; the value it left on the stack, if any
+    Done(Option<String>),
+    /// The job, or the connection, failed
+    Error(String),
+    /// The job's `RunOutcome` as JSON, whether it failed or not
+    Outcome(String),
+}
+
+impl Reply {
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let (tag, text) = match self {
+            Reply::Log(line) => (b'l', line.as_str()),
+            Reply::Done(result) => (b'd', result.as_deref().unwrap_or("")),
+            Reply::Error(message) => (b'e', message.as_str()),
+            Reply::Outcome(json) => (b'o', json.as_str()),
+        };
+        let mut payload = Vec::with_capacity(1 + text.len());
+        payload.push(tag);
+        payload.extend_from_slice(text.as_bytes());
+        write_frame(out, &payload)
+    }
+
+    /// The next reply, or `None` once the server has closed the connection.
+    pub fn read(input: &mut impl Read) -> io::Result<Option<Reply>> {
+        let Some(payload) = read_frame(input)? else {
+            return Ok(None);
+        };
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let (&tag, text) = payload.split_first().ok_or_else(|| invalid("empty reply"))?;
+        let text = String::from_utf8(text.to_vec()).map_err(|_| invalid("reply is not UTF-8"))?;
+        Ok(Some(match tag {
+            b'l' => Reply::Log(text),
+            b'd' if text.is_empty() => Reply::Done(None),
+            b'd' => Reply::Done(Some(text)),
+            b'e' => Reply::Error(text),
+            b'o' => Reply::Outcome(text),
+            _ => return Err(invalid("unknown reply tag")),
+        }))
+    }
+}
+
+/// Runs jobs for clients that know the token.
+#[derive(Debug, Clone)]
+pub struct Server {
+    token: Vec<u8>,
+    permissions: Permissions,
+    level: LogLevel,
+    outcomes: bool,
+    /// Shared by every job, so each module is decoded once per server
+    modules: Option<ModuleCache>,
+}
+
+impl Server {
+    pub fn new(token: impl Into<Vec<u8>>) -> Self {
+        Server { token: token.into(), permissions: Permissions::all(), level: LogLevel::Info, outcomes: false, modules: None }
+    }
+
+    /// What every job may do (everything by default).
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// The minimum level of the leveled logs sent back.
+    pub fn set_level(&mut self, level: LogLevel) {
+        self.level = level;
+    }
+
+    /// Reply to each job with a single `Outcome` instead of streaming its
+    /// logs (`hs2 --serve --output json`).
+    pub fn set_outcomes(&mut self, outcomes: bool) {
+        self.outcomes = outcomes;
+    }
+
+    /// Where jobs find the modules they `import` (none by default).
+    pub fn set_modules(&mut self, modules: ModuleSource) {
+        self.modules = Some(ModuleCache::new(modules));
+    }
+
+    /// Accept connections forever, each on its own thread.
+    pub fn serve(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream.context("Cannot accept connection")?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                if let Err(e) = server.handle(stream) {
+                    log::warn!("Connection from {} failed: {:#}", peer, e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve one connection until the client closes it.
+    pub fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let token = read_frame(&mut stream)?.unwrap_or_default();
+        if !same_token(&token, &self.token) {
+            Reply::Error("authentication failed".to_string()).write(&mut stream)?;
+            return Ok(());
+        }
+        while let Some(job) = read_frame(&mut stream)? {
+            if self.outcomes {
+                Reply::Outcome(self.outcome(&job).to_json()).write(&mut stream)?;
+                continue;
+            }
+            let mut host = StreamHost { out: &mut stream, failed: None };
+            let reply = match self.run(&job, &mut host) {
+                Ok(result) => Reply::Done(result),
+                Err(e) => Reply::Error(format!("{:#}", e)),
+            };
+            if let Some(e) = host.failed {
+                return Err(e).context("Cannot send log lines");
+            }
+            reply.write(&mut stream)?;
+        }
+        Ok(())
+    }
+
+    fn run(&self, job: &[u8], host: &mut dyn Host) -> Result<Option<String>> {
+        let bytecode = load(job)?;
+        let mut vm = self.vm();
+        vm.run(&bytecode, host)?;
+        Ok(vm.result().map(ToString::to_string))
+    }
+
+    fn outcome(&self, job: &[u8]) -> RunOutcome {
+        match load(job) {
+            Ok(bytecode) => self.vm().run_outcome(&bytecode, &mut BufferHost::default()),
+            Err(e) => RunOutcome { error: Some(format!("{:#}", e)), ..RunOutcome::default() },
+        }
+    }
+
+    fn vm(&self) -> VM {
+        let mut vm = VM::new();
+        vm.set_permissions(self.permissions.clone());
+        vm.set_logger(Logger::new(self.level, Destination::Host));
+        if let Some(modules) = &self.modules {
+            vm.set_module_cache(modules.clone());
+        }
+        vm
+    }
+}
+
+fn load(job: &[u8]) -> Result<Bytecode> {
+    let bytecode = Bytecode::from_bytes(job)?;
+    verify(&bytecode).context("bytecode failed verification")?;
+    Ok(bytecode)
+}
+
+/// Compare without stopping at the first difference, so response times do
+/// not reveal how much of a guess was right.
+fn same_token(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Sends each log line to the client as it happens.
+struct StreamHost<'a> {
+    out: &'a mut TcpStream,
+    /// The first write that failed; later lines are dropped
+    failed: Option<io::Error>,
+}
+
+impl Host for StreamHost<'_> {
+    fn log(&mut self, message: &str) {
+        if self.failed.is_none() {
+            if let Err(e) = Reply::Log(message.to_string()).write(self.out) {
+                self.failed = Some(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use signal_hook::consts::signal::*;
+use signal_hook::SigId;
+
+use crate::value::{Function, Value};
+
+/// Script-level signal handlers. The OS handler only raises a flag; the VM
+/// checks the flags between instructions and calls the script handler there.
+/// Dropping the table removes its handlers, and a signal no table handles
+/// any more gets its default action back.
+#[derive(Debug, Default)]
+pub struct SignalTable {
+    handlers: Vec<Handler>,
+}
+
+#[derive(Debug)]
+struct Handler {
+    signal: i32,
+    handler: Function,
+    pending: Arc<AtomicBool>,
+    id: SigId,
+}
+
+/// Per signal, how many handlers of live tables there are, and the flag
+/// that makes the default action run while there are none. signal-hook
+/// never uninstalls its OS handler, so without it a signal a dropped VM
+/// handled would be ignored for the rest of the process.
+static HANDLED: Mutex<BTreeMap<i32, (usize, Arc<AtomicBool>)>> = Mutex::new(BTreeMap::new());
+
+fn handled() -> std::sync::MutexGuard<'static, BTreeMap<i32, (usize, Arc<AtomicBool>)>> {
+    HANDLED.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl SignalTable {
+    /// Route `signal` to the script function `handler`.
+    /// Registering the same signal again replaces the handler.
+    pub fn register(&mut self, signal: i32, handler: Function) -> Result<()> {
+        if let Some(registered) = self.handlers.iter_mut().find(|h| h.signal == signal) {
+            registered.handler = handler;
+            return Ok(());
+        }
+        if signal_hook::consts::FORBIDDEN.contains(&signal) {
+            anyhow::bail!("Cannot handle signal {}", signal_name(signal));
+        }
+        let mut handled = handled();
+        let (count, unhandled) = match handled.entry(signal) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let unhandled = Arc::new(AtomicBool::new(false));
+                signal_hook::flag::register_conditional_default(signal, Arc::clone(&unhandled))
+                    .with_context(|| format!("Cannot handle signal {}", signal_name(signal)))?;
+                entry.insert((0, unhandled))
+            }
+        };
+        let pending = Arc::new(AtomicBool::new(false));
+        let id = signal_hook::flag::register(signal, Arc::clone(&pending))
+            .with_context(|| format!("Cannot handle signal {}", signal_name(signal)))?;
+        *count += 1;
+        unhandled.store(false, Ordering::SeqCst);
+        self.handlers.push(Handler { signal, handler, pending, id });
+        Ok(())
+    }
+
+    /// The next handler whose signal arrived since the last check.
+    pub fn take_pending(&self) -> Option<Function> {
+        self.handlers
+            .iter()
+            .find(|h| h.pending.swap(false, Ordering::SeqCst))
+            .map(|h| h.handler.clone())
+    }
+}
+
+impl Drop for SignalTable {
+    fn drop(&mut self) {
+        let mut handled = handled();
+        for handler in &self.handlers {
+            signal_hook::low_level::unregister(handler.id);
+            if let Some((count, unhandled)) = handled.get_mut(&handler.signal) {
+                *count -= 1;
+                unhandled.store(*count == 0, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+const SIGNALS: &[(&str, i32)] = &[
+    ("SIGHUP", SIGHUP),
+    ("SIGINT", SIGINT),
+    ("SIGQUIT", SIGQUIT),
+    ("SIGKILL", SIGKILL),
+    ("SIGUSR1", SIGUSR1),
+    ("SIGUSR2", SIGUSR2),
+    ("SIGPIPE", SIGPIPE),
+    ("SIGALRM", SIGALRM),
+    ("SIGTERM", SIGTERM),
+    ("SIGCHLD", SIGCHLD),
+    ("SIGCONT", SIGCONT),
+    ("SIGSTOP", SIGSTOP),
+    ("SIGWINCH", SIGWINCH),
+];
+
+/// Accepts `"SIGINT"`, `"INT"` or a raw signal number.
+pub fn parse_signal(value: &Value) -> Result<i32> {
+    match value {
+        Value::Int(n) => i32::try_from(*n).map_err(|_| anyhow::anyhow!("Unknown signal: {}", n)),
+        Value::Str(name) => {
+            let upper = name.to_ascii_uppercase();
+            let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+            SIGNALS
+                .iter()
+                .find(|(n, _)| *n == full)
+                .map(|(_, sig)| *sig)
+                .ok_or_else(|| anyhow::anyhow!("Unknown signal: {}", name))
+        }
+        other => Err(anyhow::anyhow!("Expected signal name or number, found {}", other.type_name())),
+    }
+}
+
+pub fn signal_name(signal: i32) -> String {
+    SIGNALS
+        .iter()
+        .find(|(_, sig)| *sig == signal)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| signal.to_string())
+}
+
+pub fn send_signal(pid: i32, signal: i32) -> Result<()> {
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Cannot send {} to process {}", signal_name(signal), pid));
+    }
+    Ok(())
+}
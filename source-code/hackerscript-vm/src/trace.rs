@@ -0,0 +1,159 @@
+//! `hs2 --record <trace.hsr>` / `hs2 --replay <trace.hsr>`: deterministic
+//! replays of a run, for debugging scripts that only fail sometimes.
+//!
+//! Everything a script learns from the outside world comes back from a
+//! native that is not pure (a prompt, the terminal size, anything a native
+//! reads through the host), so a recording is the list of those calls with
+//! their results. Replaying returns the recorded results in order instead of
+//! calling the natives; a script that makes a different call than the
+//! recording did fails with an error saying where the two diverged. Signals
+//! are not recorded, so replays of scripts that handle them may differ.
+//!
+//! The file is JSON lines: a header naming the format version and the
+//! program, then one object per call, flushed as it happens so a crash
+//! still leaves a usable trace:
+//!
+//! `{"hsr":1,"program":"9f0c2d41a6b3e857"}`
+//! `{"native":"term_prompt","args":["Name?"],"result":"Ada"}`
+//! `{"native":"term_size","args":[],"error":"not a terminal"}`
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+use crate::value::Value;
+use crate::Bytecode;
+
+/// Version of the trace format.
+pub const TRACE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    hsr: u32,
+    program: String,
+}
+
+/// One call to an impure native.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub native: String,
+    pub args: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Set instead of `result` when the call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `VM::set_trace` does with impure native calls.
+pub enum Trace {
+    Record(Box<dyn Write + Send>),
+    Replay { events: std::vec::IntoIter<Event>, position: usize },
+}
+
+impl std::fmt::Debug for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trace::Record(_) => f.write_str("Record"),
+            Trace::Replay { position, .. } => write!(f, "Replay(at {})", position),
+        }
+    }
+}
+
+impl Trace {
+    /// Record the calls `bytecode` makes to `out`.
+    pub fn record(mut out: impl Write + Send + 'static, bytecode: &Bytecode) -> Result<Self> {
+        let header = Header { hsr: TRACE_VERSION, program: fingerprint(bytecode) };
+        write_line(&mut out, &header)?;
+        Ok(Trace::Record(Box::new(out)))
+    }
+
+    /// Replay a recording of `bytecode` read from `input`.
+    pub fn replay(input: impl BufRead, bytecode: &Bytecode) -> Result<Self> {
+        let mut lines = input.lines().enumerate();
+        let (_, header) = lines.next().context("Trace is empty")?;
+        let header: Header = serde_json::from_str(&header?).context("Not a HackerScript trace")?;
+        if header.hsr != TRACE_VERSION {
+            bail!("Trace format version {} is not supported (expected {})", header.hsr, TRACE_VERSION);
+        }
+        if header.program != fingerprint(bytecode) {
+            bail!("Trace was recorded from a different program");
+        }
+        let events = lines
+            .map(|(i, line)| serde_json::from_str(&line?).with_context(|| format!("Trace line {} is invalid", i + 1)))
+            .collect::<Result<Vec<Event>>>()?;
+        Ok(Trace::Replay { events: events.into_iter(), position: 0 })
+    }
+
+    /// Create the trace file at `path` for a recording.
+    #[cfg(feature = "fs")]
+    pub fn record_to(path: &std::path::Path, bytecode: &Bytecode) -> Result<Self> {
+        let file = std::fs::File::create(path).with_context(|| format!("Cannot create trace {}", path.display()))?;
+        Trace::record(file, bytecode)
+    }
+
+    /// Replay the trace file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn replay_from(path: &std::path::Path, bytecode: &Bytecode) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("Cannot open trace {}", path.display()))?;
+        Trace::replay(std::io::BufReader::new(file), bytecode)
+            .with_context(|| format!("Cannot replay {}", path.display()))
+    }
+
+    /// Make the call to `native`: run it and record what it returned, or
+    /// return what the recording says it returned.
+    pub(crate) fn call(&mut self, native: &str, args: Vec<Value>, run: impl FnOnce(&[Value]) -> Result<Value>) -> Result<Value> {
+        match self {
+            Trace::Record(out) => {
+                let result = run(&args);
+                let event = Event {
+                    native: native.to_string(),
+                    args,
+                    result: result.as_ref().ok().cloned(),
+                    error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                };
+                write_line(out, &event)?;
+                result
+            }
+            Trace::Replay { events, position } => {
+                *position += 1;
+                let called = display_call(native, &args);
+                let Some(event) = events.next() else {
+                    bail!("Replay diverged at call {}: the recording ended, the script called {}", position, called);
+                };
+                if event.native != native || event.args != args {
+                    bail!(
+                        "Replay diverged at call {}: the recording has {}, the script called {}",
+                        position,
+                        display_call(&event.native, &event.args),
+                        called
+                    );
+                }
+                match event.error {
+                    Some(error) => Err(anyhow::anyhow!(error)),
+                    None => Ok(event.result.unwrap_or_default()),
+                }
+            }
+        }
+    }
+}
+
+fn write_line(out: &mut dyn Write, item: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(item)?;
+    line.push(b'\n');
+    out.write_all(&line).and_then(|()| out.flush()).context("Cannot write trace")
+}
+
+fn display_call(native: &str, args: &[Value]) -> String {
+    let list = Value::Array(args.to_vec()).to_string();
+    format!("`{}({})`", native, &list[1..list.len() - 1])
+}
+
+/// FNV-1a over the code and constants, as 16 hex digits.
+fn fingerprint(bytecode: &Bytecode) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let constants = bytecode.constants.iter().flat_map(|c| c.as_bytes().iter().chain(&[0]));
+    for byte in bytecode.code.iter().chain(constants) {
+        hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,427 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+mod de;
+#[cfg(feature = "serde")]
+mod ser;
+
+#[cfg(feature = "serde")]
+pub use de::from_value;
+#[cfg(feature = "serde")]
+pub use ser::to_value;
+
+/// A runtime value as seen by scripts and by embedding hosts.
+///
+/// Values are `Send + Sync` and own their contents, so one can be moved
+/// from one VM (or thread) to another with `VM::set_global` or `VM::push`,
+/// and cloning deep-copies it. The exception is `Func`: clones share the
+/// body, and a function made by a script can only be called by the VM that
+/// ran the script. Elsewhere calling it is an error.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    /// A function used as a value, e.g. a lambda
+    Func(Function),
+    /// A variant of an `enum`, e.g. `Color.Red`
+    Variant(Variant),
+}
+
+/// A callable value. The VM only carries it around; what the body holds is
+/// up to the runtime that made it (the interpreter keeps the function and
+/// the variables it captured). Two functions are equal if they are the same
+/// value. One pointer wide, so `Value` stays as small as a `String`.
+#[derive(Clone)]
+pub struct Function(Arc<FunctionData>);
+
+struct FunctionData {
+    name: String,
+    body: Box<dyn Any + Send + Sync>,
+}
+
+impl Function {
+    pub fn new(name: impl Into<String>, body: impl Any + Send + Sync) -> Self {
+        Function(Arc::new(FunctionData { name: name.into(), body: Box::new(body) }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The body, if it is a `T`.
+    pub fn body<T: Any>(&self) -> Option<&T> {
+        self.0.body.downcast_ref()
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<func {}>", self.name())
+    }
+}
+
+/// A variant of an `enum`. It equals only the same variant of the same enum;
+/// `int` gives its discriminant. One pointer wide, like `Function`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Variant(Arc<VariantData>);
+
+#[derive(PartialEq, Eq)]
+struct VariantData {
+    enum_name: String,
+    name: String,
+    value: i64,
+}
+
+impl Variant {
+    pub fn new(enum_name: impl Into<String>, name: impl Into<String>, value: i64) -> Self {
+        Variant(Arc::new(VariantData { enum_name: enum_name.into(), name: name.into(), value }))
+    }
+
+    pub fn enum_name(&self) -> &str {
+        &self.0.enum_name
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The discriminant
+    pub fn value(&self) -> i64 {
+        self.0.value
+    }
+}
+
+impl fmt::Debug for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.enum_name(), self.name())
+    }
+}
+
+/// Returned when a `Value` does not have the shape a Rust type expects.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{0}")]
+pub struct ConversionError(pub String);
+
+impl ConversionError {
+    fn expected(what: &str, got: &Value) -> Self {
+        ConversionError(format!("expected {}, found {}", what, got.type_name()))
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Func(_) => "func",
+            Value::Variant(_) => "variant",
+        }
+    }
+
+    /// Whether the value counts as true in a condition: `null`, `false`, zero
+    /// and empty strings/collections are false, everything else is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(x) => *x != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Array(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
+            Value::Func(_) | Value::Variant(_) => true,
+        }
+    }
+}
+
+/// How `log` shows a value. A string is written as is; inside arrays and
+/// maps strings are quoted. `{:#}` breaks collections that do not fit in
+/// `PRETTY_WIDTH` columns over several indented lines. Values own their
+/// contents, so there are no cycles to guard against.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            _ if f.alternate() => write_pretty(f, self, 0, 0),
+            _ => write_nested(f, self),
+        }
+    }
+}
+
+/// Line width `{:#}` tries to keep collections within.
+pub const PRETTY_WIDTH: usize = 80;
+
+fn write_nested(f: &mut impl fmt::Write, value: &Value) -> fmt::Result {
+    match value {
+        Value::Null => f.write_str("null"),
+        Value::Bool(b) => write!(f, "{}", b),
+        Value::Int(i) => write!(f, "{}", i),
+        Value::Float(x) => write!(f, "{}", x),
+        Value::Str(s) => write!(f, "{:?}", s),
+        Value::Array(items) => {
+            f.write_char('[')?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_nested(f, item)?;
+            }
+            f.write_char(']')
+        }
+        Value::Map(entries) => {
+            f.write_char('{')?;
+            for (i, (key, value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{:?}: ", key)?;
+                write_nested(f, value)?;
+            }
+            f.write_char('}')
+        }
+        Value::Func(func) => write!(f, "{:?}", func),
+        Value::Variant(variant) => write!(f, "{:?}", variant),
+    }
+}
+
+/// `value` starting at `column` on a line indented by `indent`, one element
+/// per line if the compact form is too wide.
+fn write_pretty(f: &mut fmt::Formatter<'_>, value: &Value, indent: usize, column: usize) -> fmt::Result {
+    let mut compact = String::new();
+    write_nested(&mut compact, value)?;
+    let fits = column + compact.chars().count() <= PRETTY_WIDTH;
+    match value {
+        Value::Array(items) if !fits && !items.is_empty() => {
+            f.write_str("[\n")?;
+            for item in items {
+                write!(f, "{:width$}", "", width = indent + 2)?;
+                write_pretty(f, item, indent + 2, indent + 2)?;
+                f.write_str(",\n")?;
+            }
+            write!(f, "{:width$}]", "", width = indent)
+        }
+        Value::Map(entries) if !fits && !entries.is_empty() => {
+            f.write_str("{\n")?;
+            for (key, value) in entries {
+                let key = format!("{:?}: ", key);
+                write!(f, "{:width$}{}", "", key, width = indent + 2)?;
+                write_pretty(f, value, indent + 2, indent + 2 + key.chars().count())?;
+                f.write_str(",\n")?;
+            }
+            write!(f, "{:width$}}}", "", width = indent)
+        }
+        _ => f.write_str(&compact),
+    }
+}
+
+macro_rules! int_conversions {
+    ($($t:ty),*) => {$(
+        impl TryFrom<Value> for $t {
+            type Error = ConversionError;
+
+            fn try_from(v: Value) -> Result<Self, Self::Error> {
+                match v {
+                    Value::Int(i) => <$t>::try_from(i)
+                        .map_err(|_| ConversionError(format!("{} does not fit in {}", i, stringify!($t)))),
+                    other => Err(ConversionError::expected("int", &other)),
+                }
+            }
+        }
+    )*};
+}
+
+macro_rules! from_int {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Value {
+            fn from(v: $t) -> Self {
+                Value::Int(i64::from(v))
+            }
+        }
+    )*};
+}
+
+/// Types whose values do not all fit in a script int: the ones that do not
+/// are errors, as in `to_value`, rather than wrapping to negative ints.
+macro_rules! try_from_int {
+    ($($t:ty),*) => {$(
+        impl TryFrom<$t> for Value {
+            type Error = ConversionError;
+
+            fn try_from(v: $t) -> Result<Self, Self::Error> {
+                i64::try_from(v)
+                    .map(Value::Int)
+                    .map_err(|_| ConversionError(format!("{} does not fit in a script int", v)))
+            }
+        }
+    )*};
+}
+
+int_conversions!(i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+from_int!(i8, i16, i32, i64, u8, u16, u32);
+try_from_int!(u64, usize, isize);
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Self {
+        Value::Float(v as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Float(x) => Ok(x),
+            Value::Int(i) => Ok(i as f64),
+            other => Err(ConversionError::expected("float", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f32 {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        f64::try_from(v).map(|x| x as f32)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Bool(b) => Ok(b),
+            other => Err(ConversionError::expected("bool", &other)),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Str(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Str(s) => Ok(s),
+            other => Err(ConversionError::expected("string", &other)),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for Option<T> {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Null => Ok(None),
+            other => T::try_from(other).map(Some),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Value::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Array(items) => items.into_iter().map(T::try_from).collect(),
+            other => Err(ConversionError::expected("array", &other)),
+        }
+    }
+}
+
+impl<T: Into<Value>> From<BTreeMap<String, T>> for Value {
+    fn from(v: BTreeMap<String, T>) -> Self {
+        Value::Map(v.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: Into<Value>> From<std::collections::HashMap<String, T>> for Value {
+    fn from(v: std::collections::HashMap<String, T>) -> Self {
+        Value::Map(v.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for BTreeMap<String, T> {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| T::try_from(v).map(|v| (k, v)))
+                .collect(),
+            other => Err(ConversionError::expected("map", &other)),
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error = ConversionError>> TryFrom<Value> for std::collections::HashMap<String, T> {
+    type Error = ConversionError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(k, v)| T::try_from(v).map(|v| (k, v)))
+                .collect(),
+            other => Err(ConversionError::expected("map", &other)),
+        }
+    }
+}
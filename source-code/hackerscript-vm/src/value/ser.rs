@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+
+use serde::ser::{self, Serialize};
+
+use super::{ConversionError, Value};
+
+impl ser::Error for ConversionError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        ConversionError(msg.to_string())
+    }
+}
+
+/// Convert any `Serialize` type (e.g. a host configuration struct) into a `Value`.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, ConversionError> {
+    value.serialize(ValueSerializer)
+}
+
+impl Serialize for Value {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::Float(x) => serializer.serialize_f64(*x),
+            Value::Str(s) => serializer.serialize_str(s),
+            Value::Array(items) => items.serialize(serializer),
+            Value::Map(entries) => entries.serialize(serializer),
+            Value::Variant(variant) => serializer.serialize_str(variant.name()),
+            Value::Func(func) => Err(ser::Error::custom(format!("cannot serialize {:?}", func))),
+        }
+    }
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, ConversionError> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, ConversionError> {
+        Ok(Value::Int(v as i64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, ConversionError> {
+        i64::try_from(v)
+            .map(Value::Int)
+            .map_err(|_| ConversionError(format!("{} does not fit in a script int", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, ConversionError> {
+        Ok(Value::Float(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, ConversionError> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, ConversionError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, ConversionError> {
+        Ok(Value::Str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, ConversionError> {
+        Ok(Value::Array(v.iter().map(|b| Value::Int(*b as i64)).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, ConversionError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, ConversionError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, ConversionError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ConversionError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, ConversionError> {
+        Ok(Value::Str(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, ConversionError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, ConversionError> {
+        let mut map = BTreeMap::new();
+        map.insert(variant.to_string(), to_value(value)?);
+        Ok(Value::Map(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, ConversionError> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, ConversionError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, ConversionError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: SeqSerializer(Vec::with_capacity(len)),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, ConversionError> {
+        Ok(MapSerializer::default())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapSerializer, ConversionError> {
+        Ok(MapSerializer::default())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, ConversionError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: MapSerializer::default(),
+        })
+    }
+}
+
+struct SeqSerializer(Vec<Value>);
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        Ok(Value::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+#[derive(Default)]
+struct MapSerializer {
+    entries: BTreeMap<String, Value>,
+    next_key: Option<String>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ConversionError> {
+        match to_value(key)? {
+            Value::Str(s) => self.next_key = Some(s),
+            other => return Err(ConversionError(format!("map keys must be strings, found {}", other.type_name()))),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| ConversionError("map value serialized before its key".to_string()))?;
+        self.entries.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ConversionError> {
+        self.entries.insert(key.to_string(), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+/// Wraps tuple/struct variants as `{ "Variant": ... }`.
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &str, value: Value) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert(variant.to_string(), value);
+        Value::Map(map)
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConversionError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        Ok(Self::wrap(self.variant, ser::SerializeSeq::end(self.inner)?))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Value;
+    type Error = ConversionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), ConversionError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value, ConversionError> {
+        Ok(Self::wrap(self.variant, ser::SerializeStruct::end(self.inner)?))
+    }
+}
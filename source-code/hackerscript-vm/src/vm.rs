@@ -0,0 +1,1144 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use hackerscript_bytecode::{
+    instruction_len, Bytecode, Constants, Dispatch, FuncHeader, Instruction, Opcode, ARGS, FUNC_CAPTURES, FUNC_REST,
+};
+use crate::audit::{AuditKind, AuditLog};
+use crate::exception::{self, Exception};
+use crate::host::Host;
+use crate::logger::Logger;
+use crate::modules::ModuleCache;
+use crate::natives;
+use crate::permissions::{Guarded, Permissions};
+use crate::value::{Function, Value, Variant};
+use crate::verify;
+
+/// Calls nested deeper than this fail, as they do in the interpreter.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// The most local slots a call may ask for.
+pub const MAX_SLOTS: u64 = u16::MAX as u64;
+
+/// The `.bc` contents of a precompiled module, by `repo:lib` name.
+pub type ModuleSource = fn(&str) -> Option<&'static [u8]>;
+
+// Simple VM state
+#[derive(Debug, Default)]
+pub struct VM {
+    stack: Vec<Value>,
+    pc: usize,
+    /// Offset of the instruction being executed, for the line of an error
+    at: usize,
+    frames: Vec<Frame>,
+    /// Enclosing `try`s, innermost last
+    handlers: Vec<Handler>,
+    globals: HashMap<String, Value>,
+    /// Functions made callable by `Define`
+    functions: HashMap<String, Function>,
+    /// Functions `patch` replaced, which a later `Define` leaves alone
+    patched: HashSet<String>,
+    #[cfg(feature = "signals")]
+    signals: crate::signals::SignalTable,
+    #[cfg(feature = "attach")]
+    patches: Option<crate::attach::Patches>,
+    audit: Option<AuditLog>,
+    #[cfg(feature = "record")]
+    trace: Option<crate::trace::Trace>,
+    permissions: Permissions,
+    strict_sh: bool,
+    math: Math,
+    logger: Logger,
+    /// Where `Import` finds modules
+    modules: Option<ModuleCache>,
+    /// Modules imported so far, by name, as indexes into `units`
+    imported: HashMap<String, usize>,
+    units: Vec<Arc<Bytecode>>,
+    /// The imported module whose code is running, or `None` for the program
+    unit: Option<usize>,
+    id: VmId,
+}
+
+/// Tells apart the VMs of a process, so a function made by one is not
+/// called by another, whose code it does not point into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VmId(u64);
+
+impl Default for VmId {
+    fn default() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        VmId(NEXT.fetch_add(1, AtomicOrdering::Relaxed))
+    }
+}
+
+/// Why `execute` stopped without an error.
+enum Exit {
+    Halted,
+    /// `unit` changed: carry on in the other code
+    Switched,
+    /// A call made by `call_value` returned, leaving its value on the stack
+    Returned,
+}
+
+#[derive(Debug)]
+struct Frame {
+    return_pc: usize,
+    stack_height: usize,
+    /// A signal handler's call, which returns nothing
+    signal: bool,
+    /// A call made by a native through `call_value`, which returns to it
+    callback: bool,
+    /// The top level of an imported module, by its index in `units`, which
+    /// `Halt` returns from
+    module: Option<usize>,
+    /// The code to return to
+    unit: Option<usize>,
+    /// Constant index of the called function's name; `None` for a module
+    function: Option<u64>,
+    /// The call's local slots; a module has none
+    locals: Vec<Option<Value>>,
+}
+
+/// What the VM keeps in a `Function` made by `MakeFunc`.
+struct Code {
+    header: FuncHeader,
+    /// Locals of the call the lambda was made in
+    captured: Vec<Option<Value>>,
+    /// The module the function was made in
+    unit: Option<usize>,
+    /// The VM that made it
+    vm: VmId,
+}
+
+/// Where an error goes: the `except` (or `finally`) of a `try`, with the
+/// stack and frames as they were when the `try` started.
+#[derive(Debug)]
+struct Handler {
+    pc: usize,
+    stack_height: usize,
+    frames: usize,
+    unit: Option<usize>,
+}
+
+impl VM {
+    pub fn new() -> Self {
+        let mut vm = VM::default();
+        vm.set_global(ARGS, Value::Array(Vec::new()));
+        vm
+    }
+
+    /// Push a host value onto the stack before `run`, e.g. a configuration struct
+    /// converted with `Value::from` or `value::to_value`.
+    pub fn push(&mut self, value: impl Into<Value>) {
+        self.stack.push(value.into());
+    }
+
+    /// The value left on top of the stack when the script halted, if any.
+    /// Convert it back with `TryFrom` or `value::from_value`.
+    pub fn result(&self) -> Option<&Value> {
+        self.stack.last()
+    }
+
+    /// A top-level `let` variable, after `run`.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    /// Set a top-level variable before `run`, for the script to read.
+    pub fn set_global(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.globals.insert(name.into(), value.into());
+    }
+
+    /// Record every privileged operation to `log` from now on.
+    pub fn set_audit(&mut self, log: AuditLog) {
+        self.audit = Some(log);
+    }
+
+    /// Record the results of impure natives, or replay them from a
+    /// recording instead of calling the natives.
+    #[cfg(feature = "record")]
+    pub fn set_trace(&mut self, trace: crate::trace::Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Restrict what natives and signals may touch (everything by default).
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Make `sh` fail with a `ProcessError` when a command exits with a
+    /// nonzero code or times out (`--strict-sh`), instead of returning its
+    /// result.
+    pub fn set_strict_sh(&mut self, strict: bool) {
+        self.strict_sh = strict;
+    }
+
+    /// Whether arithmetic wraps or fails on overflow and NaN (`--checked-math`).
+    pub fn set_math(&mut self, math: Math) {
+        self.math = math;
+    }
+
+    /// Where `log.info` and the other leveled logs go, and which are kept
+    /// (`info` and up, to the host, by default).
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = logger;
+    }
+
+    /// Where `import <repo:lib>` finds the modules it runs (none by default).
+    pub fn set_modules(&mut self, modules: ModuleSource) {
+        self.modules = Some(ModuleCache::new(modules));
+    }
+
+    /// Like `set_modules`, sharing the modules `cache` has decoded (and will
+    /// decode) with every other VM given a clone of it.
+    pub fn set_module_cache(&mut self, cache: ModuleCache) {
+        self.modules = Some(cache);
+    }
+
+    /// Replace the VM's functions with the top-level functions `patch`
+    /// defines, adding those it does not have, and return their names.
+    /// Globals keep their values and `patch`'s top level does not run. Calls
+    /// already running finish in the code they started in, and a function
+    /// value taken earlier (a lambda, or a function in a variable) keeps
+    /// its old code. A patch that arrives before the script defines one of
+    /// its functions is kept over the script's version. A patch cannot add
+    /// imports.
+    pub fn patch(&mut self, patch: Bytecode) -> Result<Vec<String>> {
+        verify(&patch)?;
+        let defined = top_level_functions(&patch)?;
+        if defined.is_empty() {
+            anyhow::bail!("the patch defines no functions");
+        }
+        self.units.push(Arc::new(patch));
+        let unit = Some(self.units.len() - 1);
+        let mut names = Vec::with_capacity(defined.len());
+        for (name, header) in defined {
+            let code = Code { header, captured: Vec::new(), unit, vm: self.id };
+            self.functions.insert(name.clone(), Function::new(name.as_str(), code));
+            self.patched.insert(name.clone());
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// A handle other threads can `patch` this VM through while it runs;
+    /// the patches are applied between two instructions.
+    #[cfg(feature = "attach")]
+    pub fn patcher(&mut self) -> crate::attach::Patcher {
+        self.patches.get_or_insert_with(crate::attach::Patches::new).patcher()
+    }
+
+    /// Run until `Halt`. An error inside a `try` unwinds to its handler;
+    /// one outside every `try` stops the run, with the source line as
+    /// context when the debug info has one.
+    pub fn run(&mut self, bytecode: &Bytecode, host: &mut dyn Host) -> Result<()> {
+        match self.drive(bytecode, host, 0) {
+            Ok(Exit::Halted) => Ok(()),
+            Ok(_) => Err(anyhow::anyhow!("Return outside of a call or handler")),
+            Err(err) => {
+                let unit = self.unit.map(|unit| Arc::clone(&self.units[unit]));
+                let current = unit.as_deref().unwrap_or(bytecode);
+                Err(match (current.line_at(self.at), &current.debug) {
+                    (Some(line), Some(debug)) if self.unit.is_some() => {
+                        err.context(format!("line {} of {}", line, debug.source))
+                    }
+                    (Some(line), _) => err.context(format!("line {}", line)),
+                    (None, _) => err,
+                })
+            }
+        }
+    }
+
+    /// Run `program`, or the module code `unit` points into, until it halts
+    /// or a `call_value` call returns. An error goes to the innermost `try`
+    /// above the first `base` handlers; with none, it is returned.
+    fn drive(&mut self, program: &Bytecode, host: &mut dyn Host, base: usize) -> Result<Exit> {
+        loop {
+            let unit = self.unit.map(|unit| Arc::clone(&self.units[unit]));
+            let current = unit.as_deref().unwrap_or(program);
+            let err = match self.execute(program, current, host) {
+                Ok(Exit::Switched) => continue,
+                Ok(exit) => return Ok(exit),
+                Err(err) => err,
+            };
+            if self.handlers.len() <= base {
+                return Err(err);
+            }
+            let handler = self.handlers.pop().expect("checked above");
+            let stack = self.trace(program, current);
+            self.stack.truncate(handler.stack_height);
+            self.frames.truncate(handler.frames);
+            self.stack.push(Exception::caught(&err, stack));
+            self.pc = handler.pc;
+            self.unit = handler.unit;
+        }
+    }
+
+    /// Call `func` from a native and run it to its `Return`, leaving the VM
+    /// where it was. An error the call does not catch is returned, for the
+    /// native to pass on.
+    fn call_value(
+        &mut self,
+        program: &Bytecode,
+        host: &mut dyn Host,
+        func: &Function,
+        args: Vec<Value>,
+    ) -> Result<Value> {
+        let (pc, at, unit) = (self.pc, self.at, self.unit);
+        let (frames, stack_height) = (self.frames.len(), self.stack.len());
+        self.enter(func, func.name(), args)?;
+        self.frames.last_mut().expect("just entered").callback = true;
+        let result = match self.drive(program, host, self.handlers.len()) {
+            Ok(Exit::Returned) => self.pop("Return"),
+            Ok(_) => Err(anyhow::anyhow!("`{}` did not return", func.name())),
+            Err(err) => Err(err),
+        };
+        self.frames.truncate(frames);
+        self.stack.truncate(stack_height);
+        (self.pc, self.at, self.unit) = (pc, at, unit);
+        result
+    }
+
+    /// Where the instruction running in `current` is, innermost call first:
+    /// the instruction, then the call each frame returns to. `program` is
+    /// the code outside every module.
+    fn trace(&self, program: &Bytecode, current: &Bytecode) -> Vec<String> {
+        let code = |unit: Option<usize>| unit.map_or(program, |unit| &*self.units[unit]);
+        // a function's name is a constant of the code it runs in
+        let name = |code: &'_ Bytecode, frame: Option<&Frame>| {
+            frame.and_then(|frame| frame.function).and_then(|idx| code.constants.get(idx as usize)).map(str::to_string)
+        };
+        let mut trace =
+            vec![exception::location(name(current, self.frames.last()).as_deref(), current.line_at(self.at))];
+        for (i, frame) in self.frames.iter().enumerate().rev() {
+            // the call is in the caller's code, just before where it returns to
+            let caller = code(frame.unit);
+            let function = i.checked_sub(1).and_then(|below| name(caller, self.frames.get(below)));
+            trace.push(exception::location(function.as_deref(), caller.line_at(frame.return_pc.saturating_sub(1))));
+        }
+        trace
+    }
+
+    /// Run `bytecode`, the code of the unit running; `program` is the code
+    /// outside every module, for natives that call back.
+    fn execute(&mut self, program: &Bytecode, bytecode: &Bytecode, host: &mut dyn Host) -> Result<Exit> {
+        loop {
+            // Signal handlers only ever start between two instructions, and
+            // never inside another handler
+            #[cfg(feature = "signals")]
+            if !self.frames.iter().any(|f| f.signal) {
+                if let Some(handler) = self.signals.take_pending() {
+                    let unit = self.unit;
+                    self.enter(&handler, handler.name(), Vec::new())?;
+                    self.frames.last_mut().expect("just entered").signal = true;
+                    if self.unit != unit {
+                        return Ok(Exit::Switched);
+                    }
+                }
+            }
+            #[cfg(feature = "attach")]
+            if let Some(patches) = &self.patches {
+                for (patch, reply) in patches.take() {
+                    // the attached client may have given up waiting
+                    let _ = reply.send(self.patch(patch));
+                }
+            }
+            if self.pc >= bytecode.code.len() {
+                return Err(anyhow::anyhow!("PC out of bounds"));
+            }
+            let Some((instruction, len)) = Instruction::decode(&bytecode.code, self.pc) else {
+                let op = Opcode::from_byte(bytecode.code[self.pc])
+                    .ok_or_else(|| anyhow::anyhow!("Unknown opcode"))?;
+                return Err(anyhow::anyhow!("Incomplete {:?}", op));
+            };
+            let op = instruction.opcode();
+            let at = self.pc;
+            self.at = at;
+            self.pc += len;
+            match instruction {
+                Instruction::Nop => {},
+                Instruction::PushConst { index } => {
+                    let constant = constant(bytecode, index)?;
+                    self.stack.push(Value::Str(constant.to_string()));
+                }
+                Instruction::PushInt { value } => self.stack.push(Value::Int(value)),
+                Instruction::PushFloat { value } => self.stack.push(Value::Float(value)),
+                Instruction::PushNull => self.stack.push(Value::Null),
+                Instruction::PushTrue => self.stack.push(Value::Bool(true)),
+                Instruction::PushFalse => self.stack.push(Value::Bool(false)),
+                Instruction::Pop => {
+                    self.pop("Pop")?;
+                }
+                Instruction::MakeVariant { enum_name, name, value } => {
+                    let (enum_name, name) = (constant(bytecode, enum_name)?, constant(bytecode, name)?);
+                    self.stack.push(Value::Variant(Variant::new(enum_name, name, value)));
+                }
+                Instruction::IsArrayLen { count } | Instruction::IsArrayMin { count } => {
+                    let fits = match self.pop(op.mnemonic())? {
+                        Value::Array(items) if op == Opcode::IsArrayLen => items.len() as u64 == count,
+                        Value::Array(items) => items.len() as u64 >= count,
+                        _ => false,
+                    };
+                    self.stack.push(Value::Bool(fits));
+                }
+                Instruction::HasKey { key } => {
+                    let key = constant(bytecode, key)?;
+                    let value = self.pop("HasKey")?;
+                    self.stack.push(Value::Bool(matches!(&value, Value::Map(entries) if entries.contains_key(key))));
+                }
+                Instruction::MakeMap { count } => {
+                    let items = usize::try_from(count).ok().and_then(|n| n.checked_mul(2))
+                        .filter(|&n| n <= self.stack.len())
+                        .ok_or_else(|| anyhow::anyhow!("Stack underflow on MakeMap"))?;
+                    let items = self.stack.split_off(self.stack.len() - items);
+                    let mut map = BTreeMap::new();
+                    let mut items = items.into_iter();
+                    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                        match key {
+                            Value::Str(key) => map.insert(key, value),
+                            other => return Err(anyhow::anyhow!("Map keys must be strings, found {}", other.type_name())),
+                        };
+                    }
+                    self.stack.push(Value::Map(map));
+                }
+                Instruction::Index => {
+                    let index = self.pop("Index")?;
+                    let target = self.pop("Index")?;
+                    self.stack.push(self::index(&target, &index)?);
+                }
+                Instruction::Slice => {
+                    let end = self.pop("Slice")?;
+                    let start = self.pop("Slice")?;
+                    let target = self.pop("Slice")?;
+                    self.stack.push(slice(&target, &start, &end)?);
+                }
+                Instruction::Dup => {
+                    let top = self.stack.last()
+                        .ok_or_else(|| anyhow::anyhow!("Stack underflow on Dup"))?;
+                    self.stack.push(top.clone());
+                }
+                Instruction::Swap => {
+                    let len = self.stack.len();
+                    if len < 2 {
+                        return Err(anyhow::anyhow!("Stack underflow on Swap"));
+                    }
+                    self.stack.swap(len - 2, len - 1);
+                }
+                Instruction::LoadVar { name } => {
+                    let value = self.load_global(constant(bytecode, name)?)?;
+                    self.stack.push(value);
+                }
+                Instruction::LoadLocal { slot, name } => {
+                    let slot = slot_index(slot)?;
+                    let value = match self.local(slot) {
+                        Some(value) => value.clone(),
+                        None => self.load_global(constant(bytecode, name)?)?,
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::StoreLocal { slot } => {
+                    let slot = slot_index(slot)?;
+                    let value = self.pop("StoreLocal")?;
+                    let local = self.frames.last_mut()
+                        .and_then(|frame| frame.locals.get_mut(slot))
+                        .ok_or_else(|| anyhow::anyhow!("StoreLocal to slot {}, which the call does not have", slot))?;
+                    *local = Some(value);
+                }
+                Instruction::Unbound { slot } => {
+                    let unbound = self.local(slot_index(slot)?).is_none();
+                    self.stack.push(Value::Bool(unbound));
+                }
+                Instruction::StoreVar { name } => {
+                    let name = constant(bytecode, name)?.to_string();
+                    let value = self.pop("StoreVar")?;
+                    self.globals.insert(name, value);
+                }
+                Instruction::Jump { target } => self.pc = target as usize,
+                Instruction::JumpIfFalse { target } => {
+                    if !self.pop("JumpIfFalse")?.is_truthy() {
+                        self.pc = target as usize;
+                    }
+                }
+                Instruction::JumpTable { table } | Instruction::JumpString { table } => {
+                    let value = self.pop(op.mnemonic())?;
+                    self.pc = dispatch(&table, &value, &bytecode.constants) as usize;
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem
+                | Instruction::Eq
+                | Instruction::Ne
+                | Instruction::Lt
+                | Instruction::Le
+                | Instruction::Gt
+                | Instruction::Ge => {
+                    if self.stack.len() < 2 {
+                        return Err(anyhow::anyhow!("Stack underflow on {}", op.mnemonic()));
+                    }
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push(binary_with(self.math, op, a, b)?);
+                }
+                Instruction::LogString => {
+                    let val = self.pop("LogString")?;
+                    host.log(&format!("{:#}", val));
+                }
+                Instruction::LogValues { count } => {
+                    let count = usize::try_from(count).ok()
+                        .filter(|&n| n <= self.stack.len())
+                        .ok_or_else(|| anyhow::anyhow!("Stack underflow on LogValues"))?;
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    host.log(&log_line(&values));
+                }
+                Instruction::LogAt { count, level } => {
+                    let count = usize::try_from(count).ok()
+                        .filter(|&n| n <= self.stack.len())
+                        .ok_or_else(|| anyhow::anyhow!("Stack underflow on LogAt"))?;
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    if self.logger.enabled(level) {
+                        self.logger.log(host, level, &log_line(&values))?;
+                    }
+                }
+                Instruction::BeginFunc => {
+                    // a body only runs when it is called: step over it
+                    self.pc = skip_func(&bytecode.code, self.pc)?;
+                }
+                Instruction::MakeFunc { header } => {
+                    let name = constant(bytecode, header.name)?;
+                    let captured = match self.frames.last() {
+                        Some(frame) if header.flags & FUNC_CAPTURES != 0 => frame.locals.clone(),
+                        _ => Vec::new(),
+                    };
+                    self.stack.push(Value::Func(Function::new(name, Code { header, captured, unit: self.unit, vm: self.id })));
+                }
+                Instruction::Define { name } => {
+                    let name = constant(bytecode, name)?.to_string();
+                    match self.pop("Define")? {
+                        Value::Func(_) if self.patched.contains(&name) => None,
+                        Value::Func(func) => self.functions.insert(name, func),
+                        other => return Err(anyhow::anyhow!("Define expects a function, found {}", other.type_name())),
+                    };
+                }
+                Instruction::Call { name, argc } | Instruction::CallLocal { name, argc, .. } => {
+                    let slot = match instruction {
+                        Instruction::CallLocal { slot, .. } => Some(slot_index(slot)?),
+                        _ => None,
+                    };
+                    let name = constant(bytecode, name)?;
+                    let argc = argc as usize;
+                    if self.stack.len() < argc {
+                        return Err(anyhow::anyhow!("Stack underflow on call to {}", name));
+                    }
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    // a variable holding a function, a defined function or a native, as the interpreter
+                    let variable = match slot.and_then(|slot| self.local(slot)) {
+                        Some(local) => Some(local),
+                        None => self.globals.get(name),
+                    };
+                    let func = match variable {
+                        Some(Value::Func(func)) => Some(func.clone()),
+                        _ => self.functions.get(name).cloned(),
+                    };
+                    match func {
+                        Some(func) => {
+                            let unit = self.unit;
+                            self.enter(&func, name, args)?;
+                            if self.unit != unit {
+                                return Ok(Exit::Switched);
+                            }
+                        }
+                        None => {
+                            let id = natives::id_of(name)
+                                .ok_or_else(|| anyhow::anyhow!("Undefined function `{}`", name))?;
+                            let result = self.call_native(program, bytecode, at, host, id, args)?;
+                            self.stack.push(result);
+                        }
+                    }
+                }
+                Instruction::EndFunc => {}
+                Instruction::Import { module } => {
+                    let name = constant(bytecode, module)?;
+                    if let Some(unit) = self.import(name)? {
+                        self.frames.push(Frame {
+                            return_pc: self.pc,
+                            stack_height: self.stack.len(),
+                            signal: false,
+                            callback: false,
+                            module: Some(unit),
+                            unit: self.unit,
+                            function: None,
+                            locals: Vec::new(),
+                        });
+                        self.unit = Some(unit);
+                        self.pc = 0;
+                        return Ok(Exit::Switched);
+                    }
+                }
+                Instruction::Halt => {
+                    // the end of a module's top level goes back to its `import`
+                    if self.frames.last().is_none_or(|frame| frame.module.is_none()) {
+                        return Ok(Exit::Halted);
+                    }
+                    let frame = self.frames.pop().expect("checked above");
+                    self.stack.truncate(frame.stack_height);
+                    self.pc = frame.return_pc;
+                    self.unit = frame.unit;
+                    return Ok(Exit::Switched);
+                }
+                Instruction::Try { handler } => {
+                    self.handlers.push(Handler {
+                        pc: handler as usize,
+                        stack_height: self.stack.len(),
+                        frames: self.frames.len(),
+                        unit: self.unit,
+                    });
+                }
+                Instruction::EndTry => {
+                    self.handlers.pop()
+                        .ok_or_else(|| anyhow::anyhow!("EndTry outside of a Try"))?;
+                }
+                Instruction::Throw => {
+                    let value = self.pop("Throw")?;
+                    return Err(Exception::thrown(value).into());
+                }
+                Instruction::OnSignal => {
+                    let handler = self.pop("OnSignal")?;
+                    let signal = self.pop("OnSignal")?;
+                    self.on_signal(&signal, handler)?;
+                }
+                Instruction::SendSignal => {
+                    let signal = self.pop("SendSignal")?;
+                    let pid = self.pop("SendSignal")?;
+                    self.audit(bytecode, at, AuditKind::Signal, "send_signal", &[pid.clone(), signal.clone()])?;
+                    self.send_signal(&pid, &signal)?;
+                }
+                Instruction::CallNative { native, argc } => {
+                    let (id, argc) = (native as u32, argc as usize);
+                    let (name, _) = natives::lookup(id)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown native id {}", id))?;
+                    if self.stack.len() < argc {
+                        return Err(anyhow::anyhow!("Stack underflow on native {}", name));
+                    }
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let result = self.call_native(program, bytecode, at, host, id, args)?;
+                    self.stack.push(result);
+                }
+                Instruction::Sh => {
+                    let script = self.pop("Sh")?;
+                    let (name, native) = natives::id_of("sh").and_then(natives::lookup)
+                        .expect("`sh` is a native");
+                    let output = self.call_impure(bytecode, at, host, name, native, vec![script.clone()])?;
+                    let script = String::try_from(script)
+                        .map_err(|e| anyhow::anyhow!("Sh expects a script: {}", e))?;
+                    crate::process::finish_block(&script, output.try_into()?, host, &mut self.logger)?;
+                }
+                Instruction::Return => {
+                    let frame = self.frames.pop()
+                        .ok_or_else(|| anyhow::anyhow!("Return outside of a call or handler"))?;
+                    if frame.signal {
+                        // the interrupted code must see its stack untouched
+                        self.stack.truncate(frame.stack_height);
+                    } else {
+                        let value = self.pop("Return")?;
+                        self.stack.truncate(frame.stack_height);
+                        self.stack.push(value);
+                    }
+                    self.pc = frame.return_pc;
+                    if frame.callback {
+                        self.unit = frame.unit;
+                        return Ok(Exit::Returned);
+                    }
+                    if frame.unit != self.unit {
+                        self.unit = frame.unit;
+                        return Ok(Exit::Switched);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl VM {
+    fn pop(&mut self, op: &str) -> Result<Value> {
+        self.stack.pop().ok_or_else(|| anyhow::anyhow!("Stack underflow on {}", op))
+    }
+
+    /// Local `slot` of the current call, if it is set.
+    fn local(&self, slot: usize) -> Option<&Value> {
+        self.frames.last().and_then(|frame| frame.locals.get(slot)).and_then(Option::as_ref)
+    }
+
+    /// A global, or else a defined function as a value.
+    fn load_global(&self, name: &str) -> Result<Value> {
+        self.globals.get(name)
+            .cloned()
+            .or_else(|| self.functions.get(name).cloned().map(Value::Func))
+            .ok_or_else(|| anyhow::anyhow!("Undefined variable `{}`", name))
+    }
+
+    /// Start a call of `func`: bind `args` to its parameters the way the
+    /// interpreter does and jump into its body, whose `Return` comes back.
+    fn enter(&mut self, func: &Function, name: &str, mut args: Vec<Value>) -> Result<()> {
+        let Some(code) = func.body::<Code>() else {
+            return Err(anyhow::anyhow!("`{}` cannot be called by the VM", name));
+        };
+        if code.vm != self.id {
+            return Err(anyhow::anyhow!("`{}` was made by another VM and can only be called there", name));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(anyhow::anyhow!("Call stack overflow in `{}` (more than {} nested calls)", name, MAX_CALL_DEPTH));
+        }
+        let header = &code.header;
+        if header.slots > MAX_SLOTS {
+            return Err(anyhow::anyhow!("`{}` needs {} local slots, more than {}", name, header.slots, MAX_SLOTS));
+        }
+        let (required, fixed, rest) = (header.required as usize, header.params as usize, header.flags & FUNC_REST != 0);
+        if args.len() < required || (args.len() > fixed && !rest) {
+            let expected = if rest {
+                format!("at least {}", required)
+            } else if required == fixed {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, fixed)
+            };
+            return Err(anyhow::anyhow!("`{}` takes {} argument(s) but {} were given", name, expected, args.len()));
+        }
+        let extra = args.split_off(args.len().min(fixed));
+        let mut locals = code.captured.clone();
+        let passed = args.len();
+        locals.extend(args.into_iter().map(Some));
+        // the parameters left out stay unset until their defaults run
+        locals.extend((passed..fixed).map(|_| None));
+        if rest {
+            locals.push(Some(Value::Array(extra)));
+        }
+        locals.resize(locals.len().max(header.slots as usize), None);
+        self.frames.push(Frame {
+            return_pc: self.pc,
+            stack_height: self.stack.len(),
+            signal: false,
+            callback: false,
+            module: None,
+            unit: self.unit,
+            function: Some(header.name),
+            locals,
+        });
+        self.pc = header.entry as usize + 1;
+        self.unit = code.unit;
+        Ok(())
+    }
+
+    /// Load module `name` for its first `Import`, returning its unit; `None`
+    /// when it has already run. Importing a module whose top level is still
+    /// running, from itself or a module it imports, is an error.
+    fn import(&mut self, name: &str) -> Result<Option<usize>> {
+        if let Some(&unit) = self.imported.get(name) {
+            let running = self.frames.iter().filter_map(|frame| frame.module);
+            if let Some(start) = running.clone().position(|module| module == unit) {
+                let mut cycle: Vec<&str> = running.skip(start).map(|module| self.unit_name(module)).collect();
+                cycle.push(name);
+                anyhow::bail!("import cycle: {}", cycle.join(" -> "));
+            }
+            return Ok(None);
+        }
+        let module = match &self.modules {
+            Some(modules) => modules.get(name)?,
+            None => None,
+        };
+        let module = module.ok_or_else(|| anyhow::anyhow!("No module `{}` in this runtime", name))?;
+        self.units.push(module);
+        self.imported.insert(name.to_string(), self.units.len() - 1);
+        Ok(Some(self.units.len() - 1))
+    }
+
+    /// The name module `unit` was imported by.
+    fn unit_name(&self, unit: usize) -> &str {
+        self.imported.iter().find(|(_, &module)| module == unit).map_or("?", |(name, _)| name.as_str())
+    }
+
+    /// Call native `id`, guarded, and audited unless it is pure. One that
+    /// calls back runs its functions in this VM.
+    fn call_native(
+        &mut self,
+        program: &Bytecode,
+        bytecode: &Bytecode,
+        at: usize,
+        host: &mut dyn Host,
+        id: u32,
+        args: Vec<Value>,
+    ) -> Result<Value> {
+        let (name, native) = natives::lookup(id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown native id {}", id))?;
+        if let Some(native) = natives::higher_order(name) {
+            return native(&args, &mut |func, args| self.call_value(program, host, func, args));
+        }
+        if natives::is_pure(name) {
+            return native(&mut Guarded { host, permissions: &self.permissions, strict_sh: self.strict_sh }, &args);
+        }
+        self.call_impure(bytecode, at, host, name, native, args)
+    }
+
+    /// Call a native that is not pure: audited, and recorded or replayed
+    /// when there is a trace.
+    fn call_impure(
+        &mut self,
+        bytecode: &Bytecode,
+        at: usize,
+        host: &mut dyn Host,
+        name: &str,
+        native: natives::NativeFn,
+        args: Vec<Value>,
+    ) -> Result<Value> {
+        self.audit(bytecode, at, AuditKind::Ffi, name, &args)?;
+        let mut guarded = Guarded { host, permissions: &self.permissions, strict_sh: self.strict_sh };
+        #[cfg(feature = "record")]
+        if let Some(trace) = &mut self.trace {
+            return trace.call(name, args, |args| native(&mut guarded, args));
+        }
+        native(&mut guarded, &args)
+    }
+
+    /// Log a privileged operation before it happens, when auditing is on.
+    fn audit(&mut self, bytecode: &Bytecode, at: usize, kind: AuditKind, op: &str, args: &[Value]) -> Result<()> {
+        match &mut self.audit {
+            Some(log) => {
+                let source = bytecode.debug.as_ref().map(|d| d.source.as_str());
+                log.record(kind, op, args, source, at)
+            }
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "signals")]
+    fn on_signal(&mut self, signal: &Value, handler: Value) -> Result<()> {
+        let signal = crate::signals::parse_signal(signal)?;
+        let handler = match handler {
+            Value::Func(func) if func.body::<Code>().is_some_and(|code| code.vm == self.id) => func,
+            Value::Func(func) => anyhow::bail!("on_signal: `{}` was made by another VM", func.name()),
+            other => anyhow::bail!("on_signal: expected a function, found {}", other.type_name()),
+        };
+        self.permissions.check_signal_handler()?;
+        self.signals.register(signal, handler)
+    }
+
+    #[cfg(feature = "signals")]
+    fn send_signal(&mut self, pid: &Value, signal: &Value) -> Result<()> {
+        let signal = crate::signals::parse_signal(signal)?;
+        let pid = match pid {
+            Value::Int(pid) => i32::try_from(*pid).map_err(|_| anyhow::anyhow!("Invalid pid {}", pid))?,
+            other => return Err(anyhow::anyhow!("Expected pid, found {}", other.type_name())),
+        };
+        self.permissions.check_signal(pid)?;
+        crate::signals::send_signal(pid, signal)
+    }
+
+    #[cfg(not(feature = "signals"))]
+    fn on_signal(&mut self, _signal: &Value, _handler: Value) -> Result<()> {
+        Err(anyhow::anyhow!("on_signal is not available in this build"))
+    }
+
+    #[cfg(not(feature = "signals"))]
+    fn send_signal(&mut self, _pid: &Value, _signal: &Value) -> Result<()> {
+        Err(anyhow::anyhow!("send_signal is not available in this build"))
+    }
+}
+
+/// The functions `bytecode` defines outside every function body, by name:
+/// each `MakeFunc` that a `Define` follows.
+fn top_level_functions(bytecode: &Bytecode) -> Result<Vec<(String, FuncHeader)>> {
+    let mut defined = Vec::new();
+    let mut depth = 0usize;
+    let mut made = None;
+    for (at, op) in bytecode.instructions() {
+        let instruction = Instruction::decode(&bytecode.code, at).map(|(instruction, _)| instruction);
+        match (op, instruction) {
+            (Opcode::BeginFunc, _) => depth += 1,
+            (Opcode::EndFunc, _) => depth = depth.saturating_sub(1),
+            (_, Some(Instruction::MakeFunc { header })) if depth == 0 => {
+                made = Some(header);
+                continue;
+            }
+            (_, Some(Instruction::Define { name })) if depth == 0 => {
+                if let Some(header) = made {
+                    defined.push((constant(bytecode, name)?.to_string(), header));
+                }
+            }
+            _ => {}
+        }
+        made = None;
+    }
+    Ok(defined)
+}
+
+/// Offset just past the `EndFunc` matching the `BeginFunc` before `pc`.
+fn skip_func(code: &[u8], mut pc: usize) -> Result<usize> {
+    let mut depth = 1;
+    while pc < code.len() {
+        let op = Opcode::from_byte(code[pc])
+            .ok_or_else(|| anyhow::anyhow!("Unknown opcode"))?;
+        pc += instruction_len(code, pc)
+            .ok_or_else(|| anyhow::anyhow!("Incomplete {}", op.mnemonic()))?;
+        match op {
+            Opcode::BeginFunc => depth += 1,
+            Opcode::EndFunc => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(pc);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow::anyhow!("BeginFunc without EndFunc"))
+}
+
+/// What `log a, b, c` prints: each value pretty-printed, separated by spaces.
+pub fn log_line(values: &[Value]) -> String {
+    values.iter().map(|value| format!("{:#}", value)).collect::<Vec<_>>().join(" ")
+}
+
+/// `target[index]`: a missing map key is `null`, an array index out of
+/// range is an error.
+pub fn index(target: &Value, index: &Value) -> Result<Value> {
+    match (target, index) {
+        (Value::Map(entries), Value::Str(key)) => Ok(entries.get(key).cloned().unwrap_or(Value::Null)),
+        (Value::Array(items), Value::Int(i)) => position(*i, items.len())
+            .map(|i| items[i].clone())
+            .ok_or_else(|| anyhow::anyhow!("Index {} out of range for an array of length {}", i, items.len())),
+        (Value::Str(s), Value::Int(i)) => {
+            let len = s.chars().count();
+            position(*i, len)
+                .and_then(|i| s.chars().nth(i))
+                .map(|c| Value::Str(c.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Index {} out of range for a string of length {}", i, len))
+        }
+        (Value::Map(_) | Value::Array(_) | Value::Str(_), index) => Err(anyhow::anyhow!(
+            "Cannot index {} with {}",
+            target.type_name(),
+            index.type_name()
+        )),
+        _ => Err(anyhow::anyhow!("Cannot index {}", target.type_name())),
+    }
+}
+
+/// `target[start:end]` as a new array or string. Positions count characters
+/// in strings; negative ones count from the end. Bounds are clamped to the
+/// length (`null` leaves that end open), and a start at or past the end gives
+/// an empty result, so slicing never fails on an array or string.
+pub fn slice(target: &Value, start: &Value, end: &Value) -> Result<Value> {
+    match target {
+        Value::Array(items) => {
+            let (start, end) = (bound(start, items.len(), 0)?, bound(end, items.len(), items.len())?);
+            Ok(Value::Array(items.get(start..end).unwrap_or_default().to_vec()))
+        }
+        Value::Str(s) => {
+            let len = s.chars().count();
+            let (start, end) = (bound(start, len, 0)?, bound(end, len, len)?);
+            Ok(Value::Str(s.chars().skip(start).take(end.saturating_sub(start)).collect()))
+        }
+        _ => Err(anyhow::anyhow!("Cannot slice {}", target.type_name())),
+    }
+}
+
+/// The element a possibly negative index refers to, if it is in range.
+fn position(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok().filter(|&i| i < len)
+}
+
+fn bound(value: &Value, len: usize, open: usize) -> Result<usize> {
+    match value {
+        Value::Null => Ok(open),
+        Value::Int(i) if *i < 0 => Ok(usize::try_from(len as i64 + i).unwrap_or(0)),
+        Value::Int(i) => Ok(usize::try_from(*i).map_or(len, |i| i.min(len))),
+        other => Err(anyhow::anyhow!("Slice bounds must be ints, found {}", other.type_name())),
+    }
+}
+
+/// What arithmetic does with a result its type cannot hold. Either way, an
+/// int `/` or `%` by zero is a `math:division_by_zero` error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Math {
+    /// Ints wrap around in two's complement, so `9223372036854775807 + 1`
+    /// is `-9223372036854775808`; floats follow IEEE 754, so `1.0 / 0` is
+    /// `inf`, `0.0 / 0` is `NaN` and NaN goes through every operation.
+    #[default]
+    Wrapping,
+    /// An int result out of range and an infinite float from finite operands
+    /// are `math:overflow` errors, a float divided by zero is a
+    /// `math:division_by_zero` error and a NaN from operands that are not
+    /// is a `math:nan` error, all of which `except` catches.
+    Checked,
+}
+
+/// Result of the arithmetic or comparison opcode `op` on two values, as the
+/// VM computes it by default. Other interpreters reuse it so their
+/// operators agree.
+pub fn binary(op: Opcode, a: Value, b: Value) -> Result<Value> {
+    binary_with(Math::Wrapping, op, a, b)
+}
+
+/// `binary` under `math`.
+pub fn binary_with(math: Math, op: Opcode, a: Value, b: Value) -> Result<Value> {
+    match op {
+        Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Rem => arith(math, op, a, b),
+        Opcode::Eq => Ok(Value::Bool(equals(&a, &b))),
+        Opcode::Ne => Ok(Value::Bool(!equals(&a, &b))),
+        _ => {
+            let ord = compare(&a, &b).ok_or_else(|| {
+                anyhow::anyhow!("Cannot compare {} and {}", a.type_name(), b.type_name())
+            })?;
+            Ok(Value::Bool(match op {
+                Opcode::Lt => ord == Ordering::Less,
+                Opcode::Le => ord != Ordering::Greater,
+                Opcode::Gt => ord == Ordering::Greater,
+                _ => ord != Ordering::Less,
+            }))
+        }
+    }
+}
+
+fn arith(math: Math, op: Opcode, a: Value, b: Value) -> Result<Value> {
+    Ok(match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Value::Int(int_arith(math, op, x, y)?),
+        (a @ (Value::Int(_) | Value::Float(_)), b @ (Value::Int(_) | Value::Float(_))) => {
+            Value::Float(float_arith(math, op, as_f64(&a), as_f64(&b))?)
+        }
+        (Value::Str(a), b) if op == Opcode::Add => Value::Str(format!("{}{}", a, b)),
+        (a, b) if op == Opcode::Add => {
+            return Err(anyhow::anyhow!("Cannot add {} and {}", a.type_name(), b.type_name()))
+        }
+        (a, b) => return Err(anyhow::anyhow!("Cannot {} {} and {}", op.mnemonic(), a.type_name(), b.type_name())),
+    })
+}
+
+fn int_arith(math: Math, op: Opcode, x: i64, y: i64) -> Result<i64> {
+    if y == 0 && matches!(op, Opcode::Div | Opcode::Rem) {
+        return Err(math_error("math:division_by_zero", "Division by zero".to_string(), x, y));
+    }
+    let (value, overflowed) = match op {
+        Opcode::Add => x.overflowing_add(y),
+        Opcode::Sub => x.overflowing_sub(y),
+        Opcode::Mul => x.overflowing_mul(y),
+        Opcode::Div => x.overflowing_div(y),
+        _ => x.overflowing_rem(y),
+    };
+    if overflowed && math == Math::Checked {
+        return Err(math_error("math:overflow", format!("Integer overflow in {} {} {}", x, symbol(op), y), x, y));
+    }
+    Ok(value)
+}
+
+fn float_arith(math: Math, op: Opcode, x: f64, y: f64) -> Result<f64> {
+    let value = match op {
+        Opcode::Add => x + y,
+        Opcode::Sub => x - y,
+        Opcode::Mul => x * y,
+        Opcode::Div => x / y,
+        _ => x % y,
+    };
+    if math == Math::Wrapping {
+        return Ok(value);
+    }
+    let expression = || format!("{:?} {} {:?}", x, symbol(op), y);
+    if y == 0.0 && matches!(op, Opcode::Div | Opcode::Rem) && !x.is_nan() {
+        return Err(math_error("math:division_by_zero", "Division by zero".to_string(), x, y));
+    }
+    if value.is_nan() && !x.is_nan() && !y.is_nan() {
+        return Err(math_error("math:nan", format!("NaN from {}", expression()), x, y));
+    }
+    if value.is_infinite() && x.is_finite() && y.is_finite() {
+        return Err(math_error("math:overflow", format!("Float overflow in {}", expression()), x, y));
+    }
+    Ok(value)
+}
+
+/// A `math:` error, with the operands as its `lhs` and `rhs` data.
+fn math_error(kind: &str, message: String, lhs: impl Into<Value>, rhs: impl Into<Value>) -> anyhow::Error {
+    let data = BTreeMap::from([("lhs".to_string(), lhs.into()), ("rhs".to_string(), rhs.into())]);
+    Exception::new(kind, message, data).into()
+}
+
+/// The source operator of an arithmetic opcode.
+fn symbol(op: Opcode) -> &'static str {
+    match op {
+        Opcode::Add => "+",
+        Opcode::Sub => "-",
+        Opcode::Mul => "*",
+        Opcode::Div => "/",
+        _ => "%",
+    }
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Int(i) => *i as f64,
+        Value::Float(x) => *x,
+        _ => f64::NAN,
+    }
+}
+
+/// The constant an operand names.
+fn constant(bytecode: &Bytecode, index: u64) -> Result<&str> {
+    bytecode.constants.get(index as usize)
+        .ok_or_else(|| anyhow::anyhow!("Invalid constant index"))
+}
+
+/// A local slot operand as an index into the call's locals.
+fn slot_index(slot: u64) -> Result<usize> {
+    usize::try_from(slot).map_err(|_| anyhow::anyhow!("Invalid slot {}", slot))
+}
+
+/// Where a `JumpTable` or `JumpString` goes for `value`: the entry `Eq`
+/// would match, or the default. A table entry matches an int, or a float
+/// with that value.
+fn dispatch(table: &Dispatch, value: &Value, constants: &Constants) -> u32 {
+    let index = match value {
+        Value::Str(s) if table.strings() => {
+            let key = |i: usize| constants.get(table.key(i) as usize);
+            partition_point(table.len(), |i| key(i) < Some(s.as_str())).filter(|&i| key(i) == Some(s.as_str()))
+        }
+        Value::Int(n) if !table.strings() => n.checked_sub(table.low).and_then(|i| usize::try_from(i).ok()),
+        Value::Float(x) if !table.strings() && x.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(x) => {
+            (*x as i64).checked_sub(table.low).and_then(|i| usize::try_from(i).ok())
+        }
+        _ => None,
+    };
+    index.filter(|&i| i < table.len()).map_or(table.default, |i| table.target(i))
+}
+
+/// The first of `0..len` for which `before` is false, `before` being true
+/// for a prefix of them; `None` if it is true for all.
+fn partition_point(len: usize, before: impl Fn(usize) -> bool) -> Option<usize> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if before(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    (low < len).then_some(low)
+}
+
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) => as_f64(a) == as_f64(b),
+        _ => a == b,
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => as_f64(a).partial_cmp(&as_f64(b)),
+        (Value::Str(x), Value::Str(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
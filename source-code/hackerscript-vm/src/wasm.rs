@@ -0,0 +1,88 @@
+// WebAssembly entry points. The embedding page/runtime provides the
+// `hackerscript` import module: `log`, `read` and `write` go to its
+// functions, and `sh` and the terminal natives are not available. The VM
+// never touches files or processes here.
+use anyhow::{bail, Result};
+use hackerscript_bytecode::{verify, Bytecode};
+use crate::host::Host;
+use crate::vm::VM;
+
+#[link(wasm_import_module = "hackerscript")]
+extern "C" {
+    fn hs_host_log(ptr: *const u8, len: usize);
+    /// Returns a buffer from `hs_alloc` holding the contents of `path` and
+    /// stores its length in `len`, or returns null if there is no such file.
+    fn hs_host_read(path: *const u8, path_len: usize, len: *mut usize) -> *mut u8;
+    /// Returns 0 once `data` is stored under `path`, anything else on failure.
+    fn hs_host_write(path: *const u8, path_len: usize, data: *const u8, data_len: usize) -> i32;
+}
+
+/// Host that forwards everything to the functions imported from the wasm embedder.
+#[derive(Debug, Default)]
+pub struct WasmHost;
+
+impl Host for WasmHost {
+    fn log(&mut self, message: &str) {
+        unsafe { hs_host_log(message.as_ptr(), message.len()) }
+    }
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>> {
+        let mut len = 0;
+        let ptr = unsafe { hs_host_read(path.as_ptr(), path.len(), &mut len) };
+        if ptr.is_null() {
+            bail!("Cannot read {}", path);
+        }
+        // the embedder filled a buffer it got from `hs_alloc(len)`
+        Ok(unsafe { Vec::from_raw_parts(ptr, len, len) })
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        match unsafe { hs_host_write(path.as_ptr(), path.len(), data.as_ptr(), data.len()) } {
+            0 => Ok(()),
+            code => bail!("Cannot write {} (host error {})", path, code),
+        }
+    }
+}
+
+/// Allocate `len` bytes inside wasm memory so the embedder can copy bytecode in.
+#[no_mangle]
+pub extern "C" fn hs_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// Free a buffer previously returned by `hs_alloc`.
+///
+/// # Safety
+/// `ptr` must come from `hs_alloc(len)` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn hs_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Run the bytecode stored at `ptr..ptr+len`. Returns 0 on success, 1 on error
+/// (the error message is reported through `hs_host_log`) and 2 if `ptr` is null.
+///
+/// # Safety
+/// `ptr` must be null or point to `len` initialized bytes.
+#[no_mangle]
+pub unsafe extern "C" fn hs_run(ptr: *const u8, len: usize) -> i32 {
+    if ptr.is_null() {
+        return 2;
+    }
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let mut host = WasmHost;
+    let result = Bytecode::from_bytes(bytes).and_then(|bytecode| {
+        verify(&bytecode)?;
+        VM::new().run(&bytecode, &mut host)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            host.log(&format!("error: {}", err));
+            1
+        }
+    }
+}
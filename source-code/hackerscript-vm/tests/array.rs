@@ -0,0 +1,44 @@
+use hackerscript_vm::{natives, BufferHost, Function, Value};
+
+/// Run higher-order native `name`, calling back `f` for the function it is given.
+fn call(name: &str, args: &[Value], f: impl Fn(Vec<Value>) -> Value) -> Result<Value, String> {
+    let native = natives::higher_order(name).unwrap();
+    native(args, &mut |_: &Function, args| Ok(f(args))).map_err(|e| e.to_string())
+}
+
+fn ints(items: &[i64]) -> Value {
+    Value::Array(items.iter().map(|&n| Value::Int(n)).collect())
+}
+
+#[test]
+fn natives_that_call_back_are_only_reached_through_higher_order() {
+    let f = Value::Func(Function::new("f", ()));
+    for name in ["map", "filter", "reduce", "sort", "find", "any", "all"] {
+        let (_, native) = natives::lookup(natives::id_of(name).unwrap()).unwrap();
+        assert!(native(&mut BufferHost::default(), &[ints(&[1]), f.clone()]).is_err(), "{}", name);
+        assert!(natives::is_pure(name), "{}", name);
+    }
+    assert!(natives::higher_order("zip").is_none() && natives::higher_order("int").is_none());
+}
+
+#[test]
+fn callbacks_see_each_item_and_errors_name_the_native() {
+    let f = Value::Func(Function::new("f", ()));
+    let sum = |args: Vec<Value>| match args.as_slice() {
+        [Value::Int(a), Value::Int(b)] => Value::Int(a + b),
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(call("reduce", &[ints(&[1, 2, 3]), f.clone()], sum), Ok(Value::Int(6)));
+    assert_eq!(call("reduce", &[ints(&[1, 2, 3]), f.clone(), Value::Int(10)], sum), Ok(Value::Int(16)));
+    assert_eq!(call("reduce", &[ints(&[]), f.clone()], sum), Err("reduce: empty array and no initial value".into()));
+    assert_eq!(call("map", &[Value::Int(1), f.clone()], sum), Err("map: expected array, found int".into()));
+    assert_eq!(call("map", &[ints(&[1]), Value::Int(1)], sum), Err("map: expected function, found int".into()));
+    assert_eq!(
+        call("sort", &[ints(&[2, 1]), f], |_| Value::Bool(true)),
+        Err("sort: the comparator returned bool, not a number".into())
+    );
+
+    let (_, zip) = natives::lookup(natives::id_of("zip").unwrap()).unwrap();
+    let pairs = zip(&mut BufferHost::default(), &[ints(&[1, 2, 3]), ints(&[4, 5])]).unwrap();
+    assert_eq!(pairs, Value::Array(vec![ints(&[1, 4]), ints(&[2, 5])]));
+}
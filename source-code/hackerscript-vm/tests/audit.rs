@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use hackerscript_bytecode::{BytecodeEmitter, Opcode};
+use hackerscript_vm::audit::AuditLog;
+use hackerscript_vm::{natives, BufferHost, VM};
+
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Broken;
+
+impl Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `term_bold("a \"quoted\"\nline")`, then log the result.
+fn native_call() -> hackerscript_bytecode::Bytecode {
+    let mut e = BytecodeEmitter::new();
+    e.set_source("tool.hcs");
+    let text = e.add_constant("a \"quoted\"\nline".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(text as u64);
+    e.emit(Opcode::CallNative);
+    e.emit_varint(u64::from(natives::id_of("term_bold").unwrap()));
+    e.emit_u8(1);
+    e.emit(Opcode::LogString);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn native_calls_are_recorded_as_json_lines() {
+    let out = Shared::default();
+    let mut vm = VM::new();
+    vm.set_audit(AuditLog::new(out.clone()));
+    vm.run(&native_call(), &mut BufferHost::default()).unwrap();
+
+    let log = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", log);
+    let line = lines[0];
+    assert!(line.starts_with("{\"time\":\""), "{}", line);
+    let time = &line[9..33];
+    assert!(time.ends_with('Z') && time.as_bytes()[10] == b'T', "{}", time);
+    assert!(
+        line.ends_with(r#"Z","kind":"ffi","op":"term_bold","args":["a \"quoted\"\nline"],"source":"tool.hcs","offset":2}"#),
+        "{}",
+        line
+    );
+}
+
+#[test]
+fn a_failing_audit_log_stops_the_operation() {
+    let mut vm = VM::new();
+    vm.set_audit(AuditLog::new(Broken));
+    let mut host = BufferHost::default();
+    let err = vm.run(&native_call(), &mut host).unwrap_err();
+    assert!(format!("{:#}", err).contains("audit log"), "{:#}", err);
+    assert!(host.lines.is_empty());
+}
+
+#[test]
+fn nothing_is_recorded_without_privileged_operations() {
+    let out = Shared::default();
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::PushInt);
+    e.emit_i64(1);
+    e.emit(Opcode::LogString);
+    e.emit(Opcode::Halt);
+    let mut vm = VM::new();
+    vm.set_audit(AuditLog::new(out.clone()));
+    vm.run(&e.finish(), &mut BufferHost::default()).unwrap();
+    assert!(out.0.lock().unwrap().is_empty());
+}
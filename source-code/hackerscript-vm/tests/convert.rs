@@ -0,0 +1,68 @@
+use hackerscript_vm::{natives, BufferHost, Exception, Value};
+
+fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let (_, native) = natives::lookup(natives::id_of(name).unwrap()).unwrap();
+    native(&mut BufferHost::default(), args).map_err(|e| e.to_string())
+}
+
+#[test]
+fn int_parses_prefixed_and_signed_text() {
+    assert_eq!(call("int", &["-0x8000000000000000".into()]), Ok(Value::Int(i64::MIN)));
+    assert_eq!(call("int", &["+0B11".into()]), Ok(Value::Int(3)));
+    assert_eq!(call("int", &["0o17".into()]), Ok(Value::Int(15)));
+    assert_eq!(call("int", &[Value::Float(-2.7)]), Ok(Value::Int(-2)));
+}
+
+#[test]
+fn failed_conversions_are_errors_not_zero() {
+    for (name, arg) in [
+        ("int", Value::from("")),
+        ("int", Value::from("0x")),
+        ("int", Value::from("0x-1")),
+        ("int", Value::from("1.5")),
+        ("int", Value::from("99999999999999999999")),
+        ("int", Value::Float(f64::NAN)),
+        ("int", Value::Float(1e19)),
+        ("int", Value::Null),
+        ("float", Value::from("pi")),
+        ("hex", Value::Float(1.0)),
+        ("bin", Value::from("5")),
+    ] {
+        let err = call(name, std::slice::from_ref(&arg)).expect_err(&format!("{}({:?})", name, arg));
+        assert!(err.starts_with(&format!("{}: ", name)), "{}", err);
+    }
+    assert_eq!(call("str", &[]).unwrap_err(), "str: missing argument 1");
+    assert_eq!(call("str", &[Value::Null, Value::Null]).unwrap_err(), "str: takes 1 argument but 2 were given");
+}
+
+#[test]
+fn failed_conversions_have_their_own_kind() {
+    for (name, arg) in [("int", Value::from("4 2")), ("float", Value::Null), ("bin", Value::from("5"))] {
+        let (_, native) = natives::lookup(natives::id_of(name).unwrap()).unwrap();
+        let err = native(&mut BufferHost::default(), std::slice::from_ref(&arg)).unwrap_err();
+        let Value::Map(caught) = Exception::caught(&err, Vec::new()) else { panic!("errors are maps") };
+        assert_eq!(caught["kind"], Value::from("conversion:invalid"), "{}", name);
+        assert_eq!(caught["data"], Value::from(std::collections::BTreeMap::from([("value".to_string(), arg)])));
+    }
+    // a wrong number of arguments is a mistake in the script, not a value that did not convert
+    let (_, native) = natives::lookup(natives::id_of("str").unwrap()).unwrap();
+    let err = native(&mut BufferHost::default(), &[]).unwrap_err();
+    let Value::Map(caught) = Exception::caught(&err, Vec::new()) else { panic!("errors are maps") };
+    assert_eq!(caught["kind"], Value::from("error"));
+}
+
+#[test]
+fn hex_and_bin_round_trip_through_int() {
+    for n in [0, 1, -1, 255, i64::MAX, i64::MIN] {
+        for name in ["hex", "bin"] {
+            let text = call(name, &[Value::Int(n)]).unwrap();
+            assert_eq!(call("int", &[text]), Ok(Value::Int(n)));
+        }
+    }
+}
+
+#[test]
+fn only_privileged_natives_are_audited() {
+    assert!(natives::is_pure("str"));
+    assert!(!natives::is_pure("term_prompt"));
+}
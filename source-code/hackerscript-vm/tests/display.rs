@@ -0,0 +1,31 @@
+use std::collections::BTreeMap;
+
+use hackerscript_vm::Value;
+
+fn map(entries: &[(&str, Value)]) -> Value {
+    Value::Map(entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<BTreeMap<_, _>>())
+}
+
+#[test]
+fn strings_are_quoted_only_inside_collections() {
+    let value = Value::Array(vec![Value::from("a\"b"), Value::Int(1), Value::Null]);
+    assert_eq!(Value::from("plain").to_string(), "plain");
+    assert_eq!(value.to_string(), r#"["a\"b", 1, null]"#);
+    assert_eq!(format!("{:#}", value), value.to_string());
+}
+
+#[test]
+fn wide_values_break_only_where_needed() {
+    let long = "x".repeat(60);
+    let value = map(&[
+        ("inner", map(&[("a", Value::from(long.as_str())), ("b", Value::Array(vec![Value::Int(1)]))])),
+        ("short", Value::Int(2)),
+    ]);
+    let expected = format!(
+        "{{\n  \"inner\": {{\n    \"a\": \"{}\",\n    \"b\": [1],\n  }},\n  \"short\": 2,\n}}",
+        long
+    );
+    assert_eq!(format!("{:#}", value), expected);
+    // without `#` everything stays on one line
+    assert!(!value.to_string().contains('\n'));
+}
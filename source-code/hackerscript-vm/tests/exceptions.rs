@@ -0,0 +1,171 @@
+use hackerscript_bytecode::{Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::{natives, BufferHost, Exception, Math, Permissions, Value, VM};
+
+/// `try [ <body> ] except [ ]`, halting with the error on the stack.
+fn guarded(body: impl FnOnce(&mut BytecodeEmitter)) -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::Try);
+    let handler = e.position();
+    e.emit_u32(0);
+    body(&mut e);
+    e.emit(Opcode::EndTry);
+    e.emit(Opcode::PushNull);
+    e.emit(Opcode::Halt);
+    e.patch_u32(handler, e.position() as u32);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+fn run(bytecode: &Bytecode) -> VM {
+    let mut vm = VM::new();
+    vm.run(bytecode, &mut BufferHost::default()).unwrap();
+    vm
+}
+
+#[test]
+fn errors_unwind_the_stack_to_the_try() {
+    let mut vm = VM::new();
+    vm.run(
+        &guarded(|e| {
+            for n in [1, 2, 0] {
+                e.emit(Opcode::PushInt);
+                e.emit_i64(n);
+            }
+            e.emit(Opcode::Div);
+        }),
+        &mut BufferHost::default(),
+    )
+    .unwrap();
+    let Some(Value::Map(err)) = vm.result() else { panic!("unexpected {:?}", vm.result()) };
+    assert_eq!(err["kind"], Value::from("math:division_by_zero"));
+    assert_eq!(err["message"], Value::from("Division by zero"));
+
+    // under the error: what was there before the `try`, not what the body pushed
+    let mut bytecode = guarded(|e| {
+        e.emit(Opcode::PushInt);
+        e.emit_i64(1);
+        e.emit(Opcode::Throw);
+    });
+    let halt = bytecode.code.len() - 1;
+    bytecode.code.insert(halt, Opcode::Pop as u8);
+    let mut vm = VM::new();
+    vm.push(Value::Int(7));
+    vm.push(Value::Int(8));
+    vm.run(&bytecode, &mut BufferHost::default()).unwrap();
+    assert_eq!(vm.result(), Some(&Value::Int(8)));
+}
+
+#[test]
+fn thrown_maps_are_caught_as_they_are_and_other_values_become_messages() {
+    let vm = run(&guarded(|e| {
+        let key = e.add_constant("code".to_string());
+        e.emit(Opcode::PushConst);
+        e.emit_varint(key as u64);
+        e.emit(Opcode::PushInt);
+        e.emit_i64(3);
+        e.emit(Opcode::MakeMap);
+        e.emit_varint(1);
+        e.emit(Opcode::Throw);
+    }));
+    // with the fields every error has
+    assert_eq!(
+        vm.result().unwrap().to_string(),
+        r#"{"code": 3, "data": {}, "kind": "error", "message": "", "stack": ["top level"]}"#
+    );
+
+    let vm = run(&guarded(|e| {
+        e.emit(Opcode::PushInt);
+        e.emit_i64(42);
+        e.emit(Opcode::Throw);
+    }));
+    let Some(Value::Map(err)) = vm.result() else { panic!("unexpected {:?}", vm.result()) };
+    assert_eq!((&err["kind"], &err["message"]), (&Value::from("error"), &Value::from("42")));
+}
+
+#[test]
+fn uncaught_throws_stop_the_run_with_their_message() {
+    let mut e = BytecodeEmitter::new();
+    let idx = e.add_constant("boom".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(idx as u64);
+    e.emit(Opcode::Throw);
+    e.emit(Opcode::Halt);
+    let err = VM::new().run(&e.finish(), &mut BufferHost::default()).unwrap_err();
+    assert_eq!(err.to_string(), "boom");
+    let Exception(value) = err.downcast().unwrap();
+    assert_eq!(value.to_string(), r#"{"data": {}, "kind": "error", "message": "boom", "stack": []}"#);
+}
+
+#[test]
+fn end_try_without_a_try_is_an_error() {
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::EndTry);
+    e.emit(Opcode::Halt);
+    let err = VM::new().run(&e.finish(), &mut BufferHost::default()).unwrap_err();
+    assert_eq!(err.to_string(), "EndTry outside of a Try");
+}
+
+#[test]
+fn error_builds_the_value_throw_raises() {
+    let call = |args: &[Value]| {
+        let (_, native) = natives::lookup(natives::id_of("Error").unwrap()).unwrap();
+        native(&mut BufferHost::default(), args).map_err(|e| e.to_string())
+    };
+    let data = Value::Map([("after".to_string(), Value::Int(5))].into());
+    let err = call(&["net:timeout".into(), "no answer".into(), data.clone()]).unwrap();
+    assert_eq!(
+        err.to_string(),
+        r#"{"data": {"after": 5}, "kind": "net:timeout", "message": "no answer", "stack": []}"#
+    );
+    assert_eq!(Exception::thrown(err.clone()), Exception(err));
+    let Ok(Value::Map(err)) = call(&["config:missing".into()]) else { panic!() };
+    assert_eq!((&err["message"], &err["data"]), (&Value::from(""), &Value::Map(Default::default())));
+
+    assert_eq!(call(&[]).unwrap_err(), "Error: missing argument 1");
+    assert_eq!(call(&["".into()]).unwrap_err(), "Error: the kind is empty");
+    assert_eq!(
+        call(&["a".into(), "b".into(), Value::Int(1)]).unwrap_err(),
+        "Error: expected map for the data, found int"
+    );
+}
+
+#[test]
+fn failures_of_the_runtime_are_classified() {
+    let bytecode = guarded(|e| {
+        let idx = e.add_constant("true".to_string());
+        e.emit(Opcode::PushConst);
+        e.emit_varint(idx as u64);
+        e.emit(Opcode::CallNative);
+        e.emit_varint(u64::from(natives::id_of("sh").unwrap()));
+        e.emit_u8(1);
+    });
+    let mut vm = VM::new();
+    vm.set_permissions(Permissions::none());
+    vm.run(&bytecode, &mut BufferHost::default()).unwrap();
+    let Some(Value::Map(err)) = vm.result() else { panic!("unexpected {:?}", vm.result()) };
+    assert_eq!(err["kind"], Value::from("permission:run"));
+    assert_eq!(err["data"].to_string(), r#"{"capability": "run", "target": "sh"}"#);
+    assert_eq!(err["stack"], Value::Array(vec![Value::from("top level")]));
+}
+
+#[test]
+fn checked_math_raises_overflow_as_an_error() {
+    let bytecode = guarded(|e| {
+        for n in [i64::MIN, -1] {
+            e.emit(Opcode::PushInt);
+            e.emit_i64(n);
+        }
+        e.emit(Opcode::Div);
+    });
+    let mut vm = VM::new();
+    vm.run(&bytecode, &mut BufferHost::default()).unwrap();
+    assert_eq!(vm.result(), Some(&Value::Null), "wraps by default");
+
+    let mut vm = VM::new();
+    vm.set_math(Math::Checked);
+    vm.run(&bytecode, &mut BufferHost::default()).unwrap();
+    let Some(Value::Map(err)) = vm.result() else { panic!("unexpected {:?}", vm.result()) };
+    assert_eq!(err["kind"], Value::from("math:overflow"));
+    assert_eq!(err["message"], Value::from("Integer overflow in -9223372036854775808 / -1"));
+    assert_eq!(err["data"].to_string(), r#"{"lhs": -9223372036854775808, "rhs": -1}"#);
+}
@@ -0,0 +1,41 @@
+use hackerscript_vm::vm::{index, slice};
+use hackerscript_vm::Value;
+
+fn ints(values: &[i64]) -> Value {
+    Value::Array(values.iter().copied().map(Value::Int).collect())
+}
+
+#[test]
+fn negative_indexes_count_from_the_end() {
+    let xs = ints(&[10, 20, 30]);
+    assert_eq!(index(&xs, &Value::Int(-1)).unwrap(), Value::Int(30));
+    assert_eq!(index(&xs, &Value::Int(-3)).unwrap(), Value::Int(10));
+    assert!(index(&xs, &Value::Int(-4)).is_err());
+    assert!(index(&xs, &Value::Int(3)).is_err());
+}
+
+#[test]
+fn array_slices_clamp_and_copy() {
+    let xs = ints(&[1, 2, 3, 4, 5]);
+    let cases: [(Value, Value, &[i64]); 6] = [
+        (Value::Int(1), Value::Int(4), &[2, 3, 4]),
+        (Value::Int(-2), Value::Null, &[4, 5]),
+        (Value::Null, Value::Int(-3), &[1, 2]),
+        (Value::Int(-99), Value::Int(99), &[1, 2, 3, 4, 5]),
+        (Value::Int(4), Value::Int(1), &[]),
+        (Value::Int(i64::MIN), Value::Int(i64::MAX), &[1, 2, 3, 4, 5]),
+    ];
+    for (start, end, expected) in cases {
+        assert_eq!(slice(&xs, &start, &end).unwrap(), ints(expected), "[{}:{}]", start, end);
+    }
+    // the source is untouched
+    assert_eq!(xs, ints(&[1, 2, 3, 4, 5]));
+}
+
+#[test]
+fn slicing_needs_int_bounds_and_a_sequence() {
+    let s = Value::from("abc");
+    assert!(slice(&s, &Value::from("1"), &Value::Null).is_err());
+    assert!(slice(&Value::Int(5), &Value::Null, &Value::Null).is_err());
+    assert_eq!(slice(&s, &Value::Int(1), &Value::Null).unwrap(), Value::from("bc"));
+}
@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use hackerscript_bytecode::{verify, Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::logger::Destination;
+use hackerscript_vm::{BufferHost, LogLevel, Logger, VM};
+
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `log.<level> "<level>", 1` for every level, then a plain `log "done"`.
+fn every_level() -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    for level in [LogLevel::Debug, LogLevel::Info, LogLevel::Warn, LogLevel::Error] {
+        let name = e.add_constant(level.name().to_string());
+        e.emit(Opcode::PushConst);
+        e.emit_varint(name as u64);
+        e.emit(Opcode::PushInt);
+        e.emit_i64(1);
+        e.emit(Opcode::LogAt);
+        e.emit_varint(2);
+        e.emit_u8(level as u8);
+    }
+    let done = e.add_constant("done".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(done as u64);
+    e.emit(Opcode::LogString);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn drops_messages_below_the_minimum_level() {
+    let bytecode = every_level();
+    verify(&bytecode).unwrap();
+
+    let mut host = BufferHost::default();
+    VM::new().run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["[info] info 1", "[warn] warn 1", "[error] error 1", "done"]);
+
+    let mut vm = VM::new();
+    vm.set_logger(Logger::new(LogLevel::Error, Destination::Host));
+    let mut host = BufferHost::default();
+    vm.run(&bytecode, &mut host).unwrap();
+    assert_eq!(host.lines, ["[error] error 1", "done"]);
+}
+
+#[test]
+fn writers_get_timestamped_lines_and_plain_log_stays_on_the_host() {
+    let out = Shared::default();
+    let mut vm = VM::new();
+    vm.set_logger(Logger::new(LogLevel::Debug, Destination::Writer(Box::new(out.clone()))));
+    let mut host = BufferHost::default();
+    vm.run(&every_level(), &mut host).unwrap();
+    assert_eq!(host.lines, ["done"]);
+
+    let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4);
+    // 2026-01-02T03:04:05.678Z WARN  warn 1
+    let (time, rest) = lines[2].split_once(' ').unwrap();
+    assert_eq!(time.len(), 24);
+    assert!(time.ends_with('Z') && time.as_bytes()[10] == b'T', "{}", time);
+    assert_eq!(rest, "WARN  warn 1");
+    assert!(lines[0].ends_with(" DEBUG debug 1"));
+}
+
+#[test]
+fn levels_parse_case_insensitively() {
+    assert_eq!(LogLevel::parse("WARNING"), Some(LogLevel::Warn));
+    assert_eq!(LogLevel::parse("Debug"), Some(LogLevel::Debug));
+    assert_eq!(LogLevel::parse("trace"), None);
+}
+
+#[test]
+fn verify_rejects_unknown_levels() {
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::LogAt);
+    e.emit_varint(0);
+    e.emit_u8(9);
+    e.emit(Opcode::Halt);
+    assert!(verify(&e.finish()).unwrap_err().to_string().contains("unknown level 9"));
+}
@@ -0,0 +1,8 @@
+use hackerscript_vm::natives::{self, NATIVES};
+
+#[test]
+fn the_table_follows_the_bytecode_ids() {
+    let names: Vec<&str> = NATIVES.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, hackerscript_bytecode::NATIVES);
+    assert_eq!(natives::id_of("sh"), hackerscript_bytecode::native_id("sh"));
+}
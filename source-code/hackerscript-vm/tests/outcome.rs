@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use hackerscript_bytecode::{Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::logger::Destination;
+use hackerscript_vm::{BufferHost, LogLevel, LogRecord, Logger, RunOutcome, Value, VM};
+
+/// `log "start"`, `log.info "busy"`, then `1 / divisor`.
+fn program(divisor: i64) -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    let start = e.add_constant("start".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(start as u64);
+    e.emit(Opcode::LogString);
+    let busy = e.add_constant("busy".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(busy as u64);
+    e.emit(Opcode::LogAt);
+    e.emit_varint(1);
+    e.emit_u8(LogLevel::Info as u8);
+    e.emit(Opcode::PushInt);
+    e.emit_i64(1);
+    e.emit(Opcode::PushInt);
+    e.emit_i64(divisor);
+    e.emit(Opcode::Div);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn outcomes_collect_the_result_and_the_logs() {
+    let mut host = BufferHost::default();
+    let outcome = VM::new().run_outcome(&program(1), &mut host);
+    assert!(outcome.is_ok());
+    assert_eq!(outcome.result, Some(Value::Int(1)));
+    assert_eq!(
+        outcome.logs,
+        [
+            LogRecord { level: None, message: "start".into() },
+            LogRecord { level: Some(LogLevel::Info), message: "busy".into() },
+        ]
+    );
+    // nothing reaches the host
+    assert!(host.lines.is_empty());
+}
+
+#[test]
+fn a_failed_run_keeps_what_was_logged_before_the_error() {
+    let mut vm = VM::new();
+    vm.set_logger(Logger::new(LogLevel::Warn, Destination::Host));
+    let outcome = vm.run_outcome(&program(0), &mut BufferHost::default());
+    assert_eq!(outcome.result, None);
+    assert_eq!(outcome.logs, [LogRecord { level: None, message: "start".into() }]);
+    assert_eq!(outcome.error.as_deref(), Some("Division by zero"));
+}
+
+#[test]
+fn outcomes_serialize_to_json() {
+    let outcome = RunOutcome {
+        result: Some(Value::from(vec![Value::Int(1), Value::Str("a\"b".into())])),
+        logs: vec![
+            LogRecord { level: None, message: "line\n".into() },
+            LogRecord { level: Some(LogLevel::Error), message: "bad".into() },
+        ],
+        duration: Duration::from_micros(1500),
+        error: None,
+    };
+    assert_eq!(
+        outcome.to_json(),
+        r#"{"ok":true,"result":[1,"a\"b"],"logs":[{"level":null,"message":"line\n"},{"level":"error","message":"bad"}],"duration_ms":1.500,"error":null}"#
+    );
+    let failed = RunOutcome { error: Some("boom".into()), ..RunOutcome::default() };
+    assert_eq!(failed.to_json(), r#"{"ok":false,"result":null,"logs":[],"duration_ms":0.000,"error":"boom"}"#);
+}
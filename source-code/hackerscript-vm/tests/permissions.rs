@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+
+use hackerscript_vm::{PermissionDenied, Permissions};
+
+fn sandbox(flags: &[&str]) -> Permissions {
+    let args = std::iter::once("--sandbox").chain(flags.iter().copied()).map(String::from);
+    let (permissions, rest) = Permissions::from_args(args).unwrap();
+    assert!(rest.is_empty());
+    permissions
+}
+
+#[test]
+fn without_flags_everything_is_allowed() {
+    let args = ["prog.bc".to_string()];
+    let (permissions, rest) = Permissions::from_args(args).unwrap();
+    assert_eq!(permissions, Permissions::all());
+    assert_eq!(rest, ["prog.bc"]);
+    assert!(permissions.check_run("rm").is_ok());
+}
+
+#[test]
+fn net_grants_match_addresses_ranges_and_names() {
+    let permissions = sandbox(&["--allow-net=10.0.0.0/8,192.168.1.7,Example.com,fd00::/8"]);
+    for host in ["10.1.2.3", "10.255.0.1", "192.168.1.7", "example.com", "fd12::1"] {
+        assert!(permissions.check_net(host).is_ok(), "{}", host);
+    }
+    for host in ["11.0.0.1", "192.168.1.8", "evil.com", "fe80::1"] {
+        assert!(permissions.check_net(host).is_err(), "{}", host);
+    }
+    assert!(sandbox(&["--allow-net=0.0.0.0/0"]).check_net("8.8.8.8").is_ok());
+    assert!(sandbox(&["--allow-net"]).check_net("anything").is_ok());
+}
+
+#[test]
+fn denials_name_the_missing_capability() {
+    let err = sandbox(&["--allow-run=nmap"]).check_run("masscan").unwrap_err();
+    assert_eq!(err, PermissionDenied { capability: "run", target: "masscan".into() });
+    assert_eq!(
+        err.to_string(),
+        "missing capability: run access to `masscan` (grant it with --allow-run=masscan)"
+    );
+}
+
+#[test]
+fn paths_cannot_escape_a_granted_directory() {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("permissions");
+    let granted = root.join("granted");
+    fs::create_dir_all(&granted).unwrap();
+    fs::create_dir_all(root.join("secret")).unwrap();
+    let link = granted.join("link");
+    let _ = fs::remove_file(&link);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("secret"), &link).unwrap();
+
+    let permissions = sandbox(&[&format!("--allow-read={}", granted.display())]);
+    assert!(permissions.check_read(&granted.join("new-file")).is_ok());
+    assert!(permissions.check_read(&granted.join("../secret/key")).is_err());
+    #[cfg(unix)]
+    assert!(permissions.check_read(&link.join("key")).is_err());
+    assert!(permissions.check_write(&granted.join("new-file")).is_err());
+}
+
+#[test]
+fn malformed_flags_are_rejected() {
+    for flag in ["--allow-net=10.0.0.0/33", "--allow-net=10.0.0.0/x", "--allow-signal=init", "--allow-everything"] {
+        assert!(Permissions::none().parse_flag(flag).is_err(), "{}", flag);
+    }
+}
+
+#[cfg(feature = "signals")]
+#[test]
+fn signals_need_the_signal_capability() {
+    use hackerscript_bytecode::{BytecodeEmitter, Opcode};
+    use hackerscript_vm::{BufferHost, VM};
+
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::PushInt);
+    e.emit_i64(i64::from(std::process::id()));
+    let name = e.add_constant("TERM".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(name as u64);
+    e.emit(Opcode::SendSignal);
+    e.emit(Opcode::Halt);
+    let bytecode = e.finish();
+
+    let mut vm = VM::new();
+    vm.set_permissions(sandbox(&["--allow-signal=1"]));
+    let err = vm.run(&bytecode, &mut BufferHost::default()).unwrap_err();
+    let denied = err.downcast_ref::<PermissionDenied>().expect("a permission error");
+    assert_eq!(denied.capability, "signal");
+}
@@ -0,0 +1,147 @@
+use std::time::{Duration, Instant};
+
+use hackerscript_bytecode::{Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::process::run_shell;
+use hackerscript_vm::{natives, Permissions, ProcessError, ProcessOutput, StdHost, Value, VM};
+
+#[test]
+fn commands_report_their_output_and_exit_code() {
+    let output = run_shell("echo out; echo err >&2; exit 3", None).unwrap();
+    assert_eq!(
+        output,
+        ProcessOutput { stdout: "out\n".into(), stderr: "err\n".into(), code: Some(3), timed_out: false }
+    );
+    let value = Value::from(output);
+    assert_eq!(value.to_string(), r#"{"code": 3, "stderr": "err\n", "stdout": "out\n", "timed_out": false}"#);
+}
+
+#[test]
+fn a_timeout_kills_the_command_and_what_it_started() {
+    let start = Instant::now();
+    let output = run_shell("sleep 5; echo late", Some(Duration::from_millis(100))).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(4), "took {:?}", start.elapsed());
+    assert_eq!(output, ProcessOutput { timed_out: true, ..ProcessOutput::default() });
+}
+
+/// `sh(command)`, leaving the result on the stack.
+fn sh(command: &str) -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    let idx = e.add_constant(command.to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(idx as u64);
+    e.emit(Opcode::CallNative);
+    e.emit_varint(u64::from(natives::id_of("sh").unwrap()));
+    e.emit_u8(1);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn strict_sh_turns_failures_into_process_errors() {
+    let mut vm = VM::new();
+    vm.run(&sh("exit 2"), &mut StdHost).unwrap();
+    assert_eq!(vm.result().unwrap().to_string(), r#"{"code": 2, "stderr": "", "stdout": "", "timed_out": false}"#);
+
+    let strict = || {
+        let mut vm = VM::new();
+        vm.set_strict_sh(true);
+        vm
+    };
+    strict().run(&sh("true"), &mut StdHost).unwrap();
+    let err = strict().run(&sh("echo nope >&2; exit 2"), &mut StdHost).unwrap_err();
+    assert_eq!(err.to_string(), "ProcessError: `echo nope >&2; exit 2` exited with code 2: nope");
+    let err = err.downcast::<ProcessError>().unwrap();
+    assert_eq!(err.output.code, Some(2));
+}
+
+#[test]
+fn running_commands_needs_the_run_capability() {
+    let mut vm = VM::new();
+    vm.set_permissions(Permissions::none());
+    let err = vm.run(&sh("true"), &mut StdHost).unwrap_err();
+    assert!(err.to_string().contains("--allow-run=sh"), "{}", err);
+
+    // granting the program a block runs is not enough; granting sh allows any
+    let sandbox = |flag: &str| Permissions::from_args(["--sandbox".to_string(), flag.to_string()]).unwrap().0;
+    let mut vm = VM::new();
+    vm.set_permissions(sandbox("--allow-run=true"));
+    assert!(vm.run(&sh("true"), &mut StdHost).is_err());
+    let mut vm = VM::new();
+    vm.set_permissions(sandbox("--allow-run=sh"));
+    vm.run(&sh("true; echo ran"), &mut StdHost).unwrap();
+}
+
+#[test]
+fn process_errors_can_be_caught_with_the_output() {
+    // try [ sh("...") ] except err [ ] leaves `err` on the stack
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::Try);
+    let handler = e.position();
+    e.emit_u32(0);
+    let idx = e.add_constant("echo partial; exit 4".to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(idx as u64);
+    e.emit(Opcode::CallNative);
+    e.emit_varint(u64::from(natives::id_of("sh").unwrap()));
+    e.emit_u8(1);
+    e.emit(Opcode::EndTry);
+    e.emit(Opcode::Halt);
+    e.patch_u32(handler, e.position() as u32);
+    e.emit(Opcode::Halt);
+
+    let mut vm = VM::new();
+    vm.set_strict_sh(true);
+    vm.run(&e.finish(), &mut StdHost).unwrap();
+    let Some(Value::Map(err)) = vm.result() else { panic!("unexpected {:?}", vm.result()) };
+    assert_eq!(err["kind"], Value::from("process:exit"));
+    let Value::Map(data) = &err["data"] else { panic!("unexpected {:?}", err["data"]) };
+    assert_eq!(data["code"], Value::Int(4));
+    assert_eq!(data["stdout"], Value::from("partial\n"));
+    assert_eq!(data["command"], Value::from("echo partial; exit 4"));
+    assert_eq!(err["message"], Value::from("ProcessError: `echo partial; exit 4` exited with code 4"));
+}
+
+/// Runs commands for real and keeps what is logged.
+#[derive(Default)]
+struct ShellHost {
+    lines: Vec<String>,
+}
+
+impl hackerscript_vm::Host for ShellHost {
+    fn log(&mut self, message: &str) {
+        self.lines.push(message.to_string());
+    }
+
+    fn sh(&mut self, command: &str, timeout: Option<Duration>) -> anyhow::Result<ProcessOutput> {
+        Ok(run_shell(command, timeout)?)
+    }
+}
+
+/// An `sh [ ... ]` block running `script`.
+fn block(script: &str) -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    let idx = e.add_constant(script.to_string());
+    e.emit(Opcode::PushConst);
+    e.emit_varint(idx as u64);
+    e.emit(Opcode::Sh);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn sh_blocks_log_their_output_and_fail_with_the_script() {
+    let mut host = ShellHost::default();
+    VM::new().run(&block("cd /\necho \"in $(pwd)\"\necho oops >&2"), &mut host).unwrap();
+    assert_eq!(host.lines, ["in /", "[warn] oops"]);
+
+    let mut host = ShellHost::default();
+    let err = VM::new().run(&block("echo half\nexit 5"), &mut host).unwrap_err();
+    assert_eq!(host.lines, ["half"]);
+    let err = err.downcast::<ProcessError>().unwrap();
+    assert_eq!((err.command.as_str(), err.output.code), ("echo half\nexit 5", Some(5)));
+
+    let mut vm = VM::new();
+    vm.set_permissions(Permissions::none());
+    let err = vm.run(&block("true"), &mut ShellHost::default()).unwrap_err();
+    assert!(err.to_string().contains("--allow-run=sh"), "{}", err);
+}
@@ -0,0 +1,85 @@
+use hackerscript_bytecode::{verify, Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::{BufferHost, VM};
+use proptest::prelude::*;
+
+/// One instruction of a generated chunk. Jumps name a later instruction by
+/// distance so the program always terminates; signals and natives are left
+/// out because they reach outside the process.
+#[derive(Debug, Clone)]
+enum Instr {
+    Plain(Opcode),
+    Const(u64),
+    Var(Opcode, u64),
+    Int(i64),
+    Float(f64),
+    Jump(Opcode, usize),
+}
+
+fn instr() -> impl Strategy<Value = Instr> {
+    use Opcode::*;
+    let plain = prop::sample::select(vec![
+        Nop, Add, Sub, Mul, Div, Rem, Eq, Ne, Lt, Le, Gt, Ge, PushNull, PushTrue, PushFalse, Slice, Pop, Dup, Index, LogString, Return, BeginFunc, EndFunc,
+        EndTry, Throw, Halt, Swap,
+    ]);
+    prop_oneof![
+        4 => plain.prop_map(Instr::Plain),
+        1 => (0..4u64).prop_map(Instr::Const),
+        1 => (prop::sample::select(vec![LoadVar, StoreVar, StoreLocal, Unbound, Define]), 0..4u64).prop_map(|(op, idx)| Instr::Var(op, idx)),
+        2 => prop_oneof![Just(0), Just(-1), Just(i64::MIN), Just(i64::MAX), any::<i64>()].prop_map(Instr::Int),
+        1 => any::<f64>().prop_map(Instr::Float),
+        1 => (prop::sample::select(vec![Jump, JumpIfFalse, Try]), 1..8usize).prop_map(|(op, d)| Instr::Jump(op, d)),
+    ]
+}
+
+fn assemble(instrs: &[Instr]) -> Bytecode {
+    let mut emitter = BytecodeEmitter::new();
+    for s in ["a", "b", "", "c"] {
+        emitter.add_constant(s.to_string());
+    }
+    let mut offsets = Vec::new();
+    let mut patches = Vec::new();
+    for (i, instr) in instrs.iter().enumerate() {
+        offsets.push(emitter.position());
+        match instr {
+            Instr::Plain(op) => emitter.emit(*op),
+            Instr::Const(idx) => {
+                emitter.emit(Opcode::PushConst);
+                emitter.emit_varint(*idx);
+            }
+            Instr::Var(op, idx) => {
+                emitter.emit(*op);
+                emitter.emit_varint(*idx);
+            }
+            Instr::Int(n) => {
+                emitter.emit(Opcode::PushInt);
+                emitter.emit_i64(*n);
+            }
+            Instr::Float(x) => {
+                emitter.emit(Opcode::PushFloat);
+                emitter.emit_f64(*x);
+            }
+            Instr::Jump(op, distance) => {
+                emitter.emit(*op);
+                patches.push((emitter.position(), (i + distance).min(instrs.len())));
+                emitter.emit_u32(0);
+            }
+        }
+    }
+    offsets.push(emitter.position());
+    emitter.emit(Opcode::Halt);
+    for (at, target) in patches {
+        emitter.patch_u32(at, offsets[target] as u32);
+    }
+    emitter.finish()
+}
+
+proptest! {
+    #[test]
+    fn verified_bytecode_runs_without_panicking(instrs in prop::collection::vec(instr(), 0..48)) {
+        let bytecode = assemble(&instrs);
+        if verify(&bytecode).is_ok() {
+            let mut host = BufferHost::default();
+            let _ = VM::new().run(&bytecode, &mut host);
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::collections::BTreeMap;
+
+use hackerscript_vm::{natives, BufferHost, Value};
+
+fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let (_, native) = natives::lookup(natives::id_of(name).unwrap()).unwrap();
+    native(&mut BufferHost::default(), args).map_err(|e| e.to_string())
+}
+
+#[test]
+fn field_natives_take_a_map_and_a_name() {
+    let point = Value::from(BTreeMap::from([("x".to_string(), 1)]));
+    assert_eq!(call("has_field", &[point.clone(), "x".into()]), Ok(Value::Bool(true)));
+    assert_eq!(
+        call("set_field", &[point.clone(), "y".into(), Value::Int(2)]),
+        Ok(Value::from(BTreeMap::from([("x".to_string(), 1), ("y".to_string(), 2)])))
+    );
+    assert_eq!(call("fields", &[Value::Int(1)]).unwrap_err(), "fields: expected map, found int");
+    assert_eq!(
+        call("get_field", &[point.clone(), Value::Int(1)]).unwrap_err(),
+        "get_field: expected string, found int"
+    );
+    assert_eq!(call("set_field", &[point.clone(), "y".into()]).unwrap_err(), "set_field: missing argument 3");
+    assert_eq!(call("type_of", &[point.clone(), point]).unwrap_err(), "type_of: takes 1 argument(s) but 2 were given");
+    assert!(["type_of", "fields", "methods", "has_field", "get_field", "set_field"].into_iter().all(natives::is_pure));
+}
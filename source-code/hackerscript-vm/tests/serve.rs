@@ -0,0 +1,125 @@
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use hackerscript_bytecode::{BytecodeEmitter, Opcode};
+use hackerscript_vm::serve::{read_frame, write_frame, Reply, Server};
+use hackerscript_vm::{LogLevel, Permissions};
+
+/// A server handling exactly one connection, and a client connected to it.
+fn connect(server: Server) -> (TcpStream, thread::JoinHandle<anyhow::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || server.handle(listener.accept()?.0));
+    (TcpStream::connect(addr).unwrap(), handle)
+}
+
+/// `log "hello"`, `log.debug "hidden"`, `log.warn "careful"`, then leave 42 on the stack.
+fn job() -> Vec<u8> {
+    let mut e = BytecodeEmitter::new();
+    for (text, level) in [("hello", None), ("hidden", Some(LogLevel::Debug)), ("careful", Some(LogLevel::Warn))] {
+        let idx = e.add_constant(text.to_string());
+        e.emit(Opcode::PushConst);
+        e.emit_varint(idx as u64);
+        match level {
+            None => e.emit(Opcode::LogString),
+            Some(level) => {
+                e.emit(Opcode::LogAt);
+                e.emit_varint(1);
+                e.emit_u8(level as u8);
+            }
+        }
+    }
+    e.emit(Opcode::PushInt);
+    e.emit_i64(42);
+    e.emit(Opcode::Halt);
+    e.finish().to_bytes()
+}
+
+fn replies(client: &mut TcpStream, until_done: bool) -> Vec<Reply> {
+    let mut replies = Vec::new();
+    while let Some(reply) = Reply::read(client).unwrap() {
+        let last = matches!(reply, Reply::Done(_) | Reply::Error(_));
+        replies.push(reply);
+        if last && until_done {
+            break;
+        }
+    }
+    replies
+}
+
+#[test]
+fn runs_jobs_and_streams_their_logs_back() {
+    let (mut client, server) = connect(Server::new("secret"));
+    write_frame(&mut client, b"secret").unwrap();
+    for _ in 0..2 {
+        write_frame(&mut client, &job()).unwrap();
+        let expected = [
+            Reply::Log("hello".into()),
+            Reply::Log("[warn] careful".into()),
+            Reply::Done(Some("42".into())),
+        ];
+        assert_eq!(replies(&mut client, true), expected);
+    }
+
+    // a bad job fails on its own; the connection stays usable
+    write_frame(&mut client, b"not bytecode").unwrap();
+    let [Reply::Error(message)] = &replies(&mut client, true)[..] else { panic!("expected an error") };
+    assert!(message.contains("HSBC"), "{}", message);
+
+    drop(client);
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn levels_and_permissions_apply_to_every_job() {
+    let mut server = Server::new("secret");
+    server.set_level(LogLevel::Error);
+    server.set_permissions(Permissions::none());
+    let (mut client, server) = connect(server);
+    write_frame(&mut client, b"secret").unwrap();
+    write_frame(&mut client, &job()).unwrap();
+    assert_eq!(replies(&mut client, true), [Reply::Log("hello".into()), Reply::Done(Some("42".into()))]);
+
+    // `send_signal` to pid 1 with signal 0, which only checks the process exists
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::PushInt);
+    e.emit_i64(1);
+    e.emit(Opcode::PushInt);
+    e.emit_i64(0);
+    e.emit(Opcode::SendSignal);
+    e.emit(Opcode::Halt);
+    write_frame(&mut client, &e.finish().to_bytes()).unwrap();
+    let [Reply::Error(message)] = &replies(&mut client, true)[..] else { panic!("expected an error") };
+    assert!(message.contains("signal"), "{}", message);
+    drop(client);
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn a_wrong_token_closes_the_connection() {
+    let (mut client, server) = connect(Server::new("secret"));
+    write_frame(&mut client, b"guess").unwrap();
+    assert_eq!(replies(&mut client, false), [Reply::Error("authentication failed".into())]);
+    assert!(read_frame(&mut client).unwrap().is_none());
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn outcomes_replace_the_streamed_replies() {
+    let mut server = Server::new("secret");
+    server.set_outcomes(true);
+    let (mut client, server) = connect(server);
+    write_frame(&mut client, b"secret").unwrap();
+    write_frame(&mut client, &job()).unwrap();
+    let Some(Reply::Outcome(json)) = Reply::read(&mut client).unwrap() else { panic!("expected an outcome") };
+    assert!(
+        json.starts_with(r#"{"ok":true,"result":42,"logs":[{"level":null,"message":"hello"},{"level":"warn","message":"careful"}],"duration_ms":"#),
+        "{}",
+        json
+    );
+    write_frame(&mut client, b"not bytecode").unwrap();
+    let Some(Reply::Outcome(json)) = Reply::read(&mut client).unwrap() else { panic!("expected an outcome") };
+    assert!(json.starts_with(r#"{"ok":false,"result":null,"logs":[],"#) && json.contains("HSBC"), "{}", json);
+    drop(client);
+    server.join().unwrap().unwrap();
+}
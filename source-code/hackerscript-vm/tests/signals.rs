@@ -0,0 +1,127 @@
+#![cfg(feature = "signals")]
+
+use hackerscript_bytecode::{BytecodeEmitter, FuncHeader, Instruction, Opcode};
+use hackerscript_vm::{BufferHost, Host, PermissionDenied, Permissions, VM};
+
+/// Raises SIGUSR1 in the calling thread when the script logs "raise", so
+/// the signal is pending before the `log` instruction finishes.
+#[derive(Default)]
+struct Raising(BufferHost);
+
+impl Host for Raising {
+    fn log(&mut self, message: &str) {
+        self.0.log(message);
+        if message == "raise" {
+            signal_hook::low_level::raise(signal_hook::consts::SIGUSR1).unwrap();
+        }
+    }
+}
+
+fn push_str(e: &mut BytecodeEmitter, text: &str) {
+    let index = e.add_constant(text.to_string()) as u64;
+    e.emit_instruction(&Instruction::PushConst { index });
+}
+
+fn log(e: &mut BytecodeEmitter, text: &str) {
+    push_str(e, text);
+    e.emit(Opcode::LogString);
+}
+
+#[test]
+fn a_pending_signal_runs_its_handler_at_the_next_instruction_boundary() {
+    let mut e = BytecodeEmitter::new();
+    let entry = e.position() as u32;
+    e.emit(Opcode::BeginFunc);
+    log(&mut e, "handled");
+    // what a handler returns is dropped
+    e.emit(Opcode::PushTrue);
+    e.emit(Opcode::Return);
+    e.emit(Opcode::EndFunc);
+    push_str(&mut e, "USR1");
+    let name = e.add_constant("handler".to_string()) as u64;
+    let header = FuncHeader { entry, name, slots: 0, params: 0, required: 0, flags: 0 };
+    e.emit_instruction(&Instruction::MakeFunc { header });
+    e.emit(Opcode::OnSignal);
+    // on the stack while the handler runs
+    push_str(&mut e, "kept");
+    log(&mut e, "raise");
+    e.emit(Opcode::LogString);
+    e.emit(Opcode::Halt);
+
+    let mut host = Raising::default();
+    VM::new().run(&e.finish(), &mut host).unwrap();
+    assert_eq!(host.0.lines, ["raise", "handled", "kept"]);
+}
+
+#[test]
+fn handlers_must_be_functions() {
+    let mut e = BytecodeEmitter::new();
+    push_str(&mut e, "USR1");
+    e.emit_instruction(&Instruction::PushInt { value: 0 });
+    e.emit(Opcode::OnSignal);
+    e.emit(Opcode::Halt);
+    let err = VM::new().run(&e.finish(), &mut BufferHost::default()).unwrap_err();
+    assert_eq!(err.to_string(), "on_signal: expected a function, found int");
+}
+
+/// A program that routes `signal` to an empty handler.
+fn handle(signal: &str) -> hackerscript_bytecode::Bytecode {
+    let mut e = BytecodeEmitter::new();
+    let entry = e.position() as u32;
+    e.emit(Opcode::BeginFunc);
+    e.emit(Opcode::PushNull);
+    e.emit(Opcode::Return);
+    e.emit(Opcode::EndFunc);
+    push_str(&mut e, signal);
+    let name = e.add_constant("handler".to_string()) as u64;
+    let header = FuncHeader { entry, name, slots: 0, params: 0, required: 0, flags: 0 };
+    e.emit_instruction(&Instruction::MakeFunc { header });
+    e.emit(Opcode::OnSignal);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+#[test]
+fn dropping_the_vm_gives_the_signal_its_default_action_back() {
+    const CHILD: &str = "HS_SIGNALS_CHILD";
+    if std::env::var_os(CHILD).is_some() {
+        VM::new().run(&handle("TERM"), &mut BufferHost::default()).unwrap();
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).unwrap();
+        // only reached if SIGTERM was swallowed
+        std::process::exit(0);
+    }
+    use std::os::unix::process::ExitStatusExt;
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "dropping_the_vm_gives_the_signal_its_default_action_back"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(status.signal(), Some(signal_hook::consts::SIGTERM), "{:?}", status);
+}
+
+#[test]
+fn handling_signals_needs_the_signal_permission() {
+    let mut vm = VM::new();
+    vm.set_permissions(Permissions::from_args(["--sandbox".to_string()]).unwrap().0);
+    let err = vm.run(&handle("USR2"), &mut BufferHost::default()).unwrap_err();
+    let denied = err.downcast_ref::<PermissionDenied>().expect("a permission error");
+    assert_eq!(denied.capability, "signal");
+}
+
+#[test]
+fn pids_that_do_not_fit_are_errors() {
+    let mut e = BytecodeEmitter::new();
+    e.emit_instruction(&Instruction::PushInt { value: 1 << 32 });
+    push_str(&mut e, "TERM");
+    e.emit(Opcode::SendSignal);
+    e.emit(Opcode::Halt);
+    let err = VM::new().run(&e.finish(), &mut BufferHost::default()).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid pid 4294967296");
+}
+
+#[test]
+fn signals_that_cannot_be_caught_are_errors() {
+    let err = VM::new().run(&handle("KILL"), &mut BufferHost::default()).unwrap_err();
+    assert_eq!(err.to_string(), "Cannot handle signal SIGKILL");
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "term")]
+
+use hackerscript_vm::{natives, Host, Value};
+
+/// A terminal in memory: what natives write and the lines typed at it.
+#[derive(Default)]
+struct Terminal {
+    output: String,
+    input: Vec<String>,
+}
+
+impl Host for Terminal {
+    fn log(&mut self, message: &str) {
+        self.output.push_str(message);
+        self.output.push('\n');
+    }
+
+    fn term_write(&mut self, text: &str) -> anyhow::Result<()> {
+        self.output.push_str(text);
+        Ok(())
+    }
+
+    fn term_read_line(&mut self) -> anyhow::Result<String> {
+        anyhow::ensure!(!self.input.is_empty(), "no input left");
+        Ok(self.input.remove(0))
+    }
+
+    fn term_size(&mut self) -> anyhow::Result<(u16, u16)> {
+        Ok((80, 24))
+    }
+}
+
+fn call(host: &mut dyn Host, name: &str, args: &[Value]) -> Result<Value, String> {
+    let (_, native) = natives::lookup(natives::id_of(name).unwrap()).unwrap();
+    native(host, args).map_err(|e| e.to_string())
+}
+
+#[test]
+fn terminal_output_goes_through_the_host() {
+    let mut term = Terminal::default();
+    call(&mut term, "term_move_to", &[Value::Int(4), Value::Int(2)]).unwrap();
+    call(&mut term, "term_hide_cursor", &[]).unwrap();
+    call(&mut term, "term_progress", &[Value::Int(1), Value::Int(4), Value::Int(8)]).unwrap();
+    call(&mut term, "term_progress", &[Value::Int(4), Value::Int(4), Value::Int(8)]).unwrap();
+    assert_eq!(term.output, "\x1b[3;5H\x1b[?25l\r[##      ]  25%\r[########] 100%\n");
+    let size = call(&mut term, "term_size", &[]).unwrap();
+    assert_eq!(size, Value::Array(vec![Value::Int(80), Value::Int(24)]));
+}
+
+#[test]
+fn a_progress_bar_fits_the_terminal() {
+    let mut term = Terminal::default();
+    call(&mut term, "term_progress", &[Value::Int(1), Value::Int(2), Value::Int(i64::MAX)]).unwrap();
+    assert_eq!(term.output, format!("\r[{}{}]  50%", "#".repeat(37), " ".repeat(36)));
+}
+
+#[test]
+fn prompts_read_from_the_host() {
+    let mut term = Terminal { input: vec!["root".into(), "".into(), "nope".into()], ..Default::default() };
+    assert_eq!(call(&mut term, "term_prompt", &["user: ".into()]), Ok("root".into()));
+    assert_eq!(call(&mut term, "term_confirm", &["wipe?".into(), Value::Bool(true)]), Ok(Value::Bool(true)));
+    assert_eq!(call(&mut term, "term_confirm", &["wipe?".into()]), Ok(Value::Bool(false)));
+    assert_eq!(term.output, "user: wipe? [Y/n] wipe? [y/N] ");
+    assert_eq!(call(&mut term, "term_prompt", &["more: ".into()]), Err("no input left".to_string()));
+}
+
+#[test]
+fn cursor_positions_must_fit_the_terminal() {
+    let mut term = Terminal::default();
+    for (col, row, message) in [
+        (-1, 0, "term_move_to: column -1 is not between 0 and 65535"),
+        (0, 65536, "term_move_to: row 65536 is not between 0 and 65535"),
+    ] {
+        assert_eq!(call(&mut term, "term_move_to", &[Value::Int(col), Value::Int(row)]), Err(message.to_string()));
+    }
+    assert_eq!(term.output, "");
+}
+
+#[test]
+fn hosts_without_a_terminal_refuse() {
+    let mut host = hackerscript_vm::BufferHost::default();
+    let err = call(&mut host, "term_clear", &[]).unwrap_err();
+    assert_eq!(err, "a terminal is not available in this host");
+    // colors are only text
+    assert!(call(&mut host, "term_color", &["ok".into(), "green".into()]).is_ok());
+}
@@ -0,0 +1,38 @@
+use hackerscript_bytecode::{BytecodeEmitter, Opcode};
+use hackerscript_vm::audit::AuditLog;
+use hackerscript_vm::{BufferHost, Logger, Value, VM};
+
+fn assert_send<T: Send>() {}
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn vms_and_values_cross_threads() {
+    assert_send::<VM>();
+    assert_send::<AuditLog>();
+    assert_send::<Logger>();
+    assert_send_sync::<Value>();
+
+    // `log limit * 2`, with `limit` set by the host
+    let mut e = BytecodeEmitter::new();
+    let name = e.add_constant("limit".to_string());
+    e.emit(Opcode::LoadVar);
+    e.emit_varint(name as u64);
+    e.emit(Opcode::PushInt);
+    e.emit_i64(2);
+    e.emit(Opcode::Mul);
+    e.emit(Opcode::LogValues);
+    e.emit_varint(1);
+    e.emit(Opcode::Halt);
+    let bytecode = e.finish();
+
+    let mut vm = VM::new();
+    vm.set_global("limit", Value::Int(21));
+    let lines = std::thread::spawn(move || {
+        let mut host = BufferHost::default();
+        vm.run(&bytecode, &mut host).unwrap();
+        host.lines
+    })
+    .join()
+    .unwrap();
+    assert_eq!(lines, ["42"]);
+}
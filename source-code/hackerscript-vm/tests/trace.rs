@@ -0,0 +1,100 @@
+use hackerscript_bytecode::{Bytecode, BytecodeEmitter, Opcode};
+use hackerscript_vm::natives;
+use hackerscript_vm::trace::Trace;
+use hackerscript_vm::{BufferHost, Host, Value, VM};
+
+/// `log term_size()`, then `log term_color(word, "red")`.
+fn program(word: &str) -> Bytecode {
+    let mut e = BytecodeEmitter::new();
+    e.emit(Opcode::CallNative);
+    e.emit_varint(u64::from(natives::id_of("term_size").unwrap()));
+    e.emit_u8(0);
+    e.emit(Opcode::LogString);
+    for text in [word, "red"] {
+        let idx = e.add_constant(text.to_string());
+        e.emit(Opcode::PushConst);
+        e.emit_varint(idx as u64);
+    }
+    e.emit(Opcode::CallNative);
+    e.emit_varint(u64::from(natives::id_of("term_color").unwrap()));
+    e.emit_u8(2);
+    e.emit(Opcode::LogString);
+    e.emit(Opcode::Halt);
+    e.finish()
+}
+
+/// A `BufferHost` with an 80 by 24 terminal.
+#[derive(Default)]
+struct Terminal(BufferHost);
+
+impl Host for Terminal {
+    fn log(&mut self, message: &str) {
+        self.0.log(message);
+    }
+
+    fn term_size(&mut self) -> anyhow::Result<(u16, u16)> {
+        Ok((80, 24))
+    }
+}
+
+fn run(bytecode: &Bytecode, trace: Trace) -> (anyhow::Result<()>, Vec<String>) {
+    let mut vm = VM::new();
+    vm.set_trace(trace);
+    let mut host = Terminal::default();
+    let result = vm.run(bytecode, &mut host);
+    (result, host.0.lines)
+}
+
+/// Record a run of `bytecode` and return the trace file's contents.
+fn record(bytecode: &Bytecode, name: &str) -> (Vec<String>, String) {
+    let path = std::env::temp_dir().join(format!("hs-trace-{}-{}.hsr", name, std::process::id()));
+    let (result, lines) = run(bytecode, Trace::record_to(&path, bytecode).unwrap());
+    result.unwrap();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (lines, trace)
+}
+
+#[test]
+fn replays_return_the_recorded_results() {
+    let bytecode = program("hi");
+    let (lines, trace) = record(&bytecode, "replay");
+    assert_eq!(trace.lines().count(), 3, "{}", trace);
+    assert_eq!(trace.lines().nth(1).unwrap(), r#"{"native":"term_size","args":[],"result":[80,24]}"#, "{}", trace);
+
+    let (result, replayed) = run(&bytecode, Trace::replay(trace.as_bytes(), &bytecode).unwrap());
+    result.unwrap();
+    assert_eq!(replayed, lines);
+
+    // the results come from the trace, not from the natives
+    let mut edited: Vec<String> = trace.lines().map(String::from).collect();
+    edited[1] = r#"{"native":"term_size","args":[],"result":[1,2]}"#.to_string();
+    edited[2] = r#"{"native":"term_color","args":["hi","red"],"error":"no colors today"}"#.to_string();
+    let (result, replayed) = run(&bytecode, Trace::replay(edited.join("\n").as_bytes(), &bytecode).unwrap());
+    assert_eq!(result.unwrap_err().to_string(), "no colors today");
+    assert_eq!(replayed, [Value::from(vec![Value::Int(1), Value::Int(2)]).to_string()]);
+}
+
+#[test]
+fn replays_stop_where_the_script_diverges() {
+    let bytecode = program("hi");
+    let (_, trace) = record(&bytecode, "diverge");
+    let mut events: Vec<&str> = trace.lines().collect();
+    let term_color = events.pop().unwrap().replace("\"hi\"", "\"bye\"");
+    events.push(&term_color);
+    let (result, _) = run(&bytecode, Trace::replay(events.join("\n").as_bytes(), &bytecode).unwrap());
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        r#"Replay diverged at call 2: the recording has `term_color("bye", "red")`, the script called `term_color("hi", "red")`"#
+    );
+
+    let (result, _) = run(&bytecode, Trace::replay(events[..2].join("\n").as_bytes(), &bytecode).unwrap());
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        r#"Replay diverged at call 2: the recording ended, the script called `term_color("hi", "red")`"#
+    );
+
+    let other = program("ho");
+    let err = Trace::replay(trace.as_bytes(), &other).unwrap_err();
+    assert_eq!(err.to_string(), "Trace was recorded from a different program");
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, HashMap};
+
+use hackerscript_vm::value::ConversionError;
+use hackerscript_vm::Value;
+
+#[cfg(feature = "serde")]
+mod serde_round_trip {
+    use std::collections::BTreeMap;
+
+    use hackerscript_vm::value::{from_value, to_value, ConversionError};
+    use hackerscript_vm::Value;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        name: String,
+        load: f64,
+        ports: Vec<u16>,
+        owner: Option<String>,
+        tags: BTreeMap<String, Vec<f64>>,
+    }
+
+    #[test]
+    fn nested_maps_lists_floats_and_null_round_trip() {
+        let server = Server {
+            name: "gate".to_string(),
+            load: 0.75,
+            ports: vec![22, 443],
+            owner: None,
+            tags: BTreeMap::from([("latency".to_string(), vec![1.5, -0.25]), ("empty".to_string(), vec![])]),
+        };
+        let value = to_value(&server).unwrap();
+        let Value::Map(fields) = &value else { panic!("expected a map, got {:?}", value) };
+        assert_eq!(fields["owner"], Value::Null);
+        assert_eq!(fields["load"], Value::Float(0.75));
+        assert_eq!(fields["ports"], Value::Array(vec![Value::Int(22), Value::Int(443)]));
+        let Value::Map(tags) = &fields["tags"] else { panic!("expected a map, got {:?}", fields["tags"]) };
+        assert_eq!(tags["latency"], Value::Array(vec![Value::Float(1.5), Value::Float(-0.25)]));
+        assert_eq!(from_value::<Server>(value).unwrap(), server);
+    }
+
+    #[test]
+    fn values_round_trip_through_serde() {
+        let value = Value::Map(BTreeMap::from([
+            ("list".to_string(), Value::Array(vec![Value::Int(1), Value::Float(2.5), Value::Null])),
+            ("nested".to_string(), Value::Map(BTreeMap::from([("ok".to_string(), Value::Bool(true))]))),
+        ]));
+        assert_eq!(from_value::<Value>(to_value(&value).unwrap()).unwrap(), value);
+    }
+
+    #[test]
+    fn map_keys_must_be_strings() {
+        let scores = BTreeMap::from([(1, "one"), (2, "two")]);
+        assert_eq!(to_value(&scores), Err(ConversionError("map keys must be strings, found int".to_string())));
+    }
+
+    #[test]
+    fn ints_too_big_for_the_script_are_errors() {
+        assert_eq!(to_value(&u64::MAX), Err(ConversionError(format!("{} does not fit in a script int", u64::MAX))));
+    }
+
+    #[test]
+    fn shape_mismatches_are_errors() {
+        let err = from_value::<Server>(Value::Array(vec![Value::Int(1)])).unwrap_err();
+        assert!(err.0.contains("invalid"), "{}", err);
+        assert!(from_value::<Vec<u8>>(Value::from("text")).is_err());
+    }
+}
+
+fn error<T>(result: Result<T, ConversionError>) -> String {
+    match result {
+        Ok(_) => panic!("conversion should have failed"),
+        Err(err) => err.to_string(),
+    }
+}
+
+#[test]
+fn ints_out_of_range_do_not_fit() {
+    assert_eq!(error(i8::try_from(Value::Int(300))), "300 does not fit in i8");
+    assert_eq!(error(u32::try_from(Value::Int(-1))), "-1 does not fit in u32");
+    assert_eq!(i16::try_from(Value::Int(-300)), Ok(-300));
+}
+
+#[test]
+fn ints_too_big_for_the_script_do_not_wrap() {
+    assert_eq!(error(Value::try_from(u64::MAX)), format!("{} does not fit in a script int", u64::MAX));
+    assert_eq!(error(Value::try_from(usize::MAX)), format!("{} does not fit in a script int", usize::MAX));
+    assert_eq!(Value::try_from(i64::MAX as u64), Ok(Value::Int(i64::MAX)));
+    assert_eq!(Value::from(u32::MAX), Value::Int(i64::from(u32::MAX)));
+}
+
+#[test]
+fn wrong_types_name_what_was_expected() {
+    assert_eq!(error(u8::try_from(Value::from("7"))), "expected int, found string");
+    assert_eq!(error(String::try_from(Value::Int(1))), "expected string, found int");
+    assert_eq!(error(bool::try_from(Value::Null)), "expected bool, found null");
+    assert_eq!(error(f64::try_from(Value::Bool(true))), "expected float, found bool");
+    assert_eq!(error(Vec::<i64>::try_from(Value::Null)), "expected array, found null");
+    assert_eq!(error(BTreeMap::<String, i64>::try_from(Value::Int(3))), "expected map, found int");
+    assert_eq!(error(HashMap::<String, i64>::try_from(Value::from("x"))), "expected map, found string");
+}
+
+#[test]
+fn element_errors_fail_the_whole_container() {
+    let items = Value::Array(vec![Value::Int(1), Value::from("two")]);
+    assert_eq!(error(Vec::<i64>::try_from(items)), "expected int, found string");
+    let entries = Value::Map(BTreeMap::from([("big".to_string(), Value::Int(1 << 40))]));
+    assert_eq!(error(BTreeMap::<String, u16>::try_from(entries)), "1099511627776 does not fit in u16");
+}
+
+#[test]
+fn null_is_none_but_other_types_are_not_some() {
+    assert_eq!(Option::<i64>::try_from(Value::Null), Ok(None));
+    assert_eq!(Option::<i64>::try_from(Value::Int(4)), Ok(Some(4)));
+    assert_eq!(error(Option::<i64>::try_from(Value::from("4"))), "expected int, found string");
+}
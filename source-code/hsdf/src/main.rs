@@ -1,9 +1,8 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
-use miette::{Diagnostic, MietteDiagnostic, NamedSource, Report, SourceSpan};
+use miette::{MietteDiagnostic, NamedSource, Report, SourceSpan};
 use std::fs;
 use std::path::PathBuf;
-use thiserror::Error;
 
 #[derive(Parser)]
 #[command(
@@ -50,6 +49,17 @@ enum Commands {
         #[arg(long)]
         span: Option<String>,
     },
+
+    /// Parse a .hcs file with the shared parser and pretty-print syntax errors
+    Check {
+        /// Path to .hcs source file
+        #[arg(required = true)]
+        file: PathBuf,
+
+        /// Print the parsed AST as JSON on success
+        #[arg(long)]
+        ast: bool,
+    },
 }
 
 fn main() -> Result<()> {
@@ -81,67 +91,113 @@ fn main() -> Result<()> {
         }
 
         Commands::FromText { source, message, span } => {
-            let source_code = if let Some(path) = source {
-                fs::read_to_string(&path).context("Cannot read source file")?
+            let source_code = if let Some(path) = &source {
+                fs::read_to_string(path).context("Cannot read source file")?
             } else {
                 String::new()
             };
 
-            let mut report = Report::new(MietteDiagnostic {
-                severity: Some(miette::Severity::Error),
-                                         code: Some("HS-0001".into()),
-                                         message: message.unwrap_or_else(|| "Generic error".into()),
-                                         ..Default::default()
-            });
+            let mut diagnostic = MietteDiagnostic::new(message.unwrap_or_else(|| "Generic error".into()))
+                .with_severity(miette::Severity::Error)
+                .with_code("HS-0001");
 
+            let mut with_source = false;
             if let Some(span_str) = span {
                 if let Some((start, end)) = parse_span(&span_str) {
-                    report = report.with_source_code(NamedSource::new(
-                        source.unwrap_or_default().to_string_lossy().to_string(),
-                                                                      source_code,
-                    ));
-                    report = report.with_label(miette::LabeledSpan::underline(SourceSpan::new(
+                    diagnostic = diagnostic.with_label(miette::LabeledSpan::underline(SourceSpan::new(
                         start.into(),
-                                                                                              (end - start).into(),
+                        end - start,
                     )));
+                    with_source = true;
                 }
             }
 
+            let mut report = Report::new(diagnostic);
+            if with_source {
+                report = report.with_source_code(NamedSource::new(
+                    source.unwrap_or_default().display().to_string(),
+                    source_code,
+                ));
+            }
+
             eprintln!("{:?}", report);
         }
+
+        Commands::Check { file, ast } => {
+            let source_code = fs::read_to_string(&file)
+            .with_context(|| format!("Cannot read {}", file.display()))?;
+
+            match hackerscript_parser::parse(&source_code) {
+                Ok(program) => {
+                    if ast {
+                        println!("{}", serde_json::to_string_pretty(&program)?);
+                    } else {
+                        eprintln!("{}: syntax OK", file.display());
+                    }
+                }
+                Err(err) => {
+                    let diag = parse_error_diagnostic(&file, source_code, &err);
+                    print_pretty_diagnostic(&diag)?;
+                    std::process::exit(1);
+                }
+            }
+        }
     }
 
     Ok(())
 }
 
+fn parse_error_diagnostic(
+    file: &std::path::Path,
+    source_code: String,
+    err: &hackerscript_parser::ParseError,
+) -> HsDiagnosticFile {
+    let span = hackerscript_parser::error_span(&source_code, err);
+    let help = hackerscript_parser::suggest(&source_code, err)
+        .map(|fix| format!("{}:\n{}", fix.message, fix.snippet(&source_code)));
+
+    HsDiagnosticFile {
+        filename: file.display().to_string(),
+        source_code,
+        severity: Severity::Error,
+        code: Some("HS-1000".to_string()),
+        message: "Syntax error".to_string(),
+        url: None,
+        help,
+        labels: vec![HsLabel {
+            message: err.variant.message().into_owned(),
+            offset: span.start,
+            length: span.len(),
+        }],
+    }
+}
+
 fn print_pretty_diagnostic(diag: &HsDiagnosticFile) -> Result<()> {
     let source = NamedSource::new(diag.filename.clone(), diag.source_code.clone());
 
-    let mut report = Report::new(MietteDiagnostic {
-        severity: Some(diag.severity.into()),
-                                 code: diag.code.clone(),
-                                 message: diag.message.clone(),
-                                 url: diag.url.clone(),
-                                 help: diag.help.clone(),
-                                 labels: vec![],
-                                 related: vec![],
-    });
+    let mut diagnostic = MietteDiagnostic::new(diag.message.clone()).with_severity(diag.severity.into());
+    if let Some(code) = &diag.code {
+        diagnostic = diagnostic.with_code(code.clone());
+    }
+    if let Some(url) = &diag.url {
+        diagnostic = diagnostic.with_url(url.clone());
+    }
+    if let Some(help) = &diag.help {
+        diagnostic = diagnostic.with_help(help.clone());
+    }
 
     for label in &diag.labels {
-        let span = SourceSpan::new(
-            label.offset.into(),
-                                   label.length.into(),
-        );
+        let span = SourceSpan::new(label.offset.into(), label.length);
 
         let labeled = miette::LabeledSpan::new_with_span(
             Some(label.message.clone()),
                                                          span,
         );
 
-        report = report.with_label(labeled);
+        diagnostic = diagnostic.with_label(labeled);
     }
 
-    report = report.with_source_code(source);
+    let report = Report::new(diagnostic).with_source_code(source);
 
     eprintln!("{:?}", report);
 
@@ -207,10 +263,6 @@ impl From<Severity> for miette::Severity {
     }
 }
 
-#[derive(Error, Debug)]
-#[error("example error")]
-struct ExampleError;
-
 fn create_example_diagnostic() -> HsDiagnosticFile {
     HsDiagnosticFile {
         filename: "src/main.hcs".to_string(),